@@ -454,6 +454,7 @@ mod tests {
             exit_code: Some(0),
             branch: Some("test-branch".to_string()),
             test_stage: None,
+            tests: None,
         });
 
         let summary = candidate.summary();
@@ -0,0 +1,170 @@
+//! Size and blast-radius limits on generated changes.
+//!
+//! Before a change is applied its diff is measured against
+//! `change_limits` in the config: how many files it touches, how many lines
+//! it adds and removes, and whether it touches a protected path (CI
+//! workflows, the lockfile, the ethics and constitution rules). A goal opts
+//! into touching a protected path with an `allow-path:<pattern>` tag.
+
+use glob::{MatchOptions, Pattern};
+use log::warn;
+
+use crate::code_generation::patch::{parse_unified_diff, HunkLine};
+use crate::core::config::ChangeLimitsConfig;
+use crate::core::optimization::OptimizationGoal;
+
+/// Goal tag prefix allowing changes to matching protected paths
+pub const ALLOW_PATH_TAG: &str = "allow-path:";
+
+/// Files touched and lines changed by a diff
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ChangeSize {
+    /// Paths of the touched files, in diff order
+    pub files: Vec<String>,
+
+    /// Lines added plus lines removed
+    pub lines_changed: usize,
+}
+
+/// Measure a unified diff; an empty diff changes nothing
+pub fn measure(diff: &str) -> Result<ChangeSize, String> {
+    if diff.trim().is_empty() {
+        return Ok(ChangeSize::default());
+    }
+    let mut size = ChangeSize::default();
+    for patch in parse_unified_diff(diff)? {
+        let path = patch.display_path().to_string();
+        if !size.files.contains(&path) {
+            size.files.push(path);
+        }
+        size.lines_changed += patch
+            .hunks
+            .iter()
+            .flat_map(|hunk| &hunk.lines)
+            .filter(|line| !matches!(line, HunkLine::Context(_)))
+            .count();
+    }
+    Ok(size)
+}
+
+/// How `diff` breaks the limits of `config` for `goal`, one message per
+/// broken limit or protected file
+pub fn violations(diff: &str, config: &ChangeLimitsConfig, goal: &OptimizationGoal) -> Vec<String> {
+    let size = match measure(diff) {
+        Ok(size) => size,
+        Err(e) => return vec![format!("The change could not be measured: {}", e)],
+    };
+    let mut violations = Vec::new();
+
+    if let Some(max) = config.max_files {
+        if size.files.len() > max {
+            violations.push(format!(
+                "The change touches {} files; at most {} are allowed",
+                size.files.len(),
+                max
+            ));
+        }
+    }
+    if let Some(max) = config.max_lines_changed {
+        if size.lines_changed > max {
+            violations.push(format!(
+                "The change adds and removes {} lines; at most {} are allowed",
+                size.lines_changed, max
+            ));
+        }
+    }
+
+    let allowed: Vec<&str> = goal
+        .tags
+        .iter()
+        .filter_map(|tag| tag.strip_prefix(ALLOW_PATH_TAG))
+        .map(str::trim)
+        .collect();
+    for file in &size.files {
+        let Some(protected) = config
+            .protected_paths
+            .iter()
+            .find(|pattern| matches_path(pattern, file))
+        else {
+            continue;
+        };
+        if !allowed
+            .iter()
+            .any(|allow| *allow == protected || matches_path(allow, file))
+        {
+            violations.push(format!(
+                "{} is protected by '{}'; the goal needs an {}{} tag to change it",
+                file, protected, ALLOW_PATH_TAG, protected
+            ));
+        }
+    }
+    violations
+}
+
+/// Whether glob `pattern` matches the relative `path`
+fn matches_path(pattern: &str, path: &str) -> bool {
+    let options = MatchOptions {
+        require_literal_separator: true,
+        ..MatchOptions::default()
+    };
+    match Pattern::new(pattern) {
+        Ok(glob) => glob.matches_with(path.trim_start_matches("./"), options),
+        Err(e) => {
+            warn!("Ignoring invalid path pattern '{}': {}", pattern, e);
+            false
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DIFF: &str = "--- a/src/a.rs\n+++ b/src/a.rs\n@@ -1,3 +1,3 @@\n fn a() {\n-    1\n+    2\n }\n\
+                        --- a/.github/workflows/ci.yml\n+++ b/.github/workflows/ci.yml\n@@ -1 +1,2 @@\n on: push\n+on: pull_request\n";
+
+    #[test]
+    fn test_diffs_are_measured() {
+        assert_eq!(
+            measure(DIFF).unwrap(),
+            ChangeSize {
+                files: vec![
+                    "src/a.rs".to_string(),
+                    ".github/workflows/ci.yml".to_string()
+                ],
+                lines_changed: 3,
+            }
+        );
+        assert_eq!(measure("").unwrap(), ChangeSize::default());
+    }
+
+    #[test]
+    fn test_limits_and_protected_paths() {
+        let mut goal = OptimizationGoal::new("goal-1", "Fix CI", "Run CI on pull requests");
+        let config = ChangeLimitsConfig::default();
+        assert_eq!(
+            violations(DIFF, &config, &goal),
+            vec![
+                ".github/workflows/ci.yml is protected by '.github/**'; \
+                  the goal needs an allow-path:.github/** tag to change it"
+            ]
+        );
+
+        goal.tags
+            .push("allow-path:.github/workflows/*.yml".to_string());
+        assert!(violations(DIFF, &config, &goal).is_empty());
+
+        let tight = ChangeLimitsConfig {
+            max_files: Some(1),
+            max_lines_changed: Some(2),
+            protected_paths: Vec::new(),
+        };
+        assert_eq!(
+            violations(DIFF, &tight, &goal),
+            vec![
+                "The change touches 2 files; at most 1 are allowed",
+                "The change adds and removes 3 lines; at most 2 are allowed",
+            ]
+        );
+    }
+}
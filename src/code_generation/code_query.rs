@@ -0,0 +1,548 @@
+//! The `CodeQuery` tool: structural search of Rust sources with tree-sitter.
+//!
+//! Where `Grep` matches lines, `CodeQuery` matches syntax: functions,
+//! structs, enums, traits, impls and call expressions, wherever they are
+//! split across lines. Macro arguments are not parsed by tree-sitter, so the
+//! token tree of each macro invocation is parsed again, first as items and
+//! otherwise as a list of expressions, which finds calls inside `assert!`,
+//! `format!` or item-generating macros as well.
+
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use log::warn;
+use regex::Regex;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use tree_sitter::{Node, Parser};
+
+use crate::code_generation::llm_tool::{
+    str_arg, LlmTool, ToolArgs, ToolParameter, ToolParameterType,
+};
+use crate::code_generation::workspace_guard::WorkspaceGuard;
+
+/// Most matches listed before the output is cut off
+const MAX_MATCHES: usize = 200;
+
+/// How deep macro invocations nested in macro arguments are parsed
+const MAX_MACRO_DEPTH: usize = 4;
+
+/// Wrapper that turns macro arguments into an expression list
+const EXPR_PREFIX: &str = "const _: () = [";
+const EXPR_SUFFIX: &str = "];";
+
+/// Kind of construct to search for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum QueryKind {
+    /// Free functions, methods and trait method declarations
+    Function,
+    /// Struct definitions
+    Struct,
+    /// Enum definitions
+    Enum,
+    /// Trait definitions
+    Trait,
+    /// Impl blocks, matched by trait or by type
+    Impl,
+    /// Function, associated function and method calls
+    Call,
+}
+
+impl FromStr for QueryKind {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.trim().to_lowercase().trim_end_matches('s') {
+            "function" | "fn" | "method" => Ok(QueryKind::Function),
+            "struct" => Ok(QueryKind::Struct),
+            "enum" => Ok(QueryKind::Enum),
+            "trait" => Ok(QueryKind::Trait),
+            "impl" => Ok(QueryKind::Impl),
+            "call" => Ok(QueryKind::Call),
+            _ => Err(anyhow!(
+                "Unknown kind '{}': expected function, struct, enum, trait, impl or call",
+                s
+            )),
+        }
+    }
+}
+
+impl fmt::Display for QueryKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            QueryKind::Function => "function",
+            QueryKind::Struct => "struct",
+            QueryKind::Enum => "enum",
+            QueryKind::Trait => "trait",
+            QueryKind::Impl => "impl",
+            QueryKind::Call => "call",
+        })
+    }
+}
+
+/// A construct found by a query
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CodeMatch {
+    /// First line, 1-indexed
+    pub start_line: usize,
+
+    /// Last line, 1-indexed
+    pub end_line: usize,
+
+    /// What was found, e.g. `impl Strategy for Planner` or
+    /// `Repository::open(..) in fn load`
+    pub description: String,
+}
+
+/// Name pattern of a query: a `::` path where `*` matches any characters,
+/// compared with the trailing segments of a path in the code
+#[derive(Debug, Clone)]
+struct NamePattern(Regex);
+
+impl NamePattern {
+    fn new(pattern: &str) -> Result<Self> {
+        let pattern: String = pattern.chars().filter(|c| !c.is_whitespace()).collect();
+        let regex = pattern
+            .split('*')
+            .map(regex::escape)
+            .collect::<Vec<_>>()
+            .join(".*");
+        Ok(Self(Regex::new(&format!("^{}$", regex))?))
+    }
+
+    /// Whether `path`, with generic arguments dropped, ends in the pattern
+    fn matches(&self, path: &str) -> bool {
+        let mut plain = String::new();
+        let mut depth = 0usize;
+        for c in path.chars().filter(|c| !c.is_whitespace()) {
+            match c {
+                '<' => depth += 1,
+                '>' => depth = depth.saturating_sub(1),
+                _ if depth == 0 => plain.push(c),
+                _ => {}
+            }
+        }
+        let segments: Vec<&str> = plain.split("::").filter(|s| !s.is_empty()).collect();
+        (0..segments.len()).any(|i| self.0.is_match(&segments[i..].join("::")))
+    }
+}
+
+/// Matches of `kind` in the Rust `source`, optionally restricted to names
+/// matching `name`
+pub fn query_source(source: &str, kind: QueryKind, name: Option<&str>) -> Result<Vec<CodeMatch>> {
+    let mut search = Search::new(kind, name.map(NamePattern::new).transpose()?)?;
+    search.source(source, 0, None, 0);
+    Ok(search.matches)
+}
+
+/// One query over one or more sources
+struct Search {
+    kind: QueryKind,
+    name: Option<NamePattern>,
+    parser: Parser,
+    matches: Vec<CodeMatch>,
+}
+
+impl Search {
+    fn new(kind: QueryKind, name: Option<NamePattern>) -> Result<Self> {
+        Ok(Self {
+            kind,
+            name,
+            parser: rust_parser()?,
+            matches: Vec::new(),
+        })
+    }
+
+    fn name_matches(&self, path: &str) -> bool {
+        self.name.as_ref().is_none_or(|name| name.matches(path))
+    }
+
+    /// Search `source`, whose first line is line `line_offset` (0-indexed)
+    /// of the file, inside function `function`
+    fn source(&mut self, source: &str, line_offset: usize, function: Option<&str>, depth: usize) {
+        let Some(tree) = self.parser.parse(source, None) else {
+            return;
+        };
+        let scope = Scope {
+            source,
+            line_offset,
+            function,
+            owner: None,
+            depth,
+        };
+        self.visit(tree.root_node(), &scope);
+    }
+
+    fn push(&mut self, node: Node, scope: &Scope, description: String) {
+        self.matches.push(CodeMatch {
+            start_line: scope.line_offset + node.start_position().row + 1,
+            end_line: scope.line_offset + node.end_position().row + 1,
+            description,
+        });
+    }
+
+    fn visit(&mut self, node: Node, scope: &Scope) {
+        let field = |name: &str| node.child_by_field_name(name).map(|n| scope.text(n));
+        let mut inner = scope.clone();
+        match node.kind() {
+            "function_item" | "function_signature_item" => {
+                let name = field("name").unwrap_or_default();
+                if self.kind == QueryKind::Function && self.name_matches(name) {
+                    let qualified = match scope.owner {
+                        Some(owner) => format!("{}::{}", owner, name),
+                        None => name.to_string(),
+                    };
+                    self.push(node, scope, format!("fn {}", qualified));
+                }
+                inner.function = Some(name);
+            }
+            kind @ ("struct_item" | "enum_item" | "trait_item") => {
+                let name = field("name").unwrap_or_default();
+                let wanted = match kind {
+                    "struct_item" => QueryKind::Struct,
+                    "enum_item" => QueryKind::Enum,
+                    _ => QueryKind::Trait,
+                };
+                if self.kind == wanted && self.name_matches(name) {
+                    self.push(node, scope, format!("{} {}", wanted, name));
+                }
+                inner.owner = Some(name);
+            }
+            "impl_item" => {
+                let trait_name = field("trait");
+                let type_name = field("type").unwrap_or_default();
+                if self.kind == QueryKind::Impl
+                    && (trait_name.is_some_and(|t| self.name_matches(t))
+                        || self.name_matches(type_name))
+                {
+                    let header = match trait_name {
+                        Some(t) => format!("impl {} for {}", t, type_name),
+                        None => format!("impl {}", type_name),
+                    };
+                    self.push(node, scope, header);
+                }
+                inner.owner = Some(type_name);
+            }
+            "call_expression" => {
+                if let Some(callee) = node.child_by_field_name("function") {
+                    let callee = match callee.kind() {
+                        "generic_function" => callee.child_by_field_name("function"),
+                        _ => Some(callee),
+                    };
+                    let path = callee.map(|c| match c.kind() {
+                        "field_expression" => {
+                            c.child_by_field_name("field").map_or("", |f| scope.text(f))
+                        }
+                        _ => scope.text(c),
+                    });
+                    if let Some(path) =
+                        path.filter(|p| self.kind == QueryKind::Call && self.name_matches(p))
+                    {
+                        let mut description = format!("{}(..)", compact(path));
+                        if let Some(function) = scope.function {
+                            description.push_str(&format!(" in fn {}", function));
+                        }
+                        self.push(node, scope, description);
+                    }
+                }
+            }
+            "macro_invocation" => {
+                if scope.depth < MAX_MACRO_DEPTH {
+                    let mut cursor = node.walk();
+                    let tokens = node
+                        .children(&mut cursor)
+                        .find(|child| child.kind() == "token_tree");
+                    if let Some(tokens) = tokens {
+                        self.macro_arguments(tokens, scope);
+                    }
+                }
+                return;
+            }
+            // Bodies of `macro_rules!` are patterns, not code
+            "macro_definition" => return,
+            _ => {}
+        }
+
+        let mut cursor = node.walk();
+        for child in node.named_children(&mut cursor) {
+            self.visit(child, &inner);
+        }
+    }
+
+    /// Search the arguments of a macro invocation, parsed as items when
+    /// they are valid items and as a list of expressions otherwise
+    fn macro_arguments(&mut self, tokens: Node, scope: &Scope) {
+        let text = scope.text(tokens);
+        let inner = text
+            .get(1..text.len().saturating_sub(1))
+            .unwrap_or_default();
+        let line_offset = scope.line_offset + tokens.start_position().row;
+        let depth = scope.depth + 1;
+        let as_items = self
+            .parser
+            .parse(inner, None)
+            .is_some_and(|tree| !tree.root_node().has_error());
+        if as_items {
+            self.source(inner, line_offset, scope.function, depth);
+        } else {
+            // The wrapper adds no lines, so line numbers stay the same
+            let wrapped = format!("{}{}{}", EXPR_PREFIX, inner, EXPR_SUFFIX);
+            self.source(&wrapped, line_offset, scope.function, depth);
+        }
+    }
+}
+
+/// Where in a file the search currently is
+#[derive(Clone)]
+struct Scope<'s> {
+    source: &'s str,
+    line_offset: usize,
+    /// Enclosing function, named in call matches
+    function: Option<&'s str>,
+    /// Enclosing impl, trait or type, used to qualify method names
+    owner: Option<&'s str>,
+    depth: usize,
+}
+
+impl<'s> Scope<'s> {
+    fn text(&self, node: Node) -> &'s str {
+        self.source.get(node.byte_range()).unwrap_or_default()
+    }
+}
+
+/// `text` on one line with runs of whitespace removed
+fn compact(text: &str) -> String {
+    text.split_whitespace().collect()
+}
+
+/// A tool that finds Rust constructs by their syntax
+pub struct CodeQueryTool {
+    workspace: PathBuf,
+}
+
+impl CodeQueryTool {
+    /// Create a new structural code search tool
+    pub fn new(workspace: PathBuf) -> Self {
+        Self { workspace }
+    }
+}
+
+/// A tree-sitter parser for Rust
+pub(crate) fn rust_parser() -> Result<Parser> {
+    let mut parser = Parser::new();
+    parser
+        .set_language(&tree_sitter_rust::LANGUAGE.into())
+        .map_err(|e| anyhow!("Failed to load the Rust grammar: {}", e))?;
+    Ok(parser)
+}
+
+/// Rust files under `root`, skipping target/ and ignored files
+pub(crate) fn rust_files(root: &Path) -> Vec<PathBuf> {
+    if root.is_file() {
+        return vec![root.to_path_buf()];
+    }
+    ignore::WalkBuilder::new(root)
+        .require_git(false)
+        .filter_entry(|entry| {
+            !(entry.file_type().is_some_and(|t| t.is_dir()) && entry.file_name() == "target")
+        })
+        .sort_by_file_name(|a, b| a.cmp(b))
+        .build()
+        .filter_map(|entry| match entry {
+            Ok(entry) => Some(entry.into_path()),
+            Err(e) => {
+                warn!("Error reading directory entry: {}", e);
+                None
+            }
+        })
+        .filter(|path| path.extension().is_some_and(|ext| ext == "rs"))
+        .collect()
+}
+
+#[async_trait]
+impl LlmTool for CodeQueryTool {
+    fn name(&self) -> &str {
+        "CodeQuery"
+    }
+
+    fn is_read_only(&self) -> bool {
+        true
+    }
+
+    fn description(&self) -> &str {
+        "Find Rust functions, structs, enums, traits, impls or calls by syntax rather than by text, including multi-line and macro-wrapped code. Returns file:line spans, e.g. kind=impl name=Strategy for all impls of Strategy, or kind=call name=Repository::open for every call to it and the function making it."
+    }
+
+    fn parameters(&self) -> Vec<ToolParameter> {
+        vec![
+            ToolParameter {
+                name: "kind".to_string(),
+                description: "What to find: function, struct, enum, trait, impl or call"
+                    .to_string(),
+                required: true,
+                default_value: None,
+                param_type: Some(ToolParameterType::String),
+            },
+            ToolParameter {
+                name: "name".to_string(),
+                description: "Optional name or path to match, e.g. Strategy, Repository::open or parse_*; a path matches its trailing segments, impls match by trait or type".to_string(),
+                required: false,
+                default_value: None,
+                param_type: Some(ToolParameterType::String),
+            },
+            ToolParameter {
+                name: "path".to_string(),
+                description: "File or directory to search, relative to the workspace".to_string(),
+                required: false,
+                default_value: Some(".".to_string()),
+                param_type: Some(ToolParameterType::String),
+            },
+        ]
+    }
+
+    async fn execute(&self, args: &ToolArgs) -> Result<String> {
+        let kind: QueryKind = str_arg(args, "kind")
+            .ok_or_else(|| anyhow!("kind parameter is required"))?
+            .parse()?;
+        let name = str_arg(args, "name").filter(|n| !n.trim().is_empty());
+        let path = str_arg(args, "path")
+            .filter(|p| !p.is_empty())
+            .unwrap_or_else(|| ".".to_string());
+        let root = WorkspaceGuard::new(&self.workspace).resolve(&path)?;
+        if !root.exists() {
+            return Err(anyhow!("Path not found: {}", path));
+        }
+
+        let mut search = Search::new(kind, name.as_deref().map(NamePattern::new).transpose()?)?;
+        let mut lines = Vec::new();
+        let mut total = 0;
+        for file in rust_files(&root) {
+            let Ok(source) = std::fs::read_to_string(&file) else {
+                continue;
+            };
+            search.source(&source, 0, None, 0);
+            let display = file
+                .strip_prefix(&self.workspace)
+                .unwrap_or(&file)
+                .display()
+                .to_string();
+            for m in search.matches.drain(..) {
+                total += 1;
+                if lines.len() >= MAX_MATCHES {
+                    continue;
+                }
+                let span = if m.start_line == m.end_line {
+                    m.start_line.to_string()
+                } else {
+                    format!("{}-{}", m.start_line, m.end_line)
+                };
+                lines.push(format!("{}:{} {}", display, span, m.description));
+            }
+        }
+
+        let target = match &name {
+            Some(name) => format!("{} '{}'", kind, name),
+            None => kind.to_string(),
+        };
+        if lines.is_empty() {
+            return Ok(format!("No {} found in {}", target, path));
+        }
+        let mut output = format!("Found {} match(es) for {} in {}:\n", total, target, path);
+        for line in &lines {
+            output.push_str(line);
+            output.push('\n');
+        }
+        if total > lines.len() {
+            output.push_str(&format!("... {} more matches\n", total - lines.len()));
+        }
+        Ok(output)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SOURCE: &str = r#"
+pub trait Strategy {
+    fn plan(&self) -> Plan;
+}
+
+impl crate::core::strategy::Strategy for Planner {
+    fn plan(&self) -> Plan {
+        let repo = git2::Repository::open(
+            &self.path,
+        )
+        .unwrap();
+        Plan::default()
+    }
+}
+
+impl Planner {
+    fn load(&self) {
+        assert!(Repository::open(".").is_ok(), "{}", describe(self));
+        self.repo.open();
+    }
+}
+"#;
+
+    fn query(kind: QueryKind, name: Option<&str>) -> Vec<(usize, usize, String)> {
+        query_source(SOURCE, kind, name)
+            .unwrap()
+            .into_iter()
+            .map(|m| (m.start_line, m.end_line, m.description))
+            .collect()
+    }
+
+    #[test]
+    fn test_queries_match_syntax() {
+        assert_eq!(
+            query(QueryKind::Impl, Some("Strategy")),
+            vec![(
+                6,
+                14,
+                "impl crate::core::strategy::Strategy for Planner".to_string()
+            )]
+        );
+        assert_eq!(
+            query(QueryKind::Function, Some("plan")),
+            vec![
+                (3, 3, "fn Strategy::plan".to_string()),
+                (7, 13, "fn Planner::plan".to_string())
+            ]
+        );
+        assert_eq!(query(QueryKind::Trait, None).len(), 1);
+
+        // Multi-line calls, calls inside macro arguments and method calls
+        assert_eq!(
+            query(QueryKind::Call, Some("Repository::open")),
+            vec![
+                (8, 10, "git2::Repository::open(..) in fn plan".to_string()),
+                (18, 18, "Repository::open(..) in fn load".to_string())
+            ]
+        );
+        assert_eq!(
+            query(QueryKind::Call, Some("open")).len(),
+            3,
+            "method calls match by name"
+        );
+        assert_eq!(
+            query(QueryKind::Call, Some("desc*")),
+            vec![(18, 18, "describe(..) in fn load".to_string())]
+        );
+        assert!("widget".parse::<QueryKind>().is_err());
+        assert_eq!("impls".parse::<QueryKind>().unwrap(), QueryKind::Impl);
+    }
+
+    #[test]
+    fn test_items_generated_by_macros_are_found() {
+        let source = "cfg_if::cfg_if! {\n    if #[cfg(unix)] {\n        fn a() {}\n    }\n}\nthread_local! {\n    static X: u8 = 0;\n}\nmod m {\n    pub fn b() {\n        helper!(fn nested() { go(); });\n    }\n}\n";
+        let calls = query_source(source, QueryKind::Call, Some("go")).unwrap();
+        assert_eq!(calls.len(), 1);
+        assert_eq!(calls[0].start_line, 11);
+        let functions = query_source(source, QueryKind::Function, None).unwrap();
+        let names: Vec<&str> = functions.iter().map(|m| m.description.as_str()).collect();
+        assert!(names.contains(&"fn b"), "{:?}", names);
+        assert!(names.contains(&"fn nested"), "{:?}", names);
+    }
+}
@@ -0,0 +1,483 @@
+//! Selection of the files whose contents go into a goal's context.
+//!
+//! Goals only name their files through `file:` tags, and many name none.
+//! `ContextBuilder` ranks every source file of the workspace against the goal
+//! with three signals: how often the file mentions the goal's identifiers
+//! (weighted by how rare they are), how often recent commits changed it
+//! together with the goal's files or with a message mentioning the goal,
+//! and, when a `SimilaritySource` such as an embedding index is attached,
+//! how close it is in meaning. The best files are read until the token
+//! budget is spent; the ones that no longer fit whole are shortened by the
+//! configured `TruncationStrategy`.
+
+use anyhow::Result;
+use async_trait::async_trait;
+use log::{debug, warn};
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::Arc;
+
+use crate::code_generation::language::source_files;
+use crate::code_generation::repo_map::{looks_like_code, mentioned_identifiers};
+use crate::core::config::{ContextConfig, TruncationStrategy};
+use crate::core::costs::estimate_tokens;
+
+/// Words too common in goal descriptions to say which files matter
+const STOPWORDS: &str = "\
+    the and for with that this from into when should must make add fix use \
+    code file files function test tests all are not new can its has have \
+    more less than then also category priority improve improvement support";
+
+/// Weights of the grep, git history and similarity signals
+const GREP_WEIGHT: f64 = 1.0;
+const HISTORY_WEIGHT: f64 = 0.5;
+const SIMILARITY_WEIGHT: f64 = 1.0;
+
+/// Smallest excerpt worth adding when a file does not fit whole
+const MIN_EXCERPT_TOKENS: usize = 100;
+
+/// Lines kept on each side of a relevant line
+const CONTEXT_LINES: usize = 8;
+
+/// Ranks workspace files by meaning, e.g. with an embedding index
+#[async_trait]
+pub trait SimilaritySource: Send + Sync {
+    /// Up to `limit` workspace-relative paths of the files closest to
+    /// `query`, with a score that is higher for closer files
+    async fn similar_files(&self, query: &str, limit: usize) -> Result<Vec<(String, f64)>>;
+}
+
+/// Files selected for a goal, most relevant first
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RetrievedContext {
+    /// Source files and their (possibly shortened) contents
+    pub files: Vec<(String, String)>,
+
+    /// Test files and their (possibly shortened) contents
+    pub tests: Vec<(String, String)>,
+}
+
+/// Picks and reads the files most relevant to a goal
+pub struct ContextBuilder {
+    workspace: PathBuf,
+    config: ContextConfig,
+    similarity: Option<Arc<dyn SimilaritySource>>,
+}
+
+impl ContextBuilder {
+    /// Builder for the files of `workspace`
+    pub fn new(workspace: PathBuf, config: ContextConfig) -> Self {
+        Self {
+            workspace,
+            config,
+            similarity: None,
+        }
+    }
+
+    /// Also rank files by their similarity to the goal according to
+    /// `source`
+    pub fn with_similarity(mut self, source: Arc<dyn SimilaritySource>) -> Self {
+        self.similarity = Some(source);
+        self
+    }
+
+    /// The budgets and truncation files are selected with
+    pub fn config(&self) -> &ContextConfig {
+        &self.config
+    }
+
+    /// The contents of `focus_files` and of the files most relevant to
+    /// `goal`, within the configured budgets
+    pub async fn build(&self, goal: &str, focus_files: &[String]) -> Result<RetrievedContext> {
+        let files: Vec<(String, String)> = source_files(&self.workspace)
+            .into_iter()
+            .filter_map(|path| {
+                let content = std::fs::read_to_string(&path).ok()?;
+                let relative = path.strip_prefix(&self.workspace).unwrap_or(&path);
+                Some((relative.to_string_lossy().to_string(), content))
+            })
+            .collect();
+        let keywords = keywords(goal);
+        let words: HashSet<String> = keywords.keys().cloned().collect();
+
+        let mut scores: HashMap<String, f64> = HashMap::new();
+        let mut add = |signal: HashMap<String, f64>, weight: f64| {
+            let max = signal.values().cloned().fold(0.0, f64::max);
+            if max > 0.0 {
+                for (path, score) in signal {
+                    *scores.entry(path).or_default() += weight * score / max;
+                }
+            }
+        };
+        add(grep_scores(&files, &keywords), GREP_WEIGHT);
+        add(self.history_scores(&keywords, focus_files), HISTORY_WEIGHT);
+        if let Some(similarity) = &self.similarity {
+            match similarity
+                .similar_files(goal, self.config.max_files * 2)
+                .await
+            {
+                Ok(similar) => add(similar.into_iter().collect(), SIMILARITY_WEIGHT),
+                Err(e) => warn!("Similarity search failed: {:#}", e),
+            }
+        }
+
+        let known: HashSet<&str> = files.iter().map(|(path, _)| path.as_str()).collect();
+        let mut ranked: Vec<(&str, f64)> = scores
+            .iter()
+            .filter(|(path, score)| {
+                **score > 0.0 && known.contains(path.as_str()) && !is_focus(path, focus_files)
+            })
+            .map(|(path, score)| (path.as_str(), *score))
+            .collect();
+        ranked.sort_by(|a, b| b.1.total_cmp(&a.1).then(a.0.cmp(b.0)));
+        ranked.truncate(self.config.max_files);
+        debug!("Files ranked for the goal: {:?}", ranked);
+
+        // The goal's own files first, whatever their score
+        let contents: HashMap<&str, &str> = files
+            .iter()
+            .map(|(path, content)| (path.as_str(), content.as_str()))
+            .collect();
+        let order = focus_files
+            .iter()
+            .map(String::as_str)
+            .filter(|path| contents.contains_key(path))
+            .chain(ranked.iter().map(|(path, _)| *path));
+
+        let mut context = RetrievedContext::default();
+        let (mut source_budget, mut test_budget) =
+            (self.config.max_tokens, self.config.max_test_tokens);
+        for path in order {
+            let (selected, budget) = if is_test_file(path) {
+                (&mut context.tests, &mut test_budget)
+            } else {
+                (&mut context.files, &mut source_budget)
+            };
+            let content = contents[path];
+            let tokens = estimate_tokens(content) as usize;
+            let content = if tokens <= *budget {
+                content.to_string()
+            } else if *budget >= MIN_EXCERPT_TOKENS {
+                match truncate(content, &words, *budget, self.config.truncation) {
+                    Some(excerpt) => excerpt,
+                    None => continue,
+                }
+            } else {
+                continue;
+            };
+            *budget = budget.saturating_sub(estimate_tokens(&content) as usize);
+            selected.push((path.to_string(), content));
+        }
+        Ok(context)
+    }
+
+    /// Files changed in recent commits whose message mentions a keyword or
+    /// that also changed one of `focus_files`, by number of such commits
+    fn history_scores(
+        &self,
+        keywords: &HashMap<String, f64>,
+        focus_files: &[String],
+    ) -> HashMap<String, f64> {
+        let output = Command::new("git")
+            .current_dir(&self.workspace)
+            .arg("log")
+            .arg("--max-count")
+            .arg(self.config.history_commits.to_string())
+            .arg("--name-only")
+            .arg("--pretty=format:%x00%s")
+            .output();
+        let log = match output {
+            Ok(output) if output.status.success() => {
+                String::from_utf8_lossy(&output.stdout).to_string()
+            }
+            _ => return HashMap::new(),
+        };
+
+        let mut scores: HashMap<String, f64> = HashMap::new();
+        for commit in log.split('\0').filter(|c| !c.trim().is_empty()) {
+            let mut lines = commit.lines();
+            let subject = lines.next().unwrap_or_default().to_lowercase();
+            let changed: Vec<&str> = lines.map(str::trim).filter(|l| !l.is_empty()).collect();
+            let mentions = keywords
+                .keys()
+                .filter(|k| subject.contains(k.as_str()))
+                .count();
+            let co_changed = changed.iter().any(|path| is_focus(path, focus_files));
+            let score = mentions as f64 + if co_changed { 1.0 } else { 0.0 };
+            if score > 0.0 {
+                for path in changed {
+                    *scores.entry(path.to_string()).or_default() += score;
+                }
+            }
+        }
+        scores
+    }
+}
+
+/// Lowercased identifiers and words of `goal` that can point at files,
+/// with identifiers spelled like code weighing more than plain words
+fn keywords(goal: &str) -> HashMap<String, f64> {
+    mentioned_identifiers(goal)
+        .into_iter()
+        .map(|word| {
+            let weight = if looks_like_code(&word) { 2.0 } else { 1.0 };
+            (word.to_lowercase(), weight)
+        })
+        .filter(|(word, _)| !STOPWORDS.split_whitespace().any(|stop| stop == word))
+        .collect()
+}
+
+/// Grep signal: per file, the sum over keywords of how rare the keyword is
+/// across files times the log of how often the file mentions it, with
+/// mentions in the path counting double
+fn grep_scores(
+    files: &[(String, String)],
+    keywords: &HashMap<String, f64>,
+) -> HashMap<String, f64> {
+    let lowered: Vec<(String, String)> = files
+        .iter()
+        .map(|(path, content)| (path.to_lowercase(), content.to_lowercase()))
+        .collect();
+    let mut scores = HashMap::new();
+    for (keyword, weight) in keywords {
+        let counts: Vec<usize> = lowered
+            .iter()
+            .map(|(_, content)| content.matches(keyword.as_str()).count())
+            .collect();
+        let containing = counts.iter().filter(|&&count| count > 0).count();
+        if containing == 0 {
+            continue;
+        }
+        let rarity = (files.len() as f64 / containing as f64).ln() + 1.0;
+        for ((path, _), ((lower_path, _), count)) in files.iter().zip(lowered.iter().zip(counts)) {
+            let in_path = if lower_path.contains(keyword.as_str()) {
+                2.0
+            } else {
+                0.0
+            };
+            let score = weight * rarity * ((count as f64).ln_1p() + in_path);
+            if score > 0.0 {
+                *scores.entry(path.clone()).or_default() += score;
+            }
+        }
+    }
+    scores
+}
+
+fn is_focus(path: &str, focus_files: &[String]) -> bool {
+    focus_files
+        .iter()
+        .any(|focus| Path::new(focus) == Path::new(path))
+}
+
+/// Whether `path` holds tests rather than code under test
+pub fn is_test_file(path: &str) -> bool {
+    let path = Path::new(path);
+    let stem = path
+        .file_stem()
+        .map(|s| s.to_string_lossy().to_string())
+        .unwrap_or_default();
+    path.components()
+        .any(|c| c.as_os_str() == "tests" || c.as_os_str() == "__tests__")
+        || stem.starts_with("test_")
+        || stem.ends_with("_test")
+        || stem.ends_with("_tests")
+        // app.test.ts, app.spec.js
+        || stem.ends_with(".test")
+        || stem.ends_with(".spec")
+}
+
+/// `content` shortened to about `max_tokens` tokens with `strategy`, or
+/// `None` when the strategy leaves such files out
+pub fn truncate(
+    content: &str,
+    keywords: &HashSet<String>,
+    max_tokens: usize,
+    strategy: TruncationStrategy,
+) -> Option<String> {
+    let lines: Vec<&str> = content.lines().collect();
+    match strategy {
+        TruncationStrategy::Skip => None,
+        TruncationStrategy::Head => Some(head(&lines, max_tokens)),
+        TruncationStrategy::Relevant => {
+            let relevant: Vec<usize> = lines
+                .iter()
+                .enumerate()
+                .filter(|(_, line)| {
+                    let line = line.to_lowercase();
+                    keywords.iter().any(|k| line.contains(k.as_str()))
+                })
+                .map(|(index, _)| index)
+                .collect();
+            if relevant.is_empty() {
+                return Some(head(&lines, max_tokens));
+            }
+            Some(excerpts(&lines, &relevant, max_tokens))
+        }
+    }
+}
+
+/// The first lines of a file that fit `max_tokens`
+fn head(lines: &[&str], max_tokens: usize) -> String {
+    let mut out = String::new();
+    for (index, line) in lines.iter().enumerate() {
+        let marker = omitted(index + 1, lines.len());
+        if estimate_tokens(&out) + estimate_tokens(line) + estimate_tokens(&marker) + 1
+            > max_tokens as u64
+        {
+            out.push_str(&marker);
+            return out;
+        }
+        out.push_str(line);
+        out.push('\n');
+    }
+    out
+}
+
+/// The lines around the `relevant` line indexes that fit `max_tokens`,
+/// with markers where lines were left out
+fn excerpts(lines: &[&str], relevant: &[usize], max_tokens: usize) -> String {
+    // Merge the windows around relevant lines
+    let mut windows: Vec<(usize, usize)> = Vec::new();
+    for &index in relevant {
+        let start = index.saturating_sub(CONTEXT_LINES);
+        let end = (index + CONTEXT_LINES + 1).min(lines.len());
+        match windows.last_mut() {
+            Some(last) if start <= last.1 => last.1 = end,
+            _ => windows.push((start, end)),
+        }
+    }
+
+    let mut out = String::new();
+    let mut next = 0;
+    for (start, end) in windows {
+        if start > next {
+            out.push_str(&omitted(next + 1, start));
+        }
+        for (offset, line) in lines[start..end].iter().enumerate() {
+            if estimate_tokens(&out) + estimate_tokens(line) + 12 > max_tokens as u64 {
+                out.push_str(&omitted(start + offset + 1, lines.len()));
+                return out;
+            }
+            out.push_str(line);
+            out.push('\n');
+        }
+        next = end;
+    }
+    if next < lines.len() {
+        out.push_str(&omitted(next + 1, lines.len()));
+    }
+    out
+}
+
+/// Marker for lines `first` to `last` (1-indexed) left out
+fn omitted(first: usize, last: usize) -> String {
+    format!("// ... lines {}-{} omitted\n", first, last)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write(dir: &Path, path: &str, content: &str) {
+        let path = dir.join(path);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, content).unwrap();
+    }
+
+    struct Fixed(Vec<(String, f64)>);
+
+    #[async_trait]
+    impl SimilaritySource for Fixed {
+        async fn similar_files(&self, _query: &str, _limit: usize) -> Result<Vec<(String, f64)>> {
+            Ok(self.0.clone())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_relevant_files_are_selected_within_budget() {
+        let dir = tempfile::tempdir().unwrap();
+        let ws = dir.path();
+        write(
+            ws,
+            "src/lib.rs",
+            "pub mod planner;\npub mod cache;\npub mod util;\n",
+        );
+        write(
+            ws,
+            "src/planner.rs",
+            "pub struct RetryPlanner;\nimpl RetryPlanner {\n    pub fn backoff(&self) {}\n}\n",
+        );
+        write(ws, "src/cache.rs", "pub struct Cache;\n");
+        let long: String = (0..400)
+            .map(|i| format!("fn filler_{}() {{}}\n", i))
+            .collect();
+        write(
+            ws,
+            "src/util.rs",
+            &format!("{}fn uses() {{ RetryPlanner.backoff(); }}\n{}", long, long),
+        );
+        write(
+            ws,
+            "tests/planner.rs",
+            "#[test]\nfn backoff_grows() { RetryPlanner.backoff(); }\n",
+        );
+
+        let config = ContextConfig {
+            max_tokens: 400,
+            ..ContextConfig::default()
+        };
+        let builder = ContextBuilder::new(ws.to_path_buf(), config.clone());
+        let context = builder
+            .build(
+                "Make RetryPlanner backoff exponential",
+                &["src/lib.rs".to_string()],
+            )
+            .await
+            .unwrap();
+        let paths: Vec<&str> = context.files.iter().map(|(p, _)| p.as_str()).collect();
+        assert_eq!(paths, vec!["src/lib.rs", "src/planner.rs", "src/util.rs"]);
+        assert_eq!(context.tests[0].0, "tests/planner.rs");
+
+        // The long file is cut down to the lines around the mention
+        let util = &context.files[2].1;
+        assert!(util.contains("RetryPlanner.backoff()"), "{}", util);
+        assert!(util.starts_with("// ... lines 1-392 omitted\n"), "{}", util);
+        let total: u64 = context.files.iter().map(|(_, c)| estimate_tokens(c)).sum();
+        assert!(total <= 400, "{}", total);
+
+        // Dropped entirely when files that do not fit are skipped
+        let skipping = ContextBuilder::new(
+            ws.to_path_buf(),
+            ContextConfig {
+                truncation: TruncationStrategy::Skip,
+                ..config.clone()
+            },
+        );
+        let context = skipping
+            .build("Make RetryPlanner backoff exponential", &[])
+            .await
+            .unwrap();
+        assert!(context.files.iter().all(|(p, _)| p != "src/util.rs"));
+
+        // Similarity alone can bring in a file the goal never names
+        let similar = ContextBuilder::new(ws.to_path_buf(), config)
+            .with_similarity(Arc::new(Fixed(vec![("src/cache.rs".to_string(), 0.9)])));
+        let context = similar.build("Speed things up", &[]).await.unwrap();
+        assert_eq!(context.files[0].0, "src/cache.rs");
+    }
+
+    #[test]
+    fn test_head_truncation_keeps_the_start() {
+        let content: String = (1..=100).map(|i| format!("line {}\n", i)).collect();
+        let out = truncate(&content, &HashSet::new(), 30, TruncationStrategy::Head).unwrap();
+        assert!(out.starts_with("line 1\nline 2\n"));
+        assert!(out.ends_with("omitted\n"), "{}", out);
+        assert!(estimate_tokens(&out) <= 30);
+        assert!(is_test_file("tests/api.rs") && is_test_file("src/parser_test.rs"));
+        assert!(is_test_file("web/app.test.ts") && is_test_file("pkg/server_test.go"));
+        assert!(is_test_file("tests/test_views.py") && !is_test_file("app/views.py"));
+        assert!(!is_test_file("src/testing/mod.rs"));
+    }
+}
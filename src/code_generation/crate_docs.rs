@@ -0,0 +1,531 @@
+//! crates.io and docs.rs lookup tools.
+//!
+//! `CrateSearch` and `CrateInfo` query the crates.io API so the model can
+//! pick crates, versions and feature flags that exist; `DocsRs` fetches
+//! the rustdoc page of an item and returns it as plain text, so APIs are
+//! looked up rather than remembered.
+
+use anyhow::{anyhow, Context, Result};
+use async_trait::async_trait;
+use log::info;
+use serde_json::Value as JsonValue;
+
+use crate::code_generation::llm_tool::{
+    parsed_arg, str_arg, LlmTool, ToolArgs, ToolParameter, ToolParameterType,
+};
+
+const CRATES_IO_API: &str = "https://crates.io/api/v1";
+const DOCS_RS: &str = "https://docs.rs";
+
+/// Longest docs.rs page returned, in characters
+const MAX_DOC_CHARS: usize = 12_000;
+
+/// rustdoc page prefixes, tried in order when resolving an item path
+const ITEM_KINDS: &[&str] = &[
+    "struct", "enum", "trait", "fn", "macro", "type", "constant", "static", "union", "attr",
+    "derive",
+];
+
+/// HTTP client for crates.io and docs.rs
+#[derive(Clone)]
+pub struct CratesClient {
+    http: reqwest::Client,
+    api_base: String,
+    docs_base: String,
+}
+
+impl CratesClient {
+    /// Client for the public crates.io API and docs.rs
+    pub fn new() -> Self {
+        // crates.io rejects requests without an identifying user agent
+        let http = reqwest::Client::builder()
+            .user_agent(concat!(
+                "borg/",
+                env!("CARGO_PKG_VERSION"),
+                " (autonomous coding agent)"
+            ))
+            .timeout(std::time::Duration::from_secs(30))
+            .build()
+            .unwrap_or_else(|_| reqwest::Client::new());
+        Self {
+            http,
+            api_base: CRATES_IO_API.to_string(),
+            docs_base: DOCS_RS.to_string(),
+        }
+    }
+
+    /// Use other crates.io API and docs.rs base URLs (mirrors, tests)
+    pub fn with_base_urls(
+        mut self,
+        api_base: impl Into<String>,
+        docs_base: impl Into<String>,
+    ) -> Self {
+        self.api_base = api_base.into().trim_end_matches('/').to_string();
+        self.docs_base = docs_base.into().trim_end_matches('/').to_string();
+        self
+    }
+
+    async fn get_json(&self, path: &str) -> Result<JsonValue> {
+        let url = format!("{}{}", self.api_base, path);
+        let response = self
+            .http
+            .get(&url)
+            .send()
+            .await
+            .with_context(|| format!("Failed to query {}", url))?;
+        let status = response.status();
+        if status == reqwest::StatusCode::NOT_FOUND {
+            return Err(anyhow!("Not found on crates.io"));
+        }
+        if !status.is_success() {
+            return Err(anyhow!("crates.io returned HTTP {}", status.as_u16()));
+        }
+        response
+            .json()
+            .await
+            .context("Invalid response from crates.io")
+    }
+
+    /// docs.rs page body, or `None` if the page does not exist
+    async fn get_page(&self, path: &str) -> Result<Option<String>> {
+        let url = format!("{}{}", self.docs_base, path);
+        let response = self
+            .http
+            .get(&url)
+            .send()
+            .await
+            .with_context(|| format!("Failed to fetch {}", url))?;
+        let status = response.status();
+        if status == reqwest::StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        if !status.is_success() {
+            return Err(anyhow!("docs.rs returned HTTP {}", status.as_u16()));
+        }
+        Ok(Some(response.text().await?))
+    }
+}
+
+impl Default for CratesClient {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A tool that searches crates.io
+pub struct CrateSearchTool {
+    client: CratesClient,
+}
+
+impl CrateSearchTool {
+    /// Create a new crate search tool
+    pub fn new(client: CratesClient) -> Self {
+        Self { client }
+    }
+}
+
+#[async_trait]
+impl LlmTool for CrateSearchTool {
+    fn name(&self) -> &str {
+        "CrateSearch"
+    }
+
+    fn is_read_only(&self) -> bool {
+        true
+    }
+
+    fn description(&self) -> &str {
+        "Search crates.io for crates matching a query. Returns names, latest versions, download counts and descriptions."
+    }
+
+    fn parameters(&self) -> Vec<ToolParameter> {
+        vec![
+            ToolParameter {
+                name: "query".to_string(),
+                description: "Search terms, e.g. 'async http client'".to_string(),
+                required: true,
+                default_value: None,
+                param_type: Some(ToolParameterType::String),
+            },
+            ToolParameter {
+                name: "limit".to_string(),
+                description: "Maximum number of crates to list".to_string(),
+                required: false,
+                default_value: Some("10".to_string()),
+                param_type: Some(ToolParameterType::Integer),
+            },
+        ]
+    }
+
+    async fn execute(&self, args: &ToolArgs) -> Result<String> {
+        let query = str_arg(args, "query").ok_or_else(|| anyhow!("Search query is required"))?;
+        let query = query.trim();
+        if query.is_empty() {
+            return Err(anyhow!("Search query cannot be empty"));
+        }
+        let limit = parsed_arg::<usize>(args, "limit")
+            .unwrap_or(10)
+            .clamp(1, 50);
+
+        info!("Searching crates.io: {}", query);
+        let body = self
+            .client
+            .get_json(&format!(
+                "/crates?q={}&per_page={}",
+                urlencoding::encode(query),
+                limit
+            ))
+            .await?;
+        let crates = body["crates"].as_array().cloned().unwrap_or_default();
+        if crates.is_empty() {
+            return Ok(format!("No crates found for '{}'", query));
+        }
+
+        let mut output = format!("Crates matching '{}':\n", query);
+        for (idx, krate) in crates.iter().enumerate() {
+            output.push_str(&format!(
+                "{}. {} {} ({} downloads)\n",
+                idx + 1,
+                krate["name"].as_str().unwrap_or("?"),
+                latest_version(krate),
+                krate["downloads"].as_u64().unwrap_or_default()
+            ));
+            if let Some(description) = krate["description"].as_str() {
+                output.push_str(&format!("   {}\n", one_line(description)));
+            }
+        }
+        Ok(output)
+    }
+}
+
+/// A tool that describes one crate: versions, feature flags, links
+pub struct CrateInfoTool {
+    client: CratesClient,
+}
+
+impl CrateInfoTool {
+    /// Create a new crate info tool
+    pub fn new(client: CratesClient) -> Self {
+        Self { client }
+    }
+}
+
+#[async_trait]
+impl LlmTool for CrateInfoTool {
+    fn name(&self) -> &str {
+        "CrateInfo"
+    }
+
+    fn is_read_only(&self) -> bool {
+        true
+    }
+
+    fn description(&self) -> &str {
+        "Look up a crate on crates.io: its latest stable version, recent versions, minimum Rust version and feature flags. \
+         Use before adding or upgrading a dependency."
+    }
+
+    fn parameters(&self) -> Vec<ToolParameter> {
+        vec![
+            ToolParameter {
+                name: "name".to_string(),
+                description: "Exact crate name, e.g. 'tokio'".to_string(),
+                required: true,
+                default_value: None,
+                param_type: Some(ToolParameterType::String),
+            },
+            ToolParameter {
+                name: "version".to_string(),
+                description: "Version to describe; the latest stable one when omitted".to_string(),
+                required: false,
+                default_value: None,
+                param_type: Some(ToolParameterType::String),
+            },
+        ]
+    }
+
+    async fn execute(&self, args: &ToolArgs) -> Result<String> {
+        let name = str_arg(args, "name").ok_or_else(|| anyhow!("Crate name is required"))?;
+        let name = name.trim();
+        let body = self
+            .client
+            .get_json(&format!("/crates/{}", urlencoding::encode(name)))
+            .await
+            .with_context(|| format!("Failed to look up crate '{}'", name))?;
+        let krate = &body["crate"];
+        let versions = body["versions"].as_array().cloned().unwrap_or_default();
+
+        let requested = str_arg(args, "version").filter(|v| !v.trim().is_empty());
+        let number = requested
+            .clone()
+            .unwrap_or_else(|| latest_version(krate).to_string());
+        let mut version = versions
+            .iter()
+            .find(|v| v["num"].as_str() == Some(number.as_str()))
+            .cloned();
+        // Older API responses list versions without their features
+        if version.as_ref().is_none_or(|v| v.get("features").is_none()) {
+            version = self
+                .client
+                .get_json(&format!(
+                    "/crates/{}/{}",
+                    urlencoding::encode(name),
+                    urlencoding::encode(&number)
+                ))
+                .await
+                .ok()
+                .map(|body| body["version"].clone());
+        }
+        let Some(version) = version.filter(|v| !v.is_null()) else {
+            return Err(anyhow!("Crate '{}' has no version {}", name, number));
+        };
+
+        let mut output = format!(
+            "{} {}{}\n",
+            krate["name"].as_str().unwrap_or(name),
+            number,
+            if requested.is_none() {
+                " (latest stable)"
+            } else {
+                ""
+            }
+        );
+        if version["yanked"].as_bool() == Some(true) {
+            output.push_str("WARNING: this version has been yanked\n");
+        }
+        if let Some(description) = krate["description"].as_str() {
+            output.push_str(&format!("Description: {}\n", one_line(description)));
+        }
+        for (label, key) in [
+            ("Documentation", "documentation"),
+            ("Repository", "repository"),
+        ] {
+            if let Some(url) = krate[key].as_str() {
+                output.push_str(&format!("{}: {}\n", label, url));
+            }
+        }
+        if let Some(msrv) = version["rust_version"].as_str() {
+            output.push_str(&format!("Minimum Rust version: {}\n", msrv));
+        }
+        let recent: Vec<&str> = versions
+            .iter()
+            .filter(|v| v["yanked"].as_bool() != Some(true))
+            .filter_map(|v| v["num"].as_str())
+            .take(5)
+            .collect();
+        if !recent.is_empty() {
+            output.push_str(&format!("Recent versions: {}\n", recent.join(", ")));
+        }
+
+        match version["features"].as_object() {
+            Some(features) if !features.is_empty() => {
+                output.push_str("Features:\n");
+                for (feature, enables) in features {
+                    let enables: Vec<&str> = enables
+                        .as_array()
+                        .map(|list| list.iter().filter_map(JsonValue::as_str).collect())
+                        .unwrap_or_default();
+                    output.push_str(&format!("  {} = [{}]\n", feature, enables.join(", ")));
+                }
+            }
+            _ => output.push_str("Features: none\n"),
+        }
+        Ok(output)
+    }
+}
+
+/// A tool that fetches the docs.rs page of a crate item as text
+pub struct DocsRsTool {
+    client: CratesClient,
+}
+
+impl DocsRsTool {
+    /// Create a new docs.rs tool
+    pub fn new(client: CratesClient) -> Self {
+        Self { client }
+    }
+}
+
+#[async_trait]
+impl LlmTool for DocsRsTool {
+    fn name(&self) -> &str {
+        "DocsRs"
+    }
+
+    fn is_read_only(&self) -> bool {
+        true
+    }
+
+    fn description(&self) -> &str {
+        "Fetch the docs.rs documentation of a crate item (module, struct, enum, trait, function, macro...) as plain text, \
+         including its signatures and methods."
+    }
+
+    fn parameters(&self) -> Vec<ToolParameter> {
+        vec![
+            ToolParameter {
+                name: "crate_name".to_string(),
+                description: "Crate to look in, e.g. 'tokio'".to_string(),
+                required: true,
+                default_value: None,
+                param_type: Some(ToolParameterType::String),
+            },
+            ToolParameter {
+                name: "item_path".to_string(),
+                description: "Path of the item, e.g. 'sync::Mutex' or 'tokio::sync::Mutex'; the crate root when omitted".to_string(),
+                required: false,
+                default_value: None,
+                param_type: Some(ToolParameterType::String),
+            },
+            ToolParameter {
+                name: "version".to_string(),
+                description: "Crate version".to_string(),
+                required: false,
+                default_value: Some("latest".to_string()),
+                param_type: Some(ToolParameterType::String),
+            },
+        ]
+    }
+
+    async fn execute(&self, args: &ToolArgs) -> Result<String> {
+        let crate_name =
+            str_arg(args, "crate_name").ok_or_else(|| anyhow!("Crate name is required"))?;
+        let crate_name = crate_name.trim();
+        let item_path = str_arg(args, "item_path").unwrap_or_default();
+        let version = str_arg(args, "version")
+            .filter(|v| !v.trim().is_empty())
+            .unwrap_or_else(|| "latest".to_string());
+
+        let root = format!(
+            "/{}/{}/{}",
+            urlencoding::encode(crate_name),
+            urlencoding::encode(&version),
+            crate_name.replace('-', "_")
+        );
+        let candidates = page_candidates(crate_name, &item_path);
+        for candidate in &candidates {
+            let path = format!("{}/{}", root, candidate);
+            if let Some(html) = self.client.get_page(&path).await? {
+                info!("Fetched docs.rs page {}", path);
+                let text = truncate_chars(&rustdoc_text(&html), MAX_DOC_CHARS);
+                return Ok(format!("{}{}\n\n{}", self.client.docs_base, path, text));
+            }
+        }
+        Err(anyhow!(
+            "No docs.rs page for '{}' in {} {}; fetch the parent module to see the items it contains",
+            item_path,
+            crate_name,
+            version
+        ))
+    }
+}
+
+fn latest_version(krate: &JsonValue) -> &str {
+    ["max_stable_version", "newest_version", "max_version"]
+        .iter()
+        .find_map(|key| krate[*key].as_str())
+        .unwrap_or("?")
+}
+
+fn one_line(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// rustdoc pages that may document `item_path`, relative to the crate
+/// root, most likely first
+pub fn page_candidates(crate_name: &str, item_path: &str) -> Vec<String> {
+    let ident = crate_name.replace('-', "_");
+    let mut segments: Vec<&str> = item_path
+        .split("::")
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .collect();
+    if segments.first() == Some(&ident.as_str()) || segments.first() == Some(&"crate") {
+        segments.remove(0);
+    }
+    let Some((item, modules)) = segments.split_last() else {
+        return vec!["index.html".to_string()];
+    };
+    let prefix: String = modules.iter().map(|m| format!("{}/", m)).collect();
+
+    let module = format!("{}{}/index.html", prefix, item);
+    let items = ITEM_KINDS
+        .iter()
+        .map(|kind| format!("{}{}.{}.html", prefix, kind, item));
+    // Types are capitalised, modules and functions are not
+    if item.starts_with(char::is_uppercase) {
+        items.chain(std::iter::once(module)).collect()
+    } else {
+        std::iter::once(module).chain(items).collect()
+    }
+}
+
+/// Readable text of the main content of a rustdoc page
+pub fn rustdoc_text(html: &str) -> String {
+    let start = html
+        .find("id=\"main-content\"")
+        .and_then(|i| html[..i].rfind('<'))
+        .unwrap_or(0);
+    let end = html[start..]
+        .find("</main>")
+        .map_or(html.len(), |i| start + i);
+    let mut text = html[start..end].to_string();
+
+    let replacements = [
+        // Page chrome and source links
+        (r"(?is)<script[^>]*>.*?</script>", ""),
+        (r"(?is)<style[^>]*>.*?</style>", ""),
+        (r"(?is)<nav[^>]*>.*?</nav>", ""),
+        (r#"(?is)<a[^>]*class="src[^"]*"[^>]*>.*?</a>"#, ""),
+        (r"(?is)<button[^>]*>.*?</button>", ""),
+        // Block boundaries become line breaks
+        (r"(?i)<br\s*/?>", "\n"),
+        (
+            r"(?i)</(p|div|pre|h[1-6]|li|tr|summary|section|details)>",
+            "\n",
+        ),
+        (r"(?i)<li[^>]*>", "- "),
+        (r"<[^>]+>", ""),
+    ];
+    for (pattern, replacement) in replacements {
+        let re = regex::Regex::new(pattern).expect("valid regex");
+        text = re.replace_all(&text, replacement).into_owned();
+    }
+
+    let text = text
+        .replace("&nbsp;", " ")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&#x27;", "'")
+        .replace("&amp;", "&");
+
+    let mut output = String::new();
+    let mut blank = true;
+    for line in text.lines() {
+        let line = line.trim_end();
+        if line.trim().is_empty() {
+            if !blank {
+                output.push('\n');
+            }
+            blank = true;
+            continue;
+        }
+        output.push_str(line);
+        output.push('\n');
+        blank = false;
+    }
+    output.trim().to_string()
+}
+
+fn truncate_chars(text: &str, max: usize) -> String {
+    match text.char_indices().nth(max) {
+        Some((end, _)) => format!(
+            "{}\n\n[Documentation truncated, showing first {} characters]",
+            &text[..end],
+            max
+        ),
+        None => text.to_string(),
+    }
+}
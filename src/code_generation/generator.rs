@@ -1,6 +1,8 @@
-use anyhow::Result;
+use anyhow::{bail, Result};
 use async_trait::async_trait;
 
+use crate::code_generation::patch::{apply_hunks, parse_unified_diff, FileReport, PatchReport};
+
 /// A trait representing a code generator that can propose code improvements
 #[async_trait]
 pub trait CodeGenerator: Send + Sync {
@@ -163,6 +165,10 @@ pub struct CodeImprovement {
 }
 
 /// A change to be applied to a file
+///
+/// `new_content` is one of: a unified diff (see [`FileChange::is_diff`]),
+/// the text replacing lines `start_line..=end_line` when `start_line` is
+/// set, or else the whole new file.
 #[derive(Debug, Clone)]
 pub struct FileChange {
     /// The path to the file
@@ -171,9 +177,236 @@ pub struct FileChange {
     /// The starting line number (1-indexed)
     pub start_line: Option<usize>,
 
-    /// The ending line number (1-indexed)
+    /// The ending line number (1-indexed, inclusive); `start_line - 1`
+    /// inserts before `start_line`, and `None` replaces `start_line` only
     pub end_line: Option<usize>,
 
     /// The new content
     pub new_content: String,
 }
+
+/// Comments models write in place of code they left out of a file
+const ELISION_MARKERS: &[&str] = &[
+    "... existing code",
+    "...existing code",
+    "rest of the code",
+    "rest of the file",
+    "rest of file",
+    "remaining code",
+    "unchanged code",
+    "code unchanged",
+];
+
+impl FileChange {
+    /// Whether `new_content` is a unified diff rather than replacement text
+    pub fn is_diff(&self) -> bool {
+        let content = self.new_content.trim_start();
+        content.starts_with("diff --git")
+            || content.starts_with("@@ ")
+            || (content.starts_with("--- ") && content.contains("\n+++ "))
+    }
+
+    /// The content of the file after the change, given its current content
+    /// (empty for a new file)
+    ///
+    /// Fails rather than writing a damaged file: when a diff hunk does not
+    /// match, a line range lies outside the file, or a whole-file rewrite
+    /// of an existing file leaves code out behind an "unchanged" comment.
+    pub fn apply(&self, original: &str) -> Result<String> {
+        if self.is_diff() {
+            self.apply_diff(original)
+        } else if let Some(start) = self.start_line {
+            self.apply_range(original, start)
+        } else {
+            if !original.is_empty() {
+                if let Some(line) = self.new_content.lines().find(|line| is_elision(line)) {
+                    bail!(
+                        "The new content of {} leaves code out ('{}'); send a diff or a line range instead",
+                        self.file_path,
+                        line.trim()
+                    );
+                }
+            }
+            Ok(self.new_content.clone())
+        }
+    }
+
+    fn apply_diff(&self, original: &str) -> Result<String> {
+        let diff = if self.new_content.trim_start().starts_with("@@") {
+            format!(
+                "--- a/{0}\n+++ b/{0}\n{1}",
+                self.file_path, self.new_content
+            )
+        } else {
+            self.new_content.clone()
+        };
+        let patches = parse_unified_diff(&diff).map_err(anyhow::Error::msg)?;
+        // A diff block may cover several files; apply the part for this one
+        let patch = match patches.as_slice() {
+            [patch] => patch,
+            _ => match patches.iter().find(|p| p.display_path() == self.file_path) {
+                Some(patch) => patch,
+                None => bail!("The diff has no changes for {}", self.file_path),
+            },
+        };
+        let (content, outcomes) = apply_hunks(original, &patch.hunks);
+        match content {
+            Some(content) => Ok(content),
+            None => {
+                let report = PatchReport {
+                    files: vec![FileReport {
+                        path: self.file_path.clone(),
+                        action: "modify",
+                        hunks: outcomes,
+                    }],
+                };
+                bail!("The diff does not apply:\n{}", report)
+            }
+        }
+    }
+
+    fn apply_range(&self, original: &str, start: usize) -> Result<String> {
+        let end = self.end_line.unwrap_or(start);
+        let mut lines: Vec<&str> = original.lines().collect();
+        if start == 0 || end + 1 < start || end > lines.len() {
+            bail!(
+                "Line range {}-{} is outside {}, which has {} lines",
+                start,
+                end,
+                self.file_path,
+                lines.len()
+            );
+        }
+        lines.splice(start - 1..end, self.new_content.lines());
+
+        let mut content = lines.join("\n");
+        if (original.is_empty() || original.ends_with('\n')) && !content.is_empty() {
+            content.push('\n');
+        }
+        Ok(content)
+    }
+}
+
+/// Split a `path (lines 3-7)` file header into the path and the line range
+/// it names, if any
+pub fn split_line_range(header: &str) -> (String, Option<(usize, usize)>) {
+    let re = regex::Regex::new(r"(?i)^(.*?)\s*\(?\s*lines?\s+(\d+)(?:\s*(?:-|to)\s*(\d+))?\s*\)?$")
+        .unwrap();
+    let header = header.trim();
+    match re.captures(header) {
+        Some(cap) if !cap[1].is_empty() => {
+            let start: usize = cap[2].parse().unwrap_or(0);
+            let end = cap
+                .get(3)
+                .and_then(|end| end.as_str().parse().ok())
+                .unwrap_or(start);
+            (cap[1].to_string(), Some((start, end)))
+        }
+        _ => (header.to_string(), None),
+    }
+}
+
+/// Whether `line` is a comment standing in for code left out
+fn is_elision(line: &str) -> bool {
+    let line = line.trim();
+    let Some(comment) = line
+        .strip_prefix("//")
+        .or_else(|| line.strip_prefix("/*"))
+        .or_else(|| line.strip_prefix('#'))
+    else {
+        return false;
+    };
+    let comment = comment.to_lowercase();
+    ELISION_MARKERS
+        .iter()
+        .any(|marker| comment.contains(marker))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn change(start: Option<usize>, end: Option<usize>, content: &str) -> FileChange {
+        FileChange {
+            file_path: "src/a.rs".to_string(),
+            start_line: start,
+            end_line: end,
+            new_content: content.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_split_line_range() {
+        assert_eq!(
+            split_line_range("src/a.rs (lines 3-7)"),
+            ("src/a.rs".to_string(), Some((3, 7)))
+        );
+        assert_eq!(
+            split_line_range("src/a.rs lines 4 to 4"),
+            ("src/a.rs".to_string(), Some((4, 4)))
+        );
+        assert_eq!(
+            split_line_range("src/a.rs (line 9)"),
+            ("src/a.rs".to_string(), Some((9, 9)))
+        );
+        assert_eq!(
+            split_line_range(" src/a.rs "),
+            ("src/a.rs".to_string(), None)
+        );
+    }
+
+    const FILE: &str = "fn a() {}\nfn b() {}\nfn c() {}\n";
+
+    #[test]
+    fn test_ranges_replace_insert_and_stay_in_bounds() {
+        assert_eq!(
+            change(Some(2), Some(2), "fn b2() {}\nfn b3() {}")
+                .apply(FILE)
+                .unwrap(),
+            "fn a() {}\nfn b2() {}\nfn b3() {}\nfn c() {}\n"
+        );
+        assert_eq!(
+            change(Some(1), None, "").apply(FILE).unwrap(),
+            "fn b() {}\nfn c() {}\n"
+        );
+        // An empty range inserts
+        assert_eq!(
+            change(Some(4), Some(3), "fn d() {}\n").apply(FILE).unwrap(),
+            "fn a() {}\nfn b() {}\nfn c() {}\nfn d() {}\n"
+        );
+        assert!(change(Some(3), Some(4), "x").apply(FILE).is_err());
+        assert!(change(Some(0), Some(1), "x").apply(FILE).is_err());
+    }
+
+    #[test]
+    fn test_diffs_apply_by_content_and_reject_mismatches() {
+        let diff = "@@ -7,2 +7,2 @@\n fn a() {}\n-fn b() {}\n+fn b() { todo!() }\n";
+        assert_eq!(
+            change(None, None, diff).apply(FILE).unwrap(),
+            "fn a() {}\nfn b() { todo!() }\nfn c() {}\n"
+        );
+
+        let multi = "--- a/src/z.rs\n+++ b/src/z.rs\n@@ -1 +1 @@\n-z\n+y\n\
+                     --- a/src/a.rs\n+++ b/src/a.rs\n@@ -3 +3 @@\n-fn c() {}\n+fn c() -> u8 { 0 }\n";
+        assert_eq!(
+            change(None, None, multi).apply(FILE).unwrap(),
+            "fn a() {}\nfn b() {}\nfn c() -> u8 { 0 }\n"
+        );
+
+        let stale = "@@ -1 +1 @@\n-fn x() {}\n+fn y() {}\n";
+        let err = change(None, None, stale).apply(FILE).unwrap_err();
+        assert!(err.to_string().contains("FAILED"), "{}", err);
+    }
+
+    #[test]
+    fn test_whole_file_rewrites_must_not_elide_code() {
+        let truncated = "fn a() {}\n// ... rest of the file unchanged\n";
+        assert!(change(None, None, truncated).apply(FILE).is_err());
+        // A new file has nothing to leave out
+        assert_eq!(change(None, None, truncated).apply("").unwrap(), truncated);
+        assert_eq!(
+            change(None, None, "fn z() {}\n").apply(FILE).unwrap(),
+            "fn z() {}\n"
+        );
+    }
+}
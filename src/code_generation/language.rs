@@ -0,0 +1,275 @@
+//! Languages the agent can work in.
+//!
+//! A file's language follows from its extension and a workspace's from the
+//! manifests at its root (`Cargo.toml`, `go.mod`, `package.json`, ...).
+//! Each language comes with commands that check that the code builds and
+//! run its tests; `testing.languages` in the config replaces them.
+
+use log::warn;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use crate::core::config::LanguageCommands;
+
+/// A programming language
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Language {
+    Rust,
+    Python,
+    Go,
+    #[serde(rename = "javascript")]
+    JavaScript,
+    #[serde(rename = "typescript")]
+    TypeScript,
+}
+
+impl Language {
+    /// Every supported language
+    pub const ALL: [Language; 5] = [
+        Language::Rust,
+        Language::Python,
+        Language::Go,
+        Language::JavaScript,
+        Language::TypeScript,
+    ];
+
+    /// Display name
+    pub fn name(self) -> &'static str {
+        match self {
+            Language::Rust => "Rust",
+            Language::Python => "Python",
+            Language::Go => "Go",
+            Language::JavaScript => "JavaScript",
+            Language::TypeScript => "TypeScript",
+        }
+    }
+
+    /// Info string of a fenced code block in this language
+    pub fn fence(self) -> &'static str {
+        match self {
+            Language::Rust => "rust",
+            Language::Python => "python",
+            Language::Go => "go",
+            Language::JavaScript => "javascript",
+            Language::TypeScript => "typescript",
+        }
+    }
+
+    /// File extensions, the usual one first
+    pub fn extensions(self) -> &'static [&'static str] {
+        match self {
+            Language::Rust => &["rs"],
+            Language::Python => &["py", "pyi"],
+            Language::Go => &["go"],
+            Language::JavaScript => &["js", "jsx", "mjs", "cjs"],
+            Language::TypeScript => &["ts", "tsx", "mts", "cts"],
+        }
+    }
+
+    /// The usual file extension
+    pub fn extension(self) -> &'static str {
+        self.extensions()[0]
+    }
+
+    /// Start of a line comment
+    pub fn line_comment(self) -> &'static str {
+        match self {
+            Language::Python => "#",
+            _ => "//",
+        }
+    }
+
+    /// Files at the root of a workspace written in this language
+    fn manifests(self) -> &'static [&'static str] {
+        match self {
+            Language::Rust => &["Cargo.toml"],
+            Language::Python => &[
+                "pyproject.toml",
+                "setup.py",
+                "setup.cfg",
+                "requirements.txt",
+            ],
+            Language::Go => &["go.mod"],
+            Language::JavaScript => &["package.json"],
+            Language::TypeScript => &["tsconfig.json"],
+        }
+    }
+
+    /// The language of files with extension `ext`
+    pub fn from_extension(ext: &str) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|language| language.extensions().contains(&ext))
+    }
+
+    /// The language of the file at `path`
+    pub fn from_path(path: impl AsRef<Path>) -> Option<Self> {
+        path.as_ref()
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .and_then(Self::from_extension)
+    }
+
+    /// The language a code block's info string names (`py`, `golang`, ...)
+    pub fn from_fence(info: &str) -> Option<Self> {
+        let info = info.trim().to_lowercase();
+        match info.as_str() {
+            "rust" => Some(Language::Rust),
+            "python" | "python3" => Some(Language::Python),
+            "go" | "golang" => Some(Language::Go),
+            "javascript" | "node" => Some(Language::JavaScript),
+            "typescript" => Some(Language::TypeScript),
+            other => Self::from_extension(other),
+        }
+    }
+
+    /// Commands used unless `testing.languages` replaces them
+    pub fn default_commands(self) -> LanguageCommands {
+        let (check, test) = match self {
+            Language::Rust => (Some("cargo check --all-targets"), "cargo test"),
+            Language::Python => (Some("python3 -m compileall -q ."), "python3 -m pytest -q"),
+            Language::Go => (Some("go vet ./..."), "go test ./..."),
+            Language::JavaScript => (None, "npm test"),
+            Language::TypeScript => (Some("npx tsc --noEmit"), "npm test"),
+        };
+        LanguageCommands {
+            check: check.map(str::to_string),
+            test: Some(test.to_string()),
+        }
+    }
+}
+
+impl fmt::Display for Language {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// The languages of the workspace at `root`, by the manifests at its root
+///
+/// A `tsconfig.json` makes a `package.json` project TypeScript rather than
+/// JavaScript. A workspace without any manifest is taken to be Rust.
+pub fn workspace_languages(root: &Path) -> Vec<Language> {
+    let mut languages: Vec<Language> = Language::ALL
+        .into_iter()
+        .filter(|language| {
+            language
+                .manifests()
+                .iter()
+                .any(|manifest| root.join(manifest).is_file())
+        })
+        .collect();
+    if languages.contains(&Language::TypeScript) {
+        languages.retain(|language| *language != Language::JavaScript);
+    }
+    if languages.is_empty() {
+        languages.push(Language::Rust);
+    }
+    languages
+}
+
+/// Directories holding build output or dependencies rather than sources
+const SKIPPED_DIRS: &[&str] = &[
+    "target",
+    "node_modules",
+    "vendor",
+    "dist",
+    "build",
+    "__pycache__",
+    ".venv",
+    "venv",
+];
+
+/// Source files in any supported language under `root`, skipping build
+/// output, dependencies and ignored files
+pub fn source_files(root: &Path) -> Vec<PathBuf> {
+    if root.is_file() {
+        return vec![root.to_path_buf()];
+    }
+    ignore::WalkBuilder::new(root)
+        .require_git(false)
+        .filter_entry(|entry| {
+            !(entry.file_type().is_some_and(|t| t.is_dir())
+                && entry
+                    .file_name()
+                    .to_str()
+                    .is_some_and(|name| SKIPPED_DIRS.contains(&name)))
+        })
+        .sort_by_file_name(|a, b| a.cmp(b))
+        .build()
+        .filter_map(|entry| match entry {
+            Ok(entry) => Some(entry.into_path()),
+            Err(e) => {
+                warn!("Error reading directory entry: {}", e);
+                None
+            }
+        })
+        .filter(|path| path.is_file() && Language::from_path(path).is_some())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_languages_by_extension_and_fence() {
+        assert_eq!(Language::from_path("src/lib.rs"), Some(Language::Rust));
+        assert_eq!(Language::from_path("app/views.py"), Some(Language::Python));
+        assert_eq!(
+            Language::from_path("web/App.tsx"),
+            Some(Language::TypeScript)
+        );
+        assert_eq!(Language::from_path("README.md"), None);
+        assert_eq!(Language::from_fence("py"), Some(Language::Python));
+        assert_eq!(Language::from_fence("golang"), Some(Language::Go));
+        assert_eq!(Language::from_fence("diff"), None);
+    }
+
+    #[test]
+    fn test_workspace_languages_follow_manifests() {
+        let dir = tempfile::tempdir().unwrap();
+        assert_eq!(workspace_languages(dir.path()), vec![Language::Rust]);
+
+        std::fs::write(dir.path().join("go.mod"), "module x\n").unwrap();
+        std::fs::write(dir.path().join("package.json"), "{}").unwrap();
+        std::fs::write(dir.path().join("pyproject.toml"), "").unwrap();
+        assert_eq!(
+            workspace_languages(dir.path()),
+            vec![Language::Python, Language::Go, Language::JavaScript]
+        );
+
+        std::fs::write(dir.path().join("tsconfig.json"), "{}").unwrap();
+        assert_eq!(
+            workspace_languages(dir.path()),
+            vec![Language::Python, Language::Go, Language::TypeScript]
+        );
+    }
+
+    #[test]
+    fn test_source_files_skip_dependencies() {
+        let dir = tempfile::tempdir().unwrap();
+        for file in [
+            "src/lib.rs",
+            "app/main.py",
+            "node_modules/dep/index.js",
+            "notes.txt",
+        ] {
+            let path = dir.path().join(file);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, "").unwrap();
+        }
+        let files: Vec<_> = source_files(dir.path())
+            .into_iter()
+            .map(|path| {
+                path.strip_prefix(dir.path())
+                    .unwrap()
+                    .to_string_lossy()
+                    .to_string()
+            })
+            .collect();
+        assert_eq!(files, vec!["app/main.py", "src/lib.rs"]);
+    }
+}
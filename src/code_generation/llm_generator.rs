@@ -2,27 +2,41 @@ use anyhow::{Context, Result};
 use async_trait::async_trait;
 use log::{info, warn};
 use regex::Regex;
-use serde_json::{json, Value as JsonValue};
 use std::collections::HashMap;
-use std::io::{self, Write};
 use std::path::PathBuf;
 use std::sync::Arc;
 use tokio::sync::Mutex;
+use tokio_util::sync::CancellationToken;
 use uuid::Uuid;
 
-use crate::code_generation::generator::{CodeContext, CodeGenerator, CodeImprovement, FileChange};
+use crate::code_generation::code_query::CodeQueryTool;
+use crate::code_generation::context_builder::{ContextBuilder, SimilaritySource};
+use crate::code_generation::crate_docs::{
+    CrateInfoTool, CrateSearchTool, CratesClient, DocsRsTool,
+};
+use crate::code_generation::generator::{
+    split_line_range, CodeContext, CodeGenerator, CodeImprovement, FileChange,
+};
+use crate::code_generation::language::Language;
 use crate::code_generation::llm::{LlmFactory, LlmProvider};
 use crate::code_generation::llm_tool::{
-    BashTool, CompilationFeedbackTool, EditTool, FindTestsTool, GitCommandTool, GitHistoryTool,
-    GrepTool, LlmTool, ReadTool, TestRunnerTool, ToolCall, ToolRegistry, ToolResult, WriteTool,
+    ApplyPatchTool, BashTool, CompilationFeedbackTool, DeleteTool, EditTool, FindTestsTool,
+    GitBisectTool, GitCommandTool, GitHistoryTool, GrepTool, LlmTool, LsTool, MoveTool, ReadTool,
+    TestRunnerTool, ToolRegistry, ToolResult, WriteTool,
 };
+use crate::code_generation::lsp::LspSession;
+use crate::code_generation::memory;
+use crate::code_generation::patch::parse_unified_diff;
 use crate::code_generation::prompt::PromptManager;
-use crate::core::config::{CodeGenerationConfig, LlmConfig, LlmLoggingConfig};
-use crate::core::error::ProviderError;
+use crate::code_generation::repo_map::RepoMap;
+use crate::code_generation::semantic_index::{self, SemanticSearchTool};
+use crate::code_generation::todos;
+use crate::core::config::{CodeGenerationConfig, Config, LlmConfig, LlmLoggingConfig, ModelConfig};
+use crate::core::error::{BorgError, ProviderError};
+use crate::providers::conversation::Conversation;
 use crate::providers::{
-    ContentPart as UnifiedContentPart, GenerateRequest as UnifiedGenerateRequest,
-    Message as UnifiedMessage, Provider as UnifiedProvider, Role as UnifiedRole, StreamEvent,
-    ToolCallNormalized, ToolChoice as UnifiedToolChoice, ToolSpec as UnifiedToolSpec, Usage,
+    ContentPart as UnifiedContentPart, GenerateRequest as UnifiedGenerateRequest, GenerateResponse,
+    Provider as UnifiedProvider, StreamEvent, ToolCallNormalized, ToolChoice as UnifiedToolChoice,
 };
 use crate::version_control::git::GitManager;
 
@@ -31,9 +45,15 @@ pub struct LlmCodeGenerator {
     /// The LLM provider
     llm: Box<dyn LlmProvider>,
 
+    /// Provider for commit messages (defaults to `llm`)
+    commit_llm: Option<Arc<dyn LlmProvider>>,
+
     /// Unified provider adapter (when available; used for streaming with events/tool-calls)
     provider_adapter: Option<Box<dyn UnifiedProvider>>,
 
+    /// Model served by `provider_adapter`, for token counting
+    model: String,
+
     /// The prompt manager
     prompt_manager: PromptManager,
 
@@ -46,11 +66,23 @@ pub struct LlmCodeGenerator {
     /// Maximum number of iterations for tool usage
     max_tool_iterations: usize,
 
+    /// Most tool calls run at the same time
+    max_parallel_tools: usize,
+
     /// Whether to use tools for code generation
     use_tools: bool,
 
+    /// Token budget of the repository map, 0 to leave it out
+    repo_map_tokens: usize,
+
+    /// Picks the files shown to the model when the caller names none
+    context_builder: ContextBuilder,
+
     /// Registry of available tools
     tool_registry: ToolRegistry,
+
+    /// Aborts in-flight streaming generations when fired
+    cancel: CancellationToken,
 }
 
 impl LlmCodeGenerator {
@@ -80,11 +112,14 @@ impl LlmCodeGenerator {
                 _ => None,
             };
 
-        let prompt_manager = PromptManager::new();
+        let prompt_manager = PromptManager::new().with_model(llm_cfg_clone.model.clone());
 
         // Use configuration values or defaults
         let max_tool_iterations = code_gen_config.max_tool_iterations;
+        let max_parallel_tools = code_gen_config.max_parallel_tools;
         let use_tools = code_gen_config.use_tools;
+        let repo_map_tokens = code_gen_config.repo_map_tokens;
+        let mut context_builder = ContextBuilder::new(workspace.clone(), code_gen_config.context);
 
         // Initialize tool registry
         let mut tool_registry = ToolRegistry::new();
@@ -92,6 +127,17 @@ impl LlmCodeGenerator {
         // Register available tools
         tool_registry.register(GrepTool::new(workspace.clone(), Arc::clone(&git_manager)));
         tool_registry.register(ReadTool::new(workspace.clone()));
+        tool_registry.register(LsTool::new(workspace.clone()));
+        let (find_definition, find_references, document_symbols) =
+            Arc::new(LspSession::new(workspace.clone())).tools();
+        tool_registry.register(find_definition);
+        tool_registry.register(find_references);
+        tool_registry.register(document_symbols);
+        tool_registry.register(CodeQueryTool::new(workspace.clone()));
+        if let Some(index) = semantic_index::global() {
+            tool_registry.register(SemanticSearchTool::new(Arc::clone(&index)));
+            context_builder = context_builder.with_similarity(index);
+        }
         tool_registry.register(FindTestsTool::new(workspace.clone()));
         tool_registry.register(BashTool::new(workspace.clone()));
         tool_registry.register(BashTool::new(workspace.clone()));
@@ -102,31 +148,123 @@ impl LlmCodeGenerator {
         tool_registry.register(CompilationFeedbackTool::new(workspace.clone()));
         tool_registry.register(WriteTool::new(workspace.clone()));
         tool_registry.register(EditTool::new(workspace.clone()));
+        tool_registry.register(ApplyPatchTool::new(workspace.clone()));
+        tool_registry.register(MoveTool::new(workspace.clone()));
+        tool_registry.register(DeleteTool::new(workspace.clone()));
         tool_registry.register(GitCommandTool::new(workspace.clone()));
+        tool_registry.register(GitBisectTool::new(workspace.clone()));
+        let crates = CratesClient::new();
+        tool_registry.register(CrateSearchTool::new(crates.clone()));
+        tool_registry.register(CrateInfoTool::new(crates.clone()));
+        tool_registry.register(DocsRsTool::new(crates));
         tool_registry.register(TestRunnerTool::new(workspace.clone()));
+        tool_registry.register(todos::TodoWriteTool::new());
+        tool_registry.register(todos::TodoReadTool::new());
+        tool_registry.register(memory::RememberTool::new());
+        tool_registry.register(memory::RecallTool::new());
+        for tool in crate::mcp::global_tools() {
+            tool_registry.register(tool);
+        }
 
         Ok(Self {
             llm,
+            commit_llm: None,
             provider_adapter,
+            model: llm_cfg_clone.model.clone(),
             prompt_manager,
             git_manager,
             workspace,
             max_tool_iterations,
+            max_parallel_tools,
             use_tools,
+            repo_map_tokens,
+            context_builder,
             tool_registry,
+            cancel: CancellationToken::new(),
         })
     }
 
+    /// Create a code generator on `model`, logging its calls as the
+    /// `logging` section of `config` asks
+    pub fn from_config(
+        config: &Config,
+        model: &ModelConfig,
+        git_manager: Arc<Mutex<dyn GitManager>>,
+        workspace: PathBuf,
+    ) -> Result<Self> {
+        let mut logging = LlmFactory::logging_for_dir(&config.logging.llm_log_dir);
+        logging.enabled = config.logging.enabled;
+        Self::new(
+            LlmFactory::llm_config_for_model(model),
+            config.code_generation.clone(),
+            logging,
+            git_manager,
+            workspace,
+        )
+    }
+
+    /// Abort streaming generations when `token` is cancelled (e.g. because
+    /// the goal was cancelled or a watchdog fired)
+    pub fn with_cancellation(mut self, token: CancellationToken) -> Self {
+        self.cancel = token;
+        self
+    }
+
+    /// Write commit messages with `llm` instead of the code generation model
+    pub fn with_commit_message_llm(mut self, llm: Arc<dyn LlmProvider>) -> Self {
+        self.commit_llm = Some(llm);
+        self
+    }
+
+    /// Rank files by similarity to the goal with `source` when selecting
+    /// the context
+    pub fn with_similarity(mut self, source: Arc<dyn SimilaritySource>) -> Self {
+        self.context_builder = self.context_builder.with_similarity(source);
+        self
+    }
+
     /// Extract code from LLM response
-    fn extract_code_from_response(&self, response: &str) -> Result<Vec<FileChange>> {
-        let re = Regex::new(r"```(?:rust|rs)?\s*(?:\n|\r\n)([\s\S]*?)```").unwrap();
+    ///
+    /// A block is a unified diff, the replacement of the line range named in
+    /// its `// File: path (lines a-b)` (or `# File: ...`) header, or a whole
+    /// file. Blocks in any language are accepted.
+    fn extract_code_from_response(response: &str) -> Result<Vec<FileChange>> {
+        let re = Regex::new(r"```([\w+-]*)[ \t]*\r?\n([\s\S]*?)```").unwrap();
         let mut changes = Vec::new();
 
         // Let's start by looking for specific files called out with path comments
         let file_re = Regex::new(r#"(?i)for\s+file\s+(?:"|`)?([\w./\\-]+)(?:"|`)?|file:\s*(?:"|`)?([\w./\\-]+)(?:"|`)?|filename:\s*(?:"|`)?([\w./\\-]+)(?:"|`)?"#).unwrap();
+        let header_re = Regex::new(r"(?i)^\s*(?://|#)\s*file(?:name)?:\s*(.+?)\s*$").unwrap();
 
         for cap in re.captures_iter(response) {
-            let code_block = cap[1].to_string();
+            let code_block = cap[2].to_string();
+
+            // A header comment on the first line names the file and range
+            let mut block_lines = code_block.splitn(2, '\n');
+            if let Some(header) = block_lines.next().and_then(|l| header_re.captures(l)) {
+                let (file_path, range) = split_line_range(header[1].trim_matches('`'));
+                changes.push(FileChange {
+                    file_path,
+                    start_line: range.map(|(start, _)| start),
+                    end_line: range.map(|(_, end)| end),
+                    new_content: block_lines.next().unwrap_or_default().to_string(),
+                });
+                continue;
+            }
+
+            // A diff with file headers names its own files
+            if let Ok(patches) = parse_unified_diff(&code_block) {
+                for patch in patches {
+                    changes.push(FileChange {
+                        file_path: patch.display_path().to_string(),
+                        start_line: None,
+                        end_line: None,
+                        new_content: code_block.clone(),
+                    });
+                }
+                continue;
+            }
+
             let mut file_path = String::new();
 
             // Look for a file path in close proximity to this code block
@@ -144,9 +282,12 @@ impl LlmCodeGenerator {
                     .unwrap_or_default();
             }
 
-            // If no file path found, use a default
+            // If no file path found, use a default in the block's language
             if file_path.is_empty() {
-                file_path = "src/main.rs".to_string();
+                file_path = match Language::from_fence(&cap[1]) {
+                    Some(Language::Rust) | None => "src/main.rs".to_string(),
+                    Some(language) => format!("main.{}", language.extension()),
+                };
             }
 
             changes.push(FileChange {
@@ -195,340 +336,84 @@ impl LlmCodeGenerator {
         }
     }
 
-    /// Build provider tool specifications based on registered tools (JSON schema)
-    fn build_unified_tool_specs(&self) -> Vec<UnifiedToolSpec> {
-        let mut out = Vec::new();
-        for (name, description, params) in self.tool_registry.get_tool_specifications() {
-            // Build a simple JSON schema for parameters in declared order
-            let mut schema_obj = serde_json::Map::new();
-            schema_obj.insert("type".into(), JsonValue::String("object".into()));
-
-            let mut properties = serde_json::Map::new();
-            let mut required: Vec<String> = Vec::new();
-            for p in params {
-                // Best-effort type mapping
-                let typ = "string"; // keep string; tools parse internally
-                let mut prop = serde_json::Map::new();
-                prop.insert("type".into(), JsonValue::String(typ.to_string()));
-                if let Some(def) = p.default_value {
-                    prop.insert("default".into(), JsonValue::String(def));
-                }
-                properties.insert(p.name.clone(), JsonValue::Object(prop));
-                if p.required {
-                    required.push(p.name.clone());
-                }
-            }
-            schema_obj.insert("properties".into(), JsonValue::Object(properties));
-            if !required.is_empty() {
-                schema_obj.insert(
-                    "required".into(),
-                    JsonValue::Array(required.into_iter().map(JsonValue::String).collect()),
-                );
-            }
-
-            out.push(UnifiedToolSpec {
-                name,
-                description: Some(description),
-                json_schema: Some(JsonValue::Object(schema_obj)),
-            });
-        }
-        out
-    }
-
-    /// Map a normalized tool call to ToolRegistry argument list, using parameter specs
-    fn map_normalized_tool_to_args(&self, tc: &ToolCallNormalized) -> Vec<String> {
-        let mut args: Vec<String> = Vec::new();
-        let mut params_for_tool: Option<Vec<crate::code_generation::llm_tool::ToolParameter>> =
-            None;
-        for (n, _desc, params) in self.tool_registry.get_tool_specifications() {
-            if n == tc.name {
-                params_for_tool = Some(params);
-                break;
-            }
-        }
-        if let Some(params) = params_for_tool {
-            for p in params {
-                if let Some(v) = tc.arguments_json.get(&p.name) {
-                    if let Some(s) = v.as_str() {
-                        args.push(s.to_string());
-                    } else if let Some(b) = v.as_bool() {
-                        args.push(b.to_string());
-                    } else if let Some(n) = v.as_i64() {
-                        args.push(n.to_string());
-                    } else if let Some(n) = v.as_u64() {
-                        args.push(n.to_string());
-                    } else if let Some(n) = v.as_f64() {
-                        args.push(n.to_string());
-                    } else {
-                        args.push(v.to_string());
-                    }
-                } else if let Some(def) = p.default_value {
-                    args.push(def);
-                } else {
-                    // Missing required; push empty to preserve arity (tool may validate)
-                    args.push(String::new());
-                }
-            }
-        } else {
-            // Unknown tool; pass entire JSON as single arg for debugging
-            args.push(tc.arguments_json.to_string());
-        }
-        args
-    }
-
     /// Execute a normalized tool call via ToolRegistry (public for tests)
     pub async fn execute_normalized_tool_call(&self, tc: &ToolCallNormalized) -> ToolResult {
-        let args = self.map_normalized_tool_to_args(tc);
-        let call = ToolCall {
-            tool: tc.name.clone(),
-            args,
-        };
-        self.tool_registry.execute(&call).await
+        self.tool_registry
+            .execute_json(&tc.name, &tc.arguments_json)
+            .await
     }
 
-    /// Stream via unified provider adapter and collect events, text, and usage
-    async fn stream_with_unified_provider(
+    /// Run a native tool-calling exchange: offer every registered tool to
+    /// the model, execute the calls it makes and feed the results back until
+    /// it answers without calling a tool
+    ///
+    /// The opening prompt is resent on every iteration, so it is marked as a
+    /// cacheable prefix. Providers without native tool calling get a single
+    /// generation without tools.
+    async fn generate_with_native_tools(
         &self,
+        system: &str,
         prompt: &str,
-        max_tokens: Option<usize>,
-        temperature: Option<f32>,
-        print_tokens: bool,
-        include_tools: bool,
-    ) -> Result<(String, Vec<ToolCallNormalized>, Option<Usage>)> {
-        if self.provider_adapter.is_none() {
-            // Fallback: legacy LLM interface (no events)
-            let text = self
+        max_tokens: usize,
+        temperature: f32,
+    ) -> Result<String> {
+        let Some(provider) = self.provider_adapter.as_deref() else {
+            warn!("Provider has no native tool calling; generating without tools");
+            return self
                 .llm
-                .generate_streaming(prompt, max_tokens, temperature, print_tokens)
-                .await?;
-            return Ok((text, Vec::new(), None));
-        }
-
-        info!(
-            "Starting unified streaming (include_tools={}, print_tokens={})",
-            include_tools, print_tokens
-        );
-
-        let mut content = String::new();
-        let mut tool_calls: Vec<ToolCallNormalized> = Vec::new();
-        let mut usage: Option<Usage> = None;
-
-        // Prepare request
-        let mut req = UnifiedGenerateRequest {
-            system: Some(
-                "You are an AI assistant that helps with coding in Rust. You provide clear, concise, and correct code."
-                    .to_string(),
-            ),
-            messages: vec![UnifiedMessage {
-                role: UnifiedRole::User,
-                content: vec![UnifiedContentPart::Text {
-                    text: prompt.to_string(),
-                }],
-            }],
-            tools: None,
-            tool_choice: None,
-            temperature,
-            top_p: None,
-            stop: None,
-            seed: None,
-            logit_bias: None,
-            response_format: None,
-            max_output_tokens: max_tokens.or(Some(1024)),
-            metadata: None,
-        };
-
-        if include_tools {
-            let specs = self.build_unified_tool_specs();
-            if !specs.is_empty() {
-                req.tools = Some(specs);
-                req.tool_choice = Some(UnifiedToolChoice::Auto);
-            }
-        }
-
-        let mut stdout = io::stdout();
-        let mut tool_delta_buf = String::new();
-
-        let mut on_event = |ev: StreamEvent| match ev {
-            StreamEvent::TextDelta(d) => {
-                content.push_str(&d);
-                if print_tokens {
-                    print!("{}", d);
-                    let _ = stdout.flush();
-                }
-            }
-            StreamEvent::ToolDelta(s) => {
-                tool_delta_buf.push_str(&s);
-                // Attempt to parse partial buffer
-                if let Ok(v) = serde_json::from_str::<JsonValue>(&tool_delta_buf) {
-                    if v.get("name").is_some() {
-                        let name = v
-                            .get("name")
-                            .and_then(|x| x.as_str())
-                            .unwrap_or("tool")
-                            .to_string();
-                        let id = v.get("id").and_then(|x| x.as_str()).map(|s| s.to_string());
-                        let args = v
-                            .get("arguments")
-                            .or_else(|| v.get("input"))
-                            .cloned()
-                            .unwrap_or_else(|| json!({}));
-                        tool_calls.push(ToolCallNormalized {
-                            id,
-                            name,
-                            arguments_json: args,
-                        });
-                        tool_delta_buf.clear();
-                    }
-                }
-            }
-            StreamEvent::ToolCall(tc) => {
-                tool_calls.push(tc);
-            }
-            StreamEvent::Usage(u) => {
-                usage = Some(u);
-            }
-            StreamEvent::Finished => {
-                // no-op
-            }
-            StreamEvent::Error(msg) => {
-                warn!("Streaming error event: {}", msg);
-            }
+                .generate_streaming_cancellable(
+                    &format!("{}\n\n{}", system, prompt),
+                    Some(max_tokens),
+                    Some(temperature),
+                    false,
+                    &self.cancel,
+                )
+                .await;
         };
 
-        let res = match self
-            .provider_adapter
-            .as_ref()
-            .unwrap()
-            .generate_streaming(req, &mut on_event)
-            .await
-        {
-            Ok(r) => r,
-            Err(e) => {
-                match e.clone() {
-                    ProviderError::TimeoutFirstToken { timeout_ms } => {
-                        warn!("Streaming first token timeout after {} ms", timeout_ms);
-                    }
-                    ProviderError::TimeoutStall { timeout_ms } => {
-                        warn!("Streaming stalled for {} ms", timeout_ms);
-                    }
-                    _ => {}
-                }
-                return Err(anyhow::anyhow!(e.to_string()));
-            }
-        };
-
-        if print_tokens {
-            println!();
+        let mut conversation = Conversation::new(self.model.clone())
+            .with_system(system)
+            .with_temperature(temperature)
+            .with_max_output_tokens(max_tokens);
+        let specs = self.tool_registry.tool_specs();
+        if !specs.is_empty() {
+            conversation = conversation
+                .with_tools(specs)
+                .with_tool_choice(UnifiedToolChoice::Auto);
         }
-
-        let final_text = if res.text.is_empty() {
-            content
-        } else {
-            res.text
-        };
-
+        conversation.push_user_parts(vec![
+            UnifiedContentPart::Text {
+                text: prompt.to_string(),
+            },
+            UnifiedContentPart::CacheBreakpoint,
+        ]);
+
+        let text = run_tool_loop(
+            provider,
+            &self.model,
+            &self.tool_registry,
+            &mut conversation,
+            self.max_tool_iterations,
+            self.max_parallel_tools,
+            &self.cancel,
+        )
+        .await?;
+
+        let u = conversation.usage();
         info!(
-            "Unified streaming finished (chars={}, tool_calls={})",
-            final_text.len(),
-            tool_calls.len()
+            "Tool loop usage: prompt_tokens={:?} completion_tokens={:?} total_tokens={:?} cache_read_tokens={:?} cache_write_tokens={:?}",
+            u.prompt_tokens,
+            u.completion_tokens,
+            u.total_tokens,
+            u.cache_read_tokens,
+            u.cache_write_tokens
         );
-
-        Ok((final_text, tool_calls, usage))
-    }
-
-    /// Extract tool calls from LLM response
-    #[allow(dead_code)]
-    fn extract_tool_calls(&self, response: &str) -> Vec<ToolCall> {
-        let mut tool_calls = Vec::new();
-
-        // Look for JSON-formatted tool calls
-        let re = Regex::new(r#"\{(?:\s*)"tool"(?:\s*):(?:\s*)"([^"]+)"(?:\s*),(?:\s*)"args"(?:\s*):(?:\s*)\[(.*?)\](?:\s*)\}"#).unwrap();
-
-        for cap in re.captures_iter(response) {
-            let tool_name = cap[1].to_string();
-            let args_json = format!("[{}]", &cap[2]);
-
-            if let Ok(args) = serde_json::from_str::<Vec<String>>(&args_json) {
-                tool_calls.push(ToolCall {
-                    tool: tool_name,
-                    args,
-                });
-            }
-        }
-
-        // Look for more relaxed format (non-JSON)
-        let alt_re = Regex::new(r"(?i)use\s+tool\s+([a-z_]+)(?:\s*):(?:\s*)(.+?)(?:\n|$)").unwrap();
-        for cap in alt_re.captures_iter(response) {
-            let tool_name = cap[1].to_string();
-            let args_text = cap[2].trim();
-
-            // Simple parsing of comma-separated arguments
-            let args = args_text
-                .split(',')
-                .map(|s| s.trim().trim_matches(|c| c == '\'' || c == '"').to_string())
-                .collect();
-
-            tool_calls.push(ToolCall {
-                tool: tool_name,
-                args,
-            });
-        }
-
-        tool_calls
+        Ok(text)
     }
 
     /// Generate with tools in a conversational format
-    #[allow(clippy::cognitive_complexity)]
     async fn generate_with_tools(&self, context: &CodeContext) -> Result<String> {
-        // Create initial prompt with tool instructions
-        let mut conversation = String::new();
-
-        // System message with tool instructions
-        let mut tool_descriptions = String::new();
-        // Use detailed tool specifications instead of simple descriptions
-        for (name, description, params) in self.tool_registry.get_tool_specifications() {
-            tool_descriptions.push_str(&format!("- {}: {}\n", name, description));
-
-            // Add parameter details if there are any
-            if !params.is_empty() {
-                tool_descriptions.push_str("  Parameters:\n");
-                for param in params {
-                    let required_str = if param.required {
-                        "required"
-                    } else {
-                        "optional"
-                    };
-                    let default_str = if let Some(default) = &param.default_value {
-                        format!(" (default: {})", default)
-                    } else {
-                        String::new()
-                    };
-
-                    tool_descriptions.push_str(&format!(
-                        "    - {}: {} [{}]{}\n",
-                        param.name, param.description, required_str, default_str
-                    ));
-                }
-            }
-        }
-
-        let system_message = format!(
-            "You are a skilled Rust programmer tasked with implementing code improvements. \
-            You have access to the following tools to explore and understand the codebase:\n\n{}\n\n\
-            When you need to use a tool, make a tool call using the appropriate function from the tools provided.\n\n\
-            IMPORTANT: Use a variety of tools to thoroughly explore and understand the codebase BEFORE making changes:\n\
-            1. Use 'explore_dir' to understand the project structure and file organization\n\
-            2. Use 'file_contents' to read and analyze specific files\n\
-            3. Use 'code_search' to find specific functions, patterns, or implementations\n\
-            4. Use 'find_tests' to locate and understand existing test coverage\n\
-            5. Use 'git_history' to understand how code has evolved\n\
-            6. Use 'compile_check' to validate code snippets before implementing\n\
-            7. Use 'git_command' for version control operations when needed\n\
-            8. Finally, use 'create_file' or 'modify_file' to implement your improvements\n\n\
-            CRITICAL: Do NOT rely on a single tool. Use multiple different tools to gather comprehensive information before making any changes. \
-            This ensures you understand the full context and can make informed improvements.",
-            tool_descriptions
-        );
+        let system_message = self.prompt_manager.create_tool_system_message();
 
         // Task description
         let task = if let Some(requirements) = &context.requirements {
@@ -555,6 +440,9 @@ impl LlmCodeGenerator {
                 s.push_str(&format!("```rust\n{}\n```\n", attempt.code));
                 s.push_str(&format!("Failure reason: {}\n\n", attempt.failure_reason));
 
+                if let Some(errors) = attempt.error_messages.as_ref().filter(|e| !e.is_empty()) {
+                    s.push_str(&format!("Errors:\n{}\n\n", errors.join("\n")));
+                }
                 if let Some(test_results) = &attempt.test_results {
                     s.push_str(&format!("Test results:\n{}\n\n", test_results));
                 }
@@ -564,126 +452,75 @@ impl LlmCodeGenerator {
             String::new()
         };
 
-        // Initialize conversation
-        conversation.push_str(&system_message);
-        conversation.push_str("\n\n");
-        conversation.push_str(&task);
-        conversation.push_str(&files_section);
-        conversation.push_str(&attempts_section);
-
-        let mut final_response = String::new();
-
-        // Iterative conversation with tool usage
-        for iteration in 0..self.max_tool_iterations {
-            info!(
-                "Tool iteration {}/{}",
-                iteration + 1,
-                self.max_tool_iterations
-            );
-
-            // Generate a response (prefer unified streaming to collect ToolCall events)
-            let (response, normalized_tool_calls, usage) = if self.provider_adapter.is_some() {
-                self.stream_with_unified_provider(&conversation, Some(2048), Some(0.4), false, true)
-                    .await?
-            } else {
-                let text = self
-                    .llm
-                    .generate_streaming(&conversation, Some(2048), Some(0.4), false)
-                    .await?;
-                (text, Vec::new(), None)
-            };
+        // Let a retry pick up the todos an earlier attempt left open
+        let todos_section = todos::resume_section().await;
 
-            if let Some(u) = usage {
-                info!(
-                    "Streaming usage: prompt_tokens={:?} completion_tokens={:?} total_tokens={:?}",
-                    u.prompt_tokens, u.completion_tokens, u.total_tokens
-                );
-            }
+        // What earlier goals learned about the files to change
+        let lessons_section = memory::lessons_section(&context.file_paths).await;
 
-            // Back-compat: also scan response text for legacy inline tool-call JSON when no normalized events present
-            let legacy_tool_calls = if normalized_tool_calls.is_empty() {
-                self.tool_registry.extract_tool_calls(&response)
-            } else {
-                Vec::new()
-            };
+        let contents_section = contents_section(
+            "Relevant files",
+            &context.file_paths,
+            context.file_contents.as_ref(),
+        ) + &contents_section(
+            "Related tests",
+            context.test_files.as_deref().unwrap_or_default(),
+            context.test_contents.as_ref(),
+        );
 
-            if normalized_tool_calls.is_empty() && legacy_tool_calls.is_empty() {
-                // No tool calls, so this is the final response
-                final_response = response;
-                break;
-            }
+        let structure_section = match &context.code_structure {
+            Some(map) if !map.is_empty() => format!("## Repository map:\n{}\n", map),
+            _ => String::new(),
+        };
+
+        let prompt = format!(
+            "{}{}{}{}{}{}{}",
+            task,
+            files_section,
+            lessons_section,
+            contents_section,
+            structure_section,
+            attempts_section,
+            todos_section
+        );
+        self.generate_with_native_tools(&system_message, &prompt, 2048, 0.4)
+            .await
+    }
 
-            // Add the response to the conversation
-            conversation.push_str(&format!("\n\nYou: {}\n\n", response));
-
-            // Prefer normalized tool calls when available
-            if !normalized_tool_calls.is_empty() {
-                for tc in normalized_tool_calls {
-                    info!("Normalized tool call detected: {}", tc.name);
-                    let result = self.execute_normalized_tool_call(&tc).await;
-                    if result.success {
-                        conversation.push_str(&format!(
-                            "Tool result for {}:\n{}\n\n",
-                            tc.name, result.result
-                        ));
-                    } else {
-                        conversation.push_str(&format!(
-                            "Tool error for {}: {}\n\n",
-                            tc.name,
-                            result.error.unwrap_or_else(|| "Unknown error".to_string())
-                        ));
+    /// Enhance the context with additional information
+    async fn enhance_context(&self, context: &mut CodeContext) -> Result<()> {
+        // Select the relevant files when the caller did not provide any
+        if context.file_contents.is_none() {
+            let goal = match &context.requirements {
+                Some(requirements) => format!("{}\n{}", context.task, requirements),
+                None => context.task.clone(),
+            };
+            match self.context_builder.build(&goal, &context.file_paths).await {
+                Ok(retrieved) if !retrieved.files.is_empty() => {
+                    if context.file_paths.is_empty() {
+                        context.file_paths = retrieved
+                            .files
+                            .iter()
+                            .map(|(path, _)| path.clone())
+                            .collect();
                     }
-                }
-            } else {
-                // Legacy path
-                for tool_call in legacy_tool_calls {
-                    info!(
-                        "Tool call detected: {} with {} args",
-                        tool_call.tool,
-                        tool_call.args.len()
-                    );
-
-                    // Execute the tool
-                    let tool_result = self.tool_registry.execute(&tool_call).await;
-
-                    // Add the result to the conversation
-                    if tool_result.success {
-                        conversation.push_str(&format!(
-                            "Tool result for {}:\n{}\n\n",
-                            tool_call.tool, tool_result.result
-                        ));
-                    } else {
-                        conversation.push_str(&format!(
-                            "Tool error for {}: {}\n\n",
-                            tool_call.tool,
-                            tool_result
-                                .error
-                                .unwrap_or_else(|| "Unknown error".to_string())
-                        ));
+                    context.file_contents = Some(retrieved.files.into_iter().collect());
+                    if context.test_files.is_none() && !retrieved.tests.is_empty() {
+                        context.test_files = Some(
+                            retrieved
+                                .tests
+                                .iter()
+                                .map(|(path, _)| path.clone())
+                                .collect(),
+                        );
+                        context.test_contents = Some(retrieved.tests.into_iter().collect());
                     }
                 }
+                Ok(_) => {}
+                Err(e) => warn!("Failed to select relevant files: {:#}", e),
             }
         }
 
-        if final_response.is_empty() {
-            final_response = format!(
-                "After {} tool iterations, no final response was generated. \
-                 Please provide your code implementation now based on the information gathered.",
-                self.max_tool_iterations
-            );
-
-            // Generate one more time to get a final response
-            final_response = self
-                .llm
-                .generate(&(conversation + &final_response), Some(4096), Some(0.4))
-                .await?;
-        }
-
-        Ok(final_response)
-    }
-
-    /// Enhance the context with additional information
-    async fn enhance_context(&self, context: &mut CodeContext) -> Result<()> {
         // Add file contents if not already present
         if context.file_contents.is_none() && !context.file_paths.is_empty() {
             let mut file_contents = HashMap::new();
@@ -706,7 +543,7 @@ impl LlmCodeGenerator {
             let re = Regex::new(r"- (tests/[^\n]+|src/[^\n]+)").unwrap();
 
             for file_path in &context.file_paths {
-                let result = find_tests_tool.execute(&[file_path]).await;
+                let result = find_tests_tool.execute_positional(&[file_path]).await;
                 if let Ok(result) = result {
                     if !result.contains("No test files found") {
                         // Extract test file names from the result (regex compiled above)
@@ -736,6 +573,22 @@ impl LlmCodeGenerator {
             context.test_contents = Some(test_contents);
         }
 
+        // Outline the codebase, most relevant to the task first
+        if context.code_structure.is_none() && self.repo_map_tokens > 0 {
+            let goal = match &context.requirements {
+                Some(requirements) => format!("{}\n{}", context.task, requirements),
+                None => context.task.clone(),
+            };
+            match RepoMap::build(&self.workspace) {
+                Ok(map) if !map.is_empty() => {
+                    context.code_structure =
+                        Some(map.render(&goal, &context.file_paths, self.repo_map_tokens));
+                }
+                Ok(_) => {}
+                Err(e) => warn!("Failed to build repository map: {:#}", e),
+            }
+        }
+
         Ok(())
     }
 
@@ -746,99 +599,9 @@ impl LlmCodeGenerator {
             prompt.len()
         );
 
-        let mut conversation = vec![("system", prompt.to_string())];
-
-        let mut iterations = 0;
-
-        while iterations < self.max_tool_iterations {
-            iterations += 1;
-
-            info!("Tool iteration {}/{}", iterations, self.max_tool_iterations);
-
-            // Generate the next message from the LLM
-            let llm_response = self
-                .llm
-                .generate_streaming(&conversation[0].1, Some(4096), Some(0.5), false)
-                .await?;
-
-            // Look for tool calls in the response
-            let tool_calls = self.tool_registry.extract_tool_calls(&llm_response);
-
-            if tool_calls.is_empty() {
-                info!("No tool calls found, returning response");
-                return Ok(llm_response);
-            }
-
-            info!("Found {} tool calls", tool_calls.len());
-
-            // Add the LLM response to the conversation
-            conversation.push(("assistant", llm_response));
-
-            // Execute each tool call
-            let mut all_results = String::new();
-
-            for tool_call in tool_calls {
-                info!("Executing tool: {}", tool_call.tool);
-
-                // Execute the tool
-                let result = match self.tool_registry.execute_tool(&tool_call).await {
-                    Ok(result) => result,
-                    Err(e) => {
-                        let error_message =
-                            format!("Error executing tool {}: {}", tool_call.tool, e);
-                        info!("{}", error_message);
-                        ToolResult {
-                            success: false,
-                            result: String::new(),
-                            error: Some(error_message),
-                        }
-                    }
-                };
-
-                let result_message = if result.success {
-                    format!(
-                        "Tool '{}' execution succeeded:\n{}",
-                        tool_call.tool, result.result
-                    )
-                } else {
-                    format!(
-                        "Tool '{}' execution failed:\n{}",
-                        tool_call.tool,
-                        result.error.unwrap_or_else(|| "Unknown error".to_string())
-                    )
-                };
-
-                all_results.push_str(&result_message);
-                all_results.push_str("\n\n");
-            }
-
-            // Add the tool results to the conversation
-            conversation.push(("user", all_results));
-        }
-
-        info!(
-            "Reached maximum tool iterations ({}), returning final response",
-            self.max_tool_iterations
-        );
-
-        // Get the last assistant message as the final response
-        for (role, message) in conversation.iter().rev() {
-            if *role == "assistant" {
-                return Ok(message.clone());
-            }
-        }
-
-        // If no assistant message found, generate a final response
-        let final_prompt = format!(
-            "{}\n\nPlease provide your final response based on the conversation above.",
-            prompt
-        );
-        let final_response = self
-            .llm
-            .generate_streaming(&final_prompt, Some(4096), Some(0.5), false)
-            .await?;
-
-        Ok(final_response)
+        let system = self.prompt_manager.create_system_message();
+        self.generate_with_native_tools(&system, prompt, 4096, 0.5)
+            .await
     }
 
     /// Generate a response using the git operations prompt
@@ -858,7 +621,13 @@ impl LlmCodeGenerator {
             // Direct LLM call without tools
             let response = self
                 .llm
-                .generate_streaming(&full_prompt, Some(4096), Some(0.5), false)
+                .generate_streaming_cancellable(
+                    &full_prompt,
+                    Some(4096),
+                    Some(0.5),
+                    false,
+                    &self.cancel,
+                )
                 .await?;
             Ok(response)
         }
@@ -871,40 +640,34 @@ impl LlmCodeGenerator {
         goal_id: &str,
         branch_name: &str,
     ) -> Result<String> {
-        let prompt = self.prompt_manager.create_system_message();
-
-        // Build a list of changed files
-        let mut changed_files_desc = String::new();
-        for file in &improvement.target_files {
-            changed_files_desc.push_str(&format!("- {}\n", file.file_path));
-        }
-
-        // Create a description of the changes
-        let query = format!(
-            "I need a Git commit message for the following changes:\n\n\
-            Goal ID: {}\n\
-            Branch: {}\n\
-            Task: {}\n\n\
-            Changes made to these files:\n{}\n\n\
-            Explanation of changes:\n{}\n\n\
-            Please write a clear, concise, and informative commit message that follows Git best practices. \
-            The message should have a brief summary (50-72 chars) as the first line, followed by a blank line and \
-            a more detailed explanation if needed. Focus on WHY the change was made, not just WHAT was changed. \
-            Do not include the word 'commit' in the message.",
+        // Describe the changed files for the model
+        let files: Vec<&str> = improvement
+            .target_files
+            .iter()
+            .map(|file| file.file_path.as_str())
+            .collect();
+
+        // Direct LLM call for commit message, with that model's templates
+        let llm = self.commit_llm.as_deref().unwrap_or(self.llm.as_ref());
+        let prompts = match llm.model() {
+            Some(model) => PromptManager::new().with_model(model),
+            None => PromptManager::new(),
+        };
+        let full_prompt = prompts.create_commit_message_prompt(
             goal_id,
             branch_name,
-            improvement.task,
-            changed_files_desc,
-            improvement.explanation
+            &improvement.task,
+            &files,
+            &improvement.explanation,
         );
-
-        // Combine the prompt with the user's query
-        let full_prompt = format!("{}\n\n{}", prompt, query);
-
-        // Direct LLM call for commit message
-        let response = self
-            .llm
-            .generate_streaming(&full_prompt, Some(1024), Some(0.4), false)
+        let response = llm
+            .generate_streaming_cancellable(
+                &full_prompt,
+                Some(1024),
+                Some(0.4),
+                false,
+                &self.cancel,
+            )
             .await?;
 
         // Extract just the commit message (removing any explanations the LLM might add)
@@ -955,6 +718,31 @@ impl LlmCodeGenerator {
     }
 }
 
+/// Prompt section with the contents of `paths` (in that order) followed by
+/// the other files of `contents`, empty when there are none
+fn contents_section(
+    title: &str,
+    paths: &[String],
+    contents: Option<&HashMap<String, String>>,
+) -> String {
+    let Some(contents) = contents.filter(|contents| !contents.is_empty()) else {
+        return String::new();
+    };
+    let mut rest: Vec<&String> = contents
+        .keys()
+        .filter(|path| !paths.contains(path))
+        .collect();
+    rest.sort();
+    let mut s = format!("## {}:\n", title);
+    for path in paths.iter().chain(rest) {
+        if let Some(content) = contents.get(path) {
+            s.push_str(&format!("### {}\n```rust\n{}\n```\n", path, content));
+        }
+    }
+    s.push('\n');
+    s
+}
+
 #[async_trait]
 impl CodeGenerator for LlmCodeGenerator {
     async fn generate_improvement(&self, context: &CodeContext) -> Result<CodeImprovement> {
@@ -974,13 +762,23 @@ impl CodeGenerator for LlmCodeGenerator {
             // Standard approach for first attempt
             info!("Using standard approach for code generation");
 
-            // Fetch content of all relevant files
-            let current_code = self
-                .fetch_code_content(context.file_paths.first().unwrap())
-                .await?;
+            // Fetch content of the main file, selected by the context
+            // builder when the caller named none
+            let main_file = enhanced_context
+                .file_paths
+                .first()
+                .context("No files are relevant to the task")?;
+            let current_code = match enhanced_context
+                .file_contents
+                .as_ref()
+                .and_then(|contents| contents.get(main_file))
+            {
+                Some(content) => content.clone(),
+                None => self.fetch_code_content(main_file).await?,
+            };
 
             // Determine the appropriate prompt type based on the task description
-            let prompt = if context.task.to_lowercase().contains("bug")
+            let mut prompt = if context.task.to_lowercase().contains("bug")
                 || context.task.to_lowercase().contains("fix")
             {
                 info!("Using bugfix prompt for task: {}", context.task);
@@ -1009,6 +807,18 @@ impl CodeGenerator for LlmCodeGenerator {
                     .create_improvement_prompt(context, &current_code)
             };
 
+            if let Some(map) = enhanced_context
+                .code_structure
+                .as_ref()
+                .filter(|map| !map.is_empty())
+            {
+                prompt.push_str(&format!("\n\n## REPOSITORY MAP:\n{}", map));
+            }
+            let lessons = memory::lessons_section(&enhanced_context.file_paths).await;
+            if !lessons.is_empty() {
+                prompt.push_str(&format!("\n\n{}", lessons.trim_end()));
+            }
+
             info!("Generated prompt with length: {} characters", prompt.len());
 
             // Ask the LLM with appropriate parameters based on the task
@@ -1029,12 +839,18 @@ impl CodeGenerator for LlmCodeGenerator {
             };
 
             self.llm
-                .generate_streaming(&prompt, max_tokens, temperature, false)
+                .generate_streaming_cancellable(
+                    &prompt,
+                    max_tokens,
+                    temperature,
+                    false,
+                    &self.cancel,
+                )
                 .await?
         };
 
         // Extract code changes from the response
-        let target_files = self.extract_code_from_response(&response)?;
+        let target_files = Self::extract_code_from_response(&response)?;
 
         // Generate a unique ID
         let id = Uuid::new_v4().to_string();
@@ -1105,3 +921,372 @@ impl CodeGenerator for LlmCodeGenerator {
             .await
     }
 }
+
+/// Drive `conversation` until the model answers without calling a tool,
+/// executing each call through `registry`
+///
+/// The calls of one turn run concurrently, at most `max_parallel` at a time;
+/// a call that writes a path an earlier call touches waits for it.
+///
+/// After `max_iterations` rounds of tool calls the model is asked once more
+/// with tool use disabled, so the exchange always ends with an answer.
+async fn run_tool_loop(
+    provider: &dyn UnifiedProvider,
+    model: &str,
+    registry: &ToolRegistry,
+    conversation: &mut Conversation,
+    max_iterations: usize,
+    max_parallel: usize,
+    cancel: &CancellationToken,
+) -> Result<String> {
+    for iteration in 0..max_iterations {
+        info!("Tool iteration {}/{}", iteration + 1, max_iterations);
+
+        let response = stream_turn(provider, model, conversation.request(), cancel).await?;
+        conversation.push_response(&response);
+
+        let calls = conversation.pending_tool_calls();
+        if calls.is_empty() {
+            return Ok(response.text);
+        }
+        let batch: Vec<(String, serde_json::Value)> = calls
+            .iter()
+            .map(|tc| {
+                info!("Tool call: {}", tc.name);
+                (tc.name.clone(), tc.arguments_json.clone())
+            })
+            .collect();
+        let results = registry.execute_concurrently(&batch, max_parallel).await;
+        for (tc, result) in calls.into_iter().zip(results) {
+            let call_id = tc.id.unwrap_or_default();
+            if result.success {
+                conversation.push_tool_result(call_id, result.result, false);
+            } else {
+                let error = result.error.unwrap_or_else(|| "Unknown error".to_string());
+                conversation.push_tool_result(call_id, error, true);
+            }
+        }
+    }
+
+    info!(
+        "Reached maximum tool iterations ({}), asking for a final answer",
+        max_iterations
+    );
+    let mut req = conversation.request();
+    if req.tools.is_some() {
+        req.tool_choice = Some(UnifiedToolChoice::None);
+    }
+    let response = stream_turn(provider, model, req, cancel).await?;
+    conversation.push_response(&response);
+    Ok(response.text)
+}
+
+/// Stream one turn, trimmed to the context window of `model`, and return
+/// the complete reply
+async fn stream_turn(
+    provider: &dyn UnifiedProvider,
+    model: &str,
+    req: UnifiedGenerateRequest,
+    cancel: &CancellationToken,
+) -> Result<GenerateResponse> {
+    // Tool results can outgrow the window; trim them before sending
+    let mut req = crate::providers::capabilities::fit_to_context_window(model, req);
+    req.metadata = Some(crate::providers::metadata::for_request(None));
+
+    let mut streamed = String::new();
+    let mut on_event = |ev: StreamEvent| match ev {
+        StreamEvent::TextDelta(d) => streamed.push_str(&d),
+        StreamEvent::Error(msg) => warn!("Streaming error event: {}", msg),
+        _ => {}
+    };
+
+    let mut res = match crate::providers::generate_streaming_cancellable(
+        provider,
+        req,
+        &mut on_event,
+        cancel,
+    )
+    .await
+    {
+        Ok(r) => r,
+        Err(e) => {
+            match e.clone() {
+                ProviderError::TimeoutFirstToken { timeout_ms } => {
+                    warn!("Streaming first token timeout after {} ms", timeout_ms);
+                }
+                ProviderError::TimeoutStall { timeout_ms } => {
+                    warn!("Streaming stalled for {} ms", timeout_ms);
+                }
+                ProviderError::Cancelled { message } => {
+                    return Err(anyhow::anyhow!(BorgError::Cancelled(message)));
+                }
+                _ => {}
+            }
+            return Err(anyhow::anyhow!(e.to_string()));
+        }
+    };
+
+    if res.text.is_empty() {
+        res.text = streamed;
+    }
+    info!(
+        "Turn finished (chars={}, tool_calls={})",
+        res.text.len(),
+        res.tool_calls.len()
+    );
+    Ok(res)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::code_generation::llm_tool::ToolArgs;
+    use crate::core::config::TruncationStrategy;
+    use crate::providers::{ContentPart, Role, ToolSpec};
+    use serde_json::json;
+    use std::sync::Mutex as StdMutex;
+
+    struct Shout;
+
+    #[async_trait]
+    impl LlmTool for Shout {
+        fn name(&self) -> &str {
+            "shout"
+        }
+
+        fn description(&self) -> &str {
+            "Upper-case a word"
+        }
+
+        fn parameters(&self) -> Vec<crate::code_generation::llm_tool::ToolParameter> {
+            vec![crate::code_generation::llm_tool::ToolParameter {
+                name: "word".to_string(),
+                description: "Word to shout".to_string(),
+                required: true,
+                default_value: None,
+                param_type: None,
+            }]
+        }
+
+        async fn execute(&self, args: &ToolArgs) -> Result<String> {
+            Ok(args["word"].as_str().unwrap_or_default().to_uppercase())
+        }
+    }
+
+    /// Calls `shout` until it has seen `rounds` results, then answers with
+    /// the last one
+    struct Scripted {
+        rounds: usize,
+        seen: StdMutex<Vec<UnifiedGenerateRequest>>,
+    }
+
+    #[async_trait]
+    impl UnifiedProvider for Scripted {
+        async fn generate(
+            &self,
+            req: UnifiedGenerateRequest,
+        ) -> Result<GenerateResponse, ProviderError> {
+            let results: Vec<String> = req
+                .messages
+                .iter()
+                .flat_map(|m| m.content.iter())
+                .filter_map(|p| match p {
+                    ContentPart::ToolResult { content, .. } => Some(content.clone()),
+                    _ => None,
+                })
+                .collect();
+            let may_call = !matches!(req.tool_choice, Some(UnifiedToolChoice::None));
+            self.seen.lock().unwrap().push(req);
+            let (text, tool_calls) = if results.len() < self.rounds && may_call {
+                (
+                    String::new(),
+                    vec![ToolCallNormalized {
+                        id: None,
+                        name: "shout".to_string(),
+                        arguments_json: json!({"word": "hi"}),
+                    }],
+                )
+            } else {
+                (format!("final: {}", results.join(",")), Vec::new())
+            };
+            Ok(GenerateResponse {
+                text,
+                tool_calls,
+                usage: None,
+                raw: None,
+                provider: None,
+                reasoning: None,
+                logprobs: None,
+            })
+        }
+
+        async fn generate_streaming(
+            &self,
+            req: UnifiedGenerateRequest,
+            _on_event: &mut (dyn FnMut(StreamEvent) + Send),
+        ) -> Result<GenerateResponse, ProviderError> {
+            self.generate(req).await
+        }
+    }
+
+    fn conversation(registry: &ToolRegistry) -> Conversation {
+        let mut conversation = Conversation::new("test-model")
+            .with_tools(registry.tool_specs())
+            .with_tool_choice(UnifiedToolChoice::Auto);
+        conversation.push_user("shout hi");
+        conversation
+    }
+
+    /// Configuration with one Anthropic model, `coder`, and `extra` appended
+    fn yaml_config(dir: &std::path::Path, extra: &str) -> Config {
+        serde_yaml::from_str(&format!(
+            r#"
+models:
+  - {{ name: coder, provider: anthropic, api_key: test-key, model: claude-3-5-sonnet-20241022 }}
+phases:
+  research: {{ models: [], tools: [], prompt: "" }}
+  deliberation: {{ models: [], tools: [], prompt: "" }}
+  tdd: {{ models: [], tools: [], prompt: "" }}
+agent: {{ working_dir: {}, timeout_seconds: 60, max_memory_usage_mb: 1024, max_cpu_usage_percent: 80 }}
+database: {{ path: ./data/borg.db }}
+git: {{ branch_prefix: borg/ }}
+logging: {{ enabled: false, llm_log_dir: {} }}
+{}
+"#,
+            dir.display(),
+            dir.join("logs").display(),
+            extra
+        ))
+        .unwrap()
+    }
+
+    fn generator_for(config: &Config, dir: &std::path::Path) -> LlmCodeGenerator {
+        let git_manager: Arc<Mutex<dyn GitManager>> = Arc::new(Mutex::new(
+            crate::version_control::git_implementation::GitImplementation::new(dir).unwrap(),
+        ));
+        LlmCodeGenerator::from_config(config, &config.models[0], git_manager, dir.to_path_buf())
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_from_config_applies_the_code_generation_section() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = yaml_config(
+            dir.path(),
+            "code_generation: { max_parallel_tools: 2, max_tool_iterations: 6 }",
+        );
+        let generator = generator_for(&config, dir.path());
+        assert_eq!(generator.max_parallel_tools, 2);
+        assert_eq!(generator.max_tool_iterations, 6);
+
+        let config = yaml_config(dir.path(), "");
+        assert_eq!(generator_for(&config, dir.path()).max_parallel_tools, 4);
+    }
+
+    #[tokio::test]
+    async fn test_context_budget_and_truncation_come_from_the_config_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = yaml_config(
+            dir.path(),
+            "code_generation: { context: { max_tokens: 3000, truncation: head } }",
+        );
+        let generator = generator_for(&config, dir.path());
+        let context = generator.context_builder.config();
+        assert_eq!(context.max_tokens, 3000);
+        assert_eq!(context.truncation, TruncationStrategy::Head);
+        // Settings left out keep their defaults
+        assert_eq!(context.max_files, 8);
+    }
+
+    #[test]
+    fn test_responses_yield_diffs_ranges_and_whole_files() {
+        let response = "Changes:\n\n```diff\n--- a/src/a.rs\n+++ b/src/a.rs\n@@ -1 +1 @@\n-a\n+b\n\
+                        --- /dev/null\n+++ b/src/n.rs\n@@ -0,0 +1 @@\n+n\n```\n\n\
+                        ```rust\n// File: src/b.rs (lines 3-4)\nfn b() {}\n```\n\n\
+                        ```rust\n// File: src/c.rs\nfn c() {}\n```\n\n\
+                        ```python\n# File: app/d.py (lines 2-2)\nd = 1\n```\n";
+        let changes = LlmCodeGenerator::extract_code_from_response(response).unwrap();
+        let summary: Vec<_> = changes
+            .iter()
+            .map(|c| (c.file_path.as_str(), c.start_line, c.end_line, c.is_diff()))
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                ("src/a.rs", None, None, true),
+                ("src/n.rs", None, None, true),
+                ("src/b.rs", Some(3), Some(4), false),
+                ("src/c.rs", None, None, false),
+                ("app/d.py", Some(2), Some(2), false),
+            ]
+        );
+        assert_eq!(changes[2].new_content, "fn b() {}\n");
+        assert_eq!(changes[1].apply("").unwrap(), "n\n");
+        assert_eq!(changes[4].new_content, "d = 1\n");
+    }
+
+    #[tokio::test]
+    async fn test_tool_loop_executes_native_calls_until_answer() {
+        let mut registry = ToolRegistry::new();
+        registry.register(Shout);
+        let provider = Scripted {
+            rounds: 2,
+            seen: StdMutex::new(Vec::new()),
+        };
+        let mut conversation = conversation(&registry);
+
+        let text = run_tool_loop(
+            &provider,
+            "test-model",
+            &registry,
+            &mut conversation,
+            5,
+            4,
+            &CancellationToken::new(),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(text, "final: HI,HI");
+        let seen = provider.seen.lock().unwrap();
+        assert_eq!(seen.len(), 3);
+        let tools: &Vec<ToolSpec> = seen[0].tools.as_ref().unwrap();
+        assert_eq!(tools[0].name, "shout");
+        assert_eq!(
+            tools[0].json_schema.as_ref().unwrap()["required"],
+            json!(["word"])
+        );
+        assert!(matches!(
+            conversation.messages().last().unwrap().role,
+            Role::Assistant
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_tool_loop_stops_at_max_iterations() {
+        let mut registry = ToolRegistry::new();
+        registry.register(Shout);
+        let provider = Scripted {
+            rounds: usize::MAX,
+            seen: StdMutex::new(Vec::new()),
+        };
+        let mut conversation = conversation(&registry);
+
+        let text = run_tool_loop(
+            &provider,
+            "test-model",
+            &registry,
+            &mut conversation,
+            2,
+            4,
+            &CancellationToken::new(),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(text, "final: HI,HI");
+        let seen = provider.seen.lock().unwrap();
+        assert_eq!(seen.len(), 3);
+        assert!(matches!(seen[2].tool_choice, Some(UnifiedToolChoice::None)));
+    }
+}
@@ -7,7 +7,9 @@ use std::path::{Path, PathBuf};
 use std::sync::Arc;
 use std::sync::Mutex;
 
-use crate::core::config::LlmLoggingConfig;
+use crate::core::config::{LlmLoggingConfig, RetentionPolicy};
+use crate::core::encryption::{self, Cipher};
+use crate::core::retention;
 
 /// LLM Logger to record communications between the agent and LLMs
 pub struct LlmLogger {
@@ -19,6 +21,9 @@ pub struct LlmLogger {
 
     /// Writer for the log file
     log_file: Option<Arc<Mutex<File>>>,
+
+    /// Seals each entry written to the log file, when encryption is on
+    cipher: Option<Cipher>,
 }
 
 impl LlmLogger {
@@ -28,6 +33,7 @@ impl LlmLogger {
             config,
             log_file_path: None,
             log_file: None,
+            cipher: encryption::global(),
         };
 
         // If logging is enabled, initialize the log directory and file
@@ -144,6 +150,79 @@ impl LlmLogger {
         Ok(())
     }
 
+    /// Log the reasoning/thinking text behind a response
+    pub fn log_reasoning(&self, provider: &str, model: &str, reasoning: &str) -> Result<()> {
+        if !self.config.enabled || !self.config.include_reasoning {
+            return Ok(());
+        }
+
+        let timestamp = Utc::now().format("%Y-%m-%d %H:%M:%S%.3f").to_string();
+
+        let mut log_entry = format!("\n===== REASONING: {} {} =====\n", provider, model);
+        log_entry.push_str(&format!("TIMESTAMP: {}\n", timestamp));
+        log_entry.push_str(&format!("REASONING:\n{}\n", reasoning));
+
+        self.write_to_log(&log_entry)?;
+
+        if self.config.console_logging {
+            println!("{}", log_entry);
+        }
+
+        Ok(())
+    }
+
+    /// Log a retry of a failed LLM call
+    pub fn log_retry(
+        &self,
+        provider: &str,
+        model: &str,
+        retry: u32,
+        max_retries: u32,
+        error: &str,
+        delay_ms: u64,
+    ) -> Result<()> {
+        if !self.config.enabled {
+            return Ok(());
+        }
+
+        let timestamp = Utc::now().format("%Y-%m-%d %H:%M:%S%.3f").to_string();
+
+        let mut log_entry = format!("\n===== RETRY: {} {} =====\n", provider, model);
+        log_entry.push_str(&format!("TIMESTAMP: {}\n", timestamp));
+        log_entry.push_str(&format!("ATTEMPT: retry {} of {}\n", retry, max_retries));
+        log_entry.push_str(&format!("DELAY: {}ms\n", delay_ms));
+        log_entry.push_str(&format!("ERROR: {}\n", error));
+
+        self.write_to_log(&log_entry)?;
+
+        if self.config.console_logging {
+            println!("{}", log_entry);
+        }
+
+        Ok(())
+    }
+
+    /// Log a failover from one backend of a fallback chain to the next
+    pub fn log_fallback(&self, from: &str, to: &str, error: &str) -> Result<()> {
+        if !self.config.enabled {
+            return Ok(());
+        }
+
+        let timestamp = Utc::now().format("%Y-%m-%d %H:%M:%S%.3f").to_string();
+
+        let mut log_entry = format!("\n===== FALLBACK: {} -> {} =====\n", from, to);
+        log_entry.push_str(&format!("TIMESTAMP: {}\n", timestamp));
+        log_entry.push_str(&format!("ERROR: {}\n", error));
+
+        self.write_to_log(&log_entry)?;
+
+        if self.config.console_logging {
+            println!("{}", log_entry);
+        }
+
+        Ok(())
+    }
+
     /// Write text to the log file
     fn write_to_log(&self, text: &str) -> Result<()> {
         if let Some(file) = &self.log_file {
@@ -151,6 +230,10 @@ impl LlmLogger {
                 .lock()
                 .map_err(|_| io::Error::other("Failed to acquire lock on log file"))?;
 
+            let text = match &self.cipher {
+                Some(cipher) => cipher.seal_log_entry(text)?,
+                None => text.to_string(),
+            };
             file_guard
                 .write_all(text.as_bytes())
                 .with_context(|| "Failed to write to log file")?;
@@ -165,54 +248,54 @@ impl LlmLogger {
 
     /// Clean up old log files if there are too many
     fn clean_old_logs(&self) -> Result<()> {
-        let log_dir = Path::new(&self.config.log_dir);
+        let policy = RetentionPolicy {
+            max_age_days: None,
+            keep: Some(self.config.log_files_to_keep as usize),
+        };
+        prune_logs(Path::new(&self.config.log_dir), &policy)?;
+        Ok(())
+    }
+}
 
-        // Get all log files
-        let entries = fs::read_dir(log_dir)
-            .with_context(|| format!("Failed to read log directory: {:?}", log_dir))?;
-
-        // Filter log files and sort by modification time
-        let mut log_files = Vec::new();
-        for entry in entries {
-            let entry = entry?;
-            let path = entry.path();
-
-            if path.is_file()
-                && path.extension().is_some_and(|ext| ext == "txt")
-                && path
-                    .file_name()
-                    .is_some_and(|name| name.to_string_lossy().starts_with("llm_log_"))
-            {
-                let metadata = fs::metadata(&path)?;
-                log_files.push((path, metadata.modified()?));
-            }
+/// Delete the LLM log files in `log_dir` that `policy` does not keep,
+/// newest by modification time first; returns how many were deleted
+pub fn prune_logs(log_dir: &Path, policy: &RetentionPolicy) -> Result<usize> {
+    // Get all log files
+    let entries = fs::read_dir(log_dir)
+        .with_context(|| format!("Failed to read log directory: {:?}", log_dir))?;
+
+    let mut log_files = Vec::new();
+    for entry in entries {
+        let entry = entry?;
+        let path = entry.path();
+
+        if path.is_file()
+            && path.extension().is_some_and(|ext| ext == "txt")
+            && path
+                .file_name()
+                .is_some_and(|name| name.to_string_lossy().starts_with("llm_log_"))
+        {
+            let metadata = fs::metadata(&path)?;
+            log_files.push((path, DateTime::<Utc>::from(metadata.modified()?)));
         }
+    }
 
-        // Sort by modification time (newest first)
-        log_files.sort_by(|a, b| b.1.cmp(&a.1));
-
-        // If there are more than the configured number of files, delete the oldest ones
-        if log_files.len() > self.config.log_files_to_keep as usize {
-            for (path, _) in log_files
-                .iter()
-                .skip(self.config.log_files_to_keep as usize)
-            {
-                match fs::remove_file(path) {
-                    Ok(_) => {
-                        info!("Deleted old log file: {:?}", path);
-                    }
-                    Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
-                        // Non-fatal: file already gone (race or prior cleanup)
-                        log::debug!("Old log file already missing: {:?}", path);
-                    }
-                    Err(e) => {
-                        // Non-fatal: warn and continue cleanup without failing initialization
-                        log::warn!("Failed to delete old log file {:?}: {}", path, e);
-                    }
-                }
+    let mut deleted = 0;
+    for path in retention::expired(policy, log_files, Utc::now()) {
+        match fs::remove_file(&path) {
+            Ok(_) => {
+                info!("Deleted old log file: {:?}", path);
+                deleted += 1;
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                // Non-fatal: file already gone (race or prior cleanup)
+                log::debug!("Old log file already missing: {:?}", path);
+            }
+            Err(e) => {
+                // Non-fatal: warn and continue cleanup without failing initialization
+                log::warn!("Failed to delete old log file {:?}: {}", path, e);
             }
         }
-
-        Ok(())
     }
+    Ok(deleted)
 }
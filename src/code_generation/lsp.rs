@@ -0,0 +1,988 @@
+//! Language server client and the symbol navigation tools built on it.
+//!
+//! LSP is JSON-RPC 2.0 framed with `Content-Length` headers over the
+//! server's stdin/stdout. An `LspSession` starts `rust-analyzer` for the
+//! workspace the first time one of its tools is used and shares the
+//! connection between `FindDefinition`, `FindReferences` and
+//! `DocumentSymbols`, which give the model exact navigation where `Grep`
+//! only matches text.
+
+use anyhow::{anyhow, bail, Context, Result};
+use async_trait::async_trait;
+use log::{debug, info};
+use serde_json::{json, Value as JsonValue};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader};
+use tokio::process::{Child, Command};
+use tokio::sync::{Mutex, OnceCell};
+
+use crate::code_generation::llm_tool::{
+    parsed_arg, str_arg, LlmTool, ToolArgs, ToolParameter, ToolParameterType,
+};
+use crate::code_generation::patch::workspace_path;
+use crate::mcp::{JsonRpcMessage, METHOD_NOT_FOUND};
+
+type Reader = BufReader<Box<dyn AsyncRead + Send + Unpin>>;
+type Writer = Box<dyn AsyncWrite + Send + Unpin>;
+
+/// Error codes asking the client to retry once the server has caught up
+const CONTENT_MODIFIED: i64 = -32801;
+const SERVER_CANCELLED: i64 = -32802;
+
+/// Most locations listed by `FindReferences`
+const MAX_REFERENCES: usize = 100;
+
+/// A position in a workspace file, 1-based
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Location {
+    /// Path relative to the workspace (absolute when outside it)
+    pub path: String,
+    pub line: u32,
+    pub column: u32,
+}
+
+/// An entry of a file outline
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Symbol {
+    pub name: String,
+    pub kind: &'static str,
+    /// 1-based line of the symbol's name
+    pub line: u32,
+    pub children: Vec<Symbol>,
+}
+
+struct Connection {
+    reader: Reader,
+    writer: Writer,
+}
+
+/// Connection to one language server
+pub struct LspClient {
+    root: PathBuf,
+    connection: Mutex<Connection>,
+    next_id: AtomicU64,
+    timeout: Duration,
+    /// Set once the server reports it has finished indexing
+    quiescent: AtomicBool,
+    /// Text and version of every document sent to the server
+    documents: Mutex<HashMap<String, (i64, String)>>,
+    /// Kept so the server process lives (and is killed) with the client
+    _child: Option<Child>,
+}
+
+impl LspClient {
+    /// Start `command` for the workspace `root` and complete the handshake
+    pub async fn start(command: &str, args: &[String], root: &Path) -> Result<Self> {
+        let mut child = Command::new(command)
+            .args(args)
+            .current_dir(root)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .kill_on_drop(true)
+            .spawn()
+            .with_context(|| format!("Failed to start language server {}", command))?;
+        let stdin = child
+            .stdin
+            .take()
+            .context("Language server stdin not piped")?;
+        let stdout = child
+            .stdout
+            .take()
+            .context("Language server stdout not piped")?;
+
+        let mut client = Self::over(root, stdout, stdin);
+        client._child = Some(child);
+        client.initialize().await?;
+        info!("Started language server {} for {}", command, root.display());
+        Ok(client)
+    }
+
+    /// Handshake with a server already reachable over `reader`/`writer`
+    /// (e.g. an in-process server in tests)
+    pub async fn connect_streams(
+        root: &Path,
+        reader: impl AsyncRead + Send + Unpin + 'static,
+        writer: impl AsyncWrite + Send + Unpin + 'static,
+    ) -> Result<Self> {
+        let client = Self::over(root, reader, writer);
+        client.initialize().await?;
+        Ok(client)
+    }
+
+    fn over(
+        root: &Path,
+        reader: impl AsyncRead + Send + Unpin + 'static,
+        writer: impl AsyncWrite + Send + Unpin + 'static,
+    ) -> Self {
+        Self {
+            root: root.to_path_buf(),
+            connection: Mutex::new(Connection {
+                reader: BufReader::new(Box::new(reader)),
+                writer: Box::new(writer),
+            }),
+            next_id: AtomicU64::new(1),
+            // The first queries wait for rust-analyzer to index the crate
+            timeout: Duration::from_secs(120),
+            quiescent: AtomicBool::new(false),
+            documents: Mutex::new(HashMap::new()),
+            _child: None,
+        }
+    }
+
+    async fn initialize(&self) -> Result<()> {
+        self.request(
+            "initialize",
+            json!({
+                "processId": std::process::id(),
+                "rootUri": file_uri(&self.root),
+                "workspaceFolders": [{"uri": file_uri(&self.root), "name": "workspace"}],
+                "capabilities": {
+                    "textDocument": {
+                        "documentSymbol": {"hierarchicalDocumentSymbolSupport": true},
+                        "definition": {"linkSupport": true}
+                    },
+                    // rust-analyzer reports when indexing is done
+                    "experimental": {"serverStatusNotification": true}
+                },
+                "clientInfo": {"name": "borg", "version": env!("CARGO_PKG_VERSION")}
+            }),
+        )
+        .await?;
+        self.notify("initialized", json!({})).await
+    }
+
+    /// Where the symbol at `line`/`character` (0-based, UTF-16) of `file`
+    /// is defined
+    pub async fn definition(&self, file: &str, line: u32, character: u32) -> Result<Vec<Location>> {
+        let result = self
+            .query("textDocument/definition", file, line, character, json!({}))
+            .await?;
+        Ok(self.locations(&result))
+    }
+
+    /// Every use of the symbol at `line`/`character` of `file`
+    pub async fn references(
+        &self,
+        file: &str,
+        line: u32,
+        character: u32,
+        include_declaration: bool,
+    ) -> Result<Vec<Location>> {
+        let result = self
+            .query(
+                "textDocument/references",
+                file,
+                line,
+                character,
+                json!({"context": {"includeDeclaration": include_declaration}}),
+            )
+            .await?;
+        Ok(self.locations(&result))
+    }
+
+    /// Outline of `file`
+    pub async fn document_symbols(&self, file: &str) -> Result<Vec<Symbol>> {
+        let uri = self.sync_document(file).await?;
+        self.wait_until_ready().await;
+        let result = self
+            .retrying_request(
+                "textDocument/documentSymbol",
+                json!({"textDocument": {"uri": uri}}),
+            )
+            .await?;
+        Ok(result
+            .as_array()
+            .map(|items| items.iter().filter_map(parse_symbol).collect())
+            .unwrap_or_default())
+    }
+
+    async fn query(
+        &self,
+        method: &str,
+        file: &str,
+        line: u32,
+        character: u32,
+        extra: JsonValue,
+    ) -> Result<JsonValue> {
+        let uri = self.sync_document(file).await?;
+        self.wait_until_ready().await;
+        let mut params = json!({
+            "textDocument": {"uri": uri},
+            "position": {"line": line, "character": character}
+        });
+        if let (Some(params), JsonValue::Object(extra)) = (params.as_object_mut(), extra) {
+            params.extend(extra);
+        }
+        self.retrying_request(method, params).await
+    }
+
+    /// Send `file`'s current text to the server, opening it on first use
+    async fn sync_document(&self, file: &str) -> Result<String> {
+        let path = workspace_path(&self.root, file).map_err(|e| anyhow!(e))?;
+        let text = tokio::fs::read_to_string(&path)
+            .await
+            .with_context(|| format!("Failed to read {}", file))?;
+        let uri = file_uri(&path);
+
+        let mut documents = self.documents.lock().await;
+        match documents.get_mut(&uri) {
+            Some((_, known)) if *known == text => {}
+            Some((version, known)) => {
+                *version += 1;
+                *known = text.clone();
+                let version = *version;
+                drop(documents);
+                self.notify(
+                    "textDocument/didChange",
+                    json!({
+                        "textDocument": {"uri": uri, "version": version},
+                        "contentChanges": [{"text": text}]
+                    }),
+                )
+                .await?;
+            }
+            None => {
+                documents.insert(uri.clone(), (1, text.clone()));
+                drop(documents);
+                let language = if file.ends_with(".rs") {
+                    "rust"
+                } else {
+                    "plaintext"
+                };
+                self.notify(
+                    "textDocument/didOpen",
+                    json!({
+                        "textDocument": {"uri": uri, "languageId": language, "version": 1, "text": text}
+                    }),
+                )
+                .await?;
+            }
+        }
+        Ok(uri)
+    }
+
+    /// Give the server until the timeout to finish indexing, so the first
+    /// answers are not empty
+    async fn wait_until_ready(&self) {
+        if self.quiescent.load(Ordering::SeqCst) {
+            return;
+        }
+        let mut connection = self.connection.lock().await;
+        let wait = async {
+            while !self.quiescent.load(Ordering::SeqCst) {
+                match read_frame(&mut connection.reader).await {
+                    Ok(Some(message)) => {
+                        if let Err(e) = self.handle_incoming(&mut connection, message).await {
+                            debug!("Language server: {}", e);
+                            return;
+                        }
+                    }
+                    _ => return,
+                }
+            }
+        };
+        if tokio::time::timeout(self.timeout, wait).await.is_err() {
+            debug!("Language server still indexing; querying anyway");
+            self.quiescent.store(true, Ordering::SeqCst);
+        }
+    }
+
+    /// `request`, retried while the server asks for it because its state
+    /// changed underneath the request
+    async fn retrying_request(&self, method: &str, params: JsonValue) -> Result<JsonValue> {
+        let mut attempt = 0;
+        loop {
+            match self.request(method, params.clone()).await {
+                Err(e) if attempt < 5 && e.downcast_ref::<RetryableError>().is_some() => {
+                    attempt += 1;
+                    tokio::time::sleep(Duration::from_millis(500)).await;
+                }
+                other => return other,
+            }
+        }
+    }
+
+    async fn notify(&self, method: &str, params: JsonValue) -> Result<()> {
+        let mut connection = self.connection.lock().await;
+        write_frame(
+            &mut connection.writer,
+            &JsonRpcMessage::notification(method, Some(params)),
+        )
+        .await
+    }
+
+    /// Send a request and wait for its response, answering any requests
+    /// the server makes in the meantime
+    async fn request(&self, method: &str, params: JsonValue) -> Result<JsonValue> {
+        let id = self.next_id.fetch_add(1, Ordering::SeqCst);
+        let mut connection = self.connection.lock().await;
+        write_frame(
+            &mut connection.writer,
+            &JsonRpcMessage::request(id, method, params),
+        )
+        .await?;
+
+        let exchange = async {
+            loop {
+                let Some(message) = read_frame(&mut connection.reader).await? else {
+                    bail!("language server closed the connection");
+                };
+                if message.method.is_none()
+                    && message.id.as_ref().and_then(|i| i.as_u64()) == Some(id)
+                {
+                    if let Some(error) = message.error {
+                        if matches!(error.code, CONTENT_MODIFIED | SERVER_CANCELLED) {
+                            return Err(RetryableError(error.message).into());
+                        }
+                        bail!("{} (code {})", error.message, error.code);
+                    }
+                    return Ok(message.result.unwrap_or(JsonValue::Null));
+                }
+                self.handle_incoming(&mut connection, message).await?;
+            }
+        };
+        tokio::time::timeout(self.timeout, exchange)
+            .await
+            .map_err(|_| {
+                anyhow!(
+                    "Language server did not answer {} within {:?}",
+                    method,
+                    self.timeout
+                )
+            })?
+            .with_context(|| format!("Language server request {} failed", method))
+    }
+
+    /// Answer a server-to-client request or take note of a notification
+    async fn handle_incoming(
+        &self,
+        connection: &mut Connection,
+        message: JsonRpcMessage,
+    ) -> Result<()> {
+        let (Some(id), Some(method)) = (&message.id, &message.method) else {
+            if message.method.as_deref() == Some("experimental/serverStatus") {
+                let params = message.params.unwrap_or_default();
+                if params["quiescent"].as_bool() == Some(true) {
+                    self.quiescent.store(true, Ordering::SeqCst);
+                }
+            }
+            return Ok(());
+        };
+        let reply = match method.as_str() {
+            // One (default) setting per requested item
+            "workspace/configuration" => {
+                let items = message
+                    .params
+                    .as_ref()
+                    .and_then(|p| p["items"].as_array())
+                    .map_or(0, Vec::len);
+                JsonRpcMessage::response(id.clone(), JsonValue::Array(vec![JsonValue::Null; items]))
+            }
+            "window/workDoneProgress/create"
+            | "client/registerCapability"
+            | "client/unregisterCapability"
+            | "workspace/diagnostic/refresh"
+            | "workspace/semanticTokens/refresh"
+            | "workspace/inlayHint/refresh"
+            | "workspace/codeLens/refresh" => JsonRpcMessage::response(id.clone(), JsonValue::Null),
+            other => JsonRpcMessage::error_response(
+                id.clone(),
+                METHOD_NOT_FOUND,
+                format!("Method not supported by client: {}", other),
+            ),
+        };
+        write_frame(&mut connection.writer, &reply).await
+    }
+
+    /// `Location`s from a definition/references result (a location, a
+    /// list of locations or location links, or null)
+    fn locations(&self, result: &JsonValue) -> Vec<Location> {
+        let items = match result {
+            JsonValue::Array(items) => items.clone(),
+            JsonValue::Null => Vec::new(),
+            single => vec![single.clone()],
+        };
+        items
+            .iter()
+            .filter_map(|item| {
+                let uri = item["uri"].as_str().or(item["targetUri"].as_str())?;
+                let range = if item.get("targetSelectionRange").is_some() {
+                    &item["targetSelectionRange"]
+                } else {
+                    &item["range"]
+                };
+                let path = uri_path(uri)?;
+                let path = path
+                    .strip_prefix(&self.root)
+                    .map(Path::to_path_buf)
+                    .unwrap_or(path);
+                Some(Location {
+                    path: path.to_string_lossy().to_string(),
+                    line: range["start"]["line"].as_u64()? as u32 + 1,
+                    column: range["start"]["character"].as_u64()? as u32 + 1,
+                })
+            })
+            .collect()
+    }
+}
+
+/// The server asked for the request to be retried
+#[derive(Debug)]
+struct RetryableError(String);
+
+impl std::fmt::Display for RetryableError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl std::error::Error for RetryableError {}
+
+/// Write one `Content-Length`-framed message
+pub(crate) async fn write_frame(
+    writer: &mut (impl AsyncWrite + Unpin + ?Sized),
+    message: &JsonRpcMessage,
+) -> Result<()> {
+    let body = serde_json::to_vec(message)?;
+    writer
+        .write_all(format!("Content-Length: {}\r\n\r\n", body.len()).as_bytes())
+        .await?;
+    writer.write_all(&body).await?;
+    writer.flush().await?;
+    Ok(())
+}
+
+/// Read the next framed message; `None` once the peer has closed the
+/// connection
+pub(crate) async fn read_frame(
+    reader: &mut (impl AsyncBufReadExt + Unpin + ?Sized),
+) -> Result<Option<JsonRpcMessage>> {
+    let mut length = None;
+    let mut line = String::new();
+    loop {
+        line.clear();
+        if reader.read_line(&mut line).await? == 0 {
+            return Ok(None);
+        }
+        let header = line.trim_end();
+        if header.is_empty() {
+            if length.is_some() {
+                break;
+            }
+            continue;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            if name.eq_ignore_ascii_case("Content-Length") {
+                length = Some(
+                    value
+                        .trim()
+                        .parse::<usize>()
+                        .context("Invalid Content-Length")?,
+                );
+            }
+        }
+    }
+    let mut body = vec![0; length.unwrap_or_default()];
+    reader.read_exact(&mut body).await?;
+    serde_json::from_slice(&body)
+        .map(Some)
+        .context("Invalid JSON-RPC message from language server")
+}
+
+fn file_uri(path: &Path) -> String {
+    let encoded: Vec<String> = path
+        .to_string_lossy()
+        .split('/')
+        .map(|segment| urlencoding::encode(segment).into_owned())
+        .collect();
+    format!("file://{}", encoded.join("/"))
+}
+
+fn uri_path(uri: &str) -> Option<PathBuf> {
+    let path = uri.strip_prefix("file://")?;
+    Some(PathBuf::from(urlencoding::decode(path).ok()?.into_owned()))
+}
+
+fn parse_symbol(item: &JsonValue) -> Option<Symbol> {
+    // DocumentSymbol (hierarchical) or SymbolInformation (flat)
+    let range = if item.get("selectionRange").is_some() {
+        &item["selectionRange"]
+    } else {
+        &item["location"]["range"]
+    };
+    Some(Symbol {
+        name: item["name"].as_str()?.to_string(),
+        kind: symbol_kind(item["kind"].as_u64().unwrap_or_default()),
+        line: range["start"]["line"].as_u64()? as u32 + 1,
+        children: item["children"]
+            .as_array()
+            .map(|children| children.iter().filter_map(parse_symbol).collect())
+            .unwrap_or_default(),
+    })
+}
+
+fn symbol_kind(kind: u64) -> &'static str {
+    match kind {
+        1 => "file",
+        2 => "mod",
+        3 => "namespace",
+        5 => "class",
+        6 => "method",
+        7 => "property",
+        8 => "field",
+        9 => "constructor",
+        10 => "enum",
+        11 => "trait",
+        12 => "fn",
+        13 => "let",
+        14 => "const",
+        22 => "variant",
+        23 => "struct",
+        26 => "type param",
+        _ => "symbol",
+    }
+}
+
+/// A lazily started language server for one workspace, shared by the
+/// symbol tools
+pub struct LspSession {
+    workspace: PathBuf,
+    command: String,
+    args: Vec<String>,
+    client: OnceCell<Arc<LspClient>>,
+}
+
+impl LspSession {
+    /// `rust-analyzer` for `workspace`, started on first use
+    pub fn new(workspace: PathBuf) -> Self {
+        Self {
+            workspace,
+            command: "rust-analyzer".to_string(),
+            args: Vec::new(),
+            client: OnceCell::new(),
+        }
+    }
+
+    /// Run a different language server binary
+    pub fn with_command(mut self, command: impl Into<String>, args: Vec<String>) -> Self {
+        self.command = command.into();
+        self.args = args;
+        self
+    }
+
+    /// A session over an already connected client
+    pub fn with_client(workspace: PathBuf, client: LspClient) -> Self {
+        Self {
+            client: OnceCell::new_with(Some(Arc::new(client))),
+            ..Self::new(workspace)
+        }
+    }
+
+    async fn client(&self) -> Result<Arc<LspClient>> {
+        self.client
+            .get_or_try_init(|| async {
+                LspClient::start(&self.command, &self.args, &self.workspace)
+                    .await
+                    .map(Arc::new)
+            })
+            .await
+            .cloned()
+    }
+
+    /// `FindDefinition`, `FindReferences` and `DocumentSymbols` over this
+    /// session
+    pub fn tools(
+        self: &Arc<Self>,
+    ) -> (FindDefinitionTool, FindReferencesTool, DocumentSymbolsTool) {
+        (
+            FindDefinitionTool {
+                session: self.clone(),
+            },
+            FindReferencesTool {
+                session: self.clone(),
+            },
+            DocumentSymbolsTool {
+                session: self.clone(),
+            },
+        )
+    }
+
+    /// 0-based LSP position of `symbol` on the 1-based `line` of `file`
+    fn position(&self, file: &str, line: usize, symbol: &str) -> Result<(u32, u32)> {
+        let path = workspace_path(&self.workspace, file).map_err(|e| anyhow!(e))?;
+        let text =
+            std::fs::read_to_string(&path).with_context(|| format!("Failed to read {}", file))?;
+        let line_text = text
+            .lines()
+            .nth(line.saturating_sub(1))
+            .ok_or_else(|| anyhow!("{} has no line {}", file, line))?;
+        let column = find_word(line_text, symbol)
+            .ok_or_else(|| anyhow!("'{}' not found on line {} of {}", symbol, line, file))?;
+        let character = line_text[..column].encode_utf16().count() as u32;
+        Ok((line.saturating_sub(1) as u32, character))
+    }
+
+    /// `path:line:column: source line` for each location
+    fn render(&self, locations: &[Location]) -> String {
+        let mut files: HashMap<String, Vec<String>> = HashMap::new();
+        locations
+            .iter()
+            .map(|loc| {
+                let lines = files.entry(loc.path.clone()).or_insert_with(|| {
+                    workspace_path(&self.workspace, &loc.path)
+                        .ok()
+                        .or_else(|| Some(PathBuf::from(&loc.path)))
+                        .and_then(|p| std::fs::read_to_string(p).ok())
+                        .map(|t| t.lines().map(str::to_string).collect())
+                        .unwrap_or_default()
+                });
+                let source = lines
+                    .get(loc.line as usize - 1)
+                    .map(|l| l.trim())
+                    .unwrap_or_default();
+                format!("{}:{}:{}: {}", loc.path, loc.line, loc.column, source)
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
+
+/// Byte offset of `word` in `line` as a whole identifier, falling back to
+/// any occurrence
+fn find_word(line: &str, word: &str) -> Option<usize> {
+    let is_ident = |c: char| c.is_alphanumeric() || c == '_';
+    line.match_indices(word)
+        .map(|(i, _)| i)
+        .find(|&i| {
+            let before = line[..i].chars().next_back();
+            let after = line[i + word.len()..].chars().next();
+            !before.is_some_and(is_ident) && !after.is_some_and(is_ident)
+        })
+        .or_else(|| line.find(word))
+}
+
+fn position_parameters() -> Vec<ToolParameter> {
+    vec![
+        ToolParameter {
+            name: "file_path".to_string(),
+            description: "File containing the symbol, relative to the workspace".to_string(),
+            required: true,
+            default_value: None,
+            param_type: Some(ToolParameterType::String),
+        },
+        ToolParameter {
+            name: "line".to_string(),
+            description: "1-based line on which the symbol appears".to_string(),
+            required: true,
+            default_value: None,
+            param_type: Some(ToolParameterType::Integer),
+        },
+        ToolParameter {
+            name: "symbol".to_string(),
+            description: "Name of the symbol as written on that line".to_string(),
+            required: true,
+            default_value: None,
+            param_type: Some(ToolParameterType::String),
+        },
+    ]
+}
+
+/// File, line and symbol arguments of the position-based tools
+fn position_args(args: &ToolArgs) -> Result<(String, usize, String)> {
+    let (Some(file), Some(line), Some(symbol)) = (
+        str_arg(args, "file_path"),
+        parsed_arg::<usize>(args, "line"),
+        str_arg(args, "symbol"),
+    ) else {
+        bail!("file_path, line and symbol are all required");
+    };
+    Ok((file, line, symbol))
+}
+
+/// A tool that jumps to the definition of a symbol
+pub struct FindDefinitionTool {
+    session: Arc<LspSession>,
+}
+
+#[async_trait]
+impl LlmTool for FindDefinitionTool {
+    fn name(&self) -> &str {
+        "FindDefinition"
+    }
+
+    fn is_read_only(&self) -> bool {
+        true
+    }
+
+    fn description(&self) -> &str {
+        "Find where a symbol used at a given file and line is defined, using rust-analyzer."
+    }
+
+    fn parameters(&self) -> Vec<ToolParameter> {
+        position_parameters()
+    }
+
+    async fn execute(&self, args: &ToolArgs) -> Result<String> {
+        let (file, line, symbol) = position_args(args)?;
+        let (line0, character) = self.session.position(&file, line, &symbol)?;
+        let client = self.session.client().await?;
+        let locations = client.definition(&file, line0, character).await?;
+        if locations.is_empty() {
+            return Ok(format!("No definition found for '{}'", symbol));
+        }
+        Ok(self.session.render(&locations))
+    }
+}
+
+/// A tool that lists every reference to a symbol
+pub struct FindReferencesTool {
+    session: Arc<LspSession>,
+}
+
+#[async_trait]
+impl LlmTool for FindReferencesTool {
+    fn name(&self) -> &str {
+        "FindReferences"
+    }
+
+    fn is_read_only(&self) -> bool {
+        true
+    }
+
+    fn description(&self) -> &str {
+        "List every reference to a symbol used at a given file and line, using rust-analyzer."
+    }
+
+    fn parameters(&self) -> Vec<ToolParameter> {
+        let mut params = position_parameters();
+        params.push(ToolParameter {
+            name: "include_declaration".to_string(),
+            description: "Whether to list the declaration itself".to_string(),
+            required: false,
+            default_value: Some("true".to_string()),
+            param_type: Some(ToolParameterType::Boolean),
+        });
+        params
+    }
+
+    async fn execute(&self, args: &ToolArgs) -> Result<String> {
+        let (file, line, symbol) = position_args(args)?;
+        let include_declaration = parsed_arg::<bool>(args, "include_declaration").unwrap_or(true);
+        let (line0, character) = self.session.position(&file, line, &symbol)?;
+        let client = self.session.client().await?;
+        let mut locations = client
+            .references(&file, line0, character, include_declaration)
+            .await?;
+        if locations.is_empty() {
+            return Ok(format!("No references found for '{}'", symbol));
+        }
+        locations.sort_by(|a, b| (&a.path, a.line, a.column).cmp(&(&b.path, b.line, b.column)));
+        let total = locations.len();
+        locations.truncate(MAX_REFERENCES);
+        let mut output = format!("{} references to '{}':\n", total, symbol);
+        output.push_str(&self.session.render(&locations));
+        if total > MAX_REFERENCES {
+            output.push_str(&format!("\n... {} more", total - MAX_REFERENCES));
+        }
+        Ok(output)
+    }
+}
+
+/// A tool that outlines the symbols of a file
+pub struct DocumentSymbolsTool {
+    session: Arc<LspSession>,
+}
+
+#[async_trait]
+impl LlmTool for DocumentSymbolsTool {
+    fn name(&self) -> &str {
+        "DocumentSymbols"
+    }
+
+    fn is_read_only(&self) -> bool {
+        true
+    }
+
+    fn description(&self) -> &str {
+        "Outline a file: its modules, types, functions and their members with line numbers, using rust-analyzer."
+    }
+
+    fn parameters(&self) -> Vec<ToolParameter> {
+        vec![ToolParameter {
+            name: "file_path".to_string(),
+            description: "File to outline, relative to the workspace".to_string(),
+            required: true,
+            default_value: None,
+            param_type: Some(ToolParameterType::String),
+        }]
+    }
+
+    async fn execute(&self, args: &ToolArgs) -> Result<String> {
+        let file = str_arg(args, "file_path").ok_or_else(|| anyhow!("No file path provided"))?;
+        let client = self.session.client().await?;
+        let symbols = client.document_symbols(&file).await?;
+        if symbols.is_empty() {
+            return Ok(format!("No symbols found in {}", file));
+        }
+        let mut output = String::new();
+        render_outline(&symbols, 0, &mut output);
+        Ok(output)
+    }
+}
+
+fn render_outline(symbols: &[Symbol], depth: usize, output: &mut String) {
+    for symbol in symbols {
+        output.push_str(&format!(
+            "{}{} {} (line {})\n",
+            "  ".repeat(depth),
+            symbol.kind,
+            symbol.name,
+            symbol.line
+        ));
+        render_outline(&symbol.children, depth + 1, output);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Minimal language server: indexes instantly, resolves every
+    /// definition to line 1 of lib.rs and outlines every file the same way
+    async fn fake_server(
+        reader: impl AsyncRead + Unpin,
+        mut writer: impl AsyncWrite + Unpin,
+        root: PathBuf,
+    ) -> Result<()> {
+        let mut reader = BufReader::new(reader);
+        let lib = file_uri(&root.join("src/lib.rs"));
+        let mut references_asked = false;
+        while let Some(message) = read_frame(&mut reader).await? {
+            let (Some(id), Some(method)) = (message.id.clone(), message.method.clone()) else {
+                if message.method.as_deref() == Some("initialized") {
+                    // Ask for configuration, as rust-analyzer does
+                    write_frame(
+                        &mut writer,
+                        &JsonRpcMessage::request(
+                            900,
+                            "workspace/configuration",
+                            json!({"items": [{"section": "rust-analyzer"}]}),
+                        ),
+                    )
+                    .await?;
+                    write_frame(
+                        &mut writer,
+                        &JsonRpcMessage::notification(
+                            "experimental/serverStatus",
+                            Some(json!({"health": "ok", "quiescent": true})),
+                        ),
+                    )
+                    .await?;
+                }
+                continue;
+            };
+            let params = message.params.unwrap_or_default();
+            let reply = match method.as_str() {
+                "initialize" => JsonRpcMessage::response(id, json!({"capabilities": {}})),
+                "textDocument/definition" => {
+                    assert_eq!(params["position"], json!({"line": 2, "character": 4}));
+                    JsonRpcMessage::response(
+                        id,
+                        json!([{
+                            "targetUri": lib,
+                            "targetRange": {"start": {"line": 0, "character": 0}, "end": {"line": 0, "character": 20}},
+                            "targetSelectionRange": {"start": {"line": 0, "character": 7}, "end": {"line": 0, "character": 12}}
+                        }]),
+                    )
+                }
+                "textDocument/references" if !references_asked => {
+                    references_asked = true;
+                    JsonRpcMessage::error_response(id, CONTENT_MODIFIED, "content modified")
+                }
+                "textDocument/references" => JsonRpcMessage::response(
+                    id,
+                    json!([
+                        {"uri": file_uri(&root.join("src/main.rs")), "range": {"start": {"line": 2, "character": 4}, "end": {"line": 2, "character": 9}}},
+                        {"uri": lib, "range": {"start": {"line": 0, "character": 7}, "end": {"line": 0, "character": 12}}}
+                    ]),
+                ),
+                "textDocument/documentSymbol" => JsonRpcMessage::response(
+                    id,
+                    json!([{
+                        "name": "Thing", "kind": 23,
+                        "range": {"start": {"line": 1, "character": 0}, "end": {"line": 3, "character": 1}},
+                        "selectionRange": {"start": {"line": 1, "character": 11}, "end": {"line": 1, "character": 16}},
+                        "children": [{
+                            "name": "size", "kind": 8,
+                            "range": {"start": {"line": 2, "character": 4}, "end": {"line": 2, "character": 14}},
+                            "selectionRange": {"start": {"line": 2, "character": 4}, "end": {"line": 2, "character": 8}}
+                        }]
+                    }]),
+                ),
+                other => panic!("unexpected request {}", other),
+            };
+            write_frame(&mut writer, &reply).await?;
+        }
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_symbol_tools_over_fake_server() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().to_path_buf();
+        std::fs::create_dir_all(root.join("src")).unwrap();
+        std::fs::write(root.join("src/lib.rs"), "pub fn hello() {}\n").unwrap();
+        std::fs::write(
+            root.join("src/main.rs"),
+            "fn main() {\n    // hello\n    hello();\n}\n",
+        )
+        .unwrap();
+
+        let (client_io, server_io) = tokio::io::duplex(64 * 1024);
+        let (server_read, server_write) = tokio::io::split(server_io);
+        tokio::spawn(fake_server(server_read, server_write, root.clone()));
+        let (client_read, client_write) = tokio::io::split(client_io);
+        let client = LspClient::connect_streams(&root, client_read, client_write)
+            .await
+            .unwrap();
+        let session = Arc::new(LspSession::with_client(root.clone(), client));
+        let (definition, references, symbols) = session.tools();
+
+        let found = definition
+            .execute_positional(&["src/main.rs", "3", "hello"])
+            .await
+            .unwrap();
+        assert_eq!(found, "src/lib.rs:1:8: pub fn hello() {}");
+
+        let refs = references
+            .execute_positional(&["src/main.rs", "3", "hello"])
+            .await
+            .unwrap();
+        assert_eq!(
+            refs,
+            "2 references to 'hello':\nsrc/lib.rs:1:8: pub fn hello() {}\nsrc/main.rs:3:5: hello();"
+        );
+
+        let outline = symbols.execute_positional(&["src/lib.rs"]).await.unwrap();
+        assert_eq!(outline, "struct Thing (line 2)\n  field size (line 3)\n");
+
+        let missing = definition
+            .execute_positional(&["src/main.rs", "3", "nope"])
+            .await
+            .unwrap_err();
+        assert!(missing.to_string().contains("'nope' not found on line 3"));
+    }
+
+    #[test]
+    fn test_find_word_prefers_whole_identifiers() {
+        assert_eq!(find_word("let hello_world = hello();", "hello"), Some(18));
+        assert_eq!(find_word("hello_world", "hello"), Some(0));
+        assert_eq!(find_word("nothing", "hello"), None);
+    }
+}
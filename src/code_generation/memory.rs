@@ -0,0 +1,513 @@
+//! Lessons learned about files and modules, kept across goals.
+//!
+//! A lesson is a short note scoped to a file or a directory of the
+//! workspace: "tests in src/net are flaky", "src/config uses builders".
+//! The model records them with the `Remember` tool and the strategy records
+//! why a step gave up on a file. Lessons live in the `lessons` collection,
+//! so every later goal touching the same files, or files under the same
+//! directory, is shown them in its prompt. With an embedding model
+//! configured, lessons are also embedded into the `lesson_vectors`
+//! collection, so `Recall` can find lessons about similar problems
+//! elsewhere in the workspace.
+
+use anyhow::{anyhow, Context, Result};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use std::sync::Arc;
+
+use crate::code_generation::llm_tool::{
+    str_arg, LlmTool, ToolArgs, ToolParameter, ToolParameterType,
+};
+use crate::database::{DatabaseError, DatabaseInterface, VectorEntry, VectorStore};
+use crate::providers::metadata::{self, GOAL_ID_HEADER};
+use crate::providers::Provider;
+
+/// Most lessons shown in a prompt
+const MAX_LESSONS: usize = 10;
+
+/// A note about a file or module
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Lesson {
+    /// Unique identifier
+    pub id: String,
+
+    /// Workspace-relative path of the file or directory the lesson is about
+    pub scope: String,
+
+    /// What was learned
+    pub text: String,
+
+    /// The goal during which it was learned
+    #[serde(default)]
+    pub goal_id: Option<String>,
+
+    /// When it was last recorded
+    pub updated_at: DateTime<Utc>,
+}
+
+/// `path` without a leading `./` or trailing `/`
+fn normalize(path: &str) -> String {
+    path.trim()
+        .trim_start_matches("./")
+        .trim_end_matches('/')
+        .to_string()
+}
+
+/// Whether a lesson scoped to `scope` is about the file or directory `path`:
+/// the scope is the path itself or a directory containing it
+pub fn applies_to(scope: &str, path: &str) -> bool {
+    let scope = normalize(scope);
+    scope.is_empty() || Path::new(&normalize(path)).starts_with(&scope)
+}
+
+/// Persists lessons
+pub struct MemoryStore {
+    store: Arc<dyn DatabaseInterface<Lesson>>,
+
+    /// Embeddings of the lessons and what embeds them, when configured
+    embeddings: Option<(Arc<dyn VectorStore>, Arc<dyn Provider>)>,
+}
+
+impl MemoryStore {
+    /// Lessons kept in `store`
+    pub fn new(store: Arc<dyn DatabaseInterface<Lesson>>) -> Self {
+        Self {
+            store,
+            embeddings: None,
+        }
+    }
+
+    /// Also embed lessons into `vectors` with `embedder`, for `related`
+    pub fn with_embeddings(
+        mut self,
+        vectors: Arc<dyn VectorStore>,
+        embedder: Arc<dyn Provider>,
+    ) -> Self {
+        self.embeddings = Some((vectors, embedder));
+        self
+    }
+
+    /// Record `text` about `scope`; recording a lesson again only refreshes
+    /// it
+    pub async fn record(&self, scope: &str, text: &str, goal_id: Option<&str>) -> Result<Lesson> {
+        let scope = normalize(scope);
+        let text = text.trim().to_string();
+        let existing = self
+            .all()
+            .await?
+            .into_iter()
+            .find(|lesson| lesson.scope == scope && lesson.text == text);
+        let lesson = Lesson {
+            id: existing
+                .as_ref()
+                .map(|lesson| lesson.id.clone())
+                .unwrap_or_else(|| uuid::Uuid::new_v4().to_string()),
+            scope,
+            text,
+            goal_id: goal_id.map(str::to_string),
+            updated_at: Utc::now(),
+        };
+        let saved = match existing {
+            Some(_) => self.store.update(lesson, None).await,
+            None => self.store.insert(lesson).await,
+        };
+        let lesson = saved.context("Failed to save lesson")?.entity;
+        // The lesson is kept even when it cannot be embedded
+        if let Err(e) = self.embed(std::slice::from_ref(&lesson)).await {
+            warn!("Failed to embed lesson about {}: {:#}", lesson.scope, e);
+        }
+        Ok(lesson)
+    }
+
+    /// Store the embeddings of `lessons`, when lessons are embedded
+    async fn embed(&self, lessons: &[Lesson]) -> Result<()> {
+        let Some((vectors, embedder)) = &self.embeddings else {
+            return Ok(());
+        };
+        if lessons.is_empty() {
+            return Ok(());
+        }
+        let texts: Vec<String> = lessons
+            .iter()
+            .map(|lesson| format!("{}: {}", lesson.scope, lesson.text))
+            .collect();
+        let embedded = embedder
+            .embed(&texts)
+            .await
+            .map_err(|e| anyhow!("Failed to embed lessons: {}", e))?;
+        let entries = lessons
+            .iter()
+            .zip(embedded)
+            .map(|(lesson, vector)| VectorEntry::new(&lesson.id, vector, serde_json::Value::Null))
+            .collect();
+        vectors
+            .upsert(entries)
+            .await
+            .context("Failed to save lesson embeddings")?;
+        Ok(())
+    }
+
+    /// The `limit` lessons closest in meaning to `query`, the closest
+    /// first; none when lessons are not embedded
+    pub async fn related(&self, query: &str, limit: usize) -> Result<Vec<Lesson>> {
+        let Some((vectors, embedder)) = &self.embeddings else {
+            return Ok(Vec::new());
+        };
+        // Lessons recorded before embedding was configured
+        let lessons = self.all().await?;
+        if vectors
+            .count()
+            .await
+            .context("Failed to load lesson embeddings")?
+            < lessons.len()
+        {
+            self.embed(&lessons).await?;
+        }
+
+        let query = embedder
+            .embed(&[query.to_string()])
+            .await
+            .map_err(|e| anyhow!("Failed to embed the query: {}", e))?
+            .pop()
+            .unwrap_or_default();
+        let mut related = Vec::new();
+        for found in vectors
+            .nearest(&query, limit)
+            .await
+            .context("Failed to search lesson embeddings")?
+        {
+            match self.store.get(&found.id).await {
+                Ok(record) => related.push(record.entity),
+                Err(DatabaseError::NotFound(_)) => {}
+                Err(e) => return Err(e).context("Failed to load lesson"),
+            }
+        }
+        Ok(related)
+    }
+
+    /// Every stored lesson, the most recently recorded first
+    pub async fn all(&self) -> Result<Vec<Lesson>> {
+        let mut lessons: Vec<Lesson> = self
+            .store
+            .get_all()
+            .await
+            .context("Failed to load lessons")?
+            .into_iter()
+            .map(|record| record.entity)
+            .collect();
+        lessons.sort_by_key(|lesson| std::cmp::Reverse(lesson.updated_at));
+        Ok(lessons)
+    }
+
+    /// Lessons about any of `paths`, the most recently recorded first
+    pub async fn for_paths(&self, paths: &[String]) -> Result<Vec<Lesson>> {
+        Ok(self
+            .all()
+            .await?
+            .into_iter()
+            .filter(|lesson| paths.iter().any(|path| applies_to(&lesson.scope, path)))
+            .collect())
+    }
+}
+
+/// The lesson store of the current services, if there is one
+pub fn global() -> Option<Arc<MemoryStore>> {
+    crate::core::services::Services::current().lessons.clone()
+}
+
+/// Record a lesson in the process-wide store, if one is installed; failures
+/// are logged, since losing a lesson must not fail the work that taught it
+pub async fn remember(scope: &str, text: &str, goal_id: Option<&str>) {
+    if let Some(store) = global() {
+        if let Err(e) = store.record(scope, text, goal_id).await {
+            warn!("Failed to record lesson about {}: {:#}", scope, e);
+        }
+    }
+}
+
+/// Prompt section with the lessons about `paths`, empty when there are none
+/// or when no store is installed
+pub async fn lessons_section(paths: &[String]) -> String {
+    let Some(store) = global() else {
+        return String::new();
+    };
+    let lessons = match store.for_paths(paths).await {
+        Ok(lessons) => lessons,
+        Err(e) => {
+            warn!("Failed to load lessons: {:#}", e);
+            return String::new();
+        }
+    };
+    if lessons.is_empty() {
+        return String::new();
+    }
+    let mut s = String::from("## Lessons from earlier goals:\n");
+    for lesson in lessons.iter().take(MAX_LESSONS) {
+        s.push_str(&format!("- {}: {}\n", lesson.scope, lesson.text));
+    }
+    s.push('\n');
+    s
+}
+
+/// A tool that records a lesson about a file or module
+pub struct RememberTool;
+
+impl RememberTool {
+    /// Create a new Remember tool
+    pub fn new() -> Self {
+        Self
+    }
+}
+
+impl Default for RememberTool {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[async_trait]
+impl LlmTool for RememberTool {
+    fn name(&self) -> &str {
+        "Remember"
+    }
+
+    fn description(&self) -> &str {
+        "Record a lesson about a file or directory that later goals touching it should know, e.g. that its tests are flaky or that it follows a builder pattern."
+    }
+
+    fn parameters(&self) -> Vec<ToolParameter> {
+        vec![
+            ToolParameter {
+                name: "path".to_string(),
+                description: "Workspace-relative path of the file or directory the lesson is about"
+                    .to_string(),
+                required: true,
+                default_value: None,
+                param_type: Some(ToolParameterType::String),
+            },
+            ToolParameter {
+                name: "lesson".to_string(),
+                description: "The lesson, in one or two sentences".to_string(),
+                required: true,
+                default_value: None,
+                param_type: Some(ToolParameterType::String),
+            },
+        ]
+    }
+
+    async fn execute(&self, args: &ToolArgs) -> Result<String> {
+        let path = str_arg(args, "path").ok_or_else(|| anyhow!("path parameter is required"))?;
+        let text =
+            str_arg(args, "lesson").ok_or_else(|| anyhow!("lesson parameter is required"))?;
+        if text.trim().is_empty() {
+            return Err(anyhow!("lesson must not be empty"));
+        }
+        let store = global().ok_or_else(|| anyhow!("No lesson store is available"))?;
+        let goal_id = metadata::current().get(GOAL_ID_HEADER).cloned();
+        let lesson = store.record(&path, &text, goal_id.as_deref()).await?;
+        info!("Lesson about {}: {}", lesson.scope, lesson.text);
+        Ok(format!(
+            "Remembered about {}: {}",
+            lesson.scope, lesson.text
+        ))
+    }
+}
+
+/// A tool that shows the lessons about a file or directory
+pub struct RecallTool;
+
+impl RecallTool {
+    /// Create a new Recall tool
+    pub fn new() -> Self {
+        Self
+    }
+}
+
+impl Default for RecallTool {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[async_trait]
+impl LlmTool for RecallTool {
+    fn name(&self) -> &str {
+        "Recall"
+    }
+
+    fn description(&self) -> &str {
+        "Show the lessons earlier goals recorded about a file or directory and the directories containing it, and with query=\"...\" the lessons closest in meaning to the query from anywhere in the workspace."
+    }
+
+    fn parameters(&self) -> Vec<ToolParameter> {
+        vec![
+            ToolParameter {
+                name: "path".to_string(),
+                description: "Workspace-relative path of a file or directory".to_string(),
+                required: false,
+                default_value: None,
+                param_type: Some(ToolParameterType::String),
+            },
+            ToolParameter {
+                name: "query".to_string(),
+                description: "What the lessons should be about, e.g. \"flaky network tests\""
+                    .to_string(),
+                required: false,
+                default_value: None,
+                param_type: Some(ToolParameterType::String),
+            },
+        ]
+    }
+
+    fn is_read_only(&self) -> bool {
+        true
+    }
+
+    async fn execute(&self, args: &ToolArgs) -> Result<String> {
+        let path = str_arg(args, "path");
+        let query = str_arg(args, "query");
+        if path.is_none() && query.is_none() {
+            return Err(anyhow!("path or query parameter is required"));
+        }
+        let store = global().ok_or_else(|| anyhow!("No lesson store is available"))?;
+        let mut lessons = match &path {
+            Some(path) => store.for_paths(std::slice::from_ref(path)).await?,
+            None => Vec::new(),
+        };
+        if let Some(query) = &query {
+            for lesson in store.related(query, MAX_LESSONS).await? {
+                if !lessons.contains(&lesson) {
+                    lessons.push(lesson);
+                }
+            }
+        }
+        if lessons.is_empty() {
+            return Ok(format!(
+                "No lessons about {}",
+                path.or(query).unwrap_or_default()
+            ));
+        }
+        Ok(lessons
+            .iter()
+            .map(|lesson| format!("- {}: {}", lesson.scope, lesson.text))
+            .collect::<Vec<_>>()
+            .join("\n"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::code_generation::test_support::WordEmbedder;
+    use crate::core::services::Services;
+    use crate::database::FileDb;
+    use serde_json::json;
+
+    fn args(value: serde_json::Value) -> ToolArgs {
+        value.as_object().unwrap().clone()
+    }
+
+    #[test]
+    fn test_lessons_apply_to_their_file_and_directory() {
+        assert!(applies_to("src/net", "src/net/client.rs"));
+        assert!(applies_to("./src/net/", "src/net"));
+        assert!(applies_to("src/net/client.rs", "./src/net/client.rs"));
+        assert!(!applies_to("src/net", "src/network/mod.rs"));
+        assert!(!applies_to("src/net/client.rs", "src/net"));
+    }
+
+    #[tokio::test]
+    async fn test_lessons_persist_and_reach_the_prompt() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = FileDb::<Lesson>::new(dir.path(), "lessons").await.unwrap();
+        // The store is this test's own; other tests see none
+        let services = Arc::new(Services {
+            lessons: Some(Arc::new(MemoryStore::new(Arc::new(db)))),
+            ..Default::default()
+        });
+        services
+            .scope(async {
+                let out =
+                    metadata::scope(metadata::attribution(Some("goal-3"), "code:gpt"), async {
+                        RememberTool::new()
+                            .execute(&args(json!({
+                                "path": "src/net/",
+                                "lesson": "Tests here are flaky under load"
+                            })))
+                            .await
+                            .unwrap()
+                    })
+                    .await;
+                assert_eq!(
+                    out,
+                    "Remembered about src/net: Tests here are flaky under load"
+                );
+                remember(
+                    "src/config.rs",
+                    "Settings are built with with_x builders",
+                    None,
+                )
+                .await;
+                // Recording the same lesson again keeps one copy
+                remember("src/net", "Tests here are flaky under load", Some("goal-4")).await;
+
+                let reopened = MemoryStore::new(Arc::new(
+                    FileDb::<Lesson>::new(dir.path(), "lessons").await.unwrap(),
+                ));
+                let lessons = reopened.for_paths(&["src/net".to_string()]).await.unwrap();
+                assert_eq!(lessons.len(), 1);
+                assert_eq!(lessons[0].goal_id.as_deref(), Some("goal-4"));
+
+                let section = lessons_section(&["src/net/client.rs".to_string()]).await;
+                let expected = "## Lessons from earlier goals:\n\
+                                - src/net: Tests here are flaky under load\n\n";
+                assert_eq!(section, expected);
+                assert!(lessons_section(&["src/main.rs".to_string()])
+                    .await
+                    .is_empty());
+
+                let recalled = RecallTool::new()
+                    .execute(&args(json!({ "path": "src/config.rs" })))
+                    .await
+                    .unwrap();
+                assert_eq!(
+                    recalled,
+                    "- src/config.rs: Settings are built with with_x builders"
+                );
+            })
+            .await;
+    }
+
+    #[tokio::test]
+    async fn test_related_lessons_are_found_by_meaning() {
+        let dir = tempfile::tempdir().unwrap();
+        let lessons: Arc<dyn DatabaseInterface<Lesson>> =
+            Arc::new(FileDb::<Lesson>::new(dir.path(), "lessons").await.unwrap());
+        // A lesson recorded before embedding was configured
+        MemoryStore::new(lessons.clone())
+            .record("src/net", "Tests here are flaky on a slow timeout", None)
+            .await
+            .unwrap();
+        let store = MemoryStore::new(lessons).with_embeddings(
+            Arc::new(
+                crate::database::FileVectorStore::new(dir.path(), "lesson_vectors")
+                    .await
+                    .unwrap(),
+            ),
+            Arc::new(WordEmbedder::new(&["flaky", "timeout", "builder"])),
+        );
+        store
+            .record("src/config", "Settings use a builder", None)
+            .await
+            .unwrap();
+
+        let related = store.related("which tests are flaky", 1).await.unwrap();
+        assert_eq!(related.len(), 1);
+        assert_eq!(related[0].scope, "src/net");
+        let related = store.related("builder pattern", 1).await.unwrap();
+        assert_eq!(related[0].scope, "src/config");
+    }
+}
@@ -1,10 +1,31 @@
 pub mod candidate;
+pub mod change_limits;
+pub mod code_query;
+pub mod context_builder;
+pub mod crate_docs;
 pub mod generator;
+pub mod language;
 pub mod llm;
 pub mod llm_generator;
 pub mod llm_logging;
 pub mod llm_tool;
+pub mod lsp;
+pub mod memory;
+pub mod patch;
+pub mod permissions;
 pub mod prompt;
 pub mod rater;
+pub mod repo_map;
+pub mod reviewer;
+pub mod router;
+pub mod sandbox;
+pub mod semantic_index;
 pub mod spec_generator;
 pub mod test_generator;
+#[cfg(test)]
+pub(crate) mod test_support;
+pub mod todos;
+pub mod tool_audit;
+pub mod tool_schema;
+pub mod web_fetch;
+pub mod workspace_guard;
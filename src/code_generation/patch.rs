@@ -0,0 +1,653 @@
+//! Unified diffs: parsing, fuzzy application and creation.
+//!
+//! Models often write diffs with stale line numbers, wrong hunk counts or
+//! mangled whitespace, so hunks are located by their content rather than
+//! their header: the old side of each hunk (context and removed lines) is
+//! searched for nearest to where the header says it should be, first
+//! exactly, then ignoring surrounding whitespace, and finally with up to
+//! `MAX_FUZZ` lines of outer context dropped. A patch is applied as a whole
+//! or not at all, with a report saying where each hunk landed or why it did
+//! not.
+
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use crate::code_generation::workspace_guard::WorkspaceGuard;
+
+/// Outer context lines a hunk may lose and still apply
+pub const MAX_FUZZ: usize = 2;
+
+/// One line of a hunk
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HunkLine {
+    Context(String),
+    Remove(String),
+    Add(String),
+}
+
+/// A `@@ -a,b +c,d @@` section of a file patch
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Hunk {
+    /// 1-based line where the header places the hunk in the old file
+    pub old_start: usize,
+    pub lines: Vec<HunkLine>,
+}
+
+impl Hunk {
+    /// Lines the hunk expects to find (context and removals)
+    fn old_lines(&self) -> Vec<&str> {
+        self.lines
+            .iter()
+            .filter_map(|l| match l {
+                HunkLine::Context(s) | HunkLine::Remove(s) => Some(s.as_str()),
+                HunkLine::Add(_) => None,
+            })
+            .collect()
+    }
+
+    /// Leading and trailing context line counts
+    fn outer_context(&self) -> (usize, usize) {
+        let is_context = |l: &&HunkLine| matches!(l, HunkLine::Context(_));
+        let leading = self.lines.iter().take_while(is_context).count();
+        let trailing = self.lines.iter().rev().take_while(is_context).count();
+        (leading, trailing)
+    }
+}
+
+/// Changes to one file; a missing path is `/dev/null` (file created or
+/// deleted)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FilePatch {
+    pub old_path: Option<String>,
+    pub new_path: Option<String>,
+    pub hunks: Vec<Hunk>,
+}
+
+impl FilePatch {
+    /// Path the patch is reported under
+    pub fn display_path(&self) -> &str {
+        self.new_path
+            .as_deref()
+            .or(self.old_path.as_deref())
+            .unwrap_or("/dev/null")
+    }
+}
+
+/// Parse a unified diff covering one or more files
+///
+/// `diff --git`, `index` and similar header lines are skipped; hunk line
+/// counts are ignored, since a hunk ends at the next hunk or file header.
+pub fn parse_unified_diff(diff: &str) -> Result<Vec<FilePatch>, String> {
+    let lines: Vec<&str> = diff.lines().collect();
+    let mut patches: Vec<FilePatch> = Vec::new();
+    let mut i = 0;
+    while i < lines.len() {
+        let line = lines[i];
+        if is_file_header(&lines, i) {
+            patches.push(FilePatch {
+                old_path: header_path(&line[4..]),
+                new_path: header_path(&lines[i + 1][4..]),
+                hunks: Vec::new(),
+            });
+            i += 2;
+            continue;
+        }
+        if line.starts_with("@@") {
+            let Some(patch) = patches.last_mut() else {
+                return Err(format!(
+                    "line {}: hunk before any '---'/'+++' file header",
+                    i + 1
+                ));
+            };
+            let old_start = parse_hunk_header(line)
+                .ok_or_else(|| format!("line {}: malformed hunk header '{}'", i + 1, line))?;
+            let mut hunk = Hunk {
+                old_start,
+                lines: Vec::new(),
+            };
+            i += 1;
+            while i < lines.len() && !lines[i].starts_with("@@") && !is_file_header(&lines, i) {
+                let body = lines[i];
+                if body.starts_with("diff ") {
+                    break;
+                }
+                match body.chars().next() {
+                    Some('+') => hunk.lines.push(HunkLine::Add(body[1..].to_string())),
+                    Some('-') => hunk.lines.push(HunkLine::Remove(body[1..].to_string())),
+                    Some(' ') => hunk.lines.push(HunkLine::Context(body[1..].to_string())),
+                    // "\ No newline at end of file"
+                    Some('\\') => {}
+                    // Editors and models strip the space of blank context lines
+                    None => hunk.lines.push(HunkLine::Context(String::new())),
+                    Some(_) => break,
+                }
+                i += 1;
+            }
+            while matches!(hunk.lines.last(), Some(HunkLine::Context(s)) if s.is_empty()) {
+                hunk.lines.pop();
+            }
+            patch.hunks.push(hunk);
+            continue;
+        }
+        i += 1;
+    }
+
+    if patches.is_empty() {
+        return Err("no '---'/'+++' file headers found; expected a unified diff".to_string());
+    }
+    if let Some(empty) = patches
+        .iter()
+        .find(|p| p.hunks.is_empty() && p.old_path.is_some() && p.new_path.is_some())
+    {
+        return Err(format!("no hunks for {}", empty.display_path()));
+    }
+    Ok(patches)
+}
+
+fn is_file_header(lines: &[&str], i: usize) -> bool {
+    lines[i].starts_with("--- ") && lines.get(i + 1).is_some_and(|l| l.starts_with("+++ "))
+}
+
+/// Path from a `---`/`+++` header, without `a/`/`b/` prefixes or timestamps
+fn header_path(raw: &str) -> Option<String> {
+    let path = raw.split('\t').next().unwrap_or(raw).trim();
+    if path == "/dev/null" {
+        return None;
+    }
+    let path = path
+        .strip_prefix("a/")
+        .or_else(|| path.strip_prefix("b/"))
+        .unwrap_or(path);
+    Some(path.to_string())
+}
+
+/// Old-file start line of a `@@ -a,b +c,d @@` header
+fn parse_hunk_header(line: &str) -> Option<usize> {
+    let old = line
+        .strip_prefix("@@")?
+        .trim_start()
+        .strip_prefix('-')?
+        .split([',', ' '])
+        .next()?;
+    old.parse().ok()
+}
+
+/// How a hunk was matched against the file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HunkOutcome {
+    /// Applied at 1-based `line`, `offset` lines from where its header said,
+    /// after ignoring whitespace and/or dropping `fuzz` context lines
+    Applied {
+        line: usize,
+        offset: isize,
+        whitespace: bool,
+        fuzz: usize,
+    },
+    Failed {
+        reason: String,
+    },
+}
+
+/// Per-hunk outcomes for one file
+#[derive(Debug, Clone)]
+pub struct FileReport {
+    pub path: String,
+    pub action: &'static str,
+    pub hunks: Vec<HunkOutcome>,
+}
+
+impl FileReport {
+    fn failed(&self) -> bool {
+        self.hunks
+            .iter()
+            .any(|h| matches!(h, HunkOutcome::Failed { .. }))
+    }
+}
+
+/// What applying a patch did, or would have done
+#[derive(Debug, Clone)]
+pub struct PatchReport {
+    pub files: Vec<FileReport>,
+}
+
+impl PatchReport {
+    /// Whether every hunk applied
+    pub fn success(&self) -> bool {
+        !self.files.iter().any(FileReport::failed)
+    }
+}
+
+impl fmt::Display for PatchReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for file in &self.files {
+            writeln!(f, "{} ({})", file.path, file.action)?;
+            for (i, hunk) in file.hunks.iter().enumerate() {
+                match hunk {
+                    HunkOutcome::Applied {
+                        line,
+                        offset,
+                        whitespace,
+                        fuzz,
+                    } => {
+                        write!(f, "  hunk {}: applied at line {}", i + 1, line)?;
+                        if *offset != 0 {
+                            write!(f, " (offset {:+})", offset)?;
+                        }
+                        if *whitespace {
+                            write!(f, " ignoring whitespace")?;
+                        }
+                        if *fuzz > 0 {
+                            write!(f, " with fuzz {}", fuzz)?;
+                        }
+                        writeln!(f)?;
+                    }
+                    HunkOutcome::Failed { reason } => {
+                        writeln!(f, "  hunk {}: FAILED: {}", i + 1, reason)?
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Apply `hunks` to `original`, returning the new content if every hunk
+/// applied, and each hunk's outcome either way
+pub fn apply_hunks(original: &str, hunks: &[Hunk]) -> (Option<String>, Vec<HunkOutcome>) {
+    let trailing_newline = original.is_empty() || original.ends_with('\n');
+    let mut lines: Vec<String> = original.lines().map(str::to_string).collect();
+    let mut outcomes = Vec::new();
+    // Lines added minus lines removed by the hunks applied so far
+    let mut shift: isize = 0;
+    // Hunks apply in order and must not overlap
+    let mut floor = 0;
+
+    for hunk in hunks {
+        match locate(&lines, hunk, shift, floor) {
+            Some(m) => {
+                let window = &hunk.lines[m.fuzz_lead..hunk.lines.len() - m.fuzz_trail];
+                let replaced = window
+                    .iter()
+                    .filter(|l| !matches!(l, HunkLine::Add(_)))
+                    .count();
+                let replacement =
+                    replacement(window, &lines[m.start..m.start + replaced], m.whitespace);
+                let added = replacement.len();
+                lines.splice(m.start..m.start + replaced, replacement);
+
+                let expected =
+                    hunk.old_start.saturating_sub(1) as isize + shift + m.fuzz_lead as isize;
+                outcomes.push(HunkOutcome::Applied {
+                    line: m.start + 1,
+                    offset: m.start as isize - expected.max(0),
+                    whitespace: m.whitespace,
+                    fuzz: m.fuzz_lead.max(m.fuzz_trail),
+                });
+                shift += added as isize - replaced as isize;
+                floor = m.start + added;
+            }
+            None => {
+                let first = hunk
+                    .old_lines()
+                    .into_iter()
+                    .find(|l| !l.trim().is_empty())
+                    .unwrap_or("")
+                    .trim()
+                    .to_string();
+                outcomes.push(HunkOutcome::Failed {
+                    reason: format!(
+                        "lines expected near line {} not found (first: '{}')",
+                        hunk.old_start, first
+                    ),
+                });
+            }
+        }
+    }
+
+    if outcomes
+        .iter()
+        .any(|o| matches!(o, HunkOutcome::Failed { .. }))
+    {
+        return (None, outcomes);
+    }
+    let mut content = lines.join("\n");
+    if trailing_newline && !content.is_empty() {
+        content.push('\n');
+    }
+    (Some(content), outcomes)
+}
+
+struct Match {
+    start: usize,
+    whitespace: bool,
+    fuzz_lead: usize,
+    fuzz_trail: usize,
+}
+
+fn locate(lines: &[String], hunk: &Hunk, shift: isize, floor: usize) -> Option<Match> {
+    let old = hunk.old_lines();
+    let (lead, trail) = hunk.outer_context();
+    for fuzz in 0..=MAX_FUZZ {
+        let (fl, ft) = (fuzz.min(lead), fuzz.min(trail));
+        if fuzz > 0 && fl + ft == 0 {
+            break;
+        }
+        let needle = &old[fl..old.len() - ft];
+        let expected = (hunk.old_start.saturating_sub(1) as isize + shift + fl as isize).max(0);
+        for whitespace in [false, true] {
+            if let Some(start) = nearest(lines, needle, expected as usize, floor, whitespace) {
+                return Some(Match {
+                    start,
+                    whitespace,
+                    fuzz_lead: fl,
+                    fuzz_trail: ft,
+                });
+            }
+        }
+    }
+    None
+}
+
+/// Position at or after `floor` where `needle` occurs, nearest to `expected`
+fn nearest(
+    lines: &[String],
+    needle: &[&str],
+    expected: usize,
+    floor: usize,
+    whitespace: bool,
+) -> Option<usize> {
+    let same = |a: &str, b: &str| {
+        if whitespace {
+            a.split_whitespace().eq(b.split_whitespace())
+        } else {
+            a == b
+        }
+    };
+    if needle.is_empty() {
+        // Pure insertion: trust the header
+        return Some(expected.clamp(floor, lines.len().max(floor)));
+    }
+    if needle.len() > lines.len() {
+        return None;
+    }
+    (floor..=lines.len() - needle.len())
+        .filter(|&start| {
+            needle
+                .iter()
+                .zip(&lines[start..])
+                .all(|(want, have)| same(want, have))
+        })
+        .min_by_key(|&start| start.abs_diff(expected))
+}
+
+/// New text for the `found` lines matched by the hunk lines in `window`
+///
+/// After a whitespace-insensitive match, context lines keep the file's own
+/// text rather than the hunk's version of it.
+fn replacement(window: &[HunkLine], found: &[String], whitespace: bool) -> Vec<String> {
+    let mut old_idx = 0;
+    let mut lines = Vec::new();
+    for line in window {
+        match line {
+            HunkLine::Context(text) => {
+                lines.push(if whitespace {
+                    found[old_idx].clone()
+                } else {
+                    text.clone()
+                });
+                old_idx += 1;
+            }
+            HunkLine::Remove(_) => old_idx += 1,
+            HunkLine::Add(text) => lines.push(text.clone()),
+        }
+    }
+    lines
+}
+
+/// The unified diff turning `original` into `updated`, with `path` in its
+/// file headers; a new file is a diff from empty content
+pub fn unified_diff(path: &str, original: &str, updated: &str) -> Result<String, String> {
+    let path = Path::new(path);
+    let mut patch = git2::Patch::from_buffers(
+        original.as_bytes(),
+        Some(path),
+        updated.as_bytes(),
+        Some(path),
+        None,
+    )
+    .map_err(|e| e.to_string())?;
+    let buf = patch.to_buf().map_err(|e| e.to_string())?;
+    Ok(String::from_utf8_lossy(&buf).into_owned())
+}
+
+/// `path` inside `workspace`, refusing paths that resolve outside of it
+pub fn workspace_path(workspace: &Path, path: &str) -> Result<PathBuf, String> {
+    WorkspaceGuard::new(workspace)
+        .resolve(path)
+        .map_err(|e| e.to_string())
+}
+
+/// Apply a multi-file unified diff to `workspace`
+///
+/// Nothing is written unless every hunk of every file applies; the report
+/// says what happened to each hunk in either case.
+pub fn apply_to_workspace(workspace: &Path, diff: &str) -> Result<PatchReport, String> {
+    let patches = parse_unified_diff(diff)?;
+    let mut report = PatchReport { files: Vec::new() };
+    let mut writes: Vec<(PathBuf, Option<String>)> = Vec::new();
+
+    for patch in &patches {
+        let old = patch
+            .old_path
+            .as_deref()
+            .map(|p| workspace_path(workspace, p))
+            .transpose()?;
+        let new = patch
+            .new_path
+            .as_deref()
+            .map(|p| workspace_path(workspace, p))
+            .transpose()?;
+        let path = patch.display_path().to_string();
+
+        let (action, original) = match (&old, &new) {
+            (None, Some(new)) => {
+                if new.exists() {
+                    report.files.push(FileReport {
+                        path,
+                        action: "create",
+                        hunks: vec![HunkOutcome::Failed {
+                            reason: "file already exists".to_string(),
+                        }],
+                    });
+                    continue;
+                }
+                ("create", String::new())
+            }
+            (Some(old), _) => match std::fs::read_to_string(old) {
+                Ok(content) => {
+                    let action = match &new {
+                        None => "delete",
+                        Some(new) if new != old => "rename",
+                        Some(_) => "modify",
+                    };
+                    (action, content)
+                }
+                Err(e) => {
+                    report.files.push(FileReport {
+                        path,
+                        action: "modify",
+                        hunks: vec![HunkOutcome::Failed {
+                            reason: format!("cannot read file: {}", e),
+                        }],
+                    });
+                    continue;
+                }
+            },
+            (None, None) => return Err("file header with /dev/null on both sides".to_string()),
+        };
+
+        let (content, hunks) = if patch.hunks.is_empty() {
+            (Some(original), Vec::new())
+        } else {
+            apply_hunks(&original, &patch.hunks)
+        };
+        report.files.push(FileReport {
+            path,
+            action,
+            hunks,
+        });
+        let Some(content) = content else {
+            continue;
+        };
+        match (old, new) {
+            (Some(old), None) => writes.push((old, None)),
+            (Some(old), Some(new)) if old != new => {
+                writes.push((old, None));
+                writes.push((new, Some(content)));
+            }
+            (_, Some(new)) => writes.push((new, Some(content))),
+            (None, None) => {}
+        }
+    }
+
+    if !report.success() {
+        return Ok(report);
+    }
+    for (path, content) in writes {
+        let result = match content {
+            Some(content) => path
+                .parent()
+                .map_or(Ok(()), std::fs::create_dir_all)
+                .and_then(|_| std::fs::write(&path, content)),
+            None => std::fs::remove_file(&path),
+        };
+        result.map_err(|e| format!("failed to update {}: {}", path.display(), e))?;
+    }
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ORIGINAL: &str = "fn a() {\n    1\n}\n\nfn b() {\n    2\n}\n\nfn c() {\n    3\n}\n";
+
+    #[test]
+    fn test_parse_multi_file_diff() {
+        let diff = "diff --git a/src/x.rs b/src/x.rs\nindex 1..2 100644\n--- a/src/x.rs\n+++ b/src/x.rs\n@@ -1,3 +1,3 @@\n fn a() {\n-    1\n+    10\n }\n--- /dev/null\n+++ b/src/new.rs\n@@ -0,0 +1 @@\n+pub fn new() {}\n";
+        let patches = parse_unified_diff(diff).unwrap();
+        assert_eq!(patches.len(), 2);
+        assert_eq!(patches[0].old_path.as_deref(), Some("src/x.rs"));
+        assert_eq!(
+            patches[0].hunks[0].lines,
+            vec![
+                HunkLine::Context("fn a() {".to_string()),
+                HunkLine::Remove("    1".to_string()),
+                HunkLine::Add("    10".to_string()),
+                HunkLine::Context("}".to_string()),
+            ]
+        );
+        assert_eq!(patches[1].old_path, None);
+        assert_eq!(patches[1].new_path.as_deref(), Some("src/new.rs"));
+        assert!(parse_unified_diff("just prose").is_err());
+    }
+
+    #[test]
+    fn test_created_diffs_apply_back() {
+        let updated = ORIGINAL.replace("    2\n", "    20\n    21\n");
+        let diff = unified_diff("src/x.rs", ORIGINAL, &updated).unwrap();
+        assert!(
+            diff.contains("--- a/src/x.rs\n+++ b/src/x.rs\n"),
+            "{}",
+            diff
+        );
+        let patch = &parse_unified_diff(&diff).unwrap()[0];
+        assert_eq!(apply_hunks(ORIGINAL, &patch.hunks).0.unwrap(), updated);
+
+        let diff = unified_diff("src/new.rs", "", "fn n() {}\n").unwrap();
+        let patch = &parse_unified_diff(&diff).unwrap()[0];
+        assert_eq!(apply_hunks("", &patch.hunks).0.unwrap(), "fn n() {}\n");
+    }
+
+    #[test]
+    fn test_hunks_apply_despite_stale_line_numbers() {
+        // Header says line 1 but fn c is at line 9
+        let diff = "--- a/x.rs\n+++ b/x.rs\n@@ -1,3 +1,3 @@\n fn c() {\n-    3\n+    30\n }\n";
+        let patch = &parse_unified_diff(diff).unwrap()[0];
+        let (content, outcomes) = apply_hunks(ORIGINAL, &patch.hunks);
+        assert!(content.unwrap().contains("    30\n}\n"));
+        assert_eq!(
+            outcomes[0],
+            HunkOutcome::Applied {
+                line: 9,
+                offset: 8,
+                whitespace: false,
+                fuzz: 0
+            }
+        );
+    }
+
+    #[test]
+    fn test_whitespace_and_fuzz_fallbacks() {
+        // Mis-indented context, and a leading context line that is wrong
+        let diff = "--- a/x.rs\n+++ b/x.rs\n@@ -5,3 +5,3 @@\n fn b()   {\n-  2\n+    20\n }\n@@ -8,4 +8,4 @@\n // gone\n fn c() {\n-    3\n+    30\n }\n";
+        let patch = &parse_unified_diff(diff).unwrap()[0];
+        let (content, outcomes) = apply_hunks(ORIGINAL, &patch.hunks);
+        let content = content.unwrap();
+        assert!(content.contains("fn b() {\n    20\n}"), "{}", content);
+        assert!(content.contains("fn c() {\n    30\n}"), "{}", content);
+        assert!(matches!(
+            outcomes[0],
+            HunkOutcome::Applied {
+                whitespace: true,
+                fuzz: 0,
+                ..
+            }
+        ));
+        assert!(matches!(outcomes[1], HunkOutcome::Applied { fuzz: 1, .. }));
+    }
+
+    #[test]
+    fn test_failed_hunk_is_reported_and_nothing_written() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("x.rs"), ORIGINAL).unwrap();
+        let diff = "--- a/x.rs\n+++ b/x.rs\n@@ -1,3 +1,3 @@\n fn a() {\n-    1\n+    10\n }\n@@ -20,3 +20,3 @@\n fn zzz() {\n-    9\n+    90\n }\n";
+
+        let report = apply_to_workspace(dir.path(), diff).unwrap();
+
+        assert!(!report.success());
+        let text = report.to_string();
+        assert!(text.contains("hunk 1: applied at line 1"), "{}", text);
+        assert!(
+            text.contains(
+                "hunk 2: FAILED: lines expected near line 20 not found (first: 'fn zzz() {')"
+            ),
+            "{}",
+            text
+        );
+        assert_eq!(
+            std::fs::read_to_string(dir.path().join("x.rs")).unwrap(),
+            ORIGINAL
+        );
+    }
+
+    #[test]
+    fn test_create_delete_and_escape() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("old.rs"), "x\n").unwrap();
+        let diff = "--- /dev/null\n+++ b/src/new.rs\n@@ -0,0 +1,2 @@\n+a\n+b\n--- a/old.rs\n+++ /dev/null\n@@ -1 +0,0 @@\n-x\n";
+
+        let report = apply_to_workspace(dir.path(), diff).unwrap();
+
+        assert!(report.success(), "{}", report);
+        assert_eq!(
+            std::fs::read_to_string(dir.path().join("src/new.rs")).unwrap(),
+            "a\nb\n"
+        );
+        assert!(!dir.path().join("old.rs").exists());
+        let escape = "--- a/../x.rs\n+++ b/../x.rs\n@@ -1 +1 @@\n-a\n+b\n";
+        assert!(apply_to_workspace(dir.path(), escape)
+            .unwrap_err()
+            .contains("outside the workspace"));
+    }
+}
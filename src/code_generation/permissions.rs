@@ -0,0 +1,335 @@
+//! Tool permission policy.
+//!
+//! Every tool call goes through `ToolRegistry`, which asks the process-wide
+//! `PermissionPolicy` before running it. Rules from `permissions` in the
+//! config allow, deny or ask about calls by tool name, by the paths the call
+//! touches and by the prefix of the shell command it runs. "Ask" prompts
+//! the human at the terminal when there is one and denies the call
+//! otherwise; every decision a rule or a human made is emitted as a
+//! `RunEvent::ToolPermission`.
+
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use glob::{MatchOptions, Pattern};
+use std::io::{BufRead, IsTerminal, Write};
+use std::sync::Arc;
+
+use crate::code_generation::llm_tool::{str_arg, touched_paths, ToolArgs};
+use crate::core::config::{PermissionAction, ToolPermissionsConfig};
+use crate::core::events::{self, RunEvent};
+
+/// Longest argument summary shown when asking for approval
+const MAX_SUMMARY_CHARS: usize = 300;
+
+/// Asks a human whether a tool call may run
+#[async_trait]
+pub trait PermissionPrompter: Send + Sync {
+    /// Whether the call of `tool` described by `summary` is approved
+    async fn confirm(&self, tool: &str, summary: &str) -> bool;
+}
+
+/// Prompts on stderr and reads the answer from stdin
+#[derive(Default)]
+pub struct TerminalPrompter {
+    /// One question at a time when tools run concurrently
+    lock: tokio::sync::Mutex<()>,
+}
+
+impl TerminalPrompter {
+    /// A prompter if stdin and stderr are terminals, i.e. a human is
+    /// attached
+    pub fn attached() -> Option<Self> {
+        (std::io::stdin().is_terminal() && std::io::stderr().is_terminal()).then(Self::default)
+    }
+}
+
+#[async_trait]
+impl PermissionPrompter for TerminalPrompter {
+    async fn confirm(&self, tool: &str, summary: &str) -> bool {
+        let _turn = self.lock.lock().await;
+        let question = format!("Allow tool {} with {}? [y/N] ", tool, summary);
+        tokio::task::spawn_blocking(move || {
+            eprint!("{}", question);
+            let _ = std::io::stderr().flush();
+            let mut answer = String::new();
+            if std::io::stdin().lock().read_line(&mut answer).is_err() {
+                return false;
+            }
+            matches!(answer.trim().to_lowercase().as_str(), "y" | "yes")
+        })
+        .await
+        .unwrap_or(false)
+    }
+}
+
+struct Rule {
+    tool: Pattern,
+    action: PermissionAction,
+    paths: Vec<Pattern>,
+    commands: Vec<String>,
+}
+
+impl Rule {
+    fn matches(&self, tool: &str, paths: &[String], command: Option<&str>) -> bool {
+        let options = MatchOptions {
+            require_literal_separator: true,
+            ..MatchOptions::default()
+        };
+        self.tool.matches(tool)
+            && (self.paths.is_empty()
+                || paths
+                    .iter()
+                    .any(|p| self.paths.iter().any(|g| g.matches_with(p, options))))
+            && (self.commands.is_empty()
+                || command.is_some_and(|c| {
+                    self.commands
+                        .iter()
+                        .any(|prefix| c.trim_start().starts_with(prefix.as_str()))
+                }))
+    }
+}
+
+/// Decides whether tool calls may run
+pub struct PermissionPolicy {
+    default: PermissionAction,
+    rules: Vec<Rule>,
+    prompter: Option<Arc<dyn PermissionPrompter>>,
+}
+
+impl Default for PermissionPolicy {
+    fn default() -> Self {
+        Self {
+            default: PermissionAction::Allow,
+            rules: Vec::new(),
+            prompter: None,
+        }
+    }
+}
+
+impl PermissionPolicy {
+    /// Policy for `config`, with nobody to ask
+    pub fn from_config(config: &ToolPermissionsConfig) -> Result<Self> {
+        let rules = config
+            .rules
+            .iter()
+            .map(|rule| {
+                let pattern = |glob: &str| {
+                    Pattern::new(glob)
+                        .with_context(|| format!("Invalid permission glob '{}'", glob))
+                };
+                Ok(Rule {
+                    tool: pattern(&rule.tool)?,
+                    action: rule.action,
+                    paths: rule
+                        .paths
+                        .iter()
+                        .map(|p| pattern(p.trim_start_matches("./")))
+                        .collect::<Result<_>>()?,
+                    commands: rule.commands.clone(),
+                })
+            })
+            .collect::<Result<_>>()?;
+        Ok(Self {
+            default: config.default,
+            rules,
+            prompter: None,
+        })
+    }
+
+    /// Ask `prompter` about calls the policy wants approved
+    pub fn with_prompter(mut self, prompter: Arc<dyn PermissionPrompter>) -> Self {
+        self.prompter = Some(prompter);
+        self
+    }
+
+    /// What the rules say about calling `tool` with `args`, and whether a
+    /// rule decided it
+    pub fn decide(&self, tool: &str, args: &ToolArgs) -> (PermissionAction, bool) {
+        let paths = touched_paths(args);
+        let command = str_arg(args, "command");
+        let matching: Vec<PermissionAction> = self
+            .rules
+            .iter()
+            .filter(|rule| rule.matches(tool, &paths, command.as_deref()))
+            .map(|rule| rule.action)
+            .collect();
+        [
+            PermissionAction::Deny,
+            PermissionAction::Ask,
+            PermissionAction::Allow,
+        ]
+        .into_iter()
+        .find(|action| matching.contains(action))
+        .map_or((self.default, false), |action| (action, true))
+    }
+
+    /// `Ok` if calling `tool` with `args` may go ahead, otherwise why not
+    pub async fn authorize(&self, tool: &str, args: &ToolArgs) -> Result<(), String> {
+        let (action, by_rule) = self.decide(tool, args);
+        let verdict = match action {
+            PermissionAction::Allow => Ok(()),
+            PermissionAction::Deny => Err(format!(
+                "Permission denied: the tool permission policy does not allow this {} call",
+                tool
+            )),
+            PermissionAction::Ask => match &self.prompter {
+                Some(prompter) if prompter.confirm(tool, &summary(args)).await => Ok(()),
+                Some(_) => Err(format!(
+                    "Permission denied: the user refused this {} call",
+                    tool
+                )),
+                None => Err(format!(
+                    "Permission denied: this {} call needs approval and no one is attached to approve it",
+                    tool
+                )),
+            },
+        };
+        if by_rule || action != PermissionAction::Allow {
+            events::emit(RunEvent::ToolPermission {
+                tool: tool.to_string(),
+                allowed: verdict.is_ok(),
+                asked: action == PermissionAction::Ask,
+            });
+        }
+        verdict
+    }
+}
+
+fn summary(args: &ToolArgs) -> String {
+    let text = serde_json::Value::Object(args.clone()).to_string();
+    match text.char_indices().nth(MAX_SUMMARY_CHARS) {
+        Some((end, _)) => format!("{}...", &text[..end]),
+        None => text,
+    }
+}
+
+/// The permission policy of the current services (everything allowed
+/// unless one is configured)
+pub fn global() -> Arc<PermissionPolicy> {
+    crate::core::services::Services::current()
+        .permissions
+        .clone()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::config::ToolPermissionRule;
+    use serde_json::json;
+
+    fn args(value: serde_json::Value) -> ToolArgs {
+        value.as_object().cloned().unwrap()
+    }
+
+    fn rule(
+        tool: &str,
+        action: PermissionAction,
+        paths: &[&str],
+        commands: &[&str],
+    ) -> ToolPermissionRule {
+        ToolPermissionRule {
+            tool: tool.to_string(),
+            action,
+            paths: paths.iter().map(|p| p.to_string()).collect(),
+            commands: commands.iter().map(|c| c.to_string()).collect(),
+        }
+    }
+
+    struct Answer(bool);
+
+    #[async_trait]
+    impl PermissionPrompter for Answer {
+        async fn confirm(&self, _tool: &str, _summary: &str) -> bool {
+            self.0
+        }
+    }
+
+    #[test]
+    fn test_deny_beats_ask_beats_allow() {
+        let policy = PermissionPolicy::from_config(&ToolPermissionsConfig {
+            default: PermissionAction::Ask,
+            rules: vec![
+                rule("Read", PermissionAction::Allow, &[], &[]),
+                rule("*", PermissionAction::Deny, &[".git/**", "secrets/*"], &[]),
+                rule("Bash", PermissionAction::Allow, &[], &["cargo "]),
+                rule("Bash", PermissionAction::Deny, &[], &["cargo publish"]),
+                rule("Edit", PermissionAction::Allow, &["src/**"], &[]),
+                rule("Edit", PermissionAction::Ask, &["src/main.rs"], &[]),
+            ],
+        })
+        .unwrap();
+        let decide = |tool: &str, value| policy.decide(tool, &args(value)).0;
+
+        assert_eq!(
+            decide("Read", json!({"file_path": "src/lib.rs"})),
+            PermissionAction::Allow
+        );
+        assert_eq!(
+            decide("Read", json!({"file_path": "./secrets/key"})),
+            PermissionAction::Deny
+        );
+        assert_eq!(
+            decide("Read", json!({"file_path": "secrets/a/key"})),
+            PermissionAction::Allow
+        );
+        assert_eq!(
+            decide("Bash", json!({"command": "cargo test"})),
+            PermissionAction::Allow
+        );
+        assert_eq!(
+            decide("Bash", json!({"command": " cargo publish"})),
+            PermissionAction::Deny
+        );
+        assert_eq!(
+            decide("Bash", json!({"command": "make"})),
+            PermissionAction::Ask
+        );
+        assert_eq!(
+            decide("Edit", json!({"file_path": "src/a/b.rs"})),
+            PermissionAction::Allow
+        );
+        assert_eq!(
+            decide("Edit", json!({"file_path": "src/main.rs"})),
+            PermissionAction::Ask
+        );
+        let patch = "--- a/.git/config\n+++ b/.git/config\n@@ -1 +1 @@\n-a\n+b\n";
+        assert_eq!(
+            decide("ApplyPatch", json!({"patch": patch})),
+            PermissionAction::Deny
+        );
+    }
+
+    #[tokio::test]
+    async fn test_ask_needs_an_attached_human() {
+        let config = ToolPermissionsConfig {
+            default: PermissionAction::Allow,
+            rules: vec![rule("Delete", PermissionAction::Ask, &[], &[])],
+        };
+        let call = args(json!({"path": "old.rs"}));
+
+        let unattended = PermissionPolicy::from_config(&config).unwrap();
+        let err = unattended.authorize("Delete", &call).await.unwrap_err();
+        assert!(err.contains("no one is attached"));
+
+        let approved = PermissionPolicy::from_config(&config)
+            .unwrap()
+            .with_prompter(Arc::new(Answer(true)));
+        assert!(approved.authorize("Delete", &call).await.is_ok());
+        assert!(approved.authorize("Read", &call).await.is_ok());
+
+        let refused = PermissionPolicy::from_config(&config)
+            .unwrap()
+            .with_prompter(Arc::new(Answer(false)));
+        let err = refused.authorize("Delete", &call).await.unwrap_err();
+        assert!(err.contains("the user refused"));
+    }
+
+    #[test]
+    fn test_invalid_glob_is_rejected() {
+        let config = ToolPermissionsConfig {
+            default: PermissionAction::Allow,
+            rules: vec![rule("Edit", PermissionAction::Deny, &["src/[a"], &[])],
+        };
+        assert!(PermissionPolicy::from_config(&config).is_err());
+    }
+}
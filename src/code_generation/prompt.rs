@@ -1,640 +1,493 @@
-use crate::code_generation::generator::CodeContext;
-use std::collections::HashMap;
+//! Prompt templates.
+//!
+//! Every prompt the agent sends is a handlebars template. The built-in
+//! templates live in `prompts/` and are compiled in; a `<name>.hbs` file in
+//! the configured `prompts.dir` replaces the built-in template of that name,
+//! and `prompts.models` maps a model to templates used only for it, so
+//! prompts can be tuned without recompiling.
+
+use anyhow::{bail, Context, Result};
+use handlebars::Handlebars;
+use log::{info, warn};
+use serde::Serialize;
+use serde_json::json;
+use std::path::Path;
+use std::sync::Arc;
 
-/// A prompt template manager for generating effective prompts
-pub struct PromptManager {
-    /// Template cache
-    templates: HashMap<String, String>,
+use crate::code_generation::generator::CodeContext;
+use crate::code_generation::language::Language;
+use crate::core::config::{ModelConfig, PromptsConfig};
+
+/// The built-in templates by name
+const BUILTIN: &[(&str, &str)] = &[
+    ("system", include_str!("../../prompts/system.hbs")),
+    ("code_system", include_str!("../../prompts/code_system.hbs")),
+    ("tool_system", include_str!("../../prompts/tool_system.hbs")),
+    ("improvement", include_str!("../../prompts/improvement.hbs")),
+    ("bugfix", include_str!("../../prompts/bugfix.hbs")),
+    ("feature", include_str!("../../prompts/feature.hbs")),
+    ("refactor", include_str!("../../prompts/refactor.hbs")),
+    (
+        "git_operations",
+        include_str!("../../prompts/git_operations.hbs"),
+    ),
+    (
+        "commit_message",
+        include_str!("../../prompts/commit_message.hbs"),
+    ),
+    ("spec", include_str!("../../prompts/spec.hbs")),
+    ("tests", include_str!("../../prompts/tests.hbs")),
+    (
+        "property_tests",
+        include_str!("../../prompts/property_tests.hbs"),
+    ),
+    (
+        "fuzz_targets",
+        include_str!("../../prompts/fuzz_targets.hbs"),
+    ),
+    ("review", include_str!("../../prompts/review.hbs")),
+    ("critique", include_str!("../../prompts/critique.hbs")),
+    ("rating", include_str!("../../prompts/rating.hbs")),
+    ("research", include_str!("../../prompts/research.hbs")),
+    ("proposal", include_str!("../../prompts/proposal.hbs")),
+    (
+        "proposal_review",
+        include_str!("../../prompts/proposal_review.hbs"),
+    ),
+];
+
+/// Registry key of the template `name` used for `model`
+fn model_key(name: &str, model: &str) -> String {
+    format!("{}@{}", name, model)
 }
 
-impl Default for PromptManager {
-    fn default() -> Self {
-        Self::new()
-    }
+fn new_registry() -> Handlebars<'static> {
+    let mut registry = Handlebars::new();
+    // Prompts are plain text, not HTML
+    registry.register_escape_fn(handlebars::no_escape);
+    registry
 }
 
-impl PromptManager {
-    /// Create a new prompt manager with default templates
-    pub fn new() -> Self {
-        let mut templates = HashMap::new();
-
-        // System message for Rust code generation
-        templates.insert(
-            "system_message".to_string(),
-            r#"You are an expert Rust developer specializing in high-performance, memory-safe, and reliable code.
-Your code follows these principles:
-1. Memory safety - You leverage Rust's ownership system correctly, avoiding unsafe blocks unless absolutely necessary.
-2. Error handling - You use Result and Option types properly, with appropriate error propagation and handling.
-3. Performance - You understand zero-cost abstractions and write efficient code without unnecessary allocations.
-4. Readability - Your code is idiomatic Rust with clear naming conventions and appropriate documentation.
-5. Testability - You write code that is easy to test and include test examples where appropriate.
-
-When improving code, ensure that:
-- You maintain or improve thread safety where applicable
-- You use Result instead of panicking for recoverable errors
-- You leverage the type system to prevent errors at compile time
-- You follow the Rust API guidelines for public interfaces
-- You use appropriate lifetime annotations where needed
-- You handle all error cases explicitly
-
-Whenever possible, use Rust's standard library and well-established crates rather than reinventing functionality.
-"#.to_string(),
-        );
-
-        // Enhanced improvement prompt
-        templates.insert(
-            "improvement".to_string(),
-            r#"
-## TASK DESCRIPTION:
-{{task}}
-
-{{#if requirements}}
-## REQUIREMENTS:
-{{requirements}}
-{{/if}}
-
-## FILES TO MODIFY:
-{{file_paths}}
-
-## CURRENT CODE:
-{{current_code}}
-
-{{#if previous_attempts}}
-## PREVIOUS ATTEMPTS THAT FAILED:
-{{#each previous_attempts}}
-### ATTEMPT:
-```rust
-{{this.code}}
-```
-
-### FAILURE REASON:
-{{this.failure_reason}}
-{{/each}}
-{{/if}}
-
-## INSTRUCTIONS:
-1. Analyze the current code and understand its purpose
-2. Identify ways to improve the code based on the task description
-3. Create a modified version that addresses the requested improvements
-4. Provide a clear explanation of what you changed and why
-5. Ensure your solution follows Rust best practices
-
-## RUST BEST PRACTICES TO APPLY:
-- Leverage Rust's ownership model for memory safety
-- Use Result<T, E> for recoverable errors, not unwrap() or expect() in production code
-- Implement appropriate traits (Debug, Clone, etc.) when needed
-- Use iterators and functional programming patterns when appropriate
-- Structure code in modules for proper organization
-- Use meaningful variable and function names that follow Rust conventions
-- Add appropriate documentation comments (///) for public APIs
-- Add unit tests for new functionality
-- Consider performance implications, especially for hot paths
-- Use appropriate lifetime annotations where needed
-- Ensure thread safety with proper use of Arc, Mutex, etc. where appropriate
-
-## EXPECTED OUTPUT FORMAT:
-For each file you modify, include the complete modified file in this format:
-
-```rust
-// File: path/to/file.rs
-// Modified file content here
-```
-
-## EXPLANATION:
-After the code blocks, provide a detailed explanation of:
-1. What you changed
-2. Why you made these changes
-3. How your changes improve the code
-4. Any trade-offs or considerations for your implementation
-
-Be specific about memory safety, error handling, and performance implications.
-"#
-            .to_string(),
-        );
-
-        // Enhanced bug fix prompt
-        templates.insert(
-            "bugfix".to_string(),
-            r#"
-## BUG DESCRIPTION:
-{{task}}
-
-{{#if requirements}}
-## REQUIREMENTS:
-{{requirements}}
-{{/if}}
-
-## FILES TO MODIFY:
-{{file_paths}}
-
-## CURRENT CODE:
-{{current_code}}
-
-{{#if previous_attempts}}
-## PREVIOUS ATTEMPTS THAT FAILED:
-{{#each previous_attempts}}
-### ATTEMPT:
-```rust
-{{this.code}}
-```
-
-### FAILURE REASON:
-{{this.failure_reason}}
-{{/each}}
-{{/if}}
-
-## INSTRUCTIONS:
-1. Analyze the current code and identify the bug
-2. Fix the bug while minimizing changes to the code
-3. Provide a clear explanation of what was wrong and how you fixed it
-4. Ensure your solution follows Rust best practices
-
-## COMMON RUST BUGS TO CHECK FOR:
-- Ownership/borrowing issues (e.g., use of moved values, reference lifetimes)
-- Concurrency bugs (e.g., data races, deadlocks)
-- Improper error handling (e.g., swallowed errors, unwrapped Results/Options that can fail)
-- Type conversion issues (e.g., as casts that might panic)
-- Logic errors in dealing with Option/Result types
-- Resource leaks (e.g., unclosed files, connections)
-- Integer overflow/underflow
-- Off-by-one errors in ranges or indexing
-- Missing error propagation with `?` operator
-- Improper use of unsafe code
-- Infinite loops or recursion
-
-## EXPECTED OUTPUT FORMAT:
-For each file you modify, include the complete modified file in this format:
-
-```rust
-// File: path/to/file.rs
-// Modified file content here
-```
-
-## EXPLANATION:
-After the code blocks, provide a detailed explanation of:
-1. What the bug was
-2. Root cause analysis
-3. How your changes fix the issue
-4. How to prevent similar bugs in the future
-"#
-            .to_string(),
-        );
-
-        // New feature implementation prompt
-        templates.insert(
-            "feature".to_string(),
-            r#"
-## FEATURE DESCRIPTION:
-{{task}}
-
-{{#if requirements}}
-## REQUIREMENTS:
-{{requirements}}
-{{/if}}
-
-## FILES TO MODIFY:
-{{file_paths}}
-
-## CURRENT CODE:
-{{current_code}}
-
-{{#if previous_attempts}}
-## PREVIOUS ATTEMPTS THAT FAILED:
-{{#each previous_attempts}}
-### ATTEMPT:
-```rust
-{{this.code}}
-```
-
-### FAILURE REASON:
-{{this.failure_reason}}
-{{/each}}
-{{/if}}
-
-## INSTRUCTIONS:
-1. Analyze the current codebase to understand the architecture
-2. Design and implement the new feature according to the description
-3. Ensure the implementation follows Rust best practices
-4. Maintain compatibility with the existing codebase
-5. Add appropriate error handling, documentation, and tests
-
-## IMPLEMENTATION GUIDELINES:
-- Follow existing patterns and coding style for consistency
-- Use traits for abstraction when appropriate
-- Implement proper error handling with custom error types if needed
-- Ensure thread safety if the feature might be used in concurrent contexts
-- Add appropriate logging at key points
-- Keep functions focused and modular
-- Consider performance implications, especially for operations that might scale
-- Add unit tests that cover happy path and error cases
-
-## EXPECTED OUTPUT FORMAT:
-For each file you modify, include the complete modified file in this format:
-
-```rust
-// File: path/to/file.rs
-// Modified file content here
-```
-
-For new files, include the complete file content in this format:
-
-```rust
-// File: path/to/new_file.rs
-// New file content here
-```
-
-## EXPLANATION:
-After the code blocks, provide a detailed explanation of:
-1. Your implementation approach
-2. Key design decisions and alternatives considered
-3. How your implementation satisfies the requirements
-4. Any areas that might need further refinement
-"#
-            .to_string(),
-        );
-
-        // Refactoring prompt
-        templates.insert(
-            "refactor".to_string(),
-            r#"
-## REFACTORING TASK:
-{{task}}
-
-{{#if requirements}}
-## REQUIREMENTS:
-{{requirements}}
-{{/if}}
-
-## FILES TO MODIFY:
-{{file_paths}}
-
-## CURRENT CODE:
-{{current_code}}
-
-{{#if previous_attempts}}
-## PREVIOUS ATTEMPTS THAT FAILED:
-{{#each previous_attempts}}
-### ATTEMPT:
-```rust
-{{this.code}}
-```
-
-### FAILURE REASON:
-{{this.failure_reason}}
-{{/each}}
-{{/if}}
-
-## INSTRUCTIONS:
-1. Analyze the current code to understand its functionality
-2. Refactor the code while preserving its behavior
-3. Apply Rust best practices and improve code quality
-4. Ensure the refactored code is more maintainable, efficient, or readable
-
-## REFACTORING PRINCIPLES:
-- Extract reusable logic into functions or traits
-- Remove code duplication
-- Improve variable and function naming
-- Use appropriate Rust patterns (builder, visitor, etc.) when applicable
-- Replace imperative code with functional/iterator patterns where appropriate
-- Simplify complex logic
-- Improve error handling
-- Enhance documentation
-- Consider adding unit tests to verify behavior preservation
-
-## EXPECTED OUTPUT FORMAT:
-For each file you modify, include the complete modified file in this format:
-
-```rust
-// File: path/to/file.rs
-// Modified file content here
-```
-
-## EXPLANATION:
-After the code blocks, provide a detailed explanation of:
-1. What you refactored and why
-2. How your changes improve the code
-3. What code quality aspects have been enhanced
-4. Any performance or safety improvements
-"#
-            .to_string(),
-        );
-
-        // Add template for Git operations
-        templates.insert(
-            "git_operations".to_string(),
-            r#"
-## GIT OPERATIONS GUIDE
-
-You have access to a GitCommandTool that allows you to execute Git commands directly. This tool provides you with flexibility to handle complex Git scenarios that may be difficult to express programmatically.
-
-### AVAILABLE GIT COMMANDS:
-
-You can execute standard Git commands such as:
-- git status
-- git add <files>
-- git commit -m "message"
-- git branch <branch-name>
-- git checkout <branch-name>
-- git merge <branch-name>
-- git log
-- git diff
-- git pull
-- git push (if configured)
-
-### HOW TO CALL THE TOOL:
-
-To execute a Git command, use this exact JSON format:
-```
-{"tool": "git_command", "args": ["your_git_command_here"]}
-```
-
-For example:
-```
-{"tool": "git_command", "args": ["status"]}
-```
-
-Or:
-```
-{"tool": "git_command", "args": ["log", "-n", "5"]}
-```
-
-### SAFETY CONSTRAINTS:
-
-For safety reasons, certain destructive Git commands are restricted:
-- Commands involving `--force` or `-f` flags
-- `git clean` commands
-- Hard resets (`git reset --hard`)
-- Any command with shell escape characters or pipes
-
-### BEST PRACTICES:
-
-1. **Check State First**: Always check the repository state before making changes (use `git status`)
-2. **Handle Errors**: Check command output for errors and handle them appropriately
-3. **Atomic Operations**: Keep Git operations small and focused
-4. **Clear Commit Messages**: Use descriptive commit messages that explain the "why" not just the "what"
-5. **Branch Management**: Create feature branches for new work
-6. **Conflict Resolution**: When conflicts occur, analyze the conflict and resolve appropriately
-
-### EXAMPLE WORKFLOW:
-
-1. Check current status: `{"tool": "git_command", "args": ["status"]}`
-2. Create a new branch: `{"tool": "git_command", "args": ["branch", "feature-x"]}`
-3. Switch to the branch: `{"tool": "git_command", "args": ["checkout", "feature-x"]}`
-4. Make code changes (using other tools)
-5. Check changes: `{"tool": "git_command", "args": ["status"]}`
-6. Stage changes: `{"tool": "git_command", "args": ["add", "src/modified_file.rs"]}`
-7. Commit changes: `{"tool": "git_command", "args": ["commit", "-m", "Implement feature X"]}`
-8. Check log: `{"tool": "git_command", "args": ["log", "-n", "1"]}`
-9. Switch back to main: `{"tool": "git_command", "args": ["checkout", "main"]}`
-10. Merge changes: `{"tool": "git_command", "args": ["merge", "feature-x"]}`
-
-### HANDLING MERGE CONFLICTS:
-
-If a merge conflict occurs:
-1. Identify conflicted files from command output
-2. Use ReadTool to read the conflicted files
-3. Analyze the conflicts (marked with <<<<<<< HEAD, =======, and >>>>>>> branch)
-4. Use EditTool to resolve conflicts
-5. Stage resolved files: `{"tool": "git_command", "args": ["add", "<resolved-files>"]}`
-6. Complete the merge: `{"tool": "git_command", "args": ["commit", "-m", "Resolve merge conflicts"]}`
-
-Remember to approach Git operations with care and maintain the integrity of the repository.
-"#.to_string(),
-        );
+/// The prompt templates in use
+pub struct PromptTemplates {
+    registry: Handlebars<'static>,
+}
 
-        Self { templates }
+impl Default for PromptTemplates {
+    fn default() -> Self {
+        Self::builtin()
     }
+}
 
-    /// Get the system message template
-    pub fn create_system_message(&self) -> String {
-        self.templates
-            .get("system_message")
-            .expect("System message template not found")
-            .clone()
+impl PromptTemplates {
+    /// The templates compiled into the binary
+    pub fn builtin() -> Self {
+        let mut registry = new_registry();
+        for (name, template) in BUILTIN {
+            registry
+                .register_template_string(name, template)
+                .expect("built-in prompt templates are valid");
+        }
+        Self { registry }
     }
 
-    /// Create a prompt for code improvement
-    pub fn create_improvement_prompt(&self, context: &CodeContext, current_code: &str) -> String {
-        let template = self.templates.get("improvement").unwrap();
-        let system_message = self.templates.get("system_message").unwrap();
-
-        // Combine system message and improvement template
-        let full_template = format!("{}\n\n{}", system_message, template);
-
-        // Simple template substitution for now, would use a proper templating engine in a real implementation
-        let mut prompt = full_template.replace("{{task}}", &context.task);
-
-        if let Some(requirements) = &context.requirements {
-            prompt = prompt.replace("{{#if requirements}}", "");
-            prompt = prompt.replace("{{requirements}}", requirements);
-            prompt = prompt.replace("{{/if}}", "");
-        } else {
-            prompt = prompt.replace(
-                "{{#if requirements}}\n## REQUIREMENTS:\n{{requirements}}\n{{/if}}",
-                "",
-            );
+    /// The built-in templates with the overrides of `config`; `models`
+    /// lets per-model overrides name a model entry instead of a model id
+    pub fn load(config: &PromptsConfig, models: &[ModelConfig]) -> Result<Self> {
+        let mut templates = Self::builtin();
+        let dir = Path::new(&config.dir);
+
+        if dir.is_dir() {
+            for (name, _) in BUILTIN {
+                let path = dir.join(format!("{}.hbs", name));
+                if path.is_file() {
+                    templates.register_file(name, &path)?;
+                }
+            }
         }
 
-        let file_paths = context.file_paths.join("\n");
-        prompt = prompt.replace("{{file_paths}}", &file_paths);
-
-        prompt = prompt.replace("{{current_code}}", current_code);
-
-        if !context.previous_attempts.is_empty() {
-            prompt = prompt.replace("{{#if previous_attempts}}", "");
-            prompt = prompt.replace("{{/if}}", "");
-
-            let mut attempts_text = String::new();
-
-            for attempt in &context.previous_attempts {
-                let attempt_template = "### ATTEMPT:\n```rust\n{{code}}\n```\n\n### FAILURE REASON:\n{{failure_reason}}";
-                let attempt_text = attempt_template
-                    .replace("{{code}}", &attempt.code)
-                    .replace("{{failure_reason}}", &attempt.failure_reason);
-
-                attempts_text.push_str(&attempt_text);
-                attempts_text.push_str("\n\n");
+        for (model, overrides) in &config.models {
+            let model = models
+                .iter()
+                .find(|m| &m.name == model)
+                .map_or(model.as_str(), |m| m.model.as_str());
+            for (name, file) in overrides {
+                if !BUILTIN.iter().any(|(builtin, _)| builtin == name) {
+                    bail!(
+                        "Unknown prompt template '{}' for model '{}'. Known templates: {}",
+                        name,
+                        model,
+                        BUILTIN
+                            .iter()
+                            .map(|(name, _)| *name)
+                            .collect::<Vec<_>>()
+                            .join(", ")
+                    );
+                }
+                templates.register_file(&model_key(name, model), &dir.join(file))?;
             }
-
-            prompt = prompt.replace("{{#each previous_attempts}}\n### ATTEMPT:\n```rust\n{{this.code}}\n```\n\n### FAILURE REASON:\n{{this.failure_reason}}\n{{/each}}", &attempts_text);
-        } else {
-            prompt = prompt.replace("{{#if previous_attempts}}\n## PREVIOUS ATTEMPTS THAT FAILED:\n{{#each previous_attempts}}\n### ATTEMPT:\n```rust\n{{this.code}}\n```\n\n### FAILURE REASON:\n{{this.failure_reason}}\n{{/each}}\n{{/if}}", "");
         }
-
-        prompt
+        Ok(templates)
     }
 
-    /// Create a prompt for bug fixing
-    pub fn create_bugfix_prompt(&self, context: &CodeContext, current_code: &str) -> String {
-        let template = self.templates.get("bugfix").unwrap();
-        let system_message = self.templates.get("system_message").unwrap();
-
-        // Combine system message and bugfix template
-        let full_template = format!("{}\n\n{}", system_message, template);
-
-        // Simple template substitution similar to the improvement prompt
-        let mut prompt = full_template.replace("{{task}}", &context.task);
-
-        // Same substitution pattern as in create_improvement_prompt
-        if let Some(requirements) = &context.requirements {
-            prompt = prompt.replace("{{#if requirements}}", "");
-            prompt = prompt.replace("{{requirements}}", requirements);
-            prompt = prompt.replace("{{/if}}", "");
-        } else {
-            prompt = prompt.replace(
-                "{{#if requirements}}\n## REQUIREMENTS:\n{{requirements}}\n{{/if}}",
-                "",
-            );
-        }
-
-        let file_paths = context.file_paths.join("\n");
-        prompt = prompt.replace("{{file_paths}}", &file_paths);
-
-        prompt = prompt.replace("{{current_code}}", current_code);
-
-        if !context.previous_attempts.is_empty() {
-            prompt = prompt.replace("{{#if previous_attempts}}", "");
-            prompt = prompt.replace("{{/if}}", "");
+    fn register_file(&mut self, key: &str, path: &Path) -> Result<()> {
+        let template = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read prompt template {:?}", path))?;
+        self.registry
+            .register_template_string(key, template)
+            .with_context(|| format!("Invalid prompt template {:?}", path))?;
+        info!("Using prompt template {:?} for {}", path, key);
+        Ok(())
+    }
 
-            let mut attempts_text = String::new();
+    /// Render the template `name`, the one for `model` when there is one
+    pub fn render(&self, name: &str, model: Option<&str>, data: &impl Serialize) -> Result<String> {
+        let key = model
+            .map(|model| model_key(name, model))
+            .filter(|key| self.registry.has_template(key))
+            .unwrap_or_else(|| name.to_string());
+        self.registry
+            .render(&key, data)
+            .with_context(|| format!("Failed to render prompt template '{}'", key))
+    }
+}
 
-            for attempt in &context.previous_attempts {
-                let attempt_template = "### ATTEMPT:\n```rust\n{{code}}\n```\n\n### FAILURE REASON:\n{{failure_reason}}";
-                let attempt_text = attempt_template
-                    .replace("{{code}}", &attempt.code)
-                    .replace("{{failure_reason}}", &attempt.failure_reason);
+/// The prompt templates of the current services, the built-in ones unless
+/// others are configured
+pub fn global() -> Arc<PromptTemplates> {
+    crate::core::services::Services::current().prompts.clone()
+}
 
-                attempts_text.push_str(&attempt_text);
-                attempts_text.push_str("\n\n");
-            }
+/// Render the template `name` with the current templates, falling back
+/// to the built-in template when an override fails to render
+pub fn render(name: &str, model: Option<&str>, data: &impl Serialize) -> String {
+    global().render(name, model, data).unwrap_or_else(|e| {
+        warn!("{:#}; using the built-in template", e);
+        PromptTemplates::builtin()
+            .render(name, None, data)
+            .expect("built-in prompt templates render")
+    })
+}
 
-            prompt = prompt.replace("{{#each previous_attempts}}\n### ATTEMPT:\n```rust\n{{this.code}}\n```\n\n### FAILURE REASON:\n{{this.failure_reason}}\n{{/each}}", &attempts_text);
-        } else {
-            prompt = prompt.replace("{{#if previous_attempts}}\n## PREVIOUS ATTEMPTS THAT FAILED:\n{{#each previous_attempts}}\n### ATTEMPT:\n```rust\n{{this.code}}\n```\n\n### FAILURE REASON:\n{{this.failure_reason}}\n{{/each}}\n{{/if}}", "");
-        }
+/// Builds the prompts of the code generator from the templates
+#[derive(Default)]
+pub struct PromptManager {
+    /// Model whose template overrides apply
+    model: Option<String>,
+}
 
-        prompt
+impl PromptManager {
+    /// Create a prompt manager using the default templates
+    pub fn new() -> Self {
+        Self::default()
     }
 
-    /// Create a prompt for new feature implementation
-    pub fn create_feature_prompt(&self, context: &CodeContext, current_code: &str) -> String {
-        let template = self.templates.get("feature").unwrap();
-        let system_message = self.templates.get("system_message").unwrap();
-
-        // Combine system message and feature template
-        let full_template = format!("{}\n\n{}", system_message, template);
-
-        // Template substitution (similar pattern to other methods)
-        let mut prompt = full_template.replace("{{task}}", &context.task);
-
-        if let Some(requirements) = &context.requirements {
-            prompt = prompt.replace("{{#if requirements}}", "");
-            prompt = prompt.replace("{{requirements}}", requirements);
-            prompt = prompt.replace("{{/if}}", "");
-        } else {
-            prompt = prompt.replace(
-                "{{#if requirements}}\n## REQUIREMENTS:\n{{requirements}}\n{{/if}}",
-                "",
-            );
-        }
+    /// Use the templates configured for `model`
+    pub fn with_model(mut self, model: impl Into<String>) -> Self {
+        self.model = Some(model.into());
+        self
+    }
 
-        let file_paths = context.file_paths.join("\n");
-        prompt = prompt.replace("{{file_paths}}", &file_paths);
+    fn render(&self, name: &str, data: &impl Serialize) -> String {
+        render(name, self.model.as_deref(), data)
+    }
 
-        prompt = prompt.replace("{{current_code}}", current_code);
+    /// Get the system message template
+    pub fn create_system_message(&self) -> String {
+        self.create_language_system_message(Language::Rust)
+    }
 
-        if !context.previous_attempts.is_empty() {
-            prompt = prompt.replace("{{#if previous_attempts}}", "");
-            prompt = prompt.replace("{{/if}}", "");
+    /// The system message for code in `language`
+    pub fn create_language_system_message(&self, language: Language) -> String {
+        self.render("code_system", &language_data(language))
+    }
 
-            let mut attempts_text = String::new();
+    /// The system message of tool-driven generation
+    pub fn create_tool_system_message(&self) -> String {
+        self.render("tool_system", &json!({}))
+    }
 
-            for attempt in &context.previous_attempts {
-                let attempt_template = "### ATTEMPT:\n```rust\n{{code}}\n```\n\n### FAILURE REASON:\n{{failure_reason}}";
-                let attempt_text = attempt_template
-                    .replace("{{code}}", &attempt.code)
-                    .replace("{{failure_reason}}", &attempt.failure_reason);
+    /// Render the task template `name` after the system message
+    fn create_task_prompt(&self, name: &str, context: &CodeContext, current_code: &str) -> String {
+        let attempts: Vec<_> = context
+            .previous_attempts
+            .iter()
+            .map(|attempt| {
+                json!({
+                    "code": attempt.code,
+                    "failure_reason": attempt.failure_reason,
+                    "error_messages": attempt.error_messages,
+                    "test_results": attempt.test_results,
+                })
+            })
+            .collect();
+        let language = context_language(context);
+        let mut data = language_data(language);
+        data["task"] = json!(context.task);
+        data["requirements"] = json!(context.requirements);
+        data["file_paths"] = json!(context.file_paths.join("\n"));
+        data["current_code"] = json!(current_code);
+        data["previous_attempts"] = json!(attempts);
+        format!(
+            "{}\n\n{}",
+            self.create_language_system_message(language),
+            self.render(name, &data)
+        )
+    }
 
-                attempts_text.push_str(&attempt_text);
-                attempts_text.push_str("\n\n");
-            }
+    /// Create a prompt for code improvement
+    pub fn create_improvement_prompt(&self, context: &CodeContext, current_code: &str) -> String {
+        self.create_task_prompt("improvement", context, current_code)
+    }
 
-            prompt = prompt.replace("{{#each previous_attempts}}\n### ATTEMPT:\n```rust\n{{this.code}}\n```\n\n### FAILURE REASON:\n{{this.failure_reason}}\n{{/each}}", &attempts_text);
-        } else {
-            prompt = prompt.replace("{{#if previous_attempts}}\n## PREVIOUS ATTEMPTS THAT FAILED:\n{{#each previous_attempts}}\n### ATTEMPT:\n```rust\n{{this.code}}\n```\n\n### FAILURE REASON:\n{{this.failure_reason}}\n{{/each}}\n{{/if}}", "");
-        }
+    /// Create a prompt for bug fixing
+    pub fn create_bugfix_prompt(&self, context: &CodeContext, current_code: &str) -> String {
+        self.create_task_prompt("bugfix", context, current_code)
+    }
 
-        prompt
+    /// Create a prompt for new feature implementation
+    pub fn create_feature_prompt(&self, context: &CodeContext, current_code: &str) -> String {
+        self.create_task_prompt("feature", context, current_code)
     }
 
     /// Create a prompt for code refactoring
     pub fn create_refactor_prompt(&self, context: &CodeContext, current_code: &str) -> String {
-        let template = self.templates.get("refactor").unwrap();
-        let system_message = self.templates.get("system_message").unwrap();
-
-        // Combine system message and refactor template
-        let full_template = format!("{}\n\n{}", system_message, template);
-
-        // Template substitution (similar pattern to other methods)
-        let mut prompt = full_template.replace("{{task}}", &context.task);
-
-        if let Some(requirements) = &context.requirements {
-            prompt = prompt.replace("{{#if requirements}}", "");
-            prompt = prompt.replace("{{requirements}}", requirements);
-            prompt = prompt.replace("{{/if}}", "");
-        } else {
-            prompt = prompt.replace(
-                "{{#if requirements}}\n## REQUIREMENTS:\n{{requirements}}\n{{/if}}",
-                "",
-            );
-        }
-
-        let file_paths = context.file_paths.join("\n");
-        prompt = prompt.replace("{{file_paths}}", &file_paths);
-
-        prompt = prompt.replace("{{current_code}}", current_code);
-
-        if !context.previous_attempts.is_empty() {
-            prompt = prompt.replace("{{#if previous_attempts}}", "");
-            prompt = prompt.replace("{{/if}}", "");
+        self.create_task_prompt("refactor", context, current_code)
+    }
 
-            let mut attempts_text = String::new();
+    /// Create a prompt for Git operations
+    pub fn create_git_operations_prompt(&self) -> String {
+        format!(
+            "{}\n\n{}",
+            self.create_system_message(),
+            self.render("git_operations", &json!({}))
+        )
+    }
 
-            for attempt in &context.previous_attempts {
-                let attempt_template = "### ATTEMPT:\n```rust\n{{code}}\n```\n\n### FAILURE REASON:\n{{failure_reason}}";
-                let attempt_text = attempt_template
-                    .replace("{{code}}", &attempt.code)
-                    .replace("{{failure_reason}}", &attempt.failure_reason);
+    /// Create the request for a commit message describing `files`
+    pub fn create_commit_message_prompt(
+        &self,
+        goal_id: &str,
+        branch: &str,
+        task: &str,
+        files: &[&str],
+        explanation: &str,
+    ) -> String {
+        let data = json!({
+            "goal_id": goal_id,
+            "branch": branch,
+            "task": task,
+            "files": files,
+            "explanation": explanation,
+        });
+        format!(
+            "{}\n\n{}",
+            self.create_system_message(),
+            self.render("commit_message", &data)
+        )
+    }
+}
 
-                attempts_text.push_str(&attempt_text);
-                attempts_text.push_str("\n\n");
-            }
+/// The language of the first file of `context` in a known language, Rust
+/// when there is none
+fn context_language(context: &CodeContext) -> Language {
+    context
+        .file_paths
+        .iter()
+        .find_map(Language::from_path)
+        .unwrap_or(Language::Rust)
+}
 
-            prompt = prompt.replace("{{#each previous_attempts}}\n### ATTEMPT:\n```rust\n{{this.code}}\n```\n\n### FAILURE REASON:\n{{this.failure_reason}}\n{{/each}}", &attempts_text);
-        } else {
-            prompt = prompt.replace("{{#if previous_attempts}}\n## PREVIOUS ATTEMPTS THAT FAILED:\n{{#each previous_attempts}}\n### ATTEMPT:\n```rust\n{{this.code}}\n```\n\n### FAILURE REASON:\n{{this.failure_reason}}\n{{/each}}\n{{/if}}", "");
-        }
+/// Template data describing `language`
+fn language_data(language: Language) -> serde_json::Value {
+    json!({
+        "language": language.name(),
+        "fence": language.fence(),
+        "extension": language.extension(),
+        "comment": language.line_comment(),
+        "rust": language == Language::Rust,
+    })
+}
 
-        prompt
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::code_generation::generator::PreviousAttempt;
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_task_prompts_fill_optional_sections() {
+        let mut context = CodeContext {
+            task: "Fix the overflow".to_string(),
+            file_paths: vec!["src/a.rs".to_string()],
+            requirements: None,
+            previous_attempts: Vec::new(),
+            file_contents: None,
+            test_files: None,
+            test_contents: None,
+            dependencies: None,
+            code_structure: None,
+            max_attempts: None,
+            current_attempt: None,
+            specification: None,
+            generated_tests: None,
+            failing_tests: None,
+        };
+        let prompt = PromptManager::new().create_bugfix_prompt(&context, "fn a() {}");
+        assert!(prompt.starts_with("You are an expert Rust developer"));
+        assert!(prompt.contains("## BUG DESCRIPTION:\nFix the overflow\n"));
+        assert!(prompt.contains("## CURRENT CODE:\nfn a() {}\n"));
+        assert!(!prompt.contains("REQUIREMENTS"));
+        assert!(!prompt.contains("PREVIOUS ATTEMPTS"));
+        assert!(!prompt.contains("{{"));
+
+        context.requirements = Some("Use checked_add".to_string());
+        context.previous_attempts.push(PreviousAttempt {
+            code: "a + b".to_string(),
+            failure_reason: "still overflows".to_string(),
+            timestamp: chrono::Utc::now(),
+            test_results: None,
+            error_messages: None,
+            compiled: None,
+            tests_passed: None,
+            notes: None,
+        });
+        let prompt = PromptManager::new().create_bugfix_prompt(&context, "fn a() {}");
+        assert!(prompt.contains("## REQUIREMENTS:\nUse checked_add\n"));
+        assert!(prompt.contains("```rust\na + b\n```\n\n### FAILURE REASON:\nstill overflows\n"));
+        assert!(!prompt.contains("### ERRORS"));
+        assert!(!prompt.contains("### FAILING TESTS"));
     }
 
-    /// Create a prompt for Git operations
-    pub fn create_git_operations_prompt(&self) -> String {
-        let git_template = self.templates.get("git_operations").unwrap();
-        let system_message = self.templates.get("system_message").unwrap();
-
-        // Combine system message and git operations template
-        format!("{}\n\n{}", system_message, git_template)
+    #[test]
+    fn test_retry_prompts_show_errors_and_failing_tests() {
+        let attempt = PreviousAttempt {
+            code: "a + b".to_string(),
+            failure_reason: "Compilation failed".to_string(),
+            timestamp: chrono::Utc::now(),
+            test_results: Some("tests::adds: assertion failed".to_string()),
+            error_messages: Some(vec![
+                "src/a.rs:1:5: error[E0308]: mismatched types".to_string(),
+                "src/a.rs:2:1: error: unexpected token".to_string(),
+            ]),
+            compiled: Some(false),
+            tests_passed: None,
+            notes: None,
+        };
+        let context = CodeContext {
+            task: "Fix the overflow".to_string(),
+            file_paths: vec!["src/a.rs".to_string()],
+            requirements: None,
+            previous_attempts: vec![attempt],
+            file_contents: None,
+            test_files: None,
+            test_contents: None,
+            dependencies: None,
+            code_structure: None,
+            max_attempts: None,
+            current_attempt: Some(2),
+            specification: None,
+            generated_tests: None,
+            failing_tests: None,
+        };
+        let manager = PromptManager::new();
+        for prompt in [
+            manager.create_improvement_prompt(&context, ""),
+            manager.create_bugfix_prompt(&context, ""),
+            manager.create_feature_prompt(&context, ""),
+            manager.create_refactor_prompt(&context, ""),
+        ] {
+            assert!(prompt.contains(
+                "### FAILURE REASON:\nCompilation failed\n\n\
+                 ### ERRORS:\n\
+                 src/a.rs:1:5: error[E0308]: mismatched types\n\
+                 src/a.rs:2:1: error: unexpected token\n\n\
+                 ### FAILING TESTS:\ntests::adds: assertion failed\n"
+            ));
+        }
     }
 
-    /// Add a custom template
-    pub fn add_template(&mut self, name: &str, template: &str) {
-        self.templates
-            .insert(name.to_string(), template.to_string());
+    #[test]
+    fn test_prompts_follow_the_language_of_the_files() {
+        let context = CodeContext {
+            task: "Handle empty input".to_string(),
+            file_paths: vec!["README.md".to_string(), "app/parse.py".to_string()],
+            requirements: None,
+            previous_attempts: vec![PreviousAttempt {
+                code: "return None".to_string(),
+                failure_reason: "tests failed".to_string(),
+                timestamp: chrono::Utc::now(),
+                test_results: None,
+                error_messages: None,
+                compiled: None,
+                tests_passed: None,
+                notes: None,
+            }],
+            file_contents: None,
+            test_files: None,
+            test_contents: None,
+            dependencies: None,
+            code_structure: None,
+            max_attempts: None,
+            current_attempt: None,
+            specification: None,
+            generated_tests: None,
+            failing_tests: None,
+        };
+        let prompt = PromptManager::new().create_bugfix_prompt(&context, "def parse(s): ...");
+        assert!(prompt.starts_with("You are an expert Python developer"));
+        assert!(prompt.contains("```python\nreturn None\n```"));
+        assert!(prompt.contains("```python\n# File: path/to/file.py (lines 10-14)\n"));
+        assert!(prompt.contains("follows Python best practices"));
+        assert!(!prompt.contains("RUST"));
+        assert!(!prompt.contains("ownership"));
     }
 
-    /// Get a template by name
-    pub fn get_template(&self, name: &str) -> Option<&String> {
-        self.templates.get(name)
+    #[test]
+    fn test_directory_and_model_overrides() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("review.hbs"), "Review {{title}} strictly").unwrap();
+        std::fs::write(dir.path().join("terse.hbs"), "Review {{title}} briefly").unwrap();
+        let mut model = crate::core::config::Config::for_testing().models[0].clone();
+        model.name = "cheap".to_string();
+        model.model = "small-model-1".to_string();
+
+        let config = PromptsConfig {
+            dir: dir.path().to_string_lossy().to_string(),
+            models: HashMap::from([(
+                "cheap".to_string(),
+                HashMap::from([("review".to_string(), "terse.hbs".to_string())]),
+            )]),
+        };
+        let templates = PromptTemplates::load(&config, &[model]).unwrap();
+        let data = json!({ "title": "X" });
+        assert_eq!(
+            templates.render("review", None, &data).unwrap(),
+            "Review X strictly"
+        );
+        assert_eq!(
+            templates.render("review", Some("other"), &data).unwrap(),
+            "Review X strictly"
+        );
+        assert_eq!(
+            templates
+                .render("review", Some("small-model-1"), &data)
+                .unwrap(),
+            "Review X briefly"
+        );
+        // Untouched templates stay built in
+        assert!(templates
+            .render("rating", None, &json!({}))
+            .unwrap()
+            .starts_with("You are a code quality expert"));
+
+        let config = PromptsConfig {
+            models: HashMap::from([(
+                "cheap".to_string(),
+                HashMap::from([("reveiw".to_string(), "terse.hbs".to_string())]),
+            )]),
+            ..config
+        };
+        assert!(PromptTemplates::load(&config, &[]).is_err());
     }
 }
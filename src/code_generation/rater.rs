@@ -1,9 +1,11 @@
 use anyhow::{Context, Result};
 use log::{debug, info};
+use serde_json::json;
 use std::sync::Arc;
 
 use super::candidate::GenerationCandidate;
 use super::llm::LlmProvider;
+use super::prompt;
 
 /// Rates code candidates using an LLM to assess quality
 pub struct CandidateRater {
@@ -123,40 +125,15 @@ impl CandidateRater {
             "NOT_TESTED".to_string()
         };
 
-        format!(
-            r#"You are a code quality expert. Please rate the following code improvement on a scale from 0.0 to 1.0.
-
-Consider these factors:
-- Code correctness and functionality
-- Code quality, readability, and maintainability
-- Adherence to Rust best practices
-- Test results (if available)
-- Whether the code achieves the stated task
-
-TASK:
-{task}
-
-EXPLANATION:
-{explanation}
-
-CODE:
-{code}
-
-TEST STATUS:
-{test_status}
-
-Please provide your rating as a single decimal number between 0.0 and 1.0, followed by a brief explanation.
-Format your response as:
-RATING: <number>
-EXPLANATION: <your explanation>
-
-For example:
-RATING: 0.85
-EXPLANATION: The code is well-structured and passes all tests, but could benefit from additional error handling."#,
-            task = candidate.improvement.task,
-            explanation = candidate.improvement.explanation,
-            code = candidate.improvement.code,
-            test_status = test_status
+        prompt::render(
+            "rating",
+            self.llm_provider.model(),
+            &json!({
+                "task": candidate.improvement.task,
+                "explanation": candidate.improvement.explanation,
+                "code": candidate.improvement.code,
+                "test_status": test_status,
+            }),
         )
     }
 
@@ -0,0 +1,523 @@
+//! Repository map: a compressed outline of the codebase for LLM context.
+//!
+//! Every Rust file is parsed with tree-sitter for its public items: module
+//! declarations, types, traits, functions and methods, each reduced to its
+//! signature and the first line of its doc comment, plus the trait impls
+//! it contains. Files form a graph in which an edge points from a file to
+//! the files defining the identifiers it uses. PageRank over that graph,
+//! personalized towards the files of the goal and the files defining the
+//! identifiers its description mentions, orders the files, and the outline
+//! is cut off once it reaches the token budget.
+
+use anyhow::Result;
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+use tree_sitter::{Node, Parser};
+
+use crate::code_generation::code_query::{rust_files, rust_parser};
+use crate::core::costs::estimate_tokens;
+
+/// Share of PageRank that follows edges rather than jumping back to the
+/// goal's files
+const DAMPING: f64 = 0.85;
+
+/// PageRank iterations; the ranking is stable long before
+const ITERATIONS: usize = 50;
+
+/// Weight of focus files and of identifiers spelled like code in the
+/// personalization, against 1 for plain words of the goal
+const CODE_LIKE_WEIGHT: f64 = 10.0;
+
+/// Longest signature kept, in characters
+const MAX_SIGNATURE_CHARS: usize = 160;
+
+/// One line of the map
+#[derive(Debug, Clone, PartialEq)]
+struct Entry {
+    /// Nesting under an impl or trait
+    indent: usize,
+
+    /// Signature followed by the doc line, if any
+    text: String,
+
+    /// Identifier other files refer to the item by; `None` for impl headers
+    name: Option<String>,
+}
+
+/// The outline of one file and the identifiers it uses
+#[derive(Debug, Clone)]
+struct FileOutline {
+    /// Path relative to the workspace
+    path: String,
+
+    /// Public items in source order
+    entries: Vec<Entry>,
+
+    /// Identifiers used in the file and how often
+    references: HashMap<String, usize>,
+}
+
+/// Outline of a codebase that can be ranked against a goal
+#[derive(Debug, Clone, Default)]
+pub struct RepoMap {
+    files: Vec<FileOutline>,
+}
+
+impl RepoMap {
+    /// Map of the Rust files in `workspace`
+    pub fn build(workspace: &Path) -> Result<Self> {
+        let sources = rust_files(workspace).into_iter().filter_map(|file| {
+            let source = std::fs::read_to_string(&file).ok()?;
+            let path = file.strip_prefix(workspace).unwrap_or(&file);
+            Some((path.to_string_lossy().to_string(), source))
+        });
+        Self::from_sources(sources)
+    }
+
+    /// Map of `(path, source)` pairs
+    pub fn from_sources(sources: impl IntoIterator<Item = (String, String)>) -> Result<Self> {
+        let mut parser = rust_parser()?;
+        let files = sources
+            .into_iter()
+            .filter_map(|(path, source)| outline(&mut parser, path, &source))
+            .collect();
+        Ok(Self { files })
+    }
+
+    /// Number of files in the map
+    pub fn len(&self) -> usize {
+        self.files.len()
+    }
+
+    /// Whether the map has no files
+    pub fn is_empty(&self) -> bool {
+        self.files.is_empty()
+    }
+
+    /// The outline of the files most relevant to `goal` and `focus_files`,
+    /// most relevant first, within about `max_tokens` tokens
+    pub fn render(&self, goal: &str, focus_files: &[String], max_tokens: usize) -> String {
+        let ranks = self.rank(goal, focus_files);
+        let mut order: Vec<usize> = (0..self.files.len()).collect();
+        order.sort_by(|&a, &b| ranks[b].total_cmp(&ranks[a]).then(a.cmp(&b)));
+
+        let mut out = String::new();
+        'files: for index in order {
+            let file = &self.files[index];
+            if file.entries.is_empty() {
+                continue;
+            }
+            let mut section = format!("{}{}:\n", file.path, module_suffix(&file.path));
+            for entry in &file.entries {
+                let line = format!("{}{}\n", "  ".repeat(entry.indent + 1), entry.text);
+                if estimate_tokens(&out) + estimate_tokens(&section) + estimate_tokens(&line)
+                    > max_tokens as u64
+                {
+                    // Keep the part of the file that fits, unless only its
+                    // header would be left
+                    if section.lines().count() > 1 {
+                        out.push_str(&section);
+                    }
+                    break 'files;
+                }
+                section.push_str(&line);
+            }
+            out.push_str(&section);
+        }
+        out
+    }
+
+    /// PageRank of each file, personalized towards `focus_files` and the
+    /// files defining identifiers that `goal` mentions
+    fn rank(&self, goal: &str, focus_files: &[String]) -> Vec<f64> {
+        let n = self.files.len();
+        if n == 0 {
+            return Vec::new();
+        }
+
+        let mut definers: HashMap<&str, Vec<usize>> = HashMap::new();
+        for (index, file) in self.files.iter().enumerate() {
+            let names: HashSet<&str> = file
+                .entries
+                .iter()
+                .filter_map(|entry| entry.name.as_deref())
+                .collect();
+            for name in names {
+                definers.entry(name).or_default().push(index);
+            }
+        }
+
+        // Edges weigh the square root of the number of uses, so one file
+        // calling a helper many times does not dominate, shared between
+        // all files defining the name
+        let mut edges: Vec<HashMap<usize, f64>> = vec![HashMap::new(); n];
+        for (from, file) in self.files.iter().enumerate() {
+            for (name, count) in &file.references {
+                let Some(targets) = definers.get(name.as_str()) else {
+                    continue;
+                };
+                let weight = (*count as f64).sqrt() / targets.len() as f64;
+                for &to in targets.iter().filter(|&&to| to != from) {
+                    *edges[from].entry(to).or_default() += weight;
+                }
+            }
+        }
+
+        let mentioned = mentioned_identifiers(goal);
+        let mut personal = vec![0.0; n];
+        for (index, file) in self.files.iter().enumerate() {
+            if focus_files
+                .iter()
+                .any(|focus| Path::new(&file.path) == Path::new(focus))
+            {
+                personal[index] += CODE_LIKE_WEIGHT;
+            }
+        }
+        for name in &mentioned {
+            // `CodeImprovementStrategy` says more about the goal than `retry`
+            let weight = if looks_like_code(name) {
+                CODE_LIKE_WEIGHT
+            } else {
+                1.0
+            };
+            for &index in definers.get(name.as_str()).into_iter().flatten() {
+                personal[index] += weight;
+            }
+        }
+        let total: f64 = personal.iter().sum();
+        if total == 0.0 {
+            personal = vec![1.0 / n as f64; n];
+        } else {
+            personal.iter_mut().for_each(|p| *p /= total);
+        }
+
+        let out_weights: Vec<f64> = edges.iter().map(|e| e.values().sum()).collect();
+        let mut ranks = personal.clone();
+        for _ in 0..ITERATIONS {
+            // Files without outgoing edges hand their rank back to the goal
+            let dangling: f64 = (0..n)
+                .filter(|&i| out_weights[i] == 0.0)
+                .map(|i| ranks[i])
+                .sum();
+            let mut next: Vec<f64> = personal
+                .iter()
+                .map(|p| (1.0 - DAMPING + DAMPING * dangling) * p)
+                .collect();
+            for (from, targets) in edges.iter().enumerate() {
+                for (&to, &weight) in targets {
+                    next[to] += DAMPING * ranks[from] * weight / out_weights[from];
+                }
+            }
+            ranks = next;
+        }
+        ranks
+    }
+}
+
+/// Identifiers in `text` that could name an item: words of at least three
+/// characters, and the segments of `a::b` paths
+pub(crate) fn mentioned_identifiers(text: &str) -> HashSet<String> {
+    text.split(|c: char| !(c.is_alphanumeric() || c == '_'))
+        .filter(|word| word.len() >= 3 && !word.starts_with(|c: char| c.is_ascii_digit()))
+        .map(str::to_string)
+        .collect()
+}
+
+/// Whether `word` is spelled like an identifier rather than like prose:
+/// snake_case or with capitals after the first letter
+pub(crate) fn looks_like_code(word: &str) -> bool {
+    word.contains('_') || word.chars().skip(1).any(|c| c.is_uppercase())
+}
+
+/// ` (crate::a::b)` for a file under `src/`, empty otherwise
+fn module_suffix(path: &str) -> String {
+    let Some(relative) = path.strip_prefix("src/") else {
+        return String::new();
+    };
+    let mut segments: Vec<&str> = relative.trim_end_matches(".rs").split('/').collect();
+    if matches!(segments.last(), Some(&("mod" | "lib" | "main"))) {
+        segments.pop();
+    }
+    let mut module = String::from("crate");
+    for segment in segments {
+        module.push_str("::");
+        module.push_str(segment);
+    }
+    format!(" ({})", module)
+}
+
+/// Outline of the file at `path` with contents `source`
+fn outline(parser: &mut Parser, path: String, source: &str) -> Option<FileOutline> {
+    let tree = parser.parse(source, None)?;
+    let lines: Vec<&str> = source.lines().collect();
+    let mut file = FileOutline {
+        path,
+        entries: Vec::new(),
+        references: HashMap::new(),
+    };
+    collect_items(
+        tree.root_node(),
+        source,
+        &lines,
+        0,
+        false,
+        &mut file.entries,
+    );
+    collect_references(tree.root_node(), source, &mut file.references);
+    Some(file)
+}
+
+/// Add the public items among the children of `node` to `entries`; with
+/// `all_public` (the body of a trait) every item counts as public
+fn collect_items(
+    node: Node,
+    source: &str,
+    lines: &[&str],
+    indent: usize,
+    all_public: bool,
+    entries: &mut Vec<Entry>,
+) {
+    let mut cursor = node.walk();
+    for item in node.named_children(&mut cursor) {
+        let public = all_public || is_public(item);
+        match item.kind() {
+            "function_item"
+            | "function_signature_item"
+            | "struct_item"
+            | "enum_item"
+            | "type_item"
+            | "const_item"
+            | "static_item"
+            | "union_item"
+                if public =>
+            {
+                entries.push(entry(item, source, lines, indent));
+            }
+            "trait_item" if public => {
+                entries.push(entry(item, source, lines, indent));
+                if let Some(body) = item.child_by_field_name("body") {
+                    collect_items(body, source, lines, indent + 1, true, entries);
+                }
+            }
+            "mod_item" if public => {
+                // Every `use` path names modules, so they would attract
+                // rank without saying anything about the goal
+                entries.push(Entry {
+                    name: None,
+                    ..entry(item, source, lines, indent)
+                });
+                if let Some(body) = item.child_by_field_name("body") {
+                    collect_items(body, source, lines, indent, false, entries);
+                }
+            }
+            "impl_item" => {
+                let header = Entry {
+                    indent,
+                    text: signature(item, source),
+                    name: None,
+                };
+                if item.child_by_field_name("trait").is_some() {
+                    // Trait impls are listed without their methods
+                    entries.push(header);
+                } else if let Some(body) = item.child_by_field_name("body") {
+                    let mut methods = Vec::new();
+                    collect_methods(body, source, lines, indent + 1, &mut methods);
+                    if !methods.is_empty() {
+                        entries.push(header);
+                        entries.append(&mut methods);
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Add the public functions of an inherent impl body to `entries`
+fn collect_methods(
+    body: Node,
+    source: &str,
+    lines: &[&str],
+    indent: usize,
+    entries: &mut Vec<Entry>,
+) {
+    let mut cursor = body.walk();
+    for item in body.named_children(&mut cursor) {
+        if is_public(item) && matches!(item.kind(), "function_item" | "const_item" | "type_item") {
+            entries.push(entry(item, source, lines, indent));
+        }
+    }
+}
+
+/// Whether `item` has a visibility modifier (`pub`, `pub(crate)`, ...)
+fn is_public(item: Node) -> bool {
+    item.named_child(0)
+        .is_some_and(|first| first.kind() == "visibility_modifier")
+}
+
+/// Map entry of `item`
+fn entry(item: Node, source: &str, lines: &[&str], indent: usize) -> Entry {
+    let row = item.start_position().row;
+    let mut text = signature(item, source);
+    if let Some(doc) = doc_first_line(lines, row) {
+        text.push_str(" // ");
+        text.push_str(&doc);
+    }
+    Entry {
+        indent,
+        text,
+        name: item
+            .child_by_field_name("name")
+            .and_then(|name| source.get(name.byte_range()))
+            .map(str::to_string),
+    }
+}
+
+/// `item` up to its body or value, on one line
+fn signature(item: Node, source: &str) -> String {
+    let end = item
+        .child_by_field_name("body")
+        .or_else(|| item.child_by_field_name("value"))
+        .map_or(item.end_byte(), |body| body.start_byte());
+    let text = source.get(item.start_byte()..end).unwrap_or_default();
+    let text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+    let text = text.trim_end_matches([' ', '=', ';', '{']).to_string();
+    match text.char_indices().nth(MAX_SIGNATURE_CHARS) {
+        Some((cut, _)) => format!("{}…", &text[..cut]),
+        None => text,
+    }
+}
+
+/// First line of the `///` comment above line `row` (0-indexed), skipping
+/// attributes in between
+fn doc_first_line(lines: &[&str], row: usize) -> Option<String> {
+    let mut first = None;
+    for line in lines[..row.min(lines.len())].iter().rev() {
+        let line = line.trim();
+        if let Some(doc) = line.strip_prefix("///") {
+            first = Some(doc.trim());
+        } else if !line.starts_with("#[") {
+            break;
+        }
+    }
+    first.filter(|doc| !doc.is_empty()).map(str::to_string)
+}
+
+/// Count the identifiers used under `node`
+fn collect_references(node: Node, source: &str, references: &mut HashMap<String, usize>) {
+    let mut cursor = node.walk();
+    let mut stack = vec![node];
+    while let Some(node) = stack.pop() {
+        if matches!(
+            node.kind(),
+            "identifier" | "type_identifier" | "field_identifier"
+        ) {
+            if let Some(name) = source.get(node.byte_range()) {
+                *references.entry(name.to_string()).or_default() += 1;
+            }
+        }
+        stack.extend(node.named_children(&mut cursor));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn map() -> RepoMap {
+        let planner = r#"
+/// Plans improvements
+///
+/// More detail.
+#[derive(Debug)]
+pub struct Planner {
+    steps: usize,
+}
+
+impl Planner {
+    /// Create a planner
+    pub fn new(steps: usize) -> Self {
+        Self { steps }
+    }
+
+    fn private_helper(&self) {}
+}
+
+impl Strategy for Planner {
+    fn plan(&self) {}
+}
+
+fn hidden() {}
+
+#[cfg(test)]
+mod tests {
+    pub fn not_listed() {}
+}
+"#;
+        let strategy = "pub trait Strategy {\n    /// Make a plan\n    fn plan(&self);\n}\n";
+        let user = "use crate::planner::Planner;\npub fn run() {\n    let p = Planner::new(1);\n    let q = Planner::new(2);\n}\n";
+        let unrelated = "pub const LIMIT: usize = 5;\n";
+        RepoMap::from_sources(vec![
+            ("src/other.rs".to_string(), unrelated.to_string()),
+            ("src/strategy/mod.rs".to_string(), strategy.to_string()),
+            ("src/planner.rs".to_string(), planner.to_string()),
+            ("src/main.rs".to_string(), user.to_string()),
+        ])
+        .unwrap()
+    }
+
+    #[test]
+    fn test_outline_lists_public_items() {
+        let out = map().render("Speed up Planner", &[], 10_000);
+        let planner = out
+            .split("\n\n")
+            .next()
+            .unwrap_or_default()
+            .split("src/planner.rs")
+            .nth(1)
+            .unwrap();
+        assert!(
+            planner.starts_with(
+                " (crate::planner):\n  \
+                 pub struct Planner // Plans improvements\n  \
+                 impl Planner\n    \
+                 pub fn new(steps: usize) -> Self // Create a planner\n  \
+                 impl Strategy for Planner\n"
+            ),
+            "{}",
+            out
+        );
+        assert!(!out.contains("private_helper") && !out.contains("hidden"));
+        assert!(!out.contains("not_listed"));
+        assert!(out.contains("src/strategy/mod.rs (crate::strategy):\n  pub trait Strategy\n    fn plan(&self) // Make a plan\n"), "{}", out);
+    }
+
+    #[test]
+    fn test_files_are_ranked_by_relevance_within_budget() {
+        let map = map();
+        let order = |goal: &str, focus: &[String]| -> Vec<String> {
+            map.render(goal, focus, 10_000)
+                .lines()
+                .filter(|line| !line.starts_with(' '))
+                .map(|line| {
+                    line.split(' ')
+                        .next()
+                        .unwrap()
+                        .trim_end_matches(':')
+                        .to_string()
+                })
+                .collect()
+        };
+        // The goal names the planner; the trait it implements comes next
+        assert_eq!(order("Speed up Planner", &[])[0], "src/planner.rs");
+        assert_eq!(order("Speed up Planner", &[])[1], "src/strategy/mod.rs");
+        // Focus files are ranked first
+        let focus = vec!["src/other.rs".to_string()];
+        assert_eq!(order("Tidy up", &focus)[0], "src/other.rs");
+
+        let short = map.render("Speed up Planner", &[], 30);
+        assert!(estimate_tokens(&short) <= 30, "{}", short);
+        assert!(short.starts_with("src/planner.rs"), "{}", short);
+        assert!(!short.contains("src/other.rs"));
+    }
+}
@@ -0,0 +1,241 @@
+//! Second-model review of generated changes.
+//!
+//! A reviewer model reads the diff of an improvement branch against its
+//! goal and must approve it before the branch is merged. Routed to the
+//! critic role, the same reviewer critiques a generated diff before it is
+//! written, and its comments become the revisions asked of the coder.
+
+use anyhow::{Context, Result};
+use log::{debug, info, warn};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::sync::Arc;
+
+use super::llm::LlmProvider;
+use super::prompt;
+use super::router::{ModelRole, ModelRouter};
+use crate::core::config::Config;
+use crate::core::optimization::OptimizationGoal;
+
+/// Maximum diff size (in bytes) sent to the reviewer
+const MAX_REVIEW_DIFF_BYTES: usize = 60_000;
+
+/// Verdict returned by the reviewer model
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReviewVerdict {
+    /// Whether the reviewer approved the change for merge
+    pub approved: bool,
+
+    /// Review comments (concerns, suggestions)
+    #[serde(default)]
+    pub comments: Vec<String>,
+}
+
+/// Second-model reviewer that critiques a change before it is merged, or,
+/// as a critic, before it is written to the branch
+pub struct ChangeReviewer {
+    /// LLM provider for the reviewer model
+    llm_provider: Arc<dyn LlmProvider>,
+
+    /// Name of the reviewer model (for attribution)
+    model: String,
+
+    /// Temperature for LLM calls (lower = more deterministic)
+    temperature: f32,
+
+    /// Max tokens for review responses
+    max_tokens: usize,
+}
+
+impl ChangeReviewer {
+    /// Create a new change reviewer
+    ///
+    /// # Arguments
+    /// * `llm_provider` - LLM provider for the reviewer model
+    /// * `model` - Name of the reviewer model
+    pub fn new(llm_provider: Arc<dyn LlmProvider>, model: impl Into<String>) -> Self {
+        Self {
+            llm_provider,
+            model: model.into(),
+            temperature: 0.2,
+            max_tokens: 2048,
+        }
+    }
+
+    /// Create the reviewer configured under `routing.reviewer` or
+    /// `review.reviewer_model`, if any
+    pub fn from_config(config: &Config) -> Result<Option<Self>> {
+        Self::from_router(&ModelRouter::new(config.clone()))
+    }
+
+    /// Create a reviewer on the model routed to the reviewer role, if any
+    pub fn from_router(router: &ModelRouter) -> Result<Option<Self>> {
+        Self::for_role(router, ModelRole::Reviewer)
+    }
+
+    /// Create a critic on the model routed to the critic role, if any
+    pub fn critic_from_router(router: &ModelRouter) -> Result<Option<Self>> {
+        Self::for_role(router, ModelRole::Critic)
+    }
+
+    fn for_role(router: &ModelRouter, role: ModelRole) -> Result<Option<Self>> {
+        let Some(model_config) = router.model_for(role) else {
+            return Ok(None);
+        };
+        let llm = router.provider(role)?;
+
+        info!(
+            "Change review enabled with {} model '{}'",
+            role, model_config.name
+        );
+        Ok(Some(Self::new(llm, model_config.model.clone())))
+    }
+
+    /// Name of the reviewer model
+    pub fn model_name(&self) -> &str {
+        &self.model
+    }
+
+    /// Review a diff against the goal it is meant to achieve, with the
+    /// mutants of the change its tests did not catch, if mutation testing ran
+    pub async fn review(
+        &self,
+        goal: &OptimizationGoal,
+        diff: &str,
+        surviving_mutants: &[String],
+    ) -> Result<ReviewVerdict> {
+        let prompt = self.build_review_prompt(goal, diff, surviving_mutants);
+
+        let response = self
+            .llm_provider
+            .generate(&prompt, Some(self.max_tokens), Some(self.temperature))
+            .await
+            .context("Failed to get review from reviewer model")?;
+
+        let verdict = parse_review_verdict(&response);
+        debug!(
+            "Review for goal {}: approved={}, {} comments",
+            goal.id,
+            verdict.approved,
+            verdict.comments.len()
+        );
+
+        Ok(verdict)
+    }
+
+    /// Critique a diff that has not been written yet against the goal and
+    /// its acceptance criteria; a rejection's comments are the revisions
+    /// the critic asks for
+    pub async fn critique(
+        &self,
+        goal: &OptimizationGoal,
+        criteria: &[String],
+        diff: &str,
+    ) -> Result<ReviewVerdict> {
+        let prompt = prompt::render(
+            "critique",
+            Some(&self.model),
+            &json!({
+                "title": goal.title,
+                "description": goal.description,
+                "criteria": criteria,
+                "diff": truncate_diff(diff),
+            }),
+        );
+
+        let response = self
+            .llm_provider
+            .generate(&prompt, Some(self.max_tokens), Some(self.temperature))
+            .await
+            .context("Failed to get critique from critic model")?;
+
+        let verdict = parse_review_verdict(&response);
+        debug!(
+            "Critique for goal {}: approved={}, {} comments",
+            goal.id,
+            verdict.approved,
+            verdict.comments.len()
+        );
+
+        Ok(verdict)
+    }
+
+    /// Build the review prompt for a change
+    fn build_review_prompt(
+        &self,
+        goal: &OptimizationGoal,
+        diff: &str,
+        surviving_mutants: &[String],
+    ) -> String {
+        prompt::render(
+            "review",
+            Some(&self.model),
+            &json!({
+                "title": goal.title,
+                "description": goal.description,
+                "diff": truncate_diff(diff),
+                "surviving_mutants": surviving_mutants,
+            }),
+        )
+    }
+}
+
+/// `diff` cut to the size sent to the reviewer
+fn truncate_diff(diff: &str) -> String {
+    if diff.len() > MAX_REVIEW_DIFF_BYTES {
+        let mut end = MAX_REVIEW_DIFF_BYTES;
+        while !diff.is_char_boundary(end) {
+            end -= 1;
+        }
+        format!("{}\n... (diff truncated)", &diff[..end])
+    } else {
+        diff.to_string()
+    }
+}
+
+/// Parse a reviewer response; anything unparseable is treated as a rejection
+pub fn parse_review_verdict(response: &str) -> ReviewVerdict {
+    let trimmed = response.trim();
+    let json_str = match (trimmed.find('{'), trimmed.rfind('}')) {
+        (Some(start), Some(end)) if end > start => &trimmed[start..=end],
+        _ => trimmed,
+    };
+
+    match serde_json::from_str::<ReviewVerdict>(json_str) {
+        Ok(verdict) => verdict,
+        Err(e) => {
+            warn!(
+                "Could not parse reviewer verdict, treating as rejection: {}",
+                e
+            );
+            ReviewVerdict {
+                approved: false,
+                comments: vec![format!(
+                    "Reviewer response could not be parsed: {}",
+                    trimmed.chars().take(200).collect::<String>()
+                )],
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_review_verdict_json() {
+        let verdict = parse_review_verdict(
+            "```json\n{\"approved\": false, \"comments\": [\"Deletes error handling\"]}\n```",
+        );
+        assert!(!verdict.approved);
+        assert_eq!(verdict.comments, vec!["Deletes error handling"]);
+    }
+
+    #[test]
+    fn test_parse_review_verdict_unparseable_rejects() {
+        let verdict = parse_review_verdict("Looks fine to me!");
+        assert!(!verdict.approved);
+        assert_eq!(verdict.comments.len(), 1);
+    }
+}
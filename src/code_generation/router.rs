@@ -0,0 +1,146 @@
+//! Per-role model selection.
+//!
+//! Agent roles can run on different models, e.g. a cheap model for commit
+//! messages and a strong one for code generation. `ModelRouter` resolves a
+//! role to its model entry through the `[routing]` config section, falling
+//! back to the model that already does that job, and hands out one shared
+//! provider per model so roles on the same model share its rate limits.
+
+use anyhow::{Context, Result};
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+
+use super::llm::{LlmFactory, LlmProvider};
+use crate::core::config::{Config, ModelConfig};
+
+/// Agent role that a model call is made on behalf of
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ModelRole {
+    /// Specifications and plans
+    Planner,
+    /// Code and test generation
+    Coder,
+    /// Change review
+    Reviewer,
+    /// Critique of generated diffs before they are written
+    Critic,
+    /// Commit messages and short classification calls
+    Committer,
+}
+
+impl fmt::Display for ModelRole {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            ModelRole::Planner => "planner",
+            ModelRole::Coder => "coder",
+            ModelRole::Reviewer => "reviewer",
+            ModelRole::Critic => "critic",
+            ModelRole::Committer => "committer",
+        };
+        f.write_str(name)
+    }
+}
+
+/// Resolves the model and provider for each agent role
+pub struct ModelRouter {
+    config: Config,
+    providers: Mutex<HashMap<String, Arc<dyn LlmProvider>>>,
+}
+
+impl ModelRouter {
+    /// Create a router over `config`'s models and routing table
+    pub fn new(config: Config) -> Self {
+        Self {
+            config,
+            providers: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Model entry serving `role`, if any is configured
+    ///
+    /// Unrouted roles fall back to the model already doing that job: the
+    /// first research model plans, the first TDD model codes, the reviewer
+    /// is `review.reviewer_model`, the critic `review.critic_model`, and
+    /// commit messages come from the coder.
+    pub fn model_for(&self, role: ModelRole) -> Option<&ModelConfig> {
+        let routing = &self.config.routing;
+        let phases = &self.config.phases;
+        let name = match role {
+            ModelRole::Planner => routing
+                .planner
+                .as_ref()
+                .or_else(|| phases.research.models.first()),
+            ModelRole::Coder => routing.coder.as_ref().or_else(|| phases.tdd.models.first()),
+            ModelRole::Reviewer => routing.reviewer.as_ref().or(self
+                .config
+                .review
+                .reviewer_model
+                .as_ref()),
+            ModelRole::Critic => routing.critic.as_ref().or(self
+                .config
+                .review
+                .critic_model
+                .as_ref()),
+            ModelRole::Committer => {
+                return match &routing.committer {
+                    Some(name) => self.config.get_model(name),
+                    None => self.model_for(ModelRole::Coder),
+                }
+            }
+        };
+        self.config.get_model(name?)
+    }
+
+    /// LLM provider for `role`, with the model's fallbacks and race partner
+    ///
+    /// Providers are created on first use and shared between roles that
+    /// resolve to the same model.
+    pub fn provider(&self, role: ModelRole) -> Result<Arc<dyn LlmProvider>> {
+        let model = self
+            .model_for(role)
+            .with_context(|| format!("No model is configured for the {} role", role))?;
+
+        let mut providers = self.providers.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(provider) = providers.get(&model.name) {
+            return Ok(provider.clone());
+        }
+        let provider: Arc<dyn LlmProvider> = Arc::from(
+            LlmFactory::create_for_config(&self.config, model)
+                .with_context(|| format!("Failed to create the {} model '{}'", role, model.name))?,
+        );
+        providers.insert(model.name.clone(), provider.clone());
+        Ok(provider)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_roles_resolve_through_routing_then_defaults() {
+        let mut config = Config::for_testing();
+        let mut cheap = config.models[0].clone();
+        cheap.name = "cheap".to_string();
+        config.models.push(cheap);
+
+        let router = ModelRouter::new(config.clone());
+        let name = |role| router.model_for(role).map(|m| m.name.clone());
+        assert_eq!(name(ModelRole::Planner).as_deref(), Some("test-model"));
+        assert_eq!(name(ModelRole::Committer).as_deref(), Some("test-model"));
+        assert_eq!(name(ModelRole::Reviewer), None);
+        assert_eq!(name(ModelRole::Critic), None);
+        assert!(router.provider(ModelRole::Reviewer).is_err());
+
+        config.routing.committer = Some("cheap".to_string());
+        config.review.reviewer_model = Some("cheap".to_string());
+        config.review.critic_model = Some("cheap".to_string());
+        let router = ModelRouter::new(config);
+        let name = |role| router.model_for(role).map(|m| m.name.clone());
+        assert_eq!(name(ModelRole::Coder).as_deref(), Some("test-model"));
+        assert_eq!(name(ModelRole::Committer).as_deref(), Some("cheap"));
+        assert_eq!(name(ModelRole::Reviewer).as_deref(), Some("cheap"));
+        assert_eq!(name(ModelRole::Critic).as_deref(), Some("cheap"));
+    }
+}
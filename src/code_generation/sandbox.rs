@@ -0,0 +1,308 @@
+//! Isolated execution of agent-run commands.
+//!
+//! `Bash`, `compile_check` and `run_tests` start their processes through a
+//! `Sandbox`. Without a backend they run on the host; with Docker or Podman
+//! every command gets a throwaway container of the configured image, and
+//! with bubblewrap a namespace that sees the host read-only. In all cases
+//! the workspace is mounted read-write at its own path, and CPU, memory and
+//! network limits come from `SandboxConfig`.
+
+use anyhow::{anyhow, Context, Result};
+use log::{debug, warn};
+use std::path::Path;
+use std::process::{Output, Stdio};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::process::Command;
+
+use crate::core::config::{SandboxBackend, SandboxConfig};
+
+/// Most processes a command may have alive in a container, which stops
+/// fork bombs
+const PIDS_LIMIT: u32 = 1024;
+
+/// Runs commands with the configured isolation
+#[derive(Debug, Clone, Default)]
+pub struct Sandbox {
+    config: SandboxConfig,
+}
+
+impl Sandbox {
+    /// Sandbox for `config`
+    pub fn new(config: SandboxConfig) -> Self {
+        Self { config }
+    }
+
+    /// Whether commands are isolated from the host
+    pub fn is_isolated(&self) -> bool {
+        self.config.backend != SandboxBackend::None
+    }
+
+    /// Check that the backend's executable can be run
+    pub fn check(&self) -> Result<()> {
+        let Some(program) = backend_program(self.config.backend) else {
+            return Ok(());
+        };
+        let status = std::process::Command::new(program)
+            .arg("--version")
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status()
+            .with_context(|| format!("Sandbox backend {} is not installed", program))?;
+        if !status.success() {
+            return Err(anyhow!("Sandbox backend {} is not usable", program));
+        }
+        Ok(())
+    }
+
+    /// Run `program` with `args` in `workspace` and collect its output,
+    /// killing it once `timeout` has passed
+    pub async fn run(
+        &self,
+        workspace: &Path,
+        program: &str,
+        args: &[&str],
+        timeout: Option<Duration>,
+    ) -> Result<Output> {
+        let container = backend_program(self.config.backend)
+            .filter(|_| self.config.backend != SandboxBackend::Bubblewrap)
+            .map(|_| container_name());
+        let mut cmd = self.command(workspace, program, container.as_deref());
+        cmd.args(args)
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true);
+        debug!("Running {:?}", cmd.as_std());
+
+        let child = cmd
+            .spawn()
+            .with_context(|| format!("Failed to start {}", program))?;
+        let Some(timeout) = timeout else {
+            return Ok(child.wait_with_output().await?);
+        };
+        match tokio::time::timeout(timeout, child.wait_with_output()).await {
+            Ok(output) => Ok(output?),
+            Err(_) => {
+                // Killing the client does not stop a container
+                if let (Some(program), Some(name)) =
+                    (backend_program(self.config.backend), &container)
+                {
+                    if let Err(e) = Command::new(program)
+                        .args(["rm", "-f", name])
+                        .output()
+                        .await
+                    {
+                        warn!("Failed to remove timed out container {}: {}", name, e);
+                    }
+                }
+                Err(anyhow!("Command timed out after {}ms", timeout.as_millis()))
+            }
+        }
+    }
+
+    /// Command running `program` in `workspace`; arguments added to it go
+    /// to `program`
+    fn command(&self, workspace: &Path, program: &str, container: Option<&str>) -> Command {
+        let ws = workspace.to_string_lossy().to_string();
+        let config = &self.config;
+        let mut env: Vec<(&String, &String)> = config.env.iter().collect();
+        env.sort();
+
+        let mut cmd = match config.backend {
+            SandboxBackend::None => {
+                let mut cmd = Command::new(program);
+                cmd.envs(env);
+                cmd
+            }
+            SandboxBackend::Docker | SandboxBackend::Podman => {
+                let mut cmd = Command::new(backend_program(config.backend).unwrap_or_default());
+                cmd.args(["run", "--rm", "--init"]);
+                if let Some(name) = container {
+                    cmd.args(["--name", name]);
+                }
+                if !config.network {
+                    cmd.args(["--network", "none"]);
+                }
+                if let Some(cpus) = config.cpus {
+                    cmd.arg("--cpus").arg(cpus.to_string());
+                }
+                if let Some(memory) = config.memory_mb {
+                    cmd.arg("--memory").arg(format!("{}m", memory));
+                }
+                cmd.arg("--pids-limit").arg(PIDS_LIMIT.to_string());
+                // Files written to the workspace keep its owner
+                if config.backend == SandboxBackend::Podman {
+                    cmd.arg("--userns=keep-id");
+                } else if let Some(user) = owner(workspace) {
+                    cmd.arg("--user").arg(user);
+                }
+                cmd.args(["--tmpfs", "/tmp", "-e", "HOME=/tmp"]);
+                for (key, value) in env {
+                    cmd.arg("-e").arg(format!("{}={}", key, value));
+                }
+                cmd.arg("-v").arg(format!("{}:{}", ws, ws));
+                for mount in &config.mounts {
+                    cmd.arg("-v").arg(mount);
+                }
+                cmd.arg("-w").arg(&ws).arg(&config.image).arg(program);
+                cmd
+            }
+            SandboxBackend::Bubblewrap => {
+                let mut cmd = Command::new("bwrap");
+                cmd.args(["--ro-bind", "/", "/", "--dev", "/dev", "--proc", "/proc"]);
+                cmd.args(["--tmpfs", "/tmp", "--bind", &ws, &ws]);
+                for mount in &config.mounts {
+                    let mut parts = mount.splitn(3, ':');
+                    let source = parts.next().unwrap_or_default();
+                    let dest = parts.next().unwrap_or(source);
+                    let bind = if parts.next() == Some("ro") {
+                        "--ro-bind"
+                    } else {
+                        "--bind"
+                    };
+                    cmd.args([bind, source, dest]);
+                }
+                cmd.args([
+                    "--unshare-pid",
+                    "--unshare-ipc",
+                    "--unshare-uts",
+                    "--die-with-parent",
+                ]);
+                if !config.network {
+                    cmd.arg("--unshare-net");
+                }
+                for (key, value) in env {
+                    cmd.arg("--setenv").arg(key).arg(value);
+                }
+                cmd.arg("--chdir").arg(&ws);
+                // bubblewrap has no cgroup limits; cap the address space
+                if let Some(memory) = config.memory_mb {
+                    cmd.args(["sh", "-c"])
+                        .arg(format!("ulimit -v {} && exec \"$@\"", memory * 1024))
+                        .arg("sandbox");
+                }
+                cmd.arg(program);
+                cmd
+            }
+        };
+        cmd.current_dir(workspace);
+        cmd
+    }
+}
+
+fn backend_program(backend: SandboxBackend) -> Option<&'static str> {
+    match backend {
+        SandboxBackend::None => None,
+        SandboxBackend::Docker => Some("docker"),
+        SandboxBackend::Podman => Some("podman"),
+        SandboxBackend::Bubblewrap => Some("bwrap"),
+    }
+}
+
+fn container_name() -> String {
+    static NEXT: AtomicU64 = AtomicU64::new(1);
+    format!(
+        "borg-sandbox-{}-{}",
+        std::process::id(),
+        NEXT.fetch_add(1, Ordering::SeqCst)
+    )
+}
+
+/// `uid:gid` owning `workspace`
+#[cfg(unix)]
+fn owner(workspace: &Path) -> Option<String> {
+    use std::os::unix::fs::MetadataExt;
+    let meta = std::fs::metadata(workspace).ok()?;
+    Some(format!("{}:{}", meta.uid(), meta.gid()))
+}
+
+#[cfg(not(unix))]
+fn owner(_workspace: &Path) -> Option<String> {
+    None
+}
+
+/// The sandbox of the current services (no isolation unless configured)
+pub fn global() -> Arc<Sandbox> {
+    crate::core::services::Services::current().sandbox.clone()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(cmd: &Command) -> Vec<String> {
+        std::iter::once(cmd.as_std().get_program())
+            .chain(cmd.as_std().get_args())
+            .map(|a| a.to_string_lossy().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn test_container_command_applies_limits() {
+        let sandbox = Sandbox::new(SandboxConfig {
+            backend: SandboxBackend::Podman,
+            image: "rust:1.80".to_string(),
+            cpus: Some(1.5),
+            memory_mb: Some(2048),
+            mounts: vec!["/home/me/.cargo/registry:/usr/local/cargo/registry:ro".to_string()],
+            env: [("CARGO_NET_OFFLINE".to_string(), "true".to_string())].into(),
+            ..SandboxConfig::default()
+        });
+        let cmd = sandbox.command(Path::new("/work"), "cargo", Some("borg-sandbox-1"));
+        assert_eq!(
+            args(&cmd).join(" "),
+            "podman run --rm --init --name borg-sandbox-1 --network none --cpus 1.5 \
+             --memory 2048m --pids-limit 1024 --userns=keep-id --tmpfs /tmp -e HOME=/tmp \
+             -e CARGO_NET_OFFLINE=true -v /work:/work \
+             -v /home/me/.cargo/registry:/usr/local/cargo/registry:ro -w /work rust:1.80 cargo"
+        );
+    }
+
+    #[test]
+    fn test_bubblewrap_command_caps_memory() {
+        let sandbox = Sandbox::new(SandboxConfig {
+            backend: SandboxBackend::Bubblewrap,
+            memory_mb: Some(1),
+            network: true,
+            ..SandboxConfig::default()
+        });
+        let cmd = sandbox.command(Path::new("/work"), "cargo", None);
+        assert_eq!(
+            args(&cmd).join(" "),
+            "bwrap --ro-bind / / --dev /dev --proc /proc --tmpfs /tmp --bind /work /work \
+             --unshare-pid --unshare-ipc --unshare-uts --die-with-parent --chdir /work \
+             sh -c ulimit -v 1024 && exec \"$@\" sandbox cargo"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_unsandboxed_run_times_out() {
+        let dir = tempfile::tempdir().unwrap();
+        let sandbox = Sandbox::default();
+        assert!(!sandbox.is_isolated());
+        sandbox.check().unwrap();
+
+        let output = sandbox
+            .run(dir.path(), "sh", &["-c", "pwd"], None)
+            .await
+            .unwrap();
+        let pwd = String::from_utf8_lossy(&output.stdout);
+        assert_eq!(
+            Path::new(pwd.trim()).canonicalize().unwrap(),
+            dir.path().canonicalize().unwrap()
+        );
+
+        let err = sandbox
+            .run(
+                dir.path(),
+                "sh",
+                &["-c", "sleep 5"],
+                Some(Duration::from_millis(50)),
+            )
+            .await
+            .unwrap_err();
+        assert_eq!(err.to_string(), "Command timed out after 50ms");
+    }
+}
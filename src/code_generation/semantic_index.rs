@@ -0,0 +1,640 @@
+//! Embedding index of the workspace for semantic code search.
+//!
+//! Every source file is split into overlapping line windows, which are
+//! embedded with the model named by `index.embedding_model`. The embeddings
+//! go to the `code_vectors` vector collection, and the `code_index`
+//! collection keeps one record per file with its chunks' line spans. A
+//! record remembers the hash of the contents it was built from, so
+//! re-indexing only embeds files that changed; and re-indexing only runs when git reports a different
+//! HEAD or working tree than the last time. Searches re-index first, which
+//! keeps the index in step with the edits of the current goal.
+//!
+//! The index answers the `SemanticSearch` tool and, as a
+//! `SimilaritySource`, ranks files for the context builder.
+
+use anyhow::{anyhow, Context, Result};
+use async_trait::async_trait;
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::Arc;
+
+use crate::code_generation::context_builder::SimilaritySource;
+use crate::code_generation::language::source_files;
+use crate::code_generation::llm_tool::{
+    parsed_arg, str_arg, LlmTool, ToolArgs, ToolParameter, ToolParameterType,
+};
+use crate::core::config::IndexConfig;
+use crate::database::{DatabaseError, DatabaseInterface, VectorEntry, VectorStore};
+use crate::providers::Provider;
+
+/// Lines of a chunk shown in `SemanticSearch` results
+const PREVIEW_LINES: usize = 12;
+
+/// Chunks considered per file when ranking files by similarity
+const CHUNKS_PER_FILE: usize = 4;
+
+/// One embedded window of a file
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct IndexedChunk {
+    /// First line, 1-based
+    pub start_line: usize,
+
+    /// Last line, inclusive
+    pub end_line: usize,
+}
+
+impl IndexedChunk {
+    /// Id of the chunk's embedding in the vector collection
+    fn vector_id(&self, path: &str) -> String {
+        format!("{}#{}", path, self.start_line)
+    }
+}
+
+/// The embedded chunks of one file
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IndexedFile {
+    /// Workspace-relative path
+    pub id: String,
+
+    /// SHA-256 of the contents the chunks were built from
+    pub hash: String,
+
+    /// Model that embedded the chunks
+    pub model: String,
+
+    /// The file's chunks in line order
+    pub chunks: Vec<IndexedChunk>,
+}
+
+/// A chunk matching a query
+#[derive(Debug, Clone, PartialEq)]
+pub struct SearchHit {
+    /// Workspace-relative path
+    pub path: String,
+
+    /// First line, 1-based
+    pub start_line: usize,
+
+    /// Last line, inclusive
+    pub end_line: usize,
+
+    /// Cosine similarity to the query
+    pub score: f32,
+}
+
+/// What a re-index did
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct IndexStats {
+    /// Files (re-)embedded
+    pub embedded_files: usize,
+
+    /// Chunks embedded
+    pub embedded_chunks: usize,
+
+    /// Files dropped because they no longer exist
+    pub removed_files: usize,
+}
+
+/// A file whose chunks need embedding
+struct StaleFile {
+    path: String,
+    hash: String,
+    chunks: Vec<(usize, usize, String)>,
+}
+
+/// Line windows of `content` as `(start_line, end_line, text)`, 1-based and
+/// inclusive, each sharing `overlap` lines with the one before
+pub fn chunk_lines(content: &str, lines: usize, overlap: usize) -> Vec<(usize, usize, String)> {
+    let all: Vec<&str> = content.lines().collect();
+    let step = lines.saturating_sub(overlap).max(1);
+    let mut chunks = Vec::new();
+    let mut start = 0;
+    while start < all.len() {
+        let end = (start + lines).min(all.len());
+        let text = all[start..end].join("\n");
+        if !text.trim().is_empty() {
+            chunks.push((start + 1, end, text));
+        }
+        if end == all.len() {
+            break;
+        }
+        start += step;
+    }
+    chunks
+}
+
+/// Schema version 1 kept the embeddings in the chunks; drop them and the
+/// hash, so the file is embedded into the vector collection again
+pub(crate) fn move_vectors_out(entity: &mut serde_json::Value) -> Result<(), String> {
+    if let Some(chunks) = entity.get_mut("chunks").and_then(|c| c.as_array_mut()) {
+        for chunk in chunks {
+            if let Some(chunk) = chunk.as_object_mut() {
+                chunk.remove("vector");
+            }
+        }
+    }
+    entity["hash"] = serde_json::Value::String(String::new());
+    Ok(())
+}
+
+/// Ids of the embeddings of `file`'s chunks
+fn vector_ids(file: &IndexedFile) -> Vec<String> {
+    file.chunks
+        .iter()
+        .map(|chunk| chunk.vector_id(&file.id))
+        .collect()
+}
+
+fn content_hash(content: &str) -> String {
+    Sha256::digest(content.as_bytes())
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+/// HEAD and working tree status of the repository at `workspace`, `None`
+/// outside of a git repository
+fn git_state(workspace: &Path) -> Option<String> {
+    let git = |args: &[&str]| {
+        Command::new("git")
+            .args(args)
+            .current_dir(workspace)
+            .output()
+            .ok()
+            .filter(|out| out.status.success())
+            .map(|out| String::from_utf8_lossy(&out.stdout).to_string())
+    };
+    Some(format!(
+        "{}\n{}",
+        git(&["rev-parse", "HEAD"])?,
+        git(&["status", "--porcelain", "--untracked-files=all"])?
+    ))
+}
+
+/// Embedding index of a workspace
+pub struct SemanticIndex {
+    workspace: PathBuf,
+    store: Arc<dyn DatabaseInterface<IndexedFile>>,
+    vectors: Arc<dyn VectorStore>,
+    embedder: Arc<dyn Provider>,
+    model: String,
+    config: IndexConfig,
+
+    /// The indexed files, loaded on first use, and the git state they
+    /// were last brought up to date with
+    state: tokio::sync::Mutex<Option<(Vec<IndexedFile>, Option<String>)>>,
+}
+
+impl SemanticIndex {
+    /// Index of `workspace` kept in `store` and `vectors`, embedded by
+    /// `model` through `embedder`
+    pub fn new(
+        workspace: PathBuf,
+        store: Arc<dyn DatabaseInterface<IndexedFile>>,
+        vectors: Arc<dyn VectorStore>,
+        embedder: Arc<dyn Provider>,
+        model: impl Into<String>,
+        config: IndexConfig,
+    ) -> Self {
+        Self {
+            workspace,
+            store,
+            vectors,
+            embedder,
+            model: model.into(),
+            config,
+            state: tokio::sync::Mutex::new(None),
+        }
+    }
+
+    /// Bring the index up to date with the workspace, embedding only the
+    /// files that changed since they were indexed
+    pub async fn reindex(&self) -> Result<IndexStats> {
+        let mut state = self.state.lock().await;
+        self.reindex_locked(&mut state).await
+    }
+
+    async fn reindex_locked(
+        &self,
+        state: &mut Option<(Vec<IndexedFile>, Option<String>)>,
+    ) -> Result<IndexStats> {
+        let git = git_state(&self.workspace);
+        let mut indexed: HashMap<String, IndexedFile> = match state.take() {
+            Some((files, _)) => files,
+            None => {
+                let mut files: Vec<IndexedFile> = self
+                    .store
+                    .get_all()
+                    .await
+                    .context("Failed to load the code index")?
+                    .into_iter()
+                    .map(|record| record.entity)
+                    .collect();
+                // Without their embeddings the files need embedding again
+                let embedded = self
+                    .vectors
+                    .count()
+                    .await
+                    .context("Failed to load the code vectors")?;
+                if embedded == 0 {
+                    files.iter_mut().for_each(|file| file.hash.clear());
+                }
+                files
+            }
+        }
+        .into_iter()
+        .map(|file| (file.id.clone(), file))
+        .collect();
+
+        // Chunk the files that are new or changed
+        let mut stale: Vec<StaleFile> = Vec::new();
+        let mut present = Vec::new();
+        for path in source_files(&self.workspace) {
+            let Ok(content) = std::fs::read_to_string(&path) else {
+                continue;
+            };
+            let relative = path
+                .strip_prefix(&self.workspace)
+                .unwrap_or(&path)
+                .to_string_lossy()
+                .to_string();
+            let hash = content_hash(&content);
+            present.push(relative.clone());
+            if indexed
+                .get(&relative)
+                .is_some_and(|file| file.hash == hash && file.model == self.model)
+            {
+                continue;
+            }
+            let chunks = chunk_lines(
+                &content,
+                self.config.chunk_lines.max(1),
+                self.config.chunk_overlap,
+            );
+            stale.push(StaleFile {
+                path: relative,
+                hash,
+                chunks,
+            });
+        }
+
+        let mut stats = IndexStats::default();
+        let result = self.embed_files(stale, &mut indexed, &mut stats).await;
+
+        // Drop the files that are gone
+        let gone: Vec<String> = indexed
+            .keys()
+            .filter(|path| !present.contains(path))
+            .cloned()
+            .collect();
+        for path in gone {
+            if let Some(file) = indexed.remove(&path) {
+                if let Err(e) = self.vectors.remove(&vector_ids(&file)).await {
+                    warn!("Failed to remove {} from the code vectors: {}", path, e);
+                }
+            }
+            match self.store.delete(&path).await {
+                Ok(()) | Err(DatabaseError::NotFound(_)) => stats.removed_files += 1,
+                Err(e) => warn!("Failed to remove {} from the code index: {}", path, e),
+            }
+        }
+
+        // A failed embedding leaves the state unrecorded so the next call retries
+        let git = if result.is_ok() { git } else { None };
+        *state = Some((indexed.into_values().collect(), git));
+        result?;
+        if stats.embedded_files > 0 || stats.removed_files > 0 {
+            info!(
+                "Code index updated: {} files ({} chunks) embedded, {} removed",
+                stats.embedded_files, stats.embedded_chunks, stats.removed_files
+            );
+        }
+        Ok(stats)
+    }
+
+    /// Embed `stale` files in batches and save each file once all of its
+    /// chunks are embedded
+    async fn embed_files(
+        &self,
+        stale: Vec<StaleFile>,
+        indexed: &mut HashMap<String, IndexedFile>,
+        stats: &mut IndexStats,
+    ) -> Result<()> {
+        let batch_size = self.config.batch_size.max(1);
+        let mut pending: Vec<(IndexedFile, usize)> = Vec::new();
+        let mut texts: Vec<String> = Vec::new();
+        let mut files = stale.into_iter().peekable();
+        while let Some(StaleFile { path, hash, chunks }) = files.next() {
+            let mut file = IndexedFile {
+                id: path.clone(),
+                hash,
+                model: self.model.clone(),
+                chunks: Vec::new(),
+            };
+            for (start_line, end_line, text) in &chunks {
+                file.chunks.push(IndexedChunk {
+                    start_line: *start_line,
+                    end_line: *end_line,
+                });
+                // The path tells the model what the chunk belongs to
+                texts.push(format!("// {}\n{}", path, text));
+            }
+            pending.push((file, chunks.len()));
+
+            if texts.len() < batch_size && files.peek().is_some() {
+                continue;
+            }
+            let mut vectors = Vec::with_capacity(texts.len());
+            for batch in texts.chunks(batch_size) {
+                vectors.extend(
+                    self.embedder
+                        .embed(batch)
+                        .await
+                        .map_err(|e| anyhow!("Failed to embed code chunks: {}", e))?,
+                );
+            }
+            texts.clear();
+
+            let mut vectors = vectors.into_iter();
+            for (file, count) in pending.drain(..) {
+                let entries = file
+                    .chunks
+                    .iter()
+                    .zip(vectors.by_ref().take(count))
+                    .map(|(chunk, vector)| {
+                        VectorEntry::new(
+                            chunk.vector_id(&file.id),
+                            vector,
+                            serde_json::json!({
+                                "path": file.id,
+                                "start_line": chunk.start_line,
+                                "end_line": chunk.end_line,
+                            }),
+                        )
+                    })
+                    .collect();
+                stats.embedded_files += 1;
+                stats.embedded_chunks += count;
+                // The record is saved last, so a file whose embeddings
+                // failed to save is embedded again
+                if let Some(old) = indexed.get(&file.id) {
+                    self.vectors
+                        .remove(&vector_ids(old))
+                        .await
+                        .context("Failed to remove from the code vectors")?;
+                }
+                self.vectors
+                    .upsert(entries)
+                    .await
+                    .context("Failed to save to the code vectors")?;
+                self.save(file.clone()).await?;
+                indexed.insert(file.id.clone(), file);
+            }
+        }
+        Ok(())
+    }
+
+    async fn save(&self, file: IndexedFile) -> Result<()> {
+        let saved = match self.store.update(file.clone(), None).await {
+            Err(DatabaseError::NotFound(_)) => self.store.insert(file).await,
+            saved => saved,
+        };
+        saved.context("Failed to save to the code index")?;
+        Ok(())
+    }
+
+    /// The `limit` chunks closest in meaning to `query`, re-indexing first
+    /// when the repository changed since the last search
+    pub async fn search(&self, query: &str, limit: usize) -> Result<Vec<SearchHit>> {
+        let mut state = self.state.lock().await;
+        let current = git_state(&self.workspace);
+        let up_to_date = matches!(&*state, Some((_, Some(seen))) if Some(seen) == current.as_ref());
+        if !up_to_date {
+            self.reindex_locked(&mut state).await?;
+        }
+        if state.is_none() {
+            return Ok(Vec::new());
+        }
+
+        let query = self
+            .embedder
+            .embed(&[query.to_string()])
+            .await
+            .map_err(|e| anyhow!("Failed to embed the query: {}", e))?
+            .pop()
+            .unwrap_or_default();
+        let mut hits: Vec<SearchHit> = self
+            .vectors
+            .nearest(&query, limit)
+            .await
+            .context("Failed to search the code vectors")?
+            .into_iter()
+            .filter_map(|found| {
+                let span = |key: &str| found.metadata.get(key)?.as_u64().map(|n| n as usize);
+                Some(SearchHit {
+                    path: found.metadata.get("path")?.as_str()?.to_string(),
+                    start_line: span("start_line")?,
+                    end_line: span("end_line")?,
+                    score: found.score,
+                })
+            })
+            .collect();
+        hits.sort_by(|a, b| {
+            b.score
+                .total_cmp(&a.score)
+                .then_with(|| a.path.cmp(&b.path))
+                .then(a.start_line.cmp(&b.start_line))
+        });
+        hits.truncate(limit);
+        Ok(hits)
+    }
+}
+
+#[async_trait]
+impl SimilaritySource for SemanticIndex {
+    /// Files scored by their best chunk
+    async fn similar_files(&self, query: &str, limit: usize) -> Result<Vec<(String, f64)>> {
+        let mut files: Vec<(String, f64)> = Vec::new();
+        for hit in self.search(query, limit * CHUNKS_PER_FILE).await? {
+            if !files.iter().any(|(path, _)| *path == hit.path) {
+                files.push((hit.path, hit.score.max(0.0) as f64));
+            }
+        }
+        files.truncate(limit);
+        Ok(files)
+    }
+}
+
+/// The code index of the current services, if there is one
+pub fn global() -> Option<Arc<SemanticIndex>> {
+    crate::core::services::Services::current()
+        .semantic_index
+        .clone()
+}
+
+/// A tool that finds code by what it does rather than by its text
+pub struct SemanticSearchTool {
+    index: Arc<SemanticIndex>,
+}
+
+impl SemanticSearchTool {
+    /// Create a new semantic search tool over `index`
+    pub fn new(index: Arc<SemanticIndex>) -> Self {
+        Self { index }
+    }
+
+    fn preview(&self, hit: &SearchHit) -> String {
+        let Ok(content) = std::fs::read_to_string(self.index.workspace.join(&hit.path)) else {
+            return String::new();
+        };
+        content
+            .lines()
+            .skip(hit.start_line - 1)
+            .take((hit.end_line + 1 - hit.start_line).min(PREVIEW_LINES))
+            .map(|line| format!("    {}\n", line))
+            .collect()
+    }
+}
+
+#[async_trait]
+impl LlmTool for SemanticSearchTool {
+    fn name(&self) -> &str {
+        "SemanticSearch"
+    }
+
+    fn is_read_only(&self) -> bool {
+        true
+    }
+
+    fn description(&self) -> &str {
+        "Find code by meaning using an embedding index of the workspace, e.g. query=\"where are retries backed off\" when the names involved are unknown. Returns the closest file:line spans with a preview."
+    }
+
+    fn parameters(&self) -> Vec<ToolParameter> {
+        vec![
+            ToolParameter {
+                name: "query".to_string(),
+                description: "What the code does, in natural language or code".to_string(),
+                required: true,
+                default_value: None,
+                param_type: Some(ToolParameterType::String),
+            },
+            ToolParameter {
+                name: "limit".to_string(),
+                description: "Most results to return".to_string(),
+                required: false,
+                default_value: Some("5".to_string()),
+                param_type: Some(ToolParameterType::Integer),
+            },
+        ]
+    }
+
+    async fn execute(&self, args: &ToolArgs) -> Result<String> {
+        let query = str_arg(args, "query")
+            .filter(|q| !q.trim().is_empty())
+            .ok_or_else(|| anyhow!("query parameter is required"))?;
+        let limit = parsed_arg(args, "limit").unwrap_or(5).max(1);
+        let hits = self.index.search(&query, limit).await?;
+        if hits.is_empty() {
+            return Ok("No indexed code found".to_string());
+        }
+        let mut out = String::new();
+        for hit in &hits {
+            out.push_str(&format!(
+                "{}:{}-{} (score {:.2})\n{}",
+                hit.path,
+                hit.start_line,
+                hit.end_line,
+                hit.score,
+                self.preview(hit)
+            ));
+        }
+        Ok(out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::code_generation::test_support::WordEmbedder;
+    use crate::database::{FileDb, FileVectorStore};
+
+    const WORDS: &[&str] = &["retry", "backoff", "parse", "config"];
+
+    #[test]
+    fn test_chunks_overlap_and_cover_the_file() {
+        let content: String = (1..=10).map(|i| format!("line {}\n", i)).collect();
+        let spans: Vec<(usize, usize)> = chunk_lines(&content, 4, 1)
+            .into_iter()
+            .map(|(start, end, _)| (start, end))
+            .collect();
+        assert_eq!(spans, vec![(1, 4), (4, 7), (7, 10)]);
+    }
+
+    #[tokio::test]
+    async fn test_index_embeds_only_changed_files() {
+        let ws = tempfile::tempdir().unwrap();
+        let data = tempfile::tempdir().unwrap();
+        std::fs::create_dir_all(ws.path().join("src")).unwrap();
+        std::fs::write(
+            ws.path().join("src/net.rs"),
+            "// retry with exponential backoff\nfn retry() { backoff(); }\n",
+        )
+        .unwrap();
+        std::fs::write(
+            ws.path().join("src/settings.rs"),
+            "// parse the config file\nfn parse_config() {}\n",
+        )
+        .unwrap();
+
+        let embedder = Arc::new(WordEmbedder::new(WORDS));
+        let open = |embedder: Arc<WordEmbedder>| async {
+            let store = FileDb::<IndexedFile>::new(data.path(), "code_index")
+                .await
+                .unwrap();
+            let vectors = FileVectorStore::new(data.path(), "code_vectors")
+                .await
+                .unwrap();
+            SemanticIndex::new(
+                ws.path().to_path_buf(),
+                Arc::new(store),
+                Arc::new(vectors),
+                embedder,
+                "words",
+                IndexConfig::default(),
+            )
+        };
+        let index = open(embedder.clone()).await;
+
+        let hits = index.search("how is the config parsed", 1).await.unwrap();
+        assert_eq!(hits[0].path, "src/settings.rs");
+        let similar = index
+            .similar_files("backoff between retries", 2)
+            .await
+            .unwrap();
+        assert_eq!(similar[0].0, "src/net.rs");
+        assert_eq!(similar.len(), 2);
+
+        // A reopened index only embeds what changed since
+        let embedder = Arc::new(WordEmbedder::new(WORDS));
+        let index = open(embedder.clone()).await;
+        std::fs::write(ws.path().join("src/settings.rs"), "fn load() {}\n").unwrap();
+        std::fs::remove_file(ws.path().join("src/net.rs")).unwrap();
+        let stats = index.reindex().await.unwrap();
+        assert_eq!(
+            stats,
+            IndexStats {
+                embedded_files: 1,
+                embedded_chunks: 1,
+                removed_files: 1
+            }
+        );
+        assert_eq!(embedder.inputs(), 1);
+        let hits = index.search("retry", 5).await.unwrap();
+        assert!(hits.iter().all(|hit| hit.path == "src/settings.rs"));
+    }
+}
@@ -1,11 +1,16 @@
 use anyhow::{Context, Result};
 use log::info;
 use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::fmt;
 use std::sync::Arc;
 
 use crate::code_generation::generator::CodeContext;
 use crate::code_generation::llm::LlmProvider;
+use crate::code_generation::prompt;
+use crate::code_generation::router::{ModelRole, ModelRouter};
 use crate::core::optimization::OptimizationGoal;
+use crate::providers::ResponseFormat;
 
 /// Represents a specification for a code change
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -16,11 +21,181 @@ pub struct Specification {
     /// Files to be created, modified, or deleted
     pub file_changes: Vec<SpecFileChange>,
 
+    /// Functions the change adds or alters, for property and fuzz tests
+    #[serde(default)]
+    pub functions: Vec<SpecFunction>,
+
     /// Expected behaviors that should be testable
     pub expected_behaviors: Vec<String>,
 
     /// Acceptance criteria that tests should verify
-    pub acceptance_criteria: Vec<String>,
+    pub acceptance_criteria: Vec<AcceptanceCriterion>,
+}
+
+impl Specification {
+    /// The descriptions of the acceptance criteria
+    pub fn criteria_descriptions(&self) -> Vec<String> {
+        self.acceptance_criteria
+            .iter()
+            .map(|criterion| criterion.description.clone())
+            .collect()
+    }
+
+    /// JSON Schema the LLM's specification output must follow
+    pub fn json_schema() -> serde_json::Value {
+        let strings = json!({"type": "array", "items": {"type": "string"}});
+        let optional_string = json!({"type": ["string", "null"]});
+        json!({
+            "type": "object",
+            "properties": {
+                "description": {"type": "string", "minLength": 1},
+                "file_changes": {
+                    "type": "array",
+                    "items": {
+                        "type": "object",
+                        "properties": {
+                            "path": {"type": "string", "minLength": 1},
+                            "change_type": {"type": "string", "enum": ["create", "modify", "delete"]},
+                            "description": {"type": "string"}
+                        },
+                        "required": ["path", "change_type", "description"],
+                        "additionalProperties": false
+                    }
+                },
+                "functions": {
+                    "type": "array",
+                    "items": {
+                        "type": "object",
+                        "properties": {
+                            "path": {"type": "string", "minLength": 1},
+                            "signature": {"type": "string", "minLength": 1},
+                            "properties": strings
+                        },
+                        "required": ["path", "signature", "properties"],
+                        "additionalProperties": false
+                    }
+                },
+                "expected_behaviors": strings,
+                "acceptance_criteria": {
+                    "type": "array",
+                    "items": {
+                        "type": "object",
+                        "properties": {
+                            "description": {"type": "string", "minLength": 1},
+                            "test": optional_string,
+                            "command": optional_string
+                        },
+                        "required": ["description", "test", "command"],
+                        "additionalProperties": false
+                    }
+                }
+            },
+            "required": ["description", "file_changes", "functions", "expected_behaviors", "acceptance_criteria"],
+            "additionalProperties": false
+        })
+    }
+}
+
+/// A criterion a change must meet, with how to check it
+///
+/// A criterion names the test that verifies it, a shell command that must
+/// exit successfully, or both. One with neither can only be judged by the
+/// test suite as a whole. A plain string deserializes as a criterion
+/// without checks.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(from = "CriterionRepr")]
+pub struct AcceptanceCriterion {
+    /// What must hold
+    pub description: String,
+
+    /// Name of the test that must pass, e.g. `test_parses_empty_input`
+    pub test: Option<String>,
+
+    /// Shell command, run at the workspace root, that must exit with 0
+    pub command: Option<String>,
+}
+
+impl AcceptanceCriterion {
+    /// A criterion without checks
+    pub fn new(description: impl Into<String>) -> Self {
+        Self {
+            description: description.into(),
+            test: None,
+            command: None,
+        }
+    }
+
+    /// Verify the criterion with the test `name`
+    pub fn with_test(mut self, name: impl Into<String>) -> Self {
+        self.test = Some(name.into());
+        self
+    }
+
+    /// Verify the criterion with the shell `command`
+    pub fn with_command(mut self, command: impl Into<String>) -> Self {
+        self.command = Some(command.into());
+        self
+    }
+
+    /// Whether the criterion can be checked on its own
+    pub fn is_checkable(&self) -> bool {
+        self.test.is_some() || self.command.is_some()
+    }
+}
+
+impl fmt::Display for AcceptanceCriterion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.description)
+    }
+}
+
+/// The forms an acceptance criterion is accepted in
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum CriterionRepr {
+    Text(String),
+    Checked {
+        description: String,
+        #[serde(default)]
+        test: Option<String>,
+        #[serde(default)]
+        command: Option<String>,
+    },
+}
+
+impl From<CriterionRepr> for AcceptanceCriterion {
+    fn from(repr: CriterionRepr) -> Self {
+        match repr {
+            CriterionRepr::Text(description) => Self::new(description),
+            CriterionRepr::Checked {
+                description,
+                test,
+                command,
+            } => {
+                // Models write "" for a check they leave out
+                let non_empty = |s: Option<String>| s.filter(|s| !s.trim().is_empty());
+                Self {
+                    description,
+                    test: non_empty(test),
+                    command: non_empty(command),
+                }
+            }
+        }
+    }
+}
+
+/// A function named in a specification
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SpecFunction {
+    /// Path of the function, e.g. `crate::parser::parse`
+    pub path: String,
+
+    /// Its signature, e.g. `fn parse(input: &str) -> Result<Ast, ParseError>`
+    pub signature: String,
+
+    /// Invariants that hold for every input, e.g. "never panics"
+    #[serde(default)]
+    pub properties: Vec<String>,
 }
 
 /// Type of file change in a specification
@@ -59,6 +234,11 @@ impl SpecGenerator {
         Self { llm }
     }
 
+    /// Create a generator on the model routed to the planner role
+    pub fn from_router(router: &ModelRouter) -> Result<Self> {
+        Ok(Self::new(router.provider(ModelRole::Planner)?))
+    }
+
     /// Generate a specification for the given optimization goal
     pub async fn generate_spec(
         &self,
@@ -71,7 +251,15 @@ impl SpecGenerator {
 
         let response = self
             .llm
-            .generate(&prompt, None, Some(0.3))
+            .generate_with_format(
+                &prompt,
+                None,
+                Some(0.3),
+                Some(ResponseFormat::json_schema(
+                    "specification".to_string(),
+                    Specification::json_schema(),
+                )),
+            )
             .await
             .context("Failed to generate specification from LLM")?;
 
@@ -80,63 +268,34 @@ impl SpecGenerator {
 
     /// Build the prompt for specification generation
     fn build_spec_prompt(&self, goal: &OptimizationGoal, context: &CodeContext) -> String {
-        let mut prompt = String::new();
-
-        prompt.push_str("You are a software architect. Generate a detailed specification for implementing the following goal.\n\n");
-
-        prompt.push_str("## Goal\n");
-        prompt.push_str(&format!("ID: {}\n", goal.id));
-        prompt.push_str(&format!("Title: {}\n", goal.title));
-        prompt.push_str(&format!("Description: {}\n", goal.description));
-
         // Extract file references from tags (format: "file:path/to/file.rs")
-        let file_refs: Vec<&str> = goal
+        let target_files: Vec<&str> = goal
             .tags
             .iter()
             .filter_map(|tag| tag.strip_prefix("file:"))
             .collect();
-        if !file_refs.is_empty() {
-            prompt.push_str("Target Files:\n");
-            for file_ref in file_refs {
-                prompt.push_str(&format!("- {}\n", file_ref));
-            }
-        }
-        prompt.push('\n');
-
-        prompt.push_str("## Task Context\n");
-        prompt.push_str(&format!("{}\n\n", context.task));
-
-        if !context.file_paths.is_empty() {
-            prompt.push_str("## Relevant Files\n");
-            for path in &context.file_paths {
-                prompt.push_str(&format!("- {}\n", path));
-            }
-            prompt.push('\n');
-        }
-
-        if let Some(ref contents) = context.file_contents {
-            prompt.push_str("## File Contents\n");
-            for (path, content) in contents {
-                prompt.push_str(&format!("### {}\n```\n{}\n```\n\n", path, content));
-            }
-        }
-
-        prompt.push_str("## Output Format\n");
-        prompt.push_str("Respond with a JSON object containing:\n");
-        prompt.push_str("- description: A high-level summary of what this change accomplishes\n");
-        prompt.push_str(
-            "- file_changes: Array of {path, change_type (create/modify/delete), description}\n",
-        );
-        prompt.push_str("- expected_behaviors: Array of strings describing testable behaviors\n");
-        prompt.push_str(
-            "- acceptance_criteria: Array of specific criteria that tests should verify\n\n",
-        );
-
-        prompt.push_str("Focus on WHAT should be built, not HOW. The specification should be detailed enough to write tests from.\n\n");
-
-        prompt.push_str("```json\n");
+        let file_contents: Vec<_> = context
+            .file_contents
+            .iter()
+            .flatten()
+            .map(|(path, content)| json!({ "path": path, "content": content }))
+            .collect();
 
-        prompt
+        prompt::render(
+            "spec",
+            self.llm.model(),
+            &json!({
+                "goal": {
+                    "id": goal.id,
+                    "title": goal.title,
+                    "description": goal.description,
+                },
+                "target_files": target_files,
+                "task": context.task,
+                "file_paths": context.file_paths,
+                "file_contents": file_contents,
+            }),
+        )
     }
 
     /// Parse the LLM response into a Specification
@@ -206,13 +365,48 @@ mod tests {
             "file_changes": [
                 {"path": "src/main.rs", "change_type": "modify", "description": "Add log statements"}
             ],
+            "functions": [
+                {"path": "crate::log::format_line", "signature": "fn format_line(msg: &str) -> String", "properties": ["the output contains msg"]}
+            ],
             "expected_behaviors": ["Log messages should appear on startup"],
-            "acceptance_criteria": ["Main function logs 'Starting application'"]
+            "acceptance_criteria": [
+                {"description": "Main function logs 'Starting application'", "test": "test_logs_startup", "command": null},
+                {"description": "The binary still builds", "test": "", "command": "cargo build"}
+            ]
         }"#;
 
+        let value: serde_json::Value = serde_json::from_str(json).unwrap();
+        assert!(
+            crate::providers::schema::validate(&Specification::json_schema(), &value).is_empty()
+        );
+
         let spec: Specification = serde_json::from_str(json).unwrap();
         assert_eq!(spec.description, "Add logging to main function");
         assert_eq!(spec.file_changes.len(), 1);
         assert_eq!(spec.file_changes[0].change_type, ChangeType::Modify);
+        assert_eq!(
+            spec.functions[0].properties,
+            vec!["the output contains msg"]
+        );
+        assert_eq!(
+            spec.acceptance_criteria,
+            vec![
+                AcceptanceCriterion::new("Main function logs 'Starting application'")
+                    .with_test("test_logs_startup"),
+                AcceptanceCriterion::new("The binary still builds").with_command("cargo build"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_plain_criteria_have_no_checks() {
+        let json = r#"{"description": "Test", "file_changes": [], "expected_behaviors": [],
+                       "acceptance_criteria": ["Parses empty input"]}"#;
+        let spec: Specification = serde_json::from_str(json).unwrap();
+        assert_eq!(
+            spec.acceptance_criteria,
+            vec![AcceptanceCriterion::new("Parses empty input")]
+        );
+        assert!(!spec.acceptance_criteria[0].is_checkable());
     }
 }
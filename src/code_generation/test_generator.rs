@@ -1,11 +1,20 @@
-use anyhow::{Context, Result};
-use log::info;
+use anyhow::{bail, Context, Result};
+use log::{info, warn};
+use regex::Regex;
 use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 
 use crate::code_generation::generator::CodeContext;
 use crate::code_generation::llm::LlmProvider;
+use crate::code_generation::prompt;
+use crate::code_generation::router::{ModelRole, ModelRouter};
 use crate::code_generation::spec_generator::Specification;
+use crate::core::config::{TddGateConfig, TestGenerationMode};
+use crate::providers::ResponseFormat;
+use crate::testing::libtest;
+use crate::testing::test_runner::TestFailure;
 
 /// Generated tests for a specification
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -18,6 +27,72 @@ pub struct GeneratedTests {
 
     /// Names of the individual tests
     pub test_names: Vec<String>,
+
+    /// proptest property tests, generated in the property mode
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub property_tests: Option<TestFile>,
+
+    /// cargo-fuzz targets, generated in the fuzz mode
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub fuzz_targets: Vec<FuzzTarget>,
+}
+
+/// A generated test file
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TestFile {
+    /// Path of the file, relative to the workspace
+    pub path: String,
+
+    /// Its content
+    pub code: String,
+}
+
+/// A generated cargo-fuzz target
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FuzzTarget {
+    /// Name of the target, as passed to `cargo fuzz run`
+    pub name: String,
+
+    /// Source of the target
+    pub code: String,
+}
+
+impl FuzzTarget {
+    /// Path of the target's source, relative to the workspace
+    pub fn path(&self) -> String {
+        format!("fuzz/fuzz_targets/{}.rs", self.name)
+    }
+}
+
+/// The fuzz targets an LLM answers with
+#[derive(Debug, Deserialize)]
+struct FuzzTargets {
+    targets: Vec<FuzzTarget>,
+}
+
+impl FuzzTargets {
+    fn json_schema() -> serde_json::Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "targets": {
+                    "type": "array",
+                    "minItems": 1,
+                    "items": {
+                        "type": "object",
+                        "properties": {
+                            "name": {"type": "string", "pattern": "^[a-z][a-z0-9_]*$"},
+                            "code": {"type": "string", "minLength": 1}
+                        },
+                        "required": ["name", "code"],
+                        "additionalProperties": false
+                    }
+                }
+            },
+            "required": ["targets"],
+            "additionalProperties": false
+        })
+    }
 }
 
 /// A failing test with detailed information
@@ -39,12 +114,27 @@ pub struct FailingTest {
 /// Generates tests from specifications
 pub struct TestGenerator {
     llm: Arc<dyn LlmProvider>,
+    modes: Vec<TestGenerationMode>,
 }
 
 impl TestGenerator {
     /// Create a new test generator with the given LLM provider
     pub fn new(llm: Arc<dyn LlmProvider>) -> Self {
-        Self { llm }
+        Self {
+            llm,
+            modes: Vec::new(),
+        }
+    }
+
+    /// Also generate the given kinds of tests for the functions a spec names
+    pub fn with_modes(mut self, modes: &[TestGenerationMode]) -> Self {
+        self.modes = modes.to_vec();
+        self
+    }
+
+    /// Create a generator on the model routed to the coder role
+    pub fn from_router(router: &ModelRouter) -> Result<Self> {
+        Ok(Self::new(router.provider(ModelRole::Coder)?))
     }
 
     /// Generate tests for the given specification
@@ -59,92 +149,378 @@ impl TestGenerator {
 
         let response = self
             .llm
-            .generate(&prompt, None, Some(0.2))
+            .generate_with_format(
+                &prompt,
+                None,
+                Some(0.2),
+                Some(ResponseFormat::json_schema(
+                    "generated_tests".to_string(),
+                    GeneratedTests::json_schema(),
+                )),
+            )
             .await
             .context("Failed to generate tests from LLM")?;
 
-        self.parse_test_response(&response, context)
+        let mut tests = self.parse_test_response(&response, context)?;
+
+        if spec.functions.is_empty() && !self.modes.is_empty() {
+            info!("The specification names no functions; generating example tests only");
+            return Ok(tests);
+        }
+        if self.modes.contains(&TestGenerationMode::Property) {
+            let property = self.generate_property_tests(spec, context).await?;
+            info!(
+                "Generated {} property tests at {}",
+                property.test_names.len(),
+                property.test_file_path
+            );
+            tests.test_names.extend(property.test_names);
+            tests.property_tests = Some(TestFile {
+                path: property.test_file_path,
+                code: property.test_code,
+            });
+        }
+        if self.modes.contains(&TestGenerationMode::Fuzz) {
+            tests.fuzz_targets = self.generate_fuzz_targets(spec).await?;
+            info!("Generated {} fuzz targets", tests.fuzz_targets.len());
+        }
+        Ok(tests)
+    }
+
+    /// Generate proptest property tests for the functions of `spec`
+    async fn generate_property_tests(
+        &self,
+        spec: &Specification,
+        context: &CodeContext,
+    ) -> Result<GeneratedTests> {
+        let prompt = prompt::render(
+            "property_tests",
+            self.llm.model(),
+            &json!({
+                "description": spec.description,
+                "functions": spec.functions,
+                "test_examples": test_examples(context),
+            }),
+        );
+        let response = self
+            .llm
+            .generate_with_format(
+                &prompt,
+                None,
+                Some(0.2),
+                Some(ResponseFormat::json_schema(
+                    "property_tests".to_string(),
+                    GeneratedTests::json_schema(),
+                )),
+            )
+            .await
+            .context("Failed to generate property tests from LLM")?;
+        serde_json::from_str(&extract_json(&response)).context("Failed to parse property test JSON")
+    }
+
+    /// Generate cargo-fuzz targets for the functions of `spec`
+    async fn generate_fuzz_targets(&self, spec: &Specification) -> Result<Vec<FuzzTarget>> {
+        let prompt = prompt::render(
+            "fuzz_targets",
+            self.llm.model(),
+            &json!({
+                "description": spec.description,
+                "functions": spec.functions,
+            }),
+        );
+        let response = self
+            .llm
+            .generate_with_format(
+                &prompt,
+                None,
+                Some(0.2),
+                Some(ResponseFormat::json_schema(
+                    "fuzz_targets".to_string(),
+                    FuzzTargets::json_schema(),
+                )),
+            )
+            .await
+            .context("Failed to generate fuzz targets from LLM")?;
+        let targets: FuzzTargets = serde_json::from_str(&extract_json(&response))
+            .context("Failed to parse fuzz target JSON")?;
+        Ok(targets.targets)
     }
 
     /// Build the prompt for test generation
     fn build_test_prompt(&self, spec: &Specification, context: &CodeContext) -> String {
-        let mut prompt = String::new();
+        let file_changes: Vec<_> = spec
+            .file_changes
+            .iter()
+            .map(|change| {
+                json!({
+                    "path": change.path,
+                    "change_type": format!("{:?}", change.change_type).to_lowercase(),
+                    "description": change.description,
+                })
+            })
+            .collect();
+        prompt::render(
+            "tests",
+            self.llm.model(),
+            &json!({
+                "description": spec.description,
+                "expected_behaviors": spec.expected_behaviors,
+                "acceptance_criteria": spec.acceptance_criteria,
+                "file_changes": file_changes,
+                "test_examples": test_examples(context),
+            }),
+        )
+    }
 
-        prompt.push_str(
-            "You are a test engineer. Generate Rust tests for the following specification.\n",
-        );
-        prompt
-            .push_str("The tests should verify ALL acceptance criteria and expected behaviors.\n");
-        prompt.push_str("Tests should FAIL initially (red phase of TDD) since the implementation doesn't exist yet.\n\n");
+    /// Parse the LLM response into GeneratedTests
+    fn parse_test_response(
+        &self,
+        response: &str,
+        _context: &CodeContext,
+    ) -> Result<GeneratedTests> {
+        let json_str = extract_json(response);
+
+        serde_json::from_str(&json_str).context("Failed to parse test generation JSON")
+    }
+}
 
-        prompt.push_str("## Specification\n");
-        prompt.push_str(&format!("Description: {}\n\n", spec.description));
+/// Existing test patterns, limited to avoid huge prompts
+fn test_examples(context: &CodeContext) -> Vec<serde_json::Value> {
+    context
+        .test_contents
+        .iter()
+        .flatten()
+        .take(2)
+        .map(|(path, content)| json!({ "path": path, "content": content }))
+        .collect()
+}
 
-        prompt.push_str("### Expected Behaviors\n");
-        for behavior in &spec.expected_behaviors {
-            prompt.push_str(&format!("- {}\n", behavior));
+impl GeneratedTests {
+    /// JSON Schema the LLM's test generation output must follow
+    pub fn json_schema() -> serde_json::Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "test_file_path": {"type": "string", "minLength": 1},
+                "test_code": {"type": "string", "minLength": 1},
+                "test_names": {"type": "array", "items": {"type": "string"}, "minItems": 1}
+            },
+            "required": ["test_file_path", "test_code", "test_names"],
+            "additionalProperties": false
+        })
+    }
+
+    /// Distinct test names that are actually defined as functions in the
+    /// test code or the property tests
+    pub fn defined_test_names(&self) -> Vec<&str> {
+        let mut names: Vec<&str> = Vec::new();
+        for name in &self.test_names {
+            let name = name.trim();
+            if name.is_empty() || names.contains(&name) {
+                continue;
+            }
+            let definition = format!("fn {}(", name);
+            if self.test_code.contains(&definition)
+                || self
+                    .property_tests
+                    .as_ref()
+                    .is_some_and(|file| file.code.contains(&definition))
+            {
+                names.push(name);
+            }
         }
-        prompt.push('\n');
+        names
+    }
 
-        prompt.push_str("### Acceptance Criteria\n");
-        for criterion in &spec.acceptance_criteria {
-            prompt.push_str(&format!("- {}\n", criterion));
+    /// Write the tests to `workspace`, adding the `proptest` dev-dependency
+    /// and the cargo-fuzz manifest they need; returns the written paths
+    pub fn write_to(&self, workspace: &Path) -> Result<Vec<PathBuf>> {
+        let mut files = vec![(self.test_file_path.clone(), self.test_code.clone())];
+        if let Some(property) = &self.property_tests {
+            files.push((property.path.clone(), property.code.clone()));
         }
-        prompt.push('\n');
-
-        prompt.push_str("### Files Being Changed\n");
-        for file_change in &spec.file_changes {
-            prompt.push_str(&format!(
-                "- {} ({}): {}\n",
-                file_change.path,
-                format!("{:?}", file_change.change_type).to_lowercase(),
-                file_change.description
-            ));
+        files.extend(
+            self.fuzz_targets
+                .iter()
+                .map(|target| (target.path(), target.code.clone())),
+        );
+
+        let manifest_path = workspace.join("Cargo.toml");
+        let manifest = std::fs::read_to_string(&manifest_path).ok();
+        match &manifest {
+            Some(manifest) if self.property_tests.is_some() => {
+                let updated = with_dependency(manifest, "dev-dependencies", "proptest", "\"1\"");
+                if updated != *manifest {
+                    files.push(("Cargo.toml".to_string(), updated));
+                }
+            }
+            None if self.property_tests.is_some() => {
+                warn!("No Cargo.toml in {:?}; not adding proptest", workspace)
+            }
+            _ => {}
         }
-        prompt.push('\n');
-
-        // Include existing test patterns if available
-        if let Some(ref test_contents) = context.test_contents {
-            if !test_contents.is_empty() {
-                prompt.push_str("### Existing Test Patterns (follow these patterns)\n");
-                for (path, content) in test_contents.iter().take(2) {
-                    // Limit to avoid huge prompts
-                    prompt.push_str(&format!("```rust\n// From {}\n{}\n```\n\n", path, content));
+        if !self.fuzz_targets.is_empty() {
+            match manifest.as_deref().and_then(package_name) {
+                Some(package) => {
+                    let existing = std::fs::read_to_string(workspace.join("fuzz/Cargo.toml")).ok();
+                    files.push((
+                        "fuzz/Cargo.toml".to_string(),
+                        fuzz_manifest(existing.as_deref(), &package, &self.fuzz_targets),
+                    ));
                 }
+                None => warn!(
+                    "No package manifest in {:?}; fuzz targets are written without fuzz/Cargo.toml",
+                    workspace
+                ),
             }
         }
 
-        prompt.push_str("## Output Format\n");
-        prompt.push_str("Respond with a JSON object containing:\n");
-        prompt.push_str("- test_file_path: Path where the test should be written (e.g., \"tests/feature_test.rs\" or \"src/module/tests.rs\")\n");
-        prompt.push_str("- test_code: Complete Rust test code\n");
-        prompt.push_str("- test_names: Array of test function names\n\n");
+        let mut written = Vec::new();
+        for (path, content) in files {
+            let path = workspace.join(path);
+            if let Some(parent) = path.parent() {
+                std::fs::create_dir_all(parent)
+                    .context(format!("Failed to create test directory: {:?}", parent))?;
+            }
+            std::fs::write(&path, content)
+                .context(format!("Failed to write test file: {:?}", path))?;
+            written.push(path);
+        }
+        Ok(written)
+    }
+}
 
-        prompt.push_str("Requirements:\n");
-        prompt.push_str("- Use #[test] attribute for each test\n");
-        prompt.push_str("- Include necessary imports\n");
-        prompt.push_str("- Each acceptance criterion should have at least one test\n");
-        prompt.push_str("- Tests should be clear and focused\n\n");
+/// Name of the package a Cargo manifest declares
+pub fn package_name(manifest: &str) -> Option<String> {
+    let table: toml::Table = toml::from_str(manifest).ok()?;
+    table
+        .get("package")?
+        .get("name")?
+        .as_str()
+        .map(str::to_string)
+}
 
-        prompt.push_str("```json\n");
+/// `manifest` with `name = requirement` added to `section`, unchanged when
+/// it already depends on `name`; `requirement` is a TOML value
+pub fn with_dependency(manifest: &str, section: &str, name: &str, requirement: &str) -> String {
+    let table: toml::Table = toml::from_str(manifest).unwrap_or_default();
+    let depends = ["dependencies", "dev-dependencies"]
+        .iter()
+        .any(|s| table.get(*s).and_then(|deps| deps.get(name)).is_some());
+    if depends {
+        return manifest.to_string();
+    }
+    let entry = format!("{} = {}\n", name, requirement);
+    let header = Regex::new(&format!(r"(?m)^\[{}\][ \t]*\r?\n", regex::escape(section))).unwrap();
+    match header.find(manifest) {
+        Some(m) => format!("{}{}{}", &manifest[..m.end()], entry, &manifest[m.end()..]),
+        None => {
+            let separator = if manifest.ends_with('\n') { "" } else { "\n" };
+            format!("{}{}\n[{}]\n{}", manifest, separator, section, entry)
+        }
+    }
+}
 
-        prompt
+/// The cargo-fuzz manifest for `package`: `existing`, or a new one, with a
+/// `[[bin]]` for each of `targets` it lacks
+pub fn fuzz_manifest(existing: Option<&str>, package: &str, targets: &[FuzzTarget]) -> String {
+    let mut manifest = match existing {
+        Some(existing) => with_dependency(
+            existing,
+            "dependencies",
+            "arbitrary",
+            r#"{ version = "1", features = ["derive"] }"#,
+        ),
+        None => format!(
+            r#"[package]
+name = "{package}-fuzz"
+version = "0.0.0"
+publish = false
+edition = "2021"
+
+[package.metadata]
+cargo-fuzz = true
+
+[dependencies]
+libfuzzer-sys = "0.4"
+arbitrary = {{ version = "1", features = ["derive"] }}
+
+[dependencies.{package}]
+path = ".."
+
+# Keep the fuzz crate out of the parent workspace
+[workspace]
+members = ["."]
+"#
+        ),
+    };
+    for target in targets {
+        let path = format!("fuzz_targets/{}.rs", target.name);
+        if manifest.contains(&format!("path = \"{}\"", path)) {
+            continue;
+        }
+        manifest.push_str(&format!(
+            "\n[[bin]]\nname = \"{}\"\npath = \"{}\"\ntest = false\ndoc = false\nbench = false\n",
+            target.name, path
+        ));
     }
+    manifest
+}
 
-    /// Parse the LLM response into GeneratedTests
-    fn parse_test_response(
-        &self,
-        response: &str,
-        _context: &CodeContext,
-    ) -> Result<GeneratedTests> {
-        let json_str = extract_json(response);
+/// Check that generated tests are substantial enough to make "green" meaningful.
+///
+/// Acceptance coverage is approximated as the number of defined tests per
+/// acceptance criterion, capped at 1.0.
+pub fn check_tdd_gate(
+    spec: &Specification,
+    tests: &GeneratedTests,
+    gate: &TddGateConfig,
+) -> Result<()> {
+    let test_count = tests.defined_test_names().len();
+
+    if test_count < gate.min_generated_tests {
+        warn!(
+            "TDD gate failed: {} generated tests, {} required",
+            test_count, gate.min_generated_tests
+        );
+        bail!(
+            "Generated {} test(s) but at least {} are required before implementation",
+            test_count,
+            gate.min_generated_tests
+        );
+    }
 
-        serde_json::from_str(&json_str).context("Failed to parse test generation JSON")
+    if let Some(min_coverage) = gate.min_acceptance_coverage {
+        let criteria = spec.acceptance_criteria.len();
+        let coverage = if criteria == 0 {
+            1.0
+        } else {
+            (test_count as f64 / criteria as f64).min(1.0)
+        };
+
+        if coverage < min_coverage {
+            warn!(
+                "TDD gate failed: acceptance coverage {:.2} below {:.2}",
+                coverage, min_coverage
+            );
+            bail!(
+                "Generated tests cover {:.0}% of {} acceptance criteria, {:.0}% required",
+                coverage * 100.0,
+                criteria,
+                min_coverage * 100.0
+            );
+        }
     }
+
+    Ok(())
 }
 
 /// Parse test output to identify failing tests
 pub fn parse_test_failures(test_output: &str) -> Vec<FailingTest> {
+    if let Some(run) = libtest::parse(test_output) {
+        return run.failures().iter().map(failing_test).collect();
+    }
+
     let mut failures = Vec::new();
     let mut current_test: Option<String> = None;
     let mut current_error = String::new();
@@ -174,13 +550,9 @@ pub fn parse_test_failures(test_output: &str) -> Vec<FailingTest> {
             current_error = String::new();
         }
         // Collect error message lines
-        else if current_test.is_some() {
-            let is_panic = line.starts_with("thread '") && line.contains("panicked at");
-            let is_assertion = line.contains("assertion") || line.contains("expected");
-            if is_panic || is_assertion {
-                current_error.push_str(line);
-                current_error.push('\n');
-            }
+        else if current_test.is_some() && is_error_line(line) {
+            current_error.push_str(line);
+            current_error.push('\n');
         }
     }
 
@@ -199,6 +571,33 @@ pub fn parse_test_failures(test_output: &str) -> Vec<FailingTest> {
     failures
 }
 
+/// The failing test reported by a structured test run, its error being the
+/// panic and assertion lines of its output
+pub fn failing_test(failure: &TestFailure) -> FailingTest {
+    let error: Vec<&str> = failure
+        .output
+        .lines()
+        .filter(|line| is_error_line(line))
+        .collect();
+    FailingTest {
+        name: failure.test_name.clone(),
+        error_message: if error.is_empty() {
+            failure.output.trim().to_string()
+        } else {
+            error.join("\n")
+        },
+        expected: failure.expected.clone(),
+        actual: failure.actual.clone(),
+    }
+}
+
+/// Whether a line of a failed test's output says why it failed
+fn is_error_line(line: &str) -> bool {
+    let is_panic = line.starts_with("thread '") && line.contains("panicked at");
+    let is_assertion = line.contains("assertion") || line.contains("expected");
+    is_panic || is_assertion
+}
+
 /// Extract JSON from a response that might be wrapped in markdown code blocks
 fn extract_json(response: &str) -> String {
     // Try to find JSON in code blocks first
@@ -230,6 +629,9 @@ fn extract_json(response: &str) -> String {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::code_generation::spec_generator::{AcceptanceCriterion, SpecFunction};
+    use async_trait::async_trait;
+    use std::sync::Mutex;
 
     #[test]
     fn test_parse_test_failures() {
@@ -255,16 +657,261 @@ failures:
         assert!(failures[0].error_message.contains("assertion failed"));
     }
 
+    #[test]
+    fn test_parse_test_failures_from_json_events() {
+        let output = concat!(
+            r#"{ "type": "suite", "event": "started", "test_count": 2 }"#,
+            "\n",
+            r#"{ "type": "test", "name": "test_add", "event": "ok", "exec_time": 0.001 }"#,
+            "\n",
+            r#"{ "type": "test", "name": "test_subtract", "event": "failed", "stdout": "\nthread 'test_subtract' panicked at src/lib.rs:10:9:\nassertion `left == right` failed\n  left: 5\n right: 3\nstack backtrace:\n   0: rust_begin_unwind\n" }"#,
+            "\n",
+        );
+
+        let failures = parse_test_failures(output);
+        assert_eq!(failures.len(), 1);
+        assert_eq!(failures[0].name, "test_subtract");
+        assert_eq!(
+            failures[0].error_message,
+            "thread 'test_subtract' panicked at src/lib.rs:10:9:\nassertion `left == right` failed"
+        );
+        assert_eq!(failures[0].expected.as_deref(), Some("3"));
+        assert_eq!(failures[0].actual.as_deref(), Some("5"));
+    }
+
+    fn spec_with_criteria(count: usize) -> Specification {
+        Specification {
+            description: "Add a parser".to_string(),
+            file_changes: vec![],
+            functions: vec![],
+            expected_behaviors: vec![],
+            acceptance_criteria: (0..count)
+                .map(|i| AcceptanceCriterion::new(format!("criterion {}", i)))
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn test_tdd_gate_rejects_too_few_tests() {
+        let spec = spec_with_criteria(3);
+        let tests = GeneratedTests {
+            test_file_path: "tests/parser_test.rs".to_string(),
+            test_code: "#[test]\nfn test_parses() { assert!(true); }".to_string(),
+            // A second name the code never defines does not count
+            test_names: vec!["test_parses".to_string(), "test_missing".to_string()],
+            property_tests: None,
+            fuzz_targets: Vec::new(),
+        };
+
+        let err = check_tdd_gate(&spec, &tests, &TddGateConfig::default()).unwrap_err();
+        assert!(err.to_string().contains("at least 2"));
+    }
+
+    #[test]
+    fn test_tdd_gate_checks_acceptance_coverage() {
+        let spec = spec_with_criteria(4);
+        let tests = GeneratedTests {
+            test_file_path: "tests/parser_test.rs".to_string(),
+            test_code: "#[test]\nfn test_a() {}\n#[test]\nfn test_b() {}".to_string(),
+            test_names: vec!["test_a".to_string(), "test_b".to_string()],
+            property_tests: None,
+            fuzz_targets: Vec::new(),
+        };
+        let gate = TddGateConfig {
+            min_generated_tests: 2,
+            min_acceptance_coverage: Some(0.75),
+            ..TddGateConfig::default()
+        };
+
+        assert!(check_tdd_gate(&spec, &tests, &TddGateConfig::default()).is_ok());
+        assert!(check_tdd_gate(&spec, &tests, &gate).is_err());
+    }
+
     #[test]
     fn test_generated_tests_serialization() {
         let tests = GeneratedTests {
             test_file_path: "tests/my_test.rs".to_string(),
             test_code: "#[test]\nfn test_something() {}".to_string(),
             test_names: vec!["test_something".to_string()],
+            property_tests: None,
+            fuzz_targets: Vec::new(),
         };
 
         let json = serde_json::to_string(&tests).unwrap();
         let parsed: GeneratedTests = serde_json::from_str(&json).unwrap();
         assert_eq!(parsed.test_file_path, "tests/my_test.rs");
     }
+
+    /// LLM answering each response format with its canned JSON
+    struct ScriptedLlm {
+        formats: Mutex<Vec<String>>,
+    }
+
+    #[async_trait]
+    impl LlmProvider for ScriptedLlm {
+        async fn generate(
+            &self,
+            _prompt: &str,
+            _max_tokens: Option<usize>,
+            _temperature: Option<f32>,
+        ) -> Result<String> {
+            unreachable!("tests are generated with a response format")
+        }
+
+        async fn generate_with_format(
+            &self,
+            _prompt: &str,
+            _max_tokens: Option<usize>,
+            _temperature: Option<f32>,
+            response_format: Option<ResponseFormat>,
+        ) -> Result<String> {
+            let Some(ResponseFormat::JsonSchema { json_schema }) = response_format else {
+                panic!("expected a JSON schema");
+            };
+            self.formats.lock().unwrap().push(json_schema.name.clone());
+            Ok(match json_schema.name.as_str() {
+                "generated_tests" => json!({
+                    "test_file_path": "tests/parse.rs",
+                    "test_code": "#[test]\nfn test_parses() {}",
+                    "test_names": ["test_parses"]
+                }),
+                "property_tests" => json!({
+                    "test_file_path": "tests/parse_properties.rs",
+                    "test_code": "proptest! {\n    #[test]\n    fn roundtrips(s in \".*\") {}\n}",
+                    "test_names": ["roundtrips"]
+                }),
+                _ => json!({"targets": [{"name": "parse", "code": "#![no_main]\n"}]}),
+            }
+            .to_string())
+        }
+
+        async fn generate_streaming(
+            &self,
+            prompt: &str,
+            max_tokens: Option<usize>,
+            temperature: Option<f32>,
+            _print_tokens: bool,
+        ) -> Result<String> {
+            self.generate(prompt, max_tokens, temperature).await
+        }
+    }
+
+    fn context() -> CodeContext {
+        CodeContext {
+            task: "Parse input".to_string(),
+            file_paths: Vec::new(),
+            requirements: None,
+            previous_attempts: Vec::new(),
+            file_contents: None,
+            test_files: None,
+            test_contents: None,
+            dependencies: None,
+            code_structure: None,
+            max_attempts: None,
+            current_attempt: None,
+            specification: None,
+            generated_tests: None,
+            failing_tests: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_modes_add_property_tests_and_fuzz_targets() {
+        let llm = Arc::new(ScriptedLlm {
+            formats: Mutex::new(Vec::new()),
+        });
+        let generator = TestGenerator::new(llm.clone())
+            .with_modes(&[TestGenerationMode::Property, TestGenerationMode::Fuzz]);
+
+        // Without functions to target only example tests are generated
+        let mut spec = spec_with_criteria(1);
+        let tests = generator.generate_tests(&spec, &context()).await.unwrap();
+        assert!(tests.property_tests.is_none() && tests.fuzz_targets.is_empty());
+        assert_eq!(*llm.formats.lock().unwrap(), vec!["generated_tests"]);
+
+        spec.functions.push(SpecFunction {
+            path: "crate::parse".to_string(),
+            signature: "fn parse(s: &str) -> Ast".to_string(),
+            properties: vec!["never panics".to_string()],
+        });
+        let tests = generator.generate_tests(&spec, &context()).await.unwrap();
+        assert_eq!(
+            tests.defined_test_names(),
+            vec!["test_parses", "roundtrips"]
+        );
+        assert_eq!(
+            tests.property_tests.as_ref().unwrap().path,
+            "tests/parse_properties.rs"
+        );
+        assert_eq!(tests.fuzz_targets[0].path(), "fuzz/fuzz_targets/parse.rs");
+    }
+
+    #[test]
+    fn test_dependencies_are_added_once() {
+        let manifest = "[package]\nname = \"demo\"\n\n[dev-dependencies]\ntempfile = \"3\"\n";
+        let updated = with_dependency(manifest, "dev-dependencies", "proptest", "\"1\"");
+        assert_eq!(
+            updated,
+            "[package]\nname = \"demo\"\n\n[dev-dependencies]\nproptest = \"1\"\ntempfile = \"3\"\n"
+        );
+        assert_eq!(
+            with_dependency(&updated, "dev-dependencies", "proptest", "\"1\""),
+            updated
+        );
+        assert_eq!(
+            with_dependency(
+                "[package]\nname = \"demo\"",
+                "dev-dependencies",
+                "proptest",
+                "\"1\""
+            ),
+            "[package]\nname = \"demo\"\n\n[dev-dependencies]\nproptest = \"1\"\n"
+        );
+        assert_eq!(package_name(manifest).as_deref(), Some("demo"));
+    }
+
+    #[test]
+    fn test_tests_are_written_with_their_manifests() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(
+            dir.path().join("Cargo.toml"),
+            "[package]\nname = \"demo\"\nversion = \"0.1.0\"\n",
+        )
+        .unwrap();
+        let target = |name: &str| FuzzTarget {
+            name: name.to_string(),
+            code: "#![no_main]\n".to_string(),
+        };
+        let mut tests = GeneratedTests {
+            test_file_path: "tests/parse.rs".to_string(),
+            test_code: "#[test]\nfn test_parses() {}".to_string(),
+            test_names: vec!["test_parses".to_string()],
+            property_tests: Some(TestFile {
+                path: "tests/parse_properties.rs".to_string(),
+                code: "proptest! {}".to_string(),
+            }),
+            fuzz_targets: vec![target("parse")],
+        };
+        assert_eq!(tests.write_to(dir.path()).unwrap().len(), 5);
+
+        let read = |path: &str| std::fs::read_to_string(dir.path().join(path)).unwrap();
+        assert_eq!(read("tests/parse_properties.rs"), "proptest! {}");
+        assert!(read("Cargo.toml").ends_with("\n[dev-dependencies]\nproptest = \"1\"\n"));
+        let fuzz: toml::Table = toml::from_str(&read("fuzz/Cargo.toml")).unwrap();
+        assert_eq!(fuzz["package"]["name"].as_str(), Some("demo-fuzz"));
+        assert_eq!(fuzz["dependencies"]["demo"]["path"].as_str(), Some(".."));
+
+        // A later run adds its targets to the existing fuzz manifest
+        tests.property_tests = None;
+        tests.fuzz_targets = vec![target("parse"), target("render")];
+        tests.write_to(dir.path()).unwrap();
+        let fuzz: toml::Table = toml::from_str(&read("fuzz/Cargo.toml")).unwrap();
+        let bins: Vec<&str> = fuzz["bin"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|bin| bin["name"].as_str().unwrap())
+            .collect();
+        assert_eq!(bins, vec!["parse", "render"]);
+    }
 }
@@ -0,0 +1,57 @@
+//! Fakes shared by the tests of the code generation modules.
+
+use async_trait::async_trait;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::core::error::ProviderError;
+use crate::providers::{GenerateRequest, GenerateResponse, Provider, StreamEvent};
+
+/// Embeds text as counts of a few words, counting embedded inputs
+pub(crate) struct WordEmbedder {
+    words: &'static [&'static str],
+    inputs: AtomicUsize,
+}
+
+impl WordEmbedder {
+    /// Embedder with one dimension per word of `words`
+    pub(crate) fn new(words: &'static [&'static str]) -> Self {
+        Self {
+            words,
+            inputs: AtomicUsize::new(0),
+        }
+    }
+
+    /// Number of texts embedded so far
+    pub(crate) fn inputs(&self) -> usize {
+        self.inputs.load(Ordering::SeqCst)
+    }
+}
+
+#[async_trait]
+impl Provider for WordEmbedder {
+    async fn generate(&self, _req: GenerateRequest) -> Result<GenerateResponse, ProviderError> {
+        unimplemented!()
+    }
+
+    async fn generate_streaming(
+        &self,
+        _req: GenerateRequest,
+        _on_event: &mut (dyn FnMut(StreamEvent) + Send),
+    ) -> Result<GenerateResponse, ProviderError> {
+        unimplemented!()
+    }
+
+    async fn embed(&self, inputs: &[String]) -> Result<Vec<Vec<f32>>, ProviderError> {
+        self.inputs.fetch_add(inputs.len(), Ordering::SeqCst);
+        Ok(inputs
+            .iter()
+            .map(|text| {
+                let text = text.to_lowercase();
+                self.words
+                    .iter()
+                    .map(|word| text.matches(word).count() as f32 + 0.01)
+                    .collect()
+            })
+            .collect())
+    }
+}
@@ -0,0 +1,388 @@
+//! Todo lists kept by the `TodoWrite` and `TodoRead` tools.
+//!
+//! Each goal has one list, stored in the `todos` collection under the goal
+//! id of the enclosing provider metadata scope (or `default` outside of a
+//! goal). Because the list outlives the attempt that wrote it, a retry can
+//! show the model the items it left unfinished and let it pick up from
+//! there instead of planning the work again.
+
+use anyhow::{anyhow, Context, Result};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+use crate::code_generation::llm_tool::{
+    str_arg, LlmTool, ToolArgs, ToolParameter, ToolParameterType,
+};
+use crate::database::{DatabaseError, DatabaseInterface};
+use crate::providers::metadata::{self, GOAL_ID_HEADER};
+
+/// Key of the list kept outside of any goal
+const DEFAULT_LIST: &str = "default";
+
+/// Progress of a todo item
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TodoStatus {
+    /// Not started
+    #[default]
+    Pending,
+    /// Being worked on
+    InProgress,
+    /// Done
+    Completed,
+}
+
+impl TodoStatus {
+    fn icon(self) -> &'static str {
+        match self {
+            TodoStatus::Completed => "✓",
+            TodoStatus::InProgress => "→",
+            TodoStatus::Pending => "○",
+        }
+    }
+}
+
+/// One item of a todo list
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TodoItem {
+    /// What has to be done
+    pub content: String,
+
+    /// How far along it is
+    #[serde(default)]
+    pub status: TodoStatus,
+
+    /// Present-tense form shown while the item is in progress
+    #[serde(default, rename = "activeForm", alias = "active_form")]
+    pub active_form: Option<String>,
+}
+
+/// The todo list of one goal
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TodoList {
+    /// Goal id, or `default`
+    pub id: String,
+
+    /// The items in the order the model wrote them
+    pub items: Vec<TodoItem>,
+
+    /// When the list was last written
+    pub updated_at: DateTime<Utc>,
+}
+
+impl TodoList {
+    /// Number of completed items
+    pub fn completed(&self) -> usize {
+        self.items
+            .iter()
+            .filter(|item| item.status == TodoStatus::Completed)
+            .count()
+    }
+
+    /// Items that are not completed yet
+    pub fn unfinished(&self) -> impl Iterator<Item = &TodoItem> {
+        self.items
+            .iter()
+            .filter(|item| item.status != TodoStatus::Completed)
+    }
+
+    /// One-line progress summary, e.g. `2/5 completed, working on: Add tests`
+    pub fn progress(&self) -> String {
+        let mut line = format!("{}/{} completed", self.completed(), self.items.len());
+        if let Some(item) = self
+            .items
+            .iter()
+            .find(|item| item.status == TodoStatus::InProgress)
+        {
+            line.push_str(&format!(
+                ", working on: {}",
+                item.active_form.as_deref().unwrap_or(&item.content)
+            ));
+        }
+        line
+    }
+
+    /// The list with one numbered line per item
+    pub fn render(&self) -> String {
+        if self.items.is_empty() {
+            return "Todo list is empty".to_string();
+        }
+        let mut out = String::from("Todo List:\n");
+        for (idx, item) in self.items.iter().enumerate() {
+            out.push_str(&format!(
+                "{}. {} {}\n",
+                idx + 1,
+                item.status.icon(),
+                item.content
+            ));
+        }
+        out.push_str(&format!("Progress: {}\n", self.progress()));
+        out
+    }
+}
+
+/// Key of the list for the enclosing metadata scope
+fn current_key() -> String {
+    metadata::current()
+        .get(GOAL_ID_HEADER)
+        .cloned()
+        .unwrap_or_else(|| DEFAULT_LIST.to_string())
+}
+
+/// Persists the todo list of each goal
+pub struct TodoStore {
+    store: Arc<dyn DatabaseInterface<TodoList>>,
+}
+
+impl TodoStore {
+    /// Todo lists kept in `store`
+    pub fn new(store: Arc<dyn DatabaseInterface<TodoList>>) -> Self {
+        Self { store }
+    }
+
+    /// The list of `goal_id`, if one was written
+    pub async fn get(&self, goal_id: &str) -> Result<Option<TodoList>> {
+        match self.store.get(&goal_id.to_string()).await {
+            Ok(record) => Ok(Some(record.entity)),
+            Err(DatabaseError::NotFound(_)) => Ok(None),
+            Err(e) => Err(e).context("Failed to load todo list"),
+        }
+    }
+
+    /// Replace the list of `goal_id` with `items`
+    pub async fn put(&self, goal_id: &str, items: Vec<TodoItem>) -> Result<TodoList> {
+        let list = TodoList {
+            id: goal_id.to_string(),
+            items,
+            updated_at: Utc::now(),
+        };
+        let saved = match self.store.update(list.clone(), None).await {
+            Err(DatabaseError::NotFound(_)) => self.store.insert(list).await,
+            saved => saved,
+        };
+        Ok(saved.context("Failed to save todo list")?.entity)
+    }
+
+    /// Every stored list, the most recently updated first
+    pub async fn all(&self) -> Result<Vec<TodoList>> {
+        let mut lists: Vec<TodoList> = self
+            .store
+            .get_all()
+            .await
+            .context("Failed to load todo lists")?
+            .into_iter()
+            .map(|record| record.entity)
+            .collect();
+        lists.sort_by_key(|list| std::cmp::Reverse(list.updated_at));
+        Ok(lists)
+    }
+}
+
+/// The todo store of the current services, if there is one
+pub fn global() -> Option<Arc<TodoStore>> {
+    crate::core::services::Services::current().todos.clone()
+}
+
+/// Prompt section listing the unfinished todos of the current goal, empty
+/// outside of a goal, when there are none or when no store is installed
+///
+/// A new attempt has not written its own list yet, so whatever is stored
+/// was left by an earlier attempt at the same goal.
+pub async fn resume_section() -> String {
+    let (Some(store), Some(goal_id)) = (global(), metadata::current().get(GOAL_ID_HEADER).cloned())
+    else {
+        return String::new();
+    };
+    let list = match store.get(&goal_id).await {
+        Ok(Some(list)) => list,
+        Ok(None) => return String::new(),
+        Err(e) => {
+            warn!("Failed to load todo list: {:#}", e);
+            return String::new();
+        }
+    };
+    let unfinished: Vec<&TodoItem> = list.unfinished().collect();
+    if unfinished.is_empty() {
+        return String::new();
+    }
+    let mut s = format!(
+        "## Unfinished todos from the previous attempt ({}):\n",
+        list.progress()
+    );
+    for item in unfinished {
+        s.push_str(&format!("- {} {}\n", item.status.icon(), item.content));
+    }
+    s.push_str("Resume from these items instead of starting over.\n\n");
+    s
+}
+
+/// A tool that records the todo list of the current goal
+pub struct TodoWriteTool;
+
+impl TodoWriteTool {
+    /// Create a new TodoWrite tool
+    pub fn new() -> Self {
+        Self
+    }
+}
+
+impl Default for TodoWriteTool {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[async_trait]
+impl LlmTool for TodoWriteTool {
+    fn name(&self) -> &str {
+        "TodoWrite"
+    }
+
+    fn description(&self) -> &str {
+        "Track and display todo list progress. Replaces the todo list of the current goal with the given items; the list is kept across attempts."
+    }
+
+    fn parameters(&self) -> Vec<ToolParameter> {
+        vec![ToolParameter {
+            name: "todos".to_string(),
+            description: "JSON string containing array of todo items. Each item has: content (String), status (String: pending/in_progress/completed), activeForm (String)".to_string(),
+            required: true,
+            default_value: None,
+            param_type: Some(ToolParameterType::String),
+        }]
+    }
+
+    async fn execute(&self, args: &ToolArgs) -> Result<String> {
+        // Accepts the list itself or its JSON encoding
+        let todos_json =
+            str_arg(args, "todos").ok_or_else(|| anyhow!("todos parameter is required"))?;
+        let items: Vec<TodoItem> = serde_json::from_str(&todos_json)
+            .map_err(|e| anyhow!("Failed to parse todos JSON: {}", e))?;
+
+        let key = current_key();
+        let list = match global() {
+            Some(store) => store.put(&key, items).await?,
+            None => TodoList {
+                id: key,
+                items,
+                updated_at: Utc::now(),
+            },
+        };
+        if !list.items.is_empty() {
+            info!("Todos for goal {}: {}", list.id, list.progress());
+        }
+        Ok(list.render())
+    }
+}
+
+/// A tool that shows the todo list of the current goal
+pub struct TodoReadTool;
+
+impl TodoReadTool {
+    /// Create a new TodoRead tool
+    pub fn new() -> Self {
+        Self
+    }
+}
+
+impl Default for TodoReadTool {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[async_trait]
+impl LlmTool for TodoReadTool {
+    fn name(&self) -> &str {
+        "TodoRead"
+    }
+
+    fn description(&self) -> &str {
+        "Show the todo list of the current goal, including items written in earlier attempts."
+    }
+
+    fn parameters(&self) -> Vec<ToolParameter> {
+        Vec::new()
+    }
+
+    fn is_read_only(&self) -> bool {
+        true
+    }
+
+    async fn execute(&self, _args: &ToolArgs) -> Result<String> {
+        let store = global().ok_or_else(|| anyhow!("No todo store is available"))?;
+        Ok(match store.get(&current_key()).await? {
+            Some(list) => list.render(),
+            None => "Todo list is empty".to_string(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::services::Services;
+    use crate::database::FileDb;
+    use serde_json::json;
+
+    fn args(value: serde_json::Value) -> ToolArgs {
+        value.as_object().unwrap().clone()
+    }
+
+    #[tokio::test]
+    async fn test_todos_persist_per_goal() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = FileDb::<TodoList>::new(dir.path(), "todos").await.unwrap();
+        // The store is this test's own; other tests see none
+        let services = Arc::new(Services {
+            todos: Some(Arc::new(TodoStore::new(Arc::new(db)))),
+            ..Default::default()
+        });
+        services
+            .scope(async {
+
+        let todos = json!([
+            {"content": "Read parser", "status": "completed", "activeForm": "Reading parser"},
+            {"content": "Fix overflow", "status": "in_progress", "activeForm": "Fixing overflow"},
+            {"content": "Add tests", "status": "pending"}
+        ]);
+        let out = metadata::scope(metadata::attribution(Some("goal-7"), "code:gpt"), async {
+            TodoWriteTool::new()
+                .execute(&args(json!({ "todos": todos })))
+                .await
+                .unwrap()
+        })
+        .await;
+        assert!(out.contains("2. → Fix overflow"), "{}", out);
+        assert!(out.contains("1/3 completed, working on: Fixing overflow"));
+
+        // Another goal has its own, empty list
+        let other = metadata::scope(metadata::attribution(Some("goal-8"), "code:gpt"), async {
+            TodoReadTool::new().execute(&args(json!({}))).await.unwrap()
+        })
+        .await;
+        assert_eq!(other, "Todo list is empty");
+
+        // A retry of the goal sees what was left unfinished
+        let reopened = TodoStore::new(Arc::new(
+            FileDb::<TodoList>::new(dir.path(), "todos").await.unwrap(),
+        ));
+        let list = reopened.get("goal-7").await.unwrap().unwrap();
+        assert_eq!(list.items[1].status, TodoStatus::InProgress);
+        let section = metadata::scope(metadata::attribution(Some("goal-7"), "code:gpt"), async {
+            resume_section().await
+        })
+        .await;
+        assert!(
+            section.contains("- → Fix overflow\n- ○ Add tests\n"),
+            "{}",
+            section
+        );
+        assert!(!section.contains("Read parser"));
+            })
+            .await;
+    }
+}
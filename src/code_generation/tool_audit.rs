@@ -0,0 +1,311 @@
+//! Audit log and metrics of tool invocations.
+//!
+//! `ToolRegistry` records every call it executes: the tool, a hash of its
+//! arguments, how long it took, whether it succeeded, how much output it
+//! produced and the goal and agent it ran for (from the provider metadata
+//! scope). Records are kept in the `tool_invocations` collection, and
+//! `ToolMetrics` aggregates them per tool so it is visible which tools
+//! dominate iteration time and which fail most often.
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use log::warn;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::code_generation::llm_tool::ToolResult;
+use crate::database::{DatabaseInterface, FilterOp, Query, SortOrder};
+use crate::providers::metadata::{self, AGENT_HEADER, GOAL_ID_HEADER};
+
+/// One executed tool call
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ToolInvocation {
+    /// Record key
+    pub id: String,
+
+    /// When the call finished
+    pub timestamp: DateTime<Utc>,
+
+    /// Tool name
+    pub tool: String,
+
+    /// SHA-256 of the arguments, so repeated calls can be spotted without
+    /// storing file contents or commands
+    pub args_hash: String,
+
+    /// Wall-clock time of the call
+    pub duration_ms: u64,
+
+    /// Whether the tool succeeded
+    pub success: bool,
+
+    /// Size of the result, or of the error for failed calls
+    pub output_bytes: u64,
+
+    /// Goal the call was made for, if known
+    #[serde(default)]
+    pub goal_id: Option<String>,
+
+    /// Agent (phase and model) that made the call, if known
+    #[serde(default)]
+    pub agent: Option<String>,
+}
+
+impl ToolInvocation {
+    /// Invocation of `tool` with `arguments` that produced `result`,
+    /// attributed to the enclosing metadata scope
+    pub fn new(
+        tool: &str,
+        arguments: &serde_json::Value,
+        duration: Duration,
+        result: &ToolResult,
+    ) -> Self {
+        let scope = metadata::current();
+        let output_bytes = if result.success {
+            result.result.len()
+        } else {
+            result.error.as_ref().map_or(0, String::len)
+        };
+        Self {
+            id: uuid::Uuid::new_v4().to_string(),
+            timestamp: Utc::now(),
+            tool: tool.to_string(),
+            args_hash: args_hash(arguments),
+            duration_ms: duration.as_millis() as u64,
+            success: result.success,
+            output_bytes: output_bytes as u64,
+            goal_id: scope.get(GOAL_ID_HEADER).cloned(),
+            agent: scope.get(AGENT_HEADER).cloned(),
+        }
+    }
+}
+
+/// Hex SHA-256 of `arguments` with object keys sorted
+pub fn args_hash(arguments: &serde_json::Value) -> String {
+    Sha256::digest(arguments.to_string().as_bytes())
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+/// Aggregate of the recorded calls of one tool
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct ToolMetrics {
+    /// Tool name
+    pub tool: String,
+
+    /// Number of calls
+    pub calls: u64,
+
+    /// Calls that failed
+    pub failures: u64,
+
+    /// Time spent in all calls
+    pub total_duration_ms: u64,
+
+    /// Longest single call
+    pub max_duration_ms: u64,
+
+    /// Output produced by all calls
+    pub output_bytes: u64,
+}
+
+impl ToolMetrics {
+    /// Mean duration of a call
+    pub fn average_duration_ms(&self) -> u64 {
+        self.total_duration_ms.checked_div(self.calls).unwrap_or(0)
+    }
+
+    /// Share of calls that failed, from 0 to 1
+    pub fn failure_rate(&self) -> f64 {
+        if self.calls == 0 {
+            0.0
+        } else {
+            self.failures as f64 / self.calls as f64
+        }
+    }
+}
+
+/// Per-tool metrics of `invocations`, the most time-consuming tool first
+pub fn summarize<'a>(
+    invocations: impl IntoIterator<Item = &'a ToolInvocation>,
+) -> Vec<ToolMetrics> {
+    let mut by_tool: BTreeMap<&str, ToolMetrics> = BTreeMap::new();
+    for call in invocations {
+        let metrics = by_tool
+            .entry(call.tool.as_str())
+            .or_insert_with(|| ToolMetrics {
+                tool: call.tool.clone(),
+                ..ToolMetrics::default()
+            });
+        metrics.calls += 1;
+        metrics.failures += u64::from(!call.success);
+        metrics.total_duration_ms += call.duration_ms;
+        metrics.max_duration_ms = metrics.max_duration_ms.max(call.duration_ms);
+        metrics.output_bytes += call.output_bytes;
+    }
+    let mut metrics: Vec<ToolMetrics> = by_tool.into_values().collect();
+    metrics.sort_by_key(|m| std::cmp::Reverse(m.total_duration_ms));
+    metrics
+}
+
+/// Persists tool invocations and reports on them
+pub struct ToolAuditLog {
+    store: Arc<dyn DatabaseInterface<ToolInvocation>>,
+}
+
+impl ToolAuditLog {
+    /// Audit log kept in `store`
+    pub fn new(store: Arc<dyn DatabaseInterface<ToolInvocation>>) -> Self {
+        Self { store }
+    }
+
+    /// Add `invocation` to the log
+    pub async fn record(&self, invocation: ToolInvocation) -> Result<()> {
+        self.store
+            .insert(invocation)
+            .await
+            .context("Failed to save tool invocation")?;
+        Ok(())
+    }
+
+    /// Every recorded invocation, oldest first
+    pub async fn invocations(&self) -> Result<Vec<ToolInvocation>> {
+        let mut invocations: Vec<ToolInvocation> = self
+            .store
+            .get_all()
+            .await
+            .context("Failed to load tool invocations")?
+            .into_iter()
+            .map(|record| record.entity)
+            .collect();
+        invocations.sort_by_key(|call| call.timestamp);
+        Ok(invocations)
+    }
+
+    /// Per-tool metrics of the invocations since `since` (all when `None`)
+    pub async fn metrics(&self, since: Option<DateTime<Utc>>) -> Result<Vec<ToolMetrics>> {
+        let mut query = Query::new().sort_by("timestamp", SortOrder::Ascending);
+        if let Some(since) = since {
+            query = query.filter("timestamp", FilterOp::Ge, since.to_rfc3339());
+        }
+        let invocations: Vec<ToolInvocation> = self
+            .store
+            .query(&query)
+            .await
+            .context("Failed to load tool invocations")?
+            .into_iter()
+            .map(|record| record.entity)
+            .collect();
+        Ok(summarize(invocations.iter()))
+    }
+}
+
+/// The audit log of the current services, if there is one
+pub fn global() -> Option<Arc<ToolAuditLog>> {
+    crate::core::services::Services::current()
+        .tool_audit
+        .clone()
+}
+
+/// Record a call with the process-wide audit log, if any
+///
+/// Failing to persist the record is logged rather than failing the call.
+pub async fn record(
+    tool: &str,
+    arguments: &serde_json::Value,
+    duration: Duration,
+    result: &ToolResult,
+) {
+    if let Some(log) = global() {
+        if let Err(e) = log
+            .record(ToolInvocation::new(tool, arguments, duration, result))
+            .await
+        {
+            warn!("Failed to record tool invocation: {:#}", e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::FileDb;
+    use serde_json::json;
+
+    fn result(success: bool, output: &str) -> ToolResult {
+        ToolResult {
+            success,
+            result: if success {
+                output.to_string()
+            } else {
+                String::new()
+            },
+            error: (!success).then(|| output.to_string()),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_invocations_are_attributed_and_aggregated() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = FileDb::<ToolInvocation>::new(dir.path(), "tool_invocations")
+            .await
+            .unwrap();
+        let log = ToolAuditLog::new(Arc::new(db));
+
+        let call = |tool: &str, ms: u64, res: ToolResult| {
+            ToolInvocation::new(
+                tool,
+                &json!({"file_path": "src/lib.rs"}),
+                Duration::from_millis(ms),
+                &res,
+            )
+        };
+        let attributed =
+            metadata::scope(metadata::attribution(Some("goal-1"), "code:gpt"), async {
+                call("Read", 5, result(true, "12345"))
+            })
+            .await;
+        assert_eq!(attributed.goal_id.as_deref(), Some("goal-1"));
+        assert_eq!(attributed.agent.as_deref(), Some("code:gpt"));
+        assert_eq!(
+            attributed.args_hash,
+            args_hash(&json!({"file_path": "src/lib.rs"}))
+        );
+        log.record(attributed).await.unwrap();
+        log.record(call("Read", 15, result(false, "missing")))
+            .await
+            .unwrap();
+        log.record(call("Bash", 400, result(true, "")))
+            .await
+            .unwrap();
+
+        let reopened = ToolAuditLog::new(Arc::new(
+            FileDb::<ToolInvocation>::new(dir.path(), "tool_invocations")
+                .await
+                .unwrap(),
+        ));
+        let metrics = reopened.metrics(None).await.unwrap();
+        assert_eq!(metrics.len(), 2);
+        assert_eq!(metrics[0].tool, "Bash");
+        let read = &metrics[1];
+        assert_eq!(
+            (
+                read.calls,
+                read.failures,
+                read.total_duration_ms,
+                read.max_duration_ms
+            ),
+            (2, 1, 20, 15)
+        );
+        assert_eq!(read.output_bytes, 12);
+        assert_eq!(read.average_duration_ms(), 10);
+        assert_eq!(read.failure_rate(), 0.5);
+
+        let future = Utc::now() + chrono::Duration::hours(1);
+        assert!(reopened.metrics(Some(future)).await.unwrap().is_empty());
+    }
+}
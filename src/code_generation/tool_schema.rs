@@ -0,0 +1,282 @@
+//! JSON Schema for tool arguments.
+//!
+//! Tools describe their arguments as `ToolParameter`s, while providers and
+//! MCP clients expect a JSON Schema. `parameters_schema` converts one into
+//! the other, and `validate_arguments` checks the arguments of a tool call
+//! against a schema before the tool runs, so a malformed call comes back to
+//! the model as a readable error instead of a confusing tool failure.
+
+use serde_json::{Map, Value};
+
+use crate::code_generation::llm_tool::{ToolParameter, ToolParameterType};
+
+/// JSON Schema object describing `params`, with their types, descriptions,
+/// defaults and which of them are required
+pub fn parameters_schema(params: &[ToolParameter]) -> Value {
+    let mut properties = Map::new();
+    let mut required = Vec::new();
+    for p in params {
+        let ty = p.param_type.clone().unwrap_or(ToolParameterType::String);
+        let mut prop = Map::new();
+        prop.insert("type".into(), json_type(&ty).into());
+        prop.insert("description".into(), p.description.clone().into());
+        if let Some(def) = &p.default_value {
+            prop.insert("default".into(), typed_default(&ty, def));
+        }
+        properties.insert(p.name.clone(), Value::Object(prop));
+        if p.required {
+            required.push(Value::String(p.name.clone()));
+        }
+    }
+
+    let mut schema = Map::new();
+    schema.insert("type".into(), "object".into());
+    schema.insert("properties".into(), Value::Object(properties));
+    if !required.is_empty() {
+        schema.insert("required".into(), Value::Array(required));
+    }
+    // Tools that declare no parameters predate structured calls and may
+    // accept anything
+    if !params.is_empty() {
+        schema.insert("additionalProperties".into(), false.into());
+    }
+    Value::Object(schema)
+}
+
+fn json_type(ty: &ToolParameterType) -> &'static str {
+    match ty {
+        ToolParameterType::String | ToolParameterType::Code => "string",
+        ToolParameterType::Integer => "integer",
+        ToolParameterType::Boolean => "boolean",
+    }
+}
+
+/// `default` parsed as the parameter's type, or kept as a string when it
+/// does not parse
+fn typed_default(ty: &ToolParameterType, default: &str) -> Value {
+    match ty {
+        ToolParameterType::Integer => default
+            .parse::<i64>()
+            .map(Value::from)
+            .unwrap_or_else(|_| default.into()),
+        ToolParameterType::Boolean => default
+            .parse::<bool>()
+            .map(Value::from)
+            .unwrap_or_else(|_| default.into()),
+        ToolParameterType::String | ToolParameterType::Code => default.into(),
+    }
+}
+
+/// Check `arguments` against `schema`
+///
+/// Supports the subset of JSON Schema that tool schemas use in practice:
+/// `type`, `enum`, `properties`, `required`, `additionalProperties: false`
+/// and `items`. Every violation is reported, separated by `; `.
+pub fn validate_arguments(schema: &Value, arguments: &Value) -> Result<(), String> {
+    let mut errors = Vec::new();
+    check(schema, arguments, "", &mut errors);
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(errors.join("; "))
+    }
+}
+
+fn check(schema: &Value, value: &Value, path: &str, errors: &mut Vec<String>) {
+    let types: Vec<&str> = match schema.get("type") {
+        Some(Value::String(ty)) => vec![ty.as_str()],
+        Some(Value::Array(tys)) => tys.iter().filter_map(Value::as_str).collect(),
+        _ => Vec::new(),
+    };
+    if !types.is_empty() && !types.iter().any(|ty| has_type(value, ty)) {
+        errors.push(format!(
+            "{} must be {}, got {}",
+            label(path),
+            types.join(" or "),
+            type_name(value)
+        ));
+        return;
+    }
+
+    if let Some(allowed) = schema.get("enum").and_then(Value::as_array) {
+        if !allowed.contains(value) {
+            let allowed: Vec<String> = allowed.iter().map(Value::to_string).collect();
+            errors.push(format!(
+                "{} must be one of {}",
+                label(path),
+                allowed.join(", ")
+            ));
+        }
+    }
+
+    match value {
+        Value::Object(map) => {
+            let properties = schema.get("properties").and_then(Value::as_object);
+            let required = schema.get("required").and_then(Value::as_array);
+            for name in required.into_iter().flatten().filter_map(Value::as_str) {
+                if !map.contains_key(name) {
+                    errors.push(format!("missing required argument '{}'", child(path, name)));
+                }
+            }
+            let closed = schema.get("additionalProperties") == Some(&Value::Bool(false));
+            for (name, v) in map {
+                match properties.and_then(|p| p.get(name)) {
+                    Some(prop) => check(prop, v, &child(path, name), errors),
+                    None if closed => {
+                        errors.push(format!("unknown argument '{}'", child(path, name)))
+                    }
+                    None => {}
+                }
+            }
+        }
+        Value::Array(items) => {
+            if let Some(item_schema) = schema.get("items") {
+                for (i, item) in items.iter().enumerate() {
+                    check(item_schema, item, &format!("{}[{}]", path, i), errors);
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+fn has_type(value: &Value, ty: &str) -> bool {
+    match ty {
+        "string" => value.is_string(),
+        "integer" => {
+            value.is_i64() || value.is_u64() || value.as_f64().is_some_and(|f| f.fract() == 0.0)
+        }
+        "number" => value.is_number(),
+        "boolean" => value.is_boolean(),
+        "object" => value.is_object(),
+        "array" => value.is_array(),
+        "null" => value.is_null(),
+        // Unknown types are not ours to reject
+        _ => true,
+    }
+}
+
+fn type_name(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "boolean",
+        Value::Number(n) if n.is_f64() => "number",
+        Value::Number(_) => "integer",
+        Value::String(_) => "string",
+        Value::Array(_) => "array",
+        Value::Object(_) => "object",
+    }
+}
+
+fn label(path: &str) -> String {
+    if path.is_empty() {
+        "arguments".to_string()
+    } else {
+        format!("'{}'", path)
+    }
+}
+
+fn child(path: &str, name: &str) -> String {
+    if path.is_empty() {
+        name.to_string()
+    } else {
+        format!("{}.{}", path, name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn param(
+        name: &str,
+        required: bool,
+        ty: ToolParameterType,
+        default: Option<&str>,
+    ) -> ToolParameter {
+        ToolParameter {
+            name: name.to_string(),
+            description: format!("the {}", name),
+            required,
+            default_value: default.map(str::to_string),
+            param_type: Some(ty),
+        }
+    }
+
+    #[test]
+    fn test_schema_has_types_defaults_and_required() {
+        let schema = parameters_schema(&[
+            param("path", true, ToolParameterType::String, None),
+            param("limit", false, ToolParameterType::Integer, Some("200")),
+            param("all", false, ToolParameterType::Boolean, Some("false")),
+            param("body", false, ToolParameterType::Code, Some("n/a")),
+        ]);
+
+        assert_eq!(
+            schema,
+            json!({
+                "type": "object",
+                "properties": {
+                    "path": {"type": "string", "description": "the path"},
+                    "limit": {"type": "integer", "description": "the limit", "default": 200},
+                    "all": {"type": "boolean", "description": "the all", "default": false},
+                    "body": {"type": "string", "description": "the body", "default": "n/a"}
+                },
+                "required": ["path"],
+                "additionalProperties": false
+            })
+        );
+        assert_eq!(
+            parameters_schema(&[]),
+            json!({"type": "object", "properties": {}})
+        );
+    }
+
+    #[test]
+    fn test_validation_reports_every_violation() {
+        let schema = parameters_schema(&[
+            param("path", true, ToolParameterType::String, None),
+            param("limit", false, ToolParameterType::Integer, None),
+        ]);
+
+        assert!(validate_arguments(&schema, &json!({"path": "a.rs", "limit": 5})).is_ok());
+        assert!(validate_arguments(&schema, &json!({"path": "a.rs", "limit": 5.0})).is_ok());
+        let err = validate_arguments(&schema, &json!({"limit": "5", "mode": 1})).unwrap_err();
+        assert_eq!(
+            err,
+            "missing required argument 'path'; \
+             'limit' must be integer, got string; \
+             unknown argument 'mode'"
+        );
+        assert_eq!(
+            validate_arguments(&schema, &json!(["a.rs"])).unwrap_err(),
+            "arguments must be object, got array"
+        );
+    }
+
+    #[test]
+    fn test_validation_follows_nested_schemas() {
+        let schema = json!({
+            "type": "object",
+            "properties": {
+                "mode": {"enum": ["fast", "slow"]},
+                "files": {"type": "array", "items": {"type": "string"}},
+                "opts": {"type": "object", "required": ["depth"]}
+            }
+        });
+
+        assert!(validate_arguments(&schema, &json!({"extra": true})).is_ok());
+        let err = validate_arguments(
+            &schema,
+            &json!({"mode": "medium", "files": ["a", 2], "opts": {}}),
+        )
+        .unwrap_err();
+        assert_eq!(
+            err,
+            "'files[1]' must be string, got integer; \
+             'mode' must be one of \"fast\", \"slow\"; \
+             missing required argument 'opts.depth'"
+        );
+    }
+}
@@ -0,0 +1,683 @@
+//! The `WebFetch` tool.
+//!
+//! Pages are converted from HTML to markdown, keeping headings, lists,
+//! links and code blocks, so documentation reads the way it was written.
+//! Fetches are limited to the configured domains (including redirects),
+//! skip what a site's robots.txt disallows, and go through an on-disk
+//! `FileDb` cache: a page younger than `fresh_secs` is served as is, an
+//! older one is revalidated with its ETag or Last-Modified date.
+
+use anyhow::{anyhow, Context, Result};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use html2md::{Handle, NodeData, StructuredPrinter, TagHandler, TagHandlerFactory};
+use log::{debug, warn};
+use regex::Regex;
+use reqwest::header::{ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED};
+use reqwest::{StatusCode, Url};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::Duration;
+use tokio::sync::OnceCell;
+
+use crate::code_generation::llm_tool::{
+    str_arg, LlmTool, ToolArgs, ToolParameter, ToolParameterType,
+};
+use crate::core::config::WebFetchConfig;
+use crate::core::encryption;
+use crate::database::{DatabaseError, DatabaseInterface, FileDb};
+
+/// Name of the collection holding cached pages
+const PAGE_COLLECTION: &str = "web_pages";
+
+/// User agent sent with every request
+const USER_AGENT: &str = "Borg/1.0 (Autonomous Agent)";
+
+/// Product token looked up in robots.txt
+const ROBOTS_AGENT: &str = "borg";
+
+/// Longest page returned, in characters
+const MAX_PAGE_CHARS: usize = 8000;
+
+/// Most redirects followed for one fetch
+const MAX_REDIRECTS: usize = 10;
+
+/// Elements that hold no readable content
+const SKIPPED_ELEMENTS: &[&str] = &[
+    "head", "script", "style", "noscript", "template", "svg", "nav",
+];
+
+/// A fetched page and what is needed to revalidate it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CachedPage {
+    /// SHA-256 of the URL
+    pub id: String,
+
+    /// The requested URL
+    pub url: String,
+
+    /// Content-Type of the response
+    pub content_type: String,
+
+    /// Response body
+    pub body: String,
+
+    /// ETag of the response, if it had one
+    #[serde(default)]
+    pub etag: Option<String>,
+
+    /// Last-Modified date of the response, if it had one
+    #[serde(default)]
+    pub last_modified: Option<String>,
+
+    /// When the page was fetched or last revalidated
+    pub fetched_at: DateTime<Utc>,
+}
+
+/// Cache key of `url`
+fn page_key(url: &str) -> String {
+    Sha256::digest(url.as_bytes())
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+type Store = Arc<dyn DatabaseInterface<CachedPage>>;
+
+/// Store for `dir`, opened on first use and shared by every tool using the
+/// same directory so they do not overwrite each other's writes
+fn shared_store(dir: &str) -> Arc<OnceCell<Store>> {
+    static STORES: OnceLock<Mutex<HashMap<PathBuf, Arc<OnceCell<Store>>>>> = OnceLock::new();
+    STORES
+        .get_or_init(|| Mutex::new(HashMap::new()))
+        .lock()
+        .unwrap()
+        .entry(PathBuf::from(dir))
+        .or_default()
+        .clone()
+}
+
+/// Which hosts may be fetched
+#[derive(Debug, Clone, Default)]
+struct DomainRules {
+    allowed: Vec<String>,
+    blocked: Vec<String>,
+}
+
+impl DomainRules {
+    fn check(&self, host: &str) -> Result<(), String> {
+        let host = host.to_lowercase();
+        let matches = |domain: &String| {
+            let domain = domain.trim().trim_start_matches('.').to_lowercase();
+            host == domain || host.ends_with(&format!(".{}", domain))
+        };
+        if self.blocked.iter().any(matches) {
+            return Err(format!(
+                "Fetching from {} is blocked by web_fetch.blocked_domains",
+                host
+            ));
+        }
+        if !self.allowed.is_empty() && !self.allowed.iter().any(matches) {
+            return Err(format!("{} is not in web_fetch.allowed_domains", host));
+        }
+        Ok(())
+    }
+}
+
+/// The rules of a robots.txt that apply to this agent
+#[derive(Debug, Clone)]
+pub struct RobotsRules {
+    /// Path patterns and whether they allow access
+    rules: Vec<(Regex, usize, bool)>,
+}
+
+impl RobotsRules {
+    /// Rules allowing everything, for sites without a robots.txt
+    pub fn allow_all() -> Self {
+        Self { rules: Vec::new() }
+    }
+
+    /// Rules disallowing everything, for sites whose robots.txt could not
+    /// be read
+    pub fn disallow_all() -> Self {
+        Self::parse("User-agent: *\nDisallow: /")
+    }
+
+    /// Rules of `text` for the `borg` agent, or for `*` when no group
+    /// names it
+    pub fn parse(text: &str) -> Self {
+        let mut groups: Vec<Group> = Vec::new();
+        let mut in_agents = false;
+        for line in text.lines() {
+            let line = line.split('#').next().unwrap_or_default();
+            let Some((key, value)) = line.split_once(':') else {
+                continue;
+            };
+            let value = value.trim();
+            match key.trim().to_lowercase().as_str() {
+                "user-agent" => {
+                    if !in_agents {
+                        groups.push(Group::default());
+                        in_agents = true;
+                    }
+                    if let Some(group) = groups.last_mut() {
+                        group.agents.push(value.to_lowercase());
+                    }
+                }
+                field @ ("allow" | "disallow") => {
+                    in_agents = false;
+                    if let (Some(group), false) = (groups.last_mut(), value.is_empty()) {
+                        group.rules.push((value.to_string(), field == "allow"));
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        let named = |agent: &str| {
+            groups
+                .iter()
+                .filter(|group| group.names(agent))
+                .flat_map(|group| group.rules.iter().cloned())
+                .collect::<Vec<_>>()
+        };
+        let mut rules = named(ROBOTS_AGENT);
+        if !groups.iter().any(|group| group.names(ROBOTS_AGENT)) {
+            rules = named("*");
+        }
+        Self {
+            rules: rules
+                .into_iter()
+                .filter_map(|(pattern, allow)| {
+                    Some((robots_pattern(&pattern)?, pattern.len(), allow))
+                })
+                .collect(),
+        }
+    }
+
+    /// Whether `path` (with its query) may be fetched: the longest matching
+    /// pattern decides, and `Allow` wins ties
+    pub fn allows(&self, path: &str) -> bool {
+        if path == "/robots.txt" {
+            return true;
+        }
+        self.rules
+            .iter()
+            .filter(|(pattern, _, _)| pattern.is_match(path))
+            .max_by_key(|(_, len, allow)| (*len, *allow))
+            .is_none_or(|(_, _, allow)| *allow)
+    }
+}
+
+/// User agents of a robots.txt group and the path patterns it allows or
+/// disallows
+#[derive(Default)]
+struct Group {
+    agents: Vec<String>,
+    rules: Vec<(String, bool)>,
+}
+
+impl Group {
+    fn names(&self, agent: &str) -> bool {
+        self.agents.iter().any(|a| a == agent)
+    }
+}
+
+/// Regex for a robots.txt path pattern, where `*` matches anything and a
+/// trailing `$` anchors the end
+fn robots_pattern(pattern: &str) -> Option<Regex> {
+    let (pattern, anchored) = match pattern.strip_suffix('$') {
+        Some(rest) => (rest, true),
+        None => (pattern, false),
+    };
+    let body = pattern
+        .split('*')
+        .map(regex::escape)
+        .collect::<Vec<_>>()
+        .join(".*");
+    Regex::new(&format!("^{}{}", body, if anchored { "$" } else { "" })).ok()
+}
+
+/// Drops an element and everything in it
+struct Skip;
+
+impl TagHandler for Skip {
+    fn handle(&mut self, _tag: &Handle, _printer: &mut StructuredPrinter) {}
+
+    fn after_handle(&mut self, _printer: &mut StructuredPrinter) {}
+
+    fn skip_descendants(&self) -> bool {
+        true
+    }
+}
+
+/// Writes headings as `#` lines
+struct Heading;
+
+impl TagHandler for Heading {
+    fn handle(&mut self, tag: &Handle, printer: &mut StructuredPrinter) {
+        let level = element_name(tag)
+            .and_then(|name| name.strip_prefix('h')?.parse::<usize>().ok())
+            .unwrap_or(1);
+        printer.insert_newline();
+        printer.insert_newline();
+        printer.append_str(&format!("{} ", "#".repeat(level)));
+    }
+
+    fn after_handle(&mut self, printer: &mut StructuredPrinter) {
+        printer.insert_newline();
+        printer.insert_newline();
+    }
+}
+
+/// Writes `pre` blocks as fenced code, tagged with their language
+struct CodeBlock;
+
+impl TagHandler for CodeBlock {
+    fn handle(&mut self, tag: &Handle, printer: &mut StructuredPrinter) {
+        let language = code_language(tag)
+            .or_else(|| {
+                tag.children
+                    .borrow()
+                    .iter()
+                    .find(|child| element_name(child).as_deref() == Some("code"))
+                    .and_then(code_language)
+            })
+            .unwrap_or_default();
+        printer.insert_newline();
+        printer.append_str(&format!("\n```{}\n", language));
+    }
+
+    fn after_handle(&mut self, printer: &mut StructuredPrinter) {
+        if !printer.data.ends_with('\n') {
+            printer.insert_newline();
+        }
+        printer.append_str("```\n\n");
+    }
+}
+
+struct Factory(fn() -> Box<dyn TagHandler>);
+
+impl TagHandlerFactory for Factory {
+    fn instantiate(&self) -> Box<dyn TagHandler> {
+        (self.0)()
+    }
+}
+
+fn element_name(node: &Handle) -> Option<String> {
+    match &node.data {
+        NodeData::Element { name, .. } => Some(name.local.to_string()),
+        _ => None,
+    }
+}
+
+/// Language named by the class of a code element (`language-x`, `lang-x`
+/// or rustdoc's `rust`)
+fn code_language(node: &Handle) -> Option<String> {
+    let NodeData::Element { attrs, .. } = &node.data else {
+        return None;
+    };
+    let attrs = attrs.borrow();
+    let class = attrs.iter().find(|a| &*a.name.local == "class")?;
+    class.value.split_whitespace().find_map(|token| {
+        token
+            .strip_prefix("language-")
+            .or_else(|| token.strip_prefix("lang-"))
+            .or((token == "rust").then_some(token))
+            .map(str::to_string)
+    })
+}
+
+/// Markdown rendering of an HTML page, without scripts, styles and
+/// navigation
+pub fn html_to_markdown(html: &str) -> String {
+    let mut handlers: HashMap<String, Box<dyn TagHandlerFactory>> = HashMap::new();
+    for name in SKIPPED_ELEMENTS {
+        handlers.insert(name.to_string(), Box::new(Factory(|| Box::new(Skip))));
+    }
+    for level in 1..=6 {
+        handlers.insert(
+            format!("h{}", level),
+            Box::new(Factory(|| Box::new(Heading))),
+        );
+    }
+    handlers.insert("pre".to_string(), Box::new(Factory(|| Box::new(CodeBlock))));
+
+    let markdown = html2md::parse_html_custom(html, &handlers);
+    let blank_lines = Regex::new(r"\n{3,}").unwrap();
+    blank_lines.replace_all(markdown.trim(), "\n\n").to_string()
+}
+
+/// Tool for fetching web content from URLs
+pub struct WebFetchTool {
+    client: reqwest::Client,
+    config: WebFetchConfig,
+    domains: DomainRules,
+    store: Option<Arc<OnceCell<Store>>>,
+    robots: Mutex<HashMap<String, Arc<RobotsRules>>>,
+}
+
+impl WebFetchTool {
+    /// Create a web fetch tool following `config`
+    pub fn new(config: WebFetchConfig) -> Self {
+        let domains = DomainRules {
+            allowed: config.allowed_domains.clone(),
+            blocked: config.blocked_domains.clone(),
+        };
+        let redirect_domains = domains.clone();
+        let redirects = reqwest::redirect::Policy::custom(move |attempt| {
+            if attempt.previous().len() >= MAX_REDIRECTS {
+                return attempt.error("too many redirects");
+            }
+            match redirect_domains.check(attempt.url().host_str().unwrap_or_default()) {
+                Ok(()) => attempt.follow(),
+                Err(reason) => attempt.error(reason),
+            }
+        });
+        let client = reqwest::Client::builder()
+            .user_agent(USER_AGENT)
+            .timeout(Duration::from_secs(30))
+            .redirect(redirects)
+            .build()
+            .unwrap_or_else(|_| reqwest::Client::new());
+        Self {
+            client,
+            store: config.cache_dir.as_deref().map(shared_store),
+            domains,
+            config,
+            robots: Mutex::new(HashMap::new()),
+        }
+    }
+
+    async fn store(&self) -> Option<&Store> {
+        let dir = self.config.cache_dir.as_deref()?;
+        let opened = self
+            .store
+            .as_ref()?
+            .get_or_try_init(|| async {
+                FileDb::<CachedPage>::open(dir, PAGE_COLLECTION, encryption::global())
+                    .await
+                    .map(|db| Arc::new(db) as Store)
+            })
+            .await;
+        match opened {
+            Ok(store) => Some(store),
+            Err(e) => {
+                warn!("Web page cache unavailable at {}: {}", dir, e);
+                None
+            }
+        }
+    }
+
+    async fn cached(&self, url: &str) -> Option<CachedPage> {
+        match self.store().await?.get(&page_key(url)).await {
+            Ok(record) => Some(record.entity),
+            Err(DatabaseError::NotFound(_)) => None,
+            Err(e) => {
+                warn!("Web page cache read failed: {}", e);
+                None
+            }
+        }
+    }
+
+    async fn save(&self, page: CachedPage) {
+        let Some(store) = self.store().await else {
+            return;
+        };
+        let result = match store.insert(page.clone()).await {
+            Err(DatabaseError::DuplicateKey(_)) => store.update(page, None).await,
+            other => other,
+        };
+        if let Err(e) = result {
+            warn!("Web page cache write failed: {}", e);
+        }
+    }
+
+    /// robots.txt rules of the site serving `url`, fetched once per site
+    async fn robots(&self, url: &Url) -> Arc<RobotsRules> {
+        let origin = url.origin().ascii_serialization();
+        if let Some(rules) = self.robots.lock().unwrap().get(&origin) {
+            return rules.clone();
+        }
+        // A missing robots.txt allows everything; one that cannot be read
+        // allows nothing
+        let rules = match self
+            .client
+            .get(format!("{}/robots.txt", origin))
+            .send()
+            .await
+        {
+            Ok(response) if response.status().is_success() => {
+                RobotsRules::parse(&response.text().await.unwrap_or_default())
+            }
+            Ok(response) if response.status().is_client_error() => RobotsRules::allow_all(),
+            Ok(response) => {
+                debug!("robots.txt of {} returned {}", origin, response.status());
+                RobotsRules::disallow_all()
+            }
+            Err(e) => {
+                debug!("robots.txt of {} unreachable: {}", origin, e);
+                RobotsRules::disallow_all()
+            }
+        };
+        let rules = Arc::new(rules);
+        self.robots.lock().unwrap().insert(origin, rules.clone());
+        rules
+    }
+
+    /// The page at `url`, from the cache while it is fresh or unchanged
+    async fn fetch(&self, url: &Url) -> Result<CachedPage> {
+        let cached = self.cached(url.as_str()).await;
+        if let Some(page) = &cached {
+            let age = Utc::now()
+                .signed_duration_since(page.fetched_at)
+                .to_std()
+                .unwrap_or_default();
+            if age < Duration::from_secs(self.config.fresh_secs) {
+                debug!("Web page cache hit for {}", url);
+                return Ok(page.clone());
+            }
+        }
+
+        if self.config.respect_robots_txt {
+            let path = match url.query() {
+                Some(query) => format!("{}?{}", url.path(), query),
+                None => url.path().to_string(),
+            };
+            if !self.robots(url).await.allows(&path) {
+                return Err(anyhow!(
+                    "robots.txt of {} disallows fetching {}",
+                    url.host_str().unwrap_or_default(),
+                    path
+                ));
+            }
+        }
+
+        let mut request = self.client.get(url.clone());
+        if let Some(page) = &cached {
+            if let Some(etag) = &page.etag {
+                request = request.header(IF_NONE_MATCH, etag);
+            }
+            if let Some(date) = &page.last_modified {
+                request = request.header(IF_MODIFIED_SINCE, date);
+            }
+        }
+        let response = request
+            .send()
+            .await
+            .map_err(|e| anyhow!("Failed to fetch URL: {}", e))?;
+
+        let status = response.status();
+        if let (StatusCode::NOT_MODIFIED, Some(page)) = (status, cached) {
+            let page = CachedPage {
+                fetched_at: Utc::now(),
+                ..page
+            };
+            self.save(page.clone()).await;
+            return Ok(page);
+        }
+        if !status.is_success() {
+            return Err(anyhow!(
+                "HTTP error: {} {}",
+                status.as_u16(),
+                status.canonical_reason().unwrap_or("Unknown")
+            ));
+        }
+
+        let header = |name| {
+            response
+                .headers()
+                .get(name)
+                .and_then(|v: &reqwest::header::HeaderValue| v.to_str().ok())
+                .map(str::to_string)
+        };
+        let content_type = header(reqwest::header::CONTENT_TYPE)
+            .unwrap_or_default()
+            .to_lowercase();
+        let etag = header(ETAG);
+        let last_modified = header(LAST_MODIFIED);
+        let body = response.text().await.context("Failed to read response")?;
+        let page = CachedPage {
+            id: page_key(url.as_str()),
+            url: url.to_string(),
+            content_type,
+            body,
+            etag,
+            last_modified,
+            fetched_at: Utc::now(),
+        };
+        self.save(page.clone()).await;
+        Ok(page)
+    }
+}
+
+impl Default for WebFetchTool {
+    fn default() -> Self {
+        Self::new(WebFetchConfig::default())
+    }
+}
+
+#[async_trait]
+impl LlmTool for WebFetchTool {
+    fn name(&self) -> &str {
+        "WebFetch"
+    }
+
+    fn is_read_only(&self) -> bool {
+        true
+    }
+
+    fn description(&self) -> &str {
+        "Fetch content from a URL to get factual information from the web. \
+         Useful for looking up documentation, APIs, or current information. \
+         HTML pages are returned as markdown."
+    }
+
+    fn parameters(&self) -> Vec<ToolParameter> {
+        vec![ToolParameter {
+            name: "url".to_string(),
+            description: "The URL to fetch content from".to_string(),
+            required: true,
+            default_value: None,
+            param_type: Some(ToolParameterType::String),
+        }]
+    }
+
+    async fn execute(&self, args: &ToolArgs) -> Result<String> {
+        let url = str_arg(args, "url").ok_or_else(|| anyhow!("URL is required"))?;
+        let url = url.trim();
+        if !url.starts_with("http://") && !url.starts_with("https://") {
+            return Err(anyhow!("Invalid URL: must start with http:// or https://"));
+        }
+        let url = Url::parse(url).map_err(|e| anyhow!("Invalid URL: {}", e))?;
+        self.domains
+            .check(url.host_str().unwrap_or_default())
+            .map_err(|reason| anyhow!(reason))?;
+
+        let page = self.fetch(&url).await?;
+        let text = if page.content_type.contains("text/html") {
+            html_to_markdown(&page.body)
+        } else {
+            page.body
+        };
+
+        match text.char_indices().nth(MAX_PAGE_CHARS) {
+            Some((end, _)) => Ok(format!(
+                "{}\n\n[Content truncated, showing first {} characters]",
+                &text[..end],
+                MAX_PAGE_CHARS
+            )),
+            None => Ok(text),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_robots_rules_pick_the_most_specific_group_and_pattern() {
+        let robots = RobotsRules::parse(
+            "# comment\n\
+             User-agent: *\n\
+             Disallow: /\n\
+             \n\
+             User-agent: Borg\n\
+             User-agent: other\n\
+             Disallow: /private\n\
+             Allow: /private/docs\n\
+             Disallow: /*.pdf$\n\
+             Disallow:\n",
+        );
+        assert!(robots.allows("/guide"));
+        assert!(!robots.allows("/private/keys"));
+        assert!(robots.allows("/private/docs/intro"));
+        assert!(!robots.allows("/files/book.pdf"));
+        assert!(robots.allows("/files/book.pdf?page=2"));
+
+        let everyone = RobotsRules::parse("User-agent: *\nDisallow: /search?\n");
+        assert!(everyone.allows("/search"));
+        assert!(!everyone.allows("/search?q=rust"));
+        assert!(!RobotsRules::disallow_all().allows("/"));
+        assert!(RobotsRules::disallow_all().allows("/robots.txt"));
+        assert!(RobotsRules::allow_all().allows("/anything"));
+    }
+
+    #[test]
+    fn test_domain_rules_cover_subdomains() {
+        let rules = DomainRules {
+            allowed: vec!["rust-lang.org".to_string(), "docs.rs".to_string()],
+            blocked: vec!["internals.rust-lang.org".to_string()],
+        };
+        assert!(rules.check("doc.rust-lang.org").is_ok());
+        assert!(rules.check("DOCS.RS").is_ok());
+        assert!(rules.check("notdocs.rs").is_err());
+        let blocked = rules.check("internals.rust-lang.org").unwrap_err();
+        assert!(blocked.contains("blocked_domains"));
+        assert!(DomainRules::default().check("example.com").is_ok());
+    }
+
+    #[test]
+    fn test_html_becomes_markdown() {
+        let html = "<html><head><title>T</title><style>p{}</style></head><body>\
+                    <nav><a href=\"/\">Home</a></nav><script>var x = 1;</script>\
+                    <h1>Guide &amp; more</h1><p>Use <code>spawn</code> or \
+                    <a href=\"https://docs.rs\">docs</a>.</p><h3>Example</h3>\
+                    <pre class=\"rust\"><code>fn main() {\n    println!(\"&lt;3\");\n}</code></pre>\
+                    <pre><code class=\"language-toml\">[dependencies]\n</code></pre>\
+                    <ul><li>one</li><li>two</li></ul></body></html>";
+        assert_eq!(
+            html_to_markdown(html),
+            "# Guide & more\n\n\
+             Use `spawn` or [docs](https://docs.rs).\n\n\
+             ### Example\n\n\
+             ```rust\nfn main() {\n    println!(\"<3\");\n}\n```\n\n\
+             ```toml\n[dependencies]\n```\n\n\
+             * one\n* two"
+        );
+    }
+}
@@ -0,0 +1,307 @@
+//! Confinement of tool paths to the workspace.
+//!
+//! Tools take paths from the model, which may be absolute, contain `..` or
+//! lead through symlinks. `WorkspaceGuard` resolves such a path the way the
+//! filesystem would, following every symlink along it, and accepts it only
+//! if the result is inside the workspace. Paths that do not exist yet (files
+//! about to be written) are resolved as far as they exist.
+
+use anyhow::{anyhow, Result};
+use std::path::{Component, Path, PathBuf};
+
+/// Characters that separate words in a shell command
+const SHELL_SEPARATORS: &[char] = &[
+    ' ', '\t', '\n', ';', '|', '&', '<', '>', '(', ')', '`', '=', '"', '\'',
+];
+
+/// Device files commands may use wherever the workspace is
+const ALLOWED_DEVICES: &[&str] = &["/dev/null", "/dev/stdin", "/dev/stdout", "/dev/stderr"];
+
+/// Resolves tool paths and refuses the ones outside the workspace
+#[derive(Debug, Clone)]
+pub struct WorkspaceGuard {
+    /// The workspace as configured, used to build resolved paths
+    root: PathBuf,
+
+    /// The workspace with symlinks resolved, used for the prefix check
+    real_root: PathBuf,
+}
+
+impl WorkspaceGuard {
+    /// Guard for `workspace`
+    pub fn new(workspace: impl Into<PathBuf>) -> Self {
+        let root = workspace.into();
+        let real_root = real_path(&root).unwrap_or_else(|| root.clone());
+        Self { root, real_root }
+    }
+
+    /// The workspace `path` names, relative to the workspace or absolute,
+    /// or an error if it resolves to somewhere outside of it
+    ///
+    /// The result is spelled under the configured workspace root even when
+    /// that root itself is reached through a symlink.
+    pub fn resolve(&self, path: &str) -> Result<PathBuf> {
+        let requested = Path::new(path);
+        let joined = if requested.is_absolute() {
+            requested.to_path_buf()
+        } else {
+            self.root.join(requested)
+        };
+        let relative = real_path(&joined)
+            .as_deref()
+            .and_then(|real| real.strip_prefix(&self.real_root).ok())
+            .map(Path::to_path_buf)
+            .ok_or_else(|| anyhow!("path '{}' is outside the workspace", path))?;
+        if relative.as_os_str().is_empty() {
+            Ok(self.root.clone())
+        } else {
+            Ok(self.root.join(relative))
+        }
+    }
+
+    /// Refuse `command` if one of its words is a path outside the workspace:
+    /// an absolute path, a `~` path, or one that leaves through `..` or a
+    /// symlink
+    ///
+    /// Relative words without `..` that do not lead through a workspace
+    /// symlink stay inside the workspace and are let through, as are the
+    /// standard device files. Variables in a word are expanded first; a word
+    /// that substitutes a command or names an unset variable is refused,
+    /// since what the shell puts there cannot be checked.
+    pub fn check_command(&self, command: &str) -> Result<()> {
+        if command.contains("$(") {
+            return Err(anyhow!(
+                "Command blocked: '$(' substitutes output that cannot be checked"
+            ));
+        }
+        for word in command.split(SHELL_SEPARATORS).filter(|w| !w.is_empty()) {
+            let Some(expanded) = expand_variables(word) else {
+                return Err(anyhow!(
+                    "Command blocked: '{}' may expand to a path outside the workspace",
+                    word
+                ));
+            };
+            let word = expanded.as_str();
+            if !ALLOWED_DEVICES.contains(&word) && self.leaves_workspace(word) {
+                return Err(anyhow!(
+                    "Command blocked: '{}' is outside the workspace",
+                    word
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Whether the command word `word`, read as a path, names somewhere
+    /// outside the workspace
+    fn leaves_workspace(&self, word: &str) -> bool {
+        if let Some(rest) = word.strip_prefix('~') {
+            // `~user` is another user's home, which is never the workspace
+            if !rest.is_empty() && !rest.starts_with('/') {
+                return true;
+            }
+            return match std::env::var_os("HOME") {
+                Some(home) => {
+                    let path = PathBuf::from(home).join(rest.trim_start_matches('/'));
+                    self.resolve(&path.to_string_lossy()).is_err()
+                }
+                None => true,
+            };
+        }
+        let path = Path::new(word);
+        let climbs = path.components().any(|c| c == Component::ParentDir);
+        let mut prefix = self.root.clone();
+        let links = path.components().any(|c| {
+            prefix.push(c);
+            prefix
+                .symlink_metadata()
+                .is_ok_and(|meta| meta.file_type().is_symlink())
+        });
+        (path.is_absolute() || climbs || links) && self.resolve(word).is_err()
+    }
+}
+
+/// `word` with its `$NAME` and `${NAME}` replaced by their values in the
+/// environment, or `None` if it names an unset variable or uses another
+/// kind of `${...}` expansion
+///
+/// Positional and special parameters (`$1`, `$?`, ...) are dropped; they
+/// stand for arguments and statuses, not for paths.
+fn expand_variables(word: &str) -> Option<String> {
+    let is_name = |name: &str| {
+        name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+            && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+    };
+    let mut expanded = String::new();
+    let mut rest = word;
+    while let Some(dollar) = rest.find('$') {
+        expanded.push_str(&rest[..dollar]);
+        let after = &rest[dollar + 1..];
+        if let Some(braced) = after.strip_prefix('{') {
+            let end = braced.find('}')?;
+            let name = &braced[..end];
+            if is_name(name) {
+                expanded.push_str(&std::env::var(name).ok()?);
+            } else if name.is_empty() || !name.chars().all(|c| c.is_ascii_digit()) {
+                return None;
+            }
+            rest = &braced[end + 1..];
+            continue;
+        }
+        let end = after
+            .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+            .unwrap_or(after.len());
+        let name = &after[..end];
+        rest = &after[end..];
+        if is_name(name) {
+            expanded.push_str(&std::env::var(name).ok()?);
+        } else if name.is_empty() {
+            match rest.chars().next() {
+                Some(special @ ('?' | '#' | '@' | '*' | '$' | '!' | '-')) => {
+                    rest = &rest[special.len_utf8()..];
+                }
+                _ => expanded.push('$'),
+            }
+        }
+    }
+    expanded.push_str(rest);
+    Some(expanded)
+}
+
+/// `path` made absolute with every symlink resolved; components that do not
+/// exist are kept as they are. `None` if a symlink is dangling or loops,
+/// since writing through it could land anywhere.
+fn real_path(path: &Path) -> Option<PathBuf> {
+    let mut real = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::Prefix(_) | Component::RootDir => real.push(component),
+            Component::CurDir => {}
+            // `real` has no symlinks left, so its parent is the real parent
+            Component::ParentDir => {
+                real.pop();
+            }
+            Component::Normal(name) => {
+                real.push(name);
+                if real
+                    .symlink_metadata()
+                    .is_ok_and(|meta| meta.file_type().is_symlink())
+                {
+                    real = real.canonicalize().ok()?;
+                }
+            }
+        }
+    }
+    Some(real)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_paths_stay_inside_the_workspace() {
+        let dir = tempfile::tempdir().unwrap();
+        let ws = dir.path().join("ws");
+        std::fs::create_dir_all(ws.join("src")).unwrap();
+        std::fs::write(ws.join("src/lib.rs"), "").unwrap();
+        let guard = WorkspaceGuard::new(&ws);
+
+        assert_eq!(guard.resolve("src/lib.rs").unwrap(), ws.join("src/lib.rs"));
+        assert_eq!(
+            guard.resolve("./src/../src/lib.rs").unwrap(),
+            ws.join("src/lib.rs")
+        );
+        assert_eq!(guard.resolve(".").unwrap(), ws);
+        assert_eq!(
+            guard.resolve("new/dir/file.rs").unwrap(),
+            ws.join("new/dir/file.rs")
+        );
+        let absolute = ws.join("src/lib.rs");
+        assert_eq!(
+            guard.resolve(absolute.to_str().unwrap()).unwrap(),
+            ws.join("src/lib.rs")
+        );
+
+        assert!(guard.resolve("../ws2/x").is_err());
+        assert!(guard.resolve("src/../../x").is_err());
+        assert!(guard.resolve("/etc/passwd").is_err());
+        let err = guard.resolve("..").unwrap_err();
+        assert_eq!(err.to_string(), "path '..' is outside the workspace");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_symlinks_are_followed() {
+        use std::os::unix::fs::symlink;
+
+        let outside = tempfile::tempdir().unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let ws = dir.path().join("ws");
+        std::fs::create_dir_all(ws.join("src")).unwrap();
+        symlink(outside.path(), ws.join("escape")).unwrap();
+        symlink(ws.join("src"), ws.join("alias")).unwrap();
+        symlink(ws.join("missing"), ws.join("dangling")).unwrap();
+        // The workspace itself may be reached through a symlink
+        symlink(&ws, dir.path().join("link")).unwrap();
+        let guard = WorkspaceGuard::new(dir.path().join("link"));
+
+        assert!(guard.resolve("escape/secret").is_err());
+        assert!(guard.resolve("dangling").is_err());
+        assert_eq!(
+            guard.resolve("alias/lib.rs").unwrap(),
+            dir.path().join("link/src/lib.rs")
+        );
+        // `..` after a symlink climbs from where the link points
+        assert!(guard.resolve("escape/../ws/src").is_err());
+        assert!(guard.resolve("alias/../src").is_ok());
+
+        assert!(guard.check_command("cat src/../alias/lib.rs").is_ok());
+        assert!(guard
+            .check_command("cargo test --target-dir=../out")
+            .is_err());
+        assert!(guard.check_command("cat escape/secret | wc -l").is_err());
+        assert!(guard.check_command("ls 'escape'").is_err());
+        assert!(guard.check_command("echo /usr/bin && ls ..").is_err());
+        assert!(guard.check_command("grep -rn foo src > /dev/null").is_ok());
+    }
+
+    #[test]
+    fn test_commands_naming_absolute_or_home_paths_are_blocked() {
+        let dir = tempfile::tempdir().unwrap();
+        let ws = dir.path().join("ws");
+        std::fs::create_dir_all(ws.join("src")).unwrap();
+        let guard = WorkspaceGuard::new(&ws);
+
+        assert!(guard.check_command("cat /etc/passwd").is_err());
+        assert!(guard.check_command("cp src/lib.rs /tmp/x").is_err());
+        assert!(guard.check_command("cat ~/.ssh/id_rsa").is_err());
+        assert!(guard.check_command("ls ~").is_err());
+        assert!(guard.check_command("ls ~root/.ssh").is_err());
+        let err = guard.check_command("tar cf out.tar ~/.ssh").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Command blocked: '~/.ssh' is outside the workspace"
+        );
+
+        let inside = format!("cat {}", ws.join("src/lib.rs").display());
+        assert!(guard.check_command(&inside).is_ok());
+        assert!(guard.check_command("cargo build 2> /dev/null").is_ok());
+
+        assert!(guard.check_command("cat $HOME/.ssh/id_rsa").is_err());
+        assert!(guard.check_command("cat ${HOME}/.aws/credentials").is_err());
+        assert!(guard.check_command("cd $OLDPWD").is_err());
+        assert!(guard.check_command("cat $(echo /etc/passwd)").is_err());
+        assert!(guard.check_command("cat ${HOME:-/etc}/passwd").is_err());
+        let err = guard
+            .check_command("cat $BORG_SURELY_UNSET_VARIABLE/x")
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Command blocked: '$BORG_SURELY_UNSET_VARIABLE/x' may expand to a path outside the workspace"
+        );
+
+        assert!(guard.check_command("awk '{print $1}' src/lib.rs").is_ok());
+        assert!(guard.check_command("test $? -eq 0").is_ok());
+    }
+}
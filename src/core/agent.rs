@@ -4,16 +4,30 @@ use std::fs;
 use std::path::{Path, PathBuf};
 use std::sync::Arc;
 use tokio::sync::Mutex;
+use tokio_util::sync::CancellationToken;
 
+use crate::code_generation::llm_generator::LlmCodeGenerator;
+use crate::code_generation::router::{ModelRole, ModelRouter};
+use crate::core::calibration::SuccessCalibrator;
 use crate::core::config::Config;
+use crate::core::costs::CostTracker;
+use crate::core::encryption::Cipher;
 use crate::core::ethics::EthicsManager;
+use crate::core::events::EventLog;
+use crate::core::optimization::{GoalStatus, OptimizationGoal, OptimizationManager};
+use crate::core::retention::Compactor;
+use crate::core::services::Services;
+use crate::core::strategies::code_improvement::CodeImprovementStrategy;
 use crate::core::strategy::{ActionType, Plan, StrategyManager};
+use crate::database::{DatabaseInterface, DatabaseManager};
 use crate::resource_monitor::monitor::{ResourceLimits, ResourceMonitor, SystemResourceMonitor};
 use crate::swarm::{SwarmCoordinator, SwarmCycleResult};
+use crate::testing::polyglot::PolyglotTestRunner;
 use crate::testing::simple::SimpleTestRunner;
 use crate::testing::test_runner::TestRunner;
 use crate::version_control::git::GitManager;
 use crate::version_control::git_implementation::GitImplementation;
+use crate::version_control::rollback::MergeLedger;
 
 /// The main agent structure that coordinates the self-improvement process
 pub struct Agent {
@@ -37,6 +51,24 @@ pub struct Agent {
 
     /// Strategy manager for coordinating different action strategies
     strategy_manager: Arc<Mutex<StrategyManager>>,
+
+    /// Cancels the running goal and its in-flight LLM generations
+    cancel: CancellationToken,
+
+    /// Removes logs and finished goals past their retention
+    compactor: Arc<Compactor>,
+
+    /// The merge of each goal, for rolling it back
+    merges: Arc<MergeLedger>,
+
+    /// The services shared by the components, installed for the process
+    services: Arc<Services>,
+
+    /// Goals worked by the strategies, loaded from `goals`
+    optimization_manager: Arc<Mutex<OptimizationManager>>,
+
+    /// Stored goals, including those queued with `borg goal add`
+    goals: Arc<dyn DatabaseInterface<OptimizationGoal>>,
 }
 
 #[allow(dead_code)]
@@ -62,17 +94,116 @@ impl Agent {
                 .context(format!("Failed to create log directory: {:?}", log_dir))?;
         }
 
+        // The services shared by every component, installed for the
+        // process once they are all built
+        let mut services = Services {
+            cipher: Cipher::from_config(&config.encryption)?,
+            ..Default::default()
+        };
+
+        // Stream structured run events when configured
+        if let Some(sink) = &config.logging.event_log {
+            let event_log = EventLog::open(sink).context("Failed to open event log")?;
+            services.events = Some(Arc::new(event_log));
+        }
+
+        // Redact secrets from, and reject unsafe, LLM responses
+        let filters = crate::providers::filter::from_config(&config.guardrails, &working_dir)
+            .context("Invalid guardrails configuration")?;
+        services.filters = filters;
+
+        // Run agent-issued commands in the configured sandbox
+        let sandbox = crate::code_generation::sandbox::Sandbox::new(config.sandbox.clone());
+        sandbox
+            .check()
+            .context("Configured sandbox backend is unavailable")?;
+        services.sandbox = Arc::new(sandbox);
+
+        // Check every tool call against the permission rules, asking the
+        // human at the terminal when a rule says so
+        let mut policy =
+            crate::code_generation::permissions::PermissionPolicy::from_config(&config.permissions)
+                .context("Invalid tool permissions")?;
+        if let Some(prompter) = crate::code_generation::permissions::TerminalPrompter::attached() {
+            policy = policy.with_prompter(Arc::new(prompter));
+        }
+        services.permissions = Arc::new(policy);
+
+        // Render prompts from the configured template overrides
+        let prompts =
+            crate::code_generation::prompt::PromptTemplates::load(&config.prompts, &config.models)
+                .context("Invalid prompt templates")?;
+        services.prompts = Arc::new(prompts);
+
+        // Offer the tools of the configured MCP servers to the models
+        services.mcp = crate::mcp::client::connect_all(&config.mcp.servers).await;
+
         // Create data directory for persistence
         let data_dir = working_dir.join("data");
         std::fs::create_dir_all(&data_dir)
             .context(format!("Failed to create data directory: {:?}", data_dir))?;
 
+        // Price every LLM call, enforce the configured spending limits,
+        // audit every tool call, keep the todo lists of goals, the merge of
+        // each goal and the embedding index of the workspace
+        let database = DatabaseManager::new(&data_dir, &config)
+            .await
+            .context("Failed to open database")?;
+        let cancel = CancellationToken::new();
+        services.costs = Some(Arc::new(
+            CostTracker::new(config.budget.clone(), database.daily_costs())
+                .with_cancellation(cancel.clone()),
+        ));
+        services.tool_audit = Some(Arc::new(
+            crate::code_generation::tool_audit::ToolAuditLog::new(database.tool_invocations()),
+        ));
+        services.todos = Some(Arc::new(crate::code_generation::todos::TodoStore::new(
+            database.todos(),
+        )));
+        let merges = Arc::new(MergeLedger::new(database.merges()));
+        services.merges = Some(merges.clone());
+        let mut lessons = crate::code_generation::memory::MemoryStore::new(database.lessons());
+        if let Some(name) = &config.index.embedding_model {
+            let model = config
+                .get_model(name)
+                .with_context(|| format!("Unknown embedding model '{}'", name))?;
+            let embedder = crate::code_generation::llm::LlmFactory::embedding_provider(model)
+                .context("Failed to create the embedding provider")?;
+            services.semantic_index = Some(Arc::new(
+                crate::code_generation::semantic_index::SemanticIndex::new(
+                    working_dir.clone(),
+                    database.code_index(),
+                    database.code_vectors(),
+                    embedder.clone(),
+                    model.model.clone(),
+                    config.index.clone(),
+                ),
+            ));
+            lessons = lessons.with_embeddings(database.lesson_vectors(), embedder);
+        }
+        services.lessons = Some(Arc::new(lessons));
+        let services = Arc::new(services);
+        services.clone().install();
+        let compactor = Arc::new(Compactor::new(&config, &database));
+
         // Initialize components
         let git_manager: Arc<Mutex<dyn GitManager>> = Arc::new(Mutex::new(
-            GitImplementation::new(&working_dir).context("Failed to create GitImplementation")?,
+            GitImplementation::from_config(&working_dir, &config.git)
+                .context("Failed to create GitImplementation")?,
         ));
 
-        let test_runner: Arc<dyn TestRunner> = Arc::new(SimpleTestRunner::new(&working_dir)?);
+        let cargo_runner: Arc<dyn TestRunner> = Arc::new(
+            SimpleTestRunner::new(&working_dir)?.with_filters(config.testing.filters.clone()),
+        );
+        // Other languages are checked and tested with their own commands
+        let polyglot = PolyglotTestRunner::new(&working_dir, cargo_runner.clone())
+            .with_commands(&config.testing.languages);
+        let test_runner: Arc<dyn TestRunner> = if polyglot.is_cargo_only() {
+            cargo_runner
+        } else {
+            info!("Workspace languages: {:?}", polyglot.languages());
+            Arc::new(polyglot)
+        };
 
         let resource_limits = ResourceLimits {
             max_memory_mb: config.agent.max_memory_usage_mb as f64,
@@ -81,14 +212,39 @@ impl Agent {
         };
 
         let resource_monitor: Arc<Mutex<dyn ResourceMonitor>> = Arc::new(Mutex::new(
-            SystemResourceMonitor::with_limits(resource_limits),
+            SystemResourceMonitor::with_limits(resource_limits.clone()),
         ));
 
         let ethics_manager = Arc::new(Mutex::new(EthicsManager::new()));
 
-        let strategy_manager = Arc::new(Mutex::new(StrategyManager::new(Arc::clone(
+        // Stored goals are worked by the code improvement strategy, on the
+        // model routed to the coder role
+        let optimization_manager = Arc::new(Mutex::new(OptimizationManager::new(Arc::clone(
             &ethics_manager,
         ))));
+        let mut strategies = StrategyManager::new(Arc::clone(&ethics_manager));
+        if let Some(coder) = ModelRouter::new(config.clone()).model_for(ModelRole::Coder) {
+            let generator = LlmCodeGenerator::from_config(
+                &config,
+                coder,
+                git_manager.clone(),
+                working_dir.clone(),
+            )
+            .context("Failed to create the code generator")?
+            .with_cancellation(cancel.clone());
+            let strategy = CodeImprovementStrategy::from_config(
+                &config,
+                Arc::new(generator),
+                test_runner.clone(),
+                git_manager.clone(),
+                optimization_manager.clone(),
+            )
+            .context("Failed to create the code improvement strategy")?
+            .with_resource_monitor(resource_monitor.clone(), resource_limits)
+            .with_calibrator(Arc::new(SuccessCalibrator::new(database.outcome_stats())));
+            strategies.register_strategy(strategy);
+        }
+        let strategy_manager = Arc::new(Mutex::new(strategies));
 
         let agent = Self {
             config,
@@ -98,6 +254,12 @@ impl Agent {
             resource_monitor,
             ethics_manager,
             strategy_manager,
+            cancel,
+            compactor,
+            merges,
+            services,
+            optimization_manager,
+            goals: database.goals(),
         };
 
         // Initialize the repository if needed
@@ -113,14 +275,41 @@ impl Agent {
         // Initialize the Git repository
         self.initialize_git_repository().await?;
 
+        // Ctrl-C aborts the current goal instead of waiting out long generations
+        let cancel = self.cancel.clone();
+        let interrupt = tokio::spawn(async move {
+            if tokio::signal::ctrl_c().await.is_ok() {
+                warn!("Interrupt received; cancelling the current goal");
+                cancel.cancel();
+            }
+        });
+
+        // Keep logs and finished goals within their retention meanwhile
+        let compaction = tokio::spawn(self.compactor.clone().run());
+
         // Run the improvement loop
-        self.improvement_loop().await?;
+        let result = self.improvement_loop().await;
+        interrupt.abort();
+        compaction.abort();
+        result?;
 
         info!("Improvement loop completed");
 
         Ok(())
     }
 
+    /// Token that cancels the current goal, e.g. from a budget or stall
+    /// watchdog; in-flight generations are aborted and their connections
+    /// dropped
+    pub fn cancellation_token(&self) -> CancellationToken {
+        self.cancel.clone()
+    }
+
+    /// The services the agent's components share
+    pub fn services(&self) -> &Arc<Services> {
+        &self.services
+    }
+
     /// Initialize the Git repository
     async fn initialize_git_repository(&self) -> Result<()> {
         let repo_path = &self.working_dir;
@@ -310,6 +499,11 @@ impl Agent {
     async fn improvement_loop(&mut self) -> Result<()> {
         info!("Starting swarm-based improvement loop");
 
+        // Goals queued by hand come before new proposals
+        if self.work_stored_goal().await? {
+            return Ok(());
+        }
+
         // Build codebase context
         let codebase_context = self.build_codebase_context().await?;
 
@@ -319,7 +513,9 @@ impl Agent {
             self.git_manager.clone(),
             self.test_runner.clone(),
         )
-        .await?;
+        .await?
+        .with_cancellation(self.cancel.child_token())
+        .with_merge_ledger(self.merges.clone());
 
         // Run swarm cycle
         let results = coordinator.run(&codebase_context, Some(1)).await?;
@@ -361,6 +557,74 @@ impl Agent {
     }
 }
 
+impl Agent {
+    /// Plan and execute the next open stored goal with the registered
+    /// strategies, recording how it went; returns whether there was one
+    async fn work_stored_goal(&self) -> Result<bool> {
+        if self
+            .strategy_manager
+            .lock()
+            .await
+            .get_strategies()
+            .is_empty()
+        {
+            return Ok(false);
+        }
+        let stored = self.goals.get_all().await.context("Failed to load goals")?;
+        let goal = {
+            let mut manager = self.optimization_manager.lock().await;
+            manager.clear_goals();
+            for record in stored {
+                manager.add_goal(record.entity);
+            }
+            let Some(goal) = manager.get_next_goal().map(|goal| goal.id.clone()) else {
+                return Ok(false);
+            };
+            let goal = manager.get_goal_mut(&goal).expect("goal was just found");
+            goal.update_status(GoalStatus::InProgress);
+            goal.clone()
+        };
+        info!("Working stored goal {}: {}", goal.id, goal.title);
+        self.goals
+            .update(goal.clone(), None)
+            .await
+            .context("Failed to update the goal")?;
+
+        let plan = self.strategy_manager.lock().await.create_plan(&goal).await;
+        let outcome = match plan {
+            Ok(plan) => self.strategy_manager.lock().await.execute_plan(&plan).await,
+            Err(e) => Err(e),
+        };
+        let succeeded = match outcome {
+            Ok(result) => {
+                info!("Goal {}: {}", goal.id, result.message);
+                result.success
+            }
+            Err(e) => {
+                warn!("Goal {} failed: {:#}", goal.id, e);
+                false
+            }
+        };
+
+        // A goal whose merge was rolled back was reopened meanwhile
+        let mut manager = self.optimization_manager.lock().await;
+        if let Some(worked) = manager.get_goal_mut(&goal.id) {
+            if worked.status == GoalStatus::InProgress {
+                worked.update_status(if succeeded {
+                    GoalStatus::Completed
+                } else {
+                    GoalStatus::Failed
+                });
+            }
+            self.goals
+                .update(worked.clone(), None)
+                .await
+                .context("Failed to update the goal")?;
+        }
+        Ok(true)
+    }
+}
+
 /// Initialize the agent's components
 impl Agent {
     /// Initialize the agent's components
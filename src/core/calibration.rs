@@ -0,0 +1,251 @@
+//! Empirical success-probability calibration for plans.
+//!
+//! Outcomes of executed plans are recorded per strategy and goal category so
+//! that `Plan::success_probability` reflects how often similar work actually
+//! succeeded, instead of a hardcoded guess.
+
+use anyhow::{Context, Result};
+use log::info;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use crate::database::{DatabaseError, DatabaseInterface};
+
+/// Success probability assumed before any outcomes are recorded
+pub const PRIOR_SUCCESS_PROBABILITY: f64 = 0.8;
+
+/// Weight of the prior, in pseudo-observations
+const PRIOR_WEIGHT: f64 = 2.0;
+
+/// Recorded outcomes for one strategy/category pair
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OutcomeStats {
+    /// Record key (`<strategy>::<category>`)
+    pub id: String,
+
+    /// Strategy that executed the plans
+    pub strategy: String,
+
+    /// Goal category the plans addressed
+    pub category: String,
+
+    /// Number of successful runs
+    pub successes: u64,
+
+    /// Number of failed runs
+    pub failures: u64,
+}
+
+impl OutcomeStats {
+    /// Empty stats for a strategy/category pair
+    pub fn new(strategy: &str, category: &str) -> Self {
+        Self {
+            id: Self::key(strategy, category),
+            strategy: strategy.to_string(),
+            category: category.to_string(),
+            successes: 0,
+            failures: 0,
+        }
+    }
+
+    /// Record key for a strategy/category pair
+    pub fn key(strategy: &str, category: &str) -> String {
+        format!("{}::{}", strategy, category)
+    }
+
+    /// Total recorded runs
+    pub fn attempts(&self) -> u64 {
+        self.successes + self.failures
+    }
+
+    /// Empirical success rate, smoothed toward the prior so a handful of runs
+    /// cannot pin the estimate at 0 or 1
+    pub fn success_probability(&self) -> f64 {
+        (self.successes as f64 + PRIOR_SUCCESS_PROBABILITY * PRIOR_WEIGHT)
+            / (self.attempts() as f64 + PRIOR_WEIGHT)
+    }
+}
+
+/// Tracks historical outcomes and derives calibrated success probabilities
+pub struct SuccessCalibrator {
+    /// Persistent outcome statistics
+    stats_db: Arc<dyn DatabaseInterface<OutcomeStats>>,
+}
+
+impl SuccessCalibrator {
+    /// Create a calibrator backed by the given outcome collection
+    pub fn new(stats_db: Arc<dyn DatabaseInterface<OutcomeStats>>) -> Self {
+        Self { stats_db }
+    }
+
+    /// Load the stats for a strategy/category pair, if any were recorded
+    async fn stats(&self, strategy: &str, category: &str) -> Result<Option<OutcomeStats>> {
+        match self
+            .stats_db
+            .get(&OutcomeStats::key(strategy, category))
+            .await
+        {
+            Ok(record) => Ok(Some(record.entity)),
+            Err(DatabaseError::NotFound(_)) => Ok(None),
+            Err(e) => Err(e).context("Failed to load outcome stats"),
+        }
+    }
+
+    /// Calibrated success probability for a strategy on a goal category
+    pub async fn success_probability(&self, strategy: &str, category: &str) -> Result<f64> {
+        Ok(self
+            .stats(strategy, category)
+            .await?
+            .map(|s| s.success_probability())
+            .unwrap_or(PRIOR_SUCCESS_PROBABILITY))
+    }
+
+    /// Record the outcome of a run and return the updated probability
+    pub async fn record_outcome(
+        &self,
+        strategy: &str,
+        category: &str,
+        success: bool,
+    ) -> Result<f64> {
+        let existing = self.stats(strategy, category).await?;
+        let is_new = existing.is_none();
+        let mut stats = existing.unwrap_or_else(|| OutcomeStats::new(strategy, category));
+
+        if success {
+            stats.successes += 1;
+        } else {
+            stats.failures += 1;
+        }
+        let probability = stats.success_probability();
+
+        if is_new {
+            self.stats_db.insert(stats).await
+        } else {
+            self.stats_db.update(stats, None).await
+        }
+        .context("Failed to save outcome stats")?;
+
+        info!(
+            "Recorded {} for {} / {}; calibrated success probability is now {:.2}",
+            if success { "success" } else { "failure" },
+            strategy,
+            category,
+            probability
+        );
+        Ok(probability)
+    }
+
+    /// Calibrated success probabilities for every category a strategy has run on
+    pub async fn category_success_rates(&self, strategy: &str) -> Result<HashMap<String, f64>> {
+        let records = self
+            .stats_db
+            .get_all()
+            .await
+            .context("Failed to load outcome stats")?;
+
+        Ok(records
+            .into_iter()
+            .filter(|r| r.entity.strategy == strategy)
+            .map(|r| (r.entity.category.clone(), r.entity.success_probability()))
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::FileDb;
+    use tempfile::TempDir;
+
+    async fn open_calibrator(dir: &TempDir) -> SuccessCalibrator {
+        let db = FileDb::<OutcomeStats>::new(dir.path(), "outcome_stats")
+            .await
+            .unwrap();
+        SuccessCalibrator::new(Arc::new(db))
+    }
+
+    #[tokio::test]
+    async fn test_probability_reflects_recorded_outcomes() {
+        let dir = TempDir::new().unwrap();
+        let calibrator = open_calibrator(&dir).await;
+
+        assert_eq!(
+            calibrator
+                .success_probability("Code Improvement", "Security")
+                .await
+                .unwrap(),
+            PRIOR_SUCCESS_PROBABILITY
+        );
+
+        for success in [false, false, true, false, false, false] {
+            calibrator
+                .record_outcome("Code Improvement", "Security", success)
+                .await
+                .unwrap();
+        }
+        for _ in 0..4 {
+            calibrator
+                .record_outcome("Code Improvement", "Readability", true)
+                .await
+                .unwrap();
+        }
+
+        // (1 success + 0.8 * 2) / (6 runs + 2)
+        let security = calibrator
+            .success_probability("Code Improvement", "Security")
+            .await
+            .unwrap();
+        assert!((security - 0.325).abs() < 1e-9, "{}", security);
+
+        let rates = calibrator
+            .category_success_rates("Code Improvement")
+            .await
+            .unwrap();
+        assert_eq!(rates.len(), 2);
+        assert!(rates["Readability"] > PRIOR_SUCCESS_PROBABILITY);
+        assert!(rates["Security"] < rates["Readability"]);
+
+        // Persisted across instances
+        let reopened = open_calibrator(&dir).await;
+        let reloaded = reopened
+            .success_probability("Code Improvement", "Security")
+            .await
+            .unwrap();
+        assert!((reloaded - security).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_calibrated_goal_selection_deprioritizes_failing_category() {
+        use crate::core::ethics::EthicsManager;
+        use crate::core::optimization::{
+            OptimizationCategory, OptimizationGoal, OptimizationManager,
+        };
+        use tokio::sync::Mutex;
+
+        let mut manager = OptimizationManager::new(Arc::new(Mutex::new(EthicsManager::new())));
+        let mut security = OptimizationGoal::new("sec-1", "Harden input parsing", "");
+        security.category = OptimizationCategory::Security;
+        security.priority = 80;
+        let mut readability = OptimizationGoal::new("read-1", "Split long function", "");
+        readability.category = OptimizationCategory::Readability;
+        readability.priority = 60;
+        manager.add_goal(security);
+        manager.add_goal(readability);
+
+        let no_history = HashMap::new();
+        assert_eq!(
+            manager.get_next_goal_calibrated(&no_history).unwrap().id,
+            "sec-1"
+        );
+
+        let rates = HashMap::from([
+            ("Security".to_string(), 0.2),
+            ("Readability".to_string(), 0.9),
+        ]);
+        assert_eq!(
+            manager.get_next_goal_calibrated(&rates).unwrap().id,
+            "read-1"
+        );
+    }
+}
@@ -1,8 +1,11 @@
 use anyhow::{bail, Context, Result};
 use serde::{Deserialize, Serialize};
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 use std::fs;
-use std::path::Path;
+use std::path::{Path, PathBuf};
+
+use crate::code_generation::language::Language;
+use crate::core::secrets::SecretSource;
 
 /// Top-level configuration structure
 #[derive(Debug, Clone, Deserialize)]
@@ -24,6 +27,67 @@ pub struct Config {
 
     /// Logging configuration
     pub logging: LoggingConfig,
+
+    /// Testing configuration
+    #[serde(default)]
+    pub testing: TestingConfig,
+
+    /// Change review configuration
+    #[serde(default)]
+    pub review: ReviewConfig,
+
+    /// Models used for each agent role
+    #[serde(default)]
+    pub routing: RoutingConfig,
+
+    /// LLM pricing and spending limits
+    #[serde(default)]
+    pub budget: BudgetConfig,
+
+    /// Filters applied to every LLM response
+    #[serde(default)]
+    pub guardrails: GuardrailsConfig,
+
+    /// Size and reach allowed to a generated change
+    #[serde(default)]
+    pub change_limits: ChangeLimitsConfig,
+
+    /// External MCP servers whose tools are offered to the models
+    #[serde(default)]
+    pub mcp: McpConfig,
+
+    /// Isolation for the shell commands, compile checks and tests the
+    /// agent runs
+    #[serde(default)]
+    pub sandbox: SandboxConfig,
+
+    /// Which tool calls the models may make
+    #[serde(default)]
+    pub permissions: ToolPermissionsConfig,
+
+    /// Caching, robots.txt and domain rules of the `WebFetch` tool
+    #[serde(default)]
+    pub web_fetch: WebFetchConfig,
+
+    /// Embedding index behind `SemanticSearch` and context selection
+    #[serde(default)]
+    pub index: IndexConfig,
+
+    /// Overrides of the built-in prompt templates
+    #[serde(default)]
+    pub prompts: PromptsConfig,
+
+    /// Encryption at rest of the database and LLM logs
+    #[serde(default)]
+    pub encryption: EncryptionConfig,
+
+    /// How long logs and finished goals are kept
+    #[serde(default)]
+    pub retention: RetentionConfig,
+
+    /// Tool loop and context of code generation
+    #[serde(default)]
+    pub code_generation: CodeGenerationConfig,
 }
 
 /// Model configuration
@@ -32,12 +96,16 @@ pub struct ModelConfig {
     /// Unique name for this model configuration
     pub name: String,
 
-    /// Provider name (anthropic | openai | openrouter | google | ollama)
+    /// Provider name (anthropic | openai | azure_openai | openrouter | google | ollama | groq | mistral | deepseek)
     pub provider: String,
 
     /// API key for the provider (optional for ollama)
     pub api_key: Option<String>,
 
+    /// Where to fetch the API key from at startup, instead of `api_key`
+    #[serde(default)]
+    pub api_key_source: Option<SecretSource>,
+
     /// Model name to use
     pub model: String,
 
@@ -64,6 +132,47 @@ pub struct ModelConfig {
     /// Provider-specific budget for thinking/reasoning tokens where supported
     #[serde(default)]
     pub reasoning_budget_tokens: Option<usize>,
+
+    /// Extra attempts when the provider returns no usable content (default 1)
+    #[serde(default)]
+    pub empty_response_retries: Option<usize>,
+
+    /// Continuations attempted when a stream is cut off mid-generation (default 2)
+    #[serde(default)]
+    pub stream_resume_retries: Option<usize>,
+
+    /// Azure OpenAI deployment name (defaults to `model`)
+    #[serde(default)]
+    pub deployment: Option<String>,
+
+    /// Azure OpenAI `api-version` query parameter
+    #[serde(default)]
+    pub api_version: Option<String>,
+
+    /// Retry settings for transient failures (defaults apply when unset)
+    #[serde(default)]
+    pub retry: Option<RetryConfig>,
+
+    /// Client-side request and token budgets shared by every caller of this model
+    #[serde(default)]
+    pub rate_limit: Option<RateLimitConfig>,
+
+    /// Reuse responses to identical requests (disabled when unset)
+    #[serde(default)]
+    pub cache: Option<ResponseCacheConfig>,
+
+    /// Proxy and TLS settings for this model's HTTP client
+    #[serde(default)]
+    pub network: Option<NetworkConfig>,
+
+    /// Names of other model entries tried in order when this one fails
+    #[serde(default)]
+    pub fallbacks: Vec<String>,
+
+    /// Name of another model entry sent every request alongside this one;
+    /// the first successful response wins and the other is cancelled
+    #[serde(default)]
+    pub race_with: Option<String>,
 }
 
 /// Phase configuration for TDD workflow
@@ -87,71 +196,1391 @@ pub struct PhasesConfig {
     /// Research phase configuration
     pub research: PhaseConfig,
 
-    /// Deliberation phase configuration
-    pub deliberation: PhaseConfig,
+    /// Deliberation phase configuration
+    pub deliberation: PhaseConfig,
+
+    /// TDD phase configuration
+    pub tdd: PhaseConfig,
+}
+
+/// Agent configuration
+#[derive(Debug, Clone, Deserialize)]
+pub struct AgentConfig {
+    /// Working directory for the agent
+    pub working_dir: String,
+
+    /// Timeout for operations in seconds
+    #[serde(default = "default_timeout_seconds")]
+    pub timeout_seconds: u64,
+
+    /// Maximum memory usage in MB
+    #[serde(default = "default_max_memory_mb")]
+    pub max_memory_usage_mb: u64,
+
+    /// Maximum CPU usage percent
+    #[serde(default = "default_max_cpu_percent")]
+    pub max_cpu_usage_percent: u64,
+
+    /// JSON file rewritten with the current goal, step and progress
+    #[serde(default)]
+    pub status_file: Option<String>,
+}
+
+fn default_timeout_seconds() -> u64 {
+    120
+}
+
+fn default_max_memory_mb() -> u64 {
+    4096
+}
+
+fn default_max_cpu_percent() -> u64 {
+    80
+}
+
+/// Database configuration
+#[derive(Debug, Clone, Deserialize)]
+pub struct DatabaseConfig {
+    /// Path to the database file
+    pub path: String,
+
+    /// Where agent state is stored
+    #[serde(default)]
+    pub backend: DatabaseBackend,
+
+    /// Connection to the shared database of the `postgres` backend
+    #[serde(default)]
+    pub postgres: PostgresConfig,
+}
+
+/// Storage backend for agent state
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DatabaseBackend {
+    /// JSON files in the workspace's data directory, for a single agent
+    #[default]
+    File,
+
+    /// A PostgreSQL database shared by agents on different machines
+    Postgres,
+}
+
+/// Connection to a PostgreSQL database
+#[derive(Debug, Clone, Deserialize)]
+pub struct PostgresConfig {
+    /// Connection URL; read from `url_env` when unset
+    #[serde(default)]
+    pub url: Option<String>,
+
+    /// Environment variable holding the connection URL
+    #[serde(default = "default_postgres_url_env")]
+    pub url_env: String,
+
+    /// Connections kept open in the pool
+    #[serde(default = "default_postgres_max_connections")]
+    pub max_connections: u32,
+
+    /// Seconds to wait for a free connection before failing
+    #[serde(default = "default_postgres_acquire_timeout_seconds")]
+    pub acquire_timeout_seconds: u64,
+}
+
+impl Default for PostgresConfig {
+    fn default() -> Self {
+        Self {
+            url: None,
+            url_env: default_postgres_url_env(),
+            max_connections: default_postgres_max_connections(),
+            acquire_timeout_seconds: default_postgres_acquire_timeout_seconds(),
+        }
+    }
+}
+
+impl PostgresConfig {
+    /// The configured URL, else the one in `url_env`
+    pub fn resolve_url(&self) -> Option<String> {
+        self.url
+            .clone()
+            .or_else(|| std::env::var(&self.url_env).ok())
+            .filter(|url| !url.is_empty())
+    }
+}
+
+fn default_postgres_url_env() -> String {
+    "DATABASE_URL".to_string()
+}
+
+fn default_postgres_max_connections() -> u32 {
+    10
+}
+
+fn default_postgres_acquire_timeout_seconds() -> u64 {
+    30
+}
+
+/// Git configuration
+#[derive(Debug, Clone, Deserialize)]
+pub struct GitConfig {
+    /// Branch naming convention prefix
+    pub branch_prefix: String,
+
+    /// Author and committer of the commits the agent creates
+    #[serde(default)]
+    pub author: CommitIdentity,
+
+    /// Signing of the commits the agent creates
+    #[serde(default)]
+    pub signing: SigningConfig,
+
+    /// Working-tree handling when switching branches
+    #[serde(default)]
+    pub checkout: CheckoutConfig,
+
+    /// Credit every model that contributed to a change with `Co-authored-by:` trailers
+    #[serde(default)]
+    pub co_authored_by: bool,
+
+    /// Conventions generated commit messages are corrected to follow
+    #[serde(default)]
+    pub commit_message: CommitMessageConfig,
+
+    /// Where improvement branches are worked on
+    #[serde(default)]
+    pub worktrees: WorktreeConfig,
+
+    /// Open pull requests on GitHub instead of merging locally
+    #[serde(default)]
+    pub github: GitHubConfig,
+
+    /// Validation after a local merge, and reverting merges that fail it
+    #[serde(default)]
+    pub rollback: RollbackConfig,
+
+    /// Keeping the local clone in step with a remote
+    #[serde(default)]
+    pub sync: SyncConfig,
+
+    /// Hooks run before each commit the agent makes
+    #[serde(default)]
+    pub pre_commit: PreCommitConfig,
+}
+
+/// Pre-commit hooks for the agent's commits
+#[derive(Debug, Clone, Deserialize)]
+pub struct PreCommitConfig {
+    /// Run hooks before each commit; a failing hook stops the commit and
+    /// its output is fed to the next attempt
+    #[serde(default)]
+    pub enabled: bool,
+
+    /// Shell commands to run in the checkout, e.g. `cargo fmt --check`;
+    /// the repository's own `pre-commit` hook runs when empty
+    #[serde(default)]
+    pub commands: Vec<String>,
+
+    /// Seconds each hook may run before it counts as failed
+    #[serde(default = "default_pre_commit_timeout_seconds")]
+    pub timeout_seconds: u64,
+}
+
+impl Default for PreCommitConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            commands: Vec::new(),
+            timeout_seconds: default_pre_commit_timeout_seconds(),
+        }
+    }
+}
+
+fn default_pre_commit_timeout_seconds() -> u64 {
+    600
+}
+
+/// Synchronization with a remote; fetches and pushes authenticate with the
+/// GitHub token when one is configured
+#[derive(Debug, Clone, Deserialize)]
+pub struct SyncConfig {
+    /// Fetch before each improvement, fast-forward the mainline, bring
+    /// branches the mainline moved past up to date and push local merges
+    #[serde(default)]
+    pub enabled: bool,
+
+    /// Remote to synchronize with
+    #[serde(default = "default_sync_remote")]
+    pub remote: String,
+
+    /// How a branch is brought up to date when the mainline moved on since
+    /// it was created
+    #[serde(default)]
+    pub on_divergence: DivergenceStrategy,
+
+    /// Push the mainline after each local merge
+    #[serde(default = "default_sync_push")]
+    pub push: bool,
+}
+
+impl Default for SyncConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            remote: default_sync_remote(),
+            on_divergence: DivergenceStrategy::default(),
+            push: default_sync_push(),
+        }
+    }
+}
+
+fn default_sync_remote() -> String {
+    "origin".to_string()
+}
+
+fn default_sync_push() -> bool {
+    true
+}
+
+/// How a branch catches up with a mainline that moved on
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DivergenceStrategy {
+    /// Replay the commits of the branch on top of the mainline
+    #[default]
+    Rebase,
+    /// Merge the mainline into the branch
+    Merge,
+}
+
+/// Post-merge validation of local merges
+#[derive(Debug, Clone, Deserialize)]
+pub struct RollbackConfig {
+    /// Validate the mainline after each merge and revert the merge, reopening
+    /// its goal, when validation finds a regression
+    #[serde(default)]
+    pub enabled: bool,
+
+    /// Run the tests on the mainline after the merge
+    #[serde(default = "default_rollback_run_tests")]
+    pub run_tests: bool,
+
+    /// Check that resource usage stays within the agent's limits after the
+    /// merge
+    #[serde(default = "default_rollback_check_resources")]
+    pub check_resources: bool,
+}
+
+impl Default for RollbackConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            run_tests: default_rollback_run_tests(),
+            check_resources: default_rollback_check_resources(),
+        }
+    }
+}
+
+fn default_rollback_run_tests() -> bool {
+    true
+}
+
+fn default_rollback_check_resources() -> bool {
+    true
+}
+
+/// Name and email commits are made under
+#[derive(Debug, Clone, Deserialize)]
+pub struct CommitIdentity {
+    /// Author name
+    #[serde(default = "default_commit_author_name")]
+    pub name: String,
+
+    /// Author email
+    #[serde(default = "default_commit_author_email")]
+    pub email: String,
+}
+
+impl Default for CommitIdentity {
+    fn default() -> Self {
+        Self {
+            name: default_commit_author_name(),
+            email: default_commit_author_email(),
+        }
+    }
+}
+
+fn default_commit_author_name() -> String {
+    "Borg Agent".to_string()
+}
+
+fn default_commit_author_email() -> String {
+    "borg@example.com".to_string()
+}
+
+/// Commit signing
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct SigningConfig {
+    /// Signature format; commits are unsigned by default
+    #[serde(default)]
+    pub format: SigningFormat,
+
+    /// Key to sign with: a GPG key id (the default key when unset), or the
+    /// path of an SSH private key
+    #[serde(default)]
+    pub key: Option<String>,
+
+    /// Signing program; `gpg` or `ssh-keygen` when unset
+    #[serde(default)]
+    pub program: Option<String>,
+}
+
+/// How commits are signed
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SigningFormat {
+    /// Commits are not signed
+    #[default]
+    None,
+    /// OpenPGP signatures made by `gpg`
+    Gpg,
+    /// SSH signatures made by `ssh-keygen -Y sign`
+    Ssh,
+}
+
+/// Pull request workflow on GitHub
+#[derive(Debug, Clone, Deserialize)]
+pub struct GitHubConfig {
+    /// Push improvement branches and open a pull request for each instead
+    /// of merging them into the mainline locally
+    #[serde(default)]
+    pub enabled: bool,
+
+    /// Git remote the branches are pushed to
+    #[serde(default = "default_github_remote")]
+    pub remote: String,
+
+    /// `owner/name` of the repository; derived from the remote URL when unset
+    #[serde(default)]
+    pub repository: Option<String>,
+
+    /// Base URL of the GitHub REST API
+    #[serde(default = "default_github_api_url")]
+    pub api_url: String,
+
+    /// Access token; read from `token_env` when unset
+    #[serde(default)]
+    pub token: Option<String>,
+
+    /// Environment variable holding the access token
+    #[serde(default = "default_github_token_env")]
+    pub token_env: String,
+
+    /// Wait for the commit status checks of the pull request to pass
+    #[serde(default = "default_require_ci")]
+    pub require_ci: bool,
+
+    /// Wait for an approving review of the pull request
+    #[serde(default)]
+    pub require_approval: bool,
+
+    /// Seconds between polls of the pull request status
+    #[serde(default = "default_github_poll_interval_seconds")]
+    pub poll_interval_seconds: u64,
+
+    /// Seconds to wait for checks and approvals before giving up
+    #[serde(default = "default_github_timeout_seconds")]
+    pub timeout_seconds: u64,
+}
+
+impl Default for GitHubConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            remote: default_github_remote(),
+            repository: None,
+            api_url: default_github_api_url(),
+            token: None,
+            token_env: default_github_token_env(),
+            require_ci: default_require_ci(),
+            require_approval: false,
+            poll_interval_seconds: default_github_poll_interval_seconds(),
+            timeout_seconds: default_github_timeout_seconds(),
+        }
+    }
+}
+
+impl GitHubConfig {
+    /// The configured token, else the one in `token_env`
+    pub fn resolve_token(&self) -> Option<String> {
+        self.token
+            .clone()
+            .or_else(|| std::env::var(&self.token_env).ok())
+            .filter(|token| !token.is_empty())
+    }
+}
+
+fn default_github_remote() -> String {
+    "origin".to_string()
+}
+
+fn default_github_api_url() -> String {
+    "https://api.github.com".to_string()
+}
+
+fn default_github_token_env() -> String {
+    "GITHUB_TOKEN".to_string()
+}
+
+fn default_require_ci() -> bool {
+    true
+}
+
+fn default_github_poll_interval_seconds() -> u64 {
+    30
+}
+
+fn default_github_timeout_seconds() -> u64 {
+    3600
+}
+
+/// Isolated checkouts of improvement branches
+#[derive(Debug, Clone, Deserialize)]
+pub struct WorktreeConfig {
+    /// Work on each improvement branch in its own `git worktree` instead of
+    /// checking it out in the working directory
+    #[serde(default = "default_worktrees_enabled")]
+    pub enabled: bool,
+
+    /// Directory the worktrees are created under; a `borg-worktrees`
+    /// directory in the system temp directory when unset
+    #[serde(default)]
+    pub directory: Option<PathBuf>,
+}
+
+impl Default for WorktreeConfig {
+    fn default() -> Self {
+        Self {
+            enabled: default_worktrees_enabled(),
+            directory: None,
+        }
+    }
+}
+
+fn default_worktrees_enabled() -> bool {
+    true
+}
+
+/// Policy applied to every generated commit message
+#[derive(Debug, Clone, Deserialize)]
+pub struct CommitMessageConfig {
+    /// Format of the subject line
+    #[serde(default)]
+    pub style: CommitStyle,
+
+    /// Longest subject line, in characters; longer subjects are cut at a
+    /// word boundary and kept in full in the body
+    #[serde(default = "default_max_subject_length")]
+    pub max_subject_length: usize,
+
+    /// Trailers added to every commit
+    #[serde(default)]
+    pub trailers: Vec<CommitTrailer>,
+}
+
+impl Default for CommitMessageConfig {
+    fn default() -> Self {
+        Self {
+            style: CommitStyle::default(),
+            max_subject_length: default_max_subject_length(),
+            trailers: Vec::new(),
+        }
+    }
+}
+
+fn default_max_subject_length() -> usize {
+    72
+}
+
+/// Format of a commit subject line
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CommitStyle {
+    /// Whatever the model writes
+    #[default]
+    Free,
+    /// `type(scope): description`, the type and scope inferred from the
+    /// change when the model leaves them out
+    Conventional,
+}
+
+/// Trailer identifying where a commit came from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CommitTrailer {
+    /// `Goal-Id:` of the goal the commit works on
+    GoalId,
+    /// `Borg-Attempt:` number of the attempt that produced the commit
+    Attempt,
+}
+
+/// Checkout behavior when switching branches
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct CheckoutConfig {
+    /// Whether to refuse (safe) or overwrite (force) conflicting working-tree files
+    #[serde(default)]
+    pub mode: CheckoutMode,
+
+    /// Remove untracked files that are not part of the target tree
+    #[serde(default)]
+    pub remove_untracked: bool,
+
+    /// Only check out these paths (pathspecs); all paths when empty
+    #[serde(default)]
+    pub paths: Vec<String>,
+
+    /// How conflicts are written into working-tree files
+    #[serde(default)]
+    pub conflict_style: Option<ConflictStyle>,
+}
+
+/// Conflict marker style of checked-out files
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ConflictStyle {
+    /// `<<<<<<<`, `=======` and `>>>>>>>` markers
+    Merge,
+    /// Merge markers plus the common ancestor's lines
+    Diff3,
+}
+
+/// Checkout strategy for working-tree files
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CheckoutMode {
+    /// Refuse to overwrite modified or untracked files
+    #[default]
+    Safe,
+    /// Overwrite working-tree files to match the target tree
+    Force,
+}
+
+/// Logging configuration
+#[derive(Debug, Clone, Deserialize)]
+pub struct LoggingConfig {
+    /// Whether LLM logging is enabled
+    #[serde(default = "default_logging_enabled")]
+    pub enabled: bool,
+
+    /// Directory for LLM log files
+    pub llm_log_dir: String,
+
+    /// Newline-delimited JSON event stream: a file path, or `-` for stdout
+    #[serde(default)]
+    pub event_log: Option<String>,
+}
+
+fn default_logging_enabled() -> bool {
+    true
+}
+
+/// Testing configuration
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct TestingConfig {
+    /// Benchmark sampling and comparison settings
+    #[serde(default)]
+    pub benchmark: BenchmarkConfig,
+
+    /// Acceptance gate for generated TDD tests
+    #[serde(default)]
+    pub tdd: TddGateConfig,
+
+    /// Which tests run at the iteration gate and at the merge gate
+    #[serde(default)]
+    pub filters: TestFiltersConfig,
+
+    /// How a successful run that executed zero tests is judged
+    #[serde(default)]
+    pub no_tests: NoTestsPolicy,
+
+    /// Build check and test commands per language, replacing the defaults
+    /// (`python: {test: "pytest -q"}`); a language listed here is checked
+    /// and tested even when the workspace has no manifest for it
+    #[serde(default)]
+    pub languages: HashMap<Language, LanguageCommands>,
+}
+
+/// Shell commands, run at the workspace root, that check a language's code
+/// and run its tests
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+pub struct LanguageCommands {
+    /// Fails when the code does not build (none: no build step)
+    #[serde(default)]
+    pub check: Option<String>,
+
+    /// Runs the tests (none: the language has no tests to run)
+    #[serde(default)]
+    pub test: Option<String>,
+}
+
+/// Verdict for a test run that passed without executing any tests
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NoTestsPolicy {
+    /// Accept the change as validated
+    TreatAsPass,
+
+    /// Reject the change as unverified
+    TreatAsFail,
+
+    /// Reject the change until tests have been generated for it, switching
+    /// to the TDD flow when test generation is available
+    #[default]
+    RequireGeneratedTests,
+}
+
+impl NoTestsPolicy {
+    /// Whether a passing run with zero tests counts as a pass
+    pub fn accepts_empty_run(self) -> bool {
+        self == NoTestsPolicy::TreatAsPass
+    }
+}
+
+/// Test selection for the fast iteration gate and the final merge gate
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct TestFiltersConfig {
+    /// Tests run after each change while iterating (default: everything)
+    #[serde(default)]
+    pub iteration: TestFilter,
+
+    /// Tests run before a branch is merged (default: everything)
+    #[serde(default)]
+    pub merge: TestFilter,
+
+    /// While iterating, run only the tests exercising the changed files,
+    /// falling back to `iteration` when the change can affect any test
+    #[serde(default)]
+    pub affected: bool,
+}
+
+/// Selection of cargo test targets and test names
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+pub struct TestFilter {
+    /// Target kinds to build and run; empty means cargo's default set.
+    /// `doc` cannot be combined with other target kinds.
+    #[serde(default)]
+    pub targets: Vec<TestTarget>,
+
+    /// Integration test targets to run by name (`--test <name>`)
+    #[serde(default)]
+    pub test_targets: Vec<String>,
+
+    /// Only run tests whose name contains one of these substrings
+    #[serde(default)]
+    pub include: Vec<String>,
+
+    /// Skip tests whose name contains any of these substrings
+    #[serde(default)]
+    pub exclude: Vec<String>,
+}
+
+/// Cargo test target kind (maps to `--lib`, `--tests`, ...)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum TestTarget {
+    Lib,
+    Bins,
+    Tests,
+    Examples,
+    Benches,
+    Doc,
+    AllTargets,
+}
+
+impl TestTarget {
+    /// The cargo flag selecting this target kind
+    pub fn cargo_flag(self) -> &'static str {
+        match self {
+            TestTarget::Lib => "--lib",
+            TestTarget::Bins => "--bins",
+            TestTarget::Tests => "--tests",
+            TestTarget::Examples => "--examples",
+            TestTarget::Benches => "--benches",
+            TestTarget::Doc => "--doc",
+            TestTarget::AllTargets => "--all-targets",
+        }
+    }
+}
+
+/// Minimum requirements on generated tests before TDD implementation may proceed
+#[derive(Debug, Clone, Deserialize)]
+pub struct TddGateConfig {
+    /// Minimum number of distinct generated tests
+    #[serde(default = "default_min_generated_tests")]
+    pub min_generated_tests: usize,
+
+    /// Optional minimum fraction (0.0-1.0) of acceptance criteria covered by tests
+    #[serde(default)]
+    pub min_acceptance_coverage: Option<f64>,
+
+    /// Kinds of tests generated besides example-based tests
+    #[serde(default)]
+    pub modes: Vec<TestGenerationMode>,
+
+    /// Mutation testing of the changed files once the generated tests pass
+    #[serde(default)]
+    pub mutation: MutationTestingConfig,
+}
+
+impl Default for TddGateConfig {
+    fn default() -> Self {
+        Self {
+            min_generated_tests: default_min_generated_tests(),
+            min_acceptance_coverage: None,
+            modes: Vec::new(),
+            mutation: MutationTestingConfig::default(),
+        }
+    }
+}
+
+/// Mutation testing with cargo-mutants, run to judge whether the tests of a
+/// change would notice it being broken
+#[derive(Debug, Clone, Deserialize)]
+pub struct MutationTestingConfig {
+    /// Whether to run cargo-mutants on the changed files
+    #[serde(default)]
+    pub enabled: bool,
+
+    /// Longest the whole mutation run may take
+    #[serde(default = "default_mutation_timeout_seconds")]
+    pub timeout_seconds: u64,
+}
+
+impl Default for MutationTestingConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            timeout_seconds: default_mutation_timeout_seconds(),
+        }
+    }
+}
+
+fn default_mutation_timeout_seconds() -> u64 {
+    900
+}
+
+/// A kind of generated test beyond example-based unit tests
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TestGenerationMode {
+    /// proptest property tests of the functions named in the spec
+    Property,
+    /// cargo-fuzz targets for the functions named in the spec
+    Fuzz,
+}
+
+fn default_min_generated_tests() -> usize {
+    2
+}
+
+/// Change review configuration
+#[derive(Debug, Clone, Deserialize)]
+pub struct ReviewConfig {
+    /// Reference to ModelConfig.name of a second model that must approve
+    /// a change's diff before it is merged (disabled when unset)
+    #[serde(default)]
+    pub reviewer_model: Option<String>,
+
+    /// Reference to ModelConfig.name of a model that critiques each
+    /// generated diff against the goal before it is written to the branch
+    /// (disabled when unset)
+    #[serde(default)]
+    pub critic_model: Option<String>,
+
+    /// Revisions the critic may request before the change is given up
+    #[serde(default = "default_max_revisions")]
+    pub max_revisions: usize,
+}
+
+impl Default for ReviewConfig {
+    fn default() -> Self {
+        Self {
+            reviewer_model: None,
+            critic_model: None,
+            max_revisions: default_max_revisions(),
+        }
+    }
+}
+
+fn default_max_revisions() -> usize {
+    2
+}
+
+/// Per-role model routing; each entry references a ModelConfig.name, and
+/// unset roles keep the model that does that job without routing
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct RoutingConfig {
+    /// Specifications and plans (defaults to the first research model)
+    #[serde(default)]
+    pub planner: Option<String>,
+
+    /// Code and test generation (defaults to the first TDD model)
+    #[serde(default)]
+    pub coder: Option<String>,
+
+    /// Change review (defaults to `review.reviewer_model`)
+    #[serde(default)]
+    pub reviewer: Option<String>,
+
+    /// Critique of generated diffs before they are written (defaults to
+    /// `review.critic_model`)
+    #[serde(default)]
+    pub critic: Option<String>,
+
+    /// Commit messages and short classification calls (defaults to the coder)
+    #[serde(default)]
+    pub committer: Option<String>,
+}
+
+/// Model Context Protocol servers
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct McpConfig {
+    /// Servers started at agent startup
+    #[serde(default)]
+    pub servers: Vec<McpServerConfig>,
+
+    /// `borg mcp-serve --sse`
+    #[serde(default)]
+    pub serve: McpServeConfig,
+}
+
+/// The HTTP+SSE transport of `borg mcp-serve`
+#[derive(Debug, Clone, Deserialize)]
+pub struct McpServeConfig {
+    /// Where the bearer token clients must send comes from; a fresh token
+    /// is generated and logged at startup when unset
+    #[serde(default)]
+    pub token_source: Option<SecretSource>,
+
+    /// Browser origins allowed to connect (e.g. `http://localhost:3000`);
+    /// requests with any other `Origin` header are refused
+    #[serde(default)]
+    pub allowed_origins: Vec<String>,
+
+    /// Largest request body accepted, in bytes
+    #[serde(default = "default_mcp_max_body_bytes")]
+    pub max_body_bytes: usize,
+}
+
+impl Default for McpServeConfig {
+    fn default() -> Self {
+        Self {
+            token_source: None,
+            allowed_origins: Vec::new(),
+            max_body_bytes: default_mcp_max_body_bytes(),
+        }
+    }
+}
+
+fn default_mcp_max_body_bytes() -> usize {
+    1024 * 1024
+}
+
+/// An MCP server run as a child process speaking MCP over stdio
+#[derive(Debug, Clone, Deserialize)]
+pub struct McpServerConfig {
+    /// Name used in tool names (`mcp__<name>__<tool>`) and phase tool lists
+    pub name: String,
+
+    /// Executable to run (e.g. `npx`)
+    pub command: String,
+
+    /// Arguments to the command
+    #[serde(default)]
+    pub args: Vec<String>,
+
+    /// Extra environment variables for the server process
+    #[serde(default)]
+    pub env: HashMap<String, String>,
+
+    /// Seconds to wait for any single request to the server
+    #[serde(default = "default_mcp_timeout_secs")]
+    pub timeout_secs: u64,
+}
+
+fn default_mcp_timeout_secs() -> u64 {
+    60
+}
+
+/// What happens to a tool call
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PermissionAction {
+    /// Run it
+    #[default]
+    Allow,
+
+    /// Refuse it
+    Deny,
+
+    /// Ask the human at the terminal; refuse it when nobody is attached
+    Ask,
+}
+
+/// Tool permission policy
+///
+/// A call is denied if any matching rule denies it, otherwise asked about
+/// if any matching rule asks, otherwise allowed if any matching rule
+/// allows it; calls no rule matches get `default`.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct ToolPermissionsConfig {
+    /// Action for calls no rule matches
+    #[serde(default)]
+    pub default: PermissionAction,
+
+    /// Rules, in any order
+    #[serde(default)]
+    pub rules: Vec<ToolPermissionRule>,
+}
+
+/// Action for the calls of matching tools
+#[derive(Debug, Clone, Deserialize)]
+pub struct ToolPermissionRule {
+    /// Tool name or glob (`*`, `mcp__github__*`)
+    pub tool: String,
+
+    /// What to do with matching calls
+    pub action: PermissionAction,
+
+    /// Only calls touching a path matching one of these globs, relative to
+    /// the workspace (e.g. `src/**`, `Cargo.toml`)
+    #[serde(default)]
+    pub paths: Vec<String>,
+
+    /// Only calls whose command starts with one of these prefixes (e.g.
+    /// `git push`, `cargo publish`)
+    #[serde(default)]
+    pub commands: Vec<String>,
+}
+
+/// Where agent-run commands execute
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SandboxBackend {
+    /// Directly on the host, guarded only by a blocklist of dangerous
+    /// shell commands
+    #[default]
+    None,
+
+    /// A throwaway Docker container
+    Docker,
+
+    /// A throwaway Podman container
+    Podman,
+
+    /// A bubblewrap namespace with a read-only view of the host
+    Bubblewrap,
+}
+
+/// Isolation for agent-run commands
+///
+/// The workspace is mounted read-write at its host path, so paths in
+/// commands and their output mean the same inside and outside.
+#[derive(Debug, Clone, Deserialize)]
+pub struct SandboxConfig {
+    /// Backend to run commands in
+    #[serde(default)]
+    pub backend: SandboxBackend,
+
+    /// Container image (Docker and Podman)
+    #[serde(default = "default_sandbox_image")]
+    pub image: String,
+
+    /// CPUs a command may use (Docker and Podman)
+    #[serde(default)]
+    pub cpus: Option<f64>,
+
+    /// Memory limit in megabytes
+    #[serde(default)]
+    pub memory_mb: Option<u64>,
+
+    /// Whether commands may reach the network
+    #[serde(default)]
+    pub network: bool,
+
+    /// Extra mounts as `host:sandbox[:ro]`, e.g. a cargo registry cache
+    #[serde(default)]
+    pub mounts: Vec<String>,
+
+    /// Extra environment variables inside the sandbox
+    #[serde(default)]
+    pub env: HashMap<String, String>,
+}
+
+impl Default for SandboxConfig {
+    fn default() -> Self {
+        Self {
+            backend: SandboxBackend::default(),
+            image: default_sandbox_image(),
+            cpus: None,
+            memory_mb: None,
+            network: false,
+            mounts: Vec::new(),
+            env: HashMap::new(),
+        }
+    }
+}
+
+fn default_sandbox_image() -> String {
+    "rust:latest".to_string()
+}
+
+/// How the `WebFetch` tool retrieves pages
+///
+/// Domains match themselves and their subdomains.
+#[derive(Debug, Clone, Deserialize)]
+pub struct WebFetchConfig {
+    /// Directory of the page cache; pages are not cached when unset
+    #[serde(default = "default_web_cache_dir")]
+    pub cache_dir: Option<String>,
+
+    /// How long a cached page is used before asking the server whether it
+    /// changed (by ETag or Last-Modified)
+    #[serde(default = "default_web_cache_fresh_secs")]
+    pub fresh_secs: u64,
+
+    /// Skip pages the site's robots.txt disallows
+    #[serde(default = "default_respect_robots_txt")]
+    pub respect_robots_txt: bool,
+
+    /// Only fetch from these domains; any domain when empty
+    #[serde(default)]
+    pub allowed_domains: Vec<String>,
+
+    /// Never fetch from these domains
+    #[serde(default)]
+    pub blocked_domains: Vec<String>,
+}
+
+impl Default for WebFetchConfig {
+    fn default() -> Self {
+        Self {
+            cache_dir: default_web_cache_dir(),
+            fresh_secs: default_web_cache_fresh_secs(),
+            respect_robots_txt: default_respect_robots_txt(),
+            allowed_domains: Vec::new(),
+            blocked_domains: Vec::new(),
+        }
+    }
+}
+
+fn default_web_cache_dir() -> Option<String> {
+    Some("./data/web_cache".to_string())
+}
+
+fn default_web_cache_fresh_secs() -> u64 {
+    60 * 60
+}
+
+fn default_respect_robots_txt() -> bool {
+    true
+}
+
+/// Where prompt templates are overridden
+///
+/// Templates are handlebars; the built-in ones are in the repository's
+/// `prompts/` directory.
+#[derive(Debug, Clone, Deserialize)]
+pub struct PromptsConfig {
+    /// Directory of `<name>.hbs` files replacing the built-in template of
+    /// the same name
+    #[serde(default = "default_prompts_dir")]
+    pub dir: String,
+
+    /// Templates used for one model only: model entry name (or model id)
+    /// to template name to file, relative to `dir`
+    #[serde(default)]
+    pub models: HashMap<String, HashMap<String, String>>,
+}
+
+impl Default for PromptsConfig {
+    fn default() -> Self {
+        Self {
+            dir: default_prompts_dir(),
+            models: HashMap::new(),
+        }
+    }
+}
+
+fn default_prompts_dir() -> String {
+    "./prompts".to_string()
+}
+
+/// How the workspace is chunked and embedded for semantic search
+#[derive(Debug, Clone, Deserialize)]
+pub struct IndexConfig {
+    /// Model entry that embeds the chunks; no index is kept when unset
+    #[serde(default)]
+    pub embedding_model: Option<String>,
+
+    /// Lines per chunk
+    #[serde(default = "default_index_chunk_lines")]
+    pub chunk_lines: usize,
+
+    /// Lines a chunk shares with the one before it
+    #[serde(default = "default_index_chunk_overlap")]
+    pub chunk_overlap: usize,
+
+    /// Chunks embedded per request
+    #[serde(default = "default_index_batch_size")]
+    pub batch_size: usize,
+}
+
+impl Default for IndexConfig {
+    fn default() -> Self {
+        Self {
+            embedding_model: None,
+            chunk_lines: default_index_chunk_lines(),
+            chunk_overlap: default_index_chunk_overlap(),
+            batch_size: default_index_batch_size(),
+        }
+    }
+}
+
+/// Encryption at rest of the database and LLM logs
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct EncryptionConfig {
+    /// Whether stored records and LLM log entries are encrypted
+    #[serde(default)]
+    pub enabled: bool,
+
+    /// Where the base64-encoded 256-bit key comes from; the
+    /// `BORG_ENCRYPTION_KEY` variable when unset
+    #[serde(default)]
+    pub key_source: Option<SecretSource>,
+}
+
+/// How long logs and finished goals are kept, and how often what is past
+/// that is removed
+#[derive(Debug, Clone, Deserialize)]
+pub struct RetentionConfig {
+    /// LLM log files in `logging.llm_log_dir`
+    #[serde(default)]
+    pub llm_logs: RetentionPolicy,
+
+    /// The audit log of tool calls
+    #[serde(default)]
+    pub execution_logs: RetentionPolicy,
+
+    /// Goals that completed, failed or were abandoned, with their todo lists
+    #[serde(default)]
+    pub completed_goals: RetentionPolicy,
+
+    /// Hours between compactions while the agent runs; 0 leaves them to
+    /// `borg db vacuum`
+    #[serde(default = "default_compaction_interval_hours")]
+    pub compaction_interval_hours: u64,
+}
+
+impl Default for RetentionConfig {
+    fn default() -> Self {
+        Self {
+            llm_logs: RetentionPolicy::default(),
+            execution_logs: RetentionPolicy::default(),
+            completed_goals: RetentionPolicy::default(),
+            compaction_interval_hours: default_compaction_interval_hours(),
+        }
+    }
+}
+
+fn default_compaction_interval_hours() -> u64 {
+    24
+}
+
+/// What to keep of something that grows without bound; everything is kept
+/// when neither limit is set
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+pub struct RetentionPolicy {
+    /// Remove what is older than this many days
+    #[serde(default)]
+    pub max_age_days: Option<u32>,
+
+    /// Remove all but this many of the most recent
+    #[serde(default)]
+    pub keep: Option<usize>,
+}
+
+fn default_index_chunk_lines() -> usize {
+    40
+}
+
+fn default_index_chunk_overlap() -> usize {
+    8
+}
+
+fn default_index_batch_size() -> usize {
+    32
+}
+
+/// Response filters run on every LLM response before it is used
+#[derive(Debug, Clone, Deserialize)]
+pub struct GuardrailsConfig {
+    /// Replace API-key-looking strings with a placeholder
+    #[serde(default = "default_guardrail_enabled")]
+    pub redact_secrets: bool,
+
+    /// Reject responses naming absolute paths outside the working directory
+    #[serde(default = "default_guardrail_enabled")]
+    pub block_outside_paths: bool,
+
+    /// Absolute paths (or prefixes) allowed outside the working directory
+    #[serde(default = "default_allowed_paths")]
+    pub allowed_paths: Vec<String>,
+
+    /// Regexes that reject any response they match
+    #[serde(default)]
+    pub blocked_patterns: Vec<String>,
+}
+
+impl Default for GuardrailsConfig {
+    fn default() -> Self {
+        Self {
+            redact_secrets: default_guardrail_enabled(),
+            block_outside_paths: default_guardrail_enabled(),
+            allowed_paths: default_allowed_paths(),
+            blocked_patterns: Vec::new(),
+        }
+    }
+}
+
+fn default_guardrail_enabled() -> bool {
+    true
+}
 
-    /// TDD phase configuration
-    pub tdd: PhaseConfig,
+fn default_allowed_paths() -> Vec<String> {
+    ["/dev/null", "/usr/bin/env", "/bin/sh", "/bin/bash", "/tmp"]
+        .iter()
+        .map(|p| p.to_string())
+        .collect()
 }
 
-/// Agent configuration
+/// Limits on the size and reach of a generated change, checked before it is
+/// applied
 #[derive(Debug, Clone, Deserialize)]
-pub struct AgentConfig {
-    /// Working directory for the agent
-    pub working_dir: String,
+pub struct ChangeLimitsConfig {
+    /// Most files one change may touch (unlimited when null)
+    #[serde(default = "default_max_files")]
+    pub max_files: Option<usize>,
+
+    /// Most lines one change may add and remove in total (unlimited when null)
+    #[serde(default = "default_max_lines_changed")]
+    pub max_lines_changed: Option<usize>,
+
+    /// Glob patterns of paths a change may only touch when its goal has an
+    /// `allow-path:<pattern>` tag matching them
+    #[serde(default = "default_protected_paths")]
+    pub protected_paths: Vec<String>,
+}
 
-    /// Timeout for operations in seconds
-    #[serde(default = "default_timeout_seconds")]
-    pub timeout_seconds: u64,
+impl Default for ChangeLimitsConfig {
+    fn default() -> Self {
+        Self {
+            max_files: default_max_files(),
+            max_lines_changed: default_max_lines_changed(),
+            protected_paths: default_protected_paths(),
+        }
+    }
+}
 
-    /// Maximum memory usage in MB
-    #[serde(default = "default_max_memory_mb")]
-    pub max_memory_usage_mb: u64,
+fn default_max_files() -> Option<usize> {
+    Some(20)
+}
 
-    /// Maximum CPU usage percent
-    #[serde(default = "default_max_cpu_percent")]
-    pub max_cpu_usage_percent: u64,
+fn default_max_lines_changed() -> Option<usize> {
+    Some(1000)
 }
 
-fn default_timeout_seconds() -> u64 {
-    120
+fn default_protected_paths() -> Vec<String> {
+    [
+        ".github/**",
+        "Cargo.lock",
+        "src/core/ethics.rs",
+        "src/swarm/constitution.rs",
+    ]
+    .iter()
+    .map(|p| p.to_string())
+    .collect()
 }
 
-fn default_max_memory_mb() -> u64 {
-    4096
+/// LLM pricing and spending limits
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct BudgetConfig {
+    /// Prices keyed by provider model id (e.g. `claude-opus-4-5-20251101`);
+    /// calls to unpriced models are tracked at zero cost
+    #[serde(default)]
+    pub pricing: HashMap<String, ModelPricing>,
+
+    /// Maximum spend in USD for a single agent run (unlimited when unset)
+    #[serde(default)]
+    pub max_run_usd: Option<f64>,
+
+    /// Maximum spend in USD per UTC calendar day (unlimited when unset)
+    #[serde(default)]
+    pub max_daily_usd: Option<f64>,
 }
 
-fn default_max_cpu_percent() -> u64 {
-    80
+/// Price of a model in USD per million tokens
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+pub struct ModelPricing {
+    /// Price per million prompt tokens
+    pub input_per_million: f64,
+
+    /// Price per million completion tokens
+    pub output_per_million: f64,
 }
 
-/// Database configuration
+/// Benchmark configuration for before/after comparisons
 #[derive(Debug, Clone, Deserialize)]
-pub struct DatabaseConfig {
-    /// Path to the database file
-    pub path: String,
+pub struct BenchmarkConfig {
+    /// Number of samples to collect per branch
+    #[serde(default = "default_benchmark_samples")]
+    pub samples: usize,
+
+    /// Significance level (alpha) for Welch's t-test
+    #[serde(default = "default_significance_level")]
+    pub significance_level: f64,
+
+    /// Minimum slowdown in percent before a significant change counts as a regression
+    #[serde(default = "default_regression_threshold_percent")]
+    pub regression_threshold_percent: f64,
+
+    /// Maximum time in seconds for one criterion bench target on one branch
+    #[serde(default = "default_benchmark_timeout_seconds")]
+    pub timeout_seconds: u64,
 }
 
-/// Git configuration
-#[derive(Debug, Clone, Deserialize)]
-pub struct GitConfig {
-    /// Branch naming convention prefix
-    pub branch_prefix: String,
+impl Default for BenchmarkConfig {
+    fn default() -> Self {
+        Self {
+            samples: default_benchmark_samples(),
+            significance_level: default_significance_level(),
+            regression_threshold_percent: default_regression_threshold_percent(),
+            timeout_seconds: default_benchmark_timeout_seconds(),
+        }
+    }
 }
 
-/// Logging configuration
-#[derive(Debug, Clone, Deserialize)]
-pub struct LoggingConfig {
-    /// Whether LLM logging is enabled
-    #[serde(default = "default_logging_enabled")]
-    pub enabled: bool,
+fn default_benchmark_samples() -> usize {
+    5
+}
 
-    /// Directory for LLM log files
-    pub llm_log_dir: String,
+fn default_significance_level() -> f64 {
+    0.05
 }
 
-fn default_logging_enabled() -> bool {
-    true
+fn default_regression_threshold_percent() -> f64 {
+    5.0
+}
+
+fn default_benchmark_timeout_seconds() -> u64 {
+    1800
 }
 
 /// Reasoning effort levels for provider-specific reasoning (OpenRouter unified interface)
@@ -176,25 +1605,129 @@ pub enum ReasoningEffort {
 // =====================
 
 /// Code generation configuration (legacy compatibility)
-#[derive(Debug, Deserialize, Clone, Default)]
+#[derive(Debug, Deserialize, Clone)]
 pub struct CodeGenerationConfig {
-    /// Maximum number of tool iterations
+    /// Rounds of native tool calls before the model is asked for a final answer
     #[serde(default = "default_max_tool_iterations")]
     pub max_tool_iterations: usize,
 
+    /// Most tool calls from one model turn that run at the same time; calls
+    /// writing a path another call touches still run in order
+    #[serde(default = "default_max_parallel_tools")]
+    pub max_parallel_tools: usize,
+
     /// Whether to use tools for code generation
     #[serde(default = "default_use_tools")]
     pub use_tools: bool,
+
+    /// Token budget of the repository map added to the context; 0 leaves it
+    /// out
+    #[serde(default = "default_repo_map_tokens")]
+    pub repo_map_tokens: usize,
+
+    /// Selection of the files whose contents go into the context
+    #[serde(default)]
+    pub context: ContextConfig,
+}
+
+impl Default for CodeGenerationConfig {
+    fn default() -> Self {
+        Self {
+            max_tool_iterations: default_max_tool_iterations(),
+            max_parallel_tools: default_max_parallel_tools(),
+            use_tools: default_use_tools(),
+            repo_map_tokens: default_repo_map_tokens(),
+            context: ContextConfig::default(),
+        }
+    }
 }
 
 fn default_max_tool_iterations() -> usize {
     25
 }
 
+fn default_max_parallel_tools() -> usize {
+    4
+}
+
 fn default_use_tools() -> bool {
     true
 }
 
+fn default_repo_map_tokens() -> usize {
+    1024
+}
+
+/// How files that do not fit the context budget are shortened
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TruncationStrategy {
+    /// Keep the start of the file
+    Head,
+    /// Keep the lines around mentions of the goal's identifiers
+    #[default]
+    Relevant,
+    /// Leave out files that do not fit whole
+    Skip,
+}
+
+/// Automatic selection of the files put into a goal's context
+///
+/// Files are ranked by how often they mention the goal's identifiers, by
+/// commits that touched them together with the goal's files or with a
+/// message mentioning the goal, and by embedding similarity when an index
+/// is available. The goal's own files always come first.
+#[derive(Debug, Clone, Deserialize)]
+pub struct ContextConfig {
+    /// Token budget of source file contents
+    #[serde(default = "default_context_max_tokens")]
+    pub max_tokens: usize,
+
+    /// Token budget of test file contents
+    #[serde(default = "default_context_max_test_tokens")]
+    pub max_test_tokens: usize,
+
+    /// Most files selected in addition to the goal's own files
+    #[serde(default = "default_context_max_files")]
+    pub max_files: usize,
+
+    /// How files that do not fit the remaining budget are shortened
+    #[serde(default)]
+    pub truncation: TruncationStrategy,
+
+    /// Recent commits searched for related files
+    #[serde(default = "default_context_history_commits")]
+    pub history_commits: usize,
+}
+
+impl Default for ContextConfig {
+    fn default() -> Self {
+        Self {
+            max_tokens: default_context_max_tokens(),
+            max_test_tokens: default_context_max_test_tokens(),
+            max_files: default_context_max_files(),
+            truncation: TruncationStrategy::default(),
+            history_commits: default_context_history_commits(),
+        }
+    }
+}
+
+fn default_context_max_tokens() -> usize {
+    12_000
+}
+
+fn default_context_max_test_tokens() -> usize {
+    4_000
+}
+
+fn default_context_max_files() -> usize {
+    8
+}
+
+fn default_context_history_commits() -> usize {
+    200
+}
+
 /// LLM provider configuration (legacy compatibility)
 /// Used by existing code that hasn't been migrated to ModelConfig
 #[derive(Debug, Deserialize, Clone)]
@@ -237,6 +1770,155 @@ pub struct LlmConfig {
 
     /// Max idle gap between streaming tokens before timing out
     pub stall_timeout_ms: Option<u64>,
+
+    /// Extra attempts when the provider returns no usable content (default 1)
+    pub empty_response_retries: Option<usize>,
+
+    /// Continuations attempted when a stream is cut off mid-generation (default 2)
+    pub stream_resume_retries: Option<usize>,
+
+    /// Azure OpenAI deployment name (defaults to `model`)
+    pub deployment: Option<String>,
+
+    /// Azure OpenAI `api-version` query parameter
+    pub api_version: Option<String>,
+
+    /// Retry settings for transient failures (defaults apply when unset)
+    pub retry: Option<RetryConfig>,
+
+    /// Client-side request and token budgets (unlimited when unset)
+    pub rate_limit: Option<RateLimitConfig>,
+
+    /// Reuse responses to identical requests (disabled when unset)
+    pub cache: Option<ResponseCacheConfig>,
+
+    /// Proxy and TLS settings for the HTTP client (system defaults when unset)
+    pub network: Option<NetworkConfig>,
+}
+
+/// Retry behaviour for transient provider failures (429, 5xx, timeouts)
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct RetryConfig {
+    /// Total attempts per call, including the first
+    #[serde(default = "default_retry_max_attempts")]
+    pub max_attempts: u32,
+
+    /// Delay before the first retry
+    #[serde(default = "default_retry_initial_backoff_ms")]
+    pub initial_backoff_ms: u64,
+
+    /// Upper bound on the computed backoff (a server `Retry-After` is not capped)
+    #[serde(default = "default_retry_max_backoff_ms")]
+    pub max_backoff_ms: u64,
+
+    /// Backoff growth factor per retry
+    #[serde(default = "default_retry_multiplier")]
+    pub multiplier: f64,
+
+    /// Random spread applied to each delay, as a fraction (0.2 = ±20%)
+    #[serde(default = "default_retry_jitter")]
+    pub jitter: f64,
+}
+
+impl Default for RetryConfig {
+    fn default() -> Self {
+        Self {
+            max_attempts: default_retry_max_attempts(),
+            initial_backoff_ms: default_retry_initial_backoff_ms(),
+            max_backoff_ms: default_retry_max_backoff_ms(),
+            multiplier: default_retry_multiplier(),
+            jitter: default_retry_jitter(),
+        }
+    }
+}
+
+fn default_retry_max_attempts() -> u32 {
+    3
+}
+
+fn default_retry_initial_backoff_ms() -> u64 {
+    500
+}
+
+fn default_retry_max_backoff_ms() -> u64 {
+    30_000
+}
+
+fn default_retry_multiplier() -> f64 {
+    2.0
+}
+
+fn default_retry_jitter() -> f64 {
+    0.2
+}
+
+/// Token-bucket limits applied before requests reach the provider
+///
+/// Calls beyond the budget queue until capacity frees up, for at most
+/// `max_wait_ms`.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct RateLimitConfig {
+    /// Requests allowed per minute (unlimited when unset)
+    #[serde(default)]
+    pub requests_per_minute: Option<u32>,
+
+    /// Prompt plus completion tokens allowed per minute (unlimited when unset)
+    #[serde(default)]
+    pub tokens_per_minute: Option<u32>,
+
+    /// Longest a request may queue before failing
+    #[serde(default = "default_rate_limit_max_wait_ms")]
+    pub max_wait_ms: u64,
+}
+
+fn default_rate_limit_max_wait_ms() -> u64 {
+    60_000
+}
+
+/// On-disk cache of provider responses keyed by the canonical request
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct ResponseCacheConfig {
+    /// How long a cached response stays valid
+    #[serde(default = "default_cache_ttl_secs")]
+    pub ttl_secs: u64,
+
+    /// Directory holding the cache collection
+    #[serde(default = "default_cache_dir")]
+    pub dir: String,
+}
+
+fn default_cache_ttl_secs() -> u64 {
+    24 * 60 * 60
+}
+
+fn default_cache_dir() -> String {
+    "./data/llm_cache".to_string()
+}
+
+/// Proxy and TLS settings for provider HTTP clients, for networks behind
+/// TLS-intercepting proxies
+#[derive(Debug, Clone, PartialEq, Default, Deserialize)]
+pub struct NetworkConfig {
+    /// Proxy URL for all requests (e.g. `http://proxy.corp:3128`); the
+    /// `HTTPS_PROXY`/`HTTP_PROXY` environment variables apply when unset
+    #[serde(default)]
+    pub proxy: Option<String>,
+
+    /// Comma-separated hosts that bypass `proxy`
+    #[serde(default)]
+    pub no_proxy: Option<String>,
+
+    /// PEM bundle of extra CA certificates to trust
+    #[serde(default)]
+    pub ca_bundle: Option<String>,
+
+    /// PEM client certificate for mutual TLS (requires `client_key`)
+    #[serde(default)]
+    pub client_cert: Option<String>,
+
+    /// PEM (PKCS#8) private key for `client_cert`
+    #[serde(default)]
+    pub client_key: Option<String>,
 }
 
 /// LLM logging configuration (legacy compatibility)
@@ -257,6 +1939,10 @@ pub struct LlmLoggingConfig {
     /// Whether to include full responses in logs
     pub include_full_responses: bool,
 
+    /// Whether to log the model's reasoning/thinking text alongside responses
+    #[serde(default)]
+    pub include_reasoning: bool,
+
     /// Maximum log file size in MB before rotation
     pub max_log_size_mb: u64,
 
@@ -282,15 +1968,38 @@ impl Config {
         // Expand environment variables
         let expanded_text = expand_env_vars(&config_text)?;
 
-        let config: Config = serde_yaml::from_str(&expanded_text)
+        let mut config: Config = serde_yaml::from_str(&expanded_text)
             .with_context(|| format!("Failed to parse YAML config file: {:?}", path.as_ref()))?;
 
+        // Fetch credentials from their configured secret sources
+        config.resolve_secrets()?;
+
         // Validate the configuration
         config.validate()?;
 
         Ok(config)
     }
 
+    /// Resolve every `api_key_source` into the model's `api_key`
+    pub fn resolve_secrets(&mut self) -> Result<()> {
+        for model in &mut self.models {
+            let Some(source) = &model.api_key_source else {
+                continue;
+            };
+            if model.api_key.is_some() {
+                bail!(
+                    "Model '{}' sets both api_key and api_key_source; use only one",
+                    model.name
+                );
+            }
+            let key = source
+                .resolve()
+                .with_context(|| format!("Failed to resolve API key for model '{}'", model.name))?;
+            model.api_key = Some(key);
+        }
+        Ok(())
+    }
+
     /// Validate the configuration
     pub fn validate(&self) -> Result<()> {
         // Check that at least one model is configured
@@ -315,6 +2024,56 @@ impl Config {
         self.validate_phase_tools("deliberation", &self.phases.deliberation.tools)?;
         self.validate_phase_tools("tdd", &self.phases.tdd.tools)?;
 
+        // Validate the reviewer and critic model references
+        for (kind, model) in [
+            ("reviewer", &self.review.reviewer_model),
+            ("critic", &self.review.critic_model),
+        ] {
+            if let Some(model) = model {
+                if !model_names.contains(model) {
+                    bail!(
+                        "Review references unknown {} model '{}'. Available models: {}",
+                        kind,
+                        model,
+                        model_names.iter().cloned().collect::<Vec<_>>().join(", ")
+                    );
+                }
+            }
+        }
+
+        // Validate role routing references
+        for (role, model) in [
+            ("planner", &self.routing.planner),
+            ("coder", &self.routing.coder),
+            ("reviewer", &self.routing.reviewer),
+            ("critic", &self.routing.critic),
+            ("committer", &self.routing.committer),
+        ] {
+            if let Some(model) = model {
+                if !model_names.contains(model) {
+                    bail!(
+                        "Routing for '{}' references unknown model '{}'. Available models: {}",
+                        role,
+                        model,
+                        model_names.iter().cloned().collect::<Vec<_>>().join(", ")
+                    );
+                }
+            }
+        }
+
+        if let Some(model) = &self.index.embedding_model {
+            if !model_names.contains(model) {
+                bail!(
+                    "Index references unknown embedding model '{}'. Available models: {}",
+                    model,
+                    model_names.iter().cloned().collect::<Vec<_>>().join(", ")
+                );
+            }
+        }
+        if self.index.chunk_overlap >= self.index.chunk_lines {
+            bail!("index.chunk_overlap must be smaller than index.chunk_lines");
+        }
+
         // Validate that model names are unique
         let mut seen_names = HashSet::new();
         for model in &self.models {
@@ -326,12 +2085,74 @@ impl Config {
         // Validate provider names
         for model in &self.models {
             match model.provider.as_str() {
-                "anthropic" | "openai" | "openrouter" | "google" | "ollama" => {},
-                _ => bail!("Invalid provider '{}' for model '{}'. Valid providers: anthropic, openai, openrouter, google, ollama",
+                "anthropic" | "openai" | "azure_openai" | "openrouter" | "google" | "ollama"
+                | "groq" | "mistral" | "deepseek" => {},
+                _ => bail!("Invalid provider '{}' for model '{}'. Valid providers: anthropic, openai, azure_openai, openrouter, google, ollama, groq, mistral, deepseek",
                           model.provider, model.name),
             }
         }
 
+        // Validate fallback chains
+        for model in &self.models {
+            for fallback in &model.fallbacks {
+                if fallback == &model.name {
+                    bail!("Model '{}' lists itself as a fallback", model.name);
+                }
+                let Some(target) = self.get_model(fallback) else {
+                    bail!(
+                        "Model '{}' references unknown fallback model '{}'. Available models: {}",
+                        model.name,
+                        fallback,
+                        model_names.iter().cloned().collect::<Vec<_>>().join(", ")
+                    );
+                };
+                if target.provider == "google" {
+                    bail!(
+                        "Fallback model '{}' uses provider '{}', which cannot take part in a fallback chain",
+                        fallback,
+                        target.provider
+                    );
+                }
+            }
+            if let Some(rival) = &model.race_with {
+                let Some(target) = self.get_model(rival) else {
+                    bail!(
+                        "Model '{}' races unknown model '{}'. Available models: {}",
+                        model.name,
+                        rival,
+                        model_names.iter().cloned().collect::<Vec<_>>().join(", ")
+                    );
+                };
+                if rival == &model.name {
+                    bail!("Model '{}' cannot race itself", model.name);
+                }
+                if target.provider == "google" || model.provider == "google" {
+                    bail!(
+                        "Models '{}' and '{}' cannot race: provider 'google' is not supported",
+                        model.name,
+                        rival
+                    );
+                }
+            }
+            if !model.fallbacks.is_empty() && model.provider == "google" {
+                bail!(
+                    "Model '{}' uses provider '{}', which cannot take part in a fallback chain",
+                    model.name,
+                    model.provider
+                );
+            }
+        }
+
+        // Azure OpenAI has no default endpoint
+        for model in &self.models {
+            if model.provider == "azure_openai" && model.api_base.is_none() {
+                bail!(
+                    "Model '{}' with provider 'azure_openai' must set api_base to the resource endpoint",
+                    model.name
+                );
+            }
+        }
+
         // Validate that non-ollama models have API keys
         for model in &self.models {
             if model.provider != "ollama" && model.api_key.is_none() {
@@ -343,6 +2164,31 @@ impl Config {
             }
         }
 
+        // Validate MCP server names
+        let mut mcp_names = HashSet::new();
+        for server in &self.mcp.servers {
+            if server.name.is_empty() || server.name.contains("__") {
+                bail!(
+                    "Invalid MCP server name '{}': must be non-empty and not contain '__'",
+                    server.name
+                );
+            }
+            if !mcp_names.insert(&server.name) {
+                bail!("Duplicate MCP server name found: '{}'", server.name);
+            }
+        }
+
+        // Validate guardrail patterns
+        for pattern in &self.guardrails.blocked_patterns {
+            if let Err(e) = regex::Regex::new(pattern) {
+                bail!(
+                    "Invalid guardrails.blocked_patterns entry '{}': {}",
+                    pattern,
+                    e
+                );
+            }
+        }
+
         Ok(())
     }
 
@@ -380,23 +2226,54 @@ impl Config {
         "Read",
         "Write",
         "Edit",
+        "ApplyPatch",
+        "Move",
+        "Delete",
         // Execution
         "Bash",
+        "GitBisect",
         // Search
         "Grep",
         "Glob",
+        "LS",
+        // Code navigation
+        "FindDefinition",
+        "FindReferences",
+        "DocumentSymbols",
+        "CodeQuery",
+        "SemanticSearch",
         // Web
         "WebSearch",
         "WebFetch",
+        "CrateSearch",
+        "CrateInfo",
+        "DocsRs",
         // Agent (main agent only)
         "Task",
         // Task management
         "TodoWrite",
+        "TodoRead",
+        // Memory
+        "Remember",
+        "Recall",
     ];
 
     /// Validate that all phase tool references are valid
     fn validate_phase_tools(&self, phase_name: &str, tools: &[String]) -> Result<()> {
         for tool_name in tools {
+            if let Some(rest) = tool_name.strip_prefix(crate::mcp::TOOL_PREFIX) {
+                // `mcp__<server>` or `mcp__<server>__<tool>`
+                let server = rest.split("__").next().unwrap_or_default();
+                if !self.mcp.servers.iter().any(|s| s.name == server) {
+                    bail!(
+                        "Phase '{}' references tool '{}' of unknown MCP server '{}'",
+                        phase_name,
+                        tool_name,
+                        server
+                    );
+                }
+                continue;
+            }
             if !Self::VALID_TOOLS.contains(&tool_name.as_str()) {
                 bail!(
                     "Phase '{}' references unknown tool '{}'. Available tools: {}",
@@ -414,6 +2291,29 @@ impl Config {
         self.models.iter().find(|m| m.name == name)
     }
 
+    /// Model entries named in `model.fallbacks`, in order
+    ///
+    /// Fallbacks of fallbacks are not followed, so chains cannot loop.
+    pub fn fallback_models(&self, model: &ModelConfig) -> Vec<ModelConfig> {
+        model
+            .fallbacks
+            .iter()
+            .filter_map(|name| self.get_model(name))
+            .filter(|m| m.name != model.name)
+            .cloned()
+            .collect()
+    }
+
+    /// Model entry named in `model.race_with`, if any
+    pub fn race_model(&self, model: &ModelConfig) -> Option<ModelConfig> {
+        model
+            .race_with
+            .as_deref()
+            .and_then(|name| self.get_model(name))
+            .filter(|m| m.name != model.name)
+            .cloned()
+    }
+
     /// Create a new config with default values for testing
     #[cfg(test)]
     pub fn for_testing() -> Self {
@@ -422,6 +2322,7 @@ impl Config {
                 name: "test-model".to_string(),
                 provider: "anthropic".to_string(),
                 api_key: Some("test-key".to_string()),
+                api_key_source: None,
                 model: "claude-3-5-sonnet-20241022".to_string(),
                 max_tokens: default_max_tokens(),
                 temperature: default_temperature(),
@@ -429,6 +2330,16 @@ impl Config {
                 enable_thinking: None,
                 reasoning_effort: None,
                 reasoning_budget_tokens: None,
+                empty_response_retries: None,
+                stream_resume_retries: None,
+                deployment: None,
+                api_version: None,
+                retry: None,
+                rate_limit: None,
+                cache: None,
+                network: None,
+                fallbacks: Vec::new(),
+                race_with: None,
             }],
             phases: PhasesConfig {
                 research: PhaseConfig {
@@ -452,17 +2363,46 @@ impl Config {
                 timeout_seconds: 60,
                 max_memory_usage_mb: 4096,
                 max_cpu_usage_percent: 80,
+                status_file: None,
             },
             database: DatabaseConfig {
                 path: "./data/borg.db".to_string(),
+                backend: DatabaseBackend::File,
+                postgres: PostgresConfig::default(),
             },
             git: GitConfig {
                 branch_prefix: "borg/improvement/".to_string(),
+                author: CommitIdentity::default(),
+                signing: SigningConfig::default(),
+                checkout: CheckoutConfig::default(),
+                co_authored_by: false,
+                commit_message: CommitMessageConfig::default(),
+                worktrees: WorktreeConfig::default(),
+                github: GitHubConfig::default(),
+                rollback: RollbackConfig::default(),
+                sync: SyncConfig::default(),
+                pre_commit: PreCommitConfig::default(),
             },
             logging: LoggingConfig {
                 enabled: true,
                 llm_log_dir: "./logs/llm".to_string(),
+                event_log: None,
             },
+            testing: TestingConfig::default(),
+            review: ReviewConfig::default(),
+            budget: BudgetConfig::default(),
+            routing: RoutingConfig::default(),
+            guardrails: GuardrailsConfig::default(),
+            change_limits: ChangeLimitsConfig::default(),
+            mcp: McpConfig::default(),
+            sandbox: SandboxConfig::default(),
+            permissions: ToolPermissionsConfig::default(),
+            web_fetch: WebFetchConfig::default(),
+            index: IndexConfig::default(),
+            prompts: PromptsConfig::default(),
+            encryption: EncryptionConfig::default(),
+            retention: RetentionConfig::default(),
+            code_generation: CodeGenerationConfig::default(),
         }
     }
 }
@@ -563,17 +2503,46 @@ mod tests {
                 timeout_seconds: 60,
                 max_memory_usage_mb: 4096,
                 max_cpu_usage_percent: 80,
+                status_file: None,
             },
             database: DatabaseConfig {
                 path: "./data/borg.db".to_string(),
+                backend: DatabaseBackend::File,
+                postgres: PostgresConfig::default(),
             },
             git: GitConfig {
                 branch_prefix: "borg/".to_string(),
+                author: CommitIdentity::default(),
+                signing: SigningConfig::default(),
+                checkout: CheckoutConfig::default(),
+                co_authored_by: false,
+                commit_message: CommitMessageConfig::default(),
+                worktrees: WorktreeConfig::default(),
+                github: GitHubConfig::default(),
+                rollback: RollbackConfig::default(),
+                sync: SyncConfig::default(),
+                pre_commit: PreCommitConfig::default(),
             },
             logging: LoggingConfig {
                 enabled: true,
                 llm_log_dir: "./logs".to_string(),
+                event_log: None,
             },
+            testing: TestingConfig::default(),
+            review: ReviewConfig::default(),
+            budget: BudgetConfig::default(),
+            routing: RoutingConfig::default(),
+            guardrails: GuardrailsConfig::default(),
+            change_limits: ChangeLimitsConfig::default(),
+            mcp: McpConfig::default(),
+            sandbox: SandboxConfig::default(),
+            permissions: ToolPermissionsConfig::default(),
+            web_fetch: WebFetchConfig::default(),
+            index: IndexConfig::default(),
+            prompts: PromptsConfig::default(),
+            encryption: EncryptionConfig::default(),
+            retention: RetentionConfig::default(),
+            code_generation: CodeGenerationConfig::default(),
         };
 
         assert!(config.validate().is_err());
@@ -586,6 +2555,7 @@ mod tests {
                 name: "model1".to_string(),
                 provider: "anthropic".to_string(),
                 api_key: Some("key".to_string()),
+                api_key_source: None,
                 model: "claude-3-5-sonnet-20241022".to_string(),
                 max_tokens: 1000,
                 temperature: 0.7,
@@ -593,6 +2563,16 @@ mod tests {
                 enable_thinking: None,
                 reasoning_effort: None,
                 reasoning_budget_tokens: None,
+                empty_response_retries: None,
+                stream_resume_retries: None,
+                deployment: None,
+                api_version: None,
+                retry: None,
+                rate_limit: None,
+                cache: None,
+                network: None,
+                fallbacks: Vec::new(),
+                race_with: None,
             }],
             phases: PhasesConfig {
                 research: PhaseConfig {
@@ -616,17 +2596,46 @@ mod tests {
                 timeout_seconds: 60,
                 max_memory_usage_mb: 4096,
                 max_cpu_usage_percent: 80,
+                status_file: None,
             },
             database: DatabaseConfig {
                 path: "./data/borg.db".to_string(),
+                backend: DatabaseBackend::File,
+                postgres: PostgresConfig::default(),
             },
             git: GitConfig {
                 branch_prefix: "borg/".to_string(),
+                author: CommitIdentity::default(),
+                signing: SigningConfig::default(),
+                checkout: CheckoutConfig::default(),
+                co_authored_by: false,
+                commit_message: CommitMessageConfig::default(),
+                worktrees: WorktreeConfig::default(),
+                github: GitHubConfig::default(),
+                rollback: RollbackConfig::default(),
+                sync: SyncConfig::default(),
+                pre_commit: PreCommitConfig::default(),
             },
             logging: LoggingConfig {
                 enabled: true,
                 llm_log_dir: "./logs".to_string(),
+                event_log: None,
             },
+            testing: TestingConfig::default(),
+            review: ReviewConfig::default(),
+            budget: BudgetConfig::default(),
+            routing: RoutingConfig::default(),
+            guardrails: GuardrailsConfig::default(),
+            change_limits: ChangeLimitsConfig::default(),
+            mcp: McpConfig::default(),
+            sandbox: SandboxConfig::default(),
+            permissions: ToolPermissionsConfig::default(),
+            web_fetch: WebFetchConfig::default(),
+            index: IndexConfig::default(),
+            prompts: PromptsConfig::default(),
+            encryption: EncryptionConfig::default(),
+            retention: RetentionConfig::default(),
+            code_generation: CodeGenerationConfig::default(),
         };
 
         assert!(config.validate().is_err());
@@ -639,4 +2648,86 @@ mod tests {
         assert_eq!(config.models.len(), 1);
         assert_eq!(config.models[0].name, "test-model");
     }
+
+    #[test]
+    fn test_fallback_chain_is_resolved_and_validated() {
+        let mut config = Config::for_testing();
+        let mut local = config.models[0].clone();
+        local.name = "local".to_string();
+        local.provider = "ollama".to_string();
+        local.api_key = None;
+        config.models.push(local);
+        config.models[0].fallbacks = vec!["local".to_string()];
+
+        assert!(config.validate().is_ok());
+        let chain = config.fallback_models(&config.models[0]);
+        assert_eq!(chain.len(), 1);
+        assert_eq!(chain[0].name, "local");
+
+        config.models[0].fallbacks = vec!["missing".to_string()];
+        assert!(config.validate().is_err());
+
+        config.models[0].fallbacks = vec!["test-model".to_string()];
+        assert!(config.validate().is_err());
+
+        config.models[0].fallbacks = vec!["local".to_string()];
+        config.models[1].provider = "openai".to_string();
+        config.models[1].api_key = Some("sk-test".to_string());
+        assert!(config.validate().is_ok());
+        config.models[1].provider = "google".to_string();
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn test_race_partner_is_resolved_and_validated() {
+        let mut config = Config::for_testing();
+        let mut rival = config.models[0].clone();
+        rival.name = "rival".to_string();
+        config.models.push(rival);
+
+        assert!(config.race_model(&config.models[0]).is_none());
+        config.models[0].race_with = Some("rival".to_string());
+        assert!(config.validate().is_ok());
+        assert_eq!(
+            config.race_model(&config.models[0]).map(|m| m.name),
+            Some("rival".to_string())
+        );
+
+        config.models[0].race_with = Some("test-model".to_string());
+        assert!(config.validate().is_err());
+        config.models[0].race_with = Some("missing".to_string());
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn test_phase_tools_may_name_configured_mcp_servers() {
+        let mut config = Config::for_testing();
+        config.mcp.servers.push(
+            serde_yaml::from_str("name: fs\ncommand: mcp-server-filesystem\nargs: [\".\"]\n")
+                .unwrap(),
+        );
+        config.phases.research.tools =
+            vec!["mcp__fs".to_string(), "mcp__fs__read_file".to_string()];
+        assert!(config.validate().is_ok());
+        assert_eq!(config.mcp.servers[0].timeout_secs, 60);
+
+        config.phases.research.tools = vec!["mcp__db".to_string()];
+        assert!(config.validate().is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_api_key_resolved_from_secret_command() {
+        let mut config = Config::for_testing();
+        config.models[0].api_key = None;
+        config.models[0].api_key_source = serde_yaml::from_str(
+            "type: command\ncommand: [\"sh\", \"-c\", \"printf 'sk-team-key\\\\n'\"]\n",
+        )
+        .unwrap();
+
+        config.resolve_secrets().unwrap();
+
+        assert_eq!(config.models[0].api_key.as_deref(), Some("sk-team-key"));
+        assert!(config.validate().is_ok());
+    }
 }
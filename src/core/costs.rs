@@ -0,0 +1,313 @@
+//! LLM spend tracking and budget enforcement.
+//!
+//! Every completed LLM call is priced from the configured per-model table and
+//! added to a per-day, per-model ledger in the database. Before each call the
+//! tracker checks the run and daily ceilings and refuses further calls with
+//! `BorgError::BudgetExceeded` once either is reached.
+
+use anyhow::{Context, Result};
+use chrono::{NaiveDate, Utc};
+use log::{debug, warn};
+use serde::{Deserialize, Serialize};
+use std::sync::{Arc, Mutex};
+use tokio_util::sync::CancellationToken;
+
+use crate::core::config::{BudgetConfig, ModelPricing};
+use crate::core::error::BorgError;
+use crate::database::{DatabaseError, DatabaseInterface, Query};
+
+/// Rough characters-per-token ratio used when a provider reports no usage
+const CHARS_PER_TOKEN: usize = 4;
+
+/// Accumulated spend for one model on one day
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DailyCost {
+    /// Record key (`<date>::<model>`)
+    pub id: String,
+
+    /// UTC calendar day
+    pub date: NaiveDate,
+
+    /// Provider model id
+    pub model: String,
+
+    /// Number of recorded calls
+    pub calls: u64,
+
+    /// Prompt tokens across all calls
+    pub prompt_tokens: u64,
+
+    /// Completion tokens across all calls
+    pub completion_tokens: u64,
+
+    /// Spend in USD
+    pub cost_usd: f64,
+}
+
+impl DailyCost {
+    /// Empty ledger entry for a model on a day
+    pub fn new(date: NaiveDate, model: &str) -> Self {
+        Self {
+            id: Self::key(date, model),
+            date,
+            model: model.to_string(),
+            calls: 0,
+            prompt_tokens: 0,
+            completion_tokens: 0,
+            cost_usd: 0.0,
+        }
+    }
+
+    /// Record key for a model on a day
+    pub fn key(date: NaiveDate, model: &str) -> String {
+        format!("{}::{}", date, model)
+    }
+}
+
+/// Cost in USD of a call under `pricing`
+pub fn cost_of(pricing: &ModelPricing, prompt_tokens: u64, completion_tokens: u64) -> f64 {
+    (prompt_tokens as f64 * pricing.input_per_million
+        + completion_tokens as f64 * pricing.output_per_million)
+        / 1_000_000.0
+}
+
+/// Token estimate for text whose usage the provider did not report
+pub fn estimate_tokens(text: &str) -> u64 {
+    text.len().div_ceil(CHARS_PER_TOKEN) as u64
+}
+
+/// Prices LLM calls, persists the spend and enforces the configured ceilings
+pub struct CostTracker {
+    /// Pricing table and limits
+    config: BudgetConfig,
+
+    /// Persistent per-day ledger
+    ledger: Arc<dyn DatabaseInterface<DailyCost>>,
+
+    /// Spend since this tracker was created
+    run_cost: Mutex<f64>,
+
+    /// Serializes ledger read-modify-write cycles across parallel calls
+    write_lock: tokio::sync::Mutex<()>,
+
+    /// Cancelled once a ceiling is reached, aborting in-flight generations
+    on_exceeded: Option<CancellationToken>,
+}
+
+impl CostTracker {
+    /// Create a tracker for one agent run
+    pub fn new(config: BudgetConfig, ledger: Arc<dyn DatabaseInterface<DailyCost>>) -> Self {
+        Self {
+            config,
+            ledger,
+            run_cost: Mutex::new(0.0),
+            write_lock: tokio::sync::Mutex::new(()),
+            on_exceeded: None,
+        }
+    }
+
+    /// Cancel `token` as soon as recorded spend reaches a ceiling
+    pub fn with_cancellation(mut self, token: CancellationToken) -> Self {
+        self.on_exceeded = Some(token);
+        self
+    }
+
+    /// Spend in USD during this run
+    pub fn run_cost(&self) -> f64 {
+        *self.run_cost.lock().unwrap()
+    }
+
+    /// Spend in USD across all models on `date`
+    pub async fn daily_cost(&self, date: NaiveDate) -> Result<f64> {
+        let records = self
+            .ledger
+            .query(&Query::new().eq("date", date.to_string()))
+            .await
+            .context("Failed to load LLM cost ledger")?;
+        Ok(records.iter().map(|r| r.entity.cost_usd).sum())
+    }
+
+    /// Fail with `BorgError::BudgetExceeded` if a spending ceiling is reached
+    pub async fn ensure_within_budget(&self) -> Result<()> {
+        if let Some(limit) = self.config.max_run_usd {
+            let spent = self.run_cost();
+            if spent >= limit {
+                return Err(anyhow::anyhow!(BorgError::BudgetExceeded(format!(
+                    "spent ${:.4} this run, limit is ${:.2} (budget.max_run_usd)",
+                    spent, limit
+                ))));
+            }
+        }
+        if let Some(limit) = self.config.max_daily_usd {
+            let spent = self.daily_cost(Utc::now().date_naive()).await?;
+            if spent >= limit {
+                return Err(anyhow::anyhow!(BorgError::BudgetExceeded(format!(
+                    "spent ${:.4} today, limit is ${:.2} (budget.max_daily_usd)",
+                    spent, limit
+                ))));
+            }
+        }
+        Ok(())
+    }
+
+    /// Price a completed call, add it to the ledger and return its cost
+    pub async fn record_usage(
+        &self,
+        model: &str,
+        prompt_tokens: u64,
+        completion_tokens: u64,
+    ) -> Result<f64> {
+        let cost = match self.config.pricing.get(model) {
+            Some(pricing) => cost_of(pricing, prompt_tokens, completion_tokens),
+            None => {
+                debug!("No pricing configured for model '{}'", model);
+                0.0
+            }
+        };
+        *self.run_cost.lock().unwrap() += cost;
+
+        let _guard = self.write_lock.lock().await;
+        let date = Utc::now().date_naive();
+        let existing = match self.ledger.get(&DailyCost::key(date, model)).await {
+            Ok(record) => Some(record.entity),
+            Err(DatabaseError::NotFound(_)) => None,
+            Err(e) => return Err(e).context("Failed to load LLM cost ledger"),
+        };
+        let is_new = existing.is_none();
+        let mut entry = existing.unwrap_or_else(|| DailyCost::new(date, model));
+        entry.calls += 1;
+        entry.prompt_tokens += prompt_tokens;
+        entry.completion_tokens += completion_tokens;
+        entry.cost_usd += cost;
+
+        if is_new {
+            self.ledger.insert(entry).await
+        } else {
+            self.ledger.update(entry, None).await
+        }
+        .context("Failed to save LLM cost ledger")?;
+        drop(_guard);
+
+        if let Some(token) = &self.on_exceeded {
+            if let Err(e) = self.ensure_within_budget().await {
+                warn!("{:#}; cancelling in-flight generations", e);
+                token.cancel();
+            }
+        }
+
+        Ok(cost)
+    }
+}
+
+/// The cost tracker of the current services, if there is one
+pub fn global() -> Option<Arc<CostTracker>> {
+    crate::core::services::Services::current().costs.clone()
+}
+
+/// Check the process-wide budget before an LLM call
+pub async fn ensure_within_budget() -> Result<()> {
+    match global() {
+        Some(tracker) => tracker.ensure_within_budget().await,
+        None => Ok(()),
+    }
+}
+
+/// Record a completed call with the process-wide tracker, if any
+///
+/// Failing to persist the ledger is logged rather than failing the call.
+pub async fn record_usage(model: &str, prompt_tokens: u64, completion_tokens: u64) {
+    if let Some(tracker) = global() {
+        if let Err(e) = tracker
+            .record_usage(model, prompt_tokens, completion_tokens)
+            .await
+        {
+            warn!("Failed to record LLM cost: {:#}", e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::FileDb;
+    use std::collections::HashMap;
+    use tempfile::TempDir;
+
+    async fn open_tracker(dir: &TempDir, config: BudgetConfig) -> CostTracker {
+        let db = FileDb::<DailyCost>::new(dir.path(), "llm_costs")
+            .await
+            .unwrap();
+        CostTracker::new(config, Arc::new(db))
+    }
+
+    fn priced(max_run_usd: Option<f64>, max_daily_usd: Option<f64>) -> BudgetConfig {
+        BudgetConfig {
+            pricing: HashMap::from([(
+                "big-model".to_string(),
+                ModelPricing {
+                    input_per_million: 3.0,
+                    output_per_million: 15.0,
+                },
+            )]),
+            max_run_usd,
+            max_daily_usd,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_usage_is_priced_and_persisted() {
+        let dir = TempDir::new().unwrap();
+        let tracker = open_tracker(&dir, priced(None, None)).await;
+
+        let cost = tracker
+            .record_usage("big-model", 1_000, 2_000)
+            .await
+            .unwrap();
+        assert!((cost - 0.033).abs() < 1e-9, "{}", cost);
+        tracker.record_usage("big-model", 1_000, 0).await.unwrap();
+        assert_eq!(tracker.record_usage("unpriced", 50, 50).await.unwrap(), 0.0);
+
+        // A fresh tracker (new run) sees the persisted daily spend
+        let reopened = open_tracker(&dir, priced(None, None)).await;
+        assert_eq!(reopened.run_cost(), 0.0);
+        let today = reopened.daily_cost(Utc::now().date_naive()).await.unwrap();
+        assert!((today - 0.036).abs() < 1e-9, "{}", today);
+        let entry = reopened
+            .ledger
+            .get(&DailyCost::key(Utc::now().date_naive(), "big-model"))
+            .await
+            .unwrap()
+            .entity;
+        assert_eq!(entry.calls, 2);
+        assert_eq!(entry.prompt_tokens, 2_000);
+    }
+
+    #[tokio::test]
+    async fn test_run_and_daily_ceilings_abort_calls() {
+        let dir = TempDir::new().unwrap();
+        let tracker = open_tracker(&dir, priced(Some(0.01), None)).await;
+        tracker.ensure_within_budget().await.unwrap();
+        tracker.record_usage("big-model", 0, 1_000).await.unwrap();
+
+        let err = tracker.ensure_within_budget().await.unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<BorgError>(),
+            Some(BorgError::BudgetExceeded(_))
+        ));
+        assert!(err.to_string().contains("max_run_usd"));
+
+        let token = CancellationToken::new();
+        let watched = open_tracker(&dir, priced(Some(0.01), None))
+            .await
+            .with_cancellation(token.clone());
+        watched.record_usage("big-model", 0, 100).await.unwrap();
+        assert!(!token.is_cancelled());
+        watched.record_usage("big-model", 0, 1_000).await.unwrap();
+        assert!(token.is_cancelled());
+
+        // The daily ceiling also counts spend from earlier runs
+        let next_run = open_tracker(&dir, priced(None, Some(0.01))).await;
+        let err = next_run.ensure_within_budget().await.unwrap_err();
+        assert!(err.to_string().contains("max_daily_usd"));
+    }
+}
@@ -0,0 +1,303 @@
+//! Encryption at rest of the database and LLM logs.
+//!
+//! Prompts and responses often contain proprietary source code, and so do
+//! the goals, lessons and caches derived from them. With `encryption`
+//! enabled, every stored entity and every LLM log entry is sealed on its
+//! own with AES-256-GCM under a key from `encryption.key_source`, with a
+//! fresh random nonce and bound to the collection it belongs to. Values
+//! written before encryption was enabled are still read, and are sealed
+//! when next written or by `borg db migrate`.
+
+use aes_gcm::aead::{Aead, AeadCore, KeyInit, OsRng, Payload};
+use aes_gcm::{Aes256Gcm, Key, Nonce};
+use anyhow::{anyhow, bail, Context, Result};
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use serde_json::Value;
+use std::sync::Arc;
+
+use crate::core::config::EncryptionConfig;
+use crate::core::secrets::SecretSource;
+
+/// Variable holding the key when no `key_source` is configured
+pub const DEFAULT_KEY_ENV: &str = "BORG_ENCRYPTION_KEY";
+
+/// Field of the JSON envelope of a sealed value
+const SEALED_FIELD: &str = "sealed";
+
+/// Prefix of a sealed line of an LLM log
+const SEALED_LINE_PREFIX: &str = "sealed:";
+
+/// What LLM log entries are bound to
+const LOG_CONTEXT: &str = "llm_log";
+
+/// Length of the nonce in front of every sealed value
+const NONCE_LEN: usize = 12;
+
+/// Seals and opens values with one 256-bit key
+#[derive(Clone)]
+pub struct Cipher {
+    aead: Arc<Aes256Gcm>,
+}
+
+impl std::fmt::Debug for Cipher {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("Cipher { .. }")
+    }
+}
+
+impl Cipher {
+    /// A cipher with the 32-byte `key`
+    pub fn new(key: &[u8]) -> Result<Self> {
+        if key.len() != 32 {
+            bail!("Encryption key must be 32 bytes, got {}", key.len());
+        }
+        Ok(Self {
+            aead: Arc::new(Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(key))),
+        })
+    }
+
+    /// A cipher with a base64-encoded key
+    pub fn from_base64(key: &str) -> Result<Self> {
+        let key = BASE64
+            .decode(key.trim())
+            .context("Encryption key is not valid base64")?;
+        Self::new(&key)
+    }
+
+    /// A new random key, base64-encoded
+    pub fn generate_key() -> String {
+        BASE64.encode(Aes256Gcm::generate_key(&mut OsRng))
+    }
+
+    /// The cipher configured by `config`, or `None` when encryption is off
+    pub fn from_config(config: &EncryptionConfig) -> Result<Option<Self>> {
+        if !config.enabled {
+            return Ok(None);
+        }
+        let source = config.key_source.clone().unwrap_or(SecretSource::Env {
+            var: DEFAULT_KEY_ENV.to_string(),
+        });
+        let key = source
+            .resolve()
+            .context("Failed to load the encryption key")?;
+        Ok(Some(Self::from_base64(&key)?))
+    }
+
+    /// `plaintext` sealed for `context`, as nonce followed by ciphertext
+    pub fn seal(&self, plaintext: &[u8], context: &str) -> Result<Vec<u8>> {
+        let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+        let ciphertext = self
+            .aead
+            .encrypt(
+                &nonce,
+                Payload {
+                    msg: plaintext,
+                    aad: context.as_bytes(),
+                },
+            )
+            .map_err(|_| anyhow!("Failed to encrypt"))?;
+        let mut sealed = nonce.to_vec();
+        sealed.extend(ciphertext);
+        Ok(sealed)
+    }
+
+    /// The plaintext of a value sealed for `context`
+    pub fn open(&self, sealed: &[u8], context: &str) -> Result<Vec<u8>> {
+        if sealed.len() < NONCE_LEN {
+            bail!("Encrypted value is truncated");
+        }
+        let (nonce, ciphertext) = sealed.split_at(NONCE_LEN);
+        self.aead
+            .decrypt(
+                Nonce::from_slice(nonce),
+                Payload {
+                    msg: ciphertext,
+                    aad: context.as_bytes(),
+                },
+            )
+            .map_err(|_| anyhow!("Failed to decrypt: wrong key or corrupted data"))
+    }
+
+    /// `value` sealed into a `{"sealed": "<base64>"}` envelope
+    pub fn seal_json(&self, value: &Value, context: &str) -> Result<Value> {
+        let sealed = self.seal(&serde_json::to_vec(value)?, context)?;
+        Ok(serde_json::json!({ SEALED_FIELD: BASE64.encode(sealed) }))
+    }
+
+    /// The value in a sealed envelope; values that are not sealed are
+    /// returned as they are
+    pub fn open_json(&self, value: Value, context: &str) -> Result<Value> {
+        let Some(sealed) = sealed_payload(&value) else {
+            return Ok(value);
+        };
+        let sealed = BASE64
+            .decode(sealed)
+            .context("Encrypted value is not valid base64")?;
+        Ok(serde_json::from_slice(&self.open(&sealed, context)?)?)
+    }
+
+    /// `text` sealed for `context` as one line, without a line break
+    pub fn seal_line(&self, text: &str, context: &str) -> Result<String> {
+        let sealed = self.seal(text.as_bytes(), context)?;
+        Ok(format!("{}{}", SEALED_LINE_PREFIX, BASE64.encode(sealed)))
+    }
+
+    /// The text of a line sealed for `context`; lines that are not sealed
+    /// are returned as they are
+    pub fn open_line(&self, line: &str, context: &str) -> Result<String> {
+        let Some(sealed) = line.strip_prefix(SEALED_LINE_PREFIX) else {
+            return Ok(line.to_string());
+        };
+        let sealed = BASE64
+            .decode(sealed)
+            .context("Encrypted line is not valid base64")?;
+        Ok(String::from_utf8_lossy(&self.open(&sealed, context)?).into_owned())
+    }
+
+    /// An LLM log entry as one sealed line
+    pub fn seal_log_entry(&self, entry: &str) -> Result<String> {
+        Ok(format!("{}\n", self.seal_line(entry, LOG_CONTEXT)?))
+    }
+
+    /// The text of an LLM log, with its sealed lines opened
+    pub fn open_log(&self, contents: &str) -> Result<String> {
+        let mut text = String::new();
+        for line in contents.lines() {
+            if is_sealed_line(line) {
+                text.push_str(&self.open_line(line, LOG_CONTEXT)?);
+            } else {
+                text.push_str(line);
+                text.push('\n');
+            }
+        }
+        Ok(text)
+    }
+}
+
+fn sealed_payload(value: &Value) -> Option<&str> {
+    match value.as_object() {
+        Some(object) if object.len() == 1 => object.get(SEALED_FIELD)?.as_str(),
+        _ => None,
+    }
+}
+
+/// Whether `value` is a sealed envelope
+pub fn is_sealed(value: &Value) -> bool {
+    sealed_payload(value).is_some()
+}
+
+/// Whether `line` was sealed by `Cipher::seal_line`
+pub fn is_sealed_line(line: &str) -> bool {
+    line.starts_with(SEALED_LINE_PREFIX)
+}
+
+/// The cipher of the current services, if encryption is enabled
+pub fn global() -> Option<Cipher> {
+    crate::core::services::Services::current().cipher.clone()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_sealed_values_open_only_with_their_key_and_context() {
+        let cipher = Cipher::from_base64(&Cipher::generate_key()).unwrap();
+        let goal = json!({"id": "goal-1", "description": "Refactor src/secret.rs"});
+
+        let sealed = cipher.seal_json(&goal, "optimization_goals").unwrap();
+        assert!(is_sealed(&sealed));
+        assert!(!sealed.to_string().contains("secret"));
+        // Sealing twice gives different ciphertexts
+        assert_ne!(
+            sealed,
+            cipher.seal_json(&goal, "optimization_goals").unwrap()
+        );
+
+        assert_eq!(
+            cipher
+                .open_json(sealed.clone(), "optimization_goals")
+                .unwrap(),
+            goal
+        );
+        assert!(cipher.open_json(sealed.clone(), "lessons").is_err());
+        let other = Cipher::from_base64(&Cipher::generate_key()).unwrap();
+        assert!(other.open_json(sealed, "optimization_goals").is_err());
+        // Plain values pass through
+        assert_eq!(
+            cipher
+                .open_json(goal.clone(), "optimization_goals")
+                .unwrap(),
+            goal
+        );
+    }
+
+    #[test]
+    fn test_logs_mix_sealed_and_plain_lines() {
+        let cipher = Cipher::from_base64(&Cipher::generate_key()).unwrap();
+        let entry = "\n===== REQUEST: openai gpt =====\nPROMPT:\nfn secret() {}\n";
+        let log = format!("plain line\n{}", cipher.seal_log_entry(entry).unwrap());
+
+        assert!(!log.contains("secret"));
+        assert_eq!(
+            cipher.open_log(&log).unwrap(),
+            format!("plain line\n{}", entry)
+        );
+        assert!(Cipher::from_base64("c2hvcnQ=").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_file_collections_are_sealed_on_disk() {
+        use crate::core::optimization::OptimizationGoal;
+        use crate::database::{DatabaseError, FileDb};
+
+        let dir = tempfile::tempdir().unwrap();
+        let cipher = Cipher::from_base64(&Cipher::generate_key()).unwrap();
+        // Written before encryption was enabled
+        FileDb::<OptimizationGoal>::new(dir.path(), "goals")
+            .await
+            .unwrap()
+            .insert(OptimizationGoal::new(
+                "goal-1",
+                "Plain title",
+                "Description",
+            ))
+            .await
+            .unwrap();
+
+        let goals = FileDb::<OptimizationGoal>::open(dir.path(), "goals", Some(cipher.clone()))
+            .await
+            .unwrap();
+        assert_eq!(goals.migrate().await.unwrap(), 1);
+        goals
+            .insert(OptimizationGoal::new(
+                "goal-2",
+                "Sealed title",
+                "Description",
+            ))
+            .await
+            .unwrap();
+        let stored = std::fs::read_to_string(dir.path().join("goals.json")).unwrap();
+        assert!(!stored.contains("title"), "{}", stored);
+
+        assert!(matches!(
+            FileDb::<OptimizationGoal>::new(dir.path(), "goals").await,
+            Err(DatabaseError::EncryptionError(_))
+        ));
+        let reopened = FileDb::<OptimizationGoal>::open(dir.path(), "goals", Some(cipher))
+            .await
+            .unwrap();
+        assert_eq!(
+            reopened
+                .get(&"goal-1".to_string())
+                .await
+                .unwrap()
+                .entity
+                .title,
+            "Plain title"
+        );
+        assert_eq!(reopened.migrate().await.unwrap(), 0);
+    }
+}
@@ -46,6 +46,18 @@ pub enum BorgError {
     /// External command execution errors
     #[error("Command execution failed: {0}")]
     CommandError(String),
+
+    /// Provider answered successfully but returned no usable content
+    #[error("Empty response from LLM provider: {0}")]
+    EmptyResponse(String),
+
+    /// A configured LLM spending limit has been reached
+    #[error("LLM budget exceeded: {0}")]
+    BudgetExceeded(String),
+
+    /// Operation was cancelled before it completed
+    #[error("Cancelled: {0}")]
+    Cancelled(String),
 }
 
 /// Normalized provider-layer errors
@@ -101,6 +113,17 @@ pub enum ProviderError {
         status: Option<u16>,
     },
 
+    #[error("Context length exceeded: {message}")]
+    ContextLengthExceeded {
+        details: Option<String>,
+        message: String,
+        status: Option<u16>,
+        /// Model context limit, if the provider reported it
+        max_context_tokens: Option<usize>,
+        /// Prompt size in tokens, if the provider reported it
+        prompt_tokens: Option<usize>,
+    },
+
     #[error("Streaming timeout waiting for first token after {timeout_ms} ms")]
     TimeoutFirstToken { timeout_ms: u64 },
 
@@ -109,9 +132,28 @@ pub enum ProviderError {
 
     #[error("Network error: {message}")]
     Network { message: String },
+
+    #[error("Empty response: {message}")]
+    EmptyResponse { message: String },
+
+    #[error("Generation cancelled: {message}")]
+    Cancelled { message: String },
+
+    #[error("Response rejected by {filter}: {message}")]
+    Rejected { filter: String, message: String },
 }
 
 impl ProviderError {
+    /// Attach a server-requested retry delay to a rate-limit error
+    pub fn with_retry_after(mut self, delay_ms: Option<u64>) -> Self {
+        if let ProviderError::RateLimited { retry_after_ms, .. } = &mut self {
+            if delay_ms.is_some() {
+                *retry_after_ms = delay_ms;
+            }
+        }
+        self
+    }
+
     /// Convenience to construct an InvalidParams error
     pub fn invalid_params(message: impl Into<String>, status: Option<u16>) -> Self {
         ProviderError::InvalidParams {
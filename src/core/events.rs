@@ -0,0 +1,157 @@
+//! Newline-delimited JSON event stream of everything the agent does.
+//!
+//! This is the structured counterpart to the human-readable log: one JSON
+//! object per line, flushed as it is written, so `tail -f` (or any JSONL
+//! consumer) can follow a run live. Emission is opt-in; when no event log is
+//! configured every `emit` is a no-op.
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use log::warn;
+use serde::{Deserialize, Serialize};
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+/// Sink value selecting standard output instead of a file
+pub const STDOUT_SINK: &str = "-";
+
+/// Something the agent did
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum RunEvent {
+    /// A goal was chosen to work on
+    GoalSelected { goal_id: String, title: String },
+
+    /// A plan was created for a goal
+    PlanCreated {
+        plan_id: String,
+        goal_id: String,
+        steps: usize,
+    },
+
+    /// A plan step started
+    StepStarted {
+        step_id: String,
+        description: String,
+    },
+
+    /// A plan step finished
+    StepFinished { step_id: String, success: bool },
+
+    /// Summary of one LLM call
+    LlmCall {
+        model: String,
+        prompt_chars: usize,
+        response_chars: usize,
+        duration_ms: u64,
+        success: bool,
+    },
+
+    /// One tool invocation
+    ToolCall {
+        tool: String,
+        success: bool,
+        duration_ms: u64,
+    },
+
+    /// A tool call was allowed or refused by a permission rule or a human
+    ToolPermission {
+        tool: String,
+        allowed: bool,
+        asked: bool,
+    },
+
+    /// A branch was merged into the mainline
+    Merged { branch: String },
+
+    /// A merge was blocked by a gate
+    MergeBlocked { branch: String, reason: String },
+
+    /// A merge was reverted after validation found a regression
+    Reverted {
+        branch: String,
+        commit: String,
+        reason: String,
+    },
+
+    /// A plan finished
+    PlanFinished { plan_id: String, success: bool },
+}
+
+/// One line of the event stream
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EventRecord {
+    /// When the event was emitted
+    pub timestamp: DateTime<Utc>,
+
+    /// The event itself
+    #[serde(flatten)]
+    pub event: RunEvent,
+}
+
+/// Writes events as JSON lines to a sink
+pub struct EventLog {
+    sink: Mutex<Box<dyn Write + Send>>,
+}
+
+impl EventLog {
+    /// Create an event log writing to an arbitrary sink
+    pub fn new(sink: Box<dyn Write + Send>) -> Self {
+        Self {
+            sink: Mutex::new(sink),
+        }
+    }
+
+    /// Open the configured sink: `-` for stdout, otherwise a file appended to
+    pub fn open(sink: &str) -> Result<Self> {
+        if sink == STDOUT_SINK {
+            return Ok(Self::new(Box::new(std::io::stdout())));
+        }
+
+        let path = Path::new(sink);
+        if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+            std::fs::create_dir_all(parent)
+                .with_context(|| format!("Failed to create directory {:?}", parent))?;
+        }
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .with_context(|| format!("Failed to open event log {:?}", path))?;
+        Ok(Self::new(Box::new(file)))
+    }
+
+    /// Append an event; write failures are logged, never returned
+    pub fn emit(&self, event: RunEvent) {
+        let record = EventRecord {
+            timestamp: Utc::now(),
+            event,
+        };
+        let line = match serde_json::to_string(&record) {
+            Ok(line) => line,
+            Err(e) => {
+                warn!("Failed to serialize run event: {}", e);
+                return;
+            }
+        };
+
+        let mut sink = self.sink.lock().unwrap();
+        if let Err(e) = writeln!(sink, "{}", line).and_then(|_| sink.flush()) {
+            warn!("Failed to write run event: {}", e);
+        }
+    }
+}
+
+/// The event log of the current services, if there is one
+pub fn global() -> Option<Arc<EventLog>> {
+    crate::core::services::Services::current().events.clone()
+}
+
+/// Emit an event to the process-wide event log, if any
+pub fn emit(event: RunEvent) {
+    if let Some(log) = global() {
+        log.emit(event);
+    }
+}
@@ -0,0 +1,192 @@
+//! Goals queued and edited by hand.
+//!
+//! `borg goal` adds, lists, shows, edits and cancels the optimization
+//! goals in the database, so work can be queued without editing data files
+//! or waiting for the agent to find it. The files a goal touches are kept
+//! as `file:` tags, as on the goals the agent generates.
+
+use std::str::FromStr;
+
+use anyhow::{bail, Result};
+
+use crate::core::optimization::{
+    GoalStatus, OptimizationCategory, OptimizationGoal, PriorityLevel,
+};
+
+/// Prefix of the tags naming the files a goal touches
+pub const FILE_TAG_PREFIX: &str = "file:";
+
+/// A goal priority, given as 1-100 or as a level name
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Priority(pub u8);
+
+impl FromStr for Priority {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.trim().parse::<u8>() {
+            Ok(value @ 1..=100) => Ok(Priority(value)),
+            Ok(_) => bail!("Priority must be between 1 and 100, got {}", s),
+            Err(_) => Ok(Priority(s.parse::<PriorityLevel>()?.into())),
+        }
+    }
+}
+
+/// Changes to make to a goal
+#[derive(Debug, Clone, Default)]
+pub struct GoalEdit {
+    pub title: Option<String>,
+    pub description: Option<String>,
+    pub category: Option<OptimizationCategory>,
+    pub priority: Option<Priority>,
+
+    /// Files to tag the goal with
+    pub add_files: Vec<String>,
+
+    /// Files to no longer tag the goal with
+    pub remove_files: Vec<String>,
+
+    /// Success metrics to add
+    pub add_metrics: Vec<String>,
+
+    /// Success metrics to remove, by their text
+    pub remove_metrics: Vec<String>,
+}
+
+impl GoalEdit {
+    /// Whether the edit changes nothing
+    pub fn is_empty(&self) -> bool {
+        self.title.is_none()
+            && self.description.is_none()
+            && self.category.is_none()
+            && self.priority.is_none()
+            && self.add_files.is_empty()
+            && self.remove_files.is_empty()
+            && self.add_metrics.is_empty()
+            && self.remove_metrics.is_empty()
+    }
+
+    /// Make the changes to `goal`
+    pub fn apply(&self, goal: &mut OptimizationGoal) {
+        if let Some(title) = &self.title {
+            goal.title = title.clone();
+        }
+        if let Some(description) = &self.description {
+            goal.description = description.clone();
+        }
+        if let Some(category) = &self.category {
+            // Generated goals also carry their category as a tag, which
+            // would otherwise win over the new one
+            let old_tag = goal.category.to_string().to_lowercase();
+            goal.tags.retain(|tag| *tag != old_tag);
+            goal.category = category.clone();
+        }
+        if let Some(Priority(priority)) = self.priority {
+            goal.priority = priority;
+        }
+
+        goal.tags.retain(|tag| {
+            tag.strip_prefix(FILE_TAG_PREFIX)
+                .is_none_or(|file| !self.remove_files.iter().any(|removed| removed == file))
+        });
+        for file in &self.add_files {
+            let tag = format!("{}{}", FILE_TAG_PREFIX, file);
+            if !goal.tags.contains(&tag) {
+                goal.tags.push(tag);
+            }
+        }
+
+        goal.success_metrics
+            .retain(|metric| !self.remove_metrics.contains(metric));
+        for metric in &self.add_metrics {
+            if !goal.success_metrics.contains(metric) {
+                goal.success_metrics.push(metric.clone());
+            }
+        }
+        goal.updated_at = chrono::Utc::now();
+    }
+}
+
+/// A new goal with a fresh id, made as `edit` asks
+pub fn new_goal(title: &str, description: &str, edit: &GoalEdit) -> OptimizationGoal {
+    let id = format!("goal-{}", &uuid::Uuid::new_v4().simple().to_string()[..8]);
+    let mut goal = OptimizationGoal::new(&id, title, description);
+    edit.apply(&mut goal);
+    goal
+}
+
+/// Whether `goal` is still to be worked on
+pub fn is_open(goal: &OptimizationGoal) -> bool {
+    matches!(goal.status, GoalStatus::NotStarted | GoalStatus::InProgress)
+}
+
+/// The files `goal` is tagged with
+pub fn files(goal: &OptimizationGoal) -> impl Iterator<Item = &str> {
+    goal.tags
+        .iter()
+        .filter_map(|tag| tag.strip_prefix(FILE_TAG_PREFIX))
+}
+
+/// Abandon an open goal, noting why
+pub fn cancel(goal: &mut OptimizationGoal, reason: &str) -> Result<()> {
+    if !is_open(goal) {
+        bail!("Goal {} is already {}", goal.id, goal.status);
+    }
+    goal.update_status(GoalStatus::Abandoned);
+    let note = format!("Cancelled by hand: {}", reason);
+    goal.implementation_notes = Some(match goal.implementation_notes.take() {
+        Some(notes) if !notes.is_empty() => format!("{}\n{}", notes, note),
+        _ => note,
+    });
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_edits_replace_fields_and_merge_files_and_metrics() {
+        let edit = GoalEdit {
+            category: Some("performance".parse().unwrap()),
+            priority: Some("high".parse().unwrap()),
+            add_files: vec!["src/db.rs".to_string(), "src/main.rs".to_string()],
+            add_metrics: vec!["p95 latency below 20ms".to_string()],
+            ..GoalEdit::default()
+        };
+        let mut goal = new_goal("Speed up queries", "Index the goals table", &edit);
+        assert!(goal.id.starts_with("goal-"));
+        assert_eq!(goal.category, OptimizationCategory::Performance);
+        assert_eq!(goal.priority, 75);
+
+        goal.tags.push("performance".to_string());
+        GoalEdit {
+            category: Some("Error Handling".parse().unwrap()),
+            priority: Some("90".parse().unwrap()),
+            add_files: vec!["src/db.rs".to_string()],
+            remove_files: vec!["src/main.rs".to_string()],
+            remove_metrics: vec!["p95 latency below 20ms".to_string()],
+            ..GoalEdit::default()
+        }
+        .apply(&mut goal);
+        assert_eq!(goal.category, OptimizationCategory::ErrorHandling);
+        assert_eq!(goal.priority, 90);
+        assert_eq!(goal.tags, ["file:src/db.rs"]);
+        assert_eq!(files(&goal).collect::<Vec<_>>(), ["src/db.rs"]);
+        assert!(goal.success_metrics.is_empty());
+        assert!("0".parse::<Priority>().is_err());
+        assert!("urgent".parse::<Priority>().is_err());
+    }
+
+    #[test]
+    fn test_only_open_goals_are_cancelled() {
+        let mut goal = new_goal("Title", "Description", &GoalEdit::default());
+        cancel(&mut goal, "superseded").unwrap();
+        assert_eq!(goal.status, GoalStatus::Abandoned);
+        assert_eq!(
+            goal.implementation_notes.as_deref(),
+            Some("Cancelled by hand: superseded")
+        );
+        assert!(cancel(&mut goal, "again").is_err());
+    }
+}
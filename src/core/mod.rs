@@ -1,7 +1,16 @@
 pub mod agent;
+pub mod calibration;
 pub mod config;
+pub mod costs;
+pub mod encryption;
 pub mod error;
 pub mod ethics;
+pub mod events;
+pub mod goals;
 pub mod optimization;
+pub mod retention;
+pub mod secrets;
+pub mod services;
+pub mod status;
 pub mod strategies;
 pub mod strategy;
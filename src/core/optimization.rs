@@ -3,11 +3,12 @@ use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::fmt;
+use std::str::FromStr;
 use std::sync::Arc;
 use tokio::sync::Mutex;
 
 /// Categories of optimization goals that the agent can pursue
-#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, Default)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, Hash, Default)]
 pub enum OptimizationCategory {
     /// Improve code performance (speed, memory usage, etc.)
     Performance,
@@ -54,6 +55,31 @@ impl fmt::Display for OptimizationCategory {
     }
 }
 
+impl FromStr for OptimizationCategory {
+    type Err = anyhow::Error;
+
+    /// Parse a category by name, ignoring case, spaces, dashes and
+    /// underscores, so that `test-coverage` and "Test Coverage" both work
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        match normalized(s).as_str() {
+            "performance" => Ok(OptimizationCategory::Performance),
+            "readability" => Ok(OptimizationCategory::Readability),
+            "testcoverage" => Ok(OptimizationCategory::TestCoverage),
+            "security" => Ok(OptimizationCategory::Security),
+            "complexity" => Ok(OptimizationCategory::Complexity),
+            "errorhandling" => Ok(OptimizationCategory::ErrorHandling),
+            "compatibility" => Ok(OptimizationCategory::Compatibility),
+            "financial" => Ok(OptimizationCategory::Financial),
+            "general" => Ok(OptimizationCategory::General),
+            _ => Err(anyhow::anyhow!(
+                "Unknown category '{}': expected performance, readability, test-coverage, \
+                 security, complexity, error-handling, compatibility, financial or general",
+                s
+            )),
+        }
+    }
+}
+
 /// Priority level for optimization goals
 #[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord)]
 pub enum PriorityLevel {
@@ -103,6 +129,23 @@ impl fmt::Display for PriorityLevel {
     }
 }
 
+impl FromStr for PriorityLevel {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        match normalized(s).as_str() {
+            "low" => Ok(PriorityLevel::Low),
+            "medium" => Ok(PriorityLevel::Medium),
+            "high" => Ok(PriorityLevel::High),
+            "critical" => Ok(PriorityLevel::Critical),
+            _ => Err(anyhow::anyhow!(
+                "Unknown priority '{}': expected low, medium, high or critical",
+                s
+            )),
+        }
+    }
+}
+
 /// Status of an optimization goal
 #[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
 pub enum GoalStatus {
@@ -134,6 +177,32 @@ impl fmt::Display for GoalStatus {
     }
 }
 
+impl FromStr for GoalStatus {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        match normalized(s).as_str() {
+            "notstarted" => Ok(GoalStatus::NotStarted),
+            "inprogress" => Ok(GoalStatus::InProgress),
+            "completed" => Ok(GoalStatus::Completed),
+            "failed" => Ok(GoalStatus::Failed),
+            "abandoned" => Ok(GoalStatus::Abandoned),
+            _ => Err(anyhow::anyhow!(
+                "Unknown status '{}': expected not-started, in-progress, completed, failed or abandoned",
+                s
+            )),
+        }
+    }
+}
+
+/// `s` in lower case without spaces, dashes or underscores
+fn normalized(s: &str) -> String {
+    s.chars()
+        .filter(|c| !matches!(c, ' ' | '-' | '_'))
+        .flat_map(char::to_lowercase)
+        .collect()
+}
+
 /// Resource estimates for a goal
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
 pub struct ResourceEstimates {
@@ -235,6 +304,11 @@ pub struct OptimizationGoal {
     #[serde(default)]
     pub dependencies: Vec<String>,
 
+    /// Goal whose branch this goal's branch is built on until that goal
+    /// merges; it is merged only after that goal
+    #[serde(default)]
+    pub stacked_on: Option<String>,
+
     /// Improvement level aimed for (-5 to 5, negative is regression)
     #[serde(default)]
     pub improvement_target: i8,
@@ -293,6 +367,7 @@ impl OptimizationGoal {
             milestone_id: None,
             related_goals: Vec::new(),
             dependencies: Vec::new(),
+            stacked_on: None,
             improvement_target: 0,
             ethical_considerations: Vec::new(),
             code_samples: Vec::new(),
@@ -340,6 +415,10 @@ impl OptimizationGoal {
             details.push('\n');
         }
 
+        if let Some(base) = &self.stacked_on {
+            details.push_str(&format!("## Stacked On\n{}\n\n", base));
+        }
+
         if !self.ethical_considerations.is_empty() {
             details.push_str("## Ethical Considerations\n");
             for consideration in &self.ethical_considerations {
@@ -480,22 +559,56 @@ impl OptimizationManager {
             .collect()
     }
 
+    /// Whether `goal` can be started: a goal stacked on another waits until
+    /// that goal has been started, so there is a branch to build on
+    fn stack_ready(&self, goal: &OptimizationGoal) -> bool {
+        goal.stacked_on
+            .as_deref()
+            .and_then(|base| self.get_goal(base))
+            .is_none_or(|base| base.status != GoalStatus::NotStarted)
+    }
+
     /// Get the next most important goal to work on
     pub fn get_next_goal(&self) -> Option<&OptimizationGoal> {
         // Get not started goals sorted by priority
         let mut candidate_goals: Vec<&OptimizationGoal> = self
             .goals
             .iter()
-            .filter(|g| g.status == GoalStatus::NotStarted)
+            .filter(|g| g.status == GoalStatus::NotStarted && self.stack_ready(g))
             .collect();
 
         // Sort by priority (highest first)
-        candidate_goals.sort_by(|a, b| b.priority.cmp(&a.priority));
+        candidate_goals.sort_by_key(|b| std::cmp::Reverse(b.priority));
 
         // Get the highest priority goal
         candidate_goals.first().copied()
     }
 
+    /// Get the next goal, weighting priority by each category's historical
+    /// success rate so goal types that keep failing are deprioritized
+    ///
+    /// `success_rates` maps a category name (as displayed) to its calibrated
+    /// success probability; categories without history use the prior.
+    pub fn get_next_goal_calibrated(
+        &self,
+        success_rates: &HashMap<String, f64>,
+    ) -> Option<&OptimizationGoal> {
+        let score = |goal: &OptimizationGoal| {
+            let rate = success_rates
+                .get(&goal.category.to_string())
+                .copied()
+                .unwrap_or(crate::core::calibration::PRIOR_SUCCESS_PROBABILITY);
+            goal.priority as f64 * rate
+        };
+
+        self.goals
+            .iter()
+            .filter(|g| g.status == GoalStatus::NotStarted && self.stack_ready(g))
+            // Reversed so ties go to the earliest goal, as in `get_next_goal`
+            .rev()
+            .max_by(|a, b| score(a).total_cmp(&score(b)))
+    }
+
     /// Assess the ethics of all goals
     pub async fn assess_all_goals_ethics(&mut self) {
         let mut ethics_manager = self.ethics_manager.lock().await;
@@ -0,0 +1,282 @@
+//! Retention of logs and finished goals.
+//!
+//! LLM logs, the tool call audit log and finished goals grow with every
+//! run. The compactor removes what the `retention` policies no longer
+//! keep: on start and every `compaction_interval_hours` while the agent
+//! runs, and on demand with `borg db vacuum`. Finished goals go together
+//! with their todo lists, and are kept while an open goal depends on or is
+//! stacked on them.
+
+use std::collections::HashSet;
+use std::fmt;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use log::{info, warn};
+
+use crate::code_generation::llm_logging;
+use crate::code_generation::todos::TodoList;
+use crate::code_generation::tool_audit::ToolInvocation;
+use crate::core::config::{Config, RetentionConfig, RetentionPolicy};
+use crate::core::optimization::{GoalStatus, OptimizationGoal};
+use crate::database::{DatabaseInterface, DatabaseManager, Transaction};
+
+/// The items `policy` does not keep: those older than `max_age_days` and
+/// those past the `keep` most recent
+pub fn expired<T>(
+    policy: &RetentionPolicy,
+    mut items: Vec<(T, DateTime<Utc>)>,
+    now: DateTime<Utc>,
+) -> Vec<T> {
+    items.sort_by_key(|(_, at)| std::cmp::Reverse(*at));
+    let cutoff = policy
+        .max_age_days
+        .map(|days| now - chrono::Duration::days(days as i64));
+    items
+        .into_iter()
+        .enumerate()
+        .filter(|(rank, (_, at))| {
+            policy.keep.is_some_and(|keep| *rank >= keep)
+                || cutoff.is_some_and(|cutoff| *at < cutoff)
+        })
+        .map(|(_, (item, _))| item)
+        .collect()
+}
+
+/// What one compaction removed
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Compaction {
+    /// LLM log files deleted
+    pub llm_logs: usize,
+
+    /// Tool calls removed from the audit log
+    pub execution_logs: usize,
+
+    /// Finished goals removed
+    pub completed_goals: usize,
+}
+
+impl fmt::Display for Compaction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} LLM log files, {} tool calls and {} finished goals removed",
+            self.llm_logs, self.execution_logs, self.completed_goals
+        )
+    }
+}
+
+/// Removes logs and finished goals past their retention
+pub struct Compactor {
+    config: RetentionConfig,
+    llm_log_dir: PathBuf,
+    goals: Arc<dyn DatabaseInterface<OptimizationGoal>>,
+    todos: Arc<dyn DatabaseInterface<TodoList>>,
+    tool_invocations: Arc<dyn DatabaseInterface<ToolInvocation>>,
+}
+
+impl Compactor {
+    /// A compactor of the collections of `database` and the LLM logs of
+    /// `config`
+    pub fn new(config: &Config, database: &DatabaseManager) -> Self {
+        Self {
+            config: config.retention.clone(),
+            llm_log_dir: PathBuf::from(&config.logging.llm_log_dir),
+            goals: database.goals(),
+            todos: database.todos(),
+            tool_invocations: database.tool_invocations(),
+        }
+    }
+
+    /// Remove everything past its retention now
+    pub async fn compact(&self) -> Result<Compaction> {
+        let llm_logs = if self.llm_log_dir.is_dir() {
+            llm_logging::prune_logs(&self.llm_log_dir, &self.config.llm_logs)?
+        } else {
+            0
+        };
+        Ok(Compaction {
+            llm_logs,
+            execution_logs: self.compact_execution_logs().await?,
+            completed_goals: self.compact_goals().await?,
+        })
+    }
+
+    /// Compact now and then every `compaction_interval_hours`, until the
+    /// task is dropped
+    pub async fn run(self: Arc<Self>) {
+        let hours = self.config.compaction_interval_hours;
+        if hours == 0 {
+            return;
+        }
+        let mut interval = tokio::time::interval(Duration::from_secs(hours * 60 * 60));
+        loop {
+            interval.tick().await;
+            match self.compact().await {
+                Ok(compaction) if compaction != Compaction::default() => {
+                    info!("Compacted stored data: {}", compaction)
+                }
+                Ok(_) => {}
+                Err(e) => warn!("Compaction failed: {:#}", e),
+            }
+        }
+    }
+
+    async fn compact_execution_logs(&self) -> Result<usize> {
+        if self.config.execution_logs == RetentionPolicy::default() {
+            return Ok(0);
+        }
+        let calls = self
+            .tool_invocations
+            .get_all()
+            .await?
+            .into_iter()
+            .map(|record| (record.entity.id, record.entity.timestamp))
+            .collect();
+        let expired = expired(&self.config.execution_logs, calls, Utc::now());
+        let mut txn = Transaction::new();
+        for id in &expired {
+            txn.delete(self.tool_invocations.as_ref(), id);
+        }
+        txn.commit()
+            .await
+            .context("Failed to remove expired tool calls")?;
+        Ok(expired.len())
+    }
+
+    async fn compact_goals(&self) -> Result<usize> {
+        if self.config.completed_goals == RetentionPolicy::default() {
+            return Ok(0);
+        }
+        let goals: Vec<OptimizationGoal> = self
+            .goals
+            .get_all()
+            .await?
+            .into_iter()
+            .map(|record| record.entity)
+            .collect();
+        let (finished, open): (Vec<_>, Vec<_>) = goals.into_iter().partition(|goal| {
+            matches!(
+                goal.status,
+                GoalStatus::Completed | GoalStatus::Failed | GoalStatus::Abandoned
+            )
+        });
+        let needed: HashSet<&str> = open
+            .iter()
+            .flat_map(|goal| goal.dependencies.iter().chain(&goal.stacked_on))
+            .map(String::as_str)
+            .collect();
+        let finished = finished
+            .iter()
+            .filter(|goal| !needed.contains(goal.id.as_str()))
+            .map(|goal| (goal.id.clone(), goal.updated_at))
+            .collect();
+        let expired = expired(&self.config.completed_goals, finished, Utc::now());
+
+        let todos: HashSet<String> = self
+            .todos
+            .get_all()
+            .await?
+            .into_iter()
+            .map(|record| record.entity.id)
+            .collect();
+        let mut txn = Transaction::new();
+        for id in &expired {
+            txn.delete(self.goals.as_ref(), id);
+            if todos.contains(id) {
+                txn.delete(self.todos.as_ref(), id);
+            }
+        }
+        txn.commit()
+            .await
+            .context("Failed to remove expired goals")?;
+        Ok(expired.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_expired_applies_age_and_count_limits() {
+        let now = Utc::now();
+        let items: Vec<(&str, DateTime<Utc>)> =
+            [("today", 0), ("ten", 10), ("hundred", 100), ("forty", 40)]
+                .into_iter()
+                .map(|(name, days)| (name, now - chrono::Duration::days(days)))
+                .collect();
+        let policy = |max_age_days, keep| RetentionPolicy { max_age_days, keep };
+
+        assert!(expired(&policy(None, None), items.clone(), now).is_empty());
+        assert_eq!(
+            expired(&policy(Some(30), None), items.clone(), now),
+            ["forty", "hundred"]
+        );
+        assert_eq!(
+            expired(&policy(None, Some(1)), items.clone(), now),
+            ["ten", "forty", "hundred"]
+        );
+        assert_eq!(expired(&policy(Some(90), Some(3)), items, now), ["hundred"]);
+    }
+
+    #[tokio::test]
+    async fn test_compaction_keeps_goals_open_goals_depend_on() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut config: Config = serde_yaml::from_str(&format!(
+            r#"
+models: []
+phases:
+  research: {{ models: [], tools: [], prompt: "" }}
+  deliberation: {{ models: [], tools: [], prompt: "" }}
+  tdd: {{ models: [], tools: [], prompt: "" }}
+agent: {{ working_dir: ., timeout_seconds: 60, max_memory_usage_mb: 1024, max_cpu_usage_percent: 80 }}
+database: {{ path: ./data/borg.db }}
+git: {{ branch_prefix: borg/ }}
+logging: {{ enabled: false, llm_log_dir: {} }}
+"#,
+            dir.path().join("logs").display()
+        ))
+        .unwrap();
+        config.retention.completed_goals.keep = Some(0);
+        let database = DatabaseManager::new(dir.path().join("data"), &config)
+            .await
+            .unwrap();
+
+        for id in ["base", "done", "open"] {
+            let mut goal = OptimizationGoal::new(id, "Title", "Description");
+            if id == "open" {
+                goal.dependencies.push("base".to_string());
+            } else {
+                goal.update_status(GoalStatus::Completed);
+            }
+            database.goals().insert(goal).await.unwrap();
+        }
+        database
+            .todos()
+            .insert(TodoList {
+                id: "done".to_string(),
+                items: Vec::new(),
+                updated_at: Utc::now(),
+            })
+            .await
+            .unwrap();
+
+        let compaction = Compactor::new(&config, &database).compact().await.unwrap();
+        assert_eq!(compaction.completed_goals, 1);
+        let mut left: Vec<String> = database
+            .goals()
+            .get_all()
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|record| record.entity.id)
+            .collect();
+        left.sort();
+        assert_eq!(left, ["base", "open"]);
+        assert!(database.todos().get_all().await.unwrap().is_empty());
+    }
+}
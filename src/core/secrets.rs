@@ -0,0 +1,218 @@
+//! Pluggable sources for credentials such as provider API keys.
+//!
+//! A credential can be given inline in the config (optionally via `${VAR}`
+//! expansion) or through a `SecretSource` that is resolved once at startup,
+//! so keys held in a secret manager never have to be written to disk.
+
+use anyhow::{bail, Context, Result};
+use serde::Deserialize;
+use std::path::PathBuf;
+use std::process::Command;
+
+/// Resolves a single secret value
+pub trait SecretProvider: Send + Sync {
+    /// Fetch the secret value
+    fn resolve(&self) -> Result<String>;
+}
+
+/// Reads a secret from an environment variable
+pub struct EnvSecretProvider {
+    /// Variable name
+    var: String,
+}
+
+impl EnvSecretProvider {
+    /// Create a provider reading the given variable
+    pub fn new(var: impl Into<String>) -> Self {
+        Self { var: var.into() }
+    }
+}
+
+impl SecretProvider for EnvSecretProvider {
+    fn resolve(&self) -> Result<String> {
+        std::env::var(&self.var)
+            .with_context(|| format!("Environment variable '{}' is not set", self.var))
+    }
+}
+
+/// Reads a secret from a file, ignoring surrounding whitespace
+pub struct FileSecretProvider {
+    /// File holding the secret
+    path: PathBuf,
+}
+
+impl FileSecretProvider {
+    /// Create a provider reading the given file
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+}
+
+impl SecretProvider for FileSecretProvider {
+    fn resolve(&self) -> Result<String> {
+        let contents = std::fs::read_to_string(&self.path)
+            .with_context(|| format!("Failed to read secret file {:?}", self.path))?;
+        Ok(contents.trim().to_string())
+    }
+}
+
+/// Runs an external command (e.g. `op read ...`, `vault kv get ...`) and uses
+/// its standard output as the secret
+pub struct CommandSecretProvider {
+    /// Program followed by its arguments; no shell is involved
+    command: Vec<String>,
+}
+
+impl CommandSecretProvider {
+    /// Create a provider running the given program and arguments
+    pub fn new(command: Vec<String>) -> Self {
+        Self { command }
+    }
+}
+
+impl SecretProvider for CommandSecretProvider {
+    fn resolve(&self) -> Result<String> {
+        let Some((program, args)) = self.command.split_first() else {
+            bail!("Secret command is empty");
+        };
+
+        let output = Command::new(program)
+            .args(args)
+            .output()
+            .with_context(|| format!("Failed to run secret command '{}'", program))?;
+
+        if !output.status.success() {
+            bail!(
+                "Secret command '{}' exited with {}: {}",
+                program,
+                output.status,
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+        }
+
+        Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+    }
+}
+
+/// Reads a secret from the OS keychain: the macOS Keychain, the Windows
+/// Credential Manager or the Linux kernel keyring
+pub struct KeychainSecretProvider {
+    /// Service the secret is stored under
+    service: String,
+
+    /// Account the secret is stored under
+    account: String,
+}
+
+impl KeychainSecretProvider {
+    /// Create a provider reading the secret of `account` in `service`
+    pub fn new(service: impl Into<String>, account: impl Into<String>) -> Self {
+        Self {
+            service: service.into(),
+            account: account.into(),
+        }
+    }
+
+    /// Store `secret` in the keychain
+    pub fn store(&self, secret: &str) -> Result<()> {
+        keyring::Entry::new(&self.service, &self.account)
+            .and_then(|entry| entry.set_password(secret))
+            .with_context(|| {
+                format!(
+                    "Failed to store {}/{} in the keychain",
+                    self.service, self.account
+                )
+            })
+    }
+}
+
+impl SecretProvider for KeychainSecretProvider {
+    fn resolve(&self) -> Result<String> {
+        keyring::Entry::new(&self.service, &self.account)
+            .and_then(|entry| entry.get_password())
+            .with_context(|| {
+                format!(
+                    "Failed to read {}/{} from the keychain",
+                    self.service, self.account
+                )
+            })
+    }
+}
+
+/// Where a credential comes from, as selected in config
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum SecretSource {
+    /// Environment variable
+    Env { var: String },
+
+    /// File containing only the secret
+    File { path: PathBuf },
+
+    /// External command printing the secret to stdout
+    Command { command: Vec<String> },
+
+    /// Entry of the OS keychain
+    Keychain { service: String, account: String },
+}
+
+impl SecretSource {
+    /// Provider implementing this source
+    pub fn provider(&self) -> Box<dyn SecretProvider> {
+        match self {
+            SecretSource::Env { var } => Box::new(EnvSecretProvider::new(var.clone())),
+            SecretSource::File { path } => Box::new(FileSecretProvider::new(path.clone())),
+            SecretSource::Command { command } => {
+                Box::new(CommandSecretProvider::new(command.clone()))
+            }
+            SecretSource::Keychain { service, account } => {
+                Box::new(KeychainSecretProvider::new(service, account))
+            }
+        }
+    }
+
+    /// Resolve the secret, rejecting empty values
+    pub fn resolve(&self) -> Result<String> {
+        let value = self.provider().resolve()?;
+        if value.is_empty() {
+            bail!("Secret source {:?} resolved to an empty value", self);
+        }
+        Ok(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(unix)]
+    #[test]
+    fn test_command_source_uses_command_output() {
+        let source: SecretSource = serde_yaml::from_str(
+            "type: command\ncommand: [\"sh\", \"-c\", \"echo sk-from-vault\"]\n",
+        )
+        .unwrap();
+
+        assert_eq!(source.resolve().unwrap(), "sk-from-vault");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_failing_command_is_an_error() {
+        let source = SecretSource::Command {
+            command: vec!["sh".to_string(), "-c".to_string(), "exit 3".to_string()],
+        };
+
+        assert!(source.resolve().is_err());
+    }
+
+    #[test]
+    fn test_file_source_trims_whitespace() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("key");
+        std::fs::write(&path, "sk-on-disk\n").unwrap();
+
+        let source = SecretSource::File { path };
+        assert_eq!(source.resolve().unwrap(), "sk-on-disk");
+    }
+}
@@ -0,0 +1,119 @@
+//! Services shared by the agent's components.
+//!
+//! The agent builds one `Services` from its configuration and installs it
+//! for the process; the accessors of the owning modules (`todos::global`,
+//! `costs::global`, ...) read it from there. `Services::scope` runs a future
+//! with other services instead, leaving the installed ones untouched, which
+//! is how tests give their code a store of its own.
+
+use std::future::Future;
+use std::sync::{Arc, OnceLock, RwLock};
+
+use crate::code_generation::memory::MemoryStore;
+use crate::code_generation::permissions::PermissionPolicy;
+use crate::code_generation::prompt::PromptTemplates;
+use crate::code_generation::sandbox::Sandbox;
+use crate::code_generation::semantic_index::SemanticIndex;
+use crate::code_generation::todos::TodoStore;
+use crate::code_generation::tool_audit::ToolAuditLog;
+use crate::core::costs::CostTracker;
+use crate::core::encryption::Cipher;
+use crate::core::events::EventLog;
+use crate::mcp::McpClient;
+use crate::providers::filter::ResponseFilter;
+use crate::version_control::rollback::MergeLedger;
+
+/// The services of a run; a missing one disables what depends on it
+#[derive(Clone, Default)]
+pub struct Services {
+    /// Structured run events
+    pub events: Option<Arc<EventLog>>,
+
+    /// Filters every LLM response goes through
+    pub filters: Vec<Arc<dyn ResponseFilter>>,
+
+    /// Isolation of agent-issued commands
+    pub sandbox: Arc<Sandbox>,
+
+    /// Rules deciding whether tool calls may run
+    pub permissions: Arc<PermissionPolicy>,
+
+    /// Prompt templates, the built-in ones by default
+    pub prompts: Arc<PromptTemplates>,
+
+    /// Connections to the configured MCP servers
+    pub mcp: Vec<Arc<McpClient>>,
+
+    /// Pricing and budget of LLM calls
+    pub costs: Option<Arc<CostTracker>>,
+
+    /// Record of every tool call
+    pub tool_audit: Option<Arc<ToolAuditLog>>,
+
+    /// Todo lists of goals
+    pub todos: Option<Arc<TodoStore>>,
+
+    /// Merges of goals, for rollback
+    pub merges: Option<Arc<MergeLedger>>,
+
+    /// Embedding index of the workspace
+    pub semantic_index: Option<Arc<SemanticIndex>>,
+
+    /// Lessons from earlier goals
+    pub lessons: Option<Arc<MemoryStore>>,
+
+    /// Cipher for LLM logs, when encryption is enabled
+    pub cipher: Option<Cipher>,
+}
+
+tokio::task_local! {
+    static SCOPED: Arc<Services>;
+}
+
+fn installed() -> &'static RwLock<Arc<Services>> {
+    static INSTALLED: OnceLock<RwLock<Arc<Services>>> = OnceLock::new();
+    INSTALLED.get_or_init(Default::default)
+}
+
+impl Services {
+    /// Make these the services of the process, replacing the ones
+    /// installed before
+    pub fn install(self: Arc<Self>) {
+        *installed().write().unwrap() = self;
+    }
+
+    /// Run `fut` with these services; tasks it spawns see the installed
+    /// ones
+    pub async fn scope<F: Future>(self: Arc<Self>, fut: F) -> F::Output {
+        SCOPED.scope(self, fut).await
+    }
+
+    /// The services of the enclosing `scope`, or the installed ones
+    pub fn current() -> Arc<Services> {
+        SCOPED
+            .try_with(Arc::clone)
+            .unwrap_or_else(|_| installed().read().unwrap().clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_scoped_services_do_not_leak() {
+        let scoped = Arc::new(Services::default());
+        let seen = scoped
+            .clone()
+            .scope(async {
+                // Nested scopes replace the outer services
+                let inner = Arc::new(Services::default());
+                let nested = inner.clone().scope(async { Services::current() }).await;
+                assert!(Arc::ptr_eq(&nested, &inner));
+                Services::current()
+            })
+            .await;
+        assert!(Arc::ptr_eq(&seen, &scoped));
+        assert!(!Arc::ptr_eq(&Services::current(), &scoped));
+    }
+}
@@ -0,0 +1,149 @@
+//! Small JSON status file describing what the agent is currently doing.
+//!
+//! The file is rewritten atomically on every update so an external dashboard
+//! can poll it without ever reading a half-written document.
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use log::warn;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+/// Snapshot of the agent's progress
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct RunStatus {
+    /// Goal being worked on
+    pub goal_id: Option<String>,
+
+    /// Human-readable goal title
+    pub goal_title: Option<String>,
+
+    /// Description of the step in progress
+    pub step: Option<String>,
+
+    /// 1-based index of the step in progress (0 before the first step)
+    pub step_index: usize,
+
+    /// Number of steps in the current goal
+    pub total_steps: usize,
+
+    /// Fraction of steps completed (0.0-1.0)
+    pub progress: f64,
+
+    /// Outcome of the most recently finished goal
+    pub last_outcome: Option<String>,
+
+    /// When this snapshot was written
+    pub updated_at: Option<DateTime<Utc>>,
+}
+
+/// Writes `RunStatus` snapshots to a file
+pub struct StatusReporter {
+    /// Destination of the status file
+    path: PathBuf,
+
+    /// Latest status
+    status: Mutex<RunStatus>,
+}
+
+impl StatusReporter {
+    /// Create a reporter writing to the given path
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            status: Mutex::new(RunStatus::default()),
+        }
+    }
+
+    /// Path of the status file
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Latest status snapshot
+    pub fn current(&self) -> RunStatus {
+        self.status.lock().unwrap().clone()
+    }
+
+    /// Record that work on a goal has started
+    pub fn start_goal(&self, goal_id: &str, goal_title: &str, total_steps: usize) {
+        self.update(|status| {
+            status.goal_id = Some(goal_id.to_string());
+            status.goal_title = Some(goal_title.to_string());
+            status.step = None;
+            status.step_index = 0;
+            status.total_steps = total_steps;
+            status.progress = 0.0;
+        });
+    }
+
+    /// Record that a step (0-based index) has started
+    pub fn start_step(&self, index: usize, description: &str) {
+        self.update(|status| {
+            status.step = Some(description.to_string());
+            status.step_index = index + 1;
+            status.progress = Self::fraction(index, status.total_steps);
+        });
+    }
+
+    /// Record that the current goal finished with the given outcome
+    pub fn finish_goal(&self, outcome: &str) {
+        self.update(|status| {
+            status.step = None;
+            status.progress = 1.0;
+            status.last_outcome = Some(outcome.to_string());
+        });
+    }
+
+    /// Apply a change to the status and write the file
+    ///
+    /// Write failures are logged rather than returned; monitoring must never
+    /// interrupt a run.
+    pub fn update(&self, change: impl FnOnce(&mut RunStatus)) {
+        let snapshot = {
+            let mut status = self.status.lock().unwrap();
+            change(&mut status);
+            status.updated_at = Some(Utc::now());
+            status.clone()
+        };
+
+        if let Err(e) = self.write(&snapshot) {
+            warn!("Failed to write status file {:?}: {}", self.path, e);
+        }
+    }
+
+    /// Write a snapshot via a temporary sibling file and rename
+    fn write(&self, status: &RunStatus) -> Result<()> {
+        if let Some(parent) = self.path.parent().filter(|p| !p.as_os_str().is_empty()) {
+            fs::create_dir_all(parent)
+                .with_context(|| format!("Failed to create directory {:?}", parent))?;
+        }
+
+        let mut tmp_name = self.path.file_name().unwrap_or_default().to_os_string();
+        tmp_name.push(".tmp");
+        let tmp_path = self.path.with_file_name(tmp_name);
+
+        let json = serde_json::to_vec_pretty(status).context("Failed to serialize status")?;
+        fs::write(&tmp_path, json).with_context(|| format!("Failed to write {:?}", tmp_path))?;
+        fs::rename(&tmp_path, &self.path)
+            .with_context(|| format!("Failed to replace {:?}", self.path))?;
+        Ok(())
+    }
+
+    /// Completed fraction, treating an unknown step count as no progress
+    fn fraction(done: usize, total: usize) -> f64 {
+        if total == 0 {
+            0.0
+        } else {
+            done as f64 / total as f64
+        }
+    }
+}
+
+/// Read a status file written by `StatusReporter`
+pub fn read_status(path: &Path) -> Result<RunStatus> {
+    let text = fs::read_to_string(path).with_context(|| format!("Failed to read {:?}", path))?;
+    serde_json::from_str(&text).with_context(|| format!("Invalid status file {:?}", path))
+}
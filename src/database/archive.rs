@@ -0,0 +1,273 @@
+//! Portable archives of the agent's stored state.
+//!
+//! An archive is a JSON Lines file. The first line is a manifest with the
+//! number of records of each collection and a SHA-256 checksum over their
+//! lines; every other line is one record, as `{"collection", "record"}`.
+//! Backups taken with encryption enabled seal each record line, so the
+//! archive is no easier to read than the database it came from; the
+//! manifest stays readable so an archive can be identified without the key.
+//! Checksums are over the plain lines and are verified before an archive
+//! is restored.
+
+use std::collections::BTreeMap;
+use std::io::{BufRead, Write};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+
+use crate::core::encryption::{self, Cipher};
+use crate::database::{DatabaseError, DbResult};
+
+/// Version of the archive format written
+pub const ARCHIVE_VERSION: u32 = 1;
+
+/// What sealed record lines are bound to
+const ARCHIVE_CONTEXT: &str = "borg_archive";
+
+/// First line of an archive
+#[derive(Debug, Serialize, Deserialize)]
+struct Manifest {
+    /// Version of the archive format
+    borg_archive: u32,
+
+    /// When the archive was taken
+    created_at: DateTime<Utc>,
+
+    /// What each collection should hold
+    collections: BTreeMap<String, Checksum>,
+}
+
+/// Number and checksum of the records of one collection
+#[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
+struct Checksum {
+    records: usize,
+    sha256: String,
+}
+
+/// One record line of an archive
+#[derive(Serialize, Deserialize)]
+struct Line {
+    collection: String,
+    record: Value,
+}
+
+/// The records of several collections, as stored
+#[derive(Debug, Clone)]
+pub struct Archive {
+    /// When the archive was taken
+    pub created_at: DateTime<Utc>,
+
+    collections: BTreeMap<String, Vec<Value>>,
+}
+
+impl Default for Archive {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Archive {
+    /// An empty archive taken now
+    pub fn new() -> Self {
+        Self {
+            created_at: Utc::now(),
+            collections: BTreeMap::new(),
+        }
+    }
+
+    /// Add the records of `collection`
+    pub fn add(&mut self, collection: &str, records: Vec<Value>) {
+        self.collections.insert(collection.to_string(), records);
+    }
+
+    /// The records of `collection`, if the archive has it
+    pub fn collection(&self, collection: &str) -> Option<&[Value]> {
+        self.collections.get(collection).map(Vec::as_slice)
+    }
+
+    /// Names of the collections in the archive
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.collections.keys().map(String::as_str)
+    }
+
+    /// Number of records of each collection
+    pub fn summary(&self) -> Vec<(&str, usize)> {
+        self.collections
+            .iter()
+            .map(|(name, records)| (name.as_str(), records.len()))
+            .collect()
+    }
+
+    /// Write the archive, sealing its records with `cipher` if given
+    pub fn write(&self, out: &mut impl Write, cipher: Option<&Cipher>) -> DbResult<()> {
+        let mut manifest = Manifest {
+            borg_archive: ARCHIVE_VERSION,
+            created_at: self.created_at,
+            collections: BTreeMap::new(),
+        };
+        let mut lines = Vec::new();
+        for (name, records) in &self.collections {
+            let mut hasher = Sha256::new();
+            for record in records {
+                let line = serde_json::to_string(&Line {
+                    collection: name.clone(),
+                    record: record.clone(),
+                })?;
+                hasher.update(line.as_bytes());
+                hasher.update(b"\n");
+                lines.push(line);
+            }
+            manifest.collections.insert(
+                name.clone(),
+                Checksum {
+                    records: records.len(),
+                    sha256: hex(&hasher.finalize()),
+                },
+            );
+        }
+
+        writeln!(out, "{}", serde_json::to_string(&manifest)?)?;
+        for line in lines {
+            match cipher {
+                Some(cipher) => writeln!(
+                    out,
+                    "{}",
+                    cipher
+                        .seal_line(&line, ARCHIVE_CONTEXT)
+                        .map_err(|e| DatabaseError::EncryptionError(e.to_string()))?
+                )?,
+                None => writeln!(out, "{}", line)?,
+            }
+        }
+        out.flush()?;
+        Ok(())
+    }
+
+    /// Read an archive, opening sealed records with `cipher`; fails when
+    /// the records do not match the manifest
+    pub fn read(input: impl BufRead, cipher: Option<&Cipher>) -> DbResult<Self> {
+        let mut lines = input.lines();
+        let manifest: Manifest = match lines.next() {
+            Some(line) => serde_json::from_str(&line?)
+                .map_err(|e| archive_error(format!("Not a Borg archive: {}", e)))?,
+            None => return Err(archive_error("The archive is empty")),
+        };
+        if manifest.borg_archive > ARCHIVE_VERSION {
+            return Err(archive_error(format!(
+                "The archive is in format version {}, newer than this build's {}",
+                manifest.borg_archive, ARCHIVE_VERSION
+            )));
+        }
+
+        let mut collections: BTreeMap<String, Vec<Value>> = manifest
+            .collections
+            .keys()
+            .map(|name| (name.clone(), Vec::new()))
+            .collect();
+        let mut hashers: BTreeMap<String, Sha256> = BTreeMap::new();
+        for (number, line) in lines.enumerate() {
+            let line = line?;
+            if line.is_empty() {
+                continue;
+            }
+            let line = if encryption::is_sealed_line(&line) {
+                let cipher = cipher.ok_or_else(|| {
+                    DatabaseError::EncryptionError(
+                        "The archive is encrypted but encryption is not enabled".to_string(),
+                    )
+                })?;
+                cipher
+                    .open_line(&line, ARCHIVE_CONTEXT)
+                    .map_err(|e| DatabaseError::EncryptionError(e.to_string()))?
+            } else {
+                line
+            };
+            let parsed: Line = serde_json::from_str(&line)
+                .map_err(|e| archive_error(format!("Line {}: {}", number + 2, e)))?;
+            let records = collections.get_mut(&parsed.collection).ok_or_else(|| {
+                archive_error(format!(
+                    "Line {} is in collection {}, which the manifest does not list",
+                    number + 2,
+                    parsed.collection
+                ))
+            })?;
+            let hasher = hashers.entry(parsed.collection).or_default();
+            hasher.update(line.as_bytes());
+            hasher.update(b"\n");
+            records.push(parsed.record);
+        }
+
+        for (name, expected) in &manifest.collections {
+            let found = Checksum {
+                records: collections[name].len(),
+                sha256: hex(&hashers.remove(name).unwrap_or_default().finalize()),
+            };
+            if &found != expected {
+                return Err(archive_error(format!(
+                    "Collection {} does not match its checksum ({} records, expected {}); the archive is corrupted",
+                    name, found.records, expected.records
+                )));
+            }
+        }
+        Ok(Self {
+            created_at: manifest.created_at,
+            collections,
+        })
+    }
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn archive_error(message: impl Into<String>) -> DatabaseError {
+    DatabaseError::ArchiveError(message.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn archive() -> Archive {
+        let mut archive = Archive::new();
+        archive.add(
+            "optimization_goals",
+            vec![json!({"entity": {"id": "goal-1", "title": "Speed up src/db.rs"}, "version": 3})],
+        );
+        archive.add("lessons", Vec::new());
+        archive
+    }
+
+    #[test]
+    fn test_archives_round_trip_and_detect_tampering() {
+        for cipher in [
+            None,
+            Some(Cipher::from_base64(&Cipher::generate_key()).unwrap()),
+        ] {
+            let mut written = Vec::new();
+            archive().write(&mut written, cipher.as_ref()).unwrap();
+            let text = String::from_utf8(written.clone()).unwrap();
+            assert_eq!(text.contains("src/db.rs"), cipher.is_none());
+
+            let read = Archive::read(written.as_slice(), cipher.as_ref()).unwrap();
+            assert_eq!(read.summary(), [("lessons", 0), ("optimization_goals", 1)]);
+            assert_eq!(
+                read.collection("optimization_goals").unwrap()[0]["version"],
+                3
+            );
+        }
+
+        let mut written = Vec::new();
+        archive().write(&mut written, None).unwrap();
+        let tampered = String::from_utf8(written)
+            .unwrap()
+            .replace("\"version\":3", "\"version\":4");
+        assert!(matches!(
+            Archive::read(tampered.as_bytes(), None),
+            Err(DatabaseError::ArchiveError(_))
+        ));
+    }
+}
@@ -0,0 +1,130 @@
+//! Typed access to a collection.
+//!
+//! `Collection<T>` wraps a collection of either backend and hands out
+//! entities rather than records, for callers that do not need the
+//! version and timestamps of what they read.
+
+use std::sync::Arc;
+
+use serde::Deserialize;
+
+use crate::database::query::Query;
+use crate::database::{DatabaseError, DatabaseInterface, DbResult, Entity, Record};
+
+/// A collection of `T`, returning entities
+pub struct Collection<T: Entity + for<'a> Deserialize<'a> + Unpin> {
+    inner: Arc<dyn DatabaseInterface<T>>,
+}
+
+impl<T: Entity + for<'a> Deserialize<'a> + Unpin> Clone for Collection<T> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: Arc::clone(&self.inner),
+        }
+    }
+}
+
+impl<T: Entity + for<'a> Deserialize<'a> + Unpin> Collection<T> {
+    /// Wrap a collection
+    pub fn new(inner: Arc<dyn DatabaseInterface<T>>) -> Self {
+        Self { inner }
+    }
+
+    /// The wrapped collection, for versioned updates and transactions
+    pub fn raw(&self) -> &Arc<dyn DatabaseInterface<T>> {
+        &self.inner
+    }
+
+    /// The entity with `id`, if stored
+    pub async fn get(&self, id: &T::Id) -> DbResult<Option<T>> {
+        match self.inner.get(id).await {
+            Ok(record) => Ok(Some(record.entity)),
+            Err(DatabaseError::NotFound(_)) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// The entities matching `query`
+    pub async fn find(&self, query: &Query) -> DbResult<Vec<T>> {
+        Ok(self
+            .find_records(query)
+            .await?
+            .into_iter()
+            .map(|record| record.entity)
+            .collect())
+    }
+
+    /// The records matching `query`
+    pub async fn find_records(&self, query: &Query) -> DbResult<Vec<Record<T>>> {
+        self.inner.query(query).await
+    }
+
+    /// The first entity matching `query`
+    pub async fn first(&self, query: &Query) -> DbResult<Option<T>> {
+        Ok(self.find(&query.clone().limit(1)).await?.into_iter().next())
+    }
+
+    /// Number of entities matching `query`
+    pub async fn count(&self, query: &Query) -> DbResult<usize> {
+        Ok(self.inner.query(query).await?.len())
+    }
+
+    /// Store `entity`, replacing the stored one with its id
+    pub async fn save(&self, entity: T) -> DbResult<T> {
+        let record = match self.inner.get(&entity.id()).await {
+            Ok(_) => self.inner.update(entity, None).await?,
+            Err(DatabaseError::NotFound(_)) => self.inner.insert(entity).await?,
+            Err(e) => return Err(e),
+        };
+        Ok(record.entity)
+    }
+
+    /// Remove the entity with `id`
+    pub async fn delete(&self, id: &T::Id) -> DbResult<()> {
+        self.inner.delete(id).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::optimization::{GoalStatus, OptimizationGoal};
+    use crate::database::{FileDb, SortOrder};
+
+    #[tokio::test]
+    async fn test_collection_saves_and_queries_entities() {
+        let dir = tempfile::tempdir().unwrap();
+        let goals: Collection<OptimizationGoal> =
+            Collection::new(Arc::new(FileDb::new(dir.path(), "goals").await.unwrap()));
+
+        for (id, priority) in [("goal-1", 10), ("goal-2", 90), ("goal-3", 50)] {
+            let mut goal = OptimizationGoal::new(id, "Title", "Description");
+            goal.priority = priority;
+            goals.save(goal).await.unwrap();
+        }
+        let mut done = goals.get(&"goal-3".to_string()).await.unwrap().unwrap();
+        done.update_status(GoalStatus::Completed);
+        goals.save(done).await.unwrap();
+
+        let open = Query::new()
+            .eq("status", "NotStarted")
+            .sort_by("priority", SortOrder::Descending);
+        let ids: Vec<String> = goals
+            .find(&open)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|goal| goal.id)
+            .collect();
+        assert_eq!(ids, ["goal-2", "goal-1"]);
+        assert_eq!(
+            goals
+                .count(&Query::new().eq("status", "Completed"))
+                .await
+                .unwrap(),
+            1
+        );
+        assert_eq!(goals.first(&open).await.unwrap().unwrap().id, "goal-2");
+        assert!(goals.get(&"missing".to_string()).await.unwrap().is_none());
+    }
+}
@@ -1,11 +1,123 @@
+use crate::code_generation::memory::Lesson;
+use crate::code_generation::semantic_index::{self, IndexedFile};
+use crate::code_generation::todos::TodoList;
+use crate::code_generation::tool_audit::ToolInvocation;
+use crate::code_generation::web_fetch::CachedPage;
+use crate::core::calibration::OutcomeStats;
+use crate::core::costs::DailyCost;
 use crate::core::optimization::OptimizationGoal;
-use crate::database::models::Entity;
+use crate::database::models::{Entity, EntityMigration};
+use crate::database::vector::VectorEntry;
+use crate::providers::cache::CachedResponse;
+use crate::version_control::rollback::MergeRecord;
 use std::marker::Unpin;
 
 /// Implementation of Entity trait for OptimizationGoal
 impl Entity for OptimizationGoal {
     type Id = String;
 
+    const INDEXED_FIELDS: &'static [&'static str] = &["status", "category", "created_at"];
+
+    fn id(&self) -> Self::Id {
+        self.id.clone()
+    }
+}
+
+/// Implementation of Entity trait for OutcomeStats
+impl Entity for OutcomeStats {
+    type Id = String;
+
+    fn id(&self) -> Self::Id {
+        self.id.clone()
+    }
+}
+
+/// Implementation of Entity trait for DailyCost
+impl Entity for DailyCost {
+    type Id = String;
+
+    const INDEXED_FIELDS: &'static [&'static str] = &["date"];
+
+    fn id(&self) -> Self::Id {
+        self.id.clone()
+    }
+}
+
+/// Implementation of Entity trait for CachedResponse
+impl Entity for CachedResponse {
+    type Id = String;
+
+    fn id(&self) -> Self::Id {
+        self.id.clone()
+    }
+}
+
+/// Implementation of Entity trait for ToolInvocation
+impl Entity for ToolInvocation {
+    type Id = String;
+
+    const INDEXED_FIELDS: &'static [&'static str] = &["tool", "timestamp"];
+
+    fn id(&self) -> Self::Id {
+        self.id.clone()
+    }
+}
+
+/// Implementation of Entity trait for CachedPage
+impl Entity for CachedPage {
+    type Id = String;
+
+    fn id(&self) -> Self::Id {
+        self.id.clone()
+    }
+}
+
+/// Implementation of Entity trait for TodoList
+impl Entity for TodoList {
+    type Id = String;
+
+    fn id(&self) -> Self::Id {
+        self.id.clone()
+    }
+}
+
+/// Implementation of Entity trait for IndexedFile
+impl Entity for IndexedFile {
+    type Id = String;
+
+    const SCHEMA_VERSION: u32 = 2;
+
+    fn id(&self) -> Self::Id {
+        self.id.clone()
+    }
+
+    fn migrations() -> &'static [EntityMigration] {
+        &[semantic_index::move_vectors_out]
+    }
+}
+
+/// Implementation of Entity trait for Lesson
+impl Entity for Lesson {
+    type Id = String;
+
+    fn id(&self) -> Self::Id {
+        self.id.clone()
+    }
+}
+
+/// Implementation of Entity trait for MergeRecord
+impl Entity for MergeRecord {
+    type Id = String;
+
+    fn id(&self) -> Self::Id {
+        self.id.clone()
+    }
+}
+
+/// Implementation of Entity trait for VectorEntry
+impl Entity for VectorEntry {
+    type Id = String;
+
     fn id(&self) -> Self::Id {
         self.id.clone()
     }
@@ -13,3 +125,13 @@ impl Entity for OptimizationGoal {
 
 // Implement Unpin for all entity types
 impl Unpin for OptimizationGoal {}
+impl Unpin for OutcomeStats {}
+impl Unpin for DailyCost {}
+impl Unpin for CachedResponse {}
+impl Unpin for ToolInvocation {}
+impl Unpin for CachedPage {}
+impl Unpin for TodoList {}
+impl Unpin for IndexedFile {}
+impl Unpin for Lesson {}
+impl Unpin for MergeRecord {}
+impl Unpin for VectorEntry {}
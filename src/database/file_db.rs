@@ -1,17 +1,24 @@
 use std::collections::HashMap;
 use std::fmt::Debug;
-use std::fs::{self, File};
-use std::io::{BufReader, BufWriter};
-use std::marker::PhantomData;
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufReader, BufWriter, ErrorKind};
 use std::path::{Path, PathBuf};
-use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::SystemTime;
 
-use log::{debug, error, info};
+use log::{debug, info};
 use serde::Deserialize;
 use thiserror::Error;
-use tokio::sync::RwLock;
+use tokio::sync::{OwnedRwLockWriteGuard, RwLock, RwLockReadGuard};
 
+use super::migration;
 use super::models::{Entity, Record};
+use super::query::{FieldIndex, Query};
+use super::transaction::{
+    CommitMarker, FileParticipant, LockedFile, Participant, ParticipantKind, StagedWrite, WriteOp,
+};
+use crate::core::encryption::{self, Cipher};
 
 /// Result type for database operations
 pub type DbResult<T> = Result<T, DatabaseError>;
@@ -34,233 +41,739 @@ pub enum DatabaseError {
     #[error("JSON serialization error: {0}")]
     SerializationError(#[from] serde_json::Error),
 
+    #[error("Migration error: {0}")]
+    MigrationError(String),
+
+    #[error("Encryption error: {0}")]
+    EncryptionError(String),
+
+    #[error("Archive error: {0}")]
+    ArchiveError(String),
+
     #[error("Internal database error: {0}")]
     InternalError(String),
 }
 
 /// A file-based database for storing entities
+///
+/// Every write is made under a process-wide lock of the collection and an
+/// advisory lock of its `.lock` file, on the records as last written by
+/// any process, and lands by writing a temporary file and renaming it over
+/// the collection file. Reads notice when another process replaced the
+/// file and load it again. Opening a collection finishes a transaction
+/// that was interrupted while moving its files into place.
 pub struct FileDb<T: Entity + for<'a> Deserialize<'a> + Unpin> {
-    /// Data directory where collection files are stored
-    data_dir: PathBuf,
+    /// The collection file
+    file: Arc<CollectionFile>,
 
-    /// Name of the collection (used as filename)
-    collection_name: String,
+    /// The records as last read or written
+    state: Arc<RwLock<State<T>>>,
 
-    /// In-memory cache of records
-    cache: Arc<RwLock<HashMap<T::Id, Record<T>>>>,
+    /// Serializes the writers of the collection in this process, across
+    /// every `FileDb` opened on it
+    writers: Arc<RwLock<()>>,
 
-    /// Phantom data for the entity type
-    _phantom: PhantomData<T>,
+    /// Records upgraded to the current schema, or still to be encrypted,
+    /// on load and not yet saved
+    outdated: Arc<AtomicUsize>,
 }
 
 impl<T: Entity + for<'a> Deserialize<'a> + Unpin> FileDb<T> {
     /// Create a new file database
     pub async fn new(data_dir: impl AsRef<Path>, collection_name: &str) -> DbResult<Self> {
-        let data_dir = data_dir.as_ref().to_path_buf();
+        Self::open(data_dir, collection_name, None).await
+    }
 
+    /// Open a file database whose entities are encrypted with `cipher`,
+    /// if given
+    pub async fn open(
+        data_dir: impl AsRef<Path>,
+        collection_name: &str,
+        cipher: Option<Cipher>,
+    ) -> DbResult<Self> {
         // Create data directory if it doesn't exist
-        fs::create_dir_all(&data_dir).map_err(DatabaseError::IoError)?;
-
+        fs::create_dir_all(data_dir.as_ref()).map_err(DatabaseError::IoError)?;
+        let data_dir = fs::canonicalize(data_dir.as_ref()).map_err(DatabaseError::IoError)?;
+
+        let file = Arc::new(CollectionFile {
+            path: data_dir.join(format!("{}.json", collection_name)),
+            collection: collection_name.to_string(),
+            cipher,
+        });
+        recover(&data_dir, &file.path).await?;
         let db = Self {
-            data_dir,
-            collection_name: collection_name.to_string(),
-            cache: Arc::new(RwLock::new(HashMap::new())),
-            _phantom: PhantomData,
+            writers: writers_of(&file.path),
+            file,
+            state: Arc::new(RwLock::new(State::new(HashMap::new(), None))),
+            outdated: Arc::new(AtomicUsize::new(0)),
         };
 
         // Load initial data
-        db.load_all().await?;
+        drop(db.current().await?);
 
         Ok(db)
     }
 
-    /// Get the path to the collection file
-    fn collection_path(&self) -> PathBuf {
-        self.data_dir.join(format!("{}.json", self.collection_name))
+    /// The records, loaded again first if another writer replaced the
+    /// collection file
+    async fn current(&self) -> DbResult<RwLockReadGuard<'_, State<T>>> {
+        {
+            let state = self.state.read().await;
+            if state.stamp == self.file.stamp()? {
+                return Ok(state);
+            }
+        }
+        // Wait out a write of this process to the file
+        let _writers = self.writers.read().await;
+        let mut state = self.state.write().await;
+        self.file.refresh(&mut state, &self.outdated)?;
+        Ok(state.downgrade())
     }
 
-    /// Load all records from disk
-    async fn load_all(&self) -> DbResult<()> {
-        let path = self.collection_path();
+    /// Apply `change` to the records and write them to the collection
+    /// file, holding the collection against every other writer; nothing
+    /// changes when either fails
+    async fn modify<R>(
+        &self,
+        change: impl FnOnce(&mut HashMap<T::Id, Record<T>>) -> DbResult<R>,
+    ) -> DbResult<R> {
+        let _writers = self.writers.write().await;
+        let _lock = self.file.lock().await?;
+        let mut state = self.state.write().await;
+        self.file.refresh(&mut state, &self.outdated)?;
+
+        let mut records = state.records.clone();
+        let result = change(&mut records)?;
+        let stamp = self.file.write(&records)?;
+        state.replace(records, stamp);
+        self.outdated.store(0, Ordering::SeqCst);
+        Ok(result)
+    }
 
-        // If file doesn't exist, just return empty cache
-        if !path.exists() {
-            debug!(
-                "Collection file not found at {:?}, starting with empty database",
-                path
-            );
-            return Ok(());
-        }
+    /// Get a record by ID
+    pub async fn get(&self, id: &T::Id) -> DbResult<Record<T>> {
+        self.current()
+            .await?
+            .records
+            .get(id)
+            .cloned()
+            .ok_or_else(|| DatabaseError::NotFound(id.as_ref().to_string()))
+    }
 
-        info!(
-            "Loading collection {} from {:?}",
-            self.collection_name, path
-        );
+    /// Get all records
+    pub async fn get_all(&self) -> DbResult<Vec<Record<T>>> {
+        Ok(self.current().await?.records.values().cloned().collect())
+    }
 
-        // Open and read the file
-        let file = File::open(&path).map_err(DatabaseError::IoError)?;
+    /// Records matching `query`, sorted and paged as it asks
+    pub async fn query(&self, query: &Query) -> DbResult<Vec<Record<T>>> {
+        let state = self.current().await?;
+        let candidates: Vec<&Record<T>> = match state.index.candidates(query) {
+            Some(ids) => ids.iter().filter_map(|id| state.records.get(id)).collect(),
+            None => state.records.values().collect(),
+        };
+        Ok(query
+            .apply(candidates, |record| entity_json(&record.entity))
+            .into_iter()
+            .cloned()
+            .collect())
+    }
 
-        let reader = BufReader::new(file);
+    /// Insert a new entity
+    pub async fn insert(&self, entity: T) -> DbResult<Record<T>> {
+        self.modify(|records| {
+            let id = entity.id();
 
-        // Deserialize records from JSON
-        let records: Vec<Record<T>> =
-            serde_json::from_reader(reader).map_err(DatabaseError::SerializationError)?;
+            // Ensure the ID doesn't already exist
+            if records.contains_key(&id) {
+                return Err(DatabaseError::DuplicateKey(id.as_ref().to_string()));
+            }
 
-        // Update cache with loaded records
-        let mut cache = self.cache.write().await;
-        cache.clear();
+            let record = Record::new(entity);
+            records.insert(id, record.clone());
+            Ok(record)
+        })
+        .await
+    }
 
-        for record in records {
-            cache.insert(record.id(), record);
-        }
+    /// Update an existing entity
+    pub async fn update(&self, entity: T, expected_version: Option<u64>) -> DbResult<Record<T>> {
+        self.modify(|records| {
+            let id = entity.id();
+
+            // Check if the entity exists
+            let record = records
+                .get_mut(&id)
+                .ok_or_else(|| DatabaseError::NotFound(id.as_ref().to_string()))?;
+
+            // Check version if provided
+            if let Some(expected) = expected_version {
+                if record.version != expected {
+                    return Err(DatabaseError::VersionConflict {
+                        expected,
+                        found: record.version,
+                    });
+                }
+            }
 
-        info!(
-            "Successfully loaded {} records from {}",
-            cache.len(),
-            self.collection_name
-        );
-        Ok(())
+            record.update(entity);
+            Ok(record.clone())
+        })
+        .await
     }
 
-    /// Save all records to disk
-    async fn save_all(&self) -> DbResult<()> {
-        let path = self.collection_path();
+    /// Delete a record by ID
+    pub async fn delete(&self, id: &T::Id) -> DbResult<()> {
+        self.modify(|records| match records.remove(id) {
+            Some(_) => Ok(()),
+            None => Err(DatabaseError::NotFound(id.as_ref().to_string())),
+        })
+        .await
+    }
 
-        info!("Saving collection {} to {:?}", self.collection_name, path);
+    /// This collection, for transactions
+    pub fn participant(&self) -> Participant {
+        Participant::new(ParticipantKind::File(Arc::new(FileHandle {
+            file: self.file.clone(),
+            state: self.state.clone(),
+            writers: self.writers.clone(),
+            outdated: self.outdated.clone(),
+        })))
+    }
 
-        // Create a temporary file for atomic write
-        let temp_path = path.with_extension("tmp");
+    /// Write back the records that were upgraded to the current schema
+    /// when loading; returns how many there were
+    pub async fn migrate(&self) -> DbResult<usize> {
+        drop(self.current().await?);
+        let outdated = self.outdated.load(Ordering::SeqCst);
+        if outdated > 0 {
+            self.modify(|_| Ok(())).await?;
+        }
+        Ok(outdated)
+    }
 
-        // Get all records from cache
-        let cache = self.cache.read().await;
-        let records: Vec<Record<T>> = cache.values().cloned().collect();
+    /// Clear all records
+    pub async fn clear(&self) -> DbResult<()> {
+        self.modify(|records| {
+            records.clear();
+            Ok(())
+        })
+        .await
+    }
+}
 
-        // Open a writer to the temporary file
-        let file = File::create(&temp_path).map_err(DatabaseError::IoError)?;
+/// The records of a collection with their index, and the version of the
+/// collection file they match
+struct State<T: Entity + for<'a> Deserialize<'a> + Unpin> {
+    records: HashMap<T::Id, Record<T>>,
 
-        let writer = BufWriter::new(file);
+    /// Secondary index over the entity's indexed fields
+    index: FieldIndex<T::Id>,
 
-        // Serialize records to JSON
-        serde_json::to_writer_pretty(writer, &records)
-            .map_err(DatabaseError::SerializationError)?;
+    /// `None` when there is no collection file yet
+    stamp: Option<FileStamp>,
+}
 
-        // Atomically rename the temporary file to the actual file
-        fs::rename(&temp_path, &path).map_err(DatabaseError::IoError)?;
+impl<T: Entity + for<'a> Deserialize<'a> + Unpin> State<T> {
+    fn new(records: HashMap<T::Id, Record<T>>, stamp: Option<FileStamp>) -> Self {
+        let mut index = FieldIndex::new(T::INDEXED_FIELDS);
+        for (id, record) in &records {
+            index.insert(id, &entity_json(&record.entity));
+        }
+        Self {
+            records,
+            index,
+            stamp,
+        }
+    }
 
-        info!(
-            "Successfully saved {} records to {}",
-            records.len(),
-            self.collection_name
-        );
-        Ok(())
+    /// Take `records` as written at `stamp`, reindexing the records that
+    /// were added, changed or removed
+    fn replace(&mut self, records: HashMap<T::Id, Record<T>>, stamp: FileStamp) {
+        // Every write of a record bumps its version or, when it is
+        // inserted again, its timestamps
+        let same =
+            |a: &Record<T>, b: &Record<T>| (a.version, a.updated_at) == (b.version, b.updated_at);
+        for (id, old) in &self.records {
+            if records.get(id).is_none_or(|new| !same(old, new)) {
+                self.index.remove(id, &entity_json(&old.entity));
+            }
+        }
+        for (id, new) in &records {
+            if self.records.get(id).is_none_or(|old| !same(old, new)) {
+                self.index.insert(id, &entity_json(&new.entity));
+            }
+        }
+        self.records = records;
+        self.stamp = Some(stamp);
     }
+}
 
-    /// Get a record by ID
-    pub async fn get(&self, id: &T::Id) -> DbResult<Record<T>> {
-        let cache = self.cache.read().await;
+/// One version of a collection file; since every write replaces the file,
+/// the stamp changes with every write
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct FileStamp {
+    len: u64,
+    modified: Option<SystemTime>,
+    inode: u64,
+}
 
-        cache
-            .get(id)
-            .cloned()
-            .ok_or_else(|| DatabaseError::NotFound(id.as_ref().to_string()))
+impl FileStamp {
+    fn of(metadata: &fs::Metadata) -> Self {
+        #[cfg(unix)]
+        let inode = std::os::unix::fs::MetadataExt::ino(metadata);
+        #[cfg(not(unix))]
+        let inode = 0;
+        Self {
+            len: metadata.len(),
+            modified: metadata.modified().ok(),
+            inode,
+        }
     }
+}
 
-    /// Get all records
-    pub async fn get_all(&self) -> DbResult<Vec<Record<T>>> {
-        let cache = self.cache.read().await;
+/// The lock serializing the writers of the collection file at `path` in
+/// this process
+fn writers_of(path: &Path) -> Arc<RwLock<()>> {
+    static WRITERS: OnceLock<Mutex<HashMap<PathBuf, Arc<RwLock<()>>>>> = OnceLock::new();
+    WRITERS
+        .get_or_init(Default::default)
+        .lock()
+        .unwrap()
+        .entry(path.to_path_buf())
+        .or_default()
+        .clone()
+}
 
-        let records = cache.values().cloned().collect();
-        Ok(records)
+/// Hold the advisory lock of the collection file at `path` until the
+/// returned file is dropped, waiting for other processes to release it
+///
+/// The wait happens on the blocking pool, so the async locks its callers
+/// hold do not stall a runtime worker meanwhile.
+async fn lock_collection(path: &Path) -> DbResult<File> {
+    let lock_path = path.with_extension("lock");
+    tokio::task::spawn_blocking(move || {
+        let lock = OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(lock_path)?;
+        lock.lock()?;
+        Ok(lock)
+    })
+    .await
+    .map_err(|e| DatabaseError::InternalError(format!("Collection lock task failed: {}", e)))?
+}
+
+/// Finish the commits in `data_dir` that were interrupted after their
+/// marker was written, then remove a prepared file of the collection at
+/// `path` that no commit claims
+async fn recover(data_dir: &Path, path: &Path) -> DbResult<()> {
+    for (marker_path, marker) in CommitMarker::pending(data_dir)? {
+        replay(&marker_path, &marker).await?;
     }
 
-    /// Insert a new entity
-    pub async fn insert(&self, entity: T) -> DbResult<Record<T>> {
-        let mut cache = self.cache.write().await;
+    let prepared = path.with_extension("txn");
+    if !prepared.exists() {
+        return Ok(());
+    }
+    // A live transaction holds the collection until its file is moved or
+    // removed
+    let _writers = writers_of(path).write_owned().await;
+    let _lock = lock_collection(path).await?;
+    let claimed = CommitMarker::pending(data_dir)?
+        .iter()
+        .any(|(_, marker)| marker.collections.iter().any(|c| c == path));
+    if prepared.exists() && !claimed {
+        info!("Removing stale transaction file {:?}", prepared);
+        fs::remove_file(&prepared).map_err(DatabaseError::IoError)?;
+    }
+    Ok(())
+}
+
+/// Move the prepared files of the commit marked at `marker_path` into
+/// place and remove the marker, holding every collection of the commit
+async fn replay(marker_path: &Path, marker: &CommitMarker) -> DbResult<()> {
+    let mut collections = marker.collections.clone();
+    collections.sort();
+    let mut held = Vec::new();
+    for collection in &collections {
+        let writers = writers_of(collection).write_owned().await;
+        held.push((writers, lock_collection(collection).await?));
+    }
+    // Another process may have finished it meanwhile
+    if !marker_path.exists() {
+        return Ok(());
+    }
+    for collection in &collections {
+        let prepared = collection.with_extension("txn");
+        if prepared.exists() {
+            info!("Finishing interrupted commit of {:?}", collection);
+            fs::rename(&prepared, collection).map_err(DatabaseError::IoError)?;
+        }
+    }
+    fs::remove_file(marker_path).map_err(DatabaseError::IoError)
+}
+
+/// The file a collection is kept in
+struct CollectionFile {
+    path: PathBuf,
+    collection: String,
 
-        let id = entity.id();
+    /// Seals the entities written to the file, when encrypted
+    cipher: Option<Cipher>,
+}
 
-        // Ensure the ID doesn't already exist
-        if cache.contains_key(&id) {
-            return Err(DatabaseError::DuplicateKey(id.as_ref().to_string()));
+impl CollectionFile {
+    /// The version of the file, `None` when there is none yet
+    fn stamp(&self) -> DbResult<Option<FileStamp>> {
+        match fs::metadata(&self.path) {
+            Ok(metadata) => Ok(Some(FileStamp::of(&metadata))),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(DatabaseError::IoError(e)),
         }
+    }
 
-        // Create a new record
-        let record = Record::new(entity);
+    /// Hold the advisory lock of the collection until the returned file
+    /// is dropped, waiting for other processes to release it
+    async fn lock(&self) -> DbResult<File> {
+        lock_collection(&self.path).await
+    }
 
-        // Insert into cache
-        cache.insert(id, record.clone());
+    /// Load the file into `state` if it is not the version `state` has
+    fn refresh<T: Entity + for<'a> Deserialize<'a> + Unpin>(
+        &self,
+        state: &mut State<T>,
+        outdated: &AtomicUsize,
+    ) -> DbResult<()> {
+        if state.stamp == self.stamp()? {
+            return Ok(());
+        }
+        // The stamp is taken from the file read, which a writer may have
+        // replaced since
+        let file = match File::open(&self.path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == ErrorKind::NotFound => {
+                debug!(
+                    "Collection file not found at {:?}, starting with empty database",
+                    self.path
+                );
+                *state = State::new(HashMap::new(), None);
+                return Ok(());
+            }
+            Err(e) => return Err(DatabaseError::IoError(e)),
+        };
+        let stamp = FileStamp::of(&file.metadata().map_err(DatabaseError::IoError)?);
+        info!(
+            "Loading collection {} from {:?}",
+            self.collection, self.path
+        );
 
-        // Save changes
-        drop(cache);
-        self.save_all().await?;
+        // Deserialize records from JSON, upgrading older schemas
+        let stored: Vec<serde_json::Value> = serde_json::from_reader(BufReader::new(file))
+            .map_err(DatabaseError::SerializationError)?;
+        let mut records = HashMap::new();
+        let mut upgraded = 0;
+        for raw in stored {
+            let (raw, plain) = open_record(raw, self.cipher.as_ref(), &self.collection)?;
+            let (record, migrated) = migration::read_record::<T>(raw)?;
+            upgraded += usize::from(migrated || plain);
+            records.insert(record.id(), record);
+        }
+        if upgraded > 0 {
+            info!(
+                "Upgraded {} records of {} to the current schema",
+                upgraded, self.collection
+            );
+        }
+        outdated.store(upgraded, Ordering::SeqCst);
 
-        Ok(record)
+        info!(
+            "Successfully loaded {} records from {}",
+            records.len(),
+            self.collection
+        );
+        *state = State::new(records, Some(stamp));
+        Ok(())
     }
 
-    /// Update an existing entity
-    pub async fn update(&self, entity: T, expected_version: Option<u64>) -> DbResult<Record<T>> {
-        let mut cache = self.cache.write().await;
+    /// Write `records` to `path` and flush them to disk
+    fn write_to<T: Entity + for<'a> Deserialize<'a> + Unpin>(
+        &self,
+        records: &HashMap<T::Id, Record<T>>,
+        path: &Path,
+    ) -> DbResult<()> {
+        let stored = stored_records(records.values(), self.cipher.as_ref(), &self.collection)?;
+        let mut writer = BufWriter::new(File::create(path).map_err(DatabaseError::IoError)?);
+        serde_json::to_writer_pretty(&mut writer, &stored)
+            .map_err(DatabaseError::SerializationError)?;
+        let file = writer
+            .into_inner()
+            .map_err(|e| DatabaseError::IoError(e.into_error()))?;
+        file.sync_all().map_err(DatabaseError::IoError)
+    }
 
-        let id = entity.id();
+    /// Move the file written at `written` into place; returns the new
+    /// version of the collection file
+    fn replace_with(&self, written: &Path) -> DbResult<FileStamp> {
+        fs::rename(written, &self.path).map_err(DatabaseError::IoError)?;
+        self.stamp()?
+            .ok_or_else(|| DatabaseError::InternalError(format!("{:?} vanished", self.path)))
+    }
 
-        // Check if the entity exists
-        let record = match cache.get_mut(&id) {
-            Some(record) => record,
-            None => return Err(DatabaseError::NotFound(id.as_ref().to_string())),
-        };
+    /// Replace the collection file with `records`; returns its new version
+    fn write<T: Entity + for<'a> Deserialize<'a> + Unpin>(
+        &self,
+        records: &HashMap<T::Id, Record<T>>,
+    ) -> DbResult<FileStamp> {
+        info!("Saving collection {} to {:?}", self.collection, self.path);
+
+        // Write a temporary file and rename it over the collection file, so
+        // readers never see a partly written one
+        let temp_path = self.path.with_extension("tmp");
+        if let Err(e) = self.write_to(records, &temp_path) {
+            let _ = fs::remove_file(&temp_path);
+            return Err(e);
+        }
+        let stamp = self.replace_with(&temp_path)?;
 
-        // Check version if provided
-        if let Some(expected) = expected_version {
-            if record.version != expected {
-                return Err(DatabaseError::VersionConflict {
-                    expected,
-                    found: record.version,
-                });
-            }
+        info!(
+            "Successfully saved {} records to {}",
+            records.len(),
+            self.collection
+        );
+        Ok(stamp)
+    }
+}
+
+/// The stored JSON of an entity, as queries and indexes see it
+fn entity_json<T: Entity>(entity: &T) -> serde_json::Value {
+    serde_json::to_value(entity).unwrap_or_default()
+}
+
+/// A record read from a collection file with its entity opened, and
+/// whether it still has to be sealed
+fn open_record(
+    mut raw: serde_json::Value,
+    cipher: Option<&Cipher>,
+    collection: &str,
+) -> DbResult<(serde_json::Value, bool)> {
+    let Some(entity) = raw.get_mut("entity") else {
+        return Ok((raw, false));
+    };
+    let sealed = encryption::is_sealed(entity);
+    match cipher {
+        Some(cipher) if sealed => {
+            *entity = cipher
+                .open_json(entity.take(), collection)
+                .map_err(|e| DatabaseError::EncryptionError(format!("{}: {}", collection, e)))?;
+            Ok((raw, false))
         }
+        Some(_) => Ok((raw, true)),
+        None if sealed => Err(DatabaseError::EncryptionError(format!(
+            "{} is encrypted; enable encryption and configure its key",
+            collection
+        ))),
+        None => Ok((raw, false)),
+    }
+}
 
-        // Update the record
-        record.update(entity);
+/// Records as written to a collection file, with their entities sealed
+/// when the collection is encrypted
+fn stored_records<'a, T: Entity + for<'b> Deserialize<'b> + Unpin>(
+    records: impl Iterator<Item = &'a Record<T>>,
+    cipher: Option<&Cipher>,
+    collection: &str,
+) -> DbResult<Vec<serde_json::Value>> {
+    records
+        .map(|record| {
+            let mut stored = serde_json::to_value(record)?;
+            if let Some(cipher) = cipher {
+                stored["entity"] = cipher
+                    .seal_json(&stored["entity"], collection)
+                    .map_err(|e| DatabaseError::EncryptionError(e.to_string()))?;
+            }
+            Ok(stored)
+        })
+        .collect()
+}
 
-        let updated_record = record.clone();
+/// The records and file of a collection, for transactions
+struct FileHandle<T: Entity + for<'a> Deserialize<'a> + Unpin> {
+    file: Arc<CollectionFile>,
+    state: Arc<RwLock<State<T>>>,
+    writers: Arc<RwLock<()>>,
+    outdated: Arc<AtomicUsize>,
+}
 
-        // Save changes
-        drop(cache);
-        self.save_all().await?;
+#[async_trait::async_trait]
+impl<T: Entity + for<'a> Deserialize<'a> + Unpin> FileParticipant for FileHandle<T> {
+    fn path(&self) -> PathBuf {
+        self.file.path.clone()
+    }
 
-        Ok(updated_record)
+    async fn lock(&self) -> DbResult<Box<dyn LockedFile>> {
+        let writers = self.writers.clone().write_owned().await;
+        let lock = self.file.lock().await?;
+        let mut state = self.state.clone().write_owned().await;
+        self.file.refresh(&mut state, &self.outdated)?;
+        let working = state.records.clone();
+        Ok(Box::new(LockedCollection {
+            _writers: writers,
+            _lock: lock,
+            state,
+            working,
+            file: self.file.clone(),
+            outdated: self.outdated.clone(),
+        }))
     }
+}
 
-    /// Delete a record by ID
-    pub async fn delete(&self, id: &T::Id) -> DbResult<()> {
-        let mut cache = self.cache.write().await;
+/// A collection held by a transaction, with its writes applied to a copy
+struct LockedCollection<T: Entity + for<'a> Deserialize<'a> + Unpin> {
+    _writers: OwnedRwLockWriteGuard<()>,
+    _lock: File,
+    state: OwnedRwLockWriteGuard<State<T>>,
+    working: HashMap<T::Id, Record<T>>,
+    file: Arc<CollectionFile>,
+    outdated: Arc<AtomicUsize>,
+}
 
-        // Check if the entity exists
-        if !cache.contains_key(id) {
-            return Err(DatabaseError::NotFound(id.as_ref().to_string()));
-        }
+impl<T: Entity + for<'a> Deserialize<'a> + Unpin> LockedCollection<T> {
+    fn prepared_path(&self) -> PathBuf {
+        self.file.path.with_extension("txn")
+    }
+}
 
-        // Remove the record
-        cache.remove(id);
+impl<T: Entity + for<'a> Deserialize<'a> + Unpin> LockedFile for LockedCollection<T> {
+    fn apply(&mut self, write: &StagedWrite) -> DbResult<()> {
+        match &write.op {
+            WriteOp::Insert { entity } => {
+                let entity: T = serde_json::from_value(entity.clone())?;
+                let id = entity.id();
+                if self.working.contains_key(&id) {
+                    return Err(DatabaseError::DuplicateKey(write.id.clone()));
+                }
+                self.working.insert(id, Record::new(entity));
+            }
+            WriteOp::Update {
+                entity,
+                expected_version,
+            } => {
+                let entity: T = serde_json::from_value(entity.clone())?;
+                let record = self
+                    .working
+                    .get_mut(&entity.id())
+                    .ok_or_else(|| DatabaseError::NotFound(write.id.clone()))?;
+                if let Some(expected) = *expected_version {
+                    if record.version != expected {
+                        return Err(DatabaseError::VersionConflict {
+                            expected,
+                            found: record.version,
+                        });
+                    }
+                }
+                record.update(entity);
+            }
+            WriteOp::Delete => {
+                let id = self
+                    .working
+                    .keys()
+                    .find(|id| id.as_ref() == write.id)
+                    .cloned()
+                    .ok_or_else(|| DatabaseError::NotFound(write.id.clone()))?;
+                self.working.remove(&id);
+            }
+        }
+        Ok(())
+    }
 
-        // Save changes
-        drop(cache);
-        self.save_all().await?;
+    fn prepare(&mut self) -> DbResult<()> {
+        self.file.write_to(&self.working, &self.prepared_path())
+    }
 
+    fn finish(mut self: Box<Self>) -> DbResult<()> {
+        let stamp = self.file.replace_with(&self.prepared_path())?;
+        let working = std::mem::take(&mut self.working);
+        self.state.replace(working, stamp);
+        self.outdated.store(0, Ordering::SeqCst);
         Ok(())
     }
 
-    /// Clear all records
-    pub async fn clear(&self) -> DbResult<()> {
-        let mut cache = self.cache.write().await;
+    fn abort(self: Box<Self>) {
+        let _ = fs::remove_file(self.prepared_path());
+    }
+}
 
-        // Clear the cache
-        cache.clear();
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::optimization::OptimizationGoal;
+    use crate::database::Transaction;
+
+    #[tokio::test]
+    async fn test_concurrent_writers_lose_no_updates() {
+        let dir = tempfile::tempdir().unwrap();
+        let first = Arc::new(
+            FileDb::<OptimizationGoal>::new(dir.path(), "goals")
+                .await
+                .unwrap(),
+        );
+        let second = Arc::new(
+            FileDb::<OptimizationGoal>::new(dir.path(), "goals")
+                .await
+                .unwrap(),
+        );
 
-        // Save changes
-        drop(cache);
-        self.save_all().await?;
+        let mut tasks = Vec::new();
+        for i in 0..40 {
+            let db = if i % 2 == 0 { &first } else { &second }.clone();
+            tasks.push(tokio::spawn(async move {
+                let goal = OptimizationGoal::new(&format!("goal-{}", i), "Title", "Description");
+                if i % 4 == 1 {
+                    let mut txn = Transaction::new();
+                    txn.insert(db.as_ref(), goal).unwrap();
+                    txn.commit().await.unwrap();
+                } else {
+                    db.insert(goal).await.unwrap();
+                }
+            }));
+        }
+        for task in tasks {
+            task.await.unwrap();
+        }
 
-        Ok(())
+        let reopened = FileDb::<OptimizationGoal>::new(dir.path(), "goals")
+            .await
+            .unwrap();
+        for db in [first.as_ref(), second.as_ref(), &reopened] {
+            assert_eq!(db.get_all().await.unwrap().len(), 40);
+        }
+        // Writes through one instance see those of the other
+        first.delete(&"goal-1".to_string()).await.unwrap();
+        assert!(second.get(&"goal-1".to_string()).await.is_err());
+        assert!(!dir.path().join("goals.tmp").exists());
+    }
+
+    #[tokio::test]
+    async fn test_waiting_for_another_process_does_not_block_the_runtime() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = FileDb::<OptimizationGoal>::new(dir.path(), "goals")
+            .await
+            .unwrap();
+        // Another process holding the collection
+        let held = File::create(dir.path().join("goals.lock")).unwrap();
+        held.lock().unwrap();
+
+        let insert = tokio::spawn(async move {
+            db.insert(OptimizationGoal::new("goal-1", "Title", "Description"))
+                .await
+        });
+        // On this single-threaded runtime a blocking wait would starve the
+        // timer and never let the lock go
+        tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+        assert!(!insert.is_finished());
+        drop(held);
+        insert.await.unwrap().unwrap();
     }
 }
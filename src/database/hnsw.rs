@@ -0,0 +1,333 @@
+//! Hierarchical navigable small world graph for approximate nearest
+//! neighbour search.
+//!
+//! Every vector is a node on layer 0 and, with exponentially falling
+//! probability, on the layers above. A search descends greedily from the
+//! sparse top layer and widens to `ef` candidates on layer 0. Vectors are
+//! normalized when inserted so cosine similarity is a dot product. Removed
+//! nodes stay in the graph as waypoints until they make up half of it, when
+//! the graph is rebuilt from the live ones.
+
+use std::cmp::{Ordering, Reverse};
+use std::collections::{BinaryHeap, HashMap, HashSet};
+
+/// Neighbours kept per node on the upper layers; layer 0 keeps twice as many
+const M: usize = 16;
+
+/// Candidates considered when linking a new node
+const EF_CONSTRUCTION: usize = 100;
+
+/// Candidates considered by a search, at least
+const EF_SEARCH: usize = 64;
+
+/// Highest layer a node is placed on
+const MAX_LEVEL: usize = 16;
+
+struct Node {
+    id: String,
+    vector: Vec<f32>,
+    /// Neighbours on each layer the node is on, from layer 0 up
+    neighbors: Vec<Vec<usize>>,
+    removed: bool,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+struct Candidate {
+    similarity: f32,
+    node: usize,
+}
+
+impl Eq for Candidate {}
+
+impl PartialOrd for Candidate {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Candidate {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.similarity
+            .total_cmp(&other.similarity)
+            .then(other.node.cmp(&self.node))
+    }
+}
+
+/// An approximate nearest neighbour index of vectors by id
+pub(crate) struct Hnsw {
+    nodes: Vec<Node>,
+    ids: HashMap<String, usize>,
+    entry: Option<usize>,
+    removed: usize,
+    seed: u64,
+}
+
+impl Default for Hnsw {
+    fn default() -> Self {
+        Self {
+            nodes: Vec::new(),
+            ids: HashMap::new(),
+            entry: None,
+            removed: 0,
+            seed: 0x9e37_79b9_7f4a_7c15,
+        }
+    }
+}
+
+/// `vector` scaled to unit length
+pub(crate) fn normalize(mut vector: Vec<f32>) -> Vec<f32> {
+    let norm = vector.iter().map(|x| x * x).sum::<f32>().sqrt();
+    if norm > 0.0 {
+        vector.iter_mut().for_each(|x| *x /= norm);
+    }
+    vector
+}
+
+fn dot(a: &[f32], b: &[f32]) -> f32 {
+    a.iter().zip(b).map(|(x, y)| x * y).sum()
+}
+
+impl Hnsw {
+    /// Number of vectors indexed
+    pub fn len(&self) -> usize {
+        self.ids.len()
+    }
+
+    /// Index `vector` under `id`, replacing what was indexed under it
+    pub fn insert(&mut self, id: &str, vector: &[f32]) {
+        if self.ids.contains_key(id) {
+            self.remove(id);
+        }
+        let level = self.random_level();
+        let node = self.nodes.len();
+        self.nodes.push(Node {
+            id: id.to_string(),
+            vector: normalize(vector.to_vec()),
+            neighbors: vec![Vec::new(); level + 1],
+            removed: false,
+        });
+        self.ids.insert(id.to_string(), node);
+        let Some(entry) = self.entry else {
+            self.entry = Some(node);
+            return;
+        };
+
+        let query = self.nodes[node].vector.clone();
+        let top = self.nodes[entry].neighbors.len() - 1;
+        let mut nearest = vec![entry];
+        for layer in (level + 1..=top).rev() {
+            nearest = vec![self.search_layer(&query, &nearest, 1, layer)[0].node];
+        }
+        for layer in (0..=level.min(top)).rev() {
+            let found = self.search_layer(&query, &nearest, EF_CONSTRUCTION, layer);
+            let neighbors: Vec<usize> = found.iter().take(M).map(|c| c.node).collect();
+            for &neighbor in &neighbors {
+                self.nodes[neighbor].neighbors[layer].push(node);
+                self.prune(neighbor, layer);
+            }
+            self.nodes[node].neighbors[layer] = neighbors;
+            nearest = found.iter().map(|c| c.node).collect();
+        }
+        if level > top {
+            self.entry = Some(node);
+        }
+    }
+
+    /// Forget the vector indexed under `id`; returns whether there was one
+    pub fn remove(&mut self, id: &str) -> bool {
+        let Some(node) = self.ids.remove(id) else {
+            return false;
+        };
+        self.nodes[node].removed = true;
+        self.removed += 1;
+        if self.removed * 2 > self.nodes.len() {
+            self.rebuild();
+        }
+        true
+    }
+
+    /// About the `limit` indexed vectors most similar to `query`, most
+    /// similar first, with their cosine similarity
+    pub fn search(&self, query: &[f32], limit: usize) -> Vec<(String, f32)> {
+        let Some(entry) = self.entry else {
+            return Vec::new();
+        };
+        let query = normalize(query.to_vec());
+        let mut nearest = vec![entry];
+        for layer in (1..self.nodes[entry].neighbors.len()).rev() {
+            nearest = vec![self.search_layer(&query, &nearest, 1, layer)[0].node];
+        }
+        // Removed nodes take up candidate slots without being returned
+        let ef = EF_SEARCH.max(limit) + self.removed;
+        self.search_layer(&query, &nearest, ef, 0)
+            .into_iter()
+            .filter(|c| !self.nodes[c.node].removed)
+            .take(limit)
+            .map(|c| (self.nodes[c.node].id.clone(), c.similarity))
+            .collect()
+    }
+
+    /// The `limit` indexed vectors most similar to `query` by a scan of
+    /// all of them, most similar first
+    pub fn exact(&self, query: &[f32], limit: usize) -> Vec<(String, f32)> {
+        let query = normalize(query.to_vec());
+        let mut scored: Vec<(String, f32)> = self
+            .nodes
+            .iter()
+            .filter(|node| !node.removed)
+            .map(|node| (node.id.clone(), dot(&query, &node.vector)))
+            .collect();
+        scored.sort_by(|a, b| b.1.total_cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        scored.truncate(limit);
+        scored
+    }
+
+    /// The `ef` nodes on `layer` most similar to `query` found from
+    /// `entries`, most similar first
+    fn search_layer(
+        &self,
+        query: &[f32],
+        entries: &[usize],
+        ef: usize,
+        layer: usize,
+    ) -> Vec<Candidate> {
+        let mut visited: HashSet<usize> = entries.iter().copied().collect();
+        let mut candidates: BinaryHeap<Candidate> = BinaryHeap::new();
+        let mut found: BinaryHeap<Reverse<Candidate>> = BinaryHeap::new();
+        for &node in entries {
+            let candidate = Candidate {
+                similarity: dot(query, &self.nodes[node].vector),
+                node,
+            };
+            candidates.push(candidate);
+            found.push(Reverse(candidate));
+        }
+        while found.len() > ef {
+            found.pop();
+        }
+
+        while let Some(candidate) = candidates.pop() {
+            let worst = found.peek().map_or(f32::MIN, |c| c.0.similarity);
+            if candidate.similarity < worst && found.len() >= ef {
+                break;
+            }
+            for &neighbor in &self.nodes[candidate.node].neighbors[layer] {
+                if !visited.insert(neighbor) {
+                    continue;
+                }
+                let similarity = dot(query, &self.nodes[neighbor].vector);
+                let worst = found.peek().map_or(f32::MIN, |c| c.0.similarity);
+                if found.len() < ef || similarity > worst {
+                    let next = Candidate {
+                        similarity,
+                        node: neighbor,
+                    };
+                    candidates.push(next);
+                    found.push(Reverse(next));
+                    if found.len() > ef {
+                        found.pop();
+                    }
+                }
+            }
+        }
+        found.into_sorted_vec().into_iter().map(|c| c.0).collect()
+    }
+
+    /// Keep only the closest neighbours of `node` on `layer`
+    fn prune(&mut self, node: usize, layer: usize) {
+        let max = if layer == 0 { 2 * M } else { M };
+        if self.nodes[node].neighbors[layer].len() <= max {
+            return;
+        }
+        let vector = &self.nodes[node].vector;
+        let mut scored: Vec<Candidate> = self.nodes[node].neighbors[layer]
+            .iter()
+            .map(|&neighbor| Candidate {
+                similarity: dot(vector, &self.nodes[neighbor].vector),
+                node: neighbor,
+            })
+            .collect();
+        scored.sort_by(|a, b| b.cmp(a));
+        self.nodes[node].neighbors[layer] = scored.into_iter().take(max).map(|c| c.node).collect();
+    }
+
+    /// Index the live vectors again, dropping the removed ones
+    fn rebuild(&mut self) {
+        let live: Vec<Node> = std::mem::take(&mut self.nodes)
+            .into_iter()
+            .filter(|node| !node.removed)
+            .collect();
+        self.ids.clear();
+        self.entry = None;
+        self.removed = 0;
+        for node in live {
+            self.insert(&node.id, &node.vector);
+        }
+    }
+
+    /// Layer of a new node, 0 with probability 1 - 1/M, each layer above
+    /// M times less likely than the one below
+    fn random_level(&mut self) -> usize {
+        // xorshift64, so the graph is the same for the same inserts
+        self.seed ^= self.seed << 13;
+        self.seed ^= self.seed >> 7;
+        self.seed ^= self.seed << 17;
+        let uniform = ((self.seed >> 11) as f64 + 1.0) / (1u64 << 53) as f64;
+        ((-uniform.ln() / (M as f64).ln()).floor() as usize).min(MAX_LEVEL)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vectors(count: usize, dims: usize) -> Vec<Vec<f32>> {
+        let mut seed = 42u64;
+        (0..count)
+            .map(|_| {
+                (0..dims)
+                    .map(|_| {
+                        seed = seed.wrapping_mul(6364136223846793005).wrapping_add(1);
+                        ((seed >> 33) as f32 / (1u64 << 31) as f32) - 0.5
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_search_finds_most_exact_neighbours_after_removals() {
+        let all: Vec<(String, Vec<f32>)> = vectors(1500, 24)
+            .into_iter()
+            .enumerate()
+            .map(|(i, v)| (format!("v{}", i), v))
+            .collect();
+        let mut index = Hnsw::default();
+        for (id, vector) in &all {
+            index.insert(id, vector);
+        }
+        // Removing most of them forces a rebuild along the way
+        for (id, _) in &all[..900] {
+            assert!(index.remove(id));
+        }
+        assert!(!index.remove("v0"));
+        assert_eq!(index.len(), 600);
+
+        let mut hits = 0;
+        let queries = vectors(20, 24);
+        for query in &queries {
+            let expected: Vec<String> = index
+                .exact(query, 10)
+                .into_iter()
+                .map(|(id, _)| id)
+                .collect();
+            let found: Vec<String> = index
+                .search(query, 10)
+                .into_iter()
+                .map(|(id, _)| id)
+                .collect();
+            hits += found.iter().filter(|id| expected.contains(id)).count();
+        }
+        assert!(hits >= 180, "recall {} of 200", hits);
+    }
+}
@@ -1,13 +1,31 @@
+use std::collections::HashMap;
 use std::path::{Path, PathBuf};
 use std::sync::Arc;
 
 use anyhow::{Context, Result};
 use log::info;
 use serde::Deserialize;
+use serde_json::Value;
+use tokio::sync::broadcast;
 
-use crate::core::config::Config;
+use crate::code_generation::memory::Lesson;
+use crate::code_generation::semantic_index::IndexedFile;
+use crate::code_generation::todos::TodoList;
+use crate::code_generation::tool_audit::ToolInvocation;
+use crate::core::calibration::OutcomeStats;
+use crate::core::config::{Config, DatabaseBackend};
+use crate::core::costs::DailyCost;
+use crate::core::encryption::Cipher;
 use crate::core::optimization::OptimizationGoal;
-use crate::database::{DbResult, Entity, FileDb, Record};
+use crate::database::archive::Archive;
+use crate::database::migration;
+use crate::database::postgres::PgStore;
+use crate::database::query::Query;
+use crate::database::transaction::{Participant, Transaction};
+use crate::database::vector::{FileVectorStore, VectorEntry, VectorStore};
+use crate::database::watch::{Change, Watched, Watcher};
+use crate::database::{DatabaseError, DbResult, Entity, FileDb, Record};
+use crate::version_control::rollback::MergeRecord;
 
 /// Database Manager coordinates access to all database collections
 pub struct DatabaseManager {
@@ -17,6 +35,36 @@ pub struct DatabaseManager {
 
     /// Database for optimization goals
     goals_db: Arc<dyn DatabaseInterface<OptimizationGoal>>,
+
+    /// Database for per-strategy/category plan outcomes
+    outcome_stats_db: Arc<dyn DatabaseInterface<OutcomeStats>>,
+
+    /// Database for per-day, per-model LLM spend
+    daily_costs_db: Arc<dyn DatabaseInterface<DailyCost>>,
+
+    /// Database for the tool invocation audit log
+    tool_invocations_db: Arc<dyn DatabaseInterface<ToolInvocation>>,
+
+    /// Database for the per-goal todo lists
+    todos_db: Arc<dyn DatabaseInterface<TodoList>>,
+
+    /// Database for the embedded chunks of the workspace
+    code_index_db: Arc<dyn DatabaseInterface<IndexedFile>>,
+
+    /// Database for the lessons learned about files and modules
+    lessons_db: Arc<dyn DatabaseInterface<Lesson>>,
+
+    /// Database for the merge that landed each goal
+    merges_db: Arc<dyn DatabaseInterface<MergeRecord>>,
+
+    /// Embeddings of the workspace's chunks
+    code_vectors_db: Arc<dyn VectorStore>,
+
+    /// Embeddings of the lessons
+    lesson_vectors_db: Arc<dyn VectorStore>,
+
+    /// Publishers of the changes to each collection, by name
+    watchers: HashMap<&'static str, Watcher>,
 }
 
 /// Trait for database operations
@@ -28,6 +76,9 @@ pub trait DatabaseInterface<T: Entity + for<'a> Deserialize<'a> + Unpin>: Send +
     /// Get all records
     async fn get_all(&self) -> DbResult<Vec<Record<T>>>;
 
+    /// Get the records matching a query, sorted and paged as it asks
+    async fn query(&self, query: &Query) -> DbResult<Vec<Record<T>>>;
+
     /// Insert a new entity
     async fn insert(&self, entity: T) -> DbResult<Record<T>>;
 
@@ -39,6 +90,13 @@ pub trait DatabaseInterface<T: Entity + for<'a> Deserialize<'a> + Unpin>: Send +
 
     /// Clear all records
     async fn clear(&self) -> DbResult<()>;
+
+    /// Store the records kept in an older schema in the current one;
+    /// returns how many were upgraded
+    async fn migrate(&self) -> DbResult<usize>;
+
+    /// This collection, for staging writes in a `Transaction`
+    fn participant(&self) -> Participant;
 }
 
 // Implement DatabaseInterface for FileDb
@@ -52,6 +110,10 @@ impl<T: Entity + for<'a> Deserialize<'a> + Unpin> DatabaseInterface<T> for FileD
         self.get_all().await
     }
 
+    async fn query(&self, query: &Query) -> DbResult<Vec<Record<T>>> {
+        self.query(query).await
+    }
+
     async fn insert(&self, entity: T) -> DbResult<Record<T>> {
         self.insert(entity).await
     }
@@ -67,31 +129,495 @@ impl<T: Entity + for<'a> Deserialize<'a> + Unpin> DatabaseInterface<T> for FileD
     async fn clear(&self) -> DbResult<()> {
         self.clear().await
     }
+
+    async fn migrate(&self) -> DbResult<usize> {
+        self.migrate().await
+    }
+
+    fn participant(&self) -> Participant {
+        self.participant()
+    }
+}
+
+/// Where the collections of a database manager are kept
+enum Backend {
+    File(PathBuf, Option<Cipher>),
+    Postgres(PgStore),
+}
+
+impl Backend {
+    /// The collection `name`, publishing its changes through a watcher
+    /// added to `watchers`
+    async fn open<T: Entity + for<'a> Deserialize<'a> + Unpin>(
+        &self,
+        name: &'static str,
+        watchers: &mut HashMap<&'static str, Watcher>,
+    ) -> DbResult<Arc<dyn DatabaseInterface<T>>> {
+        let inner: Arc<dyn DatabaseInterface<T>> = match self {
+            Backend::File(data_dir, cipher) => {
+                Arc::new(FileDb::open(data_dir, name, cipher.clone()).await?)
+            }
+            Backend::Postgres(store) => Arc::new(store.collection(name)),
+        };
+        let watcher = Watcher::new(name);
+        watchers.insert(name, watcher.clone());
+        Ok(Arc::new(Watched::new(inner, watcher)))
+    }
+
+    /// The vector collection `name`
+    async fn open_vectors(&self, name: &str) -> DbResult<Arc<dyn VectorStore>> {
+        Ok(match self {
+            Backend::File(data_dir, cipher) => {
+                Arc::new(FileVectorStore::open(data_dir, name, cipher.clone()).await?)
+            }
+            Backend::Postgres(store) => Arc::new(store.vectors(name)),
+        })
+    }
 }
 
 impl DatabaseManager {
     /// Create a new database manager
-    pub async fn new(data_dir: impl AsRef<Path>, _config: &Config) -> Result<Self> {
+    pub async fn new(data_dir: impl AsRef<Path>, config: &Config) -> Result<Self> {
         let data_dir = data_dir.as_ref().to_path_buf();
 
-        info!(
-            "Initializing file-based database manager with data directory: {:?}",
-            data_dir
-        );
+        let cipher = Cipher::from_config(&config.encryption)?;
+        if cipher.is_some() {
+            info!("Database contents are encrypted at rest");
+        }
+        let backend = match config.database.backend {
+            DatabaseBackend::File => {
+                info!(
+                    "Initializing file-based database manager with data directory: {:?}",
+                    data_dir
+                );
+                Backend::File(data_dir.clone(), cipher)
+            }
+            DatabaseBackend::Postgres => {
+                info!("Initializing Postgres database manager");
+                Backend::Postgres(
+                    PgStore::connect(&config.database.postgres)
+                        .await
+                        .context("Failed to connect to the Postgres database")?
+                        .with_cipher(cipher),
+                )
+            }
+        };
+
+        let mut watchers = HashMap::new();
 
         // Create database for optimization goals
-        let goals_db = FileDb::new(&data_dir, "optimization_goals")
+        let goals_db = backend
+            .open("optimization_goals", &mut watchers)
             .await
             .context("Failed to create optimization goals database")?;
 
+        // Create database for plan outcome statistics
+        let outcome_stats_db = backend
+            .open("outcome_stats", &mut watchers)
+            .await
+            .context("Failed to create outcome stats database")?;
+
+        // Create database for LLM spend
+        let daily_costs_db = backend
+            .open("llm_costs", &mut watchers)
+            .await
+            .context("Failed to create LLM cost database")?;
+
+        // Create database for tool invocations
+        let tool_invocations_db = backend
+            .open("tool_invocations", &mut watchers)
+            .await
+            .context("Failed to create tool invocation database")?;
+
+        // Create database for todo lists
+        let todos_db = backend
+            .open("todos", &mut watchers)
+            .await
+            .context("Failed to create todo database")?;
+
+        // Create database for the code index
+        let code_index_db = backend
+            .open("code_index", &mut watchers)
+            .await
+            .context("Failed to create code index database")?;
+
+        // Create database for lessons
+        let lessons_db = backend
+            .open("lessons", &mut watchers)
+            .await
+            .context("Failed to create lesson database")?;
+
+        // Create database for merges
+        let merges_db = backend
+            .open("merges", &mut watchers)
+            .await
+            .context("Failed to create merge database")?;
+
+        // Create vector collections for the code index and lessons
+        let code_vectors_db = backend
+            .open_vectors("code_vectors")
+            .await
+            .context("Failed to create code vector database")?;
+        let lesson_vectors_db = backend
+            .open_vectors("lesson_vectors")
+            .await
+            .context("Failed to create lesson vector database")?;
+
         Ok(Self {
             data_dir,
-            goals_db: Arc::new(goals_db),
+            goals_db,
+            outcome_stats_db,
+            daily_costs_db,
+            tool_invocations_db,
+            todos_db,
+            code_index_db,
+            lessons_db,
+            merges_db,
+            code_vectors_db,
+            lesson_vectors_db,
+            watchers,
         })
     }
 
+    /// Subscribe to the changes to `collection` made from now on, in this
+    /// process; `None` when there is no such collection
+    pub fn watch(&self, collection: &str) -> Option<broadcast::Receiver<Change>> {
+        self.watchers.get(collection).map(Watcher::subscribe)
+    }
+
+    /// Start a transaction over the collections of this database
+    pub fn begin(&self) -> Transaction {
+        Transaction::new()
+    }
+
+    /// Store every record kept in an older schema in the current one;
+    /// returns how many records of each collection were upgraded
+    pub async fn migrate(&self) -> Result<Vec<(&'static str, usize)>> {
+        Ok(vec![
+            ("optimization_goals", self.goals_db.migrate().await?),
+            ("outcome_stats", self.outcome_stats_db.migrate().await?),
+            ("llm_costs", self.daily_costs_db.migrate().await?),
+            (
+                "tool_invocations",
+                self.tool_invocations_db.migrate().await?,
+            ),
+            ("todos", self.todos_db.migrate().await?),
+            ("code_index", self.code_index_db.migrate().await?),
+            ("lessons", self.lessons_db.migrate().await?),
+            ("merges", self.merges_db.migrate().await?),
+        ])
+    }
+
+    /// Every record of every collection, for a backup or export
+    pub async fn export(&self) -> DbResult<Archive> {
+        let mut archive = Archive::new();
+        archive.add("optimization_goals", records(self.goals_db.as_ref()).await?);
+        archive.add(
+            "outcome_stats",
+            records(self.outcome_stats_db.as_ref()).await?,
+        );
+        archive.add("llm_costs", records(self.daily_costs_db.as_ref()).await?);
+        archive.add(
+            "tool_invocations",
+            records(self.tool_invocations_db.as_ref()).await?,
+        );
+        archive.add("todos", records(self.todos_db.as_ref()).await?);
+        archive.add("code_index", records(self.code_index_db.as_ref()).await?);
+        archive.add("lessons", records(self.lessons_db.as_ref()).await?);
+        archive.add("merges", records(self.merges_db.as_ref()).await?);
+        archive.add(
+            "code_vectors",
+            vector_records(self.code_vectors_db.as_ref()).await?,
+        );
+        archive.add(
+            "lesson_vectors",
+            vector_records(self.lesson_vectors_db.as_ref()).await?,
+        );
+        Ok(archive)
+    }
+
+    /// Replace the contents of every collection in `archive` with its
+    /// records; returns how many records of each collection were restored.
+    /// The records are replaced in one transaction, and the vectors after
+    /// it; restored records start over at version 1.
+    pub async fn restore(&self, archive: &Archive) -> DbResult<Vec<(&'static str, usize)>> {
+        let mut txn = self.begin();
+        let mut restored = vec![
+            (
+                "optimization_goals",
+                stage_restore(
+                    &mut txn,
+                    self.goals_db.as_ref(),
+                    archive,
+                    "optimization_goals",
+                )
+                .await?,
+            ),
+            (
+                "outcome_stats",
+                stage_restore(
+                    &mut txn,
+                    self.outcome_stats_db.as_ref(),
+                    archive,
+                    "outcome_stats",
+                )
+                .await?,
+            ),
+            (
+                "llm_costs",
+                stage_restore(&mut txn, self.daily_costs_db.as_ref(), archive, "llm_costs").await?,
+            ),
+            (
+                "tool_invocations",
+                stage_restore(
+                    &mut txn,
+                    self.tool_invocations_db.as_ref(),
+                    archive,
+                    "tool_invocations",
+                )
+                .await?,
+            ),
+            (
+                "todos",
+                stage_restore(&mut txn, self.todos_db.as_ref(), archive, "todos").await?,
+            ),
+            (
+                "code_index",
+                stage_restore(&mut txn, self.code_index_db.as_ref(), archive, "code_index").await?,
+            ),
+            (
+                "lessons",
+                stage_restore(&mut txn, self.lessons_db.as_ref(), archive, "lessons").await?,
+            ),
+            (
+                "merges",
+                stage_restore(&mut txn, self.merges_db.as_ref(), archive, "merges").await?,
+            ),
+        ];
+        let code_vectors = vector_entries(archive, "code_vectors")?;
+        let lesson_vectors = vector_entries(archive, "lesson_vectors")?;
+        // An archive from a newer build may hold collections this one lacks
+        let known = |name: &str| {
+            restored.iter().any(|(known, _)| *known == name)
+                || name == "code_vectors"
+                || name == "lesson_vectors"
+        };
+        if let Some(unknown) = archive.names().find(|name| !known(name)) {
+            return Err(DatabaseError::ArchiveError(format!(
+                "Unknown collection {} in the archive",
+                unknown
+            )));
+        }
+
+        txn.commit().await?;
+        for (name, store, entries) in [
+            ("code_vectors", &self.code_vectors_db, code_vectors),
+            ("lesson_vectors", &self.lesson_vectors_db, lesson_vectors),
+        ] {
+            if let Some(entries) = entries {
+                restored.push((name, entries.len()));
+                replace_vectors(store.as_ref(), entries).await?;
+            }
+        }
+        restored.retain(|(name, _)| archive.collection(name).is_some());
+        Ok(restored)
+    }
+
     /// Get the optimization goals database
     pub fn goals(&self) -> Arc<dyn DatabaseInterface<OptimizationGoal>> {
         self.goals_db.clone()
     }
+
+    /// Get the plan outcome statistics database
+    pub fn outcome_stats(&self) -> Arc<dyn DatabaseInterface<OutcomeStats>> {
+        self.outcome_stats_db.clone()
+    }
+
+    /// Get the per-day LLM spend database
+    pub fn daily_costs(&self) -> Arc<dyn DatabaseInterface<DailyCost>> {
+        self.daily_costs_db.clone()
+    }
+
+    /// Get the tool invocation audit log database
+    pub fn tool_invocations(&self) -> Arc<dyn DatabaseInterface<ToolInvocation>> {
+        self.tool_invocations_db.clone()
+    }
+
+    /// Get the per-goal todo list database
+    pub fn todos(&self) -> Arc<dyn DatabaseInterface<TodoList>> {
+        self.todos_db.clone()
+    }
+
+    /// Get the code index database
+    pub fn code_index(&self) -> Arc<dyn DatabaseInterface<IndexedFile>> {
+        self.code_index_db.clone()
+    }
+
+    /// Get the lessons database
+    pub fn lessons(&self) -> Arc<dyn DatabaseInterface<Lesson>> {
+        self.lessons_db.clone()
+    }
+
+    /// Get the per-goal merge database
+    pub fn merges(&self) -> Arc<dyn DatabaseInterface<MergeRecord>> {
+        self.merges_db.clone()
+    }
+
+    /// Get the embeddings of the workspace's chunks
+    pub fn code_vectors(&self) -> Arc<dyn VectorStore> {
+        self.code_vectors_db.clone()
+    }
+
+    /// Get the embeddings of the lessons
+    pub fn lesson_vectors(&self) -> Arc<dyn VectorStore> {
+        self.lesson_vectors_db.clone()
+    }
+}
+
+/// The records of `collection` as stored, by id
+async fn records<T: Entity + for<'a> Deserialize<'a> + Unpin>(
+    collection: &dyn DatabaseInterface<T>,
+) -> DbResult<Vec<Value>> {
+    let mut records = collection.get_all().await?;
+    records.sort_by(|a, b| a.id().as_ref().cmp(b.id().as_ref()));
+    records
+        .iter()
+        .map(|record| Ok(serde_json::to_value(record)?))
+        .collect()
+}
+
+/// The entries of a vector collection as stored, by id
+async fn vector_records(store: &dyn VectorStore) -> DbResult<Vec<Value>> {
+    store
+        .entries()
+        .await?
+        .iter()
+        .map(|entry| Ok(serde_json::to_value(entry)?))
+        .collect()
+}
+
+/// Stage replacing the records of `collection` with those of `name` in
+/// `archive`, upgraded to the current schema; returns how many there are
+async fn stage_restore<T: Entity + for<'a> Deserialize<'a> + Unpin>(
+    txn: &mut Transaction,
+    collection: &dyn DatabaseInterface<T>,
+    archive: &Archive,
+    name: &str,
+) -> DbResult<usize> {
+    let Some(records) = archive.collection(name) else {
+        return Ok(0);
+    };
+    for record in collection.get_all().await? {
+        txn.delete(collection, &record.id());
+    }
+    for raw in records {
+        let (record, _) = migration::read_record::<T>(raw.clone())?;
+        txn.insert(collection, record.entity)?;
+    }
+    Ok(records.len())
+}
+
+/// The vector entries of `name` in `archive`, if it has them
+fn vector_entries(archive: &Archive, name: &str) -> DbResult<Option<Vec<VectorEntry>>> {
+    archive
+        .collection(name)
+        .map(|records| {
+            records
+                .iter()
+                .map(|raw| Ok(serde_json::from_value(raw.clone())?))
+                .collect()
+        })
+        .transpose()
+}
+
+/// Replace every entry of `store` with `entries`
+async fn replace_vectors(store: &dyn VectorStore, entries: Vec<VectorEntry>) -> DbResult<()> {
+    let stale: Vec<String> = store
+        .entries()
+        .await?
+        .into_iter()
+        .map(|entry| entry.id)
+        .collect();
+    store.remove(&stale).await?;
+    store.upsert(entries).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn config() -> Config {
+        serde_yaml::from_str(
+            r#"
+models: []
+phases:
+  research: { models: [], tools: [], prompt: "" }
+  deliberation: { models: [], tools: [], prompt: "" }
+  tdd: { models: [], tools: [], prompt: "" }
+agent: { working_dir: ., timeout_seconds: 60, max_memory_usage_mb: 1024, max_cpu_usage_percent: 80 }
+database: { path: ./data/borg.db }
+git: { branch_prefix: borg/ }
+logging: { enabled: false, llm_log_dir: ./logs }
+"#,
+        )
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_restore_replaces_state_with_an_export() {
+        let dir = tempfile::tempdir().unwrap();
+        let source = DatabaseManager::new(dir.path().join("source"), &config())
+            .await
+            .unwrap();
+        source
+            .goals()
+            .insert(OptimizationGoal::new("goal-1", "Title", "Description"))
+            .await
+            .unwrap();
+        source
+            .lesson_vectors()
+            .upsert(vec![VectorEntry::new(
+                "lesson-1",
+                vec![1.0, 0.0],
+                json!({}),
+            )])
+            .await
+            .unwrap();
+
+        let target = DatabaseManager::new(dir.path().join("target"), &config())
+            .await
+            .unwrap();
+        target
+            .goals()
+            .insert(OptimizationGoal::new("goal-2", "Other", "Description"))
+            .await
+            .unwrap();
+        let mut written = Vec::new();
+        source
+            .export()
+            .await
+            .unwrap()
+            .write(&mut written, None)
+            .unwrap();
+        let restored = target
+            .restore(&Archive::read(written.as_slice(), None).unwrap())
+            .await
+            .unwrap();
+
+        assert!(restored.contains(&("optimization_goals", 1)));
+        assert!(restored.contains(&("lesson_vectors", 1)));
+        let goals = target.goals().get_all().await.unwrap();
+        assert_eq!(goals.len(), 1);
+        assert_eq!(goals[0].entity.id, "goal-1");
+        assert_eq!(target.lesson_vectors().count().await.unwrap(), 1);
+
+        let mut unknown = Archive::new();
+        unknown.add("plans", Vec::new());
+        assert!(matches!(
+            target.restore(&unknown).await,
+            Err(DatabaseError::ArchiveError(_))
+        ));
+        assert_eq!(target.goals().get_all().await.unwrap().len(), 1);
+    }
 }
@@ -0,0 +1,172 @@
+//! Upgrading stored entities to their current schema.
+//!
+//! Records keep the schema version their entity was stored in. Before a
+//! stored entity is deserialized, its JSON goes through the entity's
+//! migrations from that version on, so renamed or reshaped fields do not
+//! make existing data directories unreadable. Upgraded records are written
+//! back on their next update, or all at once by `borg db migrate`.
+
+use serde::Deserialize;
+use serde_json::Value;
+
+use crate::database::{DatabaseError, DbResult, Entity, Record};
+
+/// Upgrade the stored JSON of a `T` from schema version `from` to the
+/// current one; returns whether anything had to be upgraded
+pub fn upgrade_entity<T: Entity>(entity: &mut Value, from: u32) -> DbResult<bool> {
+    let from = from.max(1);
+    let current = T::SCHEMA_VERSION;
+    let name = std::any::type_name::<T>();
+    if from > current {
+        return Err(DatabaseError::MigrationError(format!(
+            "{} was stored at schema version {}, newer than this build's {}",
+            name, from, current
+        )));
+    }
+    let migrations = T::migrations();
+    for version in from..current {
+        let step = migrations.get(version as usize - 1).ok_or_else(|| {
+            DatabaseError::MigrationError(format!(
+                "{} has no migration from schema version {} to {}",
+                name,
+                version,
+                version + 1
+            ))
+        })?;
+        step(entity).map_err(|e| {
+            DatabaseError::MigrationError(format!(
+                "Upgrading {} from schema version {} to {}: {}",
+                name,
+                version,
+                version + 1,
+                e
+            ))
+        })?;
+    }
+    Ok(from < current)
+}
+
+/// A stored record, with its entity upgraded to the current schema;
+/// returns whether anything had to be upgraded
+pub fn read_record<T: Entity + for<'a> Deserialize<'a> + Unpin>(
+    mut raw: Value,
+) -> DbResult<(Record<T>, bool)> {
+    let from = raw
+        .get("schema_version")
+        .and_then(Value::as_u64)
+        .unwrap_or(1) as u32;
+    let upgraded = match raw.get_mut("entity") {
+        Some(entity) => upgrade_entity::<T>(entity, from)?,
+        None => false,
+    };
+    raw["schema_version"] = Value::from(T::SCHEMA_VERSION);
+    Ok((serde_json::from_value(raw)?, upgraded))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::{EntityMigration, FileDb};
+    use serde::Serialize;
+    use serde_json::json;
+
+    /// An entity whose `name` was split into `first` and `last`
+    #[derive(Debug, Clone, Serialize, Deserialize)]
+    struct Person {
+        id: String,
+        first: String,
+        last: String,
+        age: u32,
+    }
+
+    fn split_name(entity: &mut Value) -> Result<(), String> {
+        let name = entity
+            .get("name")
+            .and_then(Value::as_str)
+            .ok_or("missing name")?
+            .to_string();
+        let (first, last) = name.split_once(' ').unwrap_or((&name, ""));
+        entity["first"] = json!(first);
+        entity["last"] = json!(last);
+        Ok(())
+    }
+
+    fn default_age(entity: &mut Value) -> Result<(), String> {
+        entity["age"] = json!(0);
+        Ok(())
+    }
+
+    impl Entity for Person {
+        type Id = String;
+
+        const SCHEMA_VERSION: u32 = 3;
+
+        fn id(&self) -> String {
+            self.id.clone()
+        }
+
+        fn migrations() -> &'static [EntityMigration] {
+            &[split_name, default_age]
+        }
+    }
+
+    #[test]
+    fn test_records_are_upgraded_step_by_step() {
+        let stored = json!({
+            "entity": {"id": "p1", "name": "Ada Lovelace"},
+            "created_at": "2024-01-01T00:00:00Z",
+            "updated_at": "2024-01-01T00:00:00Z",
+            "version": 4
+        });
+
+        let (record, upgraded) = read_record::<Person>(stored).unwrap();
+
+        assert!(upgraded);
+        assert_eq!(record.schema_version, 3);
+        assert_eq!(record.version, 4);
+        assert_eq!(record.entity.first, "Ada");
+        assert_eq!(record.entity.last, "Lovelace");
+        assert_eq!(record.entity.age, 0);
+
+        let current = serde_json::to_value(&record).unwrap();
+        let (_, upgraded) = read_record::<Person>(current).unwrap();
+        assert!(!upgraded);
+    }
+
+    #[tokio::test]
+    async fn test_file_db_upgrades_on_load_and_migrate_writes_back() {
+        let dir = tempfile::tempdir().unwrap();
+        let stored = json!([{
+            "entity": {"id": "p1", "name": "Ada Lovelace"},
+            "created_at": "2024-01-01T00:00:00Z",
+            "updated_at": "2024-01-01T00:00:00Z",
+            "version": 1
+        }]);
+        std::fs::write(dir.path().join("people.json"), stored.to_string()).unwrap();
+
+        let people = FileDb::<Person>::new(dir.path(), "people").await.unwrap();
+        assert_eq!(
+            people.get(&"p1".to_string()).await.unwrap().entity.first,
+            "Ada"
+        );
+        assert_eq!(people.migrate().await.unwrap(), 1);
+        assert_eq!(people.migrate().await.unwrap(), 0);
+
+        let saved = std::fs::read_to_string(dir.path().join("people.json")).unwrap();
+        assert!(saved.contains("\"schema_version\": 3"), "{}", saved);
+        assert!(!saved.contains("\"name\""), "{}", saved);
+    }
+
+    #[test]
+    fn test_newer_and_broken_records_are_refused() {
+        let mut newer = json!({"id": "p1"});
+        assert!(matches!(
+            upgrade_entity::<Person>(&mut newer, 4),
+            Err(DatabaseError::MigrationError(_))
+        ));
+
+        let mut unnamed = json!({"id": "p1"});
+        let err = upgrade_entity::<Person>(&mut unnamed, 1).unwrap_err();
+        assert!(err.to_string().contains("missing name"), "{}", err);
+    }
+}
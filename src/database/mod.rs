@@ -1,13 +1,30 @@
 //! Database module for Borg
 //!
 //! This module implements a simple file-based database system
-//! that provides persistent storage for the agent's data.
+//! that provides persistent storage for the agent's data, and a
+//! PostgreSQL backend for agents sharing their state.
 
+mod archive;
+mod collection;
 mod entities;
 mod file_db;
+mod hnsw;
 mod manager;
+pub mod migration;
 mod models;
+mod postgres;
+mod query;
+mod transaction;
+mod vector;
+mod watch;
 
+pub use archive::{Archive, ARCHIVE_VERSION};
+pub use collection::Collection;
 pub use file_db::{DatabaseError, DbResult, FileDb};
-pub use manager::DatabaseManager;
-pub use models::{Entity, Record};
+pub use manager::{DatabaseInterface, DatabaseManager};
+pub use models::{Entity, EntityMigration, Record};
+pub use postgres::{PgCollection, PgStore, PgVectorStore};
+pub use query::{Filter, FilterOp, Query, SortOrder};
+pub use transaction::{Participant, Transaction};
+pub use vector::{cosine_similarity, FileVectorStore, VectorEntry, VectorMatch, VectorStore};
+pub use watch::{Change, ChangeKind, CHANGE_BUFFER};
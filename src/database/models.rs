@@ -12,10 +12,28 @@ pub trait Entity: Serialize + Clone + Debug + Send + Sync + 'static {
     /// Type of the entity's unique identifier
     type Id: AsRef<str> + Eq + Hash + Clone + Debug + Send + Sync + 'static;
 
+    /// Version of the stored form of the entity; bump it together with a
+    /// new step in `migrations()` when a change to its fields would break
+    /// reading what is already stored
+    const SCHEMA_VERSION: u32 = 1;
+
+    /// Top-level fields looked up often enough to keep a secondary index
+    /// on, for equality filters in queries
+    const INDEXED_FIELDS: &'static [&'static str] = &[];
+
     /// Get the unique identifier for this entity
     fn id(&self) -> Self::Id;
+
+    /// Steps upgrading the stored JSON of the entity, the first from
+    /// version 1 to 2, the next from 2 to 3 and so on
+    fn migrations() -> &'static [EntityMigration] {
+        &[]
+    }
 }
 
+/// Upgrades the stored JSON of an entity by one schema version
+pub type EntityMigration = fn(&mut serde_json::Value) -> Result<(), String>;
+
 /// A record in the database, which wraps an entity with metadata
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(bound(deserialize = "T: Entity + for<'a> Deserialize<'a>"))]
@@ -28,6 +46,14 @@ pub struct Record<T: Entity + for<'a> Deserialize<'a> + Unpin> {
     pub updated_at: DateTime<Utc>,
     /// Version number for optimistic concurrency control
     pub version: u64,
+    /// Schema version the entity was stored in; records from before
+    /// schema versions were kept are at version 1
+    #[serde(default = "initial_schema_version")]
+    pub schema_version: u32,
+}
+
+fn initial_schema_version() -> u32 {
+    1
 }
 
 impl<T: Entity + for<'a> Deserialize<'a> + Unpin> Record<T> {
@@ -39,6 +65,7 @@ impl<T: Entity + for<'a> Deserialize<'a> + Unpin> Record<T> {
             created_at: now,
             updated_at: now,
             version: 1,
+            schema_version: T::SCHEMA_VERSION,
         }
     }
 
@@ -47,6 +74,7 @@ impl<T: Entity + for<'a> Deserialize<'a> + Unpin> Record<T> {
         self.entity = entity;
         self.updated_at = Utc::now();
         self.version += 1;
+        self.schema_version = T::SCHEMA_VERSION;
     }
 
     /// Get the entity's ID
@@ -0,0 +1,874 @@
+//! PostgreSQL storage for agent state shared between Borg instances.
+//!
+//! Every collection lives in one `borg_records` table keyed by collection
+//! and id, with the entity stored as JSONB. Updates check the expected
+//! version in the `UPDATE` itself, so of two instances updating the same
+//! record only one wins. With encryption enabled the entity column holds
+//! sealed envelopes, so queries are answered in memory instead of in SQL. Vector collections live in `borg_vectors`, ranked
+//! by a cosine similarity function scanning the collection. The schema is created and upgraded by the
+//! migrations below, applied under an advisory lock when connecting.
+
+use std::marker::PhantomData;
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use log::info;
+use serde::Deserialize;
+use sqlx::postgres::{PgConnection, PgPool, PgPoolOptions, PgRow};
+use sqlx::Row;
+
+use crate::core::config::PostgresConfig;
+use crate::core::encryption::{self, Cipher};
+use crate::database::manager::DatabaseInterface;
+use crate::database::migration;
+use crate::database::query::{FilterOp, Query, SortOrder};
+use crate::database::transaction::{Participant, ParticipantKind, StagedWrite, WriteOp};
+use crate::database::vector::{VectorEntry, VectorMatch, VectorStore};
+use crate::database::{DatabaseError, DbResult, Entity, Record};
+
+/// A step of the schema, applied once per database in version order
+struct Migration {
+    version: i64,
+    description: &'static str,
+    sql: &'static str,
+}
+
+/// The schema; append new steps, never edit applied ones
+const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        description: "records table",
+        sql: "CREATE TABLE borg_records (
+        collection TEXT NOT NULL,
+        id TEXT NOT NULL,
+        entity JSONB NOT NULL,
+        created_at TIMESTAMPTZ NOT NULL,
+        updated_at TIMESTAMPTZ NOT NULL,
+        version BIGINT NOT NULL,
+        PRIMARY KEY (collection, id)
+    )",
+    },
+    Migration {
+        version: 2,
+        description: "entity schema versions",
+        sql: "ALTER TABLE borg_records ADD COLUMN schema_version INTEGER NOT NULL DEFAULT 1",
+    },
+    Migration {
+        version: 3,
+        description: "indexes on common entity fields",
+        sql: "CREATE INDEX borg_records_status ON borg_records (collection, (entity->'status'));
+              CREATE INDEX borg_records_category ON borg_records (collection, (entity->'category'));
+              CREATE INDEX borg_records_created_at ON borg_records (collection, (entity->'created_at'));
+              CREATE INDEX borg_records_timestamp ON borg_records (collection, (entity->'timestamp'));
+              CREATE INDEX borg_records_tool ON borg_records (collection, (entity->'tool'));
+              CREATE INDEX borg_records_date ON borg_records (collection, (entity->'date'))",
+    },
+    Migration {
+        version: 4,
+        description: "vector collections",
+        sql: "CREATE TABLE borg_vectors (
+                  collection TEXT NOT NULL,
+                  id TEXT NOT NULL,
+                  vector REAL[] NOT NULL,
+                  metadata JSONB NOT NULL DEFAULT 'null',
+                  PRIMARY KEY (collection, id)
+              );
+              CREATE FUNCTION borg_cosine(a REAL[], b REAL[]) RETURNS DOUBLE PRECISION
+              LANGUAGE SQL IMMUTABLE PARALLEL SAFE AS $$
+                  SELECT SUM(x * y) / NULLIF(SQRT(SUM(x * x)) * SQRT(SUM(y * y)), 0)
+                  FROM UNNEST(a, b) AS t(x, y)
+              $$",
+    },
+];
+
+/// Advisory lock key serializing migrations between instances
+const MIGRATION_LOCK: i64 = 0x626f_7267;
+
+fn backend_error(e: sqlx::Error) -> DatabaseError {
+    DatabaseError::InternalError(format!("Postgres: {}", e))
+}
+
+/// A pool of connections to the shared database
+#[derive(Clone)]
+pub struct PgStore {
+    pool: PgPool,
+    cipher: Option<Cipher>,
+}
+
+impl PgStore {
+    /// Connect to the database of `config` and bring its schema up to date
+    pub async fn connect(config: &PostgresConfig) -> DbResult<Self> {
+        let url = config.resolve_url().ok_or_else(|| {
+            DatabaseError::InternalError(format!(
+                "No Postgres URL configured; set database.postgres.url or {}",
+                config.url_env
+            ))
+        })?;
+        let pool = PgPoolOptions::new()
+            .max_connections(config.max_connections)
+            .acquire_timeout(Duration::from_secs(config.acquire_timeout_seconds))
+            .connect(&url)
+            .await
+            .map_err(backend_error)?;
+        let store = Self { pool, cipher: None };
+        store.migrate().await?;
+        Ok(store)
+    }
+
+    /// Apply the migrations this database has not seen yet
+    pub async fn migrate(&self) -> DbResult<()> {
+        let mut tx = self.pool.begin().await.map_err(backend_error)?;
+        sqlx::query("SELECT pg_advisory_xact_lock($1)")
+            .bind(MIGRATION_LOCK)
+            .execute(&mut *tx)
+            .await
+            .map_err(backend_error)?;
+        sqlx::query(
+            "CREATE TABLE IF NOT EXISTS borg_schema_migrations (
+                version BIGINT PRIMARY KEY,
+                description TEXT NOT NULL,
+                applied_at TIMESTAMPTZ NOT NULL DEFAULT now()
+            )",
+        )
+        .execute(&mut *tx)
+        .await
+        .map_err(backend_error)?;
+        let applied: Option<i64> =
+            sqlx::query_scalar("SELECT MAX(version) FROM borg_schema_migrations")
+                .fetch_one(&mut *tx)
+                .await
+                .map_err(backend_error)?;
+
+        for migration in MIGRATIONS
+            .iter()
+            .filter(|m| m.version > applied.unwrap_or(0))
+        {
+            info!(
+                "Applying database migration {}: {}",
+                migration.version, migration.description
+            );
+            sqlx::raw_sql(migration.sql)
+                .execute(&mut *tx)
+                .await
+                .map_err(backend_error)?;
+            sqlx::query(
+                "INSERT INTO borg_schema_migrations (version, description) VALUES ($1, $2)",
+            )
+            .bind(migration.version)
+            .bind(migration.description)
+            .execute(&mut *tx)
+            .await
+            .map_err(backend_error)?;
+        }
+        tx.commit().await.map_err(backend_error)
+    }
+
+    /// Encrypt the entities of this database's collections with `cipher`
+    pub fn with_cipher(mut self, cipher: Option<Cipher>) -> Self {
+        self.cipher = cipher;
+        self
+    }
+
+    /// The collection `name` of this database
+    pub fn collection<T: Entity + for<'a> Deserialize<'a> + Unpin>(
+        &self,
+        name: &str,
+    ) -> PgCollection<T> {
+        PgCollection {
+            pool: self.pool.clone(),
+            collection: name.to_string(),
+            cipher: self.cipher.clone(),
+            _phantom: PhantomData,
+        }
+    }
+
+    /// The vector collection `name` of this database
+    pub fn vectors(&self, name: &str) -> PgVectorStore {
+        PgVectorStore {
+            pool: self.pool.clone(),
+            collection: name.to_string(),
+        }
+    }
+}
+
+/// `field` for use in SQL, which only takes plain field names
+fn sql_field(field: &str) -> DbResult<&str> {
+    if !field.is_empty() && field.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+        Ok(field)
+    } else {
+        Err(DatabaseError::InternalError(format!(
+            "Cannot query field {:?}",
+            field
+        )))
+    }
+}
+
+/// `entity` as stored in `collection`, sealed when encrypted
+fn seal_entity(
+    cipher: Option<&Cipher>,
+    collection: &str,
+    entity: serde_json::Value,
+) -> DbResult<serde_json::Value> {
+    match cipher {
+        Some(cipher) => cipher
+            .seal_json(&entity, collection)
+            .map_err(|e| DatabaseError::EncryptionError(e.to_string())),
+        None => Ok(entity),
+    }
+}
+
+/// An entity stored in `collection`, opened when sealed
+fn open_entity(
+    cipher: Option<&Cipher>,
+    collection: &str,
+    entity: serde_json::Value,
+) -> DbResult<serde_json::Value> {
+    match cipher {
+        Some(cipher) => cipher
+            .open_json(entity, collection)
+            .map_err(|e| DatabaseError::EncryptionError(format!("{}: {}", collection, e))),
+        None if encryption::is_sealed(&entity) => Err(DatabaseError::EncryptionError(format!(
+            "{} is encrypted; enable encryption and configure its key",
+            collection
+        ))),
+        None => Ok(entity),
+    }
+}
+
+/// A collection of the shared database, as a transaction target
+#[derive(Clone)]
+pub(crate) struct PgTarget {
+    pool: PgPool,
+    collection: String,
+    cipher: Option<Cipher>,
+}
+
+/// Apply `writes` in one database transaction, or none of them
+pub(crate) async fn commit_writes(writes: &[(PgTarget, StagedWrite)]) -> DbResult<()> {
+    let Some((first, _)) = writes.first() else {
+        return Ok(());
+    };
+    let mut tx = first.pool.begin().await.map_err(backend_error)?;
+    for (target, write) in writes {
+        // Dropping the transaction on an error rolls it back
+        apply_write(&mut tx, target, write).await?;
+    }
+    tx.commit().await.map_err(backend_error)
+}
+
+async fn apply_write(
+    conn: &mut PgConnection,
+    target: &PgTarget,
+    write: &StagedWrite,
+) -> DbResult<()> {
+    let collection = target.collection.as_str();
+    let seal = |entity: &serde_json::Value| {
+        seal_entity(target.cipher.as_ref(), collection, entity.clone())
+    };
+    let now = Utc::now();
+    match &write.op {
+        WriteOp::Insert { entity } => {
+            let inserted = sqlx::query(
+                "INSERT INTO borg_records
+                     (collection, id, entity, created_at, updated_at, version, schema_version)
+                 VALUES ($1, $2, $3, $4, $4, 1, $5)
+                 ON CONFLICT DO NOTHING",
+            )
+            .bind(collection)
+            .bind(&write.id)
+            .bind(seal(entity)?)
+            .bind(now)
+            .bind(write.schema_version as i32)
+            .execute(&mut *conn)
+            .await
+            .map_err(backend_error)?;
+            if inserted.rows_affected() == 0 {
+                return Err(DatabaseError::DuplicateKey(write.id.clone()));
+            }
+        }
+        WriteOp::Update {
+            entity,
+            expected_version,
+        } => {
+            let updated = sqlx::query(
+                "UPDATE borg_records
+                 SET entity = $3, updated_at = $4, version = version + 1, schema_version = $6
+                 WHERE collection = $1 AND id = $2 AND ($5::BIGINT IS NULL OR version = $5)",
+            )
+            .bind(collection)
+            .bind(&write.id)
+            .bind(seal(entity)?)
+            .bind(now)
+            .bind(expected_version.map(|v| v as i64))
+            .bind(write.schema_version as i32)
+            .execute(&mut *conn)
+            .await
+            .map_err(backend_error)?;
+            if updated.rows_affected() == 0 {
+                let found: Option<i64> = sqlx::query_scalar(
+                    "SELECT version FROM borg_records WHERE collection = $1 AND id = $2",
+                )
+                .bind(collection)
+                .bind(&write.id)
+                .fetch_optional(&mut *conn)
+                .await
+                .map_err(backend_error)?;
+                return Err(match (found, expected_version) {
+                    (Some(found), Some(expected)) => DatabaseError::VersionConflict {
+                        expected: *expected,
+                        found: found as u64,
+                    },
+                    _ => DatabaseError::NotFound(write.id.clone()),
+                });
+            }
+        }
+        WriteOp::Delete => {
+            let deleted = sqlx::query("DELETE FROM borg_records WHERE collection = $1 AND id = $2")
+                .bind(collection)
+                .bind(&write.id)
+                .execute(&mut *conn)
+                .await
+                .map_err(backend_error)?;
+            if deleted.rows_affected() == 0 {
+                return Err(DatabaseError::NotFound(write.id.clone()));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// One collection of entities in the shared database
+pub struct PgCollection<T: Entity + for<'a> Deserialize<'a> + Unpin> {
+    pool: PgPool,
+    collection: String,
+    cipher: Option<Cipher>,
+    _phantom: PhantomData<T>,
+}
+
+impl<T: Entity + for<'a> Deserialize<'a> + Unpin> PgCollection<T> {
+    fn record(&self, row: &PgRow) -> DbResult<Record<T>> {
+        let entity: serde_json::Value = row.try_get("entity").map_err(backend_error)?;
+        let mut entity = open_entity(self.cipher.as_ref(), &self.collection, entity)?;
+        let version: i64 = row.try_get("version").map_err(backend_error)?;
+        let schema_version: i32 = row.try_get("schema_version").map_err(backend_error)?;
+        migration::upgrade_entity::<T>(&mut entity, schema_version as u32)?;
+        Ok(Record {
+            entity: serde_json::from_value(entity)?,
+            created_at: row.try_get("created_at").map_err(backend_error)?,
+            updated_at: row.try_get("updated_at").map_err(backend_error)?,
+            version: version as u64,
+            schema_version: T::SCHEMA_VERSION,
+        })
+    }
+
+    async fn current_version(&self, id: &str) -> DbResult<Option<u64>> {
+        let version: Option<i64> = sqlx::query_scalar(
+            "SELECT version FROM borg_records WHERE collection = $1 AND id = $2",
+        )
+        .bind(&self.collection)
+        .bind(id)
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(backend_error)?;
+        Ok(version.map(|v| v as u64))
+    }
+}
+
+#[async_trait::async_trait]
+impl<T: Entity + for<'a> Deserialize<'a> + Unpin> DatabaseInterface<T> for PgCollection<T> {
+    async fn get(&self, id: &T::Id) -> DbResult<Record<T>> {
+        let row = sqlx::query(
+            "SELECT entity, created_at, updated_at, version, schema_version FROM borg_records
+             WHERE collection = $1 AND id = $2",
+        )
+        .bind(&self.collection)
+        .bind(id.as_ref())
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(backend_error)?
+        .ok_or_else(|| DatabaseError::NotFound(id.as_ref().to_string()))?;
+        self.record(&row)
+    }
+
+    async fn query(&self, query: &Query) -> DbResult<Vec<Record<T>>> {
+        // Sealed entities can only be filtered once opened
+        if self.cipher.is_some() {
+            let records = self.get_all().await?;
+            return Ok(query.apply(records, |record| {
+                serde_json::to_value(&record.entity).unwrap_or_default()
+            }));
+        }
+        let mut sql = String::from(
+            "SELECT entity, created_at, updated_at, version, schema_version FROM borg_records
+             WHERE collection = $1",
+        );
+        let mut args = Vec::new();
+        for filter in &query.filters {
+            let field = sql_field(&filter.field)?;
+            args.push(filter.value.clone());
+            let arg = args.len() + 1;
+            let clause = match (&filter.value, filter.op) {
+                (_, FilterOp::Ne) => format!("entity->'{}' IS DISTINCT FROM ${}", field, arg),
+                (_, FilterOp::Eq) => format!("entity->'{}' = ${}", field, arg),
+                // Timestamps compare as times, not as their text
+                (serde_json::Value::String(value), op)
+                    if DateTime::parse_from_rfc3339(value).is_ok() =>
+                {
+                    format!(
+                        "(entity->>'{}')::timestamptz {} (${}#>>'{{}}')::timestamptz",
+                        field,
+                        op.sql(),
+                        arg
+                    )
+                }
+                (_, op) => format!("entity->'{}' {} ${}", field, op.sql(), arg),
+            };
+            sql.push_str(" AND ");
+            sql.push_str(&clause);
+        }
+        if let Some((field, order)) = &query.sort {
+            let direction = match order {
+                SortOrder::Ascending => "ASC",
+                SortOrder::Descending => "DESC",
+            };
+            sql.push_str(&format!(
+                " ORDER BY entity->'{}' {} NULLS LAST",
+                sql_field(field)?,
+                direction
+            ));
+        }
+        sql.push_str(&format!(" OFFSET {}", query.offset));
+        if let Some(limit) = query.limit {
+            sql.push_str(&format!(" LIMIT {}", limit));
+        }
+
+        let mut statement = sqlx::query(&sql).bind(&self.collection);
+        for arg in args {
+            statement = statement.bind(arg);
+        }
+        let rows = statement
+            .fetch_all(&self.pool)
+            .await
+            .map_err(backend_error)?;
+        rows.iter().map(|row| self.record(row)).collect()
+    }
+
+    async fn get_all(&self) -> DbResult<Vec<Record<T>>> {
+        let rows = sqlx::query(
+            "SELECT entity, created_at, updated_at, version, schema_version FROM borg_records
+             WHERE collection = $1",
+        )
+        .bind(&self.collection)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(backend_error)?;
+        rows.iter().map(|row| self.record(row)).collect()
+    }
+
+    async fn insert(&self, entity: T) -> DbResult<Record<T>> {
+        let record = Record::new(entity);
+        let id = record.id();
+        let inserted = sqlx::query(
+            "INSERT INTO borg_records
+                 (collection, id, entity, created_at, updated_at, version, schema_version)
+             VALUES ($1, $2, $3, $4, $5, $6, $7)
+             ON CONFLICT DO NOTHING",
+        )
+        .bind(&self.collection)
+        .bind(id.as_ref())
+        .bind(seal_entity(
+            self.cipher.as_ref(),
+            &self.collection,
+            serde_json::to_value(&record.entity)?,
+        )?)
+        .bind(record.created_at)
+        .bind(record.updated_at)
+        .bind(record.version as i64)
+        .bind(record.schema_version as i32)
+        .execute(&self.pool)
+        .await
+        .map_err(backend_error)?;
+        if inserted.rows_affected() == 0 {
+            return Err(DatabaseError::DuplicateKey(id.as_ref().to_string()));
+        }
+        Ok(record)
+    }
+
+    async fn update(&self, entity: T, expected_version: Option<u64>) -> DbResult<Record<T>> {
+        let id = entity.id();
+        let updated_at: DateTime<Utc> = Utc::now();
+        let row = sqlx::query(
+            "UPDATE borg_records
+             SET entity = $3, updated_at = $4, version = version + 1, schema_version = $6
+             WHERE collection = $1 AND id = $2 AND ($5::BIGINT IS NULL OR version = $5)
+             RETURNING entity, created_at, updated_at, version, schema_version",
+        )
+        .bind(&self.collection)
+        .bind(id.as_ref())
+        .bind(seal_entity(
+            self.cipher.as_ref(),
+            &self.collection,
+            serde_json::to_value(&entity)?,
+        )?)
+        .bind(updated_at)
+        .bind(expected_version.map(|v| v as i64))
+        .bind(T::SCHEMA_VERSION as i32)
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(backend_error)?;
+        if let Some(row) = row {
+            return self.record(&row);
+        }
+
+        // Nothing matched: tell a missing record from a stale version
+        match (self.current_version(id.as_ref()).await?, expected_version) {
+            (Some(found), Some(expected)) => {
+                Err(DatabaseError::VersionConflict { expected, found })
+            }
+            _ => Err(DatabaseError::NotFound(id.as_ref().to_string())),
+        }
+    }
+
+    async fn delete(&self, id: &T::Id) -> DbResult<()> {
+        let deleted = sqlx::query("DELETE FROM borg_records WHERE collection = $1 AND id = $2")
+            .bind(&self.collection)
+            .bind(id.as_ref())
+            .execute(&self.pool)
+            .await
+            .map_err(backend_error)?;
+        if deleted.rows_affected() == 0 {
+            return Err(DatabaseError::NotFound(id.as_ref().to_string()));
+        }
+        Ok(())
+    }
+
+    async fn clear(&self) -> DbResult<()> {
+        sqlx::query("DELETE FROM borg_records WHERE collection = $1")
+            .bind(&self.collection)
+            .execute(&self.pool)
+            .await
+            .map_err(backend_error)?;
+        Ok(())
+    }
+
+    fn participant(&self) -> Participant {
+        Participant::new(ParticipantKind::Postgres(PgTarget {
+            pool: self.pool.clone(),
+            collection: self.collection.clone(),
+            cipher: self.cipher.clone(),
+        }))
+    }
+
+    async fn migrate(&self) -> DbResult<usize> {
+        // Records in an older schema, and plain ones once encryption is on
+        let rows = sqlx::query(
+            "SELECT id, entity, schema_version FROM borg_records
+             WHERE collection = $1
+               AND (schema_version < $2 OR ($3 AND NOT (entity ? 'sealed')))",
+        )
+        .bind(&self.collection)
+        .bind(T::SCHEMA_VERSION as i32)
+        .bind(self.cipher.is_some())
+        .fetch_all(&self.pool)
+        .await
+        .map_err(backend_error)?;
+
+        let mut upgraded = 0;
+        for row in rows {
+            let id: String = row.try_get("id").map_err(backend_error)?;
+            let entity: serde_json::Value = row.try_get("entity").map_err(backend_error)?;
+            let mut entity = open_entity(self.cipher.as_ref(), &self.collection, entity)?;
+            let from: i32 = row.try_get("schema_version").map_err(backend_error)?;
+            migration::upgrade_entity::<T>(&mut entity, from as u32)?;
+            let entity = seal_entity(self.cipher.as_ref(), &self.collection, entity)?;
+            // Another instance may have rewritten the record meanwhile
+            let written = sqlx::query(
+                "UPDATE borg_records SET entity = $3, schema_version = $5
+                 WHERE collection = $1 AND id = $2 AND schema_version = $4",
+            )
+            .bind(&self.collection)
+            .bind(&id)
+            .bind(entity)
+            .bind(from)
+            .bind(T::SCHEMA_VERSION as i32)
+            .execute(&self.pool)
+            .await
+            .map_err(backend_error)?;
+            upgraded += written.rows_affected() as usize;
+        }
+        Ok(upgraded)
+    }
+}
+
+/// A vector collection of the shared database
+#[derive(Clone)]
+pub struct PgVectorStore {
+    pool: PgPool,
+    collection: String,
+}
+
+#[async_trait::async_trait]
+impl VectorStore for PgVectorStore {
+    async fn upsert(&self, entries: Vec<VectorEntry>) -> DbResult<()> {
+        let mut tx = self.pool.begin().await.map_err(backend_error)?;
+        for entry in entries {
+            sqlx::query(
+                "INSERT INTO borg_vectors (collection, id, vector, metadata)
+                 VALUES ($1, $2, $3, $4)
+                 ON CONFLICT (collection, id)
+                 DO UPDATE SET vector = EXCLUDED.vector, metadata = EXCLUDED.metadata",
+            )
+            .bind(&self.collection)
+            .bind(&entry.id)
+            .bind(&entry.vector)
+            .bind(&entry.metadata)
+            .execute(&mut *tx)
+            .await
+            .map_err(backend_error)?;
+        }
+        tx.commit().await.map_err(backend_error)
+    }
+
+    async fn remove(&self, ids: &[String]) -> DbResult<()> {
+        sqlx::query("DELETE FROM borg_vectors WHERE collection = $1 AND id = ANY($2)")
+            .bind(&self.collection)
+            .bind(ids)
+            .execute(&self.pool)
+            .await
+            .map_err(backend_error)?;
+        Ok(())
+    }
+
+    async fn nearest(&self, query: &[f32], limit: usize) -> DbResult<Vec<VectorMatch>> {
+        let rows = sqlx::query(
+            "SELECT id, metadata, borg_cosine(vector, $2)::REAL AS score FROM borg_vectors
+             WHERE collection = $1
+             ORDER BY score DESC NULLS LAST, id
+             LIMIT $3",
+        )
+        .bind(&self.collection)
+        .bind(query)
+        .bind(limit as i64)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(backend_error)?;
+        rows.iter()
+            .map(|row| {
+                Ok(VectorMatch {
+                    id: row.try_get("id").map_err(backend_error)?,
+                    score: row
+                        .try_get::<Option<f32>, _>("score")
+                        .map_err(backend_error)?
+                        .unwrap_or(0.0),
+                    metadata: row.try_get("metadata").map_err(backend_error)?,
+                })
+            })
+            .collect()
+    }
+
+    async fn count(&self) -> DbResult<usize> {
+        let count: i64 =
+            sqlx::query_scalar("SELECT COUNT(*) FROM borg_vectors WHERE collection = $1")
+                .bind(&self.collection)
+                .fetch_one(&self.pool)
+                .await
+                .map_err(backend_error)?;
+        Ok(count as usize)
+    }
+
+    async fn entries(&self) -> DbResult<Vec<VectorEntry>> {
+        let rows = sqlx::query(
+            "SELECT id, vector, metadata FROM borg_vectors WHERE collection = $1 ORDER BY id",
+        )
+        .bind(&self.collection)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(backend_error)?;
+        rows.iter()
+            .map(|row| {
+                Ok(VectorEntry {
+                    id: row.try_get("id").map_err(backend_error)?,
+                    vector: row.try_get("vector").map_err(backend_error)?,
+                    metadata: row.try_get("metadata").map_err(backend_error)?,
+                })
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::optimization::OptimizationGoal;
+    use crate::database::Transaction;
+
+    #[test]
+    fn test_migrations_are_in_version_order() {
+        assert!(MIGRATIONS.windows(2).all(|w| w[0].version < w[1].version));
+        assert_eq!(MIGRATIONS[0].version, 1);
+    }
+
+    // Needs a scratch database in BORG_TEST_POSTGRES_URL
+    #[tokio::test]
+    #[ignore]
+    async fn test_records_round_trip_with_optimistic_locking() {
+        let config = PostgresConfig {
+            url: std::env::var("BORG_TEST_POSTGRES_URL").ok(),
+            ..PostgresConfig::default()
+        };
+        let store = PgStore::connect(&config).await.unwrap();
+        // Connecting again finds the schema up to date
+        store.migrate().await.unwrap();
+        let goals =
+            store.collection::<OptimizationGoal>(&format!("goals-{}", uuid::Uuid::new_v4()));
+
+        let goal = OptimizationGoal::new("goal-1", "Title", "Description");
+        let inserted = goals.insert(goal.clone()).await.unwrap();
+        assert_eq!(inserted.version, 1);
+        assert!(matches!(
+            goals.insert(goal.clone()).await,
+            Err(DatabaseError::DuplicateKey(_))
+        ));
+
+        let mut changed = goal.clone();
+        changed.title = "Changed".to_string();
+        let updated = goals.update(changed.clone(), Some(1)).await.unwrap();
+        assert_eq!(updated.version, 2);
+        assert!(matches!(
+            goals.update(changed.clone(), Some(1)).await,
+            Err(DatabaseError::VersionConflict {
+                expected: 1,
+                found: 2
+            })
+        ));
+        assert_eq!(
+            goals.get(&"goal-1".to_string()).await.unwrap().entity.title,
+            "Changed"
+        );
+        assert_eq!(goals.get_all().await.unwrap().len(), 1);
+        let mut other = OptimizationGoal::new("goal-2", "Other", "Description");
+        other.priority = 90;
+        goals.insert(other).await.unwrap();
+        let by_priority = goals
+            .query(
+                &Query::new()
+                    .eq("status", "NotStarted")
+                    .filter("created_at", FilterOp::Le, Utc::now().to_rfc3339())
+                    .sort_by("priority", SortOrder::Descending)
+                    .limit(1),
+            )
+            .await
+            .unwrap();
+        assert_eq!(by_priority.len(), 1);
+        assert_eq!(by_priority[0].entity.id, "goal-2");
+        goals.delete(&"goal-2".to_string()).await.unwrap();
+        assert_eq!(goals.migrate().await.unwrap(), 0);
+
+        // A failing write rolls back the whole transaction
+        let mut txn = Transaction::new();
+        txn.delete(&goals, &"goal-1".to_string());
+        txn.insert(&goals, goal.clone()).unwrap();
+        txn.update(&goals, changed.clone(), Some(2)).unwrap();
+        assert!(matches!(
+            txn.commit().await,
+            Err(DatabaseError::VersionConflict {
+                expected: 2,
+                found: 1
+            })
+        ));
+        assert_eq!(goals.get(&"goal-1".to_string()).await.unwrap().version, 2);
+
+        let mut txn = Transaction::new();
+        txn.update(&goals, changed.clone(), Some(2)).unwrap();
+        txn.commit().await.unwrap();
+        assert_eq!(goals.get(&"goal-1".to_string()).await.unwrap().version, 3);
+
+        goals.delete(&"goal-1".to_string()).await.unwrap();
+        assert!(matches!(
+            goals.get(&"goal-1".to_string()).await,
+            Err(DatabaseError::NotFound(_))
+        ));
+        assert!(matches!(
+            goals.update(changed, None).await,
+            Err(DatabaseError::NotFound(_))
+        ));
+    }
+
+    // Needs a scratch database in BORG_TEST_POSTGRES_URL
+    #[tokio::test]
+    #[ignore]
+    async fn test_vectors_rank_by_similarity() {
+        let config = PostgresConfig {
+            url: std::env::var("BORG_TEST_POSTGRES_URL").ok(),
+            ..PostgresConfig::default()
+        };
+        let store = PgStore::connect(&config).await.unwrap();
+        let vectors = store.vectors(&format!("vectors-{}", uuid::Uuid::new_v4()));
+        vectors
+            .upsert(vec![
+                VectorEntry::new("east", vec![1.0, 0.0], serde_json::json!({"n": 1})),
+                VectorEntry::new("north", vec![0.0, 1.0], serde_json::json!({"n": 2})),
+            ])
+            .await
+            .unwrap();
+        vectors
+            .upsert(vec![VectorEntry::new(
+                "north",
+                vec![0.6, 0.8],
+                serde_json::json!({"n": 3}),
+            )])
+            .await
+            .unwrap();
+
+        let nearest = vectors.nearest(&[0.0, 1.0], 2).await.unwrap();
+        assert_eq!(nearest[0].id, "north");
+        assert_eq!(nearest[0].metadata["n"], 3);
+        assert!((nearest[0].score - 0.8).abs() < 1e-6);
+        assert_eq!(nearest[1].id, "east");
+
+        vectors.remove(&["north".to_string()]).await.unwrap();
+        assert_eq!(vectors.count().await.unwrap(), 1);
+        assert_eq!(vectors.entries().await.unwrap()[0].vector, [1.0, 0.0]);
+        vectors.remove(&["east".to_string()]).await.unwrap();
+    }
+
+    // Needs a scratch database in BORG_TEST_POSTGRES_URL
+    #[tokio::test]
+    #[ignore]
+    async fn test_encrypted_collections_store_sealed_entities() {
+        let config = PostgresConfig {
+            url: std::env::var("BORG_TEST_POSTGRES_URL").ok(),
+            ..PostgresConfig::default()
+        };
+        let name = format!("goals-{}", uuid::Uuid::new_v4());
+        let plain = PgStore::connect(&config).await.unwrap();
+        let store = plain
+            .clone()
+            .with_cipher(Some(Cipher::from_base64(&Cipher::generate_key()).unwrap()));
+        plain
+            .collection::<OptimizationGoal>(&name)
+            .insert(OptimizationGoal::new("goal-1", "Plain", "Description"))
+            .await
+            .unwrap();
+
+        let goals = store.collection::<OptimizationGoal>(&name);
+        assert_eq!(goals.migrate().await.unwrap(), 1);
+        goals
+            .insert(OptimizationGoal::new("goal-2", "Sealed", "Description"))
+            .await
+            .unwrap();
+        let found = goals
+            .query(&Query::new().eq("title", "Sealed"))
+            .await
+            .unwrap();
+        assert_eq!(found.len(), 1);
+        assert!(matches!(
+            plain
+                .collection::<OptimizationGoal>(&name)
+                .get(&"goal-1".to_string())
+                .await,
+            Err(DatabaseError::EncryptionError(_))
+        ));
+        goals.clear().await.unwrap();
+    }
+}
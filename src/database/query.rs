@@ -0,0 +1,330 @@
+//! Filtering, sorting and paging stored entities.
+//!
+//! Queries name top-level fields of the stored entity JSON. The file
+//! backend answers equality filters on an entity's indexed fields from a
+//! secondary index and evaluates the rest against the candidates; the
+//! Postgres backend turns queries into SQL over indexed JSONB expressions.
+
+use std::cmp::Ordering;
+use std::collections::{HashMap, HashSet};
+use std::hash::Hash;
+
+use chrono::{DateTime, FixedOffset};
+use serde_json::Value;
+
+/// How a filter compares a field with its value
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FilterOp {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+impl FilterOp {
+    /// SQL operator of the comparison
+    pub(crate) fn sql(self) -> &'static str {
+        match self {
+            FilterOp::Eq => "=",
+            FilterOp::Ne => "<>",
+            FilterOp::Lt => "<",
+            FilterOp::Le => "<=",
+            FilterOp::Gt => ">",
+            FilterOp::Ge => ">=",
+        }
+    }
+}
+
+/// A condition on one field
+#[derive(Debug, Clone, PartialEq)]
+pub struct Filter {
+    /// Top-level field of the entity
+    pub field: String,
+
+    /// The comparison
+    pub op: FilterOp,
+
+    /// What the field is compared with
+    pub value: Value,
+}
+
+impl Filter {
+    /// Whether the field of `entity` passes the filter; a missing field
+    /// only passes `Ne`
+    pub fn matches(&self, entity: &Value) -> bool {
+        let Some(field) = entity.get(&self.field) else {
+            return self.op == FilterOp::Ne;
+        };
+        match compare(field, &self.value) {
+            Some(ordering) => match self.op {
+                FilterOp::Eq => ordering == Ordering::Equal,
+                FilterOp::Ne => ordering != Ordering::Equal,
+                FilterOp::Lt => ordering == Ordering::Less,
+                FilterOp::Le => ordering != Ordering::Greater,
+                FilterOp::Gt => ordering == Ordering::Greater,
+                FilterOp::Ge => ordering != Ordering::Less,
+            },
+            None => self.op == FilterOp::Ne,
+        }
+    }
+}
+
+/// Direction of a sort
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SortOrder {
+    Ascending,
+    Descending,
+}
+
+/// Which records to return, in what order
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Query {
+    /// Conditions every record meets
+    pub filters: Vec<Filter>,
+
+    /// Field and direction to sort by
+    pub sort: Option<(String, SortOrder)>,
+
+    /// Records skipped after sorting
+    pub offset: usize,
+
+    /// Most records returned
+    pub limit: Option<usize>,
+}
+
+impl Query {
+    /// Every record, in no particular order
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Only records whose `field` compares to `value` by `op`
+    pub fn filter(mut self, field: &str, op: FilterOp, value: impl Into<Value>) -> Self {
+        self.filters.push(Filter {
+            field: field.to_string(),
+            op,
+            value: value.into(),
+        });
+        self
+    }
+
+    /// Only records whose `field` equals `value`
+    pub fn eq(self, field: &str, value: impl Into<Value>) -> Self {
+        self.filter(field, FilterOp::Eq, value)
+    }
+
+    /// Sort by `field`
+    pub fn sort_by(mut self, field: &str, order: SortOrder) -> Self {
+        self.sort = Some((field.to_string(), order));
+        self
+    }
+
+    /// Skip the first `offset` records
+    pub fn offset(mut self, offset: usize) -> Self {
+        self.offset = offset;
+        self
+    }
+
+    /// Return at most `limit` records
+    pub fn limit(mut self, limit: usize) -> Self {
+        self.limit = Some(limit);
+        self
+    }
+
+    /// Whether `entity` passes every filter
+    pub fn matches(&self, entity: &Value) -> bool {
+        self.filters.iter().all(|filter| filter.matches(entity))
+    }
+
+    /// The items of `candidates` matching the query, sorted and paged;
+    /// `entity` gives the stored JSON of an item
+    pub fn apply<R>(&self, candidates: Vec<R>, entity: impl Fn(&R) -> Value) -> Vec<R> {
+        let mut matching: Vec<(Value, R)> = candidates
+            .into_iter()
+            .map(|item| (entity(&item), item))
+            .filter(|(json, _)| self.matches(json))
+            .collect();
+        if let Some((field, order)) = &self.sort {
+            matching.sort_by(|(a, _), (b, _)| {
+                // Records without the field come last either way
+                match (a.get(field), b.get(field)) {
+                    (Some(a), Some(b)) => {
+                        let ordering = compare(a, b).unwrap_or(Ordering::Equal);
+                        match order {
+                            SortOrder::Ascending => ordering,
+                            SortOrder::Descending => ordering.reverse(),
+                        }
+                    }
+                    (Some(_), None) => Ordering::Less,
+                    (None, Some(_)) => Ordering::Greater,
+                    (None, None) => Ordering::Equal,
+                }
+            });
+        }
+        matching
+            .into_iter()
+            .skip(self.offset)
+            .take(self.limit.unwrap_or(usize::MAX))
+            .map(|(_, item)| item)
+            .collect()
+    }
+}
+
+/// Order of two JSON values of the same kind; timestamps compare as
+/// times, `None` for values that do not compare
+pub fn compare(a: &Value, b: &Value) -> Option<Ordering> {
+    match (a, b) {
+        (Value::Number(a), Value::Number(b)) => a.as_f64()?.partial_cmp(&b.as_f64()?),
+        (Value::String(a), Value::String(b)) => match (timestamp(a), timestamp(b)) {
+            (Some(a), Some(b)) => Some(a.cmp(&b)),
+            _ => Some(a.cmp(b)),
+        },
+        (Value::Bool(a), Value::Bool(b)) => Some(a.cmp(b)),
+        (Value::Null, Value::Null) => Some(Ordering::Equal),
+        (a, b) if a == b => Some(Ordering::Equal),
+        _ => None,
+    }
+}
+
+fn timestamp(value: &str) -> Option<DateTime<FixedOffset>> {
+    DateTime::parse_from_rfc3339(value).ok()
+}
+
+/// Ids of records by the values of their indexed fields
+#[derive(Debug)]
+pub(crate) struct FieldIndex<Id> {
+    fields: &'static [&'static str],
+    entries: HashMap<(&'static str, String), HashSet<Id>>,
+}
+
+impl<Id: Eq + Hash + Clone> FieldIndex<Id> {
+    /// An empty index over `fields`
+    pub fn new(fields: &'static [&'static str]) -> Self {
+        Self {
+            fields,
+            entries: HashMap::new(),
+        }
+    }
+
+    fn keys<'a>(&self, entity: &'a Value) -> impl Iterator<Item = (&'static str, String)> + 'a {
+        let fields = self.fields;
+        fields
+            .iter()
+            .filter_map(move |field| Some((*field, entity.get(*field)?.to_string())))
+    }
+
+    /// Index `entity` under `id`
+    pub fn insert(&mut self, id: &Id, entity: &Value) {
+        for key in self.keys(entity).collect::<Vec<_>>() {
+            self.entries.entry(key).or_default().insert(id.clone());
+        }
+    }
+
+    /// Forget `entity`, indexed under `id`
+    pub fn remove(&mut self, id: &Id, entity: &Value) {
+        for key in self.keys(entity).collect::<Vec<_>>() {
+            if let Some(ids) = self.entries.get_mut(&key) {
+                ids.remove(id);
+                if ids.is_empty() {
+                    self.entries.remove(&key);
+                }
+            }
+        }
+    }
+
+    /// Ids of the only records that can match `query`, when one of its
+    /// equality filters is on an indexed field
+    pub fn candidates(&self, query: &Query) -> Option<HashSet<Id>> {
+        let mut candidates: Option<HashSet<Id>> = None;
+        for filter in &query.filters {
+            if filter.op != FilterOp::Eq {
+                continue;
+            }
+            let Some(field) = self.fields.iter().find(|f| **f == filter.field) else {
+                continue;
+            };
+            let ids = self
+                .entries
+                .get(&(*field, filter.value.to_string()))
+                .cloned()
+                .unwrap_or_default();
+            candidates = Some(match candidates {
+                Some(found) => found.intersection(&ids).cloned().collect(),
+                None => ids,
+            });
+        }
+        candidates
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_queries_filter_sort_and_page() {
+        let goals = vec![
+            json!({"id": "a", "status": "NotStarted", "priority": 10, "created_at": "2024-01-01T00:00:00.500Z"}),
+            json!({"id": "b", "status": "Completed", "priority": 50, "created_at": "2024-01-01T00:00:00Z"}),
+            json!({"id": "c", "status": "NotStarted", "priority": 90, "created_at": "2024-01-02T00:00:00Z"}),
+            json!({"id": "d", "status": "NotStarted", "created_at": "2024-01-03T00:00:00Z"}),
+        ];
+        let ids = |query: &Query| -> Vec<String> {
+            query
+                .apply(goals.clone(), |goal| goal.clone())
+                .iter()
+                .map(|goal| goal["id"].as_str().unwrap().to_string())
+                .collect()
+        };
+
+        let open = Query::new()
+            .eq("status", "NotStarted")
+            .sort_by("priority", SortOrder::Descending);
+        assert_eq!(ids(&open), ["c", "a", "d"]);
+        assert_eq!(ids(&open.clone().offset(1).limit(1)), ["a"]);
+        assert_eq!(
+            ids(&Query::new().filter("priority", FilterOp::Ge, 50)),
+            ["b", "c"]
+        );
+        assert_eq!(
+            ids(&Query::new().sort_by("created_at", SortOrder::Ascending)),
+            ["b", "a", "c", "d"]
+        );
+        assert_eq!(
+            ids(&Query::new().filter("priority", FilterOp::Ne, 10)),
+            ["b", "c", "d"]
+        );
+    }
+
+    #[test]
+    fn test_index_narrows_equality_filters() {
+        let mut index = FieldIndex::new(&["status", "category"]);
+        index.insert(
+            &"a",
+            &json!({"status": "NotStarted", "category": "Security"}),
+        );
+        index.insert(
+            &"b",
+            &json!({"status": "NotStarted", "category": "General"}),
+        );
+        index.insert(
+            &"c",
+            &json!({"status": "Completed", "category": "Security"}),
+        );
+
+        let query = Query::new()
+            .eq("status", "NotStarted")
+            .eq("category", "Security");
+        assert_eq!(index.candidates(&query), Some(HashSet::from(["a"])));
+        assert_eq!(index.candidates(&Query::new().eq("title", "x")), None);
+
+        index.remove(
+            &"a",
+            &json!({"status": "NotStarted", "category": "Security"}),
+        );
+        assert_eq!(index.candidates(&query), Some(HashSet::new()));
+    }
+}
@@ -0,0 +1,467 @@
+//! Atomic writes across collections.
+//!
+//! A transaction stages inserts, updates and deletes on collections of one
+//! database and applies all of them or none. File collections are locked
+//! in path order and the writes are checked against a copy of each; only
+//! when all of them apply are the new files written and moved into place.
+//! A commit marker listing the collections is written before the first one
+//! is moved, so a commit interrupted halfway is finished when one of its
+//! collections is opened next. Postgres collections run the writes in a
+//! native transaction.
+
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::database::postgres::{self, PgTarget};
+use crate::database::watch::Watcher;
+use crate::database::{DatabaseError, DatabaseInterface, DbResult, Entity};
+
+/// A staged write to one record
+#[derive(Debug, Clone)]
+pub(crate) struct StagedWrite {
+    /// Id of the record
+    pub id: String,
+
+    /// What happens to it
+    pub op: WriteOp,
+
+    /// Schema version of the entity written
+    pub schema_version: u32,
+}
+
+/// The kind of a staged write, with the entity as JSON
+#[derive(Debug, Clone)]
+pub(crate) enum WriteOp {
+    Insert {
+        entity: Value,
+    },
+    Update {
+        entity: Value,
+        expected_version: Option<u64>,
+    },
+    Delete,
+}
+
+/// How a collection takes part in transactions
+#[derive(Clone)]
+pub struct Participant(pub(crate) ParticipantKind, Option<Watcher>);
+
+impl Participant {
+    pub(crate) fn new(kind: ParticipantKind) -> Self {
+        Self(kind, None)
+    }
+
+    /// The same collection, publishing the committed writes to `watcher`
+    pub(crate) fn watched(self, watcher: Watcher) -> Self {
+        Self(self.0, Some(watcher))
+    }
+}
+
+#[derive(Clone)]
+pub(crate) enum ParticipantKind {
+    File(Arc<dyn FileParticipant>),
+    Postgres(PgTarget),
+}
+
+/// A collection file that can be locked for a transaction
+#[async_trait::async_trait]
+pub(crate) trait FileParticipant: Send + Sync {
+    /// The collection file; collections are locked in path order
+    fn path(&self) -> PathBuf;
+
+    /// Hold the collection against every other writer, in this process
+    /// and others, until the transaction is done
+    async fn lock(&self) -> DbResult<Box<dyn LockedFile>>;
+}
+
+/// A locked collection file with the writes of a transaction applied to a
+/// copy of its records
+pub(crate) trait LockedFile: Send {
+    /// Apply a write to the copy, or fail without changing it
+    fn apply(&mut self, write: &StagedWrite) -> DbResult<()>;
+
+    /// Write the copy next to the collection file
+    fn prepare(&mut self) -> DbResult<()>;
+
+    /// Move the prepared file into place and make the copy current
+    fn finish(self: Box<Self>) -> DbResult<()>;
+
+    /// Remove the prepared file, leaving the collection as it was
+    fn abort(self: Box<Self>);
+}
+
+/// The decision to commit a transaction on file collections
+///
+/// Written before the first prepared file is moved into place and removed
+/// after the last; while it exists, the prepared files of the collections
+/// it lists belong in their place.
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct CommitMarker {
+    /// The collection files of the transaction
+    pub collections: Vec<PathBuf>,
+}
+
+impl CommitMarker {
+    /// Where the marker of a transaction on `collections` is kept: next to
+    /// the first of them, which the transaction holds
+    fn path_for(collections: &[PathBuf]) -> Option<PathBuf> {
+        collections
+            .first()
+            .map(|first| first.with_extension("commit"))
+    }
+
+    /// Write the marker to `path` and flush it to disk, replacing the file
+    /// whole so no reader sees half a marker
+    fn write(&self, path: &Path) -> DbResult<()> {
+        let temp = path.with_extension("commit.tmp");
+        let file = File::create(&temp)?;
+        serde_json::to_writer(&file, self)?;
+        file.sync_all()?;
+        fs::rename(&temp, path)?;
+        Ok(())
+    }
+
+    /// The markers in `dir` of commits that did not finish
+    pub(crate) fn pending(dir: &Path) -> DbResult<Vec<(PathBuf, CommitMarker)>> {
+        let mut markers = Vec::new();
+        for entry in fs::read_dir(dir)? {
+            let path = entry?.path();
+            if path.extension().is_some_and(|ext| ext == "commit") {
+                let marker = serde_json::from_slice(&fs::read(&path)?)?;
+                markers.push((path, marker));
+            }
+        }
+        Ok(markers)
+    }
+}
+
+/// Writes to several collections, applied together by `commit`
+#[derive(Default)]
+pub struct Transaction {
+    writes: Vec<(Participant, StagedWrite)>,
+}
+
+impl Transaction {
+    /// An empty transaction
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Whether nothing was staged
+    pub fn is_empty(&self) -> bool {
+        self.writes.is_empty()
+    }
+
+    /// Stage the insert of a new entity
+    pub fn insert<T: Entity + for<'a> Deserialize<'a> + Unpin>(
+        &mut self,
+        collection: &dyn DatabaseInterface<T>,
+        entity: T,
+    ) -> DbResult<()> {
+        self.stage(
+            collection,
+            entity.id().as_ref(),
+            WriteOp::Insert {
+                entity: serde_json::to_value(&entity)?,
+            },
+        );
+        Ok(())
+    }
+
+    /// Stage the update of an existing entity, at `expected_version` if
+    /// given
+    pub fn update<T: Entity + for<'a> Deserialize<'a> + Unpin>(
+        &mut self,
+        collection: &dyn DatabaseInterface<T>,
+        entity: T,
+        expected_version: Option<u64>,
+    ) -> DbResult<()> {
+        self.stage(
+            collection,
+            entity.id().as_ref(),
+            WriteOp::Update {
+                entity: serde_json::to_value(&entity)?,
+                expected_version,
+            },
+        );
+        Ok(())
+    }
+
+    /// Stage the delete of a record
+    pub fn delete<T: Entity + for<'a> Deserialize<'a> + Unpin>(
+        &mut self,
+        collection: &dyn DatabaseInterface<T>,
+        id: &T::Id,
+    ) {
+        self.stage(collection, id.as_ref(), WriteOp::Delete);
+    }
+
+    fn stage<T: Entity + for<'a> Deserialize<'a> + Unpin>(
+        &mut self,
+        collection: &dyn DatabaseInterface<T>,
+        id: &str,
+        op: WriteOp,
+    ) {
+        self.writes.push((
+            collection.participant(),
+            StagedWrite {
+                id: id.to_string(),
+                op,
+                schema_version: T::SCHEMA_VERSION,
+            },
+        ));
+    }
+
+    /// Drop the staged writes
+    pub fn rollback(self) {}
+
+    /// Apply every staged write, or none if one of them fails
+    pub async fn commit(self) -> DbResult<()> {
+        let mut files: BTreeMap<PathBuf, (Arc<dyn FileParticipant>, Vec<StagedWrite>)> =
+            BTreeMap::new();
+        let mut rows = Vec::new();
+        let mut watched = Vec::new();
+        for (Participant(kind, watcher), write) in self.writes {
+            if let Some(watcher) = watcher {
+                watched.push((watcher, write.clone()));
+            }
+            match kind {
+                ParticipantKind::File(file) => files
+                    .entry(file.path())
+                    .or_insert_with(|| (file, Vec::new()))
+                    .1
+                    .push(write),
+                ParticipantKind::Postgres(target) => rows.push((target, write)),
+            }
+        }
+        if !files.is_empty() && !rows.is_empty() {
+            return Err(DatabaseError::InternalError(
+                "A transaction cannot span file and Postgres collections".to_string(),
+            ));
+        }
+        if !rows.is_empty() {
+            postgres::commit_writes(&rows).await?;
+        } else {
+            commit_files(files).await?;
+        }
+
+        for (watcher, write) in &watched {
+            watcher.notify_write(write);
+        }
+        Ok(())
+    }
+}
+
+/// Apply the writes to each collection file, or none if one of them fails
+///
+/// Once every new file is prepared the commit marker is written; from then
+/// on the commit stands, and a failure moving the files into place leaves
+/// the rest to be finished by the next `FileDb::open`.
+async fn commit_files(
+    files: BTreeMap<PathBuf, (Arc<dyn FileParticipant>, Vec<StagedWrite>)>,
+) -> DbResult<()> {
+    let paths: Vec<PathBuf> = files.keys().cloned().collect();
+
+    // Check every write before anything is written
+    let mut locked = Vec::new();
+    for (file, writes) in files.into_values() {
+        let mut collection = file.lock().await?;
+        for write in &writes {
+            collection.apply(write)?;
+        }
+        locked.push(collection);
+    }
+
+    let mut prepared = Vec::new();
+    let mut failure = None;
+    for mut collection in locked {
+        if failure.is_some() {
+            break;
+        }
+        match collection.prepare() {
+            Ok(()) => prepared.push(collection),
+            Err(e) => failure = Some(e),
+        }
+    }
+    let marker_path = CommitMarker::path_for(&paths);
+    if failure.is_none() {
+        if let Some(marker_path) = &marker_path {
+            let marker = CommitMarker { collections: paths };
+            if let Err(e) = marker.write(marker_path) {
+                let _ = fs::remove_file(marker_path.with_extension("commit.tmp"));
+                failure = Some(e);
+            }
+        }
+    }
+    if let Some(e) = failure {
+        prepared
+            .into_iter()
+            .for_each(|collection| collection.abort());
+        return Err(e);
+    }
+    for collection in prepared {
+        collection.finish()?;
+    }
+    if let Some(marker_path) = marker_path {
+        fs::remove_file(marker_path)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::optimization::{GoalStatus, OptimizationGoal};
+    use crate::database::FileDb;
+    use crate::version_control::rollback::MergeRecord;
+
+    #[tokio::test]
+    async fn test_file_transactions_apply_all_writes_or_none() {
+        let dir = tempfile::tempdir().unwrap();
+        let goals = FileDb::<OptimizationGoal>::new(dir.path(), "goals")
+            .await
+            .unwrap();
+        let merges = FileDb::<MergeRecord>::new(dir.path(), "merges")
+            .await
+            .unwrap();
+        goals
+            .insert(OptimizationGoal::new("goal-1", "Title", "Description"))
+            .await
+            .unwrap();
+
+        // A stale version fails the whole transaction
+        let mut done = goals.get(&"goal-1".to_string()).await.unwrap().entity;
+        done.update_status(GoalStatus::Completed);
+        let mut txn = Transaction::new();
+        txn.insert(&merges, MergeRecord::new("goal-1", "b", "master", "abc"))
+            .unwrap();
+        txn.update(&goals, done.clone(), Some(7)).unwrap();
+        assert!(matches!(
+            txn.commit().await,
+            Err(DatabaseError::VersionConflict {
+                expected: 7,
+                found: 1
+            })
+        ));
+        assert!(merges.get_all().await.unwrap().is_empty());
+        let reopened = FileDb::<MergeRecord>::new(dir.path(), "merges")
+            .await
+            .unwrap();
+        assert!(reopened.get_all().await.unwrap().is_empty());
+
+        let mut txn = Transaction::new();
+        txn.insert(&merges, MergeRecord::new("goal-1", "b", "master", "abc"))
+            .unwrap();
+        txn.update(&goals, done, Some(1)).unwrap();
+        txn.commit().await.unwrap();
+
+        let stored = goals.get(&"goal-1".to_string()).await.unwrap();
+        assert_eq!(stored.version, 2);
+        assert_eq!(stored.entity.status, GoalStatus::Completed);
+        let reopened = FileDb::<MergeRecord>::new(dir.path(), "merges")
+            .await
+            .unwrap();
+        assert_eq!(reopened.get_all().await.unwrap().len(), 1);
+
+        let mut txn = Transaction::new();
+        txn.delete(&goals, &"goal-1".to_string());
+        txn.delete(&goals, &"goal-1".to_string());
+        assert!(matches!(
+            txn.commit().await,
+            Err(DatabaseError::NotFound(_))
+        ));
+        assert!(goals.get(&"goal-1".to_string()).await.is_ok());
+    }
+
+    /// A collection whose prepared file is never moved into place, as if
+    /// the process died there
+    struct FailingFinish(Arc<dyn FileParticipant>);
+
+    struct FailingLocked(Box<dyn LockedFile>);
+
+    #[async_trait::async_trait]
+    impl FileParticipant for FailingFinish {
+        fn path(&self) -> PathBuf {
+            self.0.path()
+        }
+
+        async fn lock(&self) -> DbResult<Box<dyn LockedFile>> {
+            Ok(Box::new(FailingLocked(self.0.lock().await?)))
+        }
+    }
+
+    impl LockedFile for FailingLocked {
+        fn apply(&mut self, write: &StagedWrite) -> DbResult<()> {
+            self.0.apply(write)
+        }
+
+        fn prepare(&mut self) -> DbResult<()> {
+            self.0.prepare()
+        }
+
+        fn finish(self: Box<Self>) -> DbResult<()> {
+            Err(DatabaseError::IoError(std::io::Error::other(
+                "injected rename failure",
+            )))
+        }
+
+        fn abort(self: Box<Self>) {
+            self.0.abort()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_interrupted_commit_is_finished_on_open() {
+        let dir = tempfile::tempdir().unwrap();
+        let goals = FileDb::<OptimizationGoal>::new(dir.path(), "goals")
+            .await
+            .unwrap();
+        let merges = FileDb::<MergeRecord>::new(dir.path(), "merges")
+            .await
+            .unwrap();
+        goals
+            .insert(OptimizationGoal::new("goal-1", "Title", "Description"))
+            .await
+            .unwrap();
+        let mut done = goals.get(&"goal-1".to_string()).await.unwrap().entity;
+        done.update_status(GoalStatus::Completed);
+
+        // merges.json sorts after goals.json, so its rename is the second
+        let mut txn = Transaction::new();
+        txn.update(&goals, done, Some(1)).unwrap();
+        txn.insert(&merges, MergeRecord::new("goal-1", "b", "master", "abc"))
+            .unwrap();
+        let (participant, _) = txn.writes.last_mut().unwrap();
+        let ParticipantKind::File(file) = &participant.0 else {
+            unreachable!()
+        };
+        *participant =
+            Participant::new(ParticipantKind::File(Arc::new(FailingFinish(file.clone()))));
+        assert!(txn.commit().await.is_err());
+        let data_dir = std::fs::canonicalize(dir.path()).unwrap();
+        assert!(data_dir.join("goals.commit").exists());
+        assert!(data_dir.join("merges.txn").exists());
+
+        let reopened = FileDb::<MergeRecord>::new(dir.path(), "merges")
+            .await
+            .unwrap();
+        assert_eq!(reopened.get_all().await.unwrap().len(), 1);
+        let stored = goals.get(&"goal-1".to_string()).await.unwrap();
+        assert_eq!(stored.entity.status, GoalStatus::Completed);
+        assert!(!data_dir.join("goals.commit").exists());
+        assert!(!data_dir.join("merges.txn").exists());
+
+        // A prepared file no commit claims is left over and removed
+        std::fs::write(data_dir.join("goals.txn"), "[]").unwrap();
+        let reopened = FileDb::<OptimizationGoal>::new(dir.path(), "goals")
+            .await
+            .unwrap();
+        assert_eq!(reopened.get_all().await.unwrap().len(), 1);
+        assert!(!data_dir.join("goals.txn").exists());
+    }
+}
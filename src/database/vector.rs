@@ -0,0 +1,252 @@
+//! Vector collections for similarity search over embeddings.
+//!
+//! A vector store keeps embeddings by id, each with a little JSON metadata,
+//! and answers which stored vectors are closest to a query by cosine
+//! similarity. The file backend keeps the entries in a collection file and
+//! an HNSW graph over them in memory, rebuilt on open; small stores are
+//! scanned exactly instead. The Postgres backend keeps them in the
+//! `borg_vectors` table and ranks them in SQL.
+
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashSet;
+use std::path::Path;
+use tokio::sync::RwLock;
+
+use crate::core::encryption::Cipher;
+use crate::database::hnsw::Hnsw;
+use crate::database::{DatabaseError, DbResult, FileDb, Transaction};
+
+/// Stores of at most this many vectors are searched exactly
+const FLAT_SEARCH_LIMIT: usize = 1024;
+
+/// An embedding with what it is an embedding of
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct VectorEntry {
+    /// Unique identifier
+    pub id: String,
+
+    /// The embedding
+    pub vector: Vec<f32>,
+
+    /// What the caller needs to make sense of a match
+    #[serde(default)]
+    pub metadata: Value,
+}
+
+impl VectorEntry {
+    /// An entry of `vector` under `id`
+    pub fn new(id: impl Into<String>, vector: Vec<f32>, metadata: Value) -> Self {
+        Self {
+            id: id.into(),
+            vector,
+            metadata,
+        }
+    }
+}
+
+/// A stored vector close to a query
+#[derive(Debug, Clone, PartialEq)]
+pub struct VectorMatch {
+    /// Id of the entry
+    pub id: String,
+
+    /// Cosine similarity to the query
+    pub score: f32,
+
+    /// Metadata of the entry
+    pub metadata: Value,
+}
+
+/// Cosine similarity of two vectors, 0 when either is all zeros
+pub fn cosine_similarity(a: &[f32], b: &[f32]) -> f32 {
+    let dot: f32 = a.iter().zip(b).map(|(x, y)| x * y).sum();
+    let norm = |v: &[f32]| v.iter().map(|x| x * x).sum::<f32>().sqrt();
+    let denom = norm(a) * norm(b);
+    if denom == 0.0 {
+        0.0
+    } else {
+        dot / denom
+    }
+}
+
+/// A collection of vectors searchable by similarity
+#[async_trait]
+pub trait VectorStore: Send + Sync {
+    /// Store `entries`, replacing the stored ones with the same ids
+    async fn upsert(&self, entries: Vec<VectorEntry>) -> DbResult<()>;
+
+    /// Remove the entries with `ids`, skipping ids that are not stored
+    async fn remove(&self, ids: &[String]) -> DbResult<()>;
+
+    /// The `limit` entries most similar to `query`, most similar first
+    async fn nearest(&self, query: &[f32], limit: usize) -> DbResult<Vec<VectorMatch>>;
+
+    /// Number of stored entries
+    async fn count(&self) -> DbResult<usize>;
+
+    /// Every stored entry, by id
+    async fn entries(&self) -> DbResult<Vec<VectorEntry>>;
+}
+
+/// Vectors kept in a collection file, searched through an in-memory graph
+pub struct FileVectorStore {
+    db: FileDb<VectorEntry>,
+
+    /// The graph over the stored vectors; writers hold it for the whole
+    /// write so the file and the graph change together
+    index: RwLock<Hnsw>,
+}
+
+impl FileVectorStore {
+    /// Open the vector collection `name` in `data_dir`
+    pub async fn new(data_dir: impl AsRef<Path>, name: &str) -> DbResult<Self> {
+        Self::open(data_dir, name, None).await
+    }
+
+    /// Open the vector collection `name` in `data_dir`, encrypted with
+    /// `cipher` if given
+    pub async fn open(
+        data_dir: impl AsRef<Path>,
+        name: &str,
+        cipher: Option<Cipher>,
+    ) -> DbResult<Self> {
+        let db = FileDb::<VectorEntry>::open(data_dir, name, cipher).await?;
+        let mut index = Hnsw::default();
+        for record in db.get_all().await? {
+            index.insert(&record.entity.id, &record.entity.vector);
+        }
+        Ok(Self {
+            db,
+            index: RwLock::new(index),
+        })
+    }
+}
+
+#[async_trait]
+impl VectorStore for FileVectorStore {
+    async fn upsert(&self, entries: Vec<VectorEntry>) -> DbResult<()> {
+        let mut index = self.index.write().await;
+        // The last entry for an id wins
+        let mut seen = HashSet::new();
+        let entries: Vec<VectorEntry> = entries
+            .into_iter()
+            .rev()
+            .filter(|entry| seen.insert(entry.id.clone()))
+            .collect();
+
+        let mut txn = Transaction::new();
+        for entry in &entries {
+            match self.db.get(&entry.id).await {
+                Ok(_) => txn.update(&self.db, entry.clone(), None)?,
+                Err(DatabaseError::NotFound(_)) => txn.insert(&self.db, entry.clone())?,
+                Err(e) => return Err(e),
+            }
+        }
+        txn.commit().await?;
+        for entry in &entries {
+            index.insert(&entry.id, &entry.vector);
+        }
+        Ok(())
+    }
+
+    async fn remove(&self, ids: &[String]) -> DbResult<()> {
+        let mut index = self.index.write().await;
+        let mut txn = Transaction::new();
+        let mut removed = HashSet::new();
+        for id in ids {
+            if removed.insert(id) && index.remove(id) {
+                txn.delete(&self.db, id);
+            }
+        }
+        txn.commit().await
+    }
+
+    async fn nearest(&self, query: &[f32], limit: usize) -> DbResult<Vec<VectorMatch>> {
+        let found = {
+            let index = self.index.read().await;
+            if index.len() <= FLAT_SEARCH_LIMIT {
+                index.exact(query, limit)
+            } else {
+                index.search(query, limit)
+            }
+        };
+        let mut matches = Vec::with_capacity(found.len());
+        for (id, score) in found {
+            match self.db.get(&id).await {
+                Ok(record) => matches.push(VectorMatch {
+                    id,
+                    score,
+                    metadata: record.entity.metadata,
+                }),
+                // Removed since the search
+                Err(DatabaseError::NotFound(_)) => {}
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(matches)
+    }
+
+    async fn count(&self) -> DbResult<usize> {
+        Ok(self.index.read().await.len())
+    }
+
+    async fn entries(&self) -> DbResult<Vec<VectorEntry>> {
+        let mut entries: Vec<VectorEntry> = self
+            .db
+            .get_all()
+            .await?
+            .into_iter()
+            .map(|record| record.entity)
+            .collect();
+        entries.sort_by(|a, b| a.id.cmp(&b.id));
+        Ok(entries)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[tokio::test]
+    async fn test_file_vectors_persist_and_rank_by_similarity() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = FileVectorStore::new(dir.path(), "vectors").await.unwrap();
+        store
+            .upsert(vec![
+                VectorEntry::new("east", vec![1.0, 0.0], json!({"name": "east"})),
+                VectorEntry::new("north", vec![0.0, 1.0], json!({"name": "north"})),
+                VectorEntry::new("west", vec![-1.0, 0.0], json!({"name": "west"})),
+            ])
+            .await
+            .unwrap();
+        // Upserting an id again replaces its vector
+        store
+            .upsert(vec![VectorEntry::new(
+                "north",
+                vec![0.6, 0.8],
+                json!({"name": "north-east"}),
+            )])
+            .await
+            .unwrap();
+
+        let nearest = store.nearest(&[2.0, 0.1], 2).await.unwrap();
+        let ids: Vec<&str> = nearest.iter().map(|m| m.id.as_str()).collect();
+        assert_eq!(ids, ["east", "north"]);
+        assert_eq!(nearest[1].metadata["name"], "north-east");
+        assert!((nearest[0].score - cosine_similarity(&[2.0, 0.1], &[1.0, 0.0])).abs() < 1e-6);
+
+        store
+            .remove(&["east".to_string(), "missing".to_string()])
+            .await
+            .unwrap();
+        let reopened = FileVectorStore::new(dir.path(), "vectors").await.unwrap();
+        assert_eq!(reopened.count().await.unwrap(), 2);
+        assert_eq!(
+            reopened.nearest(&[1.0, 0.0], 1).await.unwrap()[0].id,
+            "north"
+        );
+    }
+}
@@ -0,0 +1,212 @@
+//! Notifications of changes to collections.
+//!
+//! The collections of a `DatabaseManager` publish every insert, update and
+//! delete that succeeds, whether written on its own or in a transaction,
+//! on a broadcast channel per collection. Subscribers get the changes made
+//! in this process from the time they subscribe; one that falls more than
+//! `CHANGE_BUFFER` changes behind is told how many it missed and should
+//! reload what it shows.
+
+use std::sync::Arc;
+
+use serde::Deserialize;
+use serde_json::Value;
+use tokio::sync::broadcast;
+
+use crate::database::query::Query;
+use crate::database::transaction::{Participant, StagedWrite, WriteOp};
+use crate::database::{DatabaseInterface, DbResult, Entity, Record};
+
+/// Changes kept for subscribers that have not received them yet
+pub const CHANGE_BUFFER: usize = 1024;
+
+/// What happened to a record
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChangeKind {
+    Created,
+    Updated,
+    Deleted,
+}
+
+/// A change to one record of a collection
+#[derive(Debug, Clone, PartialEq)]
+pub struct Change {
+    /// The collection changed
+    pub collection: String,
+
+    /// Id of the record
+    pub id: String,
+
+    /// What happened to it
+    pub kind: ChangeKind,
+
+    /// The entity written, as JSON; `None` for deletes
+    pub entity: Option<Value>,
+}
+
+/// Publishes the changes to one collection
+#[derive(Clone)]
+pub(crate) struct Watcher {
+    collection: String,
+    sender: broadcast::Sender<Change>,
+}
+
+impl Watcher {
+    /// A watcher of `collection` without subscribers yet
+    pub fn new(collection: &str) -> Self {
+        Self {
+            collection: collection.to_string(),
+            sender: broadcast::channel(CHANGE_BUFFER).0,
+        }
+    }
+
+    /// A new subscription to the changes
+    pub fn subscribe(&self) -> broadcast::Receiver<Change> {
+        self.sender.subscribe()
+    }
+
+    fn notify(&self, id: String, kind: ChangeKind, entity: Option<Value>) {
+        // Sending only fails when nobody is subscribed
+        let _ = self.sender.send(Change {
+            collection: self.collection.clone(),
+            id,
+            kind,
+            entity,
+        });
+    }
+
+    /// Publish a write committed in a transaction
+    pub fn notify_write(&self, write: &StagedWrite) {
+        let (kind, entity) = match &write.op {
+            WriteOp::Insert { entity } => (ChangeKind::Created, Some(entity.clone())),
+            WriteOp::Update { entity, .. } => (ChangeKind::Updated, Some(entity.clone())),
+            WriteOp::Delete => (ChangeKind::Deleted, None),
+        };
+        self.notify(write.id.clone(), kind, entity);
+    }
+
+    fn notify_record<T: Entity + for<'a> Deserialize<'a> + Unpin>(
+        &self,
+        record: &Record<T>,
+        kind: ChangeKind,
+    ) {
+        self.notify(
+            record.id().as_ref().to_string(),
+            kind,
+            serde_json::to_value(&record.entity).ok(),
+        );
+    }
+}
+
+/// A collection that publishes its changes
+pub(crate) struct Watched<T: Entity + for<'a> Deserialize<'a> + Unpin> {
+    inner: Arc<dyn DatabaseInterface<T>>,
+    watcher: Watcher,
+}
+
+impl<T: Entity + for<'a> Deserialize<'a> + Unpin> Watched<T> {
+    /// Publish the changes to `inner` through `watcher`
+    pub fn new(inner: Arc<dyn DatabaseInterface<T>>, watcher: Watcher) -> Self {
+        Self { inner, watcher }
+    }
+}
+
+#[async_trait::async_trait]
+impl<T: Entity + for<'a> Deserialize<'a> + Unpin> DatabaseInterface<T> for Watched<T> {
+    async fn get(&self, id: &T::Id) -> DbResult<Record<T>> {
+        self.inner.get(id).await
+    }
+
+    async fn get_all(&self) -> DbResult<Vec<Record<T>>> {
+        self.inner.get_all().await
+    }
+
+    async fn query(&self, query: &Query) -> DbResult<Vec<Record<T>>> {
+        self.inner.query(query).await
+    }
+
+    async fn insert(&self, entity: T) -> DbResult<Record<T>> {
+        let record = self.inner.insert(entity).await?;
+        self.watcher.notify_record(&record, ChangeKind::Created);
+        Ok(record)
+    }
+
+    async fn update(&self, entity: T, expected_version: Option<u64>) -> DbResult<Record<T>> {
+        let record = self.inner.update(entity, expected_version).await?;
+        self.watcher.notify_record(&record, ChangeKind::Updated);
+        Ok(record)
+    }
+
+    async fn delete(&self, id: &T::Id) -> DbResult<()> {
+        self.inner.delete(id).await?;
+        self.watcher
+            .notify(id.as_ref().to_string(), ChangeKind::Deleted, None);
+        Ok(())
+    }
+
+    async fn clear(&self) -> DbResult<()> {
+        let cleared = self.inner.get_all().await?;
+        self.inner.clear().await?;
+        for record in cleared {
+            self.watcher
+                .notify(record.id().as_ref().to_string(), ChangeKind::Deleted, None);
+        }
+        Ok(())
+    }
+
+    async fn migrate(&self) -> DbResult<usize> {
+        self.inner.migrate().await
+    }
+
+    fn participant(&self) -> Participant {
+        self.inner.participant().watched(self.watcher.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::optimization::{GoalStatus, OptimizationGoal};
+    use crate::database::{FileDb, Transaction};
+
+    #[tokio::test]
+    async fn test_writes_and_committed_transactions_are_published() {
+        let dir = tempfile::tempdir().unwrap();
+        let watcher = Watcher::new("goals");
+        let goals = Watched::new(
+            Arc::new(
+                FileDb::<OptimizationGoal>::new(dir.path(), "goals")
+                    .await
+                    .unwrap(),
+            ),
+            watcher.clone(),
+        );
+        let mut changes = watcher.subscribe();
+
+        let mut goal = OptimizationGoal::new("goal-1", "Title", "Description");
+        goals.insert(goal.clone()).await.unwrap();
+        goal.update_status(GoalStatus::InProgress);
+        goals.update(goal.clone(), None).await.unwrap();
+        // A failed transaction publishes nothing
+        let mut txn = Transaction::new();
+        txn.update(&goals, goal.clone(), Some(9)).unwrap();
+        assert!(txn.commit().await.is_err());
+        let mut txn = Transaction::new();
+        txn.delete(&goals, &goal.id);
+        txn.commit().await.unwrap();
+
+        let created = changes.recv().await.unwrap();
+        assert_eq!(created.kind, ChangeKind::Created);
+        assert_eq!(created.collection, "goals");
+        assert_eq!(created.entity.unwrap()["status"], "NotStarted");
+        let updated = changes.recv().await.unwrap();
+        assert_eq!(updated.kind, ChangeKind::Updated);
+        assert_eq!(updated.entity.unwrap()["status"], "InProgress");
+        let deleted = changes.recv().await.unwrap();
+        assert_eq!(
+            (deleted.id.as_str(), deleted.kind, deleted.entity),
+            ("goal-1", ChangeKind::Deleted, None)
+        );
+        assert!(changes.try_recv().is_err());
+    }
+}
@@ -1,6 +1,7 @@
 pub mod code_generation;
 pub mod core;
 pub mod database;
+pub mod mcp;
 pub mod providers;
 pub mod resource_monitor;
 pub mod swarm;
@@ -1,10 +1,25 @@
 use anyhow::{Context, Result};
-use clap::{Parser, Subcommand};
+use clap::{Args, Parser, Subcommand, ValueEnum};
 use log::{info, LevelFilter};
 use std::path::Path;
+use std::sync::Arc;
 
+use borg::code_generation::permissions::PermissionPolicy;
+use borg::code_generation::tool_audit::ToolAuditLog;
 use borg::core::agent::Agent;
 use borg::core::config::Config;
+use borg::core::encryption::{Cipher, DEFAULT_KEY_ENV};
+use borg::core::goals::{self, GoalEdit, Priority};
+use borg::core::optimization::{GoalStatus, OptimizationCategory, OptimizationGoal};
+use borg::core::retention::Compactor;
+use borg::core::secrets::KeychainSecretProvider;
+use borg::core::services::Services;
+use borg::database::{Archive, DatabaseManager};
+use borg::mcp::server::{workspace_registry, McpServer, SseOptions, DEFAULT_SSE_ADDR};
+use borg::providers::health::{check_models, CheckStatus};
+use borg::version_control::bisect::{self, Bisector};
+use borg::version_control::git_implementation::GitImplementation;
+use borg::version_control::rollback;
 
 #[derive(Parser)]
 #[clap(author, version, about = "Borg - Autonomous Self-Improving AI Agent")]
@@ -28,6 +43,231 @@ enum Commands {
 
     /// Display information about the agent
     Info,
+
+    /// Inspect the configured LLM providers
+    Providers {
+        #[command(subcommand)]
+        command: ProvidersCommand,
+    },
+
+    /// Serve the workspace tools over the Model Context Protocol (stdio by default)
+    McpServe {
+        /// Serve over HTTP+SSE instead of stdio, on 127.0.0.1:8765 or the
+        /// address given; clients must send the bearer token from
+        /// `mcp.serve.token_source`, or the one logged at startup
+        #[clap(long, num_args = 0..=1, default_missing_value = DEFAULT_SSE_ADDR)]
+        sse: Option<String>,
+
+        /// Only expose tools that read the workspace
+        #[clap(long)]
+        read_only: bool,
+    },
+
+    /// Show which tools take the most time and fail most often
+    ToolStats {
+        /// Only count calls from the last N days
+        #[clap(long)]
+        days: Option<i64>,
+    },
+
+    /// Revert the merge of a goal on the mainline and reopen the goal
+    Rollback {
+        /// The goal whose merge is reverted
+        goal_id: String,
+
+        /// Why the merge is rolled back, kept with the reopened goal
+        #[clap(long, default_value = "rolled back by hand")]
+        reason: String,
+    },
+
+    /// Queue and manage optimization goals
+    Goal {
+        #[command(subcommand)]
+        command: GoalCommand,
+    },
+
+    /// Manage the agent's stored state
+    Db {
+        #[command(subcommand)]
+        command: DbCommand,
+    },
+
+    /// Find the commit that broke a check and add a goal to fix it
+    Bisect {
+        /// A commit where the check passes
+        good: String,
+
+        /// A commit where the check fails
+        #[clap(long, default_value = "HEAD")]
+        bad: String,
+
+        /// The check to run at each step (exit 0 good, 125 skip, else bad)
+        #[clap(long)]
+        check: String,
+
+        /// Only report the commit, without adding a fix goal
+        #[clap(long)]
+        no_goal: bool,
+    },
+}
+
+#[derive(Subcommand)]
+enum ProvidersCommand {
+    /// Verify API keys, reachability and that configured model IDs exist
+    Check,
+}
+
+#[derive(Subcommand)]
+enum GoalCommand {
+    /// Queue a new goal
+    Add {
+        /// Short title of the goal
+        title: String,
+
+        /// What should be improved; defaults to the title
+        #[clap(short, long)]
+        description: Option<String>,
+
+        #[command(flatten)]
+        options: GoalOptions,
+    },
+
+    /// List goals, the highest priority first
+    List {
+        /// Only list goals with this status (e.g. not-started, completed)
+        #[clap(long)]
+        status: Option<GoalStatus>,
+
+        /// Also list completed, failed and abandoned goals
+        #[clap(long)]
+        all: bool,
+    },
+
+    /// Show everything about a goal
+    Show {
+        /// The goal to show
+        id: String,
+    },
+
+    /// Change a goal
+    Edit {
+        /// The goal to change
+        id: String,
+
+        /// New title
+        #[clap(long)]
+        title: Option<String>,
+
+        /// New description
+        #[clap(short, long)]
+        description: Option<String>,
+
+        #[command(flatten)]
+        options: GoalOptions,
+
+        /// Stop tagging the goal with this file (repeatable)
+        #[clap(long = "remove-file", value_name = "PATH")]
+        remove_files: Vec<String>,
+
+        /// Remove this success metric (repeatable)
+        #[clap(long = "remove-metric", value_name = "METRIC")]
+        remove_metrics: Vec<String>,
+    },
+
+    /// Abandon an open goal
+    Cancel {
+        /// The goal to cancel
+        id: String,
+
+        /// Why the goal is cancelled, kept with the goal
+        #[clap(long, default_value = "no longer wanted")]
+        reason: String,
+    },
+}
+
+/// Fields set by both `goal add` and `goal edit`
+#[derive(Args)]
+struct GoalOptions {
+    /// Category (e.g. performance, security, test-coverage)
+    #[clap(long)]
+    category: Option<OptimizationCategory>,
+
+    /// Priority from 1 to 100, or low, medium, high or critical
+    #[clap(short, long)]
+    priority: Option<Priority>,
+
+    /// A file the goal touches (repeatable)
+    #[clap(long = "file", value_name = "PATH")]
+    files: Vec<String>,
+
+    /// A success metric the change must meet (repeatable)
+    #[clap(long = "metric", value_name = "METRIC")]
+    metrics: Vec<String>,
+}
+
+impl GoalOptions {
+    fn into_edit(self) -> GoalEdit {
+        GoalEdit {
+            category: self.category,
+            priority: self.priority,
+            add_files: self.files,
+            add_metrics: self.metrics,
+            ..GoalEdit::default()
+        }
+    }
+}
+
+#[derive(Subcommand)]
+enum DbCommand {
+    /// Upgrade records stored by older versions to the current schema, and
+    /// encrypt plain records when encryption is enabled
+    Migrate,
+
+    /// Generate a key for `encryption`
+    Keygen {
+        /// Store the key in the OS keychain instead of printing it
+        #[clap(long)]
+        keychain: bool,
+    },
+
+    /// Print an encrypted LLM log in plain text
+    DecryptLog {
+        /// The log file
+        path: std::path::PathBuf,
+    },
+
+    /// Write every collection to an archive with checksums, encrypted when
+    /// encryption is enabled
+    Backup {
+        /// The archive to write
+        path: std::path::PathBuf,
+    },
+
+    /// Replace the stored state with the contents of an archive
+    Restore {
+        /// An archive written by `db backup` or `db export`
+        path: std::path::PathBuf,
+    },
+
+    /// Remove logs and finished goals past their `retention` now
+    Vacuum,
+
+    /// Write every collection in plain text, for other tools
+    Export {
+        /// Output format
+        #[clap(long, value_enum, default_value = "jsonl")]
+        format: ExportFormat,
+
+        /// Write to this file instead of standard output
+        #[clap(long)]
+        output: Option<std::path::PathBuf>,
+    },
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum ExportFormat {
+    /// A manifest line with checksums, then one line per record
+    Jsonl,
 }
 
 fn main() -> Result<()> {
@@ -42,10 +282,23 @@ fn main() -> Result<()> {
     };
     env_logger::Builder::new().filter_level(log_level).init();
 
+    // A new key is needed before the config that uses it can be loaded
+    if let Some(Commands::Db {
+        command: DbCommand::Keygen { keychain },
+    }) = &cli.command
+    {
+        return generate_encryption_key(*keychain);
+    }
+
     // Load configuration (YAML format)
     let config_path = determine_config_path(&cli.config)?;
     info!("Using configuration file: {}", config_path.display());
     let config = Config::from_file(&config_path)?;
+    Arc::new(Services {
+        cipher: Cipher::from_config(&config.encryption)?,
+        ..Default::default()
+    })
+    .install();
 
     // Ensure logs directory exists
     if config.logging.enabled {
@@ -55,15 +308,70 @@ fn main() -> Result<()> {
         info!("Ensured log directory exists: {:?}", log_dir);
     }
 
-    // Initialize and run the agent
-    tokio::runtime::Builder::new_multi_thread()
+    let runtime = tokio::runtime::Builder::new_multi_thread()
         .enable_all()
-        .build()?
-        .block_on(async {
-            let agent = Agent::new(config).await?;
-            agent.initialize().await?;
-            handle_commands(cli.command, agent).await
-        })
+        .build()?;
+
+    // Provider checks run without starting the agent
+    if let Some(Commands::Providers {
+        command: ProvidersCommand::Check,
+    }) = &cli.command
+    {
+        return runtime.block_on(check_providers(&config));
+    }
+
+    // The MCP server only needs the workspace, not the agent
+    if let Some(Commands::McpServe { sse, read_only }) = &cli.command {
+        return runtime.block_on(serve_mcp(&config, sse.as_deref(), *read_only));
+    }
+
+    // Tool metrics only need the database
+    if let Some(Commands::ToolStats { days }) = &cli.command {
+        return runtime.block_on(print_tool_stats(&config, *days));
+    }
+
+    // Rolling back only needs the database and the repository
+    if let Some(Commands::Rollback { goal_id, reason }) = &cli.command {
+        return runtime.block_on(roll_back_goal(&config, goal_id, reason));
+    }
+
+    // Goals are managed in the database alone
+    if let Some(Commands::Goal { command }) = cli.command {
+        return runtime.block_on(manage_goals(&config, command));
+    }
+
+    // Database maintenance only needs the database
+    if let Some(Commands::Db { command }) = &cli.command {
+        return match command {
+            DbCommand::Migrate => runtime.block_on(migrate_database(&config)),
+            DbCommand::Keygen { .. } => unreachable!("handled before loading the config"),
+            DbCommand::DecryptLog { path } => decrypt_log(&config, path),
+            DbCommand::Vacuum => runtime.block_on(vacuum_database(&config)),
+            DbCommand::Backup { path } => runtime.block_on(back_up_database(&config, path)),
+            DbCommand::Restore { path } => runtime.block_on(restore_database(&config, path)),
+            DbCommand::Export { format, output } => {
+                runtime.block_on(export_database(&config, *format, output.as_deref()))
+            }
+        };
+    }
+
+    // Bisecting only needs the repository, and the database for the goal
+    if let Some(Commands::Bisect {
+        good,
+        bad,
+        check,
+        no_goal,
+    }) = &cli.command
+    {
+        return runtime.block_on(bisect_regression(&config, good, bad, check, *no_goal));
+    }
+
+    // Initialize and run the agent
+    runtime.block_on(async {
+        let agent = Agent::new(config).await?;
+        agent.initialize().await?;
+        handle_commands(cli.command, agent).await
+    })
 }
 
 /// Determine which configuration file to use
@@ -85,6 +393,425 @@ fn print_banner() {
     println!("====================================================\n");
 }
 
+/// Check every configured model and fail if any cannot be used
+async fn check_providers(config: &Config) -> Result<()> {
+    let checks = check_models(config).await;
+    for check in &checks {
+        let outcome = match &check.status {
+            CheckStatus::Ok => "ok".to_string(),
+            CheckStatus::ModelNotFound => "model not offered by provider".to_string(),
+            CheckStatus::Unverified => "reachable; model list not available".to_string(),
+            CheckStatus::Failed(reason) => reason.clone(),
+        };
+        println!(
+            "{} {} ({}/{}) in {} ms: {}",
+            if check.passed() { "✅" } else { "❌" },
+            check.name,
+            check.provider,
+            check.model,
+            check.latency_ms,
+            outcome
+        );
+    }
+
+    let failed = checks.iter().filter(|c| !c.passed()).count();
+    if failed > 0 {
+        anyhow::bail!(
+            "{} of {} configured model(s) failed the provider check",
+            failed,
+            checks.len()
+        );
+    }
+    println!("All {} configured model(s) are ready", checks.len());
+    Ok(())
+}
+
+/// The agent's database in the working directory
+async fn open_database(config: &Config) -> Result<DatabaseManager> {
+    let data_dir = Path::new(&config.agent.working_dir).join("data");
+    DatabaseManager::new(&data_dir, config)
+        .await
+        .context("Failed to open database")
+}
+
+/// Print per-tool call metrics from the audit log, the most time-consuming
+/// tool first
+async fn print_tool_stats(config: &Config, days: Option<i64>) -> Result<()> {
+    let database = open_database(config).await?;
+    let since = days.map(|d| chrono::Utc::now() - chrono::Duration::days(d));
+    let metrics = ToolAuditLog::new(database.tool_invocations())
+        .metrics(since)
+        .await?;
+    if metrics.is_empty() {
+        println!("No tool calls recorded");
+        return Ok(());
+    }
+    println!(
+        "{:<20} {:>7} {:>8} {:>10} {:>9} {:>9} {:>11}",
+        "Tool", "Calls", "Failed", "Total ms", "Avg ms", "Max ms", "Output B"
+    );
+    for m in &metrics {
+        println!(
+            "{:<20} {:>7} {:>7.0}% {:>10} {:>9} {:>9} {:>11}",
+            m.tool,
+            m.calls,
+            m.failure_rate() * 100.0,
+            m.total_duration_ms,
+            m.average_duration_ms(),
+            m.max_duration_ms,
+            m.output_bytes
+        );
+    }
+    Ok(())
+}
+
+/// Revert the recorded merge of a goal and reopen the stored goal
+async fn roll_back_goal(config: &Config, goal_id: &str, reason: &str) -> Result<()> {
+    let database = open_database(config).await?;
+    let git = GitImplementation::from_config(&config.agent.working_dir, &config.git)?;
+    let (record, reopened) = rollback::roll_back_goal(&database, &git, goal_id, reason).await?;
+    let revert = record
+        .reverted
+        .as_ref()
+        .map(|revert| revert.commit.as_str())
+        .unwrap_or_default();
+    println!(
+        "Reverted merge {} of {} in {}",
+        record.merge_commit, record.branch, revert
+    );
+    if reopened {
+        println!("Reopened goal {}", goal_id);
+    }
+    Ok(())
+}
+
+/// Add, list, show, edit or cancel stored goals
+async fn manage_goals(config: &Config, command: GoalCommand) -> Result<()> {
+    let database = open_database(config).await?;
+    let store = database.goals();
+    match command {
+        GoalCommand::Add {
+            title,
+            description,
+            options,
+        } => {
+            let description = description.unwrap_or_else(|| title.clone());
+            let goal = goals::new_goal(&title, &description, &options.into_edit());
+            let goal_id = goal.id.clone();
+            store.insert(goal).await.context("Failed to add the goal")?;
+            println!("Added goal {}", goal_id);
+        }
+        GoalCommand::List { status, all } => {
+            let mut listed: Vec<OptimizationGoal> = store
+                .get_all()
+                .await?
+                .into_iter()
+                .map(|record| record.entity)
+                .filter(|goal| match status {
+                    Some(status) => goal.status == status,
+                    None => all || goals::is_open(goal),
+                })
+                .collect();
+            if listed.is_empty() {
+                println!("No goals");
+                return Ok(());
+            }
+            listed.sort_by(|a, b| {
+                b.priority
+                    .cmp(&a.priority)
+                    .then(a.created_at.cmp(&b.created_at))
+            });
+            println!(
+                "{:<24} {:<12} {:>8} {:<15} Title",
+                "Id", "Status", "Priority", "Category"
+            );
+            for goal in &listed {
+                println!(
+                    "{:<24} {:<12} {:>8} {:<15} {}",
+                    goal.id,
+                    goal.status.to_string(),
+                    goal.priority,
+                    goal.category.to_string(),
+                    goal.title
+                );
+            }
+        }
+        GoalCommand::Show { id } => {
+            let goal = store
+                .get(&id)
+                .await
+                .with_context(|| format!("No goal {}", id))?
+                .entity;
+            print!("{}", goal.details());
+            println!("## Id\n{}\n", goal.id);
+            println!("## Category\n{}\n", goal.category);
+            println!("## Priority\n{}\n", goal.priority);
+            let files: Vec<&str> = goals::files(&goal).collect();
+            if !files.is_empty() {
+                println!("## Files\n{}\n", bullets(files));
+            }
+            if !goal.success_metrics.is_empty() {
+                println!("## Success Metrics\n{}\n", bullets(&goal.success_metrics));
+            }
+            if let Some(notes) = &goal.implementation_notes {
+                println!("## Notes\n{}\n", notes);
+            }
+        }
+        GoalCommand::Edit {
+            id,
+            title,
+            description,
+            options,
+            remove_files,
+            remove_metrics,
+        } => {
+            let edit = GoalEdit {
+                title,
+                description,
+                remove_files,
+                remove_metrics,
+                ..options.into_edit()
+            };
+            if edit.is_empty() {
+                anyhow::bail!("Nothing to change; see `borg goal edit --help`");
+            }
+            let stored = store
+                .get(&id)
+                .await
+                .with_context(|| format!("No goal {}", id))?;
+            let mut goal = stored.entity;
+            edit.apply(&mut goal);
+            store
+                .update(goal, Some(stored.version))
+                .await
+                .context("Failed to update the goal")?;
+            println!("Updated goal {}", id);
+        }
+        GoalCommand::Cancel { id, reason } => {
+            let stored = store
+                .get(&id)
+                .await
+                .with_context(|| format!("No goal {}", id))?;
+            let mut goal = stored.entity;
+            goals::cancel(&mut goal, &reason)?;
+            store
+                .update(goal, Some(stored.version))
+                .await
+                .context("Failed to cancel the goal")?;
+            println!("Cancelled goal {}", id);
+        }
+    }
+    Ok(())
+}
+
+/// `items` as a Markdown list
+fn bullets(items: impl IntoIterator<Item = impl AsRef<str>>) -> String {
+    items
+        .into_iter()
+        .map(|item| format!("- {}", item.as_ref()))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Upgrade the stored records of every collection to the current schema
+async fn migrate_database(config: &Config) -> Result<()> {
+    let database = open_database(config).await?;
+    for (collection, upgraded) in database.migrate().await? {
+        println!("{:<20} {} records upgraded", collection, upgraded);
+    }
+    Ok(())
+}
+
+/// Remove what the retention policies no longer keep
+async fn vacuum_database(config: &Config) -> Result<()> {
+    let database = open_database(config).await?;
+    let compaction = Compactor::new(config, &database).compact().await?;
+    println!("{}", compaction);
+    Ok(())
+}
+
+/// Write an archive of every collection to `path`, sealed when encryption
+/// is enabled
+async fn back_up_database(config: &Config, path: &Path) -> Result<()> {
+    let database = open_database(config).await?;
+    let archive = database.export().await?;
+    let cipher = Cipher::from_config(&config.encryption)?;
+    // Written next to the archive and moved into place, so a failed
+    // backup never replaces a good one
+    let partial = path.with_extension("partial");
+    let mut out = std::io::BufWriter::new(
+        std::fs::File::create(&partial)
+            .with_context(|| format!("Failed to create archive {:?}", partial))?,
+    );
+    archive.write(&mut out, cipher.as_ref())?;
+    drop(out);
+    std::fs::rename(&partial, path)
+        .with_context(|| format!("Failed to write archive {:?}", path))?;
+    for (collection, records) in archive.summary() {
+        println!("{:<20} {} records", collection, records);
+    }
+    println!(
+        "Backed up to {}{}",
+        path.display(),
+        if cipher.is_some() { " (encrypted)" } else { "" }
+    );
+    Ok(())
+}
+
+/// Replace the stored state with an archive, after checking it
+async fn restore_database(config: &Config, path: &Path) -> Result<()> {
+    let file =
+        std::fs::File::open(path).with_context(|| format!("Failed to open archive {:?}", path))?;
+    let cipher = Cipher::from_config(&config.encryption)?;
+    let archive = Archive::read(std::io::BufReader::new(file), cipher.as_ref())
+        .with_context(|| format!("Failed to read archive {:?}", path))?;
+    let database = open_database(config).await?;
+    for (collection, records) in database.restore(&archive).await? {
+        println!("{:<20} {} records restored", collection, records);
+    }
+    println!("Restored the state of {}", archive.created_at.to_rfc3339());
+    Ok(())
+}
+
+/// Write every collection in plain text to `output` or standard output
+async fn export_database(
+    config: &Config,
+    format: ExportFormat,
+    output: Option<&Path>,
+) -> Result<()> {
+    let database = open_database(config).await?;
+    let archive = database.export().await?;
+    match (format, output) {
+        (ExportFormat::Jsonl, Some(output)) => {
+            let mut out = std::io::BufWriter::new(
+                std::fs::File::create(output)
+                    .with_context(|| format!("Failed to create {:?}", output))?,
+            );
+            archive.write(&mut out, None)?;
+        }
+        (ExportFormat::Jsonl, None) => archive.write(&mut std::io::stdout().lock(), None)?,
+    }
+    Ok(())
+}
+
+/// Keychain entry `db keygen --keychain` stores the key under
+const KEYCHAIN_SERVICE: &str = "borg";
+const KEYCHAIN_ACCOUNT: &str = "encryption-key";
+
+/// Print a new encryption key, or store it in the OS keychain, with the
+/// config that uses it
+fn generate_encryption_key(keychain: bool) -> Result<()> {
+    let key = Cipher::generate_key();
+    if keychain {
+        KeychainSecretProvider::new(KEYCHAIN_SERVICE, KEYCHAIN_ACCOUNT).store(&key)?;
+        println!("Stored a new key in the OS keychain. Enable it with:\n");
+        println!("encryption:\n  enabled: true\n  key_source:\n    type: keychain");
+        println!(
+            "    service: {}\n    account: {}",
+            KEYCHAIN_SERVICE, KEYCHAIN_ACCOUNT
+        );
+    } else {
+        println!("{}", key);
+        eprintln!(
+            "Set {} to this key and `encryption.enabled: true`, and keep a copy: data encrypted with it cannot be read without it",
+            DEFAULT_KEY_ENV
+        );
+    }
+    Ok(())
+}
+
+/// Print an LLM log with its encrypted entries decrypted
+fn decrypt_log(config: &Config, path: &Path) -> Result<()> {
+    let cipher = Cipher::from_config(&config.encryption)?
+        .context("Encryption is not enabled in the configuration")?;
+    let contents = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read log file {:?}", path))?;
+    print!("{}", cipher.open_log(&contents)?);
+    Ok(())
+}
+
+/// Bisect the workspace history for the commit that broke `check` and
+/// store a goal to fix it
+async fn bisect_regression(
+    config: &Config,
+    good: &str,
+    bad: &str,
+    check: &str,
+    no_goal: bool,
+) -> Result<()> {
+    let report = Bisector::new(Path::new(&config.agent.working_dir), check)
+        .run(good, bad)
+        .await?;
+    print!("{}", report);
+    if no_goal {
+        return Ok(());
+    }
+
+    let database = open_database(config).await?;
+    let goal = bisect::fix_goal(&report);
+    let goal_id = goal.id.clone();
+    database
+        .goals()
+        .insert(goal)
+        .await
+        .context("Failed to add the fix goal")?;
+    println!("Added goal {}", goal_id);
+    Ok(())
+}
+
+/// Print the progress of each goal's todo list, the most recently updated
+/// first
+async fn print_todo_progress(services: &Services) -> Result<()> {
+    let Some(store) = &services.todos else {
+        return Ok(());
+    };
+    let lists = store.all().await?;
+    if lists.is_empty() {
+        return Ok(());
+    }
+    println!("Todos:");
+    for list in &lists {
+        println!("  {}: {}", list.id, list.progress());
+    }
+    Ok(())
+}
+
+/// Expose the workspace tools to MCP clients until the client disconnects
+/// (stdio) or the process is stopped (SSE)
+async fn serve_mcp(config: &Config, sse: Option<&str>, read_only: bool) -> Result<()> {
+    let sandbox = borg::code_generation::sandbox::Sandbox::new(config.sandbox.clone());
+    sandbox
+        .check()
+        .context("Configured sandbox backend is unavailable")?;
+    // stdin carries the protocol, so calls needing approval are refused
+    let policy =
+        PermissionPolicy::from_config(&config.permissions).context("Invalid tool permissions")?;
+    let database = open_database(config).await?;
+    Arc::new(Services {
+        sandbox: Arc::new(sandbox),
+        permissions: Arc::new(policy),
+        tool_audit: Some(Arc::new(ToolAuditLog::new(database.tool_invocations()))),
+        cipher: Cipher::from_config(&config.encryption)?,
+        ..Default::default()
+    })
+    .install();
+    let registry = workspace_registry(Path::new(&config.agent.working_dir), read_only)?;
+    let server = McpServer::new(registry);
+    match sse {
+        Some(addr) => {
+            let options = SseOptions::from_config(&config.mcp.serve)?;
+            if config.mcp.serve.token_source.is_none() {
+                info!(
+                    "MCP clients must send: Authorization: Bearer {}",
+                    options.token
+                );
+            }
+            Arc::new(server).serve_sse(addr, options).await
+        }
+        None => server.serve_stdio().await,
+    }
+}
+
 /// Handle the commands passed to the agent
 async fn handle_commands(command: Option<Commands>, mut agent: Agent) -> Result<()> {
     match command {
@@ -107,7 +834,16 @@ async fn handle_commands(command: Option<Commands>, mut agent: Agent) -> Result<
             );
             println!("Mode: Swarm-based improvements");
             println!("Models configured: {}", agent.get_config().models.len());
-            Ok(())
+            print_todo_progress(agent.services()).await
+        }
+        Some(Commands::Providers { .. })
+        | Some(Commands::McpServe { .. })
+        | Some(Commands::ToolStats { .. })
+        | Some(Commands::Rollback { .. })
+        | Some(Commands::Goal { .. })
+        | Some(Commands::Db { .. })
+        | Some(Commands::Bisect { .. }) => {
+            unreachable!("handled before the agent starts")
         }
     }
 }
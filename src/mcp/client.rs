@@ -0,0 +1,516 @@
+//! MCP client: talks to one server and adapts its tools to `LlmTool`.
+
+use anyhow::{anyhow, bail, Context, Result};
+use async_trait::async_trait;
+use log::{debug, info, warn};
+use serde::Deserialize;
+use serde_json::{json, Map, Value as JsonValue};
+use std::process::Stdio;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader};
+use tokio::process::{Child, Command};
+use tokio::sync::Mutex;
+
+use super::{JsonRpcMessage, METHOD_NOT_FOUND, PROTOCOL_VERSION, TOOL_PREFIX};
+use crate::code_generation::llm_tool::{LlmTool, ToolArgs, ToolParameter, ToolParameterType};
+use crate::core::config::McpServerConfig;
+
+type Reader = BufReader<Box<dyn AsyncRead + Send + Unpin>>;
+type Writer = Box<dyn AsyncWrite + Send + Unpin>;
+
+/// A tool advertised by an MCP server in `tools/list`
+#[derive(Debug, Clone, Deserialize)]
+pub struct McpToolInfo {
+    pub name: String,
+    #[serde(default)]
+    pub description: Option<String>,
+    /// JSON Schema of the tool's arguments
+    #[serde(rename = "inputSchema", default)]
+    pub input_schema: JsonValue,
+}
+
+/// Text output of a `tools/call`
+#[derive(Debug, Clone)]
+pub struct McpToolOutput {
+    /// Text content parts, joined by newlines
+    pub text: String,
+    /// Whether the server reported the call as failed
+    pub is_error: bool,
+}
+
+struct Connection {
+    reader: Reader,
+    writer: Writer,
+}
+
+/// Connection to one MCP server
+pub struct McpClient {
+    name: String,
+    connection: Mutex<Connection>,
+    next_id: AtomicU64,
+    timeout: Duration,
+    tools: Vec<McpToolInfo>,
+    /// Kept so the server process lives (and is killed) with the client
+    _child: Option<Child>,
+}
+
+impl McpClient {
+    /// Start the server described by `config` and complete the handshake
+    pub async fn connect(config: &McpServerConfig) -> Result<Self> {
+        let mut child = Command::new(&config.command)
+            .args(&config.args)
+            .envs(&config.env)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::inherit())
+            .kill_on_drop(true)
+            .spawn()
+            .with_context(|| {
+                format!(
+                    "Failed to start MCP server '{}' ({})",
+                    config.name, config.command
+                )
+            })?;
+        let stdin = child.stdin.take().context("MCP server stdin not piped")?;
+        let stdout = child.stdout.take().context("MCP server stdout not piped")?;
+
+        let mut client = Self::over(&config.name, stdout, stdin)
+            .with_timeout(Duration::from_secs(config.timeout_secs));
+        client._child = Some(child);
+        client.initialize().await?;
+        Ok(client)
+    }
+
+    /// Handshake with a server already reachable over `reader`/`writer`
+    /// (e.g. an in-process server in tests)
+    pub async fn connect_streams(
+        name: &str,
+        reader: impl AsyncRead + Send + Unpin + 'static,
+        writer: impl AsyncWrite + Send + Unpin + 'static,
+    ) -> Result<Self> {
+        let mut client = Self::over(name, reader, writer);
+        client.initialize().await?;
+        Ok(client)
+    }
+
+    fn over(
+        name: &str,
+        reader: impl AsyncRead + Send + Unpin + 'static,
+        writer: impl AsyncWrite + Send + Unpin + 'static,
+    ) -> Self {
+        Self {
+            name: name.to_string(),
+            connection: Mutex::new(Connection {
+                reader: BufReader::new(Box::new(reader)),
+                writer: Box::new(writer),
+            }),
+            next_id: AtomicU64::new(1),
+            timeout: Duration::from_secs(60),
+            tools: Vec::new(),
+            _child: None,
+        }
+    }
+
+    fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// `initialize`, `notifications/initialized`, then the tool listing
+    async fn initialize(&mut self) -> Result<()> {
+        let init = self
+            .request(
+                "initialize",
+                json!({
+                    "protocolVersion": PROTOCOL_VERSION,
+                    "capabilities": {},
+                    "clientInfo": {"name": "borg", "version": env!("CARGO_PKG_VERSION")}
+                }),
+            )
+            .await?;
+        debug!(
+            "MCP server '{}' speaks protocol {}",
+            self.name,
+            init["protocolVersion"].as_str().unwrap_or("?")
+        );
+        self.notify("notifications/initialized").await?;
+
+        let mut cursor: Option<String> = None;
+        loop {
+            let params = match &cursor {
+                Some(c) => json!({"cursor": c}),
+                None => json!({}),
+            };
+            let page = self.request("tools/list", params).await?;
+            let tools: Vec<McpToolInfo> = serde_json::from_value(page["tools"].clone())
+                .with_context(|| format!("Invalid tools/list from MCP server '{}'", self.name))?;
+            self.tools.extend(tools);
+            cursor = page["nextCursor"].as_str().map(str::to_string);
+            if cursor.is_none() {
+                break;
+            }
+        }
+        info!(
+            "Connected to MCP server '{}' with {} tools",
+            self.name,
+            self.tools.len()
+        );
+        Ok(())
+    }
+
+    /// Server name from the configuration
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Tools the server advertised at connect time
+    pub fn tools(&self) -> &[McpToolInfo] {
+        &self.tools
+    }
+
+    /// Call the server's tool `name`
+    pub async fn call_tool(&self, name: &str, arguments: JsonValue) -> Result<McpToolOutput> {
+        let result = self
+            .request("tools/call", json!({"name": name, "arguments": arguments}))
+            .await?;
+        let text = result["content"]
+            .as_array()
+            .map(|parts| {
+                parts
+                    .iter()
+                    .map(|part| match part["type"].as_str() {
+                        Some("text") => part["text"].as_str().unwrap_or_default().to_string(),
+                        // Images, audio and resources are described, not inlined
+                        Some(other) => format!("[{} content]", other),
+                        None => part.to_string(),
+                    })
+                    .collect::<Vec<_>>()
+                    .join("\n")
+            })
+            .unwrap_or_default();
+        Ok(McpToolOutput {
+            text,
+            is_error: result["isError"].as_bool().unwrap_or(false),
+        })
+    }
+
+    /// `LlmTool` adapters for every advertised tool
+    pub fn tool_adapters(self: &Arc<Self>) -> Vec<McpTool> {
+        self.tools
+            .iter()
+            .map(|info| McpTool::new(self.clone(), info.clone()))
+            .collect()
+    }
+
+    async fn notify(&self, method: &str) -> Result<()> {
+        let mut connection = self.connection.lock().await;
+        write_message(
+            &mut connection.writer,
+            &JsonRpcMessage::notification(method, None),
+        )
+        .await
+    }
+
+    /// Send a request and wait for its response, answering any requests
+    /// the server makes in the meantime
+    async fn request(&self, method: &str, params: JsonValue) -> Result<JsonValue> {
+        let id = self.next_id.fetch_add(1, Ordering::SeqCst);
+        let mut connection = self.connection.lock().await;
+        write_message(
+            &mut connection.writer,
+            &JsonRpcMessage::request(id, method, params),
+        )
+        .await?;
+
+        let exchange = async {
+            loop {
+                let Some(message) = read_message(&mut connection.reader).await? else {
+                    bail!("server closed the connection");
+                };
+                match (&message.id, &message.method) {
+                    (Some(reply_id), None) if reply_id.as_u64() == Some(id) => {
+                        if let Some(error) = message.error {
+                            bail!("{} (code {})", error.message, error.code);
+                        }
+                        return Ok(message.result.unwrap_or(JsonValue::Null));
+                    }
+                    // Server-to-client request: only `ping` is supported
+                    (Some(request_id), Some(method)) => {
+                        let reply = if method == "ping" {
+                            JsonRpcMessage::response(request_id.clone(), json!({}))
+                        } else {
+                            JsonRpcMessage::error_response(
+                                request_id.clone(),
+                                METHOD_NOT_FOUND,
+                                format!("Method not supported by client: {}", method),
+                            )
+                        };
+                        write_message(&mut connection.writer, &reply).await?;
+                    }
+                    // Notifications (logging, progress) and stale responses
+                    _ => debug!("MCP server '{}': ignoring {:?}", self.name, message.method),
+                }
+            }
+        };
+        tokio::time::timeout(self.timeout, exchange)
+            .await
+            .map_err(|_| {
+                anyhow!(
+                    "MCP server '{}' did not answer {} within {:?}",
+                    self.name,
+                    method,
+                    self.timeout
+                )
+            })?
+            .with_context(|| format!("MCP request {} to '{}' failed", method, self.name))
+    }
+}
+
+/// Write one newline-delimited message
+pub(crate) async fn write_message(
+    writer: &mut (impl AsyncWrite + Unpin + ?Sized),
+    message: &JsonRpcMessage,
+) -> Result<()> {
+    let mut line = serde_json::to_string(message)?;
+    line.push('\n');
+    writer.write_all(line.as_bytes()).await?;
+    writer.flush().await?;
+    Ok(())
+}
+
+/// Read the next message, skipping blank lines; `None` once the peer has
+/// closed the connection
+pub(crate) async fn read_message(
+    reader: &mut (impl AsyncBufReadExt + Unpin + ?Sized),
+) -> Result<Option<JsonRpcMessage>> {
+    let mut line = String::new();
+    loop {
+        line.clear();
+        if reader.read_line(&mut line).await? == 0 {
+            return Ok(None);
+        }
+        if !line.trim().is_empty() {
+            return serde_json::from_str(line.trim())
+                .map(Some)
+                .with_context(|| format!("Invalid JSON-RPC message: {}", line.trim()));
+        }
+    }
+}
+
+/// Connect to every configured server, skipping (with a warning) those that
+/// fail to start
+pub async fn connect_all(servers: &[McpServerConfig]) -> Vec<Arc<McpClient>> {
+    let mut clients = Vec::new();
+    for server in servers {
+        match McpClient::connect(server).await {
+            Ok(client) => clients.push(Arc::new(client)),
+            Err(e) => warn!("MCP server '{}' unavailable: {:#}", server.name, e),
+        }
+    }
+    clients
+}
+
+/// An MCP server tool usable as an `LlmTool`
+///
+/// Registered as `mcp__<server>__<tool>`. Positional arguments follow
+/// `parameters()` (required ones first) and are converted to the types the
+/// tool's input schema declares.
+pub struct McpTool {
+    client: Arc<McpClient>,
+    info: McpToolInfo,
+    name: String,
+    description: String,
+}
+
+impl McpTool {
+    fn new(client: Arc<McpClient>, info: McpToolInfo) -> Self {
+        let name = format!("{}{}__{}", TOOL_PREFIX, client.name(), info.name);
+        let description = info
+            .description
+            .clone()
+            .unwrap_or_else(|| format!("{} (MCP server '{}')", info.name, client.name()));
+        Self {
+            client,
+            info,
+            name,
+            description,
+        }
+    }
+
+    fn properties(&self) -> Map<String, JsonValue> {
+        self.info.input_schema["properties"]
+            .as_object()
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    fn required(&self) -> Vec<String> {
+        self.info.input_schema["required"]
+            .as_array()
+            .map(|names| {
+                names
+                    .iter()
+                    .filter_map(|n| n.as_str().map(str::to_string))
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+}
+
+/// Parse a positional argument as the JSON type `schema` declares
+fn typed_argument(raw: &str, schema: &JsonValue) -> JsonValue {
+    match schema["type"].as_str() {
+        Some("string") | None => JsonValue::String(raw.to_string()),
+        Some(_) => serde_json::from_str(raw).unwrap_or_else(|_| JsonValue::String(raw.to_string())),
+    }
+}
+
+#[async_trait]
+impl LlmTool for McpTool {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn description(&self) -> &str {
+        &self.description
+    }
+
+    fn parameters(&self) -> Vec<ToolParameter> {
+        let properties = self.properties();
+        let required = self.required();
+        let optional = properties.keys().filter(|k| !required.contains(k)).cloned();
+        required
+            .iter()
+            .cloned()
+            .chain(optional)
+            .map(|name| {
+                let schema = properties.get(&name).cloned().unwrap_or_default();
+                ToolParameter {
+                    description: schema["description"]
+                        .as_str()
+                        .unwrap_or_default()
+                        .to_string(),
+                    required: required.contains(&name),
+                    default_value: schema.get("default").map(|d| match d {
+                        JsonValue::String(s) => s.clone(),
+                        other => other.to_string(),
+                    }),
+                    param_type: Some(match schema["type"].as_str() {
+                        Some("integer") => ToolParameterType::Integer,
+                        Some("boolean") => ToolParameterType::Boolean,
+                        _ => ToolParameterType::String,
+                    }),
+                    name,
+                }
+            })
+            .collect()
+    }
+
+    fn input_schema(&self) -> Option<JsonValue> {
+        Some(self.info.input_schema.clone())
+    }
+
+    async fn execute(&self, args: &ToolArgs) -> Result<String> {
+        // Positional callers pass every argument as a string
+        let properties = self.properties();
+        let arguments: Map<String, JsonValue> = args
+            .iter()
+            .map(|(name, value)| {
+                let value = match (value, properties.get(name)) {
+                    (JsonValue::String(raw), Some(schema)) => typed_argument(raw.as_str(), schema),
+                    _ => value.clone(),
+                };
+                (name.clone(), value)
+            })
+            .collect();
+
+        let output = self
+            .client
+            .call_tool(&self.info.name, JsonValue::Object(arguments))
+            .await?;
+        if output.is_error {
+            bail!("{}", output.text);
+        }
+        Ok(output.text)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Minimal MCP server answering over `reader`/`writer`
+    async fn fake_server(
+        reader: impl AsyncRead + Unpin,
+        mut writer: impl AsyncWrite + Unpin,
+    ) -> Result<()> {
+        let mut reader = BufReader::new(reader);
+        loop {
+            let Some(message) = read_message(&mut reader).await? else {
+                return Ok(());
+            };
+            let (Some(id), Some(method)) = (message.id, message.method) else {
+                continue;
+            };
+            let result = match method.as_str() {
+                "initialize" => json!({"protocolVersion": PROTOCOL_VERSION, "capabilities": {}}),
+                "tools/list" => json!({"tools": [{
+                    "name": "add",
+                    "description": "Add two numbers",
+                    "inputSchema": {
+                        "type": "object",
+                        "properties": {
+                            "a": {"type": "integer"},
+                            "b": {"type": "integer"},
+                            "note": {"type": "string"}
+                        },
+                        "required": ["b", "a"]
+                    }
+                }]}),
+                "tools/call" => {
+                    let args = &message.params.unwrap_or_default()["arguments"];
+                    match (args["a"].as_i64(), args["b"].as_i64()) {
+                        (Some(a), Some(b)) => {
+                            json!({"content": [{"type": "text", "text": (a + b).to_string()}]})
+                        }
+                        _ => {
+                            json!({"content": [{"type": "text", "text": "bad args"}], "isError": true})
+                        }
+                    }
+                }
+                _ => json!({}),
+            };
+            write_message(&mut writer, &JsonRpcMessage::response(id, result)).await?;
+        }
+    }
+
+    #[tokio::test]
+    async fn test_tools_listed_and_called_with_typed_arguments() {
+        let (client_io, server_io) = tokio::io::duplex(4096);
+        let (server_read, server_write) = tokio::io::split(server_io);
+        tokio::spawn(fake_server(server_read, server_write));
+        let (client_read, client_write) = tokio::io::split(client_io);
+
+        let client = Arc::new(
+            McpClient::connect_streams("calc", client_read, client_write)
+                .await
+                .expect("handshake"),
+        );
+        let tools = client.tool_adapters();
+        assert_eq!(tools.len(), 1);
+        let add = &tools[0];
+        assert_eq!(add.name(), "mcp__calc__add");
+        let names: Vec<String> = add.parameters().into_iter().map(|p| p.name).collect();
+        assert_eq!(names, ["b", "a", "note"]);
+        assert!(add.input_schema().is_some());
+
+        assert_eq!(
+            add.execute_positional(&["2", "40", ""]).await.unwrap(),
+            "42"
+        );
+        let err = add.execute_positional(&["x", "y"]).await.unwrap_err();
+        assert_eq!(err.to_string(), "bad args");
+    }
+}
@@ -0,0 +1,108 @@
+//! Model Context Protocol (MCP) support.
+//!
+//! MCP is JSON-RPC 2.0 exchanged as newline-delimited JSON over a server
+//! process's stdin/stdout. `client` connects to the servers configured under
+//! `mcp.servers` and adapts their tools to `LlmTool`s, so they register into
+//! a `ToolRegistry` like the built-in ones; `server` does the reverse,
+//! serving Borg's workspace tools to other agents (`borg mcp-serve`).
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value as JsonValue;
+use std::sync::Arc;
+
+pub mod client;
+pub mod server;
+
+pub use client::{McpClient, McpTool, McpToolInfo};
+
+/// Protocol revision sent in `initialize`
+pub const PROTOCOL_VERSION: &str = "2025-06-18";
+
+/// Prefix of registered MCP tool names (`mcp__<server>__<tool>`)
+pub const TOOL_PREFIX: &str = "mcp__";
+
+/// A JSON-RPC 2.0 request, notification or response
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct JsonRpcMessage {
+    pub jsonrpc: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<JsonValue>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub method: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub params: Option<JsonValue>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub result: Option<JsonValue>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<JsonRpcError>,
+}
+
+/// Error object of a failed JSON-RPC call
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JsonRpcError {
+    pub code: i64,
+    pub message: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub data: Option<JsonValue>,
+}
+
+/// JSON-RPC error code for unknown methods
+pub const METHOD_NOT_FOUND: i64 = -32601;
+
+impl JsonRpcMessage {
+    /// A request expecting a response with the same `id`
+    pub fn request(id: u64, method: &str, params: JsonValue) -> Self {
+        Self {
+            jsonrpc: "2.0".to_string(),
+            id: Some(JsonValue::from(id)),
+            method: Some(method.to_string()),
+            params: Some(params),
+            ..Self::default()
+        }
+    }
+
+    /// A notification, which gets no response
+    pub fn notification(method: &str, params: Option<JsonValue>) -> Self {
+        Self {
+            jsonrpc: "2.0".to_string(),
+            method: Some(method.to_string()),
+            params,
+            ..Self::default()
+        }
+    }
+
+    /// A successful response to the request `id`
+    pub fn response(id: JsonValue, result: JsonValue) -> Self {
+        Self {
+            jsonrpc: "2.0".to_string(),
+            id: Some(id),
+            result: Some(result),
+            ..Self::default()
+        }
+    }
+
+    /// A failed response to the request `id`
+    pub fn error_response(id: JsonValue, code: i64, message: impl Into<String>) -> Self {
+        Self {
+            jsonrpc: "2.0".to_string(),
+            id: Some(id),
+            error: Some(JsonRpcError {
+                code,
+                message: message.into(),
+                data: None,
+            }),
+            ..Self::default()
+        }
+    }
+}
+
+/// The MCP server connections of the current services (none unless
+/// configured)
+pub fn global() -> Vec<Arc<McpClient>> {
+    crate::core::services::Services::current().mcp.clone()
+}
+
+/// Tools of every connected MCP server, as registrable `LlmTool`s
+pub fn global_tools() -> Vec<McpTool> {
+    global().iter().flat_map(McpClient::tool_adapters).collect()
+}
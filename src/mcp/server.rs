@@ -0,0 +1,582 @@
+//! MCP server: exposes a `ToolRegistry` to other agents and IDEs.
+//!
+//! Two transports are supported. `serve_stdio` speaks newline-delimited
+//! JSON-RPC on stdin/stdout (logs go to stderr). `serve_sse` implements the
+//! HTTP+SSE transport: a client opens `GET /sse`, receives an `endpoint`
+//! event naming its `POST /messages?session_id=…` URL, and gets each
+//! response back as a `message` event on the stream. Every HTTP request
+//! must carry the server's bearer token; requests from browser origins
+//! that are not allowed, and, on loopback, for other hosts (DNS
+//! rebinding), are refused, as are bodies over `max_body_bytes`.
+
+use anyhow::{Context, Result};
+use log::{debug, info, warn};
+use serde_json::{json, Value as JsonValue};
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::path::Path;
+use std::sync::Arc;
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::{mpsc, Mutex};
+
+use super::client::{read_message, write_message};
+use super::{JsonRpcMessage, METHOD_NOT_FOUND, PROTOCOL_VERSION};
+use crate::code_generation::code_query::CodeQueryTool;
+use crate::code_generation::llm_tool::{
+    ApplyPatchTool, CompilationFeedbackTool, DeleteTool, EditTool, FindTestsTool, GitBisectTool,
+    GitCommandTool, GitHistoryTool, GlobTool, GrepTool, LsTool, MoveTool, ReadTool, TestRunnerTool,
+    ToolRegistry, WriteTool,
+};
+use crate::code_generation::lsp::LspSession;
+use crate::core::config::McpServeConfig;
+use crate::version_control::git::GitManager;
+use crate::version_control::git_implementation::GitImplementation;
+
+/// JSON-RPC error code for malformed params
+const INVALID_PARAMS: i64 = -32602;
+
+/// JSON-RPC error code for messages that are not valid JSON
+const PARSE_ERROR: i64 = -32700;
+
+/// Address the HTTP+SSE transport listens on when none is given
+pub const DEFAULT_SSE_ADDR: &str = "127.0.0.1:8765";
+
+/// Most bytes read for the request line and headers of one request
+const MAX_HEADER_BYTES: u64 = 16 * 1024;
+
+/// Who may use the HTTP+SSE transport, and how much they may send
+#[derive(Clone)]
+pub struct SseOptions {
+    /// Bearer token every request must carry
+    pub token: String,
+
+    /// Browser origins allowed to connect
+    pub allowed_origins: Vec<String>,
+
+    /// Largest request body accepted, in bytes
+    pub max_body_bytes: usize,
+}
+
+impl std::fmt::Debug for SseOptions {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SseOptions")
+            .field("allowed_origins", &self.allowed_origins)
+            .field("max_body_bytes", &self.max_body_bytes)
+            .finish_non_exhaustive()
+    }
+}
+
+impl SseOptions {
+    /// The options of `config`, with a fresh token when it names no
+    /// token source
+    pub fn from_config(config: &McpServeConfig) -> Result<Self> {
+        let token = match &config.token_source {
+            Some(source) => source
+                .resolve()
+                .context("Failed to load the MCP server token")?,
+            None => Self::generate_token(),
+        };
+        Ok(Self {
+            token,
+            allowed_origins: config.allowed_origins.clone(),
+            max_body_bytes: config.max_body_bytes,
+        })
+    }
+
+    /// A new random token
+    pub fn generate_token() -> String {
+        format!(
+            "{}{}",
+            uuid::Uuid::new_v4().simple(),
+            uuid::Uuid::new_v4().simple()
+        )
+    }
+
+    /// Why a request with `headers` to a server on `local` is refused, as
+    /// an HTTP status and reason; `None` when it is allowed
+    fn refusal(
+        &self,
+        local: SocketAddr,
+        headers: &HashMap<String, String>,
+    ) -> Option<(&'static str, &'static str)> {
+        if local.ip().is_loopback() {
+            let host = headers.get("host").map(String::as_str).unwrap_or_default();
+            if !is_loopback_host(host) {
+                return Some(("403 Forbidden", "host not allowed"));
+            }
+        }
+        if let Some(origin) = headers.get("origin") {
+            if !self.allowed_origins.iter().any(|allowed| allowed == origin) {
+                return Some(("403 Forbidden", "origin not allowed"));
+            }
+        }
+        let token = headers
+            .get("authorization")
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .unwrap_or_default();
+        if !same_token(token.trim(), &self.token) {
+            return Some(("401 Unauthorized", "missing or wrong bearer token"));
+        }
+        None
+    }
+}
+
+/// Whether the `Host` header `host` names this machine
+fn is_loopback_host(host: &str) -> bool {
+    let name = match host.strip_prefix('[') {
+        Some(v6) => v6.split(']').next().unwrap_or_default(),
+        None => host.rsplit_once(':').map_or(host, |(name, _)| name),
+    };
+    matches!(name, "localhost" | "127.0.0.1" | "::1")
+}
+
+/// Compare tokens in time independent of where they differ
+fn same_token(given: &str, expected: &str) -> bool {
+    given.len() == expected.len()
+        && given
+            .bytes()
+            .zip(expected.bytes())
+            .fold(0, |diff, (a, b)| diff | (a ^ b))
+            == 0
+}
+
+/// Borg's workspace tools over `workspace`; `read_only` leaves out the tools
+/// that modify files, run commands or run tests
+pub fn workspace_registry(workspace: &Path, read_only: bool) -> Result<ToolRegistry> {
+    let workspace = workspace.to_path_buf();
+    let git_manager: Arc<Mutex<dyn GitManager>> = Arc::new(Mutex::new(
+        GitImplementation::new(&workspace).context("Failed to open the workspace repository")?,
+    ));
+
+    let mut registry = ToolRegistry::new();
+    registry.register(ReadTool::new(workspace.clone()));
+    registry.register(GrepTool::new(workspace.clone(), git_manager.clone()));
+    registry.register(GlobTool::new(workspace.clone()));
+    registry.register(LsTool::new(workspace.clone()));
+    let (find_definition, find_references, document_symbols) =
+        Arc::new(LspSession::new(workspace.clone())).tools();
+    registry.register(find_definition);
+    registry.register(find_references);
+    registry.register(document_symbols);
+    registry.register(CodeQueryTool::new(workspace.clone()));
+    registry.register(FindTestsTool::new(workspace.clone()));
+    registry.register(GitHistoryTool::new(workspace.clone(), git_manager));
+    if !read_only {
+        registry.register(WriteTool::new(workspace.clone()));
+        registry.register(EditTool::new(workspace.clone()));
+        registry.register(ApplyPatchTool::new(workspace.clone()));
+        registry.register(MoveTool::new(workspace.clone()));
+        registry.register(DeleteTool::new(workspace.clone()));
+        registry.register(GitCommandTool::new(workspace.clone()));
+        registry.register(GitBisectTool::new(workspace.clone()));
+        registry.register(CompilationFeedbackTool::new(workspace.clone()));
+        registry.register(TestRunnerTool::new(workspace));
+    }
+    Ok(registry)
+}
+
+/// Answers MCP requests from a tool registry
+pub struct McpServer {
+    registry: ToolRegistry,
+}
+
+impl McpServer {
+    /// Serve the tools in `registry`
+    pub fn new(registry: ToolRegistry) -> Self {
+        Self { registry }
+    }
+
+    /// Response to `message`, or `None` for notifications
+    pub async fn handle(&self, message: JsonRpcMessage) -> Option<JsonRpcMessage> {
+        let id = message.id?;
+        let method = message.method.unwrap_or_default();
+        let params = message.params.unwrap_or(JsonValue::Null);
+        debug!("MCP request {}", method);
+        Some(match method.as_str() {
+            "initialize" => JsonRpcMessage::response(
+                id,
+                json!({
+                    "protocolVersion": PROTOCOL_VERSION,
+                    "capabilities": {"tools": {"listChanged": false}},
+                    "serverInfo": {"name": "borg", "version": env!("CARGO_PKG_VERSION")}
+                }),
+            ),
+            "ping" => JsonRpcMessage::response(id, json!({})),
+            "tools/list" => JsonRpcMessage::response(id, json!({"tools": self.list_tools()})),
+            "tools/call" => match params["name"].as_str() {
+                Some(name) => JsonRpcMessage::response(id, self.call_tool(name, &params).await),
+                None => JsonRpcMessage::error_response(id, INVALID_PARAMS, "missing tool name"),
+            },
+            other => JsonRpcMessage::error_response(
+                id,
+                METHOD_NOT_FOUND,
+                format!("Method not found: {}", other),
+            ),
+        })
+    }
+
+    fn list_tools(&self) -> Vec<JsonValue> {
+        self.registry
+            .tool_specs()
+            .into_iter()
+            .map(|spec| {
+                json!({
+                    "name": spec.name,
+                    "description": spec.description.unwrap_or_default(),
+                    "inputSchema": spec.json_schema
+                })
+            })
+            .collect()
+    }
+
+    async fn call_tool(&self, name: &str, params: &JsonValue) -> JsonValue {
+        let arguments = params.get("arguments").cloned().unwrap_or(json!({}));
+        let result = self.registry.execute_json(name, &arguments).await;
+        let text = match result.error {
+            Some(error) if !result.success => error,
+            _ => result.result,
+        };
+        json!({
+            "content": [{"type": "text", "text": text}],
+            "isError": !result.success
+        })
+    }
+
+    /// Serve one client over newline-delimited JSON until it disconnects
+    pub async fn serve(
+        &self,
+        reader: impl AsyncRead + Unpin,
+        mut writer: impl AsyncWrite + Unpin,
+    ) -> Result<()> {
+        let mut reader = BufReader::new(reader);
+        loop {
+            let message = match read_message(&mut reader).await {
+                Ok(Some(message)) => message,
+                Ok(None) => return Ok(()),
+                Err(e) if e.is::<serde_json::Error>() => {
+                    warn!("Unreadable MCP message: {:#}", e);
+                    let reply =
+                        JsonRpcMessage::error_response(JsonValue::Null, PARSE_ERROR, "Parse error");
+                    write_message(&mut writer, &reply).await?;
+                    continue;
+                }
+                Err(e) => return Err(e),
+            };
+            if let Some(reply) = self.handle(message).await {
+                write_message(&mut writer, &reply).await?;
+            }
+        }
+    }
+
+    /// Serve a single client on stdin/stdout
+    pub async fn serve_stdio(&self) -> Result<()> {
+        info!("Serving MCP over stdio");
+        self.serve(tokio::io::stdin(), tokio::io::stdout()).await
+    }
+
+    /// Serve clients over HTTP+SSE on `addr` (e.g. `127.0.0.1:8765`)
+    pub async fn serve_sse(self: Arc<Self>, addr: &str, options: SseOptions) -> Result<()> {
+        let listener = TcpListener::bind(addr)
+            .await
+            .with_context(|| format!("Failed to bind MCP server to {}", addr))?;
+        let local = listener.local_addr()?;
+        if !local.ip().is_loopback() {
+            warn!(
+                "MCP server listens on {}, reachable from other machines",
+                local
+            );
+        }
+        info!("Serving MCP over SSE at http://{}/sse", local);
+        self.serve_sse_on(listener, options).await
+    }
+
+    /// Serve clients over HTTP+SSE on an already bound listener
+    pub async fn serve_sse_on(
+        self: Arc<Self>,
+        listener: TcpListener,
+        options: SseOptions,
+    ) -> Result<()> {
+        let local = listener.local_addr()?;
+        let options = Arc::new(options);
+        let sessions: Sessions = Arc::new(Mutex::new(HashMap::new()));
+        loop {
+            let (stream, _) = listener.accept().await?;
+            let server = self.clone();
+            let sessions = sessions.clone();
+            let options = options.clone();
+            tokio::spawn(async move {
+                if let Err(e) = server.handle_http(stream, local, &options, sessions).await {
+                    debug!("MCP HTTP connection ended: {:#}", e);
+                }
+            });
+        }
+    }
+
+    async fn handle_http(
+        &self,
+        stream: TcpStream,
+        local: SocketAddr,
+        options: &SseOptions,
+        sessions: Sessions,
+    ) -> Result<()> {
+        let (reader, mut writer) = stream.into_split();
+        let mut reader = BufReader::new(reader);
+
+        // The request line and headers, read no further than their limit
+        let mut head = Vec::new();
+        let mut budget = MAX_HEADER_BYTES;
+        loop {
+            let mut line = String::new();
+            let read = (&mut reader).take(budget).read_line(&mut line).await?;
+            if !line.ends_with('\n') && read as u64 == budget {
+                return respond(&mut writer, "431 Request Header Fields Too Large", "").await;
+            }
+            budget -= read as u64;
+            if read == 0 || (line.trim().is_empty() && !head.is_empty()) {
+                break;
+            }
+            head.push(line);
+        }
+        let request_line = head.first().cloned().unwrap_or_default();
+        let mut parts = request_line.split_whitespace();
+        let method = parts.next().unwrap_or_default().to_string();
+        let target = parts.next().unwrap_or_default().to_string();
+        let headers: HashMap<String, String> = head
+            .iter()
+            .skip(1)
+            .filter_map(|header| header.split_once(':'))
+            .map(|(name, value)| (name.trim().to_ascii_lowercase(), value.trim().to_string()))
+            .collect();
+        if let Some((status, reason)) = options.refusal(local, &headers) {
+            return respond(&mut writer, status, reason).await;
+        }
+        let content_length: usize = match headers.get("content-length") {
+            Some(value) => match value.parse() {
+                Ok(length) => length,
+                Err(_) => {
+                    return respond(&mut writer, "400 Bad Request", "bad content length").await
+                }
+            },
+            None => 0,
+        };
+        if content_length > options.max_body_bytes {
+            return respond(
+                &mut writer,
+                "413 Payload Too Large",
+                "request body too large",
+            )
+            .await;
+        }
+        let (path, query) = target.split_once('?').unwrap_or((target.as_str(), ""));
+
+        match (method.as_str(), path) {
+            ("GET", "/sse") => {
+                let session_id = uuid::Uuid::new_v4().simple().to_string();
+                let (tx, mut rx) = mpsc::unbounded_channel::<String>();
+                sessions.lock().await.insert(session_id.clone(), tx);
+                writer
+                    .write_all(
+                        b"HTTP/1.1 200 OK\r\nContent-Type: text/event-stream\r\n\
+                          Cache-Control: no-cache\r\nConnection: keep-alive\r\n\r\n",
+                    )
+                    .await?;
+                let endpoint = format!(
+                    "event: endpoint\ndata: /messages?session_id={}\n\n",
+                    session_id
+                );
+                let streamed = async {
+                    writer.write_all(endpoint.as_bytes()).await?;
+                    writer.flush().await?;
+                    while let Some(message) = rx.recv().await {
+                        writer
+                            .write_all(format!("event: message\ndata: {}\n\n", message).as_bytes())
+                            .await?;
+                        writer.flush().await?;
+                    }
+                    anyhow::Ok(())
+                }
+                .await;
+                sessions.lock().await.remove(&session_id);
+                streamed
+            }
+            ("POST", "/messages") => {
+                let session = query
+                    .split('&')
+                    .find_map(|kv| kv.strip_prefix("session_id="))
+                    .unwrap_or_default();
+                let Some(tx) = sessions.lock().await.get(session).cloned() else {
+                    return respond(&mut writer, "404 Not Found", "unknown session").await;
+                };
+                let mut body = vec![0; content_length];
+                reader.read_exact(&mut body).await?;
+                let message: JsonRpcMessage = match serde_json::from_slice(&body) {
+                    Ok(message) => message,
+                    Err(e) => return respond(&mut writer, "400 Bad Request", &e.to_string()).await,
+                };
+                respond(&mut writer, "202 Accepted", "Accepted").await?;
+                if let Some(reply) = self.handle(message).await {
+                    let _ = tx.send(serde_json::to_string(&reply)?);
+                }
+                Ok(())
+            }
+            _ => respond(&mut writer, "404 Not Found", "not found").await,
+        }
+    }
+}
+
+/// Open SSE streams by session id
+type Sessions = Arc<Mutex<HashMap<String, mpsc::UnboundedSender<String>>>>;
+
+async fn respond(writer: &mut (impl AsyncWrite + Unpin), status: &str, body: &str) -> Result<()> {
+    writer
+        .write_all(
+            format!(
+                "HTTP/1.1 {}\r\nContent-Type: text/plain\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                status,
+                body.len(),
+                body
+            )
+            .as_bytes(),
+        )
+        .await?;
+    writer.flush().await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::code_generation::llm_tool::LlmTool;
+    use crate::mcp::McpClient;
+
+    #[tokio::test]
+    async fn test_client_drives_workspace_tools_over_stdio_framing() {
+        let workspace = tempfile::tempdir().unwrap();
+        std::fs::write(workspace.path().join("notes.txt"), "hello from borg\n").unwrap();
+        let server = McpServer::new(workspace_registry(workspace.path(), true).unwrap());
+
+        let (client_io, server_io) = tokio::io::duplex(64 * 1024);
+        let (server_read, server_write) = tokio::io::split(server_io);
+        tokio::spawn(async move { server.serve(server_read, server_write).await });
+        let (client_read, client_write) = tokio::io::split(client_io);
+        let client = Arc::new(
+            McpClient::connect_streams("borg", client_read, client_write)
+                .await
+                .expect("handshake"),
+        );
+
+        let names: Vec<&str> = client.tools().iter().map(|t| t.name.as_str()).collect();
+        assert!(names.contains(&"Read"));
+        assert!(!names.contains(&"Write"), "read-only registry: {:?}", names);
+
+        let read = client
+            .tool_adapters()
+            .into_iter()
+            .find(|t| t.name() == "mcp__borg__Read")
+            .unwrap();
+        let output = read.execute_positional(&["notes.txt"]).await.unwrap();
+        assert!(output.contains("hello from borg"), "{}", output);
+
+        let missing = client
+            .call_tool("Nope", json!({}))
+            .await
+            .expect("tool errors are results");
+        assert!(missing.is_error);
+    }
+
+    #[tokio::test]
+    async fn test_sse_transport_answers_on_the_event_stream() {
+        let workspace = tempfile::tempdir().unwrap();
+        let server = Arc::new(McpServer::new(
+            workspace_registry(workspace.path(), true).unwrap(),
+        ));
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base = format!("http://{}", listener.local_addr().unwrap());
+        let options = SseOptions {
+            token: SseOptions::generate_token(),
+            allowed_origins: vec!["http://localhost:3000".to_string()],
+            max_body_bytes: 1024,
+        };
+        let token = options.token.clone();
+        tokio::spawn(server.serve_sse_on(listener, options));
+
+        let http = reqwest::Client::new();
+        let status = |request: reqwest::RequestBuilder| async move {
+            request.send().await.unwrap().status().as_u16()
+        };
+        assert_eq!(status(http.get(format!("{}/sse", base))).await, 401);
+        assert_eq!(
+            status(http.get(format!("{}/sse", base)).bearer_auth("wrong")).await,
+            401
+        );
+        assert_eq!(
+            status(
+                http.get(format!("{}/sse", base))
+                    .bearer_auth(&token)
+                    .header("Origin", "https://evil.example")
+            )
+            .await,
+            403
+        );
+        assert_eq!(
+            status(
+                http.get(format!("{}/sse", base))
+                    .bearer_auth(&token)
+                    .header("Host", "evil.example")
+            )
+            .await,
+            403
+        );
+
+        let mut events = http
+            .get(format!("{}/sse", base))
+            .bearer_auth(&token)
+            .header("Origin", "http://localhost:3000")
+            .send()
+            .await
+            .unwrap()
+            .bytes_stream();
+        let mut decoder = crate::providers::SseDecoder::new();
+        let mut pending = std::collections::VecDeque::new();
+        macro_rules! next_event {
+            () => {
+                loop {
+                    use futures_util::StreamExt;
+                    if let Some(event) = pending.pop_front() {
+                        break event;
+                    }
+                    let chunk = events.next().await.expect("stream open").unwrap();
+                    pending.extend(decoder.push_bytes(&chunk));
+                }
+            };
+        }
+
+        let endpoint = next_event!();
+        assert_eq!(endpoint.event.as_deref(), Some("endpoint"));
+        let messages = format!("{}{}", base, endpoint.data);
+        // Refused before any body is read
+        assert_eq!(
+            status(
+                http.post(&messages)
+                    .bearer_auth(&token)
+                    .header("Content-Length", usize::MAX.to_string())
+            )
+            .await,
+            413
+        );
+        assert_eq!(
+            status(
+                http.post(&messages)
+                    .bearer_auth(&token)
+                    .json(&JsonRpcMessage::request(1, "tools/list", json!({})))
+            )
+            .await,
+            202
+        );
+
+        let reply: JsonRpcMessage = serde_json::from_str(&next_event!().data).unwrap();
+        let tools = reply.result.unwrap()["tools"].as_array().unwrap().len();
+        assert!(tools >= 5);
+    }
+}
@@ -11,8 +11,8 @@ use tokio::time::timeout;
 use crate::core::config::LlmConfig;
 use crate::core::error::ProviderError;
 use crate::providers::{
-    ContentPart, GenerateRequest, GenerateResponse, Role, SseDecoder, StreamEvent,
-    ToolCallNormalized, ToolChoice, ToolSpec, Usage,
+    ContentPart, GenerateRequest, GenerateResponse, ModelInfo, ResponseFormat, Role, SseDecoder,
+    StreamEvent, ToolCallAccumulator, ToolCallNormalized, ToolChoice, ToolSpec, Usage,
 };
 
 /// Anthropic Messages API adapter implementing the canonical Provider contracts
@@ -40,12 +40,8 @@ impl AnthropicProvider {
             })?
         };
 
-        let client = Client::builder()
-            .timeout(Duration::from_secs(120))
-            .build()
-            .map_err(|e| ProviderError::Network {
-                message: format!("Failed to create HTTP client: {}", e),
-            })?;
+        let client =
+            crate::providers::http::build_client(cfg.network.as_ref(), Duration::from_secs(120))?;
 
         Ok(Self {
             client,
@@ -63,6 +59,7 @@ impl AnthropicProvider {
 
     fn build_messages(&self, req: &GenerateRequest) -> Vec<JsonValue> {
         let mut out = Vec::new();
+        let mut breakpoints_left = crate::providers::ANTHROPIC_MAX_CACHE_BREAKPOINTS;
         for m in &req.messages {
             // Anthropic supports "user" and "assistant" roles
             let role = match m.role {
@@ -73,31 +70,22 @@ impl AnthropicProvider {
                     // If a message arrives with System role, downgrade to 'user' to preserve content
                     "user"
                 }
+                // Tool results go back to the model as `tool_result` blocks
+                // in a user turn
+                Role::Tool
+                    if m.content
+                        .iter()
+                        .any(|p| matches!(p, ContentPart::ToolResult { .. })) =>
+                {
+                    "user"
+                }
                 Role::Tool => {
                     // No native "tool" role in Anthropic content; treat as assistant echo
                     "assistant"
                 }
             };
-            let mut content: Vec<JsonValue> = Vec::new();
-            for part in &m.content {
-                match part {
-                    ContentPart::Text { text } => {
-                        content.push(json!({"type":"text","text": text}));
-                    }
-                    ContentPart::ImageUrl { url, mime } => {
-                        // Anthropic multi-modal: image via URL source
-                        let media_type = mime.clone().unwrap_or_else(|| "image/*".to_string());
-                        content.push(json!({
-                            "type": "image",
-                            "source": {
-                                "type": "url",
-                                "url": url,
-                                "media_type": media_type
-                            }
-                        }));
-                    }
-                }
-            }
+            let mut content =
+                crate::providers::anthropic_content(&m.content, &mut breakpoints_left);
             // If no parts provided, still send empty text node to be safe
             if content.is_empty() {
                 content.push(json!({"type":"text","text": ""}));
@@ -137,6 +125,60 @@ impl AnthropicProvider {
         }
     }
 
+    /// Anthropic has no response_format; a schema becomes a tool the model
+    /// is forced to call, and plain JSON mode becomes a system instruction
+    fn apply_response_format(payload: &mut JsonValue, format: &Option<ResponseFormat>) {
+        let Some(obj) = payload.as_object_mut() else {
+            return;
+        };
+        match format {
+            Some(ResponseFormat::JsonSchema { json_schema }) => {
+                let tool = json!({
+                    "name": json_schema.name,
+                    "description": "Return the result as structured data",
+                    "input_schema": json_schema.schema
+                });
+                match obj.get_mut("tools").and_then(|t| t.as_array_mut()) {
+                    Some(tools) => tools.push(tool),
+                    None => {
+                        obj.insert("tools".to_string(), json!([tool]));
+                    }
+                }
+                obj.insert(
+                    "tool_choice".to_string(),
+                    json!({"type": "tool", "name": json_schema.name}),
+                );
+            }
+            Some(ResponseFormat::JsonObject) => {
+                let instruction = "Respond with a single JSON object and nothing else.";
+                let system = match obj.get("system").and_then(|s| s.as_str()) {
+                    Some(sys) => format!("{}\n\n{}", sys, instruction),
+                    None => instruction.to_string(),
+                };
+                obj.insert("system".to_string(), JsonValue::String(system));
+            }
+            None => {}
+        }
+    }
+
+    /// Turn the forced schema tool call back into the response text
+    fn structured_output(
+        format: &Option<ResponseFormat>,
+        mut response: GenerateResponse,
+    ) -> GenerateResponse {
+        if let Some(ResponseFormat::JsonSchema { json_schema }) = format {
+            if let Some(pos) = response
+                .tool_calls
+                .iter()
+                .position(|tc| tc.name == json_schema.name)
+            {
+                let call = response.tool_calls.remove(pos);
+                response.text = call.arguments_json.to_string();
+            }
+        }
+        response
+    }
+
     fn apply_headers(
         &self,
         mut rb: reqwest::RequestBuilder,
@@ -155,20 +197,11 @@ impl AnthropicProvider {
                 rb = rb.header(k, v);
             }
         }
-        if let Some(meta) = &req.metadata {
-            for (k, v) in meta {
-                // do not clobber auth/version headers
-                if k.eq_ignore_ascii_case("x-api-key")
-                    || k.eq_ignore_ascii_case("anthropic-version")
-                    || k.eq_ignore_ascii_case("content-type")
-                    || k.eq_ignore_ascii_case("accept")
-                {
-                    continue;
-                }
-                rb = rb.header(k, v);
-            }
-        }
-        rb
+        crate::providers::metadata::apply_headers(
+            rb,
+            req.metadata.as_ref(),
+            &["x-api-key", "anthropic-version", "content-type", "accept"],
+        )
     }
 
     fn map_http_error(status: u16, body: String) -> ProviderError {
@@ -196,6 +229,10 @@ impl AnthropicProvider {
                 message: "Anthropic server error".to_string(),
                 status: Some(status),
             }
+        } else if let Some(err) =
+            crate::providers::capabilities::parse_context_length_error(status, &body)
+        {
+            err
         } else if lower.contains("unsupported parameter") || lower.contains("invalid") {
             ProviderError::InvalidParams {
                 details: Some(body),
@@ -213,6 +250,11 @@ impl AnthropicProvider {
         }
     }
 
+    /// Content block index of a streaming event (0 when absent)
+    fn block_index(v: &JsonValue) -> usize {
+        v.get("index").and_then(|x| x.as_u64()).unwrap_or(0) as usize
+    }
+
     fn parse_usage(v: &JsonValue) -> Option<Usage> {
         let prompt_tokens = v
             .get("input_tokens")
@@ -226,12 +268,43 @@ impl AnthropicProvider {
             (Some(p), Some(c)) => Some(p + c),
             _ => None,
         };
+        // `input_tokens` excludes cached prompt tokens; they are reported apart
+        let cache_read_tokens = v
+            .get("cache_read_input_tokens")
+            .and_then(|x| x.as_u64())
+            .map(|x| x as u32);
+        let cache_write_tokens = v
+            .get("cache_creation_input_tokens")
+            .and_then(|x| x.as_u64())
+            .map(|x| x as u32);
         Some(Usage {
             prompt_tokens,
             completion_tokens,
             total_tokens,
+            retries: None,
+            cache_read_tokens,
+            cache_write_tokens,
         })
     }
+
+    /// Streams report input (and cache) usage in `message_start` and output
+    /// usage in `message_delta`; later counters win
+    fn merge_usage(base: Option<Usage>, update: Usage) -> Usage {
+        let base = base.unwrap_or_default();
+        let prompt_tokens = update.prompt_tokens.or(base.prompt_tokens);
+        let completion_tokens = update.completion_tokens.or(base.completion_tokens);
+        Usage {
+            prompt_tokens,
+            completion_tokens,
+            total_tokens: match (prompt_tokens, completion_tokens) {
+                (Some(p), Some(c)) => Some(p + c),
+                _ => None,
+            },
+            retries: None,
+            cache_read_tokens: update.cache_read_tokens.or(base.cache_read_tokens),
+            cache_write_tokens: update.cache_write_tokens.or(base.cache_write_tokens),
+        }
+    }
 }
 
 #[async_trait]
@@ -283,6 +356,7 @@ impl crate::providers::Provider for AnthropicProvider {
                 obj.insert("tool_choice".to_string(), tc);
             }
         }
+        Self::apply_response_format(&mut payload, &req.response_format);
 
         // Send
         let rb = self.client.post(&url);
@@ -296,12 +370,13 @@ impl crate::providers::Provider for AnthropicProvider {
             })?;
 
         let status = resp.status().as_u16();
+        let retry_after = crate::providers::retry::retry_after_ms(resp.headers());
         let text = resp.text().await.map_err(|e| ProviderError::Network {
             message: format!("Failed reading Anthropic response: {}", e),
         })?;
 
         if !(200..300).contains(&status) {
-            return Err(Self::map_http_error(status, text));
+            return Err(Self::map_http_error(status, text).with_retry_after(retry_after));
         }
 
         // Parse JSON
@@ -311,6 +386,7 @@ impl crate::providers::Provider for AnthropicProvider {
 
         // Extract content text and tool calls
         let mut out_text = String::new();
+        let mut reasoning = String::new();
         let mut tool_calls: Vec<ToolCallNormalized> = Vec::new();
 
         if let Some(arr) = v.get("content").and_then(|x| x.as_array()) {
@@ -322,6 +398,11 @@ impl crate::providers::Provider for AnthropicProvider {
                                 out_text.push_str(s);
                             }
                         }
+                        "thinking" => {
+                            if let Some(s) = block.get("thinking").and_then(|x| x.as_str()) {
+                                reasoning.push_str(s);
+                            }
+                        }
                         "tool_use" => {
                             let id = block
                                 .get("id")
@@ -352,12 +433,43 @@ impl crate::providers::Provider for AnthropicProvider {
                 .and_then(Self::parse_usage)
         });
 
-        Ok(GenerateResponse {
+        let response = GenerateResponse {
             text: out_text,
             tool_calls,
             usage,
             raw: Some(v),
-        })
+            provider: None,
+            reasoning: (!reasoning.is_empty()).then_some(reasoning),
+            logprobs: None,
+        };
+        Self::structured_output(&req.response_format, response).non_empty("Anthropic")
+    }
+
+    fn supports_stream_resume(&self) -> bool {
+        true
+    }
+
+    async fn list_models(&self) -> Result<Vec<ModelInfo>, ProviderError> {
+        let url = format!("{}/models?limit=1000", self.api_base.trim_end_matches('/'));
+        let rb = self
+            .client
+            .get(url)
+            .header("x-api-key", &self.api_key)
+            .header("anthropic-version", "2023-06-01");
+        let v = crate::providers::get_json(rb, "Anthropic", Self::map_http_error).await?;
+        Ok(v.get("data")
+            .and_then(|d| d.as_array())
+            .map(|models| {
+                models
+                    .iter()
+                    .filter_map(|m| m.get("id").and_then(|id| id.as_str()))
+                    .map(|id| ModelInfo {
+                        id: id.to_string(),
+                        context_length: None,
+                    })
+                    .collect()
+            })
+            .unwrap_or_default())
     }
 
     async fn generate_streaming(
@@ -407,6 +519,7 @@ impl crate::providers::Provider for AnthropicProvider {
                 obj.insert("tool_choice".to_string(), tc);
             }
         }
+        Self::apply_response_format(&mut payload, &req.response_format);
 
         // Send request
         let rb = self.client.post(&url);
@@ -420,18 +533,22 @@ impl crate::providers::Provider for AnthropicProvider {
             })?;
 
         let status = resp.status().as_u16();
+        let retry_after = crate::providers::retry::retry_after_ms(resp.headers());
         if !(200..300).contains(&status) {
             let body = resp
                 .text()
                 .await
                 .unwrap_or_else(|e| format!("Could not read error body: {}", e));
-            return Err(Self::map_http_error(status, body));
+            return Err(Self::map_http_error(status, body).with_retry_after(retry_after));
         }
 
         let mut stream = resp.bytes_stream();
         let mut content = String::new();
+        let mut reasoning = String::new();
         let mut decoder = SseDecoder::new();
         let mut tool_calls: Vec<ToolCallNormalized> = Vec::new();
+        let mut pending_tools = ToolCallAccumulator::new();
+        let mut usage: Option<Usage> = None;
 
         let first_timeout = self.first_token_timeout_ms;
         let stall_timeout = self.stall_timeout_ms;
@@ -443,84 +560,14 @@ impl crate::providers::Provider for AnthropicProvider {
             } else {
                 first_timeout
             };
-            let next = timeout(Duration::from_millis(cur), stream.next()).await;
-            match next {
-                Ok(Some(Ok(bytes))) => {
-                    let s = String::from_utf8_lossy(&bytes);
-                    for data_line in decoder.push_chunk(&s) {
-                        // Each data_line is a JSON object per Anthropic SSE
-                        if let Ok(v) = serde_json::from_str::<JsonValue>(&data_line) {
-                            if let Some(t) = v.get("type").and_then(|x| x.as_str()) {
-                                match t {
-                                    "content_block_delta" => {
-                                        // text delta appears as delta.text (type can be "text_delta")
-                                        if let Some(delta) = v.get("delta") {
-                                            if let Some(txt) =
-                                                delta.get("text").and_then(|x| x.as_str())
-                                            {
-                                                content.push_str(txt);
-                                                on_event(StreamEvent::TextDelta(txt.to_string()));
-                                                got_first = true;
-                                            }
-                                        }
-                                    }
-                                    "content_block_start" => {
-                                        // tool_use start may include id, name, input (possibly empty)
-                                        if let Some(cb) = v.get("content_block") {
-                                            if cb
-                                                .get("type")
-                                                .and_then(|x| x.as_str())
-                                                .is_some_and(|k| k == "tool_use")
-                                            {
-                                                let id = cb
-                                                    .get("id")
-                                                    .and_then(|x| x.as_str())
-                                                    .map(|s| s.to_string());
-                                                let name = cb
-                                                    .get("name")
-                                                    .and_then(|x| x.as_str())
-                                                    .unwrap_or("tool")
-                                                    .to_string();
-                                                let args = cb
-                                                    .get("input")
-                                                    .cloned()
-                                                    .unwrap_or_else(|| json!({}));
-                                                let tc = ToolCallNormalized {
-                                                    id,
-                                                    name,
-                                                    arguments_json: args,
-                                                };
-                                                on_event(StreamEvent::ToolCall(tc.clone()));
-                                                tool_calls.push(tc);
-                                            }
-                                        }
-                                    }
-                                    "message_stop" => {
-                                        on_event(StreamEvent::Finished);
-                                    }
-                                    "message_delta" => {
-                                        if let Some(u) = v.get("usage") {
-                                            if let Some(usage) = Self::parse_usage(u) {
-                                                on_event(StreamEvent::Usage(usage));
-                                            }
-                                        }
-                                    }
-                                    _ => {
-                                        debug!("Unhandled Anthropic SSE event type: {}", t);
-                                    }
-                                }
-                            }
-                        } else {
-                            warn!("Failed to parse Anthropic SSE data line");
-                        }
-                    }
-                }
+            let (events, ended) = match timeout(Duration::from_millis(cur), stream.next()).await {
+                Ok(Some(Ok(bytes))) => (decoder.push_bytes(&bytes), false),
                 Ok(Some(Err(e))) => {
                     return Err(ProviderError::Network {
                         message: format!("Error reading streaming response: {}", e),
                     });
                 }
-                Ok(None) => break,
+                Ok(None) => (decoder.finish().into_iter().collect(), true),
                 Err(_) => {
                     // Timeout
                     if !got_first {
@@ -529,14 +576,124 @@ impl crate::providers::Provider for AnthropicProvider {
                         return Err(ProviderError::TimeoutStall { timeout_ms: cur });
                     }
                 }
+            };
+            for event in events.into_iter().filter(|e| !e.is_done()) {
+                let data_line = event.data;
+                // Each data_line is a JSON object per Anthropic SSE
+                if let Ok(v) = serde_json::from_str::<JsonValue>(&data_line) {
+                    if let Some(t) = v.get("type").and_then(|x| x.as_str()) {
+                        match t {
+                            "content_block_delta" => {
+                                let index = Self::block_index(&v);
+                                if let Some(delta) = v.get("delta") {
+                                    // text delta appears as delta.text (type "text_delta")
+                                    if let Some(txt) = delta.get("text").and_then(|x| x.as_str()) {
+                                        content.push_str(txt);
+                                        on_event(StreamEvent::TextDelta(txt.to_string()));
+                                        got_first = true;
+                                    }
+                                    // extended thinking streams as delta.thinking
+                                    if let Some(thought) =
+                                        delta.get("thinking").and_then(|x| x.as_str())
+                                    {
+                                        reasoning.push_str(thought);
+                                        on_event(StreamEvent::ReasoningDelta(thought.to_string()));
+                                        got_first = true;
+                                    }
+                                    // tool arguments stream as delta.partial_json
+                                    if let Some(partial) =
+                                        delta.get("partial_json").and_then(|x| x.as_str())
+                                    {
+                                        pending_tools.push_arguments(index, partial);
+                                        on_event(StreamEvent::ToolDelta(partial.to_string()));
+                                        got_first = true;
+                                    }
+                                }
+                            }
+                            "content_block_start" => {
+                                // tool_use start carries id and name; input follows as deltas
+                                if let Some(cb) = v.get("content_block") {
+                                    if cb
+                                        .get("type")
+                                        .and_then(|x| x.as_str())
+                                        .is_some_and(|k| k == "tool_use")
+                                    {
+                                        let id = cb
+                                            .get("id")
+                                            .and_then(|x| x.as_str())
+                                            .map(|s| s.to_string());
+                                        let name = cb
+                                            .get("name")
+                                            .and_then(|x| x.as_str())
+                                            .unwrap_or("tool")
+                                            .to_string();
+                                        pending_tools.start(
+                                            Self::block_index(&v),
+                                            id,
+                                            name,
+                                            cb.get("input").cloned(),
+                                        );
+                                        got_first = true;
+                                    }
+                                }
+                            }
+                            "content_block_stop" => {
+                                if let Some(tc) = pending_tools.finish(Self::block_index(&v)) {
+                                    on_event(StreamEvent::ToolCall(tc.clone()));
+                                    tool_calls.push(tc);
+                                }
+                            }
+                            "message_stop" => {
+                                for tc in pending_tools.finish_all() {
+                                    on_event(StreamEvent::ToolCall(tc.clone()));
+                                    tool_calls.push(tc);
+                                }
+                                on_event(StreamEvent::Finished);
+                            }
+                            "message_start" => {
+                                if let Some(u) = v
+                                    .get("message")
+                                    .and_then(|m| m.get("usage"))
+                                    .and_then(Self::parse_usage)
+                                {
+                                    usage = Some(Self::merge_usage(usage.take(), u));
+                                }
+                            }
+                            "message_delta" => {
+                                if let Some(u) = v.get("usage").and_then(Self::parse_usage) {
+                                    let merged = Self::merge_usage(usage.take(), u);
+                                    on_event(StreamEvent::Usage(merged.clone()));
+                                    usage = Some(merged);
+                                }
+                            }
+                            _ => {
+                                debug!("Unhandled Anthropic SSE event type: {}", t);
+                            }
+                        }
+                    }
+                } else {
+                    warn!("Failed to parse Anthropic SSE data line");
+                }
+            }
+            if ended {
+                // Calls left open by a truncated stream are still surfaced
+                for tc in pending_tools.finish_all() {
+                    on_event(StreamEvent::ToolCall(tc.clone()));
+                    tool_calls.push(tc);
+                }
+                break;
             }
         }
 
-        Ok(GenerateResponse {
+        let response = GenerateResponse {
             text: content,
             tool_calls,
-            usage: None,
+            usage,
             raw: None,
-        })
+            provider: None,
+            reasoning: (!reasoning.is_empty()).then_some(reasoning),
+            logprobs: None,
+        };
+        Ok(Self::structured_output(&req.response_format, response))
     }
 }
@@ -0,0 +1,364 @@
+// File: src/providers/azure_openai.rs
+use async_trait::async_trait;
+use futures_util::StreamExt;
+use log::debug;
+use reqwest::Client;
+use serde_json::{json, Value as JsonValue};
+use std::collections::HashMap;
+use std::time::Duration;
+use tokio::time::timeout;
+
+use crate::core::config::LlmConfig;
+use crate::core::error::ProviderError;
+use crate::providers::openrouter::OpenRouterProvider as OpenAiChat;
+use crate::providers::{
+    GenerateRequest, GenerateResponse, SseDecoder, StreamEvent, ToolCallAccumulator,
+};
+
+/// `api-version` used when none is configured
+pub const DEFAULT_AZURE_API_VERSION: &str = "2024-10-21";
+
+/// Azure OpenAI adapter.
+///
+/// Speaks the OpenAI chat-completions wire format, but requests are routed to
+/// a named deployment on the resource endpoint
+/// (`{api_base}/openai/deployments/{deployment}/chat/completions`), carry an
+/// `api-version` query parameter, and authenticate with the `api-key` header
+/// instead of a Bearer token.
+pub struct AzureOpenAiProvider {
+    client: Client,
+    api_key: String,
+    endpoint: String,
+    deployment: String,
+    api_version: String,
+    headers: Option<HashMap<String, String>>,
+    first_token_timeout_ms: u64,
+    stall_timeout_ms: u64,
+}
+
+impl AzureOpenAiProvider {
+    /// Create an Azure OpenAI provider from LlmConfig
+    ///
+    /// Falls back to `AZURE_OPENAI_API_KEY` / `AZURE_OPENAI_ENDPOINT` when the
+    /// key or `api_base` is not configured; the deployment defaults to `model`.
+    pub fn from_config(cfg: &LlmConfig) -> Result<Self, ProviderError> {
+        let api_key = if !cfg.api_key.is_empty() {
+            cfg.api_key.clone()
+        } else {
+            std::env::var("AZURE_OPENAI_API_KEY").map_err(|_| ProviderError::Auth {
+                details: None,
+                code: None,
+                message: "Missing AZURE_OPENAI_API_KEY".to_string(),
+                status: None,
+            })?
+        };
+
+        let endpoint = match &cfg.api_base {
+            Some(base) => base.clone(),
+            None => std::env::var("AZURE_OPENAI_ENDPOINT").map_err(|_| {
+                ProviderError::InvalidParams {
+                    details: None,
+                    code: None,
+                    message: "Azure OpenAI requires api_base (or AZURE_OPENAI_ENDPOINT)"
+                        .to_string(),
+                    status: None,
+                }
+            })?,
+        };
+
+        let client =
+            crate::providers::http::build_client(cfg.network.as_ref(), Duration::from_secs(120))?;
+
+        Ok(Self {
+            client,
+            api_key,
+            endpoint,
+            deployment: cfg.deployment.clone().unwrap_or_else(|| cfg.model.clone()),
+            api_version: cfg
+                .api_version
+                .clone()
+                .unwrap_or_else(|| DEFAULT_AZURE_API_VERSION.to_string()),
+            headers: cfg.headers.clone(),
+            first_token_timeout_ms: cfg.first_token_timeout_ms.unwrap_or(30_000),
+            stall_timeout_ms: cfg.stall_timeout_ms.unwrap_or(10_000),
+        })
+    }
+
+    fn chat_url(&self) -> String {
+        format!(
+            "{}/openai/deployments/{}/chat/completions?api-version={}",
+            self.endpoint.trim_end_matches('/'),
+            self.deployment,
+            self.api_version
+        )
+    }
+
+    /// OpenAI-style payload; the deployment, not a `model` field, picks the model
+    fn build_payload(req: &GenerateRequest, stream: bool) -> JsonValue {
+        let mut payload = json!({
+            "messages": OpenAiChat::map_messages(req),
+            "max_tokens": req.max_output_tokens.unwrap_or(1024),
+            "temperature": req.temperature.unwrap_or(0.7),
+        });
+
+        if let Some(obj) = payload.as_object_mut() {
+            if stream {
+                obj.insert("stream".to_string(), json!(true));
+            }
+            if let Some(tp) = req.top_p {
+                obj.insert("top_p".to_string(), json!(tp));
+            }
+            if let Some(stops) = &req.stop {
+                obj.insert("stop".to_string(), json!(stops));
+            }
+            if let Some(seed) = req.seed {
+                obj.insert("seed".to_string(), json!(seed));
+            }
+            if let Some(t) = OpenAiChat::map_tools_openai(&req.tools) {
+                obj.insert("tools".to_string(), json!(t));
+            }
+            if let Some(tc) = OpenAiChat::map_tool_choice_openai(&req.tool_choice) {
+                obj.insert("tool_choice".to_string(), tc);
+            }
+            if let Some(rf) = OpenAiChat::map_response_format(&req.response_format) {
+                obj.insert("response_format".to_string(), rf);
+            }
+        }
+        crate::providers::apply_openai_logprobs(&mut payload, req.logprobs);
+        payload
+    }
+
+    fn apply_headers(
+        &self,
+        mut rb: reqwest::RequestBuilder,
+        req: &GenerateRequest,
+        sse: bool,
+    ) -> reqwest::RequestBuilder {
+        rb = rb
+            .header("api-key", &self.api_key)
+            .header("Content-Type", "application/json");
+        if sse {
+            rb = rb.header("Accept", "text/event-stream");
+        }
+        if let Some(h) = &self.headers {
+            for (k, v) in h {
+                rb = rb.header(k, v);
+            }
+        }
+        crate::providers::metadata::apply_headers(
+            rb,
+            req.metadata.as_ref(),
+            &["api-key", "authorization", "content-type", "accept"],
+        )
+    }
+
+    fn map_http_error(status: u16, body: String) -> ProviderError {
+        if status == 401 || status == 403 {
+            ProviderError::Auth {
+                details: Some(body),
+                code: None,
+                message: "Authentication failed for Azure OpenAI".to_string(),
+                status: Some(status),
+            }
+        } else if status == 404 {
+            ProviderError::ModelUnavailable {
+                details: Some(body),
+                code: None,
+                message: "Azure OpenAI deployment not found".to_string(),
+                status: Some(status),
+            }
+        } else if status == 429 {
+            ProviderError::RateLimited {
+                details: Some(body),
+                code: None,
+                message: "Rate limited by Azure OpenAI".to_string(),
+                status: Some(status),
+                retry_after_ms: None,
+            }
+        } else if status >= 500 {
+            ProviderError::ServerError {
+                details: Some(body),
+                code: None,
+                message: "Azure OpenAI server error".to_string(),
+                status: Some(status),
+            }
+        } else if let Some(err) =
+            crate::providers::capabilities::parse_context_length_error(status, &body)
+        {
+            err
+        } else {
+            ProviderError::InvalidParams {
+                details: Some(body),
+                code: None,
+                message: "Invalid parameters for Azure OpenAI".to_string(),
+                status: Some(status),
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl crate::providers::Provider for AzureOpenAiProvider {
+    async fn generate(&self, req: GenerateRequest) -> Result<GenerateResponse, ProviderError> {
+        let payload = Self::build_payload(&req, false);
+
+        let rb = self.client.post(self.chat_url());
+        let rb = self.apply_headers(rb, &req, false);
+        let resp = rb
+            .json(&payload)
+            .send()
+            .await
+            .map_err(|e| ProviderError::Network {
+                message: format!("Azure OpenAI network error: {}", e),
+            })?;
+
+        let status = resp.status().as_u16();
+        let retry_after = crate::providers::retry::retry_after_ms(resp.headers());
+        let text = resp.text().await.map_err(|e| ProviderError::Network {
+            message: format!("Failed reading Azure OpenAI response: {}", e),
+        })?;
+
+        if !(200..300).contains(&status) {
+            return Err(Self::map_http_error(status, text).with_retry_after(retry_after));
+        }
+
+        let v: JsonValue = serde_json::from_str(&text).map_err(|e| ProviderError::Network {
+            message: format!("Invalid JSON from Azure OpenAI: {}", e),
+        })?;
+
+        let choice = v.get("choices").and_then(|c| c.get(0));
+        let content = choice
+            .and_then(|c| c.get("message"))
+            .and_then(|m| m.get("content"))
+            .and_then(|x| x.as_str())
+            .unwrap_or("")
+            .to_string();
+        let tool_calls = choice
+            .map(OpenAiChat::normalize_tool_calls)
+            .unwrap_or_default();
+        let usage = v.get("usage").and_then(OpenAiChat::parse_usage_openai);
+        let logprobs = choice.and_then(crate::providers::parse_openai_logprobs);
+
+        GenerateResponse {
+            text: content,
+            tool_calls,
+            usage,
+            raw: Some(v),
+            provider: None,
+            reasoning: None,
+            logprobs,
+        }
+        .non_empty("Azure OpenAI")
+    }
+
+    fn supports_stream_resume(&self) -> bool {
+        true
+    }
+
+    async fn generate_streaming(
+        &self,
+        req: GenerateRequest,
+        on_event: &mut (dyn FnMut(StreamEvent) + Send),
+    ) -> Result<GenerateResponse, ProviderError> {
+        let payload = Self::build_payload(&req, true);
+
+        let rb = self.client.post(self.chat_url());
+        let rb = self.apply_headers(rb, &req, true);
+        let resp = rb
+            .json(&payload)
+            .send()
+            .await
+            .map_err(|e| ProviderError::Network {
+                message: format!("Azure OpenAI network error: {}", e),
+            })?;
+
+        let status = resp.status().as_u16();
+        let retry_after = crate::providers::retry::retry_after_ms(resp.headers());
+        if !(200..300).contains(&status) {
+            let body = resp
+                .text()
+                .await
+                .unwrap_or_else(|e| format!("Could not read error body: {}", e));
+            return Err(Self::map_http_error(status, body).with_retry_after(retry_after));
+        }
+
+        let mut stream = resp.bytes_stream();
+        let mut content = String::new();
+        let mut decoder = SseDecoder::new();
+        let mut pending_tools = ToolCallAccumulator::new();
+        let mut tool_calls = Vec::new();
+        let mut logprobs = Vec::new();
+
+        let first_timeout = self.first_token_timeout_ms;
+        let stall_timeout = self.stall_timeout_ms;
+        let mut got_first = false;
+
+        loop {
+            let cur = if got_first {
+                stall_timeout
+            } else {
+                first_timeout
+            };
+            let (events, ended) = match timeout(Duration::from_millis(cur), stream.next()).await {
+                Ok(Some(Ok(chunk))) => (decoder.push_bytes(&chunk), false),
+                Ok(Some(Err(e))) => {
+                    return Err(ProviderError::Network {
+                        message: format!("Error reading streaming response: {}", e),
+                    });
+                }
+                Ok(None) => (decoder.finish().into_iter().collect(), true),
+                Err(_) => {
+                    if !got_first {
+                        return Err(ProviderError::TimeoutFirstToken { timeout_ms: cur });
+                    } else {
+                        return Err(ProviderError::TimeoutStall { timeout_ms: cur });
+                    }
+                }
+            };
+            for event in events.into_iter().filter(|e| !e.is_done()) {
+                let data_line = event.data;
+                if let Some(ev) = crate::providers::parse_openai_chat_sse(&data_line) {
+                    if let StreamEvent::TextDelta(d) = &ev {
+                        content.push_str(d);
+                        got_first = true;
+                    }
+                    on_event(ev);
+                } else {
+                    // Azure sends prompt-filter annotations before the first delta
+                    debug!("Unhandled Azure OpenAI SSE line: {}", data_line);
+                }
+                if let Some(tokens) = crate::providers::parse_openai_chat_logprobs_sse(&data_line) {
+                    logprobs.extend(tokens);
+                }
+                for ev in pending_tools.push_openai_chat_sse(&data_line) {
+                    got_first = true;
+                    on_event(ev);
+                }
+                if crate::providers::is_openai_chat_finish(&data_line) {
+                    for tc in pending_tools.finish_all() {
+                        on_event(StreamEvent::ToolCall(tc.clone()));
+                        tool_calls.push(tc);
+                    }
+                    on_event(StreamEvent::Finished);
+                }
+            }
+            if ended {
+                for tc in pending_tools.finish_all() {
+                    on_event(StreamEvent::ToolCall(tc.clone()));
+                    tool_calls.push(tc);
+                }
+                break;
+            }
+        }
+
+        Ok(GenerateResponse {
+            text: content,
+            tool_calls,
+            usage: None,
+            raw: None,
+            provider: None,
+            reasoning: None,
+            logprobs: (!logprobs.is_empty()).then_some(logprobs),
+        })
+    }
+}
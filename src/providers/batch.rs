@@ -0,0 +1,394 @@
+//! Bulk generation for many independent requests.
+//!
+//! `Provider::generate_batch` answers a list of requests in order. Providers
+//! without a native batch endpoint fan the requests out concurrently with
+//! `generate_concurrently`, bounded by a concurrency limit. `OpenAiBatch`
+//! drives the OpenAI Batch API instead: the requests are uploaded as one
+//! JSONL file, processed asynchronously at a discount, and the results are
+//! downloaded once the batch finishes. That suits bulk work such as scoring
+//! many candidate goals, where latency matters less than cost.
+
+use futures_util::{stream, StreamExt};
+use log::{debug, info};
+use reqwest::Client;
+use serde_json::{json, Value as JsonValue};
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use crate::core::error::ProviderError;
+use crate::providers::{GenerateRequest, GenerateResponse, Provider};
+
+/// Requests in flight at once when a batch is fanned out
+pub const DEFAULT_BATCH_CONCURRENCY: usize = 4;
+
+/// Answer `reqs` with `provider.generate`, at most `limit` at a time
+///
+/// Results are returned in request order.
+pub async fn generate_concurrently<P: Provider + ?Sized>(
+    provider: &P,
+    reqs: Vec<GenerateRequest>,
+    limit: usize,
+) -> Vec<Result<GenerateResponse, ProviderError>> {
+    stream::iter(reqs)
+        .map(|req| provider.generate(req))
+        .buffered(limit.max(1))
+        .collect()
+        .await
+}
+
+/// Batch statuses after which the batch makes no further progress
+const TERMINAL_STATUSES: &[&str] = &["completed", "failed", "expired", "cancelled"];
+
+/// Client for the OpenAI Batch API (`/files` and `/batches`)
+pub struct OpenAiBatch {
+    client: Client,
+    api_base: String,
+    api_key: String,
+    poll_interval: Duration,
+    max_wait: Duration,
+}
+
+impl OpenAiBatch {
+    pub fn new(client: Client, api_base: &str, api_key: &str) -> Self {
+        Self {
+            client,
+            api_base: api_base.trim_end_matches('/').to_string(),
+            api_key: api_key.to_string(),
+            poll_interval: Duration::from_secs(30),
+            // The completion window is 24h; allow a little slack on top
+            max_wait: Duration::from_secs(25 * 60 * 60),
+        }
+    }
+
+    /// How often the batch status is checked
+    pub fn with_poll_interval(mut self, poll_interval: Duration) -> Self {
+        self.poll_interval = poll_interval;
+        self
+    }
+
+    /// How long to wait for the batch before giving up
+    pub fn with_max_wait(mut self, max_wait: Duration) -> Self {
+        self.max_wait = max_wait;
+        self
+    }
+
+    fn url(&self, path: &str) -> String {
+        format!("{}/{}", self.api_base, path)
+    }
+
+    /// Run `bodies` (`/chat/completions` payloads) as one batch
+    ///
+    /// Returns one result per body, in order: the completion JSON, or the
+    /// error the batch reported for that request (mapped with `map_error`).
+    /// Failures of the batch itself (upload, creation, expiry) are returned
+    /// as the outer error.
+    pub async fn run(
+        &self,
+        bodies: Vec<JsonValue>,
+        map_error: impl Fn(u16, String) -> ProviderError,
+    ) -> Result<Vec<Result<JsonValue, ProviderError>>, ProviderError> {
+        let count = bodies.len();
+        if count == 0 {
+            return Ok(Vec::new());
+        }
+
+        let input_file_id = self.upload(&bodies, &map_error).await?;
+        let created = self
+            .send_json(
+                self.client.post(self.url("batches")).json(&json!({
+                    "input_file_id": input_file_id,
+                    "endpoint": "/v1/chat/completions",
+                    "completion_window": "24h",
+                })),
+                &map_error,
+            )
+            .await?;
+        let batch_id = Self::str_field(&created, "id")?;
+        info!("Submitted batch {} with {} requests", batch_id, count);
+
+        let batch = self.wait(&batch_id, &map_error).await?;
+        let status = batch.get("status").and_then(|s| s.as_str()).unwrap_or("");
+        let mut results: HashMap<String, Result<JsonValue, ProviderError>> = HashMap::new();
+        for key in ["output_file_id", "error_file_id"] {
+            if let Some(file_id) = batch.get(key).and_then(|f| f.as_str()) {
+                let content = self.download(file_id, &map_error).await?;
+                for line in content.lines().filter(|l| !l.trim().is_empty()) {
+                    if let Some((id, result)) = Self::parse_result_line(line, &map_error) {
+                        results.insert(id, result);
+                    }
+                }
+            }
+        }
+        if results.is_empty() && status != "completed" {
+            return Err(ProviderError::ProviderOutage {
+                details: batch.get("errors").map(|e| e.to_string()),
+                code: None,
+                message: format!("Batch {} ended with status '{}'", batch_id, status),
+                status: None,
+            });
+        }
+
+        Ok((0..count)
+            .map(|i| {
+                results.remove(&Self::custom_id(i)).unwrap_or_else(|| {
+                    Err(ProviderError::ProviderOutage {
+                        details: None,
+                        code: None,
+                        message: format!(
+                            "Batch {} (status '{}') returned no result for request {}",
+                            batch_id, status, i
+                        ),
+                        status: None,
+                    })
+                })
+            })
+            .collect())
+    }
+
+    fn custom_id(index: usize) -> String {
+        format!("request-{}", index)
+    }
+
+    /// Upload the requests as a JSONL file; returns the file id
+    async fn upload(
+        &self,
+        bodies: &[JsonValue],
+        map_error: &impl Fn(u16, String) -> ProviderError,
+    ) -> Result<String, ProviderError> {
+        let mut jsonl = String::new();
+        for (i, body) in bodies.iter().enumerate() {
+            let line = json!({
+                "custom_id": Self::custom_id(i),
+                "method": "POST",
+                "url": "/v1/chat/completions",
+                "body": body,
+            });
+            jsonl.push_str(&line.to_string());
+            jsonl.push('\n');
+        }
+
+        // reqwest is built without its multipart feature; the form is small
+        // enough to assemble by hand
+        let boundary = format!("borg-batch-{}", uuid::Uuid::new_v4().simple());
+        let form = format!(
+            "--{b}\r\nContent-Disposition: form-data; name=\"purpose\"\r\n\r\nbatch\r\n\
+             --{b}\r\nContent-Disposition: form-data; name=\"file\"; filename=\"batch.jsonl\"\r\n\
+             Content-Type: application/jsonl\r\n\r\n{jsonl}\r\n--{b}--\r\n",
+            b = boundary,
+            jsonl = jsonl
+        );
+        let uploaded = self
+            .send_json(
+                self.client
+                    .post(self.url("files"))
+                    .header(
+                        "Content-Type",
+                        format!("multipart/form-data; boundary={}", boundary),
+                    )
+                    .body(form),
+                map_error,
+            )
+            .await?;
+        Self::str_field(&uploaded, "id")
+    }
+
+    /// Poll the batch until it reaches a terminal status
+    async fn wait(
+        &self,
+        batch_id: &str,
+        map_error: &impl Fn(u16, String) -> ProviderError,
+    ) -> Result<JsonValue, ProviderError> {
+        let started = Instant::now();
+        loop {
+            let batch = self
+                .send_json(
+                    self.client.get(self.url(&format!("batches/{}", batch_id))),
+                    map_error,
+                )
+                .await?;
+            let status = batch.get("status").and_then(|s| s.as_str()).unwrap_or("");
+            if TERMINAL_STATUSES.contains(&status) {
+                return Ok(batch);
+            }
+            if started.elapsed() >= self.max_wait {
+                return Err(ProviderError::ProviderOutage {
+                    details: None,
+                    code: None,
+                    message: format!(
+                        "Batch {} still '{}' after {}s",
+                        batch_id,
+                        status,
+                        started.elapsed().as_secs()
+                    ),
+                    status: None,
+                });
+            }
+            debug!("Batch {} is '{}'; polling again", batch_id, status);
+            tokio::time::sleep(self.poll_interval).await;
+        }
+    }
+
+    async fn download(
+        &self,
+        file_id: &str,
+        map_error: &impl Fn(u16, String) -> ProviderError,
+    ) -> Result<String, ProviderError> {
+        let rb = self
+            .client
+            .get(self.url(&format!("files/{}/content", file_id)))
+            .bearer_auth(&self.api_key);
+        let resp = rb.send().await.map_err(|e| ProviderError::Network {
+            message: format!("Batch network error: {}", e),
+        })?;
+        let status = resp.status().as_u16();
+        let text = resp.text().await.map_err(|e| ProviderError::Network {
+            message: format!("Failed reading batch results: {}", e),
+        })?;
+        if !(200..300).contains(&status) {
+            return Err(map_error(status, text));
+        }
+        Ok(text)
+    }
+
+    async fn send_json(
+        &self,
+        rb: reqwest::RequestBuilder,
+        map_error: &impl Fn(u16, String) -> ProviderError,
+    ) -> Result<JsonValue, ProviderError> {
+        crate::providers::get_json(rb.bearer_auth(&self.api_key), "OpenAI Batch", |s, b| {
+            map_error(s, b)
+        })
+        .await
+    }
+
+    fn str_field(v: &JsonValue, key: &str) -> Result<String, ProviderError> {
+        v.get(key)
+            .and_then(|x| x.as_str())
+            .map(|s| s.to_string())
+            .ok_or_else(|| ProviderError::Network {
+                message: format!("Batch API response is missing '{}'", key),
+            })
+    }
+
+    /// One line of an output or error file: the request's custom id and its
+    /// completion body or error
+    fn parse_result_line(
+        line: &str,
+        map_error: &impl Fn(u16, String) -> ProviderError,
+    ) -> Option<(String, Result<JsonValue, ProviderError>)> {
+        let v: JsonValue = serde_json::from_str(line).ok()?;
+        let id = v.get("custom_id")?.as_str()?.to_string();
+        let response = v.get("response").filter(|r| !r.is_null());
+        let status = response
+            .and_then(|r| r.get("status_code"))
+            .and_then(|s| s.as_u64())
+            .map(|s| s as u16);
+        let body = response.and_then(|r| r.get("body")).cloned();
+        let result = match (status, body) {
+            (Some(status), Some(body)) if (200..300).contains(&status) => Ok(body),
+            (Some(status), body) => Err(map_error(
+                status,
+                body.map(|b| b.to_string()).unwrap_or_default(),
+            )),
+            (None, _) => Err(ProviderError::ProviderOutage {
+                details: v.get("error").map(|e| e.to_string()),
+                code: v
+                    .get("error")
+                    .and_then(|e| e.get("code"))
+                    .and_then(|c| c.as_str())
+                    .map(|c| c.to_string()),
+                message: "Batch request failed".to_string(),
+                status: None,
+            }),
+        };
+        Some((id, result))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::providers::{ContentPart, Message, Role, StreamEvent};
+    use async_trait::async_trait;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    /// Echoes the request text after a short delay, tracking peak concurrency
+    #[derive(Default)]
+    struct Echo {
+        in_flight: AtomicUsize,
+        peak: AtomicUsize,
+    }
+
+    #[async_trait]
+    impl Provider for Echo {
+        async fn generate(&self, req: GenerateRequest) -> Result<GenerateResponse, ProviderError> {
+            let now = self.in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+            self.peak.fetch_max(now, Ordering::SeqCst);
+            tokio::time::sleep(Duration::from_millis(10)).await;
+            self.in_flight.fetch_sub(1, Ordering::SeqCst);
+            let text = match &req.messages[0].content[0] {
+                ContentPart::Text { text } => text.clone(),
+                _ => String::new(),
+            };
+            Ok(GenerateResponse {
+                text,
+                tool_calls: Vec::new(),
+                usage: None,
+                raw: None,
+                provider: None,
+                reasoning: None,
+                logprobs: None,
+            })
+        }
+
+        async fn generate_streaming(
+            &self,
+            req: GenerateRequest,
+            _on_event: &mut (dyn FnMut(StreamEvent) + Send),
+        ) -> Result<GenerateResponse, ProviderError> {
+            self.generate(req).await
+        }
+    }
+
+    fn request(text: &str) -> GenerateRequest {
+        GenerateRequest {
+            system: None,
+            messages: vec![Message {
+                role: Role::User,
+                content: vec![ContentPart::Text {
+                    text: text.to_string(),
+                }],
+            }],
+            tools: None,
+            tool_choice: None,
+            temperature: None,
+            top_p: None,
+            stop: None,
+            seed: None,
+            logit_bias: None,
+            response_format: None,
+            max_output_tokens: None,
+            metadata: None,
+            logprobs: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_fan_out_keeps_order_and_limit() {
+        let provider = Echo::default();
+        let reqs = (0..10).map(|i| request(&format!("goal {}", i))).collect();
+        let results = generate_concurrently(&provider, reqs, 3).await;
+
+        let texts: Vec<String> = results.into_iter().map(|r| r.unwrap().text).collect();
+        let expected: Vec<String> = (0..10).map(|i| format!("goal {}", i)).collect();
+        assert_eq!(texts, expected);
+        assert!(provider.peak.load(Ordering::SeqCst) <= 3);
+
+        // The trait default fans out the same way
+        let results = provider
+            .generate_batch(vec![request("a"), request("b")])
+            .await;
+        assert_eq!(results.len(), 2);
+        assert!(provider.peak.load(Ordering::SeqCst) <= DEFAULT_BATCH_CONCURRENCY);
+    }
+}
@@ -0,0 +1,427 @@
+//! Response cache for the provider layer.
+//!
+//! `CachingProvider` hashes the canonical `GenerateRequest` (messages, tools
+//! and sampling parameters, but not per-call metadata) together with the
+//! provider/model namespace, and answers exact repeats from an on-disk
+//! `FileDb` collection until the entry's TTL runs out. Retries that re-send
+//! the same context-gathering prompt are then served without a provider call.
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use log::{debug, warn};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::Duration;
+use tokio::sync::OnceCell;
+
+use crate::core::encryption;
+use crate::core::error::ProviderError;
+use crate::database::{DatabaseError, DatabaseInterface, FileDb};
+use crate::providers::{GenerateRequest, GenerateResponse, ModelInfo, Provider, StreamEvent};
+
+/// Name of the collection holding cached responses
+const CACHE_COLLECTION: &str = "llm_responses";
+
+/// `GenerateResponse::provider` of responses served from the cache
+pub const CACHE_PROVIDER: &str = "cache";
+
+/// A stored response and when it was produced
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CachedResponse {
+    /// Request hash (see `cache_key`)
+    pub id: String,
+
+    /// The provider's response
+    pub response: GenerateResponse,
+
+    /// When the response was stored
+    pub stored_at: DateTime<Utc>,
+}
+
+/// Cache key for `req` sent to `namespace` (typically `provider/model`)
+pub fn cache_key(namespace: &str, req: &GenerateRequest) -> String {
+    let mut canonical = req.clone();
+    canonical.metadata = None;
+    // Going through `Value` sorts map keys, so hash-map fields hash stably
+    let body = serde_json::to_value(&canonical)
+        .map(|v| v.to_string())
+        .unwrap_or_default();
+
+    let mut hasher = Sha256::new();
+    hasher.update(namespace.as_bytes());
+    hasher.update([0]);
+    hasher.update(body.as_bytes());
+    hasher
+        .finalize()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+type Store = Arc<dyn DatabaseInterface<CachedResponse>>;
+
+/// Store for `dir`, opened on first use and shared by every provider using
+/// the same directory so they do not overwrite each other's writes
+fn shared_store(dir: &str) -> Arc<OnceCell<Store>> {
+    static STORES: OnceLock<Mutex<HashMap<PathBuf, Arc<OnceCell<Store>>>>> = OnceLock::new();
+    STORES
+        .get_or_init(|| Mutex::new(HashMap::new()))
+        .lock()
+        .unwrap()
+        .entry(PathBuf::from(dir))
+        .or_default()
+        .clone()
+}
+
+/// Provider decorator answering repeated requests from the cache
+pub struct CachingProvider {
+    inner: Box<dyn Provider>,
+    namespace: String,
+    ttl: Duration,
+    dir: String,
+    store: Arc<OnceCell<Store>>,
+}
+
+impl CachingProvider {
+    /// Cache `inner`'s responses under `namespace` in `dir` for `ttl`
+    pub fn new(
+        inner: Box<dyn Provider>,
+        namespace: impl Into<String>,
+        dir: impl Into<String>,
+        ttl: Duration,
+    ) -> Self {
+        let dir = dir.into();
+        Self {
+            inner,
+            namespace: namespace.into(),
+            ttl,
+            store: shared_store(&dir),
+            dir,
+        }
+    }
+
+    /// Use `store` instead of the shared on-disk collection
+    pub fn with_store(mut self, store: Store) -> Self {
+        self.store = Arc::new(OnceCell::new_with(Some(store)));
+        self
+    }
+
+    async fn store(&self) -> Option<&Store> {
+        let opened = self
+            .store
+            .get_or_try_init(|| async {
+                FileDb::<CachedResponse>::open(&self.dir, CACHE_COLLECTION, encryption::global())
+                    .await
+                    .map(|db| Arc::new(db) as Store)
+            })
+            .await;
+        match opened {
+            Ok(store) => Some(store),
+            Err(e) => {
+                warn!("Response cache unavailable at {}: {}", self.dir, e);
+                None
+            }
+        }
+    }
+
+    /// Fresh cached response for `key`, evicting it if it has expired
+    async fn lookup(&self, key: &String) -> Option<GenerateResponse> {
+        let store = self.store().await?;
+        let record = match store.get(key).await {
+            Ok(record) => record.entity,
+            Err(DatabaseError::NotFound(_)) => return None,
+            Err(e) => {
+                warn!("Response cache read failed: {}", e);
+                return None;
+            }
+        };
+
+        let age = Utc::now()
+            .signed_duration_since(record.stored_at)
+            .to_std()
+            .unwrap_or_default();
+        if age > self.ttl {
+            if let Err(e) = store.delete(key).await {
+                warn!("Failed to evict expired cache entry: {}", e);
+            }
+            return None;
+        }
+        debug!("Response cache hit for {}", self.namespace);
+        Some(GenerateResponse {
+            provider: Some(CACHE_PROVIDER.to_string()),
+            ..record.response
+        })
+    }
+
+    async fn save(&self, key: String, response: &GenerateResponse) {
+        if response.is_empty() {
+            return;
+        }
+        let Some(store) = self.store().await else {
+            return;
+        };
+        let entry = CachedResponse {
+            id: key,
+            response: response.clone(),
+            stored_at: Utc::now(),
+        };
+        // An expired entry may have been replaced concurrently; either way
+        // the newest response wins
+        let result = match store.insert(entry.clone()).await {
+            Err(DatabaseError::DuplicateKey(_)) => store.update(entry, None).await,
+            other => other,
+        };
+        if let Err(e) = result {
+            warn!("Response cache write failed: {}", e);
+        }
+    }
+}
+
+#[async_trait]
+impl Provider for CachingProvider {
+    async fn generate(&self, req: GenerateRequest) -> Result<GenerateResponse, ProviderError> {
+        let key = cache_key(&self.namespace, &req);
+        if let Some(hit) = self.lookup(&key).await {
+            return Ok(hit);
+        }
+        let response = self.inner.generate(req).await?;
+        self.save(key, &response).await;
+        Ok(response)
+    }
+
+    fn supports_stream_resume(&self) -> bool {
+        self.inner.supports_stream_resume()
+    }
+
+    async fn generate_batch(
+        &self,
+        reqs: Vec<GenerateRequest>,
+    ) -> Vec<Result<GenerateResponse, ProviderError>> {
+        // Native batches go straight to the provider and are not cached
+        if self.inner.supports_native_batch() {
+            return self.inner.generate_batch(reqs).await;
+        }
+        crate::providers::batch::generate_concurrently(
+            self,
+            reqs,
+            crate::providers::batch::DEFAULT_BATCH_CONCURRENCY,
+        )
+        .await
+    }
+
+    fn supports_native_batch(&self) -> bool {
+        self.inner.supports_native_batch()
+    }
+
+    async fn list_models(&self) -> Result<Vec<ModelInfo>, ProviderError> {
+        self.inner.list_models().await
+    }
+
+    async fn embed(&self, inputs: &[String]) -> Result<Vec<Vec<f32>>, ProviderError> {
+        self.inner.embed(inputs).await
+    }
+
+    async fn health(&self) -> Result<(), ProviderError> {
+        self.inner.health().await
+    }
+
+    async fn generate_streaming(
+        &self,
+        req: GenerateRequest,
+        on_event: &mut (dyn FnMut(StreamEvent) + Send),
+    ) -> Result<GenerateResponse, ProviderError> {
+        let key = cache_key(&self.namespace, &req);
+        if let Some(hit) = self.lookup(&key).await {
+            // Replay the cached answer as a single-chunk stream
+            if let Some(reasoning) = &hit.reasoning {
+                on_event(StreamEvent::ReasoningDelta(reasoning.clone()));
+            }
+            if !hit.text.is_empty() {
+                on_event(StreamEvent::TextDelta(hit.text.clone()));
+            }
+            for call in &hit.tool_calls {
+                on_event(StreamEvent::ToolCall(call.clone()));
+            }
+            if let Some(usage) = &hit.usage {
+                on_event(StreamEvent::Usage(usage.clone()));
+            }
+            on_event(StreamEvent::Finished);
+            return Ok(hit);
+        }
+        let response = self.inner.generate_streaming(req, on_event).await?;
+        self.save(key, &response).await;
+        Ok(response)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::encryption::Cipher;
+    use crate::core::services::Services;
+    use crate::providers::{ContentPart, Message, Role};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use tempfile::TempDir;
+
+    struct Counting(Arc<AtomicUsize>);
+
+    #[async_trait]
+    impl Provider for Counting {
+        async fn generate(&self, req: GenerateRequest) -> Result<GenerateResponse, ProviderError> {
+            let n = self.0.fetch_add(1, Ordering::SeqCst);
+            let prompt: String = req
+                .messages
+                .iter()
+                .flat_map(|m| m.content.iter())
+                .filter_map(|p| match p {
+                    ContentPart::Text { text } => Some(text.as_str()),
+                    _ => None,
+                })
+                .collect();
+            Ok(GenerateResponse {
+                text: format!("answer {} to {}", n, prompt),
+                tool_calls: Vec::new(),
+                usage: None,
+                raw: None,
+                provider: None,
+                reasoning: None,
+                logprobs: None,
+            })
+        }
+
+        async fn generate_streaming(
+            &self,
+            req: GenerateRequest,
+            on_event: &mut (dyn FnMut(StreamEvent) + Send),
+        ) -> Result<GenerateResponse, ProviderError> {
+            let res = self.generate(req).await?;
+            on_event(StreamEvent::TextDelta(res.text.clone()));
+            Ok(res)
+        }
+    }
+
+    fn req(text: &str, temperature: f32) -> GenerateRequest {
+        GenerateRequest {
+            system: None,
+            messages: vec![Message {
+                role: Role::User,
+                content: vec![ContentPart::Text {
+                    text: text.to_string(),
+                }],
+            }],
+            tools: None,
+            tool_choice: None,
+            temperature: Some(temperature),
+            top_p: None,
+            stop: None,
+            seed: None,
+            logit_bias: None,
+            response_format: None,
+            max_output_tokens: None,
+            metadata: None,
+            logprobs: None,
+        }
+    }
+
+    async fn caching(dir: &TempDir, calls: &Arc<AtomicUsize>, ttl: Duration) -> CachingProvider {
+        let db = FileDb::<CachedResponse>::new(dir.path(), CACHE_COLLECTION)
+            .await
+            .unwrap();
+        CachingProvider::new(Box::new(Counting(calls.clone())), "test/model", "", ttl)
+            .with_store(Arc::new(db))
+    }
+
+    #[test]
+    fn test_key_covers_sampling_params_but_not_metadata() {
+        let base = req("hi", 0.0);
+        let mut with_meta = base.clone();
+        with_meta.metadata = Some(HashMap::from([("x-trace".to_string(), "1".to_string())]));
+
+        assert_eq!(cache_key("a/m", &base), cache_key("a/m", &with_meta));
+        assert_ne!(cache_key("a/m", &base), cache_key("a/m", &req("hi", 0.5)));
+        assert_ne!(cache_key("a/m", &base), cache_key("b/m", &base));
+    }
+
+    #[tokio::test]
+    async fn test_exact_repeat_is_served_from_cache() {
+        let dir = TempDir::new().unwrap();
+        let calls = Arc::new(AtomicUsize::new(0));
+        let provider = caching(&dir, &calls, Duration::from_secs(60)).await;
+
+        let first = provider.generate(req("hi", 0.0)).await.unwrap();
+        let again = provider.generate(req("hi", 0.0)).await.unwrap();
+        assert_eq!(first.text, again.text);
+        assert_eq!(again.provider.as_deref(), Some(CACHE_PROVIDER));
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+
+        let mut streamed = String::new();
+        let mut on_event = |ev: StreamEvent| {
+            if let StreamEvent::TextDelta(d) = ev {
+                streamed.push_str(&d);
+            }
+        };
+        provider
+            .generate_streaming(req("hi", 0.0), &mut on_event)
+            .await
+            .unwrap();
+        assert_eq!(streamed, first.text);
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+
+        provider.generate(req("other", 0.0)).await.unwrap();
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn test_expired_entries_are_refreshed() {
+        let dir = TempDir::new().unwrap();
+        let calls = Arc::new(AtomicUsize::new(0));
+        let provider = caching(&dir, &calls, Duration::ZERO).await;
+
+        provider.generate(req("hi", 0.0)).await.unwrap();
+        tokio::time::sleep(Duration::from_millis(5)).await;
+        let second = provider.generate(req("hi", 0.0)).await.unwrap();
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+        assert!(second.text.starts_with("answer 1"));
+    }
+
+    #[tokio::test]
+    async fn test_cache_is_sealed_when_encryption_is_enabled() {
+        let dir = TempDir::new().unwrap();
+        let calls = Arc::new(AtomicUsize::new(0));
+        let cipher = Cipher::from_base64(&Cipher::generate_key()).unwrap();
+        let services = Arc::new(Services {
+            cipher: Some(cipher),
+            ..Default::default()
+        });
+        let cache_dir = dir.path().to_string_lossy().to_string();
+
+        services
+            .scope(async {
+                let provider = CachingProvider::new(
+                    Box::new(Counting(calls.clone())),
+                    "test/model",
+                    cache_dir.clone(),
+                    Duration::from_secs(60),
+                );
+                let first = provider
+                    .generate(req("the launch codes", 0.0))
+                    .await
+                    .unwrap();
+                let again = provider
+                    .generate(req("the launch codes", 0.0))
+                    .await
+                    .unwrap();
+                assert_eq!(first.text, again.text);
+                assert_eq!(calls.load(Ordering::SeqCst), 1);
+            })
+            .await;
+
+        let stored =
+            std::fs::read_to_string(dir.path().join(format!("{}.json", CACHE_COLLECTION))).unwrap();
+        assert!(!stored.is_empty());
+        assert!(!stored.contains("launch codes"), "{}", stored);
+    }
+}
@@ -0,0 +1,501 @@
+//! Model context-window capabilities.
+//!
+//! A small static table seeds the known context limits; providers refine it at
+//! runtime when a request fails with a "context length exceeded" error that
+//! reports the model's real limit. Prompts counted (with `count_tokens`) to
+//! exceed a known limit are truncated before sending; requests that still
+//! overflow are retried once with their context truncated to fit the
+//! (possibly corrected) budget.
+
+use log::{info, warn};
+use regex::Regex;
+use std::collections::HashMap;
+use std::sync::{Mutex, OnceLock};
+
+use crate::core::error::ProviderError;
+use crate::providers::{count_tokens, ContentPart, GenerateRequest, GenerateResponse, Provider};
+
+/// Known context windows by model-name prefix (longest matching prefix wins)
+const STATIC_CONTEXT_LIMITS: &[(&str, usize)] = &[
+    ("claude", 200_000),
+    ("anthropic/claude", 200_000),
+    ("gpt-4o", 128_000),
+    ("openai/gpt-4o", 128_000),
+    ("gpt-4-turbo", 128_000),
+    ("gpt-4", 8_192),
+    ("gpt-3.5-turbo", 16_385),
+    ("o1", 200_000),
+    ("o3", 200_000),
+    ("o4", 200_000),
+];
+
+/// Rough characters-per-token ratio used when the provider reports no counts
+const CHARS_PER_TOKEN: usize = 4;
+
+/// Fraction of the input budget actually used, leaving room for estimate error
+const SAFETY_MARGIN: f64 = 0.9;
+
+/// Marker inserted where context was cut
+const TRUNCATION_MARKER: &str = "\n... (truncated to fit the model context window) ...\n";
+
+static RUNTIME_CONTEXT_LIMITS: OnceLock<Mutex<HashMap<String, usize>>> = OnceLock::new();
+
+fn runtime_limits() -> &'static Mutex<HashMap<String, usize>> {
+    RUNTIME_CONTEXT_LIMITS.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+/// Context window for `model`: a runtime-reported limit if one was recorded,
+/// else the static table entry
+pub fn max_context_tokens(model: &str) -> Option<usize> {
+    if let Some(limit) = runtime_limits().lock().unwrap().get(model) {
+        return Some(*limit);
+    }
+
+    STATIC_CONTEXT_LIMITS
+        .iter()
+        .filter(|(prefix, _)| model.starts_with(prefix))
+        .max_by_key(|(prefix, _)| prefix.len())
+        .map(|(_, limit)| *limit)
+}
+
+/// Record the context window a provider reported for `model`
+pub fn record_max_context_tokens(model: &str, tokens: usize) {
+    let previous = runtime_limits()
+        .lock()
+        .unwrap()
+        .insert(model.to_string(), tokens);
+    if previous != Some(tokens) {
+        info!(
+            "Updated context window for model '{}' to {} tokens",
+            model, tokens
+        );
+    }
+}
+
+/// Detect a "context length exceeded" error body and extract any reported
+/// limit and prompt size
+///
+/// Recognizes the OpenAI/OpenRouter phrasing ("maximum context length is N
+/// tokens ... resulted in M tokens") and Anthropic's ("prompt is too long: M
+/// tokens > N maximum").
+pub fn parse_context_length_error(status: u16, body: &str) -> Option<ProviderError> {
+    let lower = body.to_lowercase();
+    let is_context_error = lower.contains("context_length_exceeded")
+        || lower.contains("maximum context length")
+        || lower.contains("context window")
+        || lower.contains("prompt is too long");
+    if !is_context_error {
+        return None;
+    }
+
+    let capture = |pattern: &str| -> Option<usize> {
+        Regex::new(pattern)
+            .ok()?
+            .captures(&lower)?
+            .get(1)?
+            .as_str()
+            .replace(',', "")
+            .parse()
+            .ok()
+    };
+
+    let max_context_tokens = capture(r"maximum context length is ([\d,]+)")
+        .or_else(|| capture(r"tokens > ([\d,]+) maximum"))
+        .or_else(|| capture(r"context window (?:of|is) ([\d,]+)"));
+    let prompt_tokens = capture(r"([\d,]+) in the messages")
+        .or_else(|| capture(r"prompt is too long: ([\d,]+) tokens"))
+        .or_else(|| capture(r"resulted in ([\d,]+) tokens"))
+        .or_else(|| capture(r"you requested ([\d,]+) tokens"));
+
+    Some(ProviderError::ContextLengthExceeded {
+        details: Some(body.to_string()),
+        message: match max_context_tokens {
+            Some(limit) => format!(
+                "Prompt exceeds the model context window of {} tokens",
+                limit
+            ),
+            None => "Prompt exceeds the model context window".to_string(),
+        },
+        status: Some(status),
+        max_context_tokens,
+        prompt_tokens,
+    })
+}
+
+/// Estimated prompt size of a request in tokens
+pub(crate) fn estimate_prompt_tokens(req: &GenerateRequest) -> usize {
+    let chars = req.system.as_ref().map(|s| s.len()).unwrap_or(0)
+        + req
+            .messages
+            .iter()
+            .flat_map(|m| m.content.iter())
+            .map(|part| match part {
+                ContentPart::Text { text } | ContentPart::ToolResult { content: text, .. } => {
+                    text.len()
+                }
+                ContentPart::ToolCall {
+                    name, arguments, ..
+                } => name.len() + arguments.to_string().len(),
+                ContentPart::ImageUrl { .. } | ContentPart::CacheBreakpoint => 0,
+            })
+            .sum::<usize>();
+    chars.div_ceil(CHARS_PER_TOKEN)
+}
+
+/// Cut `excess` bytes out of the middle of `text`, keeping the head and tail
+fn truncate_middle(text: &str, excess: usize) -> String {
+    let keep = text.len().saturating_sub(excess + TRUNCATION_MARKER.len());
+    let mut head_end = keep / 2;
+    while !text.is_char_boundary(head_end) {
+        head_end -= 1;
+    }
+    let mut tail_start = text.len() - (keep - keep / 2);
+    while !text.is_char_boundary(tail_start) {
+        tail_start += 1;
+    }
+    format!(
+        "{}{}{}",
+        &text[..head_end],
+        TRUNCATION_MARKER,
+        &text[tail_start..]
+    )
+}
+
+/// Shrink a request so its prompt plus output budget fits `max_context` tokens
+///
+/// `prompt_tokens` is the provider-reported prompt size, when available; it
+/// calibrates the chars-per-token estimate for this request. The largest text
+/// parts are truncated first, from the middle, so instructions at the start and
+/// the most recent content at the end survive.
+pub fn fit_request_to_context(
+    req: &GenerateRequest,
+    max_context: usize,
+    prompt_tokens: Option<usize>,
+) -> GenerateRequest {
+    let mut fitted = req.clone();
+
+    // Never let the output budget consume the whole window
+    let max_output = fitted
+        .max_output_tokens
+        .unwrap_or(0)
+        .min(max_context / 4)
+        .max(1);
+    if fitted.max_output_tokens.is_some() {
+        fitted.max_output_tokens = Some(max_output);
+    }
+
+    let input_budget = ((max_context.saturating_sub(max_output)) as f64 * SAFETY_MARGIN) as usize;
+    let estimated = estimate_prompt_tokens(&fitted);
+    let actual = prompt_tokens.unwrap_or(estimated).max(1);
+    if actual <= input_budget {
+        return fitted;
+    }
+
+    let total_chars = estimated * CHARS_PER_TOKEN;
+    let target_chars = (total_chars as f64 * input_budget as f64 / actual as f64) as usize;
+    let mut excess = total_chars.saturating_sub(target_chars);
+
+    while excess > 0 {
+        let largest = fitted
+            .messages
+            .iter_mut()
+            .flat_map(|m| m.content.iter_mut())
+            .filter_map(|part| match part {
+                // Tool output is usually the bulk of an agentic conversation
+                ContentPart::Text { text } | ContentPart::ToolResult { content: text, .. }
+                    if text.len() > TRUNCATION_MARKER.len() * 2 =>
+                {
+                    Some(text)
+                }
+                _ => None,
+            })
+            .max_by_key(|text| text.len());
+        let Some(text) = largest else {
+            break;
+        };
+
+        let cut = excess.min(text.len() - TRUNCATION_MARKER.len() * 2);
+        let before = text.len();
+        *text = truncate_middle(text, cut);
+        let removed = before.saturating_sub(text.len());
+        if removed == 0 {
+            break;
+        }
+        excess = excess.saturating_sub(removed);
+    }
+
+    fitted
+}
+
+/// Prompt size of a request in `model`'s tokens
+pub fn count_prompt_tokens(model: &str, req: &GenerateRequest) -> usize {
+    // Role markers and message framing cost a few tokens per message
+    const PER_MESSAGE_OVERHEAD: usize = 4;
+    let system = req
+        .system
+        .as_deref()
+        .map(|s| count_tokens(model, s) + PER_MESSAGE_OVERHEAD)
+        .unwrap_or(0);
+    system
+        + req
+            .messages
+            .iter()
+            .map(|m| {
+                PER_MESSAGE_OVERHEAD
+                    + m.content
+                        .iter()
+                        .map(|part| match part {
+                            ContentPart::Text { text }
+                            | ContentPart::ToolResult { content: text, .. } => {
+                                count_tokens(model, text)
+                            }
+                            ContentPart::ToolCall {
+                                name, arguments, ..
+                            } => count_tokens(model, &format!("{}{}", name, arguments)),
+                            ContentPart::ImageUrl { .. } | ContentPart::CacheBreakpoint => 0,
+                        })
+                        .sum::<usize>()
+            })
+            .sum::<usize>()
+}
+
+/// Truncate `req` before sending if its counted prompt would not fit
+/// `model`'s known context window, instead of waiting for the provider to
+/// reject it; requests for models with unknown limits pass unchanged
+pub fn fit_to_context_window(model: &str, req: GenerateRequest) -> GenerateRequest {
+    let Some(limit) = max_context_tokens(model) else {
+        return req;
+    };
+    let prompt_tokens = count_prompt_tokens(model, &req);
+    let output = req.max_output_tokens.unwrap_or(0);
+    if prompt_tokens + output <= limit {
+        return req;
+    }
+    warn!(
+        "Prompt of ~{} tokens plus {} output tokens exceeds the {}-token context of '{}'; truncating",
+        prompt_tokens, output, limit, model
+    );
+    fit_request_to_context(&req, limit, Some(prompt_tokens))
+}
+
+/// Run `provider.generate`, retrying once with truncated context if the
+/// provider rejects the request for exceeding the context window
+///
+/// A limit reported in the error updates the capability table so later
+/// requests for `model` use the corrected budget.
+pub async fn generate_with_context_retry(
+    provider: &dyn Provider,
+    model: &str,
+    req: GenerateRequest,
+) -> Result<GenerateResponse, ProviderError> {
+    let retry_req = req.clone();
+    match provider.generate(req).await {
+        Err(
+            err @ ProviderError::ContextLengthExceeded {
+                max_context_tokens: reported,
+                prompt_tokens,
+                ..
+            },
+        ) => {
+            if let Some(limit) = reported {
+                record_max_context_tokens(model, limit);
+            }
+            let Some(limit) = reported.or_else(|| max_context_tokens(model)) else {
+                warn!(
+                    "Context length exceeded for model '{}' with no known limit; not retrying",
+                    model
+                );
+                return Err(err);
+            };
+
+            warn!(
+                "Context length exceeded for model '{}'; retrying once truncated to {} tokens",
+                model, limit
+            );
+            provider
+                .generate(fit_request_to_context(&retry_req, limit, prompt_tokens))
+                .await
+        }
+        other => other,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::providers::{Message, Role, StreamEvent};
+    use async_trait::async_trait;
+
+    const OPENAI_CONTEXT_ERROR: &str = r#"{"error":{"message":"This model's maximum context length is 8192 tokens. However, you requested 12000 tokens (11000 in the messages, 1000 in the completion). Please reduce the length of the messages or completion.","type":"invalid_request_error","param":"messages","code":"context_length_exceeded"}}"#;
+
+    /// Rejects any prompt longer than `max_prompt_chars`, recording every request
+    struct ContextLimitedProvider {
+        max_prompt_chars: usize,
+        requests: Mutex<Vec<GenerateRequest>>,
+    }
+
+    #[async_trait]
+    impl Provider for ContextLimitedProvider {
+        async fn generate(&self, req: GenerateRequest) -> Result<GenerateResponse, ProviderError> {
+            let chars = estimate_prompt_tokens(&req) * CHARS_PER_TOKEN;
+            self.requests.lock().unwrap().push(req);
+            if chars > self.max_prompt_chars {
+                return Err(parse_context_length_error(400, OPENAI_CONTEXT_ERROR).unwrap());
+            }
+            Ok(GenerateResponse {
+                text: "ok".to_string(),
+                tool_calls: Vec::new(),
+                usage: None,
+                raw: None,
+                provider: None,
+                reasoning: None,
+                logprobs: None,
+            })
+        }
+
+        async fn generate_streaming(
+            &self,
+            req: GenerateRequest,
+            _on_event: &mut (dyn FnMut(StreamEvent) + Send),
+        ) -> Result<GenerateResponse, ProviderError> {
+            self.generate(req).await
+        }
+    }
+
+    fn request_with_prompt(prompt: String) -> GenerateRequest {
+        GenerateRequest {
+            system: None,
+            messages: vec![Message {
+                role: Role::User,
+                content: vec![ContentPart::Text { text: prompt }],
+            }],
+            tools: None,
+            tool_choice: None,
+            temperature: None,
+            top_p: None,
+            stop: None,
+            seed: None,
+            logit_bias: None,
+            response_format: None,
+            max_output_tokens: Some(1000),
+            metadata: None,
+            logprobs: None,
+        }
+    }
+
+    #[test]
+    fn test_parse_openai_context_error() {
+        match parse_context_length_error(400, OPENAI_CONTEXT_ERROR) {
+            Some(ProviderError::ContextLengthExceeded {
+                max_context_tokens,
+                prompt_tokens,
+                ..
+            }) => {
+                assert_eq!(max_context_tokens, Some(8192));
+                assert_eq!(prompt_tokens, Some(11000));
+            }
+            other => panic!("expected context length error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_parse_anthropic_context_error() {
+        let body = r#"{"type":"error","error":{"type":"invalid_request_error","message":"prompt is too long: 210371 tokens > 200000 maximum"}}"#;
+        match parse_context_length_error(400, body) {
+            Some(ProviderError::ContextLengthExceeded {
+                max_context_tokens,
+                prompt_tokens,
+                ..
+            }) => {
+                assert_eq!(max_context_tokens, Some(200_000));
+                assert_eq!(prompt_tokens, Some(210_371));
+            }
+            other => panic!("expected context length error, got {:?}", other),
+        }
+        assert!(parse_context_length_error(400, "invalid temperature").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_context_error_triggers_single_truncated_retry() {
+        let model = "test-context-retry-model";
+        let provider = ContextLimitedProvider {
+            max_prompt_chars: 8192 * CHARS_PER_TOKEN,
+            requests: Mutex::new(Vec::new()),
+        };
+        let prompt = format!("INSTRUCTIONS {} LATEST", "x".repeat(44_000));
+
+        let response = generate_with_context_retry(&provider, model, request_with_prompt(prompt))
+            .await
+            .unwrap();
+        assert_eq!(response.text, "ok");
+
+        let requests = provider.requests.lock().unwrap();
+        assert_eq!(requests.len(), 2, "expected exactly one retry");
+        let retried = match &requests[1].messages[0].content[0] {
+            ContentPart::Text { text } => text.clone(),
+            _ => unreachable!(),
+        };
+        assert!(retried.len() < 44_000);
+        assert!(retried.starts_with("INSTRUCTIONS"));
+        assert!(retried.ends_with("LATEST"));
+        assert!(retried.contains("truncated to fit"));
+        assert_eq!(max_context_tokens(model), Some(8192));
+    }
+
+    #[tokio::test]
+    async fn test_context_error_is_not_retried_twice() {
+        let provider = ContextLimitedProvider {
+            max_prompt_chars: 0,
+            requests: Mutex::new(Vec::new()),
+        };
+
+        let result = generate_with_context_retry(
+            &provider,
+            "test-context-no-loop-model",
+            request_with_prompt("x".repeat(1_000)),
+        )
+        .await;
+
+        assert!(matches!(
+            result,
+            Err(ProviderError::ContextLengthExceeded { .. })
+        ));
+        assert_eq!(provider.requests.lock().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_oversized_prompt_is_truncated_before_sending() {
+        let model = "test-context-guard-model";
+        let prompt = format!(
+            "INSTRUCTIONS {} LATEST",
+            "let value = compute(input); ".repeat(2_000)
+        );
+        let req = request_with_prompt(prompt.clone());
+
+        // Unknown limit: left alone
+        let unchanged = fit_to_context_window(model, req.clone());
+        assert_eq!(
+            count_prompt_tokens(model, &unchanged),
+            count_prompt_tokens(model, &req)
+        );
+
+        record_max_context_tokens(model, 4096);
+        let fitted = fit_to_context_window(model, req);
+        let tokens = count_prompt_tokens(model, &fitted);
+        assert!(tokens + 1000 <= 4096, "{} prompt tokens", tokens);
+        let ContentPart::Text { text } = &fitted.messages[0].content[0] else {
+            unreachable!()
+        };
+        assert!(text.starts_with("INSTRUCTIONS"));
+        assert!(text.ends_with("LATEST"));
+
+        // Prompts that already fit pass through untouched
+        let small = request_with_prompt("short prompt".to_string());
+        let ContentPart::Text { text } =
+            &fit_to_context_window(model, small).messages[0].content[0]
+        else {
+            unreachable!()
+        };
+        assert_eq!(text, "short prompt");
+    }
+}
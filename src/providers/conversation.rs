@@ -0,0 +1,413 @@
+//! Multi-turn conversations over a `Provider`.
+//!
+//! A `Conversation` owns the message history of an agentic exchange: the
+//! user's task, each assistant reply with the tool calls it made, and the
+//! results of those calls. `request` turns the history into a
+//! `GenerateRequest` that fits the token budget, dropping the oldest
+//! exchanges (an assistant turn together with its tool results) when it
+//! would not; the opening message is always kept. `send` makes the call and
+//! records the reply, so a loop of `send`, run `pending_tool_calls`,
+//! `push_tool_result` drives a model until it stops calling tools.
+
+use log::debug;
+
+use crate::core::error::ProviderError;
+use crate::providers::capabilities::{count_prompt_tokens, max_context_tokens};
+use crate::providers::{
+    ContentPart, GenerateRequest, GenerateResponse, Message, Provider, Role, ToolCallNormalized,
+    ToolChoice, ToolSpec, Usage,
+};
+
+/// Message history, tools and token accounting for one multi-turn exchange
+#[derive(Debug, Clone)]
+pub struct Conversation {
+    model: String,
+    system: Option<String>,
+    messages: Vec<Message>,
+    tools: Option<Vec<ToolSpec>>,
+    tool_choice: Option<ToolChoice>,
+    temperature: Option<f32>,
+    max_output_tokens: Option<usize>,
+    token_budget: Option<usize>,
+    usage: Usage,
+    next_call_id: usize,
+}
+
+impl Conversation {
+    /// Empty conversation with `model`, whose tokenizer and context window
+    /// size the prompt
+    pub fn new(model: impl Into<String>) -> Self {
+        Self {
+            model: model.into(),
+            system: None,
+            messages: Vec::new(),
+            tools: None,
+            tool_choice: None,
+            temperature: None,
+            max_output_tokens: None,
+            token_budget: None,
+            usage: Usage::default(),
+            next_call_id: 0,
+        }
+    }
+
+    /// Set the system prompt
+    pub fn with_system(mut self, system: impl Into<String>) -> Self {
+        self.system = Some(system.into());
+        self
+    }
+
+    /// Offer `tools` to the model on every turn
+    pub fn with_tools(mut self, tools: Vec<ToolSpec>) -> Self {
+        self.tools = Some(tools);
+        self
+    }
+
+    /// Constrain whether the model must call a tool
+    pub fn with_tool_choice(mut self, choice: ToolChoice) -> Self {
+        self.tool_choice = Some(choice);
+        self
+    }
+
+    /// Sampling temperature for every turn
+    pub fn with_temperature(mut self, temperature: f32) -> Self {
+        self.temperature = Some(temperature);
+        self
+    }
+
+    /// Output limit for every turn
+    pub fn with_max_output_tokens(mut self, tokens: usize) -> Self {
+        self.max_output_tokens = Some(tokens);
+        self
+    }
+
+    /// Cap prompt plus output at `tokens` (defaults to the model's known
+    /// context window; unlimited when that is unknown too)
+    pub fn with_token_budget(mut self, tokens: usize) -> Self {
+        self.token_budget = Some(tokens);
+        self
+    }
+
+    /// Add a user turn
+    pub fn push_user(&mut self, text: impl Into<String>) {
+        self.messages.push(Message {
+            role: Role::User,
+            content: vec![ContentPart::Text { text: text.into() }],
+        });
+    }
+
+    /// Add a user turn made of `parts`, e.g. text followed by a cache
+    /// breakpoint
+    pub fn push_user_parts(&mut self, parts: Vec<ContentPart>) {
+        self.messages.push(Message {
+            role: Role::User,
+            content: parts,
+        });
+    }
+
+    /// Record a model reply, its tool calls and its token usage
+    ///
+    /// Tool calls without an id are given one, so their results can refer
+    /// back to them.
+    pub fn push_response(&mut self, response: &GenerateResponse) {
+        let mut content = Vec::new();
+        if !response.text.is_empty() {
+            content.push(ContentPart::Text {
+                text: response.text.clone(),
+            });
+        }
+        for call in &response.tool_calls {
+            let id = call.id.clone().unwrap_or_else(|| {
+                self.next_call_id += 1;
+                format!("call_{}", self.next_call_id)
+            });
+            content.push(ContentPart::ToolCall {
+                id,
+                name: call.name.clone(),
+                arguments: call.arguments_json.clone(),
+            });
+        }
+        if let Some(usage) = &response.usage {
+            add_usage(&mut self.usage, usage);
+        }
+        self.messages.push(Message {
+            role: Role::Assistant,
+            content,
+        });
+    }
+
+    /// Record the outcome of the tool call `call_id`
+    ///
+    /// Results of calls from the same reply share one tool message.
+    pub fn push_tool_result(
+        &mut self,
+        call_id: impl Into<String>,
+        content: impl Into<String>,
+        is_error: bool,
+    ) {
+        let part = ContentPart::ToolResult {
+            call_id: call_id.into(),
+            content: content.into(),
+            is_error,
+        };
+        match self.messages.last_mut() {
+            Some(last) if matches!(last.role, Role::Tool) => last.content.push(part),
+            _ => self.messages.push(Message {
+                role: Role::Tool,
+                content: vec![part],
+            }),
+        }
+    }
+
+    /// Tool calls from the latest reply that have no result yet, with the
+    /// ids their results must use
+    pub fn pending_tool_calls(&self) -> Vec<ToolCallNormalized> {
+        let Some(reply) = self
+            .messages
+            .iter()
+            .rposition(|m| matches!(m.role, Role::Assistant))
+        else {
+            return Vec::new();
+        };
+        let answered: Vec<&str> = self.messages[reply + 1..]
+            .iter()
+            .flat_map(|m| m.content.iter())
+            .filter_map(|p| match p {
+                ContentPart::ToolResult { call_id, .. } => Some(call_id.as_str()),
+                _ => None,
+            })
+            .collect();
+        self.messages[reply]
+            .content
+            .iter()
+            .filter_map(|p| match p {
+                ContentPart::ToolCall {
+                    id,
+                    name,
+                    arguments,
+                } if !answered.contains(&id.as_str()) => Some(ToolCallNormalized {
+                    id: Some(id.clone()),
+                    name: name.clone(),
+                    arguments_json: arguments.clone(),
+                }),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// The full message history
+    pub fn messages(&self) -> &[Message] {
+        &self.messages
+    }
+
+    /// Token usage summed over every recorded reply
+    pub fn usage(&self) -> &Usage {
+        &self.usage
+    }
+
+    /// Prompt size of the next request in the model's tokens
+    pub fn prompt_tokens(&self) -> usize {
+        count_prompt_tokens(&self.model, &self.request())
+    }
+
+    /// Request for the next turn, trimmed to the token budget
+    pub fn request(&self) -> GenerateRequest {
+        let mut req = GenerateRequest {
+            system: self.system.clone(),
+            messages: self.messages.clone(),
+            tools: self.tools.clone(),
+            tool_choice: self.tool_choice.clone(),
+            temperature: self.temperature,
+            top_p: None,
+            stop: None,
+            seed: None,
+            logit_bias: None,
+            response_format: None,
+            max_output_tokens: self.max_output_tokens,
+            metadata: None,
+            logprobs: None,
+        };
+        let Some(budget) = self
+            .token_budget
+            .or_else(|| max_context_tokens(&self.model))
+        else {
+            return req;
+        };
+        let input_budget = budget.saturating_sub(self.max_output_tokens.unwrap_or(0));
+
+        let mut dropped = 0;
+        while count_prompt_tokens(&self.model, &req) > input_budget {
+            // Never drop the opening message or the exchange in progress
+            let Some(end) = next_exchange_end(&req.messages) else {
+                break;
+            };
+            dropped += end - 1;
+            req.messages.drain(1..end);
+        }
+        if dropped > 0 {
+            debug!(
+                "Dropped {} old messages to fit the {}-token budget of '{}'",
+                dropped, budget, self.model
+            );
+        }
+        req
+    }
+
+    /// Send the next turn to `provider` and record the reply
+    pub async fn send(
+        &mut self,
+        provider: &dyn Provider,
+    ) -> Result<GenerateResponse, ProviderError> {
+        let response = provider.generate(self.request()).await?;
+        self.push_response(&response);
+        Ok(response)
+    }
+}
+
+/// End (exclusive) of the oldest exchange after the opening message, if it
+/// is not also the latest one
+///
+/// An exchange starts at a user or assistant message and takes the tool
+/// results that follow it, so a call is never separated from its result.
+fn next_exchange_end(messages: &[Message]) -> Option<usize> {
+    if messages.len() < 2 {
+        return None;
+    }
+    let end = 2 + messages[2..]
+        .iter()
+        .position(|m| !matches!(m.role, Role::Tool))?;
+    Some(end)
+}
+
+fn add_usage(total: &mut Usage, usage: &Usage) {
+    fn add(total: &mut Option<u32>, value: Option<u32>) {
+        if let Some(value) = value {
+            *total = Some(total.unwrap_or(0) + value);
+        }
+    }
+    add(&mut total.prompt_tokens, usage.prompt_tokens);
+    add(&mut total.completion_tokens, usage.completion_tokens);
+    add(&mut total.total_tokens, usage.total_tokens);
+    add(&mut total.retries, usage.retries);
+    add(&mut total.cache_read_tokens, usage.cache_read_tokens);
+    add(&mut total.cache_write_tokens, usage.cache_write_tokens);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::providers::StreamEvent;
+    use async_trait::async_trait;
+    use serde_json::json;
+    use std::sync::Mutex;
+
+    /// Calls the `echo` tool once, then answers with the tool's output
+    struct Scripted {
+        seen: Mutex<Vec<GenerateRequest>>,
+    }
+
+    #[async_trait]
+    impl Provider for Scripted {
+        async fn generate(&self, req: GenerateRequest) -> Result<GenerateResponse, ProviderError> {
+            let result = req
+                .messages
+                .iter()
+                .flat_map(|m| m.content.iter())
+                .find_map(|p| match p {
+                    ContentPart::ToolResult { content, .. } => Some(content.clone()),
+                    _ => None,
+                });
+            self.seen.lock().unwrap().push(req);
+            let (text, tool_calls) = match result {
+                Some(output) => (format!("done: {}", output), Vec::new()),
+                None => (
+                    String::new(),
+                    vec![ToolCallNormalized {
+                        id: None,
+                        name: "echo".to_string(),
+                        arguments_json: json!({"text": "hi"}),
+                    }],
+                ),
+            };
+            Ok(GenerateResponse {
+                text,
+                tool_calls,
+                usage: Some(Usage {
+                    total_tokens: Some(10),
+                    ..Usage::default()
+                }),
+                raw: None,
+                provider: None,
+                reasoning: None,
+                logprobs: None,
+            })
+        }
+
+        async fn generate_streaming(
+            &self,
+            req: GenerateRequest,
+            _on_event: &mut (dyn FnMut(StreamEvent) + Send),
+        ) -> Result<GenerateResponse, ProviderError> {
+            self.generate(req).await
+        }
+    }
+
+    #[tokio::test]
+    async fn test_tool_loop_round_trip() {
+        let provider = Scripted {
+            seen: Mutex::new(Vec::new()),
+        };
+        let mut conversation = Conversation::new("test-model").with_tools(vec![ToolSpec {
+            name: "echo".to_string(),
+            description: None,
+            json_schema: None,
+        }]);
+        conversation.push_user("say hi");
+
+        conversation.send(&provider).await.unwrap();
+        let calls = conversation.pending_tool_calls();
+        assert_eq!(calls.len(), 1);
+        assert_eq!(calls[0].id.as_deref(), Some("call_1"));
+
+        conversation.push_tool_result("call_1", "hi", false);
+        assert!(conversation.pending_tool_calls().is_empty());
+        let answer = conversation.send(&provider).await.unwrap();
+
+        assert_eq!(answer.text, "done: hi");
+        assert_eq!(conversation.messages().len(), 4);
+        assert_eq!(conversation.usage().total_tokens, Some(20));
+        assert!(provider.seen.lock().unwrap()[1].tools.is_some());
+    }
+
+    #[test]
+    fn test_budget_drops_oldest_exchanges_with_their_results() {
+        let mut conversation = Conversation::new("test-model");
+        conversation.push_user("task");
+        for i in 0..5 {
+            conversation.push_response(&GenerateResponse {
+                text: String::new(),
+                tool_calls: vec![ToolCallNormalized {
+                    id: Some(format!("c{}", i)),
+                    name: "read".to_string(),
+                    arguments_json: json!({}),
+                }],
+                usage: None,
+                raw: None,
+                provider: None,
+                reasoning: None,
+                logprobs: None,
+            });
+            conversation.push_tool_result(format!("c{}", i), "x".repeat(400), false);
+        }
+        let full = conversation.prompt_tokens();
+
+        let trimmed = conversation.with_token_budget(full / 2);
+        let req = trimmed.request();
+        assert!(count_prompt_tokens("test-model", &req) <= full / 2);
+        assert!(matches!(req.messages[0].role, Role::User));
+        // Each surviving call is still followed by its result
+        assert!(matches!(req.messages[1].role, Role::Assistant));
+        assert!(matches!(req.messages.last().unwrap().role, Role::Tool));
+        assert_eq!(req.messages.len() % 2, 1);
+    }
+}
@@ -0,0 +1,225 @@
+// File: src/providers/deepseek.rs
+use async_trait::async_trait;
+
+use crate::core::config::LlmConfig;
+use crate::core::error::ProviderError;
+use crate::providers::openai_compat::{Dialect, OpenAiCompatProvider};
+use crate::providers::{GenerateRequest, GenerateResponse, ModelInfo, StreamEvent};
+
+const THINK_OPEN: &str = "<think>";
+const THINK_CLOSE: &str = "</think>";
+
+/// DeepSeek adapter.
+///
+/// OpenAI-compatible chat completions at `https://api.deepseek.com`. R1
+/// (`deepseek-reasoner`) returns its chain of thought in `reasoning_content`;
+/// R1 served elsewhere (and its distills) inline it in the answer as a
+/// `<think>...</think>` block instead. Both end up in
+/// `GenerateResponse::reasoning` and `ReasoningDelta` events, so the answer
+/// text holds only the final answer.
+pub struct DeepSeekProvider {
+    inner: OpenAiCompatProvider,
+}
+
+impl DeepSeekProvider {
+    /// Create a DeepSeek provider from LlmConfig (key falls back to `DEEPSEEK_API_KEY`)
+    pub fn from_config(cfg: &LlmConfig) -> Result<Self, ProviderError> {
+        let dialect = Dialect {
+            name: "DeepSeek",
+            default_base: "https://api.deepseek.com",
+            key_env: "DEEPSEEK_API_KEY",
+            max_tokens_field: "max_tokens",
+            seed_field: "seed",
+            max_stop_sequences: Some(16),
+            required_tool_choice: "required",
+            // The reasoner rejects logprobs requests
+            logprobs: false,
+            reasoning_effort: false,
+        };
+        Ok(Self {
+            inner: OpenAiCompatProvider::from_config(cfg, dialect)?,
+        })
+    }
+}
+
+/// Move inline `<think>` blocks from `response.text` into `reasoning`
+fn separate_inline_thinking(mut response: GenerateResponse) -> GenerateResponse {
+    let mut splitter = ThinkSplitter::default();
+    let mut events = splitter.push(&response.text);
+    events.extend(splitter.finish());
+
+    let mut text = String::new();
+    let mut thinking = String::new();
+    for event in events {
+        match event {
+            StreamEvent::TextDelta(d) => text.push_str(&d),
+            StreamEvent::ReasoningDelta(d) => thinking.push_str(&d),
+            _ => {}
+        }
+    }
+    if !thinking.is_empty() {
+        response.reasoning = Some(match response.reasoning.take() {
+            Some(existing) => format!("{}\n{}", existing, thinking),
+            None => thinking,
+        });
+        response.text = text.trim_start().to_string();
+    }
+    response
+}
+
+/// Splits streamed answer text into answer and `<think>` block deltas,
+/// holding back any suffix that may be the start of a tag
+#[derive(Default)]
+pub struct ThinkSplitter {
+    in_think: bool,
+    pending: String,
+}
+
+impl ThinkSplitter {
+    /// Events for the next chunk of answer text
+    pub fn push(&mut self, chunk: &str) -> Vec<StreamEvent> {
+        self.pending.push_str(chunk);
+        let mut events = Vec::new();
+        loop {
+            let tag = if self.in_think {
+                THINK_CLOSE
+            } else {
+                THINK_OPEN
+            };
+            match self.pending.find(tag) {
+                Some(at) => {
+                    let before: String = self.pending.drain(..at).collect();
+                    self.pending.drain(..tag.len());
+                    self.emit(&mut events, before);
+                    self.in_think = !self.in_think;
+                }
+                None => {
+                    // Keep a trailing partial tag for the next chunk
+                    let keep = (1..tag.len())
+                        .rev()
+                        .find(|&n| self.pending.ends_with(&tag[..n]))
+                        .unwrap_or(0);
+                    let ready: String = self.pending.drain(..self.pending.len() - keep).collect();
+                    self.emit(&mut events, ready);
+                    return events;
+                }
+            }
+        }
+    }
+
+    /// Events for text held back at the end of the stream
+    pub fn finish(&mut self) -> Vec<StreamEvent> {
+        let mut events = Vec::new();
+        let rest = std::mem::take(&mut self.pending);
+        self.emit(&mut events, rest);
+        events
+    }
+
+    fn emit(&self, events: &mut Vec<StreamEvent>, text: String) {
+        if text.is_empty() {
+            return;
+        }
+        events.push(if self.in_think {
+            StreamEvent::ReasoningDelta(text)
+        } else {
+            StreamEvent::TextDelta(text)
+        });
+    }
+}
+
+#[async_trait]
+impl crate::providers::Provider for DeepSeekProvider {
+    async fn generate(&self, req: GenerateRequest) -> Result<GenerateResponse, ProviderError> {
+        self.inner.generate(req).await.map(separate_inline_thinking)
+    }
+
+    fn supports_stream_resume(&self) -> bool {
+        true
+    }
+
+    async fn generate_streaming(
+        &self,
+        req: GenerateRequest,
+        on_event: &mut (dyn FnMut(StreamEvent) + Send),
+    ) -> Result<GenerateResponse, ProviderError> {
+        let mut splitter = ThinkSplitter::default();
+        let response = {
+            let mut split = |event: StreamEvent| match event {
+                StreamEvent::TextDelta(d) => splitter.push(&d).into_iter().for_each(&mut *on_event),
+                // Flush held-back text before the stream ends
+                StreamEvent::Finished => {
+                    splitter.finish().into_iter().for_each(&mut *on_event);
+                    on_event(StreamEvent::Finished);
+                }
+                other => on_event(other),
+            };
+            self.inner.generate_streaming(req, &mut split).await?
+        };
+        splitter.finish().into_iter().for_each(&mut *on_event);
+        Ok(separate_inline_thinking(response))
+    }
+
+    async fn list_models(&self) -> Result<Vec<ModelInfo>, ProviderError> {
+        self.inner.list_models().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn collect(events: Vec<StreamEvent>) -> (String, String) {
+        let (mut text, mut thinking) = (String::new(), String::new());
+        for event in events {
+            match event {
+                StreamEvent::TextDelta(d) => text.push_str(&d),
+                StreamEvent::ReasoningDelta(d) => thinking.push_str(&d),
+                _ => {}
+            }
+        }
+        (text, thinking)
+    }
+
+    #[test]
+    fn test_think_tags_split_across_chunks() {
+        let mut splitter = ThinkSplitter::default();
+        let mut events = Vec::new();
+        for chunk in [
+            "<th",
+            "ink>plan ",
+            "it</thi",
+            "nk>\n```diff",
+            "\n+x\n```",
+            " <",
+        ] {
+            events.extend(splitter.push(chunk));
+        }
+        events.extend(splitter.finish());
+
+        let (text, thinking) = collect(events);
+        assert_eq!(thinking, "plan it");
+        assert_eq!(text, "\n```diff\n+x\n``` <");
+    }
+
+    #[test]
+    fn test_inline_thinking_moves_to_reasoning() {
+        let response = GenerateResponse {
+            text: "<think>why</think>\n\nanswer".to_string(),
+            tool_calls: Vec::new(),
+            usage: None,
+            raw: None,
+            provider: None,
+            reasoning: None,
+            logprobs: None,
+        };
+        let response = separate_inline_thinking(response);
+        assert_eq!(response.text, "answer");
+        assert_eq!(response.reasoning.as_deref(), Some("why"));
+
+        let plain = separate_inline_thinking(GenerateResponse {
+            text: "a < b".to_string(),
+            ..response
+        });
+        assert_eq!(plain.text, "a < b");
+    }
+}
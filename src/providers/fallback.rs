@@ -0,0 +1,242 @@
+//! Cross-provider fallback chain.
+//!
+//! A `FallbackProvider` holds an ordered list of backends (e.g. OpenRouter,
+//! then Anthropic, then a local Ollama model). Each call goes to the first
+//! backend; when it fails or times out the next one is tried, and so on.
+//! The response records which backend actually answered in
+//! `GenerateResponse::provider`.
+
+use async_trait::async_trait;
+use log::warn;
+use std::sync::Arc;
+
+use crate::code_generation::llm_logging::LlmLogger;
+use crate::core::error::ProviderError;
+use crate::providers::{GenerateRequest, GenerateResponse, ModelInfo, Provider, StreamEvent};
+
+/// Provider that fails over to the next backend in order
+pub struct FallbackProvider {
+    chain: Vec<(String, Box<dyn Provider>)>,
+    logger: Option<Arc<LlmLogger>>,
+}
+
+impl FallbackProvider {
+    /// Chain `primary` with no fallbacks yet
+    pub fn new(name: impl Into<String>, primary: Box<dyn Provider>) -> Self {
+        Self {
+            chain: vec![(name.into(), primary)],
+            logger: None,
+        }
+    }
+
+    /// Append a backend tried after all previously added ones
+    pub fn with_fallback(mut self, name: impl Into<String>, provider: Box<dyn Provider>) -> Self {
+        self.chain.push((name.into(), provider));
+        self
+    }
+
+    /// Record each failover in the LLM log
+    pub fn with_logger(mut self, logger: Arc<LlmLogger>) -> Self {
+        self.logger = Some(logger);
+        self
+    }
+
+    /// Names of the backends, in the order they are tried
+    pub fn names(&self) -> Vec<&str> {
+        self.chain.iter().map(|(name, _)| name.as_str()).collect()
+    }
+
+    fn note_failover(&self, index: usize, err: &ProviderError) {
+        let from = &self.chain[index].0;
+        let to = &self.chain[index + 1].0;
+        warn!(
+            "Provider '{}' failed ({}); falling back to '{}'",
+            from, err, to
+        );
+        if let Some(logger) = &self.logger {
+            if let Err(e) = logger.log_fallback(from, to, &err.to_string()) {
+                warn!("Failed to write fallback to LLM log: {}", e);
+            }
+        }
+    }
+
+    /// Tag `response` with the backend's name, unless a nested provider
+    /// (a race or the response cache) already recorded who answered
+    fn answered_by(name: &str, response: GenerateResponse) -> GenerateResponse {
+        GenerateResponse {
+            provider: response.provider.or_else(|| Some(name.to_string())),
+            ..response
+        }
+    }
+}
+
+#[async_trait]
+impl Provider for FallbackProvider {
+    async fn generate(&self, req: GenerateRequest) -> Result<GenerateResponse, ProviderError> {
+        let last = self.chain.len() - 1;
+        for (index, (name, provider)) in self.chain.iter().enumerate() {
+            match provider.generate(req.clone()).await {
+                Ok(response) => return Ok(Self::answered_by(name, response)),
+                Err(e) if index == last => return Err(e),
+                Err(e) => self.note_failover(index, &e),
+            }
+        }
+        unreachable!("fallback chain is never empty")
+    }
+
+    fn supports_stream_resume(&self) -> bool {
+        self.chain
+            .iter()
+            .all(|(_, provider)| provider.supports_stream_resume())
+    }
+
+    /// Models of the primary backend
+    async fn list_models(&self) -> Result<Vec<ModelInfo>, ProviderError> {
+        self.chain[0].1.list_models().await
+    }
+
+    /// Embeddings of the primary backend; vectors of different models are
+    /// not comparable, so there is no failover
+    async fn embed(&self, inputs: &[String]) -> Result<Vec<Vec<f32>>, ProviderError> {
+        self.chain[0].1.embed(inputs).await
+    }
+
+    /// Healthy when any backend in the chain is
+    async fn health(&self) -> Result<(), ProviderError> {
+        let last = self.chain.len() - 1;
+        for (index, (_, provider)) in self.chain.iter().enumerate() {
+            match provider.health().await {
+                Ok(()) => return Ok(()),
+                Err(e) if index == last => return Err(e),
+                Err(_) => {}
+            }
+        }
+        unreachable!("fallback chain is never empty")
+    }
+
+    async fn generate_streaming(
+        &self,
+        req: GenerateRequest,
+        on_event: &mut (dyn FnMut(StreamEvent) + Send),
+    ) -> Result<GenerateResponse, ProviderError> {
+        let last = self.chain.len() - 1;
+        for (index, (name, provider)) in self.chain.iter().enumerate() {
+            let mut emitted = false;
+            let result = {
+                let mut forward = |event: StreamEvent| {
+                    emitted = true;
+                    on_event(event);
+                };
+                provider.generate_streaming(req.clone(), &mut forward).await
+            };
+
+            match result {
+                Ok(response) => return Ok(Self::answered_by(name, response)),
+                // Switching backends after output reached the caller would
+                // interleave two different answers
+                Err(e) if emitted || index == last => return Err(e),
+                Err(e) => self.note_failover(index, &e),
+            }
+        }
+        unreachable!("fallback chain is never empty")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::providers::{ContentPart, Message, Role};
+
+    struct Fixed(Result<&'static str, u16>);
+
+    #[async_trait]
+    impl Provider for Fixed {
+        async fn generate(&self, _req: GenerateRequest) -> Result<GenerateResponse, ProviderError> {
+            match self.0 {
+                Ok(text) => Ok(GenerateResponse {
+                    text: text.to_string(),
+                    tool_calls: Vec::new(),
+                    usage: None,
+                    raw: None,
+                    provider: None,
+                    reasoning: None,
+                    logprobs: None,
+                }),
+                Err(status) => Err(ProviderError::ServerError {
+                    details: None,
+                    code: None,
+                    message: "down".to_string(),
+                    status: Some(status),
+                }),
+            }
+        }
+
+        async fn generate_streaming(
+            &self,
+            req: GenerateRequest,
+            on_event: &mut (dyn FnMut(StreamEvent) + Send),
+        ) -> Result<GenerateResponse, ProviderError> {
+            let res = self.generate(req).await?;
+            on_event(StreamEvent::TextDelta(res.text.clone()));
+            Ok(res)
+        }
+    }
+
+    fn req() -> GenerateRequest {
+        GenerateRequest {
+            system: None,
+            messages: vec![Message {
+                role: Role::User,
+                content: vec![ContentPart::Text {
+                    text: "hi".to_string(),
+                }],
+            }],
+            tools: None,
+            tool_choice: None,
+            temperature: None,
+            top_p: None,
+            stop: None,
+            seed: None,
+            logit_bias: None,
+            response_format: None,
+            max_output_tokens: None,
+            metadata: None,
+            logprobs: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_fails_over_in_order_and_records_answering_provider() {
+        let chain = FallbackProvider::new("openrouter", Box::new(Fixed(Err(503))))
+            .with_fallback("anthropic", Box::new(Fixed(Err(500))))
+            .with_fallback("local", Box::new(Fixed(Ok("from local"))));
+        assert_eq!(chain.names(), vec!["openrouter", "anthropic", "local"]);
+
+        let res = chain.generate(req()).await.expect("local answers");
+        assert_eq!(res.text, "from local");
+        assert_eq!(res.provider.as_deref(), Some("local"));
+
+        let mut text = String::new();
+        let mut on_event = |ev: StreamEvent| {
+            if let StreamEvent::TextDelta(d) = ev {
+                text.push_str(&d);
+            }
+        };
+        let res = chain
+            .generate_streaming(req(), &mut on_event)
+            .await
+            .expect("local streams");
+        assert_eq!(res.provider.as_deref(), Some("local"));
+        assert_eq!(text, "from local");
+    }
+
+    #[tokio::test]
+    async fn test_last_error_is_returned_when_all_fail() {
+        let chain = FallbackProvider::new("a", Box::new(Fixed(Err(503))))
+            .with_fallback("b", Box::new(Fixed(Err(502))));
+        match chain.generate(req()).await {
+            Err(ProviderError::ServerError { status, .. }) => assert_eq!(status, Some(502)),
+            other => panic!("unexpected: {:?}", other.map(|r| r.text)),
+        }
+    }
+}
@@ -0,0 +1,60 @@
+// File: src/providers/groq.rs
+use async_trait::async_trait;
+
+use crate::core::config::LlmConfig;
+use crate::core::error::ProviderError;
+use crate::providers::openai_compat::{Dialect, OpenAiCompatProvider};
+use crate::providers::{GenerateRequest, GenerateResponse, ModelInfo, StreamEvent};
+
+/// Groq adapter.
+///
+/// OpenAI-compatible chat completions at `https://api.groq.com/openai/v1`,
+/// except that the output limit is sent as `max_completion_tokens` and at
+/// most four stop sequences are accepted. Streaming usage arrives under
+/// `x_groq.usage` on the final chunk.
+pub struct GroqProvider {
+    inner: OpenAiCompatProvider,
+}
+
+impl GroqProvider {
+    /// Create a Groq provider from LlmConfig (key falls back to `GROQ_API_KEY`)
+    pub fn from_config(cfg: &LlmConfig) -> Result<Self, ProviderError> {
+        let dialect = Dialect {
+            name: "Groq",
+            default_base: "https://api.groq.com/openai/v1",
+            key_env: "GROQ_API_KEY",
+            max_tokens_field: "max_completion_tokens",
+            seed_field: "seed",
+            max_stop_sequences: Some(4),
+            required_tool_choice: "required",
+            logprobs: false,
+            reasoning_effort: false,
+        };
+        Ok(Self {
+            inner: OpenAiCompatProvider::from_config(cfg, dialect)?,
+        })
+    }
+}
+
+#[async_trait]
+impl crate::providers::Provider for GroqProvider {
+    async fn generate(&self, req: GenerateRequest) -> Result<GenerateResponse, ProviderError> {
+        self.inner.generate(req).await
+    }
+
+    fn supports_stream_resume(&self) -> bool {
+        true
+    }
+
+    async fn generate_streaming(
+        &self,
+        req: GenerateRequest,
+        on_event: &mut (dyn FnMut(StreamEvent) + Send),
+    ) -> Result<GenerateResponse, ProviderError> {
+        self.inner.generate_streaming(req, on_event).await
+    }
+
+    async fn list_models(&self) -> Result<Vec<ModelInfo>, ProviderError> {
+        self.inner.list_models().await
+    }
+}
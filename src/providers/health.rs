@@ -0,0 +1,137 @@
+//! Pre-run provider checks.
+//!
+//! `check_models` builds a bare provider for every configured model and
+//! verifies that it is reachable and accepts our credentials
+//! (`Provider::health`) and that the configured model id is actually served
+//! (`Provider::list_models`). `borg providers check` prints the results so
+//! a typo in a key or model id shows up before a run starts, not halfway in.
+
+use futures::future::join_all;
+use std::time::Instant;
+
+use crate::code_generation::llm::LlmFactory;
+use crate::core::config::{Config, ModelConfig};
+use crate::core::error::ProviderError;
+use crate::providers::{ModelInfo, Provider, UNSUPPORTED_CODE};
+
+/// Outcome of checking one model entry
+#[derive(Debug, Clone, PartialEq)]
+pub enum CheckStatus {
+    /// Reachable, authenticated and the model is listed
+    Ok,
+    /// Reachable, but the provider does not offer the configured model
+    ModelNotFound,
+    /// The provider cannot list its models, so the id was not verified
+    Unverified,
+    /// Unreachable, rejected our credentials, or misconfigured
+    Failed(String),
+}
+
+/// Result of checking one configured model
+#[derive(Debug, Clone)]
+pub struct ModelCheck {
+    /// `ModelConfig::name`
+    pub name: String,
+
+    /// Provider the model is served by
+    pub provider: String,
+
+    /// Provider model id
+    pub model: String,
+
+    /// What the check found
+    pub status: CheckStatus,
+
+    /// Time taken by the check
+    pub latency_ms: u64,
+}
+
+impl ModelCheck {
+    /// Whether a run can rely on this model
+    pub fn passed(&self) -> bool {
+        matches!(self.status, CheckStatus::Ok | CheckStatus::Unverified)
+    }
+}
+
+fn is_unsupported(err: &ProviderError) -> bool {
+    matches!(
+        err,
+        ProviderError::InvalidParams { code: Some(code), .. } if code == UNSUPPORTED_CODE
+    )
+}
+
+/// Whether `id` is among `models`
+///
+/// Ollama serves untagged names as `:latest`, and `-latest` aliases
+/// (Anthropic) resolve to any dated snapshot of the same model.
+pub fn model_listed(models: &[ModelInfo], id: &str) -> bool {
+    let alias = id.strip_suffix("-latest");
+    models.iter().any(|m| {
+        m.id == id
+            || m.id == format!("{}:latest", id)
+            || alias.is_some_and(|base| m.id.starts_with(base))
+    })
+}
+
+/// Check that `provider` is healthy and serves `model`
+pub async fn check_provider(provider: &dyn Provider, model: &str) -> CheckStatus {
+    match provider.health().await {
+        Ok(()) => {}
+        Err(e) if is_unsupported(&e) => return CheckStatus::Unverified,
+        Err(e) => return CheckStatus::Failed(e.to_string()),
+    }
+    match provider.list_models().await {
+        Ok(models) if model_listed(&models, model) => CheckStatus::Ok,
+        Ok(_) => CheckStatus::ModelNotFound,
+        Err(e) if is_unsupported(&e) => CheckStatus::Unverified,
+        Err(e) => CheckStatus::Failed(e.to_string()),
+    }
+}
+
+async fn check_model(model_config: &ModelConfig) -> ModelCheck {
+    let started = Instant::now();
+    let status = match LlmFactory::discovery_provider(model_config) {
+        Ok(provider) => check_provider(provider.as_ref(), &model_config.model).await,
+        Err(e) => CheckStatus::Failed(format!("{:#}", e)),
+    };
+    ModelCheck {
+        name: model_config.name.clone(),
+        provider: model_config.provider.clone(),
+        model: model_config.model.clone(),
+        status,
+        latency_ms: started.elapsed().as_millis() as u64,
+    }
+}
+
+/// Check every configured model, concurrently, in configuration order
+pub async fn check_models(config: &Config) -> Vec<ModelCheck> {
+    join_all(config.models.iter().map(check_model)).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn listed(ids: &[&str]) -> Vec<ModelInfo> {
+        ids.iter()
+            .map(|id| ModelInfo {
+                id: id.to_string(),
+                context_length: None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_model_listed_accepts_tags_and_aliases() {
+        let models = listed(&[
+            "llama3:latest",
+            "claude-3-7-sonnet-20250219",
+            "openai/gpt-4o",
+        ]);
+        assert!(model_listed(&models, "openai/gpt-4o"));
+        assert!(model_listed(&models, "llama3"));
+        assert!(model_listed(&models, "claude-3-7-sonnet-latest"));
+        assert!(!model_listed(&models, "gpt-4o"));
+        assert!(!model_listed(&models, "claude-3-5-haiku-latest"));
+    }
+}
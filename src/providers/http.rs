@@ -0,0 +1,107 @@
+//! HTTP client construction shared by every provider.
+//!
+//! Applies the model's `NetworkConfig`: an explicit proxy (with bypass list),
+//! extra trusted CA certificates for TLS-intercepting proxies, and a client
+//! certificate for mutual TLS. Without one, reqwest's defaults apply,
+//! including the `HTTPS_PROXY`/`HTTP_PROXY` environment variables.
+
+use reqwest::{Certificate, Client, Identity, Proxy};
+use std::time::Duration;
+
+use crate::core::config::NetworkConfig;
+use crate::core::error::ProviderError;
+
+/// Client with `timeout` and the proxy/TLS settings in `network`
+pub fn build_client(
+    network: Option<&NetworkConfig>,
+    timeout: Duration,
+) -> Result<Client, ProviderError> {
+    let mut builder = Client::builder().timeout(timeout);
+
+    if let Some(network) = network {
+        if let Some(url) = &network.proxy {
+            let proxy = Proxy::all(url)
+                .map_err(|e| invalid(format!("Invalid proxy URL '{}': {}", url, e)))?
+                .no_proxy(
+                    network
+                        .no_proxy
+                        .as_deref()
+                        .and_then(reqwest::NoProxy::from_string),
+                );
+            builder = builder.proxy(proxy);
+        }
+
+        if let Some(path) = &network.ca_bundle {
+            let pem = read(path, "CA bundle")?;
+            let certs = Certificate::from_pem_bundle(&pem)
+                .map_err(|e| invalid(format!("Invalid CA bundle '{}': {}", path, e)))?;
+            for cert in certs {
+                builder = builder.add_root_certificate(cert);
+            }
+        }
+
+        match (&network.client_cert, &network.client_key) {
+            (Some(cert_path), Some(key_path)) => {
+                let cert = read(cert_path, "client certificate")?;
+                let key = read(key_path, "client key")?;
+                let identity = Identity::from_pkcs8_pem(&cert, &key).map_err(|e| {
+                    invalid(format!("Invalid client certificate '{}': {}", cert_path, e))
+                })?;
+                builder = builder.identity(identity);
+            }
+            (None, None) => {}
+            _ => {
+                return Err(invalid(
+                    "client_cert and client_key must be configured together".to_string(),
+                ))
+            }
+        }
+    }
+
+    builder.build().map_err(|e| ProviderError::Network {
+        message: format!("Failed to create HTTP client: {}", e),
+    })
+}
+
+fn read(path: &str, what: &str) -> Result<Vec<u8>, ProviderError> {
+    std::fs::read(path).map_err(|e| invalid(format!("Cannot read {} '{}': {}", what, path, e)))
+}
+
+fn invalid(message: String) -> ProviderError {
+    ProviderError::InvalidParams {
+        details: None,
+        code: None,
+        message,
+        status: None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_network_settings_are_validated() {
+        assert!(build_client(None, Duration::from_secs(1)).is_ok());
+
+        let proxied = NetworkConfig {
+            proxy: Some("http://proxy.corp:3128".to_string()),
+            no_proxy: Some("localhost,127.0.0.1".to_string()),
+            ..Default::default()
+        };
+        assert!(build_client(Some(&proxied), Duration::from_secs(1)).is_ok());
+
+        let missing_ca = NetworkConfig {
+            ca_bundle: Some("/nonexistent/ca.pem".to_string()),
+            ..Default::default()
+        };
+        let err = build_client(Some(&missing_ca), Duration::from_secs(1)).unwrap_err();
+        assert!(err.to_string().contains("CA bundle"), "{}", err);
+
+        let half_identity = NetworkConfig {
+            client_cert: Some("cert.pem".to_string()),
+            ..Default::default()
+        };
+        assert!(build_client(Some(&half_identity), Duration::from_secs(1)).is_err());
+    }
+}
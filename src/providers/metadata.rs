@@ -0,0 +1,116 @@
+//! Per-request metadata forwarded to providers as HTTP headers.
+//!
+//! Static headers come from the model config. `scope` attaches dynamic
+//! attribution (goal, swarm agent) to every LLM call made while a future
+//! runs, and `for_request` combines both with a fresh request id. Providers
+//! forward the result with `apply_headers`, so individual calls can be traced
+//! to goals and agents in provider dashboards and proxy logs.
+
+use std::future::Future;
+
+use crate::providers::Metadata;
+
+/// Header carrying a unique id per LLM call
+pub const REQUEST_ID_HEADER: &str = "X-Request-Id";
+
+/// Header carrying the goal or proposal an LLM call works on
+pub const GOAL_ID_HEADER: &str = "X-Borg-Goal-Id";
+
+/// Header carrying the agent (phase and model) making an LLM call
+pub const AGENT_HEADER: &str = "X-Borg-Agent";
+
+tokio::task_local! {
+    static SCOPED: Metadata;
+}
+
+/// Run `fut` with `entries` attached to every LLM call it makes
+///
+/// Scopes nest: inner entries are added to (and override) outer ones.
+pub async fn scope<F: Future>(entries: Metadata, fut: F) -> F::Output {
+    let mut merged = current();
+    merged.extend(entries);
+    SCOPED.scope(merged, fut).await
+}
+
+/// Metadata attached by the enclosing `scope`s, if any
+pub fn current() -> Metadata {
+    SCOPED.try_with(|m| m.clone()).unwrap_or_default()
+}
+
+/// Attribution entries for `agent` working on `goal_id`
+pub fn attribution(goal_id: Option<&str>, agent: &str) -> Metadata {
+    let mut entries = Metadata::from([(AGENT_HEADER.to_string(), agent.to_string())]);
+    if let Some(goal_id) = goal_id {
+        entries.insert(GOAL_ID_HEADER.to_string(), goal_id.to_string());
+    }
+    entries
+}
+
+/// Metadata for one call: `base` overlaid with the scoped entries and a
+/// fresh request id
+pub fn for_request(base: Option<&Metadata>) -> Metadata {
+    let mut meta = base.cloned().unwrap_or_default();
+    meta.extend(current());
+    meta.insert(
+        REQUEST_ID_HEADER.to_string(),
+        uuid::Uuid::new_v4().to_string(),
+    );
+    meta
+}
+
+/// Add `meta` to `rb` as headers
+///
+/// Names in `reserved` (auth, content negotiation) are never overridden, and
+/// entries that are not valid header names or values are skipped rather
+/// than failing the request.
+pub fn apply_headers(
+    mut rb: reqwest::RequestBuilder,
+    meta: Option<&Metadata>,
+    reserved: &[&str],
+) -> reqwest::RequestBuilder {
+    let Some(meta) = meta else {
+        return rb;
+    };
+    for (k, v) in meta {
+        if reserved.iter().any(|r| k.eq_ignore_ascii_case(r)) {
+            continue;
+        }
+        match (
+            reqwest::header::HeaderName::from_bytes(k.as_bytes()),
+            reqwest::header::HeaderValue::from_str(v),
+        ) {
+            (Ok(name), Ok(value)) => rb = rb.header(name, value),
+            _ => log::debug!("Skipping metadata '{}' that is not a valid header", k),
+        }
+    }
+    rb
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_scopes_nest_and_feed_each_request() {
+        assert!(current().is_empty());
+
+        let base = Metadata::from([("X-Title".to_string(), "borg".to_string())]);
+        let meta = scope(attribution(None, "research:gpt"), async {
+            scope(attribution(Some("goal-7"), "tdd:gpt"), async {
+                for_request(Some(&base))
+            })
+            .await
+        })
+        .await;
+
+        assert_eq!(meta.get("X-Title").map(String::as_str), Some("borg"));
+        assert_eq!(meta.get(GOAL_ID_HEADER).map(String::as_str), Some("goal-7"));
+        assert_eq!(meta.get(AGENT_HEADER).map(String::as_str), Some("tdd:gpt"));
+        assert!(meta.contains_key(REQUEST_ID_HEADER));
+        assert_ne!(
+            for_request(None).get(REQUEST_ID_HEADER),
+            for_request(None).get(REQUEST_ID_HEADER)
+        );
+        assert!(current().is_empty());
+    }
+}
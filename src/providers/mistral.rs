@@ -0,0 +1,63 @@
+// File: src/providers/mistral.rs
+use async_trait::async_trait;
+
+use crate::core::config::LlmConfig;
+use crate::core::error::ProviderError;
+use crate::providers::openai_compat::{Dialect, OpenAiCompatProvider};
+use crate::providers::{GenerateRequest, GenerateResponse, ModelInfo, StreamEvent};
+
+/// Mistral adapter.
+///
+/// OpenAI-compatible chat completions at `https://api.mistral.ai/v1`, except
+/// that the seed is sent as `random_seed` and a forced tool call is
+/// requested with `tool_choice: "any"` rather than `"required"`.
+pub struct MistralProvider {
+    inner: OpenAiCompatProvider,
+}
+
+impl MistralProvider {
+    /// Create a Mistral provider from LlmConfig (key falls back to `MISTRAL_API_KEY`)
+    pub fn from_config(cfg: &LlmConfig) -> Result<Self, ProviderError> {
+        let dialect = Dialect {
+            name: "Mistral",
+            default_base: "https://api.mistral.ai/v1",
+            key_env: "MISTRAL_API_KEY",
+            max_tokens_field: "max_tokens",
+            seed_field: "random_seed",
+            max_stop_sequences: None,
+            required_tool_choice: "any",
+            logprobs: false,
+            reasoning_effort: false,
+        };
+        Ok(Self {
+            inner: OpenAiCompatProvider::from_config(cfg, dialect)?,
+        })
+    }
+}
+
+#[async_trait]
+impl crate::providers::Provider for MistralProvider {
+    async fn generate(&self, req: GenerateRequest) -> Result<GenerateResponse, ProviderError> {
+        self.inner.generate(req).await
+    }
+
+    fn supports_stream_resume(&self) -> bool {
+        true
+    }
+
+    async fn generate_streaming(
+        &self,
+        req: GenerateRequest,
+        on_event: &mut (dyn FnMut(StreamEvent) + Send),
+    ) -> Result<GenerateResponse, ProviderError> {
+        self.inner.generate_streaming(req, on_event).await
+    }
+
+    async fn list_models(&self) -> Result<Vec<ModelInfo>, ProviderError> {
+        self.inner.list_models().await
+    }
+
+    async fn embed(&self, inputs: &[String]) -> Result<Vec<Vec<f32>>, ProviderError> {
+        self.inner.embed(inputs).await
+    }
+}
@@ -7,9 +7,34 @@ use std::collections::HashMap;
 use crate::core::error::ProviderError;
 
 pub mod anthropic;
+pub mod azure_openai;
+pub mod batch;
+pub mod cache;
+pub mod capabilities;
+pub mod conversation;
+pub mod deepseek;
+pub mod fallback;
+pub mod filter;
+pub mod groq;
+pub mod health;
+pub mod http;
+pub mod metadata;
+pub mod mistral;
 pub mod ollama;
+pub mod openai;
+pub(crate) mod openai_compat;
 pub mod openrouter;
+pub mod racing;
 pub mod rate_limiter;
+pub mod resume;
+pub mod retry;
+pub mod schema;
+pub mod sse;
+pub mod tokenizer;
+
+pub use sse::{SseDecoder, SseEvent};
+pub use tokenizer::count_tokens;
+
 /// Common metadata map for provider hints/headers
 pub type Metadata = HashMap<String, String>;
 
@@ -35,6 +60,185 @@ pub enum ContentPart {
         #[serde(default)]
         mime: Option<String>,
     },
+    /// Marks the end of a prompt prefix that stays the same across calls
+    ///
+    /// Providers with prompt caching (Anthropic) cache everything up to and
+    /// including the preceding part; the others ignore the marker.
+    CacheBreakpoint,
+    /// A tool call the assistant made (in `Role::Assistant` messages)
+    ToolCall {
+        id: String,
+        name: String,
+        arguments: JsonValue,
+    },
+    /// The outcome of the tool call `call_id` (in `Role::Tool` messages)
+    ToolResult {
+        call_id: String,
+        content: String,
+        #[serde(default)]
+        is_error: bool,
+    },
+}
+
+impl ContentPart {
+    /// Inline image from raw bytes, carried as a base64 `data:` URL
+    pub fn image_base64(mime: &str, bytes: &[u8]) -> Self {
+        use base64::Engine;
+        ContentPart::ImageUrl {
+            url: format!(
+                "data:{};base64,{}",
+                mime,
+                base64::engine::general_purpose::STANDARD.encode(bytes)
+            ),
+            mime: Some(mime.to_string()),
+        }
+    }
+
+    /// Inline image read from disk (e.g. a screenshot or diagram)
+    ///
+    /// The media type is derived from the file extension.
+    pub fn image_file(path: &std::path::Path) -> std::io::Result<Self> {
+        let ext = path
+            .extension()
+            .and_then(|e| e.to_str())
+            .map(|e| e.to_ascii_lowercase())
+            .unwrap_or_default();
+        let mime = match ext.as_str() {
+            "png" => "image/png",
+            "jpg" | "jpeg" => "image/jpeg",
+            "gif" => "image/gif",
+            "webp" => "image/webp",
+            other => {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::InvalidInput,
+                    format!("unsupported image type '{}'", other),
+                ))
+            }
+        };
+        Ok(Self::image_base64(mime, &std::fs::read(path)?))
+    }
+}
+
+/// Where an image part's pixels come from
+#[derive(Debug, Clone, PartialEq)]
+pub enum ImageSource<'a> {
+    /// Remote image fetched by the provider
+    Url(&'a str),
+    /// Inline base64 data
+    Base64 { media_type: String, data: &'a str },
+}
+
+/// Classify an image URL, unpacking base64 `data:` URLs
+pub fn image_source<'a>(url: &'a str, mime: &Option<String>) -> ImageSource<'a> {
+    if let Some(rest) = url.strip_prefix("data:") {
+        if let Some((header, data)) = rest.split_once(',') {
+            if let Some(media_type) = header.strip_suffix(";base64") {
+                let media_type = if media_type.is_empty() {
+                    mime.clone().unwrap_or_else(|| "image/png".to_string())
+                } else {
+                    media_type.to_string()
+                };
+                return ImageSource::Base64 { media_type, data };
+            }
+        }
+    }
+    ImageSource::Url(url)
+}
+
+/// OpenAI chat `content`: a plain string for text-only messages, otherwise
+/// an array of `text` and `image_url` parts
+pub(crate) fn openai_chat_content(parts: &[ContentPart]) -> JsonValue {
+    if !parts
+        .iter()
+        .any(|p| matches!(p, ContentPart::ImageUrl { .. }))
+    {
+        let text = parts
+            .iter()
+            .filter_map(|p| match p {
+                ContentPart::Text { text } => Some(text.as_str()),
+                _ => None,
+            })
+            .collect::<Vec<_>>()
+            .join(" ");
+        return json!(text);
+    }
+
+    json!(parts
+        .iter()
+        .filter_map(|p| match p {
+            ContentPart::Text { text } => Some(json!({"type": "text", "text": text})),
+            // OpenAI accepts both remote and data: URLs here
+            ContentPart::ImageUrl { url, .. } => {
+                Some(json!({"type": "image_url", "image_url": {"url": url}}))
+            }
+            // Tool calls and results travel as dedicated message fields
+            ContentPart::CacheBreakpoint
+            | ContentPart::ToolCall { .. }
+            | ContentPart::ToolResult { .. } => None,
+        })
+        .collect::<Vec<_>>())
+}
+
+/// Anthropic accepts at most this many `cache_control` breakpoints per request
+pub(crate) const ANTHROPIC_MAX_CACHE_BREAKPOINTS: usize = 4;
+
+/// Anthropic content blocks, with images as `url` or `base64` sources and
+/// tool calls and results as `tool_use` and `tool_result` blocks
+///
+/// A `CacheBreakpoint` puts `cache_control` on the block before it; ones past
+/// `breakpoints_left` are dropped (the counter is shared across messages).
+pub(crate) fn anthropic_content(
+    parts: &[ContentPart],
+    breakpoints_left: &mut usize,
+) -> Vec<JsonValue> {
+    let mut out: Vec<JsonValue> = Vec::new();
+    for p in parts {
+        match p {
+            ContentPart::Text { text } => out.push(json!({"type": "text", "text": text})),
+            ContentPart::ImageUrl { url, mime } => out.push(match image_source(url, mime) {
+                ImageSource::Url(url) => json!({
+                    "type": "image",
+                    "source": {"type": "url", "url": url}
+                }),
+                ImageSource::Base64 { media_type, data } => json!({
+                    "type": "image",
+                    "source": {"type": "base64", "media_type": media_type, "data": data}
+                }),
+            }),
+            ContentPart::ToolCall {
+                id,
+                name,
+                arguments,
+            } => out.push(json!({
+                "type": "tool_use",
+                "id": id,
+                "name": name,
+                "input": arguments
+            })),
+            ContentPart::ToolResult {
+                call_id,
+                content,
+                is_error,
+            } => out.push(json!({
+                "type": "tool_result",
+                "tool_use_id": call_id,
+                "content": content,
+                "is_error": is_error
+            })),
+            ContentPart::CacheBreakpoint => {
+                if *breakpoints_left == 0 {
+                    continue;
+                }
+                if let Some(block) = out.last_mut().and_then(|b| b.as_object_mut()) {
+                    if !block.contains_key("cache_control") {
+                        block.insert("cache_control".into(), json!({"type": "ephemeral"}));
+                        *breakpoints_left -= 1;
+                    }
+                }
+            }
+        }
+    }
+    out
 }
 
 /// Canonical message (role + parts)
@@ -111,7 +315,7 @@ pub struct ToolCallNormalized {
 }
 
 /// Canonical usage counters
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct Usage {
     #[serde(default)]
     pub prompt_tokens: Option<u32>,
@@ -119,6 +323,15 @@ pub struct Usage {
     pub completion_tokens: Option<u32>,
     #[serde(default)]
     pub total_tokens: Option<u32>,
+    /// Retries needed before the call succeeded (set by `retry::RetryingProvider`)
+    #[serde(default)]
+    pub retries: Option<u32>,
+    /// Prompt tokens served from the provider's prompt cache
+    #[serde(default)]
+    pub cache_read_tokens: Option<u32>,
+    /// Prompt tokens written to the provider's prompt cache
+    #[serde(default)]
+    pub cache_write_tokens: Option<u32>,
 }
 
 /// Canonical generate request
@@ -153,6 +366,11 @@ pub struct GenerateRequest {
     /// Optional provider metadata (headers, extra flags)
     #[serde(default)]
     pub metadata: Option<Metadata>,
+
+    /// Return per-token log probabilities with this many top alternatives
+    /// (0 for the chosen tokens only); providers without logprobs ignore it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub logprobs: Option<u8>,
 }
 
 /// Canonical generate response
@@ -167,6 +385,59 @@ pub struct GenerateResponse {
     /// Provider raw JSON for diagnostics
     #[serde(default)]
     pub raw: Option<JsonValue>,
+    /// Which backend answered, when a composite (e.g. a fallback chain) chose one
+    #[serde(default)]
+    pub provider: Option<String>,
+    /// Reasoning/thinking text the model produced before its answer
+    #[serde(default)]
+    pub reasoning: Option<String>,
+    /// Per-token log probabilities, when requested and supported
+    #[serde(default)]
+    pub logprobs: Option<Vec<TokenLogprob>>,
+}
+
+/// Log probability of one output token
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TokenLogprob {
+    pub token: String,
+    pub logprob: f64,
+    /// Most likely alternatives at this position, best first
+    #[serde(default)]
+    pub top: Vec<TopLogprob>,
+}
+
+/// An alternative token the model considered
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TopLogprob {
+    pub token: String,
+    pub logprob: f64,
+}
+
+impl GenerateResponse {
+    /// Whether the response carries neither text nor tool calls
+    pub fn is_empty(&self) -> bool {
+        self.text.trim().is_empty() && self.tool_calls.is_empty()
+    }
+
+    /// Model confidence in its output: the geometric mean of the token
+    /// probabilities, in `(0, 1]`; `None` without logprobs
+    pub fn confidence(&self) -> Option<f64> {
+        let logprobs = self.logprobs.as_ref().filter(|l| !l.is_empty())?;
+        let mean = logprobs.iter().map(|t| t.logprob).sum::<f64>() / logprobs.len() as f64;
+        Some(mean.exp())
+    }
+
+    /// Reject a response without usable content (no choices, blank text, no
+    /// tool calls) with the uniform `EmptyResponse` error
+    pub fn non_empty(self, provider: &str) -> Result<Self, ProviderError> {
+        if self.is_empty() {
+            Err(ProviderError::EmptyResponse {
+                message: format!("{} returned no usable content", provider),
+            })
+        } else {
+            Ok(self)
+        }
+    }
 }
 
 /// Unified streaming event model
@@ -174,6 +445,7 @@ pub struct GenerateResponse {
 #[serde(tag = "event", content = "data", rename_all = "snake_case")]
 pub enum StreamEvent {
     TextDelta(String),
+    ReasoningDelta(String),
     ToolDelta(String),
     ToolCall(ToolCallNormalized),
     Usage(Usage),
@@ -181,6 +453,20 @@ pub enum StreamEvent {
     Error(String),
 }
 
+/// `ProviderError` code for operations a provider does not implement
+pub const UNSUPPORTED_CODE: &str = "unsupported";
+
+/// A model advertised by a provider
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ModelInfo {
+    /// Model id as accepted in requests
+    pub id: String,
+
+    /// Context window in tokens, when the provider reports it
+    #[serde(default)]
+    pub context_length: Option<usize>,
+}
+
 /// Provider trait for canonical generate APIs
 #[async_trait]
 pub trait Provider: Send + Sync {
@@ -194,32 +480,163 @@ pub trait Provider: Send + Sync {
         req: GenerateRequest,
         on_event: &mut (dyn FnMut(StreamEvent) + Send),
     ) -> Result<GenerateResponse, ProviderError>;
+
+    /// Whether an interrupted stream can be continued by resending the
+    /// partial output as a trailing assistant message (see `resume`).
+    /// Such providers must emit `StreamEvent::Finished` on normal completion.
+    fn supports_stream_resume(&self) -> bool {
+        false
+    }
+
+    /// Answer many independent requests; results come back in request order
+    ///
+    /// The default fans the requests out over `generate`, at most
+    /// `batch::DEFAULT_BATCH_CONCURRENCY` at a time. Providers with a native
+    /// batch endpoint override this and report `supports_native_batch`.
+    async fn generate_batch(
+        &self,
+        reqs: Vec<GenerateRequest>,
+    ) -> Vec<Result<GenerateResponse, ProviderError>> {
+        batch::generate_concurrently(self, reqs, batch::DEFAULT_BATCH_CONCURRENCY).await
+    }
+
+    /// Whether `generate_batch` uses a provider-side batch endpoint rather
+    /// than fanning out over `generate`
+    fn supports_native_batch(&self) -> bool {
+        false
+    }
+
+    /// Models this provider can serve
+    async fn list_models(&self) -> Result<Vec<ModelInfo>, ProviderError> {
+        Err(ProviderError::InvalidParams {
+            details: None,
+            code: Some(UNSUPPORTED_CODE.to_string()),
+            message: "model discovery is not supported by this provider".to_string(),
+            status: None,
+        })
+    }
+
+    /// Verify that the provider is reachable and accepts our credentials
+    async fn health(&self) -> Result<(), ProviderError> {
+        self.list_models().await.map(|_| ())
+    }
+
+    /// Embedding vectors of `inputs` from the configured model, one per
+    /// input and in the same order
+    async fn embed(&self, _inputs: &[String]) -> Result<Vec<Vec<f32>>, ProviderError> {
+        Err(ProviderError::InvalidParams {
+            details: None,
+            code: Some(UNSUPPORTED_CODE.to_string()),
+            message: "embeddings are not supported by this provider".to_string(),
+            status: None,
+        })
+    }
+}
+
+/// POST `payload` to `rb` and parse the JSON body, mapping HTTP failures
+/// with `map_error`
+pub(crate) async fn post_json(
+    rb: reqwest::RequestBuilder,
+    payload: &JsonValue,
+    provider: &str,
+    map_error: impl FnOnce(u16, String) -> ProviderError,
+) -> Result<JsonValue, ProviderError> {
+    get_json(rb.json(payload), provider, map_error).await
+}
+
+/// The vectors of an embeddings response, checking that there is one per
+/// input
+pub(crate) fn parse_embeddings(
+    vectors: Option<&JsonValue>,
+    inputs: usize,
+    provider: &str,
+) -> Result<Vec<Vec<f32>>, ProviderError> {
+    let vectors: Vec<Vec<f32>> = vectors
+        .and_then(|v| v.as_array())
+        .map(|vectors| {
+            vectors
+                .iter()
+                .map(|v| {
+                    v.as_array()
+                        .map(|xs| {
+                            xs.iter()
+                                .filter_map(|x| x.as_f64())
+                                .map(|x| x as f32)
+                                .collect()
+                        })
+                        .unwrap_or_default()
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+    if vectors.len() != inputs || vectors.iter().any(|v| v.is_empty()) {
+        return Err(ProviderError::Network {
+            message: format!(
+                "{} returned {} embeddings for {} inputs",
+                provider,
+                vectors.len(),
+                inputs
+            ),
+        });
+    }
+    Ok(vectors)
+}
+
+/// GET `rb` and parse the JSON body, mapping HTTP failures with `map_error`
+pub(crate) async fn get_json(
+    rb: reqwest::RequestBuilder,
+    provider: &str,
+    map_error: impl FnOnce(u16, String) -> ProviderError,
+) -> Result<JsonValue, ProviderError> {
+    let resp = rb.send().await.map_err(|e| ProviderError::Network {
+        message: format!("{} network error: {}", provider, e),
+    })?;
+    let status = resp.status().as_u16();
+    let text = resp.text().await.map_err(|e| ProviderError::Network {
+        message: format!("Failed reading {} response: {}", provider, e),
+    })?;
+    if !(200..300).contains(&status) {
+        return Err(map_error(status, text));
+    }
+    serde_json::from_str(&text).map_err(|e| ProviderError::Network {
+        message: format!("Invalid JSON from {}: {}", provider, e),
+    })
+}
+
+/// Stream from `provider` until done or until `cancel` fires
+///
+/// Cancelling drops the in-flight request future, which closes the HTTP
+/// connection instead of draining the rest of the stream.
+pub async fn generate_streaming_cancellable(
+    provider: &dyn Provider,
+    req: GenerateRequest,
+    on_event: &mut (dyn FnMut(StreamEvent) + Send),
+    cancel: &tokio_util::sync::CancellationToken,
+) -> Result<GenerateResponse, ProviderError> {
+    tokio::select! {
+        biased;
+        _ = cancel.cancelled() => Err(ProviderError::Cancelled {
+            message: "generation cancelled mid-stream".to_string(),
+        }),
+        res = provider.generate_streaming(req, on_event) => res,
+    }
 }
 
 /// Mapping helpers (RFC: canonical -> provider requests)
 pub fn map_internal_to_openai_chat(req: &GenerateRequest) -> JsonValue {
-    // Messages mapping: flatten text; images skipped for now
+    // Messages mapping: text-only content stays a string, images become parts
     let mut messages: Vec<JsonValue> = Vec::new();
     if let Some(sys) = &req.system {
         messages.push(json!({"role":"system","content": sys}));
     }
     for m in &req.messages {
-        let text = m
-            .content
-            .iter()
-            .filter_map(|p| match p {
-                ContentPart::Text { text } => Some(text.as_str()),
-                ContentPart::ImageUrl { .. } => None,
-            })
-            .collect::<Vec<_>>()
-            .join(" ");
         let role = match m.role {
             Role::System => "system",
             Role::User => "user",
             Role::Assistant => "assistant",
             Role::Tool => "tool",
         };
-        messages.push(json!({"role": role, "content": text}));
+        messages.push(json!({"role": role, "content": openai_chat_content(&m.content)}));
     }
 
     json!({
@@ -248,7 +665,7 @@ pub fn map_internal_to_openai_responses(req: &GenerateRequest) -> JsonValue {
                 .iter()
                 .filter_map(|p| match p {
                     ContentPart::Text { text } => Some(text.as_str()),
-                    ContentPart::ImageUrl { .. } => None,
+                    _ => None,
                 })
                 .collect::<Vec<_>>()
                 .join(" ");
@@ -259,8 +676,29 @@ pub fn map_internal_to_openai_responses(req: &GenerateRequest) -> JsonValue {
         buf
     };
 
+    let images: Vec<JsonValue> = req
+        .messages
+        .iter()
+        .flat_map(|m| m.content.iter())
+        .filter_map(|p| match p {
+            ContentPart::ImageUrl { url, .. } => {
+                Some(json!({"type": "input_image", "image_url": url}))
+            }
+            _ => None,
+        })
+        .collect();
+
+    // Images need the structured input form; text-only stays a plain string
+    let input = if images.is_empty() {
+        json!(user_text)
+    } else {
+        let mut content = vec![json!({"type": "input_text", "text": user_text})];
+        content.extend(images);
+        json!([{"role": "user", "content": content}])
+    };
+
     json!({
-        "input": user_text,
+        "input": input,
         "max_output_tokens": req.max_output_tokens.unwrap_or(1024),
         "temperature": req.temperature.unwrap_or(0.7),
     })
@@ -268,27 +706,21 @@ pub fn map_internal_to_openai_responses(req: &GenerateRequest) -> JsonValue {
 
 pub fn map_internal_to_anthropic(req: &GenerateRequest) -> JsonValue {
     let mut messages: Vec<JsonValue> = Vec::new();
+    let mut breakpoints_left = ANTHROPIC_MAX_CACHE_BREAKPOINTS;
     // Anthropic treats system special; newer API can include it separately
     if let Some(sys) = &req.system {
         messages.push(json!({"role": "system", "content": sys}));
     }
     for m in &req.messages {
-        let text = m
-            .content
-            .iter()
-            .filter_map(|p| match p {
-                ContentPart::Text { text } => Some(text.as_str()),
-                ContentPart::ImageUrl { .. } => None,
-            })
-            .collect::<Vec<_>>()
-            .join(" ");
         let role = match m.role {
             Role::System => "system",
             Role::User => "user",
             Role::Assistant => "assistant",
             Role::Tool => "tool",
         };
-        messages.push(json!({"role": role, "content": text}));
+        messages.push(
+            json!({"role": role, "content": anthropic_content(&m.content, &mut breakpoints_left)}),
+        );
     }
 
     json!({
@@ -303,50 +735,181 @@ pub fn map_internal_to_openrouter(req: &GenerateRequest) -> JsonValue {
     map_internal_to_openai_chat(req)
 }
 
-/// Backpressure-safe SSE decoder (very simple)
-pub struct SseDecoder {
+/// Newline-delimited JSON decoder that tolerates objects split across chunks
+pub struct NdjsonDecoder {
     buffer: String,
 }
 
-impl SseDecoder {
+impl NdjsonDecoder {
     pub fn new() -> Self {
         Self {
             buffer: String::new(),
         }
     }
 
-    /// Push raw chunk; returns complete "data: ..." lines (without prefix)
+    /// Push raw chunk; returns the complete, non-empty lines
     pub fn push_chunk(&mut self, chunk: &str) -> Vec<String> {
         self.buffer.push_str(chunk);
         let mut out = Vec::new();
-        let mut start = 0usize;
-
-        while let Some(idx) = self.buffer[start..].find('\n') {
-            let line_end = start + idx;
-            let line = &self.buffer[start..line_end];
-            let line = line.trim_end_matches('\r');
-            if let Some(json_str) = line.strip_prefix("data: ") {
-                if json_str != "[DONE]" {
-                    out.push(json_str.to_string());
-                }
+        while let Some(idx) = self.buffer.find('\n') {
+            let line: String = self.buffer.drain(..=idx).collect();
+            let line = line.trim();
+            if !line.is_empty() {
+                out.push(line.to_string());
             }
-            start = line_end + 1;
         }
-
-        // retain leftover (partial line)
-        let leftover = self.buffer[start..].to_string();
-        self.buffer.clear();
-        self.buffer.push_str(&leftover);
         out
     }
+
+    /// Take whatever is left once the stream ends (a final unterminated line)
+    pub fn finish(&mut self) -> Option<String> {
+        let rest = std::mem::take(&mut self.buffer);
+        let rest = rest.trim();
+        (!rest.is_empty()).then(|| rest.to_string())
+    }
 }
 
-impl Default for SseDecoder {
+impl Default for NdjsonDecoder {
     fn default() -> Self {
         Self::new()
     }
 }
 
+/// Tool call whose arguments are still streaming in
+#[derive(Default)]
+struct PendingToolCall {
+    id: Option<String>,
+    name: String,
+    arguments: String,
+    initial: Option<JsonValue>,
+}
+
+/// Reassembles streamed tool calls from per-index argument fragments
+///
+/// Both Anthropic (`input_json_delta`) and OpenAI-chat (`delta.tool_calls`)
+/// stream a call's name first and its JSON arguments in pieces; a call is
+/// complete once its block closes or the stream finishes.
+#[derive(Default)]
+pub struct ToolCallAccumulator {
+    pending: std::collections::BTreeMap<usize, PendingToolCall>,
+}
+
+impl ToolCallAccumulator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Begin a call; `initial` is used when no argument fragments follow
+    pub fn start(
+        &mut self,
+        index: usize,
+        id: Option<String>,
+        name: String,
+        initial: Option<JsonValue>,
+    ) {
+        self.pending.insert(
+            index,
+            PendingToolCall {
+                id,
+                name,
+                arguments: String::new(),
+                initial,
+            },
+        );
+    }
+
+    /// Append a fragment of a call's JSON arguments
+    pub fn push_arguments(&mut self, index: usize, fragment: &str) {
+        self.pending
+            .entry(index)
+            .or_default()
+            .arguments
+            .push_str(fragment);
+    }
+
+    /// Feed an OpenAI-chat SSE line; returns `ToolDelta` events for new argument text
+    pub fn push_openai_chat_sse(&mut self, json_line: &str) -> Vec<StreamEvent> {
+        let mut events = Vec::new();
+        let Ok(v) = serde_json::from_str::<JsonValue>(json_line) else {
+            return events;
+        };
+        let Some(calls) = v
+            .get("choices")
+            .and_then(|c| c.get(0))
+            .and_then(|c| c.get("delta"))
+            .and_then(|d| d.get("tool_calls"))
+            .and_then(|t| t.as_array())
+        else {
+            return events;
+        };
+
+        for (position, call) in calls.iter().enumerate() {
+            let index = call
+                .get("index")
+                .and_then(|i| i.as_u64())
+                .map(|i| i as usize)
+                .unwrap_or(position);
+            let entry = self.pending.entry(index).or_default();
+            if let Some(id) = call.get("id").and_then(|x| x.as_str()) {
+                entry.id = Some(id.to_string());
+            }
+            let function = call.get("function");
+            if let Some(name) = function
+                .and_then(|f| f.get("name"))
+                .and_then(|x| x.as_str())
+            {
+                entry.name.push_str(name);
+            }
+            if let Some(args) = function
+                .and_then(|f| f.get("arguments"))
+                .and_then(|x| x.as_str())
+                .filter(|a| !a.is_empty())
+            {
+                entry.arguments.push_str(args);
+                events.push(StreamEvent::ToolDelta(args.to_string()));
+            }
+        }
+        events
+    }
+
+    /// Complete the call at `index`, parsing its accumulated arguments
+    pub fn finish(&mut self, index: usize) -> Option<ToolCallNormalized> {
+        self.pending.remove(&index).map(Self::complete)
+    }
+
+    /// Complete every call still pending, in index order
+    pub fn finish_all(&mut self) -> Vec<ToolCallNormalized> {
+        std::mem::take(&mut self.pending)
+            .into_values()
+            .map(Self::complete)
+            .collect()
+    }
+
+    fn complete(call: PendingToolCall) -> ToolCallNormalized {
+        let arguments_json = if call.arguments.trim().is_empty() {
+            call.initial.unwrap_or_else(|| json!({}))
+        } else {
+            serde_json::from_str(&call.arguments).unwrap_or_else(|e| {
+                log::warn!(
+                    "Tool call '{}' streamed invalid JSON arguments: {}",
+                    call.name,
+                    e
+                );
+                json!({})
+            })
+        };
+        ToolCallNormalized {
+            id: call.id,
+            name: if call.name.is_empty() {
+                "function".to_string()
+            } else {
+                call.name
+            },
+            arguments_json,
+        }
+    }
+}
+
 /// Parse a single OpenAI-Chat SSE JSON line into a StreamEvent::TextDelta if present
 pub fn parse_openai_chat_sse(json_line: &str) -> Option<StreamEvent> {
     if let Ok(v) = serde_json::from_str::<JsonValue>(json_line) {
@@ -363,6 +926,91 @@ pub fn parse_openai_chat_sse(json_line: &str) -> Option<StreamEvent> {
     None
 }
 
+/// Reasoning text of an OpenAI-chat `message` or streamed `delta`
+///
+/// DeepSeek and vLLM use `reasoning_content`; OpenRouter and Groq use
+/// `reasoning`.
+pub fn openai_chat_reasoning(message: &JsonValue) -> Option<&str> {
+    message
+        .get("reasoning_content")
+        .or_else(|| message.get("reasoning"))
+        .and_then(|r| r.as_str())
+        .filter(|r| !r.is_empty())
+}
+
+/// Parse a single OpenAI-Chat SSE JSON line into a StreamEvent::ReasoningDelta if present
+pub fn parse_openai_chat_reasoning_sse(json_line: &str) -> Option<StreamEvent> {
+    let v = serde_json::from_str::<JsonValue>(json_line).ok()?;
+    let delta = v.get("choices")?.get(0)?.get("delta")?;
+    openai_chat_reasoning(delta).map(|r| StreamEvent::ReasoningDelta(r.to_string()))
+}
+
+/// Request OpenAI-chat logprobs (`logprobs`, `top_logprobs`) in `payload`
+pub(crate) fn apply_openai_logprobs(payload: &mut JsonValue, logprobs: Option<u8>) {
+    let (Some(top), Some(obj)) = (logprobs, payload.as_object_mut()) else {
+        return;
+    };
+    obj.insert("logprobs".to_string(), json!(true));
+    if top > 0 {
+        obj.insert("top_logprobs".to_string(), json!(top));
+    }
+}
+
+/// Token logprobs of an OpenAI-chat choice (`choice.logprobs.content`);
+/// works for both full choices and streamed chunk choices
+pub fn parse_openai_logprobs(choice: &JsonValue) -> Option<Vec<TokenLogprob>> {
+    let entry = |v: &JsonValue| -> Option<(String, f64)> {
+        Some((
+            v.get("token")?.as_str()?.to_string(),
+            v.get("logprob")?.as_f64()?,
+        ))
+    };
+    let content = choice.get("logprobs")?.get("content")?.as_array()?;
+    Some(
+        content
+            .iter()
+            .filter_map(|t| {
+                let (token, logprob) = entry(t)?;
+                let top = t
+                    .get("top_logprobs")
+                    .and_then(|a| a.as_array())
+                    .map(|alts| {
+                        alts.iter()
+                            .filter_map(|a| {
+                                entry(a).map(|(token, logprob)| TopLogprob { token, logprob })
+                            })
+                            .collect()
+                    })
+                    .unwrap_or_default();
+                Some(TokenLogprob {
+                    token,
+                    logprob,
+                    top,
+                })
+            })
+            .collect(),
+    )
+}
+
+/// Token logprobs carried by one OpenAI-chat SSE line
+pub fn parse_openai_chat_logprobs_sse(json_line: &str) -> Option<Vec<TokenLogprob>> {
+    let v = serde_json::from_str::<JsonValue>(json_line).ok()?;
+    parse_openai_logprobs(v.get("choices")?.get(0)?)
+}
+
+/// Whether an OpenAI-chat SSE line carries the final `finish_reason`
+pub fn is_openai_chat_finish(json_line: &str) -> bool {
+    serde_json::from_str::<JsonValue>(json_line)
+        .ok()
+        .and_then(|v| {
+            v.get("choices")
+                .and_then(|c| c.get(0))
+                .and_then(|c| c.get("finish_reason"))
+                .map(|r| !r.is_null())
+        })
+        .unwrap_or(false)
+}
+
 /// Parse a single OpenAI-Responses SSE line (output_text.delta etc.)
 pub fn parse_openai_responses_sse(json_line: &str) -> Option<StreamEvent> {
     if let Ok(v) = serde_json::from_str::<JsonValue>(json_line) {
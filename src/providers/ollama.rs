@@ -9,9 +9,15 @@ use std::collections::HashMap;
 use std::time::Duration;
 use tokio::time::timeout;
 
-use crate::core::config::ModelConfig;
+use crate::core::config::LlmConfig;
 use crate::core::error::ProviderError;
-use crate::providers::{ContentPart, GenerateRequest, GenerateResponse, Role, StreamEvent, Usage};
+use crate::providers::{
+    image_source, ContentPart, GenerateRequest, GenerateResponse, ImageSource, ModelInfo,
+    NdjsonDecoder, ResponseFormat, Role, StreamEvent, Usage,
+};
+
+/// Default endpoint of a locally running Ollama server
+pub const DEFAULT_OLLAMA_BASE: &str = "http://localhost:11434";
 
 /// Ollama provider for local LLM inference
 /// Supports the Ollama /api/generate and /api/chat endpoints
@@ -22,28 +28,73 @@ pub struct OllamaProvider {
     first_token_timeout_ms: u64,
     stall_timeout_ms: u64,
     static_headers: Option<HashMap<String, String>>,
+    // Configured defaults, used when a request does not set its own
+    default_max_tokens: Option<usize>,
+    default_temperature: Option<f32>,
 }
 
 impl OllamaProvider {
-    /// Create an Ollama provider from ModelConfig
-    pub fn from_config(cfg: &ModelConfig) -> Result<Self, ProviderError> {
-        let client = Client::builder()
-            .timeout(Duration::from_secs(300)) // Ollama can be slow on first load
-            .build()
-            .map_err(|e| ProviderError::Network {
-                message: format!("Failed to create HTTP client: {}", e),
-            })?;
+    /// Create an Ollama provider from LlmConfig (no API key required)
+    pub fn from_config(cfg: &LlmConfig) -> Result<Self, ProviderError> {
+        // Ollama can be slow on first load
+        let client =
+            crate::providers::http::build_client(cfg.network.as_ref(), Duration::from_secs(300))?;
 
         Ok(Self {
             client,
             base_url: cfg
                 .api_base
                 .clone()
-                .unwrap_or_else(|| "http://localhost:11434".to_string()),
+                .unwrap_or_else(|| DEFAULT_OLLAMA_BASE.to_string()),
             model: cfg.model.clone(),
-            first_token_timeout_ms: 60_000, // Longer for local models
-            stall_timeout_ms: 30_000,
-            static_headers: None, // ModelConfig doesn't have headers field yet
+            // Longer defaults for local models, which may need to load first
+            first_token_timeout_ms: cfg.first_token_timeout_ms.unwrap_or(60_000),
+            stall_timeout_ms: cfg.stall_timeout_ms.unwrap_or(30_000),
+            static_headers: cfg.headers.clone(),
+            default_max_tokens: (cfg.max_tokens > 0).then_some(cfg.max_tokens),
+            default_temperature: Some(cfg.temperature),
+        })
+    }
+
+    /// Map sampling parameters to Ollama's `options`, omitted when empty
+    fn build_options(&self, req: &GenerateRequest) -> Option<OllamaOptions> {
+        let options = OllamaOptions {
+            num_predict: req
+                .max_output_tokens
+                .or(self.default_max_tokens)
+                .map(|t| t as i32),
+            temperature: req.temperature.or(self.default_temperature),
+            top_p: req.top_p,
+            stop: req.stop.clone(),
+            seed: req.seed,
+        };
+
+        if options.num_predict.is_some()
+            || options.temperature.is_some()
+            || options.top_p.is_some()
+            || options.stop.is_some()
+            || options.seed.is_some()
+        {
+            Some(options)
+        } else {
+            None
+        }
+    }
+
+    fn usage_from_counts(prompt: Option<u64>, completion: Option<u64>) -> Option<Usage> {
+        if prompt.is_none() && completion.is_none() {
+            return None;
+        }
+        Some(Usage {
+            prompt_tokens: prompt.map(|c| c as u32),
+            completion_tokens: completion.map(|c| c as u32),
+            total_tokens: match (prompt, completion) {
+                (Some(p), Some(c)) => Some((p + c) as u32),
+                _ => None,
+            },
+            retries: None,
+            cache_read_tokens: None,
+            cache_write_tokens: None,
         })
     }
 
@@ -69,21 +120,69 @@ impl OllamaProvider {
                 Role::Tool => "assistant", // Ollama doesn't have a dedicated tool role
             };
 
-            // Concatenate text parts (Ollama doesn't support multi-part content in the same way)
+            // Concatenate text parts; images travel separately as base64
             let text = m
                 .content
                 .iter()
                 .filter_map(|p| match p {
                     ContentPart::Text { text } => Some(text.as_str()),
-                    ContentPart::ImageUrl { .. } => None, // TODO: Add image support later
+                    _ => None,
                 })
                 .collect::<Vec<_>>()
                 .join(" ");
+            let images: Vec<&str> = m
+                .content
+                .iter()
+                .filter_map(|p| match p {
+                    ContentPart::ImageUrl { url, mime } => match image_source(url, mime) {
+                        ImageSource::Base64 { data, .. } => Some(data),
+                        ImageSource::Url(url) => {
+                            // Ollama only takes inline image data
+                            warn!("Ollama cannot fetch remote images; dropping {}", url);
+                            None
+                        }
+                    },
+                    _ => None,
+                })
+                .collect();
+
+            // Tool results each become a `tool` message
+            let results: Vec<JsonValue> = m
+                .content
+                .iter()
+                .filter_map(|p| match p {
+                    ContentPart::ToolResult { content, .. } => {
+                        Some(json!({"role": "tool", "content": content}))
+                    }
+                    _ => None,
+                })
+                .collect();
+            if !results.is_empty() {
+                out.extend(results);
+                continue;
+            }
 
-            out.push(json!({
+            let mut message = json!({
                 "role": role,
                 "content": text
-            }));
+            });
+            if !images.is_empty() {
+                message["images"] = json!(images);
+            }
+            let calls: Vec<JsonValue> = m
+                .content
+                .iter()
+                .filter_map(|p| match p {
+                    ContentPart::ToolCall {
+                        name, arguments, ..
+                    } => Some(json!({"function": {"name": name, "arguments": arguments}})),
+                    _ => None,
+                })
+                .collect();
+            if !calls.is_empty() {
+                message["tool_calls"] = json!(calls);
+            }
+            out.push(message);
         }
 
         out
@@ -102,16 +201,7 @@ impl OllamaProvider {
             }
         }
 
-        if let Some(meta) = &req.metadata {
-            for (k, v) in meta {
-                if k.eq_ignore_ascii_case("content-type") {
-                    continue;
-                }
-                rb = rb.header(k, v);
-            }
-        }
-
-        rb
+        crate::providers::metadata::apply_headers(rb, req.metadata.as_ref(), &["content-type"])
     }
 
     fn map_http_error(status: u16, body: String) -> ProviderError {
@@ -130,6 +220,10 @@ impl OllamaProvider {
                 message: "Ollama server error".to_string(),
                 status: Some(status),
             }
+        } else if let Some(err) =
+            crate::providers::capabilities::parse_context_length_error(status, &body)
+        {
+            err
         } else if lower.contains("invalid") || lower.contains("error") {
             ProviderError::InvalidParams {
                 details: Some(body),
@@ -146,6 +240,13 @@ impl OllamaProvider {
             }
         }
     }
+
+    fn map_format(format: &Option<ResponseFormat>) -> Option<JsonValue> {
+        format.as_ref().map(|f| match f {
+            ResponseFormat::JsonObject => json!("json"),
+            ResponseFormat::JsonSchema { json_schema } => json_schema.schema.clone(),
+        })
+    }
 }
 
 #[derive(Serialize)]
@@ -155,6 +256,9 @@ struct OllamaChatRequest {
     stream: bool,
     #[serde(skip_serializing_if = "Option::is_none")]
     options: Option<OllamaOptions>,
+    /// `"json"` or a JSON Schema the output must follow
+    #[serde(skip_serializing_if = "Option::is_none")]
+    format: Option<JsonValue>,
 }
 
 #[derive(Serialize)]
@@ -167,6 +271,8 @@ struct OllamaOptions {
     top_p: Option<f32>,
     #[serde(skip_serializing_if = "Option::is_none")]
     stop: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    seed: Option<u64>,
 }
 
 #[derive(Deserialize)]
@@ -183,13 +289,19 @@ struct OllamaChatResponse {
 #[derive(Deserialize)]
 struct OllamaMessage {
     content: String,
+    /// Set by thinking models (e.g. deepseek-r1, qwen3)
+    #[serde(default)]
+    thinking: Option<String>,
 }
 
 #[derive(Deserialize)]
 struct OllamaStreamChunk {
     message: Option<OllamaMessage>,
+    #[serde(default)]
     done: bool,
     #[serde(default)]
+    error: Option<String>,
+    #[serde(default)]
     prompt_eval_count: Option<u64>,
     #[serde(default)]
     eval_count: Option<u64>,
@@ -200,30 +312,12 @@ impl crate::providers::Provider for OllamaProvider {
     async fn generate(&self, req: GenerateRequest) -> Result<GenerateResponse, ProviderError> {
         let url = format!("{}/api/chat", self.base_url.trim_end_matches('/'));
 
-        // Build options
-        let options = OllamaOptions {
-            num_predict: req.max_output_tokens.map(|t| t as i32),
-            temperature: req.temperature,
-            top_p: req.top_p,
-            stop: req.stop.clone(),
-        };
-
-        // Only include options if at least one field is set
-        let options_to_send = if options.num_predict.is_some()
-            || options.temperature.is_some()
-            || options.top_p.is_some()
-            || options.stop.is_some()
-        {
-            Some(options)
-        } else {
-            None
-        };
-
         let payload = OllamaChatRequest {
             model: self.model.clone(),
             messages: self.build_messages(&req),
             stream: false,
-            options: options_to_send,
+            options: self.build_options(&req),
+            format: Self::map_format(&req.response_format),
         };
 
         // Send request
@@ -252,31 +346,21 @@ impl crate::providers::Provider for OllamaProvider {
                 message: format!("Invalid JSON from Ollama: {}", e),
             })?;
 
-        // Calculate usage if available
-        let usage = if ollama_response.prompt_eval_count.is_some()
-            || ollama_response.eval_count.is_some()
-        {
-            Some(Usage {
-                prompt_tokens: ollama_response.prompt_eval_count.map(|c| c as u32),
-                completion_tokens: ollama_response.eval_count.map(|c| c as u32),
-                total_tokens: match (
-                    ollama_response.prompt_eval_count,
-                    ollama_response.eval_count,
-                ) {
-                    (Some(p), Some(c)) => Some((p + c) as u32),
-                    _ => None,
-                },
-            })
-        } else {
-            None
-        };
+        let usage = Self::usage_from_counts(
+            ollama_response.prompt_eval_count,
+            ollama_response.eval_count,
+        );
 
-        Ok(GenerateResponse {
+        GenerateResponse {
             text: ollama_response.message.content,
             tool_calls: vec![], // Ollama doesn't support tool calling in the same way
             usage,
             raw: serde_json::from_str(&text).ok(),
-        })
+            provider: None,
+            reasoning: ollama_response.message.thinking.filter(|t| !t.is_empty()),
+            logprobs: None,
+        }
+        .non_empty("Ollama")
     }
 
     async fn generate_streaming(
@@ -286,29 +370,12 @@ impl crate::providers::Provider for OllamaProvider {
     ) -> Result<GenerateResponse, ProviderError> {
         let url = format!("{}/api/chat", self.base_url.trim_end_matches('/'));
 
-        // Build options
-        let options = OllamaOptions {
-            num_predict: req.max_output_tokens.map(|t| t as i32),
-            temperature: req.temperature,
-            top_p: req.top_p,
-            stop: req.stop.clone(),
-        };
-
-        let options_to_send = if options.num_predict.is_some()
-            || options.temperature.is_some()
-            || options.top_p.is_some()
-            || options.stop.is_some()
-        {
-            Some(options)
-        } else {
-            None
-        };
-
         let payload = OllamaChatRequest {
             model: self.model.clone(),
             messages: self.build_messages(&req),
             stream: true,
-            options: options_to_send,
+            options: self.build_options(&req),
+            format: Self::map_format(&req.response_format),
         };
 
         // Send request
@@ -332,13 +399,59 @@ impl crate::providers::Provider for OllamaProvider {
         }
 
         let mut stream = resp.bytes_stream();
+        let mut decoder = NdjsonDecoder::new();
         let mut content = String::new();
+        let mut reasoning = String::new();
         let mut usage_info: Option<Usage> = None;
 
         let first_timeout = self.first_token_timeout_ms;
         let stall_timeout = self.stall_timeout_ms;
         let mut got_first = false;
 
+        // Ollama streams newline-delimited JSON (not SSE)
+        let mut handle_line = |line: &str| -> Result<(), ProviderError> {
+            let chunk = match serde_json::from_str::<OllamaStreamChunk>(line) {
+                Ok(chunk) => chunk,
+                Err(e) => {
+                    warn!(
+                        "Failed to parse Ollama stream chunk: {} - line: {}",
+                        e, line
+                    );
+                    return Ok(());
+                }
+            };
+
+            if let Some(error) = chunk.error {
+                return Err(ProviderError::ServerError {
+                    details: Some(line.to_string()),
+                    code: None,
+                    message: format!("Ollama stream error: {}", error),
+                    status: None,
+                });
+            }
+
+            if let Some(msg) = &chunk.message {
+                if let Some(thinking) = msg.thinking.as_ref().filter(|t| !t.is_empty()) {
+                    reasoning.push_str(thinking);
+                    on_event(StreamEvent::ReasoningDelta(thinking.clone()));
+                }
+                if !msg.content.is_empty() {
+                    content.push_str(&msg.content);
+                    on_event(StreamEvent::TextDelta(msg.content.clone()));
+                }
+            }
+
+            if chunk.done {
+                // Usage arrives on the final chunk
+                usage_info = Self::usage_from_counts(chunk.prompt_eval_count, chunk.eval_count);
+                if let Some(u) = &usage_info {
+                    on_event(StreamEvent::Usage(u.clone()));
+                }
+                on_event(StreamEvent::Finished);
+            }
+            Ok(())
+        };
+
         loop {
             let cur = if got_first {
                 stall_timeout
@@ -348,57 +461,9 @@ impl crate::providers::Provider for OllamaProvider {
 
             match timeout(Duration::from_millis(cur), stream.next()).await {
                 Ok(Some(Ok(bytes))) => {
-                    let s = String::from_utf8_lossy(&bytes);
-
-                    // Ollama streams newline-delimited JSON (not SSE)
-                    for line in s.lines() {
-                        let line = line.trim();
-                        if line.is_empty() {
-                            continue;
-                        }
-
-                        match serde_json::from_str::<OllamaStreamChunk>(line) {
-                            Ok(chunk) => {
-                                if let Some(msg) = &chunk.message {
-                                    if !msg.content.is_empty() {
-                                        content.push_str(&msg.content);
-                                        on_event(StreamEvent::TextDelta(msg.content.clone()));
-                                        got_first = true;
-                                    }
-                                }
-
-                                if chunk.done {
-                                    // Extract usage info from final chunk
-                                    if chunk.prompt_eval_count.is_some()
-                                        || chunk.eval_count.is_some()
-                                    {
-                                        usage_info = Some(Usage {
-                                            prompt_tokens: chunk
-                                                .prompt_eval_count
-                                                .map(|c| c as u32),
-                                            completion_tokens: chunk.eval_count.map(|c| c as u32),
-                                            total_tokens: match (
-                                                chunk.prompt_eval_count,
-                                                chunk.eval_count,
-                                            ) {
-                                                (Some(p), Some(c)) => Some((p + c) as u32),
-                                                _ => None,
-                                            },
-                                        });
-                                        if let Some(u) = &usage_info {
-                                            on_event(StreamEvent::Usage(u.clone()));
-                                        }
-                                    }
-                                    on_event(StreamEvent::Finished);
-                                }
-                            }
-                            Err(e) => {
-                                warn!(
-                                    "Failed to parse Ollama stream chunk: {} - line: {}",
-                                    e, line
-                                );
-                            }
-                        }
+                    got_first = true;
+                    for line in decoder.push_chunk(&String::from_utf8_lossy(&bytes)) {
+                        handle_line(&line)?;
                     }
                 }
                 Ok(Some(Err(e))) => {
@@ -406,7 +471,12 @@ impl crate::providers::Provider for OllamaProvider {
                         message: format!("Error reading streaming response: {}", e),
                     });
                 }
-                Ok(None) => break,
+                Ok(None) => {
+                    if let Some(line) = decoder.finish() {
+                        handle_line(&line)?;
+                    }
+                    break;
+                }
                 Err(_) => {
                     if !got_first {
                         return Err(ProviderError::TimeoutFirstToken { timeout_ms: cur });
@@ -422,6 +492,41 @@ impl crate::providers::Provider for OllamaProvider {
             tool_calls: vec![],
             usage: usage_info,
             raw: None,
+            provider: None,
+            reasoning: (!reasoning.is_empty()).then_some(reasoning),
+            logprobs: None,
         })
     }
+
+    async fn list_models(&self) -> Result<Vec<ModelInfo>, ProviderError> {
+        let url = format!("{}/api/tags", self.base_url.trim_end_matches('/'));
+        let v = crate::providers::get_json(self.client.get(url), "Ollama", Self::map_http_error)
+            .await?;
+        Ok(v.get("models")
+            .and_then(|m| m.as_array())
+            .map(|models| {
+                models
+                    .iter()
+                    .filter_map(|m| m.get("name").and_then(|n| n.as_str()))
+                    .map(|name| ModelInfo {
+                        id: name.to_string(),
+                        context_length: None,
+                    })
+                    .collect()
+            })
+            .unwrap_or_default())
+    }
+
+    async fn embed(&self, inputs: &[String]) -> Result<Vec<Vec<f32>>, ProviderError> {
+        let url = format!("{}/api/embed", self.base_url.trim_end_matches('/'));
+        let payload = json!({ "model": self.model, "input": inputs });
+        let v = crate::providers::post_json(
+            self.client.post(url),
+            &payload,
+            "Ollama",
+            Self::map_http_error,
+        )
+        .await?;
+        crate::providers::parse_embeddings(v.get("embeddings"), inputs.len(), "Ollama")
+    }
 }
@@ -0,0 +1,250 @@
+// File: src/providers/openai.rs
+use async_trait::async_trait;
+use std::collections::HashMap;
+use std::sync::{Mutex, OnceLock};
+use std::time::Duration;
+
+use crate::core::config::LlmConfig;
+use crate::core::error::ProviderError;
+use crate::providers::openai_compat::{Dialect, OpenAiCompatProvider};
+use crate::providers::{ContentPart, GenerateRequest, GenerateResponse, ModelInfo, StreamEvent};
+
+/// OpenAI adapter.
+///
+/// Chat completions at `https://api.openai.com/v1`. The output limit is sent
+/// as `max_completion_tokens`, which every current model accepts (reasoning
+/// models reject `max_tokens`), and the configured reasoning effort as
+/// `reasoning_effort`. Batches go through the Batch API when the base URL
+/// is OpenAI's own.
+///
+/// Models that reject chat completions with an unsupported-parameter error
+/// are retried on the legacy `responses` endpoint, first with
+/// `max_output_tokens` and then `max_completion_tokens`; the endpoint that
+/// worked is remembered per API base and model for the rest of the process.
+pub struct OpenAiProvider {
+    inner: OpenAiCompatProvider,
+    native_batch: bool,
+    batch_poll_interval: Duration,
+}
+
+impl OpenAiProvider {
+    /// Create an OpenAI provider from LlmConfig (key falls back to `OPENAI_API_KEY`)
+    pub fn from_config(cfg: &LlmConfig) -> Result<Self, ProviderError> {
+        let dialect = Dialect {
+            name: "OpenAI",
+            default_base: "https://api.openai.com/v1",
+            key_env: "OPENAI_API_KEY",
+            max_tokens_field: "max_completion_tokens",
+            seed_field: "seed",
+            max_stop_sequences: Some(4),
+            required_tool_choice: "required",
+            logprobs: true,
+            reasoning_effort: true,
+        };
+        let inner = OpenAiCompatProvider::from_config(cfg, dialect)?;
+        Ok(Self {
+            native_batch: inner.api_base().contains("api.openai.com"),
+            inner,
+            batch_poll_interval: Duration::from_secs(30),
+        })
+    }
+
+    /// Use the Batch API for `generate_batch` (on by default when the base
+    /// URL is OpenAI's)
+    pub fn with_native_batch(mut self, enabled: bool) -> Self {
+        self.native_batch = enabled;
+        self
+    }
+
+    /// How often a submitted batch is polled for completion
+    pub fn with_batch_poll_interval(mut self, interval: Duration) -> Self {
+        self.batch_poll_interval = interval;
+        self
+    }
+}
+
+/// Endpoint a model was last served from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Endpoint {
+    Chat,
+    ResponsesMaxOutput,
+    ResponsesMaxCompletion,
+}
+
+impl Endpoint {
+    fn limit_field(self) -> &'static str {
+        match self {
+            Endpoint::ResponsesMaxCompletion => "max_completion_tokens",
+            _ => "max_output_tokens",
+        }
+    }
+}
+
+/// Endpoints that worked, by API base and model
+fn endpoint_cache() -> &'static Mutex<HashMap<(String, String), Endpoint>> {
+    static CACHE: OnceLock<Mutex<HashMap<(String, String), Endpoint>>> = OnceLock::new();
+    CACHE.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+/// Whether `err` is the 400 OpenAI returns for parameters a model does not
+/// take on this endpoint
+fn is_unsupported_parameter(err: &ProviderError) -> bool {
+    match err {
+        ProviderError::InvalidParams {
+            details: Some(body),
+            status: Some(400),
+            ..
+        } => {
+            let body = body.to_lowercase();
+            body.contains("unsupported parameter") || body.contains("v1/responses")
+        }
+        _ => false,
+    }
+}
+
+impl OpenAiProvider {
+    fn cache_key(&self) -> (String, String) {
+        (
+            self.inner.api_base().to_string(),
+            self.inner.model().to_string(),
+        )
+    }
+
+    fn cached_endpoint(&self) -> Endpoint {
+        endpoint_cache()
+            .lock()
+            .unwrap()
+            .get(&self.cache_key())
+            .copied()
+            .unwrap_or(Endpoint::Chat)
+    }
+
+    fn remember(&self, endpoint: Endpoint) {
+        endpoint_cache()
+            .lock()
+            .unwrap()
+            .insert(self.cache_key(), endpoint);
+    }
+
+    /// Whether a chat failure should be retried on the `responses`
+    /// endpoint, which takes neither tools nor images
+    fn falls_back(err: &ProviderError, req: &GenerateRequest) -> bool {
+        is_unsupported_parameter(err)
+            && req.tools.as_ref().is_none_or(|t| t.is_empty())
+            && req.messages.iter().all(|m| {
+                m.content
+                    .iter()
+                    .all(|p| matches!(p, ContentPart::Text { .. } | ContentPart::CacheBreakpoint))
+            })
+    }
+
+    /// Generate through the `responses` endpoint starting with `endpoint`'s
+    /// limit field, switching to `max_completion_tokens` when the model
+    /// asks for it
+    async fn generate_responses(
+        &self,
+        req: &GenerateRequest,
+        endpoint: Endpoint,
+    ) -> Result<GenerateResponse, ProviderError> {
+        let result = match self
+            .inner
+            .generate_responses(req, endpoint.limit_field())
+            .await
+        {
+            Err(e)
+                if endpoint == Endpoint::ResponsesMaxOutput
+                    && is_unsupported_parameter(&e)
+                    && matches!(&e, ProviderError::InvalidParams { details: Some(d), .. }
+                        if d.contains("max_completion_tokens")) =>
+            {
+                let retry = Endpoint::ResponsesMaxCompletion;
+                self.inner
+                    .generate_responses(req, retry.limit_field())
+                    .await
+                    .map(|r| (r, retry))
+            }
+            other => other.map(|r| (r, endpoint)),
+        };
+        let (res, endpoint) = result?;
+        self.remember(endpoint);
+        Ok(res)
+    }
+}
+
+#[async_trait]
+impl crate::providers::Provider for OpenAiProvider {
+    async fn generate(&self, req: GenerateRequest) -> Result<GenerateResponse, ProviderError> {
+        let endpoint = self.cached_endpoint();
+        if endpoint != Endpoint::Chat {
+            return self.generate_responses(&req, endpoint).await;
+        }
+        match self.inner.generate(req.clone()).await {
+            Err(e) if Self::falls_back(&e, &req) => {
+                self.generate_responses(&req, Endpoint::ResponsesMaxOutput)
+                    .await
+            }
+            other => other,
+        }
+    }
+
+    fn supports_stream_resume(&self) -> bool {
+        true
+    }
+
+    async fn generate_streaming(
+        &self,
+        req: GenerateRequest,
+        on_event: &mut (dyn FnMut(StreamEvent) + Send),
+    ) -> Result<GenerateResponse, ProviderError> {
+        // The `responses` fallback is not streamed; its text arrives as a
+        // single delta
+        let endpoint = self.cached_endpoint();
+        let res = if endpoint != Endpoint::Chat {
+            self.generate_responses(&req, endpoint).await?
+        } else {
+            match self.inner.generate_streaming(req.clone(), on_event).await {
+                Err(e) if Self::falls_back(&e, &req) => {
+                    self.generate_responses(&req, Endpoint::ResponsesMaxOutput)
+                        .await?
+                }
+                other => return other,
+            }
+        };
+        on_event(StreamEvent::TextDelta(res.text.clone()));
+        if let Some(usage) = &res.usage {
+            on_event(StreamEvent::Usage(usage.clone()));
+        }
+        on_event(StreamEvent::Finished);
+        Ok(res)
+    }
+
+    async fn generate_batch(
+        &self,
+        reqs: Vec<GenerateRequest>,
+    ) -> Vec<Result<GenerateResponse, ProviderError>> {
+        if self.native_batch {
+            self.inner
+                .generate_native_batch(reqs, self.batch_poll_interval)
+                .await
+        } else {
+            crate::providers::batch::generate_concurrently(
+                self,
+                reqs,
+                crate::providers::batch::DEFAULT_BATCH_CONCURRENCY,
+            )
+            .await
+        }
+    }
+
+    fn supports_native_batch(&self) -> bool {
+        self.native_batch
+    }
+
+    async fn list_models(&self) -> Result<Vec<ModelInfo>, ProviderError> {
+        self.inner.list_models().await
+    }
+
+    async fn embed(&self, inputs: &[String]) -> Result<Vec<Vec<f32>>, ProviderError> {
+        self.inner.embed(inputs).await
+    }
+}
@@ -0,0 +1,615 @@
+// File: src/providers/openai_compat.rs
+use futures_util::StreamExt;
+use log::debug;
+use reqwest::Client;
+use serde_json::{json, Value as JsonValue};
+use std::collections::HashMap;
+use std::time::Duration;
+use tokio::time::timeout;
+
+use crate::core::config::{LlmConfig, ReasoningEffort};
+use crate::core::error::ProviderError;
+use crate::providers::openrouter::OpenRouterProvider as OpenAiChat;
+use crate::providers::{
+    GenerateRequest, GenerateResponse, ModelInfo, SseDecoder, StreamEvent, ToolCallAccumulator,
+    ToolChoice, Usage,
+};
+
+/// How a hosted OpenAI-compatible API deviates from OpenAI's chat format
+pub(crate) struct Dialect {
+    /// Display name used in errors and logs
+    pub name: &'static str,
+
+    /// API base used when `api_base` is not configured
+    pub default_base: &'static str,
+
+    /// Environment variable holding the API key
+    pub key_env: &'static str,
+
+    /// Request field carrying the output token limit
+    pub max_tokens_field: &'static str,
+
+    /// Request field carrying the sampling seed
+    pub seed_field: &'static str,
+
+    /// Most stop sequences the API accepts; extra ones are dropped
+    pub max_stop_sequences: Option<usize>,
+
+    /// `tool_choice` value that forces a tool call
+    pub required_tool_choice: &'static str,
+
+    /// Whether the API returns token logprobs when asked
+    pub logprobs: bool,
+
+    /// Whether the configured reasoning effort is sent as `reasoning_effort`
+    pub reasoning_effort: bool,
+}
+
+/// Chat-completions client shared by the OpenAI-compatible backends
+/// (OpenAI, Groq, Mistral); the `Dialect` captures their differences
+pub(crate) struct OpenAiCompatProvider {
+    dialect: Dialect,
+    client: Client,
+    api_key: String,
+    api_base: String,
+    model: String,
+    headers: Option<HashMap<String, String>>,
+    first_token_timeout_ms: u64,
+    stall_timeout_ms: u64,
+    reasoning_effort: Option<ReasoningEffort>,
+}
+
+impl OpenAiCompatProvider {
+    pub fn from_config(cfg: &LlmConfig, dialect: Dialect) -> Result<Self, ProviderError> {
+        let api_key = if !cfg.api_key.is_empty() {
+            cfg.api_key.clone()
+        } else {
+            std::env::var(dialect.key_env).map_err(|_| ProviderError::Auth {
+                details: None,
+                code: None,
+                message: format!("Missing {}", dialect.key_env),
+                status: None,
+            })?
+        };
+
+        let client =
+            crate::providers::http::build_client(cfg.network.as_ref(), Duration::from_secs(120))?;
+
+        Ok(Self {
+            api_key,
+            api_base: cfg
+                .api_base
+                .clone()
+                .unwrap_or_else(|| dialect.default_base.to_string()),
+            dialect,
+            client,
+            model: cfg.model.clone(),
+            headers: cfg.headers.clone(),
+            first_token_timeout_ms: cfg.first_token_timeout_ms.unwrap_or(30_000),
+            stall_timeout_ms: cfg.stall_timeout_ms.unwrap_or(10_000),
+            reasoning_effort: cfg.reasoning_effort.clone(),
+        })
+    }
+
+    /// API base requests go to
+    pub fn api_base(&self) -> &str {
+        &self.api_base
+    }
+
+    /// Model requests are made with
+    pub fn model(&self) -> &str {
+        &self.model
+    }
+
+    fn url(&self, path: &str) -> String {
+        format!("{}/{}", self.api_base.trim_end_matches('/'), path)
+    }
+
+    pub fn build_payload(&self, req: &GenerateRequest, stream: bool) -> JsonValue {
+        let d = &self.dialect;
+        let mut payload = json!({
+            "model": self.model,
+            "messages": OpenAiChat::map_messages(req),
+            "temperature": req.temperature.unwrap_or(0.7),
+        });
+
+        if let Some(obj) = payload.as_object_mut() {
+            obj.insert(
+                d.max_tokens_field.to_string(),
+                json!(req.max_output_tokens.unwrap_or(1024)),
+            );
+            if stream {
+                obj.insert("stream".to_string(), json!(true));
+            }
+            if let Some(tp) = req.top_p {
+                obj.insert("top_p".to_string(), json!(tp));
+            }
+            if let Some(stops) = &req.stop {
+                let limit = d.max_stop_sequences.unwrap_or(stops.len());
+                if stops.len() > limit {
+                    debug!(
+                        "{} accepts at most {} stop sequences; dropping {}",
+                        d.name,
+                        limit,
+                        stops.len() - limit
+                    );
+                }
+                obj.insert("stop".to_string(), json!(&stops[..limit.min(stops.len())]));
+            }
+            if let Some(seed) = req.seed {
+                obj.insert(d.seed_field.to_string(), json!(seed));
+            }
+            if let Some(t) = OpenAiChat::map_tools_openai(&req.tools) {
+                obj.insert("tools".to_string(), json!(t));
+            }
+            let choice = match &req.tool_choice {
+                Some(ToolChoice::Required) => Some(json!(d.required_tool_choice)),
+                other => OpenAiChat::map_tool_choice_openai(other),
+            };
+            if let Some(tc) = choice {
+                obj.insert("tool_choice".to_string(), tc);
+            }
+            if let Some(rf) = OpenAiChat::map_response_format(&req.response_format) {
+                obj.insert("response_format".to_string(), rf);
+            }
+            if d.reasoning_effort {
+                if let Some(effort) = &self.reasoning_effort {
+                    obj.insert("reasoning_effort".to_string(), json!(effort));
+                }
+            }
+        }
+        if d.logprobs {
+            crate::providers::apply_openai_logprobs(&mut payload, req.logprobs);
+        }
+        payload
+    }
+
+    fn apply_headers(
+        &self,
+        mut rb: reqwest::RequestBuilder,
+        req: &GenerateRequest,
+        sse: bool,
+    ) -> reqwest::RequestBuilder {
+        rb = rb
+            .header("Authorization", format!("Bearer {}", self.api_key))
+            .header("Content-Type", "application/json");
+        if sse {
+            rb = rb.header("Accept", "text/event-stream");
+        }
+        if let Some(h) = &self.headers {
+            for (k, v) in h {
+                rb = rb.header(k, v);
+            }
+        }
+        crate::providers::metadata::apply_headers(
+            rb,
+            req.metadata.as_ref(),
+            &["authorization", "content-type", "accept"],
+        )
+    }
+
+    fn map_http_error(name: &str, status: u16, body: String) -> ProviderError {
+        if status == 401 || status == 403 {
+            ProviderError::Auth {
+                details: Some(body),
+                code: None,
+                message: format!("Authentication failed for {}", name),
+                status: Some(status),
+            }
+        } else if status == 404 {
+            ProviderError::ModelUnavailable {
+                details: Some(body),
+                code: None,
+                message: format!("Model not found on {}", name),
+                status: Some(status),
+            }
+        } else if status == 429 {
+            ProviderError::RateLimited {
+                details: Some(body),
+                code: None,
+                message: format!("Rate limited by {}", name),
+                status: Some(status),
+                retry_after_ms: None,
+            }
+        } else if status >= 500 {
+            ProviderError::ServerError {
+                details: Some(body),
+                code: None,
+                message: format!("{} server error", name),
+                status: Some(status),
+            }
+        } else if let Some(err) =
+            crate::providers::capabilities::parse_context_length_error(status, &body)
+        {
+            err
+        } else if status == 413 {
+            // Groq rejects oversized prompts before tokenizing them
+            ProviderError::ContextLengthExceeded {
+                details: Some(body),
+                message: format!("Request too large for {}", name),
+                status: Some(status),
+                max_context_tokens: None,
+                prompt_tokens: None,
+            }
+        } else {
+            ProviderError::InvalidParams {
+                details: Some(body),
+                code: None,
+                message: format!("Invalid parameters for {}", name),
+                status: Some(status),
+            }
+        }
+    }
+
+    /// Usage from a response body or final stream chunk (Groq nests it
+    /// under `x_groq` while streaming)
+    fn parse_usage(v: &JsonValue) -> Option<Usage> {
+        v.get("usage")
+            .filter(|u| !u.is_null())
+            .or_else(|| v.get("x_groq").and_then(|x| x.get("usage")))
+            .and_then(OpenAiChat::parse_usage_openai)
+    }
+
+    pub async fn generate(&self, req: GenerateRequest) -> Result<GenerateResponse, ProviderError> {
+        let name = self.dialect.name;
+        let payload = self.build_payload(&req, false);
+
+        let rb = self.client.post(self.url("chat/completions"));
+        let rb = self.apply_headers(rb, &req, false);
+        let resp = rb
+            .json(&payload)
+            .send()
+            .await
+            .map_err(|e| ProviderError::Network {
+                message: format!("{} network error: {}", name, e),
+            })?;
+
+        let status = resp.status().as_u16();
+        let retry_after = crate::providers::retry::retry_after_ms(resp.headers());
+        let text = resp.text().await.map_err(|e| ProviderError::Network {
+            message: format!("Failed reading {} response: {}", name, e),
+        })?;
+
+        if !(200..300).contains(&status) {
+            return Err(Self::map_http_error(name, status, text).with_retry_after(retry_after));
+        }
+
+        let v: JsonValue = serde_json::from_str(&text).map_err(|e| ProviderError::Network {
+            message: format!("Invalid JSON from {}: {}", name, e),
+        })?;
+
+        self.parse_completion(v)
+    }
+
+    /// Text, tool calls, usage, reasoning and logprobs of a chat completion
+    fn parse_completion(&self, v: JsonValue) -> Result<GenerateResponse, ProviderError> {
+        let choice = v.get("choices").and_then(|c| c.get(0));
+        let content = choice
+            .and_then(|c| c.get("message"))
+            .and_then(|m| m.get("content"))
+            .and_then(|x| x.as_str())
+            .unwrap_or("")
+            .to_string();
+        let tool_calls = choice
+            .map(OpenAiChat::normalize_tool_calls)
+            .unwrap_or_default();
+        let usage = Self::parse_usage(&v);
+        let reasoning = OpenAiChat::parse_reasoning(&v);
+        let logprobs = choice.and_then(crate::providers::parse_openai_logprobs);
+
+        GenerateResponse {
+            text: content,
+            tool_calls,
+            usage,
+            raw: Some(v),
+            provider: None,
+            reasoning,
+            logprobs,
+        }
+        .non_empty(self.dialect.name)
+    }
+
+    /// Generate through the `responses` endpoint, sending the output limit
+    /// as `limit_field`; only the text of `req`'s messages is forwarded
+    pub async fn generate_responses(
+        &self,
+        req: &GenerateRequest,
+        limit_field: &str,
+    ) -> Result<GenerateResponse, ProviderError> {
+        let name = self.dialect.name;
+        let input: Vec<JsonValue> = req
+            .messages
+            .iter()
+            .map(|m| {
+                let text: String = m
+                    .content
+                    .iter()
+                    .filter_map(|p| match p {
+                        crate::providers::ContentPart::Text { text } => Some(text.as_str()),
+                        _ => None,
+                    })
+                    .collect();
+                json!({ "role": m.role, "content": text })
+            })
+            .collect();
+        let mut payload = json!({
+            "model": self.model,
+            "input": input,
+            "temperature": req.temperature.unwrap_or(0.7),
+        });
+        if let Some(obj) = payload.as_object_mut() {
+            obj.insert(
+                limit_field.to_string(),
+                json!(req.max_output_tokens.unwrap_or(1024)),
+            );
+            if let Some(system) = &req.system {
+                obj.insert("instructions".to_string(), json!(system));
+            }
+            if let Some(tp) = req.top_p {
+                obj.insert("top_p".to_string(), json!(tp));
+            }
+            if self.dialect.reasoning_effort {
+                if let Some(effort) = &self.reasoning_effort {
+                    obj.insert("reasoning".to_string(), json!({ "effort": effort }));
+                }
+            }
+        }
+
+        let rb = self.client.post(self.url("responses"));
+        let rb = self.apply_headers(rb, req, false);
+        let v = crate::providers::post_json(rb, &payload, name, |status, body| {
+            Self::map_http_error(name, status, body)
+        })
+        .await?;
+
+        // `output_text` is a convenience field some deployments omit
+        let text = v
+            .get("output_text")
+            .and_then(|t| t.as_str())
+            .map(str::to_string)
+            .unwrap_or_else(|| {
+                v.get("output")
+                    .and_then(|o| o.as_array())
+                    .into_iter()
+                    .flatten()
+                    .filter_map(|item| item.get("content").and_then(|c| c.as_array()))
+                    .flatten()
+                    .filter_map(|c| c.get("text").and_then(|t| t.as_str()))
+                    .collect()
+            });
+        let usage = v.get("usage").filter(|u| !u.is_null()).map(|u| {
+            let field = |k: &str| u.get(k).and_then(|x| x.as_u64()).map(|x| x as u32);
+            Usage {
+                prompt_tokens: field("input_tokens"),
+                completion_tokens: field("output_tokens"),
+                total_tokens: field("total_tokens"),
+                ..Default::default()
+            }
+        });
+
+        GenerateResponse {
+            text,
+            tool_calls: Vec::new(),
+            usage,
+            raw: Some(v),
+            provider: None,
+            reasoning: None,
+            logprobs: None,
+        }
+        .non_empty(name)
+    }
+
+    /// Run `reqs` through the OpenAI Batch API, polling every `poll_interval`
+    pub async fn generate_native_batch(
+        &self,
+        reqs: Vec<GenerateRequest>,
+        poll_interval: Duration,
+    ) -> Vec<Result<GenerateResponse, ProviderError>> {
+        let name = self.dialect.name;
+        let count = reqs.len();
+        let bodies = reqs.iter().map(|r| self.build_payload(r, false)).collect();
+        let batch = crate::providers::batch::OpenAiBatch::new(
+            self.client.clone(),
+            &self.api_base,
+            &self.api_key,
+        )
+        .with_poll_interval(poll_interval);
+        match batch
+            .run(bodies, |status, body| {
+                Self::map_http_error(name, status, body)
+            })
+            .await
+        {
+            Ok(results) => results
+                .into_iter()
+                .map(|r| r.and_then(|v| self.parse_completion(v)))
+                .collect(),
+            Err(e) => vec![Err(e); count],
+        }
+    }
+
+    pub async fn generate_streaming(
+        &self,
+        req: GenerateRequest,
+        on_event: &mut (dyn FnMut(StreamEvent) + Send),
+    ) -> Result<GenerateResponse, ProviderError> {
+        let name = self.dialect.name;
+        let payload = self.build_payload(&req, true);
+
+        let rb = self.client.post(self.url("chat/completions"));
+        let rb = self.apply_headers(rb, &req, true);
+        let resp = rb
+            .json(&payload)
+            .send()
+            .await
+            .map_err(|e| ProviderError::Network {
+                message: format!("{} network error: {}", name, e),
+            })?;
+
+        let status = resp.status().as_u16();
+        let retry_after = crate::providers::retry::retry_after_ms(resp.headers());
+        if !(200..300).contains(&status) {
+            let body = resp
+                .text()
+                .await
+                .unwrap_or_else(|e| format!("Could not read error body: {}", e));
+            return Err(Self::map_http_error(name, status, body).with_retry_after(retry_after));
+        }
+
+        let mut stream = resp.bytes_stream();
+        let mut content = String::new();
+        let mut reasoning = String::new();
+        let mut decoder = SseDecoder::new();
+        let mut pending_tools = ToolCallAccumulator::new();
+        let mut tool_calls = Vec::new();
+        let mut logprobs = Vec::new();
+        let mut usage = None;
+
+        let first_timeout = self.first_token_timeout_ms;
+        let stall_timeout = self.stall_timeout_ms;
+        let mut got_first = false;
+
+        loop {
+            let cur = if got_first {
+                stall_timeout
+            } else {
+                first_timeout
+            };
+            let (events, ended) = match timeout(Duration::from_millis(cur), stream.next()).await {
+                Ok(Some(Ok(chunk))) => (decoder.push_bytes(&chunk), false),
+                Ok(Some(Err(e))) => {
+                    return Err(ProviderError::Network {
+                        message: format!("Error reading streaming response: {}", e),
+                    });
+                }
+                Ok(None) => (decoder.finish().into_iter().collect(), true),
+                Err(_) => {
+                    if !got_first {
+                        return Err(ProviderError::TimeoutFirstToken { timeout_ms: cur });
+                    } else {
+                        return Err(ProviderError::TimeoutStall { timeout_ms: cur });
+                    }
+                }
+            };
+            for event in events.into_iter().filter(|e| !e.is_done()) {
+                let data_line = event.data;
+                if let Some(ev) = crate::providers::parse_openai_chat_sse(&data_line) {
+                    if let StreamEvent::TextDelta(d) = &ev {
+                        content.push_str(d);
+                        got_first = true;
+                    }
+                    on_event(ev);
+                }
+                if let Some(ev) = crate::providers::parse_openai_chat_reasoning_sse(&data_line) {
+                    if let StreamEvent::ReasoningDelta(d) = &ev {
+                        reasoning.push_str(d);
+                        got_first = true;
+                    }
+                    on_event(ev);
+                }
+                if let Some(tokens) = crate::providers::parse_openai_chat_logprobs_sse(&data_line) {
+                    logprobs.extend(tokens);
+                }
+                for ev in pending_tools.push_openai_chat_sse(&data_line) {
+                    got_first = true;
+                    on_event(ev);
+                }
+                if let Some(u) = serde_json::from_str::<JsonValue>(&data_line)
+                    .ok()
+                    .as_ref()
+                    .and_then(Self::parse_usage)
+                {
+                    on_event(StreamEvent::Usage(u.clone()));
+                    usage = Some(u);
+                }
+                if crate::providers::is_openai_chat_finish(&data_line) {
+                    for tc in pending_tools.finish_all() {
+                        on_event(StreamEvent::ToolCall(tc.clone()));
+                        tool_calls.push(tc);
+                    }
+                    on_event(StreamEvent::Finished);
+                }
+            }
+            if ended {
+                for tc in pending_tools.finish_all() {
+                    on_event(StreamEvent::ToolCall(tc.clone()));
+                    tool_calls.push(tc);
+                }
+                break;
+            }
+        }
+
+        Ok(GenerateResponse {
+            text: content,
+            tool_calls,
+            usage,
+            raw: None,
+            provider: None,
+            reasoning: (!reasoning.is_empty()).then_some(reasoning),
+            logprobs: (!logprobs.is_empty()).then_some(logprobs),
+        })
+    }
+
+    pub async fn list_models(&self) -> Result<Vec<ModelInfo>, ProviderError> {
+        let name = self.dialect.name;
+        let rb = self
+            .client
+            .get(self.url("models"))
+            .header("Authorization", format!("Bearer {}", self.api_key));
+        let v = crate::providers::get_json(rb, name, |status, body| {
+            Self::map_http_error(name, status, body)
+        })
+        .await?;
+        Ok(v.get("data")
+            .and_then(|d| d.as_array())
+            .map(|models| {
+                models
+                    .iter()
+                    .filter_map(|m| {
+                        Some(ModelInfo {
+                            id: m.get("id")?.as_str()?.to_string(),
+                            context_length: m
+                                .get("context_window")
+                                .or_else(|| m.get("max_context_length"))
+                                .and_then(|c| c.as_u64())
+                                .map(|c| c as usize),
+                        })
+                    })
+                    .collect()
+            })
+            .unwrap_or_default())
+    }
+
+    /// Embeddings of `inputs` from the `embeddings` endpoint
+    pub async fn embed(&self, inputs: &[String]) -> Result<Vec<Vec<f32>>, ProviderError> {
+        let name = self.dialect.name;
+        let mut rb = self
+            .client
+            .post(self.url("embeddings"))
+            .header("Authorization", format!("Bearer {}", self.api_key));
+        if let Some(headers) = &self.headers {
+            for (k, v) in headers {
+                rb = rb.header(k, v);
+            }
+        }
+        let payload = json!({ "model": self.model, "input": inputs });
+        let v = crate::providers::post_json(rb, &payload, name, |status, body| {
+            Self::map_http_error(name, status, body)
+        })
+        .await?;
+
+        // Entries carry their input index and need not come back in order
+        let mut data: Vec<&JsonValue> = v
+            .get("data")
+            .and_then(|d| d.as_array())
+            .map(|d| d.iter().collect())
+            .unwrap_or_default();
+        data.sort_by_key(|entry| entry.get("index").and_then(|i| i.as_u64()));
+        let vectors = JsonValue::Array(
+            data.into_iter()
+                .filter_map(|entry| entry.get("embedding").cloned())
+                .collect(),
+        );
+        crate::providers::parse_embeddings(Some(&vectors), inputs.len(), name)
+    }
+}
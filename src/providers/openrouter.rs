@@ -11,8 +11,8 @@ use tokio::time::timeout;
 use crate::core::config::LlmConfig;
 use crate::core::error::ProviderError;
 use crate::providers::{
-    ContentPart, GenerateRequest, GenerateResponse, ResponseFormat, Role, SseDecoder, StreamEvent,
-    ToolCallNormalized, ToolChoice, ToolSpec, Usage,
+    ContentPart, GenerateRequest, GenerateResponse, ModelInfo, ResponseFormat, Role, SseDecoder,
+    StreamEvent, ToolCallAccumulator, ToolCallNormalized, ToolChoice, ToolSpec, Usage,
 };
 
 /// OpenRouter adapter using OpenAI-style /chat/completions by default.
@@ -26,6 +26,9 @@ pub struct OpenRouterProvider {
     headers: Option<HashMap<String, String>>,
     first_token_timeout_ms: u64,
     stall_timeout_ms: u64,
+    /// Send `generate_batch` through the OpenAI Batch API
+    native_batch: bool,
+    batch_poll_interval: Duration,
 }
 
 impl OpenRouterProvider {
@@ -42,27 +45,40 @@ impl OpenRouterProvider {
             })?
         };
 
-        let client = Client::builder()
-            .timeout(Duration::from_secs(120))
-            .build()
-            .map_err(|e| ProviderError::Network {
-                message: format!("Failed to create HTTP client: {}", e),
-            })?;
+        let client =
+            crate::providers::http::build_client(cfg.network.as_ref(), Duration::from_secs(120))?;
 
+        let api_base = cfg
+            .api_base
+            .clone()
+            .unwrap_or_else(|| "https://openrouter.ai/api/v1".to_string());
         Ok(Self {
             client,
             api_key,
-            api_base: cfg
-                .api_base
-                .clone()
-                .unwrap_or_else(|| "https://openrouter.ai/api/v1".to_string()),
+            // OpenRouter has no batch endpoint; OpenAI itself does
+            native_batch: api_base.contains("api.openai.com"),
+            api_base,
             model: cfg.model.clone(),
             headers: cfg.headers.clone(),
             first_token_timeout_ms: cfg.first_token_timeout_ms.unwrap_or(30_000),
             stall_timeout_ms: cfg.stall_timeout_ms.unwrap_or(10_000),
+            batch_poll_interval: Duration::from_secs(30),
         })
     }
 
+    /// Use the OpenAI Batch API for `generate_batch` (on by default when the
+    /// base URL is OpenAI's)
+    pub fn with_native_batch(mut self, enabled: bool) -> Self {
+        self.native_batch = enabled;
+        self
+    }
+
+    /// How often a submitted batch is polled for completion
+    pub fn with_batch_poll_interval(mut self, interval: Duration) -> Self {
+        self.batch_poll_interval = interval;
+        self
+    }
+
     fn build_url(&self, path: &str) -> String {
         format!(
             "{}/{}",
@@ -71,35 +87,77 @@ impl OpenRouterProvider {
         )
     }
 
-    fn map_messages(req: &GenerateRequest) -> Vec<JsonValue> {
+    pub(crate) fn map_messages(req: &GenerateRequest) -> Vec<JsonValue> {
         // OpenAI-style messages: include system as a dedicated message when present
         let mut msgs: Vec<JsonValue> = Vec::new();
         if let Some(sys) = &req.system {
             msgs.push(json!({"role":"system","content": sys}));
         }
         for m in &req.messages {
+            // Each tool result is a `tool` message of its own, keyed by call id
+            let results: Vec<JsonValue> = m
+                .content
+                .iter()
+                .filter_map(|p| match p {
+                    ContentPart::ToolResult {
+                        call_id,
+                        content,
+                        is_error,
+                    } => Some(json!({
+                        "role": "tool",
+                        "tool_call_id": call_id,
+                        "content": if *is_error {
+                            format!("Error: {}", content)
+                        } else {
+                            content.clone()
+                        }
+                    })),
+                    _ => None,
+                })
+                .collect();
+            if !results.is_empty() {
+                msgs.extend(results);
+                continue;
+            }
+
             let role = match m.role {
                 Role::System => "system",
                 Role::User => "user",
                 Role::Assistant => "assistant",
                 Role::Tool => "tool",
             };
-            // Concatenate text parts; ignore images in chat format
-            let text = m
+            let mut msg = json!({
+                "role": role,
+                "content": crate::providers::openai_chat_content(&m.content)
+            });
+            let calls: Vec<JsonValue> = m
                 .content
                 .iter()
                 .filter_map(|p| match p {
-                    ContentPart::Text { text } => Some(text.as_str()),
-                    ContentPart::ImageUrl { .. } => None,
+                    ContentPart::ToolCall {
+                        id,
+                        name,
+                        arguments,
+                    } => Some(json!({
+                        "id": id,
+                        "type": "function",
+                        "function": {"name": name, "arguments": arguments.to_string()}
+                    })),
+                    _ => None,
                 })
-                .collect::<Vec<_>>()
-                .join(" ");
-            msgs.push(json!({"role": role, "content": text}));
+                .collect();
+            if !calls.is_empty() {
+                if msg["content"] == json!("") {
+                    msg["content"] = JsonValue::Null;
+                }
+                msg["tool_calls"] = json!(calls);
+            }
+            msgs.push(msg);
         }
         msgs
     }
 
-    fn map_tools_openai(tools: &Option<Vec<ToolSpec>>) -> Option<Vec<JsonValue>> {
+    pub(crate) fn map_tools_openai(tools: &Option<Vec<ToolSpec>>) -> Option<Vec<JsonValue>> {
         tools.as_ref().map(|v| {
             v.iter()
                 .map(|t| {
@@ -120,7 +178,7 @@ impl OpenRouterProvider {
         })
     }
 
-    fn map_tool_choice_openai(choice: &Option<ToolChoice>) -> Option<JsonValue> {
+    pub(crate) fn map_tool_choice_openai(choice: &Option<ToolChoice>) -> Option<JsonValue> {
         match choice {
             Some(ToolChoice::Auto) => Some(json!("auto")),
             Some(ToolChoice::Required) => Some(json!("required")),
@@ -129,7 +187,7 @@ impl OpenRouterProvider {
         }
     }
 
-    fn map_response_format(format: &Option<ResponseFormat>) -> Option<JsonValue> {
+    pub(crate) fn map_response_format(format: &Option<ResponseFormat>) -> Option<JsonValue> {
         format.as_ref().map(|f| match f {
             ResponseFormat::JsonObject => json!({"type": "json_object"}),
             ResponseFormat::JsonSchema { json_schema } => json!({
@@ -161,22 +219,23 @@ impl OpenRouterProvider {
                 rb = rb.header(k, v);
             }
         }
-        // Forward request metadata headers if present
-        if let Some(meta) = &req.metadata {
-            for (k, v) in meta {
-                if k.eq_ignore_ascii_case("authorization")
-                    || k.eq_ignore_ascii_case("content-type")
-                    || k.eq_ignore_ascii_case("accept")
-                {
-                    continue;
-                }
-                rb = rb.header(k, v);
-            }
-        }
-        rb
+        crate::providers::metadata::apply_headers(
+            rb,
+            req.metadata.as_ref(),
+            &["authorization", "content-type", "accept"],
+        )
     }
 
-    fn parse_usage_openai(v: &JsonValue) -> Option<Usage> {
+    /// Reasoning text of the first choice's message
+    pub(crate) fn parse_reasoning(v: &JsonValue) -> Option<String> {
+        v.get("choices")
+            .and_then(|c| c.get(0))
+            .and_then(|c| c.get("message"))
+            .and_then(crate::providers::openai_chat_reasoning)
+            .map(str::to_string)
+    }
+
+    pub(crate) fn parse_usage_openai(v: &JsonValue) -> Option<Usage> {
         let prompt_tokens = v
             .get("prompt_tokens")
             .and_then(|x| x.as_u64())
@@ -193,14 +252,103 @@ impl OpenRouterProvider {
                 (Some(p), Some(c)) => Some(p + c),
                 _ => None,
             });
+        // OpenAI-style APIs count cached tokens inside `prompt_tokens`
+        let cache_read_tokens = v
+            .get("prompt_tokens_details")
+            .and_then(|d| d.get("cached_tokens"))
+            .and_then(|x| x.as_u64())
+            .map(|x| x as u32);
         Some(Usage {
             prompt_tokens,
             completion_tokens,
             total_tokens,
+            retries: None,
+            cache_read_tokens,
+            cache_write_tokens: None,
         })
     }
 
-    fn normalize_tool_calls(choice: &JsonValue) -> Vec<ToolCallNormalized> {
+    /// Non-streaming `/chat/completions` body for `req`
+    pub(crate) fn build_payload(&self, req: &GenerateRequest) -> JsonValue {
+        // Base OpenAI-style payload
+        let mut payload = json!({
+            "model": self.model,
+            "messages": Self::map_messages(req),
+            "max_tokens": req.max_output_tokens.unwrap_or(1024),
+            "temperature": req.temperature.unwrap_or(0.7),
+        });
+
+        // Optional sampling and stops
+        if let Some(tp) = req.top_p {
+            if let Some(obj) = payload.as_object_mut() {
+                obj.insert("top_p".to_string(), json!(tp));
+            }
+        }
+        if let Some(stops) = &req.stop {
+            if let Some(obj) = payload.as_object_mut() {
+                obj.insert("stop".to_string(), json!(stops));
+            }
+        }
+
+        // Tools and tool_choice
+        if let Some(t) = Self::map_tools_openai(&req.tools) {
+            if let Some(obj) = payload.as_object_mut() {
+                obj.insert("tools".to_string(), json!(t));
+            }
+        }
+        if let Some(tc) = Self::map_tool_choice_openai(&req.tool_choice) {
+            if let Some(obj) = payload.as_object_mut() {
+                obj.insert("tool_choice".to_string(), tc);
+            }
+        }
+
+        // Response format for structured outputs
+        if let Some(rf) = Self::map_response_format(&req.response_format) {
+            if let Some(obj) = payload.as_object_mut() {
+                obj.insert("response_format".to_string(), rf);
+            }
+        }
+        crate::providers::apply_openai_logprobs(&mut payload, req.logprobs);
+        payload
+    }
+
+    /// Text, tool calls, usage and reasoning of a chat completion
+    pub(crate) fn parse_completion(v: JsonValue) -> Result<GenerateResponse, ProviderError> {
+        let content = v
+            .get("choices")
+            .and_then(|c| c.get(0))
+            .and_then(|c| c.get("message"))
+            .and_then(|m| m.get("content"))
+            .and_then(|x| x.as_str())
+            .unwrap_or("")
+            .to_string();
+
+        let tool_calls = v
+            .get("choices")
+            .and_then(|c| c.get(0))
+            .map(Self::normalize_tool_calls)
+            .unwrap_or_default();
+
+        let usage = v.get("usage").and_then(Self::parse_usage_openai);
+        let reasoning = Self::parse_reasoning(&v);
+        let logprobs = v
+            .get("choices")
+            .and_then(|c| c.get(0))
+            .and_then(crate::providers::parse_openai_logprobs);
+
+        GenerateResponse {
+            text: content,
+            tool_calls,
+            usage,
+            raw: Some(v),
+            provider: None,
+            reasoning,
+            logprobs,
+        }
+        .non_empty("OpenRouter")
+    }
+
+    pub(crate) fn normalize_tool_calls(choice: &JsonValue) -> Vec<ToolCallNormalized> {
         let mut out = Vec::new();
         if let Some(tool_calls) = choice.get("message").and_then(|m| m.get("tool_calls")) {
             if let Some(arr) = tool_calls.as_array() {
@@ -254,6 +402,10 @@ impl OpenRouterProvider {
                 message: "OpenRouter server error".to_string(),
                 status: Some(status),
             }
+        } else if let Some(err) =
+            crate::providers::capabilities::parse_context_length_error(status, &body)
+        {
+            err
         } else if lower.contains("unsupported parameter") || lower.contains("invalid") {
             ProviderError::InvalidParams {
                 details: Some(body),
@@ -276,45 +428,7 @@ impl OpenRouterProvider {
 impl crate::providers::Provider for OpenRouterProvider {
     async fn generate(&self, req: GenerateRequest) -> Result<GenerateResponse, ProviderError> {
         let url = self.build_url("chat/completions");
-
-        // Base OpenAI-style payload
-        let mut payload = json!({
-            "model": self.model,
-            "messages": Self::map_messages(&req),
-            "max_tokens": req.max_output_tokens.unwrap_or(1024),
-            "temperature": req.temperature.unwrap_or(0.7),
-        });
-
-        // Optional sampling and stops
-        if let Some(tp) = req.top_p {
-            if let Some(obj) = payload.as_object_mut() {
-                obj.insert("top_p".to_string(), json!(tp));
-            }
-        }
-        if let Some(stops) = &req.stop {
-            if let Some(obj) = payload.as_object_mut() {
-                obj.insert("stop".to_string(), json!(stops));
-            }
-        }
-
-        // Tools and tool_choice
-        if let Some(t) = Self::map_tools_openai(&req.tools) {
-            if let Some(obj) = payload.as_object_mut() {
-                obj.insert("tools".to_string(), json!(t));
-            }
-        }
-        if let Some(tc) = Self::map_tool_choice_openai(&req.tool_choice) {
-            if let Some(obj) = payload.as_object_mut() {
-                obj.insert("tool_choice".to_string(), tc);
-            }
-        }
-
-        // Response format for structured outputs
-        if let Some(rf) = Self::map_response_format(&req.response_format) {
-            if let Some(obj) = payload.as_object_mut() {
-                obj.insert("response_format".to_string(), rf);
-            }
-        }
+        let payload = self.build_payload(&req);
 
         // Send request
         let rb = self.client.post(&url);
@@ -328,6 +442,7 @@ impl crate::providers::Provider for OpenRouterProvider {
             })?;
 
         let status = resp.status().as_u16();
+        let retry_after = crate::providers::retry::retry_after_ms(resp.headers());
         let text = resp.text().await.map_err(|e| ProviderError::Network {
             message: format!("Failed reading OpenRouter response: {}", e),
         })?;
@@ -378,63 +493,97 @@ impl crate::providers::Provider for OpenRouterProvider {
                         serde_json::from_str(&text2).map_err(|e| ProviderError::Network {
                             message: format!("Invalid JSON from OpenRouter retry: {}", e),
                         })?;
-                    // Extract text and tool calls
-                    let content = v
-                        .get("choices")
-                        .and_then(|c| c.get(0))
-                        .and_then(|c| c.get("message"))
-                        .and_then(|m| m.get("content"))
-                        .and_then(|x| x.as_str())
-                        .unwrap_or("")
-                        .to_string();
-
-                    let tool_calls = v
-                        .get("choices")
-                        .and_then(|c| c.get(0))
-                        .map(Self::normalize_tool_calls)
-                        .unwrap_or_default();
-
-                    let usage = v.get("usage").and_then(Self::parse_usage_openai);
-
-                    return Ok(GenerateResponse {
-                        text: content,
-                        tool_calls,
-                        usage,
-                        raw: Some(v),
-                    });
+                    return Self::parse_completion(v);
                 }
             }
 
-            return Err(Self::map_http_error(status, text));
+            return Err(Self::map_http_error(status, text).with_retry_after(retry_after));
         }
 
         let v: JsonValue = serde_json::from_str(&text).map_err(|e| ProviderError::Network {
             message: format!("Invalid JSON from OpenRouter: {}", e),
         })?;
 
-        let content = v
-            .get("choices")
-            .and_then(|c| c.get(0))
-            .and_then(|c| c.get("message"))
-            .and_then(|m| m.get("content"))
-            .and_then(|x| x.as_str())
-            .unwrap_or("")
-            .to_string();
+        Self::parse_completion(v)
+    }
 
-        let tool_calls = v
-            .get("choices")
-            .and_then(|c| c.get(0))
-            .map(Self::normalize_tool_calls)
-            .unwrap_or_default();
+    fn supports_stream_resume(&self) -> bool {
+        true
+    }
 
-        let usage = v.get("usage").and_then(Self::parse_usage_openai);
+    async fn generate_batch(
+        &self,
+        reqs: Vec<GenerateRequest>,
+    ) -> Vec<Result<GenerateResponse, ProviderError>> {
+        if !self.native_batch {
+            return crate::providers::batch::generate_concurrently(
+                self,
+                reqs,
+                crate::providers::batch::DEFAULT_BATCH_CONCURRENCY,
+            )
+            .await;
+        }
 
-        Ok(GenerateResponse {
-            text: content,
-            tool_calls,
-            usage,
-            raw: Some(v),
-        })
+        let count = reqs.len();
+        let bodies = reqs.iter().map(|r| self.build_payload(r)).collect();
+        let batch = crate::providers::batch::OpenAiBatch::new(
+            self.client.clone(),
+            &self.api_base,
+            &self.api_key,
+        )
+        .with_poll_interval(self.batch_poll_interval);
+        match batch.run(bodies, Self::map_http_error).await {
+            Ok(results) => results
+                .into_iter()
+                .map(|r| r.and_then(Self::parse_completion))
+                .collect(),
+            Err(e) => vec![Err(e); count],
+        }
+    }
+
+    fn supports_native_batch(&self) -> bool {
+        self.native_batch
+    }
+
+    async fn list_models(&self) -> Result<Vec<ModelInfo>, ProviderError> {
+        let rb = self
+            .client
+            .get(self.build_url("models"))
+            .header("Authorization", format!("Bearer {}", self.api_key));
+        let v = crate::providers::get_json(rb, "OpenRouter", Self::map_http_error).await?;
+        Ok(v.get("data")
+            .and_then(|d| d.as_array())
+            .map(|models| {
+                models
+                    .iter()
+                    .filter_map(|m| {
+                        Some(ModelInfo {
+                            id: m.get("id")?.as_str()?.to_string(),
+                            context_length: m
+                                .get("context_length")
+                                .and_then(|c| c.as_u64())
+                                .map(|c| c as usize),
+                        })
+                    })
+                    .collect()
+            })
+            .unwrap_or_default())
+    }
+
+    async fn health(&self) -> Result<(), ProviderError> {
+        // OpenRouter's model list is public, so check the key explicitly;
+        // other OpenAI-compatible servers authenticate `/models` itself
+        if self.api_base.contains("openrouter.ai") {
+            let rb = self
+                .client
+                .get(self.build_url("key"))
+                .header("Authorization", format!("Bearer {}", self.api_key));
+            crate::providers::get_json(rb, "OpenRouter", Self::map_http_error)
+                .await
+                .map(|_| ())
+        } else {
+            self.list_models().await.map(|_| ())
+        }
     }
 
     async fn generate_streaming(
@@ -479,6 +628,7 @@ impl crate::providers::Provider for OpenRouterProvider {
                 obj.insert("response_format".to_string(), rf);
             }
         }
+        crate::providers::apply_openai_logprobs(&mut payload, req.logprobs);
 
         // Send request
         let rb = self.client.post(&url);
@@ -492,6 +642,7 @@ impl crate::providers::Provider for OpenRouterProvider {
             })?;
 
         let status = resp.status().as_u16();
+        let retry_after = crate::providers::retry::retry_after_ms(resp.headers());
         if !(200..300).contains(&status) {
             let body = resp
                 .text()
@@ -536,13 +687,17 @@ impl crate::providers::Provider for OpenRouterProvider {
                     return Err(Self::map_http_error(status2, body2));
                 }
             } else {
-                return Err(Self::map_http_error(status, body));
+                return Err(Self::map_http_error(status, body).with_retry_after(retry_after));
             }
         }
 
         let mut stream = resp.bytes_stream();
         let mut content = String::new();
+        let mut reasoning = String::new();
         let mut decoder = SseDecoder::new();
+        let mut pending_tools = ToolCallAccumulator::new();
+        let mut tool_calls = Vec::new();
+        let mut logprobs = Vec::new();
 
         let first_timeout = self.first_token_timeout_ms;
         let stall_timeout = self.stall_timeout_ms;
@@ -554,29 +709,14 @@ impl crate::providers::Provider for OpenRouterProvider {
             } else {
                 first_timeout
             };
-            match timeout(Duration::from_millis(cur), stream.next()).await {
-                Ok(Some(Ok(chunk))) => {
-                    let s = String::from_utf8_lossy(&chunk);
-                    for data_line in decoder.push_chunk(&s) {
-                        // Parse as OpenAI-chat SSE
-                        if let Some(ev) = crate::providers::parse_openai_chat_sse(&data_line) {
-                            if let StreamEvent::TextDelta(d) = &ev {
-                                content.push_str(d);
-                                got_first = true;
-                            }
-                            on_event(ev);
-                        } else {
-                            // Not a standard delta; ignore but keep debug
-                            debug!("Unhandled OpenRouter SSE line: {}", data_line);
-                        }
-                    }
-                }
+            let (events, ended) = match timeout(Duration::from_millis(cur), stream.next()).await {
+                Ok(Some(Ok(chunk))) => (decoder.push_bytes(&chunk), false),
                 Ok(Some(Err(e))) => {
                     return Err(ProviderError::Network {
                         message: format!("Error reading streaming response: {}", e),
                     });
                 }
-                Ok(None) => break,
+                Ok(None) => (decoder.finish().into_iter().collect(), true),
                 Err(_) => {
                     if !got_first {
                         return Err(ProviderError::TimeoutFirstToken { timeout_ms: cur });
@@ -584,14 +724,61 @@ impl crate::providers::Provider for OpenRouterProvider {
                         return Err(ProviderError::TimeoutStall { timeout_ms: cur });
                     }
                 }
+            };
+            for event in events.into_iter().filter(|e| !e.is_done()) {
+                let data_line = event.data;
+                // Parse as OpenAI-chat SSE
+                if let Some(ev) = crate::providers::parse_openai_chat_sse(&data_line) {
+                    if let StreamEvent::TextDelta(d) = &ev {
+                        content.push_str(d);
+                        got_first = true;
+                    }
+                    on_event(ev);
+                } else {
+                    // Not a standard delta; ignore but keep debug
+                    debug!("Unhandled OpenRouter SSE line: {}", data_line);
+                }
+                // Reasoning models stream their thinking alongside (often
+                // empty) content deltas
+                if let Some(ev) = crate::providers::parse_openai_chat_reasoning_sse(&data_line) {
+                    if let StreamEvent::ReasoningDelta(d) = &ev {
+                        reasoning.push_str(d);
+                        got_first = true;
+                    }
+                    on_event(ev);
+                }
+                if let Some(tokens) = crate::providers::parse_openai_chat_logprobs_sse(&data_line) {
+                    logprobs.extend(tokens);
+                }
+                for ev in pending_tools.push_openai_chat_sse(&data_line) {
+                    got_first = true;
+                    on_event(ev);
+                }
+                if crate::providers::is_openai_chat_finish(&data_line) {
+                    for tc in pending_tools.finish_all() {
+                        on_event(StreamEvent::ToolCall(tc.clone()));
+                        tool_calls.push(tc);
+                    }
+                    on_event(StreamEvent::Finished);
+                }
+            }
+            if ended {
+                for tc in pending_tools.finish_all() {
+                    on_event(StreamEvent::ToolCall(tc.clone()));
+                    tool_calls.push(tc);
+                }
+                break;
             }
         }
 
         Ok(GenerateResponse {
             text: content,
-            tool_calls: vec![], // tool calls are not emitted in text delta path; final choices parsing not available in SSE
+            tool_calls,
             usage: None,
             raw: None,
+            provider: None,
+            reasoning: (!reasoning.is_empty()).then_some(reasoning),
+            logprobs: (!logprobs.is_empty()).then_some(logprobs),
         })
     }
 }
@@ -0,0 +1,316 @@
+//! Speculative racing between two providers.
+//!
+//! A `RacingProvider` sends every request to two backends at once and keeps
+//! whichever answers first, dropping (and so cancelling) the other request.
+//! It trades doubled spend for latency when one backend is erratic. When a
+//! racer fails the other still gets to finish; the response records which
+//! backend won in `GenerateResponse::provider`.
+//!
+//! Streaming commits to the first racer that emits an event, since output
+//! from two different answers cannot be interleaved.
+
+use async_trait::async_trait;
+use futures_util::future::BoxFuture;
+use log::debug;
+use tokio::sync::mpsc;
+
+use crate::core::error::ProviderError;
+use crate::providers::{GenerateRequest, GenerateResponse, ModelInfo, Provider, StreamEvent};
+
+/// Provider that races two backends and returns the first success
+pub struct RacingProvider {
+    racers: [(String, Box<dyn Provider>); 2],
+}
+
+type Outcome = Result<GenerateResponse, ProviderError>;
+
+/// Await `racer`, or never resolve once it has been dropped
+async fn run(racer: &mut Option<BoxFuture<'_, Outcome>>) -> Outcome {
+    match racer {
+        Some(fut) => fut.await,
+        None => std::future::pending().await,
+    }
+}
+
+impl RacingProvider {
+    /// Race `primary` against `rival`
+    pub fn new(
+        primary_name: impl Into<String>,
+        primary: Box<dyn Provider>,
+        rival_name: impl Into<String>,
+        rival: Box<dyn Provider>,
+    ) -> Self {
+        Self {
+            racers: [(primary_name.into(), primary), (rival_name.into(), rival)],
+        }
+    }
+
+    /// Names of the two racers, primary first
+    pub fn names(&self) -> [&str; 2] {
+        [&self.racers[0].0, &self.racers[1].0]
+    }
+
+    fn won_by(&self, index: usize, response: GenerateResponse) -> GenerateResponse {
+        debug!("Race won by '{}'", self.racers[index].0);
+        GenerateResponse {
+            provider: Some(self.racers[index].0.clone()),
+            ..response
+        }
+    }
+}
+
+#[async_trait]
+impl Provider for RacingProvider {
+    async fn generate(&self, req: GenerateRequest) -> Result<GenerateResponse, ProviderError> {
+        let mut first = Some(self.racers[0].1.generate(req.clone()));
+        let mut second = Some(self.racers[1].1.generate(req));
+        let mut first_error = None;
+        loop {
+            let (index, outcome) = tokio::select! {
+                r = run(&mut first), if first.is_some() => {
+                    first = None;
+                    (0, r)
+                }
+                r = run(&mut second), if second.is_some() => {
+                    second = None;
+                    (1, r)
+                }
+            };
+            match outcome {
+                Ok(response) => return Ok(self.won_by(index, response)),
+                Err(e) if first.is_none() && second.is_none() => {
+                    return Err(first_error.unwrap_or(e))
+                }
+                Err(e) => {
+                    debug!("Racer '{}' failed: {}", self.racers[index].0, e);
+                    first_error = Some(e);
+                }
+            }
+        }
+    }
+
+    fn supports_stream_resume(&self) -> bool {
+        self.racers
+            .iter()
+            .all(|(_, provider)| provider.supports_stream_resume())
+    }
+
+    /// Models of the primary backend
+    async fn list_models(&self) -> Result<Vec<ModelInfo>, ProviderError> {
+        self.racers[0].1.list_models().await
+    }
+
+    /// Embeddings of the primary backend, whose vectors are the ones an
+    /// index was built with
+    async fn embed(&self, inputs: &[String]) -> Result<Vec<Vec<f32>>, ProviderError> {
+        self.racers[0].1.embed(inputs).await
+    }
+
+    /// Healthy when either racer is
+    async fn health(&self) -> Result<(), ProviderError> {
+        match self.racers[0].1.health().await {
+            Ok(()) => Ok(()),
+            Err(e) => self.racers[1].1.health().await.map_err(|_| e),
+        }
+    }
+
+    async fn generate_streaming(
+        &self,
+        req: GenerateRequest,
+        on_event: &mut (dyn FnMut(StreamEvent) + Send),
+    ) -> Result<GenerateResponse, ProviderError> {
+        let (tx0, mut rx0) = mpsc::unbounded_channel();
+        let (tx1, mut rx1) = mpsc::unbounded_channel();
+        let stream = |index: usize, tx: mpsc::UnboundedSender<StreamEvent>, req| {
+            let provider = &self.racers[index].1;
+            Box::pin(async move {
+                let mut forward = move |event: StreamEvent| {
+                    let _ = tx.send(event);
+                };
+                provider.generate_streaming(req, &mut forward).await
+            }) as BoxFuture<'_, Outcome>
+        };
+        let mut first = Some(stream(0, tx0, req.clone()));
+        let mut second = Some(stream(1, tx1, req));
+        // The first racer to emit an event leads; the other is dropped
+        let mut leader: Option<usize> = None;
+        let mut first_error = None;
+
+        loop {
+            let (index, outcome) = tokio::select! {
+                Some(event) = rx0.recv(), if leader != Some(1) => {
+                    if leader.is_none() {
+                        leader = Some(0);
+                        second = None;
+                    }
+                    on_event(event);
+                    continue;
+                }
+                Some(event) = rx1.recv(), if leader != Some(0) => {
+                    if leader.is_none() {
+                        leader = Some(1);
+                        first = None;
+                    }
+                    on_event(event);
+                    continue;
+                }
+                r = run(&mut first), if first.is_some() => {
+                    first = None;
+                    (0, r)
+                }
+                r = run(&mut second), if second.is_some() => {
+                    second = None;
+                    (1, r)
+                }
+            };
+            match outcome {
+                Ok(response) => {
+                    // Events sent just before the racer finished are still queued
+                    let rx = if index == 0 { &mut rx0 } else { &mut rx1 };
+                    while let Ok(event) = rx.try_recv() {
+                        on_event(event);
+                    }
+                    return Ok(self.won_by(index, response));
+                }
+                // Switching racers after output reached the caller would
+                // interleave two different answers
+                Err(e) if leader == Some(index) => return Err(e),
+                Err(e) if first.is_none() && second.is_none() => {
+                    return Err(first_error.unwrap_or(e))
+                }
+                Err(e) => {
+                    debug!("Racer '{}' failed: {}", self.racers[index].0, e);
+                    first_error = Some(e);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::providers::{ContentPart, Message, Role};
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    /// Answers `result` after `delay_ms`, recording whether it got that far
+    struct Delayed {
+        delay_ms: u64,
+        result: Result<&'static str, u16>,
+        finished: Arc<AtomicBool>,
+    }
+
+    impl Delayed {
+        fn new(delay_ms: u64, result: Result<&'static str, u16>) -> (Self, Arc<AtomicBool>) {
+            let finished = Arc::new(AtomicBool::new(false));
+            let provider = Self {
+                delay_ms,
+                result,
+                finished: finished.clone(),
+            };
+            (provider, finished)
+        }
+    }
+
+    #[async_trait]
+    impl Provider for Delayed {
+        async fn generate(&self, _req: GenerateRequest) -> Result<GenerateResponse, ProviderError> {
+            tokio::time::sleep(Duration::from_millis(self.delay_ms)).await;
+            self.finished.store(true, Ordering::SeqCst);
+            match self.result {
+                Ok(text) => Ok(GenerateResponse {
+                    text: text.to_string(),
+                    tool_calls: Vec::new(),
+                    usage: None,
+                    raw: None,
+                    provider: None,
+                    reasoning: None,
+                    logprobs: None,
+                }),
+                Err(status) => Err(ProviderError::ServerError {
+                    details: None,
+                    code: None,
+                    message: "down".to_string(),
+                    status: Some(status),
+                }),
+            }
+        }
+
+        async fn generate_streaming(
+            &self,
+            req: GenerateRequest,
+            on_event: &mut (dyn FnMut(StreamEvent) + Send),
+        ) -> Result<GenerateResponse, ProviderError> {
+            let res = self.generate(req).await?;
+            on_event(StreamEvent::TextDelta(res.text.clone()));
+            Ok(res)
+        }
+    }
+
+    fn req() -> GenerateRequest {
+        GenerateRequest {
+            system: None,
+            messages: vec![Message {
+                role: Role::User,
+                content: vec![ContentPart::Text {
+                    text: "hi".to_string(),
+                }],
+            }],
+            tools: None,
+            tool_choice: None,
+            temperature: None,
+            top_p: None,
+            stop: None,
+            seed: None,
+            logit_bias: None,
+            response_format: None,
+            max_output_tokens: None,
+            metadata: None,
+            logprobs: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_fastest_success_wins_and_loser_is_cancelled() {
+        let (slow, slow_finished) = Delayed::new(2_000, Ok("slow"));
+        let (fast, _) = Delayed::new(10, Ok("fast"));
+        let race = RacingProvider::new("openrouter", Box::new(slow), "anthropic", Box::new(fast));
+
+        let res = race.generate(req()).await.expect("fast answers");
+        assert_eq!(res.text, "fast");
+        assert_eq!(res.provider.as_deref(), Some("anthropic"));
+        assert!(!slow_finished.load(Ordering::SeqCst), "loser was dropped");
+
+        let mut text = String::new();
+        let mut on_event = |ev: StreamEvent| {
+            if let StreamEvent::TextDelta(d) = ev {
+                text.push_str(&d);
+            }
+        };
+        let res = race
+            .generate_streaming(req(), &mut on_event)
+            .await
+            .expect("fast streams");
+        assert_eq!(res.provider.as_deref(), Some("anthropic"));
+        assert_eq!(text, "fast");
+    }
+
+    #[tokio::test]
+    async fn test_failed_racer_leaves_the_other_to_finish() {
+        let (failing, _) = Delayed::new(1, Err(503));
+        let (slow, _) = Delayed::new(30, Ok("slow"));
+        let race = RacingProvider::new("a", Box::new(failing), "b", Box::new(slow));
+        let res = race.generate(req()).await.expect("b answers");
+        assert_eq!(res.provider.as_deref(), Some("b"));
+
+        let (a, _) = Delayed::new(1, Err(503));
+        let (b, _) = Delayed::new(5, Err(502));
+        let race = RacingProvider::new("a", Box::new(a), "b", Box::new(b));
+        match race.generate(req()).await {
+            Err(ProviderError::ServerError { status, .. }) => assert_eq!(status, Some(503)),
+            other => panic!("unexpected: {:?}", other.map(|r| r.text)),
+        }
+    }
+}
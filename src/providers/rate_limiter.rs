@@ -1,11 +1,25 @@
-//! Per-model rate limiter with exponential backoff
+//! Client-side rate limiting for provider calls.
+//!
+//! `ModelRateLimiter` backs off after 429 responses. `TokenBucketLimiter`
+//! enforces configured requests-per-minute and tokens-per-minute budgets up
+//! front; limiters are shared process-wide per provider and model, so
+//! parallel swarm agents draw from the same budget.
 
+use async_trait::async_trait;
 use rand::RngCore;
 use std::collections::HashMap;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex, OnceLock};
 use std::time::{Duration, Instant};
 use tokio::sync::RwLock;
 
+use crate::core::config::RateLimitConfig;
+use crate::core::error::ProviderError;
+use crate::providers::{GenerateRequest, GenerateResponse, ModelInfo, Provider, StreamEvent};
+
+/// Error code of the `RateLimited` error returned when a request queued
+/// longer than `max_wait_ms`; such errors are not retried
+pub const QUEUE_TIMEOUT_CODE: &str = "rate_limit_queue_timeout";
+
 /// Per-model rate limiter with exponential backoff
 #[derive(Debug, Default)]
 pub struct ModelRateLimiter {
@@ -95,6 +109,223 @@ impl ModelRateLimiter {
     }
 }
 
+/// Refilling budget of units (requests or tokens) per minute
+#[derive(Debug)]
+struct TokenBucket {
+    capacity: f64,
+    available: f64,
+    refill_per_sec: f64,
+    refilled_at: Instant,
+}
+
+impl TokenBucket {
+    fn per_minute(limit: u32) -> Self {
+        let capacity = limit.max(1) as f64;
+        Self {
+            capacity,
+            available: capacity,
+            refill_per_sec: capacity / 60.0,
+            refilled_at: Instant::now(),
+        }
+    }
+
+    fn refill(&mut self, now: Instant) {
+        let elapsed = now
+            .saturating_duration_since(self.refilled_at)
+            .as_secs_f64();
+        self.available = (self.available + elapsed * self.refill_per_sec).min(self.capacity);
+        self.refilled_at = now;
+    }
+
+    /// Time until `amount` units are available (zero when they already are)
+    fn wait_for(&self, amount: f64) -> Duration {
+        let missing = amount.min(self.capacity) - self.available;
+        if missing <= 0.0 {
+            Duration::ZERO
+        } else {
+            Duration::from_secs_f64(missing / self.refill_per_sec)
+        }
+    }
+
+    fn take(&mut self, amount: f64) {
+        self.available -= amount.min(self.capacity);
+    }
+}
+
+#[derive(Debug)]
+struct Buckets {
+    requests: Option<TokenBucket>,
+    tokens: Option<TokenBucket>,
+}
+
+/// Requests-per-minute and tokens-per-minute limiter with queueing
+#[derive(Debug)]
+pub struct TokenBucketLimiter {
+    buckets: Mutex<Buckets>,
+    max_wait: Duration,
+}
+
+impl TokenBucketLimiter {
+    pub fn new(config: &RateLimitConfig) -> Self {
+        Self {
+            buckets: Mutex::new(Buckets {
+                requests: config.requests_per_minute.map(TokenBucket::per_minute),
+                tokens: config.tokens_per_minute.map(TokenBucket::per_minute),
+            }),
+            max_wait: Duration::from_millis(config.max_wait_ms),
+        }
+    }
+
+    /// Limiter shared by every caller using the same `key`
+    ///
+    /// The first configuration registered for a key wins.
+    pub fn shared(key: &str, config: &RateLimitConfig) -> Arc<Self> {
+        static LIMITERS: OnceLock<Mutex<HashMap<String, Arc<TokenBucketLimiter>>>> =
+            OnceLock::new();
+        let mut limiters = LIMITERS
+            .get_or_init(|| Mutex::new(HashMap::new()))
+            .lock()
+            .unwrap();
+        limiters
+            .entry(key.to_string())
+            .or_insert_with(|| Arc::new(Self::new(config)))
+            .clone()
+    }
+
+    /// Take one request and `tokens` tokens, waiting for capacity if needed
+    ///
+    /// Fails with a `RateLimited` error (code `QUEUE_TIMEOUT_CODE`) when the
+    /// budget cannot be met within `max_wait_ms`.
+    pub async fn acquire(&self, tokens: usize) -> Result<(), ProviderError> {
+        let started = Instant::now();
+        loop {
+            let wait = {
+                let mut buckets = self.buckets.lock().unwrap();
+                let now = Instant::now();
+                let Buckets {
+                    requests,
+                    tokens: token_bucket,
+                } = &mut *buckets;
+                for bucket in [requests.as_mut(), token_bucket.as_mut()]
+                    .into_iter()
+                    .flatten()
+                {
+                    bucket.refill(now);
+                }
+
+                let wait = requests
+                    .as_ref()
+                    .map(|b| b.wait_for(1.0))
+                    .unwrap_or_default()
+                    .max(
+                        token_bucket
+                            .as_ref()
+                            .map(|b| b.wait_for(tokens as f64))
+                            .unwrap_or_default(),
+                    );
+                if wait.is_zero() {
+                    if let Some(b) = requests.as_mut() {
+                        b.take(1.0);
+                    }
+                    if let Some(b) = token_bucket.as_mut() {
+                        b.take(tokens as f64);
+                    }
+                    return Ok(());
+                }
+                wait
+            };
+
+            if started.elapsed() + wait > self.max_wait {
+                return Err(ProviderError::RateLimited {
+                    details: None,
+                    code: Some(QUEUE_TIMEOUT_CODE.to_string()),
+                    message: format!(
+                        "client-side rate limit not available within {} ms",
+                        self.max_wait.as_millis()
+                    ),
+                    status: None,
+                    retry_after_ms: Some(wait.as_millis() as u64),
+                });
+            }
+            log::debug!("Rate limit budget exhausted; queueing for {:?}", wait);
+            tokio::time::sleep(wait).await;
+        }
+    }
+}
+
+/// Provider decorator that draws each call from a `TokenBucketLimiter`
+///
+/// A call is charged one request plus its estimated prompt tokens and
+/// requested completion tokens.
+pub struct RateLimitedProvider {
+    inner: Box<dyn Provider>,
+    limiter: Arc<TokenBucketLimiter>,
+}
+
+impl RateLimitedProvider {
+    pub fn new(inner: Box<dyn Provider>, limiter: Arc<TokenBucketLimiter>) -> Self {
+        Self { inner, limiter }
+    }
+
+    fn token_cost(req: &GenerateRequest) -> usize {
+        crate::providers::capabilities::estimate_prompt_tokens(req)
+            + req.max_output_tokens.unwrap_or(0)
+    }
+}
+
+#[async_trait]
+impl Provider for RateLimitedProvider {
+    async fn generate(&self, req: GenerateRequest) -> Result<GenerateResponse, ProviderError> {
+        self.limiter.acquire(Self::token_cost(&req)).await?;
+        self.inner.generate(req).await
+    }
+
+    fn supports_stream_resume(&self) -> bool {
+        self.inner.supports_stream_resume()
+    }
+
+    async fn generate_batch(
+        &self,
+        reqs: Vec<GenerateRequest>,
+    ) -> Vec<Result<GenerateResponse, ProviderError>> {
+        // Native batches run under the provider's separate batch quota
+        if self.inner.supports_native_batch() {
+            return self.inner.generate_batch(reqs).await;
+        }
+        crate::providers::batch::generate_concurrently(
+            self,
+            reqs,
+            crate::providers::batch::DEFAULT_BATCH_CONCURRENCY,
+        )
+        .await
+    }
+
+    fn supports_native_batch(&self) -> bool {
+        self.inner.supports_native_batch()
+    }
+
+    async fn list_models(&self) -> Result<Vec<ModelInfo>, ProviderError> {
+        self.inner.list_models().await
+    }
+
+    async fn embed(&self, inputs: &[String]) -> Result<Vec<Vec<f32>>, ProviderError> {
+        self.inner.embed(inputs).await
+    }
+
+    async fn health(&self) -> Result<(), ProviderError> {
+        self.inner.health().await
+    }
+
+    async fn generate_streaming(
+        &self,
+        req: GenerateRequest,
+        on_event: &mut (dyn FnMut(StreamEvent) + Send),
+    ) -> Result<GenerateResponse, ProviderError> {
+        self.limiter.acquire(Self::token_cost(&req)).await?;
+        self.inner.generate_streaming(req, on_event).await
+    }
+}
+
 fn format_duration(d: Duration) -> String {
     let secs = d.as_secs();
     let ms = d.subsec_millis();
@@ -127,6 +358,54 @@ mod tests {
         // (though the backoff_until might still be in the future briefly)
     }
 
+    fn limits(rpm: Option<u32>, tpm: Option<u32>, max_wait_ms: u64) -> RateLimitConfig {
+        RateLimitConfig {
+            requests_per_minute: rpm,
+            tokens_per_minute: tpm,
+            max_wait_ms,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_request_budget_queues_then_times_out() {
+        // 600 rpm refills one request every 100ms
+        let limiter = TokenBucketLimiter::new(&limits(Some(600), None, 1_000));
+        for _ in 0..600 {
+            limiter.acquire(0).await.unwrap();
+        }
+
+        let started = Instant::now();
+        limiter.acquire(0).await.unwrap();
+        assert!(started.elapsed() >= Duration::from_millis(50));
+
+        let strict = TokenBucketLimiter::new(&limits(Some(1), None, 10));
+        strict.acquire(0).await.unwrap();
+        match strict.acquire(0).await {
+            Err(ProviderError::RateLimited { code, .. }) => {
+                assert_eq!(code.as_deref(), Some(QUEUE_TIMEOUT_CODE))
+            }
+            other => panic!("expected queue timeout, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_token_budget_is_charged() {
+        let limiter = TokenBucketLimiter::new(&limits(None, Some(1_000), 0));
+        limiter.acquire(900).await.unwrap();
+        assert!(limiter.acquire(200).await.is_err());
+        limiter.acquire(50).await.unwrap();
+    }
+
+    #[test]
+    fn test_shared_limiters_are_keyed() {
+        let config = limits(Some(10), None, 0);
+        let a = TokenBucketLimiter::shared("test/shared-a", &config);
+        let again = TokenBucketLimiter::shared("test/shared-a", &config);
+        let b = TokenBucketLimiter::shared("test/shared-b", &config);
+        assert!(Arc::ptr_eq(&a, &again));
+        assert!(!Arc::ptr_eq(&a, &b));
+    }
+
     #[tokio::test]
     async fn test_different_models_independent() {
         let limiter = ModelRateLimiter::new();
@@ -0,0 +1,112 @@
+//! Resuming streamed generations after a transient disconnect.
+//!
+//! When a stream drops mid-generation (socket closed, stall timeout, or the
+//! body ending without the provider's completion marker), a follow-up request
+//! is sent with the text received so far as a trailing assistant message.
+//! Providers that honour assistant prefill continue from that point, so the
+//! caller still gets the complete text.
+
+use log::warn;
+
+use crate::core::error::ProviderError;
+use crate::providers::{
+    ContentPart, GenerateRequest, GenerateResponse, Message, Provider, Role, StreamEvent,
+};
+
+/// Continuation attempts made when no explicit limit is configured
+pub const DEFAULT_STREAM_RESUME_RETRIES: usize = 2;
+
+/// Whether a streaming error means the connection dropped mid-generation
+fn is_disconnect(err: &ProviderError) -> bool {
+    matches!(
+        err,
+        ProviderError::Network { .. } | ProviderError::TimeoutStall { .. }
+    )
+}
+
+/// Build the follow-up request that continues from `partial`
+///
+/// The partial text becomes a trailing assistant message. Trailing whitespace
+/// is dropped since providers reject prefills ending in whitespace; the model
+/// re-emits it as part of the continuation.
+pub fn continuation_request(req: &GenerateRequest, partial: &str) -> GenerateRequest {
+    let mut next = req.clone();
+    next.messages.push(Message {
+        role: Role::Assistant,
+        content: vec![ContentPart::Text {
+            text: partial.trim_end().to_string(),
+        }],
+    });
+    next
+}
+
+/// Stream a generation, continuing from the partial text up to
+/// `max_resumes` times if the stream is cut off
+///
+/// Providers without `supports_stream_resume` are called once, unchanged.
+/// The returned response carries the full text (and reasoning) across all
+/// attempts.
+pub async fn generate_streaming_with_resume(
+    provider: &dyn Provider,
+    req: GenerateRequest,
+    on_event: &mut (dyn FnMut(StreamEvent) + Send),
+    max_resumes: usize,
+) -> Result<GenerateResponse, ProviderError> {
+    if !provider.supports_stream_resume() {
+        return provider.generate_streaming(req, on_event).await;
+    }
+
+    let mut received = String::new();
+    let mut reasoning = String::new();
+    let mut resumes = 0;
+    let mut current = req.clone();
+
+    loop {
+        let mut finished = false;
+        let mut attempt_text = String::new();
+        let result = {
+            let mut forward = |event: StreamEvent| {
+                match &event {
+                    StreamEvent::TextDelta(delta) => attempt_text.push_str(delta),
+                    StreamEvent::ReasoningDelta(delta) => reasoning.push_str(delta),
+                    StreamEvent::Finished => finished = true,
+                    _ => {}
+                }
+                on_event(event);
+            };
+            provider.generate_streaming(current, &mut forward).await
+        };
+        received.push_str(&attempt_text);
+
+        let interrupted = match &result {
+            Ok(_) => !finished,
+            Err(e) => is_disconnect(e),
+        };
+        if !interrupted || received.trim().is_empty() || resumes >= max_resumes {
+            if resumes == 0 {
+                return result;
+            }
+            if interrupted {
+                warn!(
+                    "Stream still interrupted after {} continuation attempt(s)",
+                    resumes
+                );
+            }
+            return result.map(|response| GenerateResponse {
+                text: received,
+                reasoning: (!reasoning.is_empty()).then_some(reasoning),
+                ..response
+            });
+        }
+
+        resumes += 1;
+        warn!(
+            "Stream interrupted after {} chars; continuing (attempt {} of {})",
+            received.len(),
+            resumes,
+            max_resumes
+        );
+        received.truncate(received.trim_end().len());
+        current = continuation_request(&req, &received);
+    }
+}
@@ -0,0 +1,316 @@
+//! Retrying decorator for any `Provider`.
+//!
+//! Transient failures (rate limits, 5xx, network errors, streaming timeouts)
+//! are retried with exponential backoff plus random jitter. A `Retry-After`
+//! hint from the server takes precedence over the computed backoff. Each
+//! retry is written to the LLM log, and the number of retries a call needed
+//! is reported in `Usage::retries`.
+
+use async_trait::async_trait;
+use log::warn;
+use rand::Rng;
+use reqwest::header::HeaderMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::code_generation::llm_logging::LlmLogger;
+use crate::core::config::RetryConfig;
+use crate::core::error::ProviderError;
+use crate::providers::rate_limiter::QUEUE_TIMEOUT_CODE;
+use crate::providers::{
+    GenerateRequest, GenerateResponse, ModelInfo, Provider, StreamEvent, Usage,
+};
+
+/// Whether an error is worth retrying unchanged
+pub fn is_retryable(err: &ProviderError) -> bool {
+    // Already waited out the client-side queue
+    if let ProviderError::RateLimited {
+        code: Some(code), ..
+    } = err
+    {
+        if code == QUEUE_TIMEOUT_CODE {
+            return false;
+        }
+    }
+    matches!(
+        err,
+        ProviderError::RateLimited { .. }
+            | ProviderError::ServerError { .. }
+            | ProviderError::ProviderOutage { .. }
+            | ProviderError::TimeoutFirstToken { .. }
+            | ProviderError::TimeoutStall { .. }
+            | ProviderError::Network { .. }
+    )
+}
+
+/// Server-requested delay from `retry-after-ms` or `Retry-After` (seconds)
+pub fn retry_after_ms(headers: &HeaderMap) -> Option<u64> {
+    let value = |name: &str| headers.get(name).and_then(|v| v.to_str().ok());
+
+    if let Some(ms) = value("retry-after-ms").and_then(|v| v.trim().parse::<f64>().ok()) {
+        return Some(ms.max(0.0) as u64);
+    }
+    value("retry-after")
+        .and_then(|v| v.trim().parse::<f64>().ok())
+        .map(|secs| (secs.max(0.0) * 1000.0) as u64)
+}
+
+/// Delay before retry number `retry` (1-based) after `err`
+///
+/// A server-provided `Retry-After` is honoured as-is; otherwise the delay
+/// grows by `multiplier` per retry, capped at `max_backoff_ms`, and is then
+/// scaled by a random factor in `[1 - jitter, 1 + jitter]`.
+pub fn backoff_delay(config: &RetryConfig, retry: u32, err: &ProviderError) -> Duration {
+    if let ProviderError::RateLimited {
+        retry_after_ms: Some(ms),
+        ..
+    } = err
+    {
+        return Duration::from_millis(*ms);
+    }
+
+    let exp = config.multiplier.powi(retry.saturating_sub(1) as i32);
+    let base = (config.initial_backoff_ms as f64 * exp).min(config.max_backoff_ms as f64);
+    let jitter = config.jitter.clamp(0.0, 1.0);
+    let factor = if jitter > 0.0 {
+        rand::rng().random_range(1.0 - jitter..=1.0 + jitter)
+    } else {
+        1.0
+    };
+    Duration::from_millis((base * factor).max(0.0) as u64)
+}
+
+/// Provider decorator that retries transient failures
+pub struct RetryingProvider {
+    inner: Box<dyn Provider>,
+    config: RetryConfig,
+    logger: Option<(Arc<LlmLogger>, String, String)>,
+}
+
+impl RetryingProvider {
+    /// Wrap `inner` with the given retry settings
+    pub fn new(inner: Box<dyn Provider>, config: RetryConfig) -> Self {
+        Self {
+            inner,
+            config,
+            logger: None,
+        }
+    }
+
+    /// Record each retry in the LLM log under `provider`/`model`
+    pub fn with_logger(
+        mut self,
+        logger: Arc<LlmLogger>,
+        provider: impl Into<String>,
+        model: impl Into<String>,
+    ) -> Self {
+        self.logger = Some((logger, provider.into(), model.into()));
+        self
+    }
+
+    /// Log and sleep before retry number `retry`; `None` when out of attempts
+    async fn prepare_retry(&self, retry: u32, err: &ProviderError) -> Option<()> {
+        if !is_retryable(err) || retry >= self.config.max_attempts.max(1) {
+            return None;
+        }
+
+        let delay = backoff_delay(&self.config, retry, err);
+        warn!(
+            "Provider call failed ({}); retry {} of {} in {:?}",
+            err,
+            retry,
+            self.config.max_attempts.max(1) - 1,
+            delay
+        );
+        if let Some((logger, provider, model)) = &self.logger {
+            if let Err(e) = logger.log_retry(
+                provider,
+                model,
+                retry,
+                self.config.max_attempts.max(1) - 1,
+                &err.to_string(),
+                delay.as_millis() as u64,
+            ) {
+                warn!("Failed to write retry to LLM log: {}", e);
+            }
+        }
+        tokio::time::sleep(delay).await;
+        Some(())
+    }
+
+    fn record_retries(mut response: GenerateResponse, retries: u32) -> GenerateResponse {
+        if retries > 0 {
+            response.usage.get_or_insert_with(Usage::default).retries = Some(retries);
+        }
+        response
+    }
+}
+
+#[async_trait]
+impl Provider for RetryingProvider {
+    async fn generate(&self, req: GenerateRequest) -> Result<GenerateResponse, ProviderError> {
+        let mut retries = 0;
+        loop {
+            match self.inner.generate(req.clone()).await {
+                Ok(response) => return Ok(Self::record_retries(response, retries)),
+                Err(e) => {
+                    if self.prepare_retry(retries + 1, &e).await.is_none() {
+                        return Err(e);
+                    }
+                    retries += 1;
+                }
+            }
+        }
+    }
+
+    fn supports_stream_resume(&self) -> bool {
+        self.inner.supports_stream_resume()
+    }
+
+    async fn generate_batch(
+        &self,
+        reqs: Vec<GenerateRequest>,
+    ) -> Vec<Result<GenerateResponse, ProviderError>> {
+        // Native batches report failures per request; they are not retried here
+        if self.inner.supports_native_batch() {
+            return self.inner.generate_batch(reqs).await;
+        }
+        crate::providers::batch::generate_concurrently(
+            self,
+            reqs,
+            crate::providers::batch::DEFAULT_BATCH_CONCURRENCY,
+        )
+        .await
+    }
+
+    fn supports_native_batch(&self) -> bool {
+        self.inner.supports_native_batch()
+    }
+
+    async fn list_models(&self) -> Result<Vec<ModelInfo>, ProviderError> {
+        self.inner.list_models().await
+    }
+
+    async fn embed(&self, inputs: &[String]) -> Result<Vec<Vec<f32>>, ProviderError> {
+        let mut retries = 0;
+        loop {
+            match self.inner.embed(inputs).await {
+                Ok(vectors) => return Ok(vectors),
+                Err(e) => {
+                    if self.prepare_retry(retries + 1, &e).await.is_none() {
+                        return Err(e);
+                    }
+                    retries += 1;
+                }
+            }
+        }
+    }
+
+    async fn health(&self) -> Result<(), ProviderError> {
+        self.inner.health().await
+    }
+
+    async fn generate_streaming(
+        &self,
+        req: GenerateRequest,
+        on_event: &mut (dyn FnMut(StreamEvent) + Send),
+    ) -> Result<GenerateResponse, ProviderError> {
+        let mut retries = 0;
+        loop {
+            let mut emitted = false;
+            let result = {
+                let mut forward = |event: StreamEvent| {
+                    emitted = true;
+                    on_event(event);
+                };
+                self.inner
+                    .generate_streaming(req.clone(), &mut forward)
+                    .await
+            };
+
+            match result {
+                Ok(response) => return Ok(Self::record_retries(response, retries)),
+                // Once output has reached the caller a retry would duplicate
+                // it; mid-stream failures are left to stream resumption
+                Err(e) if emitted => return Err(e),
+                Err(e) => {
+                    if self.prepare_retry(retries + 1, &e).await.is_none() {
+                        return Err(e);
+                    }
+                    retries += 1;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::header::HeaderValue;
+
+    fn rate_limited(retry_after_ms: Option<u64>) -> ProviderError {
+        ProviderError::RateLimited {
+            details: None,
+            code: None,
+            message: "slow down".to_string(),
+            status: Some(429),
+            retry_after_ms,
+        }
+    }
+
+    #[test]
+    fn test_retry_after_headers() {
+        let mut headers = HeaderMap::new();
+        assert_eq!(retry_after_ms(&headers), None);
+        headers.insert("retry-after", HeaderValue::from_static("2"));
+        assert_eq!(retry_after_ms(&headers), Some(2000));
+        headers.insert("retry-after-ms", HeaderValue::from_static("150"));
+        assert_eq!(retry_after_ms(&headers), Some(150));
+    }
+
+    #[test]
+    fn test_backoff_grows_and_is_capped() {
+        let config = RetryConfig {
+            initial_backoff_ms: 100,
+            max_backoff_ms: 350,
+            multiplier: 2.0,
+            jitter: 0.0,
+            ..RetryConfig::default()
+        };
+        let err = rate_limited(None);
+        let delays: Vec<u64> = (1..=4)
+            .map(|n| backoff_delay(&config, n, &err).as_millis() as u64)
+            .collect();
+        assert_eq!(delays, vec![100, 200, 350, 350]);
+
+        // Server hint wins over computed backoff
+        assert_eq!(
+            backoff_delay(&config, 1, &rate_limited(Some(1234))).as_millis(),
+            1234
+        );
+    }
+
+    #[test]
+    fn test_jitter_stays_in_range() {
+        let config = RetryConfig {
+            initial_backoff_ms: 1000,
+            jitter: 0.25,
+            ..RetryConfig::default()
+        };
+        for _ in 0..50 {
+            let ms = backoff_delay(&config, 1, &rate_limited(None)).as_millis();
+            assert!((750..=1250).contains(&ms), "{}", ms);
+        }
+    }
+
+    #[test]
+    fn test_client_errors_are_not_retried() {
+        assert!(is_retryable(&rate_limited(None)));
+        assert!(is_retryable(&ProviderError::TimeoutStall { timeout_ms: 1 }));
+        assert!(!is_retryable(&ProviderError::invalid_params(
+            "bad",
+            Some(400)
+        )));
+    }
+}
@@ -0,0 +1,209 @@
+//! Client-side checking of structured output.
+//!
+//! Providers enforce `ResponseFormat` to varying degrees (OpenAI strict mode,
+//! Anthropic tool forcing, Ollama `format`, or not at all), so responses are
+//! re-checked here. The validator covers the JSON Schema subset used for
+//! structured output: `type`, `properties`, `required`,
+//! `additionalProperties: false`, `items`, `enum`, `minItems` and
+//! `minLength`.
+
+use serde_json::Value as JsonValue;
+
+use crate::providers::ResponseFormat;
+
+/// Parse the JSON value in `text`, which may be wrapped in a markdown code
+/// block or surrounded by prose
+pub fn extract_json(text: &str) -> Option<JsonValue> {
+    let trimmed = text.trim();
+    if let Ok(v) = serde_json::from_str(trimmed) {
+        return Some(v);
+    }
+
+    if let Some(start) = trimmed.find("```") {
+        let after = &trimmed[start + 3..];
+        let body = after.strip_prefix("json").unwrap_or(after);
+        if let Some(end) = body.find("```") {
+            if let Ok(v) = serde_json::from_str(body[..end].trim()) {
+                return Some(v);
+            }
+        }
+    }
+
+    let start = trimmed.find(['{', '['])?;
+    let end = trimmed.rfind(['}', ']'])?;
+    if end <= start {
+        return None;
+    }
+    serde_json::from_str(&trimmed[start..=end]).ok()
+}
+
+/// Violations of `schema` by `instance`, each prefixed with its JSON path
+pub fn validate(schema: &JsonValue, instance: &JsonValue) -> Vec<String> {
+    let mut errors = Vec::new();
+    validate_at(schema, instance, "$", &mut errors);
+    errors
+}
+
+/// Problems with `text` as a response in `format`; empty when it conforms
+pub fn check_response(format: &ResponseFormat, text: &str) -> Vec<String> {
+    let Some(value) = extract_json(text) else {
+        return vec!["response is not valid JSON".to_string()];
+    };
+    match format {
+        ResponseFormat::JsonObject if !value.is_object() => {
+            vec!["$: expected a JSON object".to_string()]
+        }
+        ResponseFormat::JsonObject => Vec::new(),
+        ResponseFormat::JsonSchema { json_schema } => validate(&json_schema.schema, &value),
+    }
+}
+
+fn type_matches(expected: &str, instance: &JsonValue) -> bool {
+    match expected {
+        "object" => instance.is_object(),
+        "array" => instance.is_array(),
+        "string" => instance.is_string(),
+        "boolean" => instance.is_boolean(),
+        "null" => instance.is_null(),
+        "number" => instance.is_number(),
+        "integer" => instance.is_i64() || instance.is_u64(),
+        // Unknown keywords are not ours to reject
+        _ => true,
+    }
+}
+
+fn validate_at(schema: &JsonValue, instance: &JsonValue, path: &str, errors: &mut Vec<String>) {
+    let Some(schema) = schema.as_object() else {
+        return;
+    };
+
+    if let Some(expected) = schema.get("type") {
+        let allowed: Vec<&str> = match expected {
+            JsonValue::String(t) => vec![t.as_str()],
+            JsonValue::Array(ts) => ts.iter().filter_map(|t| t.as_str()).collect(),
+            _ => Vec::new(),
+        };
+        if !allowed.is_empty() && !allowed.iter().any(|t| type_matches(t, instance)) {
+            errors.push(format!("{}: expected {}", path, allowed.join(" or ")));
+            return;
+        }
+    }
+
+    if let Some(options) = schema.get("enum").and_then(|e| e.as_array()) {
+        if !options.contains(instance) {
+            errors.push(format!(
+                "{}: {} is not one of {:?}",
+                path, instance, options
+            ));
+        }
+    }
+
+    if let (Some(min), Some(s)) = (
+        schema.get("minLength").and_then(|m| m.as_u64()),
+        instance.as_str(),
+    ) {
+        if (s.chars().count() as u64) < min {
+            errors.push(format!("{}: shorter than {} characters", path, min));
+        }
+    }
+
+    if let Some(obj) = instance.as_object() {
+        if let Some(required) = schema.get("required").and_then(|r| r.as_array()) {
+            for key in required.iter().filter_map(|k| k.as_str()) {
+                if !obj.contains_key(key) {
+                    errors.push(format!("{}: missing required property '{}'", path, key));
+                }
+            }
+        }
+        let properties = schema.get("properties").and_then(|p| p.as_object());
+        let closed = schema.get("additionalProperties") == Some(&JsonValue::Bool(false));
+        for (key, value) in obj {
+            match properties.and_then(|p| p.get(key)) {
+                Some(sub) => validate_at(sub, value, &format!("{}.{}", path, key), errors),
+                None if closed => {
+                    errors.push(format!("{}: unexpected property '{}'", path, key));
+                }
+                None => {}
+            }
+        }
+    }
+
+    if let Some(items) = instance.as_array() {
+        if let Some(min) = schema.get("minItems").and_then(|m| m.as_u64()) {
+            if (items.len() as u64) < min {
+                errors.push(format!("{}: fewer than {} items", path, min));
+            }
+        }
+        if let Some(item_schema) = schema.get("items") {
+            for (i, item) in items.iter().enumerate() {
+                validate_at(item_schema, item, &format!("{}[{}]", path, i), errors);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn schema() -> JsonValue {
+        json!({
+            "type": "object",
+            "properties": {
+                "path": {"type": "string", "minLength": 1},
+                "kind": {"type": "string", "enum": ["create", "modify"]},
+                "names": {"type": "array", "items": {"type": "string"}, "minItems": 1}
+            },
+            "required": ["path", "kind", "names"],
+            "additionalProperties": false
+        })
+    }
+
+    #[test]
+    fn test_extract_json_from_prose_and_fences() {
+        assert_eq!(extract_json("{\"a\":1}"), Some(json!({"a": 1})));
+        assert_eq!(
+            extract_json("Here you go:\n```json\n{\"a\": 2}\n```\nDone."),
+            Some(json!({"a": 2}))
+        );
+        assert_eq!(
+            extract_json("Result: {\"a\": [3]} as asked"),
+            Some(json!({"a": [3]}))
+        );
+        assert_eq!(extract_json("no json here"), None);
+    }
+
+    #[test]
+    fn test_validate_reports_each_violation_with_path() {
+        let ok = json!({"path": "src/a.rs", "kind": "create", "names": ["t"]});
+        assert!(validate(&schema(), &ok).is_empty());
+
+        let bad = json!({"path": "", "kind": "rename", "names": [1], "extra": true});
+        let errors = validate(&schema(), &bad);
+        assert_eq!(errors.len(), 4, "{:?}", errors);
+        assert!(errors.iter().any(|e| e.starts_with("$.path: shorter")));
+        assert!(errors.iter().any(|e| e.starts_with("$.kind:")));
+        assert!(errors.iter().any(|e| e == "$.names[0]: expected string"));
+        assert!(errors
+            .iter()
+            .any(|e| e.contains("unexpected property 'extra'")));
+
+        let missing = validate(&schema(), &json!({"path": "x", "kind": "modify"}));
+        assert_eq!(missing, vec!["$: missing required property 'names'"]);
+    }
+
+    #[test]
+    fn test_check_response_by_format() {
+        assert!(check_response(&ResponseFormat::JsonObject, "{}").is_empty());
+        assert!(!check_response(&ResponseFormat::JsonObject, "[1]").is_empty());
+        assert!(!check_response(&ResponseFormat::JsonObject, "sorry").is_empty());
+
+        let format = ResponseFormat::json_schema("file".to_string(), schema());
+        assert!(check_response(
+            &format,
+            "```json\n{\"path\":\"a\",\"kind\":\"modify\",\"names\":[\"x\"]}\n```"
+        )
+        .is_empty());
+    }
+}
@@ -0,0 +1,245 @@
+//! Server-sent events decoding for streaming providers.
+//!
+//! Implements the event-stream format from the HTML spec: `data:` fields
+//! spanning several lines, `event:` and `id:` fields, `:` comments, and
+//! LF, CRLF or lone CR line endings. Input is raw bytes, so a multibyte
+//! character split across network chunks is decoded once both halves
+//! arrive instead of turning into replacement characters.
+
+/// One dispatched server-sent event
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SseEvent {
+    /// `event:` name, if the server sent one
+    pub event: Option<String>,
+
+    /// `data:` lines joined with `\n`
+    pub data: String,
+
+    /// Last `id:` seen on the stream
+    pub id: Option<String>,
+}
+
+impl SseEvent {
+    /// Whether this is the OpenAI-style `[DONE]` end marker
+    pub fn is_done(&self) -> bool {
+        self.data == "[DONE]"
+    }
+}
+
+/// Incremental SSE parser fed with raw response chunks
+#[derive(Default)]
+pub struct SseDecoder {
+    /// Trailing bytes of an incomplete UTF-8 sequence
+    pending_bytes: Vec<u8>,
+
+    /// Decoded text not yet terminated by a line ending
+    line_buffer: String,
+
+    /// A chunk ended in `\r`; a leading `\n` in the next one belongs to it
+    after_cr: bool,
+
+    /// Whether the stream start (and a possible BOM) has been seen
+    started: bool,
+
+    /// Fields of the event being assembled
+    event: Option<String>,
+    data: String,
+    last_id: Option<String>,
+}
+
+impl SseDecoder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Push a raw response chunk; returns the events it completes
+    pub fn push_bytes(&mut self, chunk: &[u8]) -> Vec<SseEvent> {
+        self.pending_bytes.extend_from_slice(chunk);
+        let mut text = String::new();
+        let mut rest = self.pending_bytes.as_slice();
+        loop {
+            match std::str::from_utf8(rest) {
+                Ok(valid) => {
+                    text.push_str(valid);
+                    rest = &[];
+                    break;
+                }
+                Err(e) => {
+                    let (valid, after) = rest.split_at(e.valid_up_to());
+                    // `valid_up_to` guarantees this prefix is UTF-8
+                    text.push_str(std::str::from_utf8(valid).unwrap_or_default());
+                    match e.error_len() {
+                        // Invalid bytes: replace them and carry on
+                        Some(len) => {
+                            text.push(char::REPLACEMENT_CHARACTER);
+                            rest = &after[len..];
+                        }
+                        // Truncated sequence: wait for the next chunk
+                        None => {
+                            rest = after;
+                            break;
+                        }
+                    }
+                }
+            }
+        }
+        self.pending_bytes = rest.to_vec();
+        self.push_chunk(&text)
+    }
+
+    /// Push already-decoded text; returns the events it completes
+    pub fn push_chunk(&mut self, chunk: &str) -> Vec<SseEvent> {
+        let mut chunk = chunk;
+        if !self.started && !chunk.is_empty() {
+            self.started = true;
+            chunk = chunk.strip_prefix('\u{feff}').unwrap_or(chunk);
+        }
+        if self.after_cr {
+            self.after_cr = false;
+            chunk = chunk.strip_prefix('\n').unwrap_or(chunk);
+        }
+        self.line_buffer.push_str(chunk);
+
+        let mut events = Vec::new();
+        let mut start = 0;
+        let buffer = std::mem::take(&mut self.line_buffer);
+        let bytes = buffer.as_bytes();
+        let mut i = 0;
+        while i < bytes.len() {
+            match bytes[i] {
+                b'\n' => {
+                    self.process_line(&buffer[start..i], &mut events);
+                    i += 1;
+                    start = i;
+                }
+                b'\r' => {
+                    self.process_line(&buffer[start..i], &mut events);
+                    i += 1;
+                    if i == bytes.len() {
+                        self.after_cr = true;
+                    } else if bytes[i] == b'\n' {
+                        i += 1;
+                    }
+                    start = i;
+                }
+                _ => i += 1,
+            }
+        }
+        self.line_buffer = buffer[start..].to_string();
+        events
+    }
+
+    /// Flush at end of stream: an unterminated last line and an event that
+    /// was not followed by a blank line are still delivered
+    pub fn finish(&mut self) -> Option<SseEvent> {
+        if !self.pending_bytes.is_empty() {
+            let bytes = std::mem::take(&mut self.pending_bytes);
+            self.line_buffer.push_str(&String::from_utf8_lossy(&bytes));
+        }
+        let line = std::mem::take(&mut self.line_buffer);
+        if !line.is_empty() {
+            // A non-empty line never completes an event by itself
+            self.process_line(&line, &mut Vec::new());
+        }
+        self.dispatch()
+    }
+
+    fn process_line(&mut self, line: &str, events: &mut Vec<SseEvent>) {
+        if line.is_empty() {
+            events.extend(self.dispatch());
+            return;
+        }
+        if line.starts_with(':') {
+            return;
+        }
+        let (field, value) = match line.split_once(':') {
+            Some((field, value)) => (field, value.strip_prefix(' ').unwrap_or(value)),
+            None => (line, ""),
+        };
+        match field {
+            "data" => {
+                self.data.push_str(value);
+                self.data.push('\n');
+            }
+            "event" => self.event = Some(value.to_string()),
+            "id" if !value.contains('\0') => self.last_id = Some(value.to_string()),
+            // `retry` and unknown fields are ignored
+            _ => {}
+        }
+    }
+
+    /// Complete the event being assembled; events without data are dropped
+    fn dispatch(&mut self) -> Option<SseEvent> {
+        let event = self.event.take();
+        let mut data = std::mem::take(&mut self.data);
+        if data.is_empty() {
+            return None;
+        }
+        data.pop();
+        Some(SseEvent {
+            event,
+            data,
+            id: self.last_id.clone(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn data(events: &[SseEvent]) -> Vec<&str> {
+        events.iter().map(|e| e.data.as_str()).collect()
+    }
+
+    #[test]
+    fn test_fields_comments_and_multi_line_data() {
+        let mut decoder = SseDecoder::new();
+        let events = decoder.push_chunk(
+            ": keep-alive\nevent: delta\nid: 7\ndata: {\"a\":\ndata:1}\n\ndata:plain\n\n",
+        );
+        assert_eq!(
+            events[0],
+            SseEvent {
+                event: Some("delta".to_string()),
+                data: "{\"a\":\n1}".to_string(),
+                id: Some("7".to_string()),
+            }
+        );
+        assert_eq!(events[1].event, None);
+        assert_eq!(events[1].data, "plain");
+        assert_eq!(events[1].id.as_deref(), Some("7"));
+
+        // Events without data are not dispatched
+        assert!(decoder.push_chunk("event: ping\n\n").is_empty());
+    }
+
+    #[test]
+    fn test_line_endings_split_across_chunks() {
+        let mut decoder = SseDecoder::new();
+        let mut events = decoder.push_chunk("data: one\r");
+        events.extend(decoder.push_chunk("\n\r\ndata: two\r\r"));
+        events.extend(decoder.push_chunk("data: th"));
+        events.extend(decoder.push_chunk("ree\n"));
+        assert_eq!(data(&events), vec!["one", "two"]);
+
+        // The last event is still delivered without a trailing blank line
+        assert_eq!(decoder.finish().map(|e| e.data), Some("three".to_string()));
+        assert_eq!(decoder.finish(), None);
+    }
+
+    #[test]
+    fn test_multibyte_characters_split_across_chunks() {
+        let bytes = "data: h\u{e9}llo \u{1f980}\n\n".as_bytes();
+        let split = bytes.iter().position(|&b| b == 0xf0).unwrap() + 2;
+        let mut decoder = SseDecoder::new();
+        let mut events = decoder.push_bytes(&bytes[..split]);
+        assert!(events.is_empty());
+        events.extend(decoder.push_bytes(&bytes[split..]));
+        assert_eq!(data(&events), vec!["h\u{e9}llo \u{1f980}"]);
+
+        let mut bom = SseDecoder::new();
+        let events = bom.push_bytes(b"\xef\xbb\xbfdata: [DONE]\n\n");
+        assert!(events[0].is_done());
+    }
+}
@@ -0,0 +1,315 @@
+//! Token counting for prompts.
+//!
+//! `count_tokens` runs a tiktoken-style byte-pair encoder when the model's
+//! encoding ranks are available locally: `cl100k_base.tiktoken` or
+//! `o200k_base.tiktoken` in `$BORG_TIKTOKEN_DIR` (default
+//! `~/.cache/borg/tiktoken`), in the format OpenAI publishes. Otherwise, and
+//! for model families with unpublished tokenizers, it falls back to a
+//! heuristic over the same pre-tokenization, which tracks real counts far
+//! better than a flat characters-per-token ratio on code.
+
+use log::debug;
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex, OnceLock};
+
+/// Environment variable naming the directory holding `.tiktoken` rank files
+pub const TIKTOKEN_DIR_ENV: &str = "BORG_TIKTOKEN_DIR";
+
+/// Number of tokens `text` encodes to for `model`
+pub fn count_tokens(model: &str, text: &str) -> usize {
+    if text.is_empty() {
+        return 0;
+    }
+    match encoding_for_model(model).and_then(load_encoding) {
+        Some(bpe) => bpe.count(text),
+        None => pretokenize(text)
+            .into_iter()
+            .map(heuristic_piece_tokens)
+            .sum(),
+    }
+}
+
+/// Name of the BPE encoding `model` uses, for OpenAI model families
+pub fn encoding_for_model(model: &str) -> Option<&'static str> {
+    // Provider-prefixed ids such as "openai/gpt-4o" name the model last
+    let name = model.rsplit('/').next().unwrap_or(model).to_lowercase();
+    const O200K: &[&str] = &["gpt-4o", "gpt-4.1", "gpt-4.5", "gpt-5", "o1", "o3", "o4"];
+    const CL100K: &[&str] = &["gpt-4", "gpt-3.5", "text-embedding-3", "text-embedding-ada"];
+    if O200K.iter().any(|p| name.starts_with(p)) {
+        Some("o200k_base")
+    } else if CL100K.iter().any(|p| name.starts_with(p)) {
+        Some("cl100k_base")
+    } else {
+        None
+    }
+}
+
+/// Byte-pair encoder over tiktoken merge ranks
+pub struct Bpe {
+    ranks: HashMap<Vec<u8>, u32>,
+}
+
+impl Bpe {
+    /// Parse a `.tiktoken` file: one `<base64 token> <rank>` pair per line
+    pub fn from_tiktoken(data: &str) -> Result<Self, String> {
+        use base64::Engine;
+
+        let mut ranks = HashMap::new();
+        for (n, line) in data.lines().enumerate() {
+            if line.trim().is_empty() {
+                continue;
+            }
+            let (token, rank) = line
+                .split_once(' ')
+                .ok_or_else(|| format!("line {}: expected '<token> <rank>'", n + 1))?;
+            let token = base64::engine::general_purpose::STANDARD
+                .decode(token)
+                .map_err(|e| format!("line {}: {}", n + 1, e))?;
+            let rank = rank
+                .trim()
+                .parse::<u32>()
+                .map_err(|e| format!("line {}: {}", n + 1, e))?;
+            ranks.insert(token, rank);
+        }
+        Ok(Self { ranks })
+    }
+
+    /// Token count of `text`
+    pub fn count(&self, text: &str) -> usize {
+        pretokenize(text)
+            .into_iter()
+            .map(|piece| self.piece_tokens(piece.as_bytes()))
+            .sum()
+    }
+
+    /// Merge the lowest-ranked adjacent pair until none is in the vocabulary
+    fn piece_tokens(&self, piece: &[u8]) -> usize {
+        if piece.len() <= 1 || self.ranks.contains_key(piece) {
+            return 1;
+        }
+        // Start offsets of the current parts, plus the end of the piece
+        let mut bounds: Vec<usize> = (0..=piece.len()).collect();
+        loop {
+            let best = (0..bounds.len().saturating_sub(2))
+                .filter_map(|i| {
+                    self.ranks
+                        .get(&piece[bounds[i]..bounds[i + 2]])
+                        .map(|rank| (*rank, i))
+                })
+                .min();
+            match best {
+                Some((_, i)) => {
+                    bounds.remove(i + 1);
+                }
+                None => return bounds.len() - 1,
+            }
+        }
+    }
+}
+
+/// Encodings read so far, by name (`None` when the rank file is missing)
+type LoadedEncodings = HashMap<&'static str, Option<Arc<Bpe>>>;
+
+fn loaded_encodings() -> &'static Mutex<LoadedEncodings> {
+    static LOADED: OnceLock<Mutex<LoadedEncodings>> = OnceLock::new();
+    LOADED.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+fn tiktoken_dir() -> Option<PathBuf> {
+    std::env::var_os(TIKTOKEN_DIR_ENV)
+        .map(PathBuf::from)
+        .or_else(|| {
+            std::env::var_os("HOME").map(|h| {
+                PathBuf::from(h)
+                    .join(".cache")
+                    .join("borg")
+                    .join("tiktoken")
+            })
+        })
+}
+
+/// Ranks for `name`, read once per process; `None` when not installed
+fn load_encoding(name: &'static str) -> Option<Arc<Bpe>> {
+    let mut loaded = loaded_encodings().lock().unwrap_or_else(|e| e.into_inner());
+    loaded
+        .entry(name)
+        .or_insert_with(|| {
+            let path = tiktoken_dir()?.join(format!("{}.tiktoken", name));
+            let data = std::fs::read_to_string(&path).ok()?;
+            match Bpe::from_tiktoken(&data) {
+                Ok(bpe) => Some(Arc::new(bpe)),
+                Err(e) => {
+                    debug!("Ignoring malformed {}: {}", path.display(), e);
+                    None
+                }
+            }
+        })
+        .clone()
+}
+
+fn is_letter(c: char) -> bool {
+    c.is_alphabetic()
+}
+
+fn is_number(c: char) -> bool {
+    c.is_numeric()
+}
+
+fn is_newline(c: char) -> bool {
+    c == '\r' || c == '\n'
+}
+
+fn is_other(c: char) -> bool {
+    !c.is_whitespace() && !is_letter(c) && !is_number(c)
+}
+
+/// Split `text` the way the cl100k pattern does: contractions, words with
+/// one leading non-letter, digit runs of at most three, punctuation runs,
+/// and whitespace (a single space before a word stays with the word)
+pub fn pretokenize(text: &str) -> Vec<&str> {
+    let chars: Vec<(usize, char)> = text.char_indices().collect();
+    let at = |i: usize| chars.get(i).map(|(_, c)| *c);
+    let offset = |i: usize| chars.get(i).map(|(o, _)| *o).unwrap_or(text.len());
+    let run = |mut i: usize, pred: &dyn Fn(char) -> bool| {
+        while at(i).is_some_and(pred) {
+            i += 1;
+        }
+        i
+    };
+
+    let mut pieces = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i].1;
+        let end = if let Some(len) = contraction_len(&chars[i..]) {
+            i + len
+        } else if is_letter(c) {
+            run(i, &is_letter)
+        } else if !is_newline(c)
+            && !is_letter(c)
+            && !is_number(c)
+            && at(i + 1).is_some_and(is_letter)
+        {
+            run(i + 1, &is_letter)
+        } else if is_number(c) {
+            run(i, &is_number).min(i + 3)
+        } else if is_other(c) || (c == ' ' && at(i + 1).is_some_and(is_other)) {
+            let start = if c == ' ' { i + 1 } else { i };
+            run(run(start, &is_other), &is_newline)
+        } else {
+            // Whitespace run
+            let ws_end = run(i, &|c: char| c.is_whitespace());
+            let last_newline = (i..ws_end).rev().find(|&j| at(j).is_some_and(is_newline));
+            match last_newline {
+                Some(j) => j + 1,
+                // Leave one space to lead the following word
+                None if ws_end < chars.len() && ws_end - i > 1 => ws_end - 1,
+                None => ws_end,
+            }
+        };
+        pieces.push(&text[offset(i)..offset(end)]);
+        i = end;
+    }
+    pieces
+}
+
+/// Length in chars of an English contraction suffix at the start of `chars`
+fn contraction_len(chars: &[(usize, char)]) -> Option<usize> {
+    if chars.first()?.1 != '\'' {
+        return None;
+    }
+    let rest: String = chars[1..]
+        .iter()
+        .take(2)
+        .map(|(_, c)| c.to_ascii_lowercase())
+        .collect();
+    if rest.starts_with("re") || rest.starts_with("ve") || rest.starts_with("ll") {
+        Some(3)
+    } else if rest.starts_with(['s', 't', 'm', 'd']) {
+        Some(2)
+    } else {
+        None
+    }
+}
+
+/// Estimated tokens for one pre-tokenized piece, without a vocabulary
+fn heuristic_piece_tokens(piece: &str) -> usize {
+    let trimmed = piece.trim_start_matches(' ');
+    if trimmed.is_empty() || trimmed.chars().all(char::is_whitespace) {
+        // Indentation and blank lines merge into few tokens
+        return piece.len().div_ceil(8).max(1);
+    }
+    if !trimmed.is_ascii() {
+        // Non-Latin scripts run close to one token per character
+        return trimmed.chars().count().max(1);
+    }
+    let first = trimmed.chars().next().unwrap_or(' ');
+    if first.is_ascii_digit() {
+        1
+    } else if first.is_ascii_alphabetic() || trimmed.len() > 1 && is_letter_tail(trimmed) {
+        // Common words are single tokens; long identifiers split every ~4 chars
+        if trimmed.len() <= 7 {
+            1
+        } else {
+            trimmed.len().div_ceil(4)
+        }
+    } else {
+        trimmed.len().div_ceil(2)
+    }
+}
+
+/// Whether `piece` is a single leading non-letter followed by letters
+fn is_letter_tail(piece: &str) -> bool {
+    piece.chars().skip(1).all(|c| c.is_ascii_alphabetic())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pretokenize_follows_cl100k_splits() {
+        assert_eq!(
+            pretokenize("Hello world, it's 12345!\n\n    fn main()"),
+            vec![
+                "Hello", " world", ",", " it", "'s", " ", "123", "45", "!\n\n", "   ", " fn",
+                " main", "()"
+            ]
+        );
+        assert_eq!(pretokenize("a  b  "), vec!["a", " ", " b", "  "]);
+        assert_eq!(pretokenize(""), Vec::<&str>::new());
+    }
+
+    #[test]
+    fn test_bpe_merges_by_rank() {
+        use base64::Engine;
+        let b64 = |s: &str| base64::engine::general_purpose::STANDARD.encode(s);
+        let vocab = [("ab", 0), ("cd", 1), ("abcd", 2), (" x", 3)]
+            .iter()
+            .map(|(t, r)| format!("{} {}", b64(t), r))
+            .collect::<Vec<_>>()
+            .join("\n");
+        let bpe = Bpe::from_tiktoken(&vocab).expect("vocab");
+
+        // "abcd" is one token; "abcde" merges to [abcd][e]; " xy" to [ x][y]
+        assert_eq!(bpe.count("abcd"), 1);
+        assert_eq!(bpe.count("abcde"), 2);
+        assert_eq!(bpe.count("abcd xy"), 3);
+        assert!(Bpe::from_tiktoken("not-a-pair").is_err());
+    }
+
+    #[test]
+    fn test_heuristic_counts_are_plausible() {
+        assert_eq!(count_tokens("claude-3-5-sonnet", ""), 0);
+        assert_eq!(count_tokens("claude-3-5-sonnet", "The quick brown fox"), 4);
+
+        let code = "fn main() {\n    let total: u32 = values.iter().sum();\n}\n";
+        let tokens = count_tokens("claude-3-5-sonnet", code);
+        assert!((15..=30).contains(&tokens), "{} tokens", tokens);
+
+        assert_eq!(encoding_for_model("openai/gpt-4o-mini"), Some("o200k_base"));
+        assert_eq!(encoding_for_model("gpt-4-turbo"), Some("cl100k_base"));
+        assert_eq!(encoding_for_model("claude-3-7-sonnet"), None);
+    }
+}
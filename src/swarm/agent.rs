@@ -4,12 +4,15 @@ use anyhow::Result;
 use async_trait::async_trait;
 use regex::Regex;
 use serde::{Deserialize, Serialize};
+use serde_json::json;
 use std::sync::Arc;
 
 use super::constitution::{Constitution, ProposedAction};
 use super::lens::AgentLens;
 use super::telos::EudaimonicTelos;
 use crate::code_generation::llm::LlmProvider;
+use crate::code_generation::prompt;
+use crate::code_generation::router::{ModelRole, ModelRouter};
 use crate::providers::ResponseFormat;
 
 /// Extract JSON from a response that may be wrapped in markdown code blocks.
@@ -115,82 +118,49 @@ impl LlmSwarmAgent {
         }
     }
 
-    fn build_research_prompt(&self, telos: &EudaimonicTelos, context: &str) -> String {
-        format!(
-            r#"{system_modifier}
-
-{telos_prompt}
-
-Your specific perspective as {role}:
-- Focus: {description}
-- Priorities: {priorities}
-
-Analyze the codebase and propose ONE specific improvement that:
-1. Aligns with the eudaimonic telos (human flourishing)
-2. Respects constitutional constraints (corrigibility, safety, low impact)
-3. Reflects your unique perspective as {role}
+    /// Create an agent on the model routed to the planner role
+    pub fn from_router(
+        lens: AgentLens,
+        router: &ModelRouter,
+        constitution: Arc<Constitution>,
+    ) -> Result<Self> {
+        Ok(Self::new(
+            lens,
+            router.provider(ModelRole::Planner)?,
+            constitution,
+        ))
+    }
 
-Respond in JSON format:
-{{
-    "title": "Brief title of the improvement",
-    "description": "Detailed description of what to change",
-    "rationale": "Why this improves human flourishing",
-    "files_to_modify": ["path/to/file.rs"],
-    "files_to_create": ["path/to/new.rs"],
-    "files_to_delete": [],
-    "estimated_lines_changed": 50,
-    "expected_benefits": ["benefit1", "benefit2"],
-    "potential_risks": ["risk1"]
-}}"#,
-            system_modifier = self.lens.system_prompt_modifier,
-            telos_prompt = telos.generate_research_prompt(context),
-            role = self.lens.name,
-            description = self.lens.role_description,
-            priorities = self.lens.priorities.join(", "),
+    fn build_research_prompt(&self, telos: &EudaimonicTelos, context: &str) -> String {
+        prompt::render(
+            "proposal",
+            self.llm.model(),
+            &json!({
+                "system_modifier": self.lens.system_prompt_modifier,
+                "research": telos.generate_research_prompt(context),
+                "role": self.lens.name,
+                "description": self.lens.role_description,
+                "priorities": self.lens.priorities.join(", "),
+            }),
         )
     }
 
     fn build_analysis_prompt(&self, proposal: &Proposal, telos: &EudaimonicTelos) -> String {
-        format!(
-            r#"{system_modifier}
-
-You are evaluating a proposal from another agent. Your role is {role}.
-
-The intrinsic telos is: {purpose}
-
-PROPOSAL TO EVALUATE:
-Title: {title}
-Description: {description}
-Rationale: {rationale}
-Files affected: {files}
-Estimated changes: {lines} lines
-
-Expected benefits: {benefits}
-Potential risks: {risks}
-
-Evaluate this proposal through your lens ({role}):
-- Does it align with human flourishing?
-- Does it respect constitutional constraints?
-- What concerns do you have from your perspective?
-
-Respond in JSON format:
-{{
-    "score": 0.0-1.0,  // 0.0 = veto, 1.0 = full approval
-    "is_veto": false,  // true if you believe this should NOT proceed
-    "rationale": "Your reasoning",
-    "concerns": ["concern1", "concern2"],
-    "suggestions": ["suggestion1"]
-}}"#,
-            system_modifier = self.lens.system_prompt_modifier,
-            role = self.lens.name,
-            purpose = telos.purpose,
-            title = proposal.title,
-            description = proposal.description,
-            rationale = proposal.rationale,
-            files = proposal.files_to_modify.join(", "),
-            lines = proposal.estimated_lines_changed,
-            benefits = proposal.expected_benefits.join(", "),
-            risks = proposal.potential_risks.join(", "),
+        prompt::render(
+            "proposal_review",
+            self.llm.model(),
+            &json!({
+                "system_modifier": self.lens.system_prompt_modifier,
+                "role": self.lens.name,
+                "purpose": telos.purpose,
+                "title": proposal.title,
+                "description": proposal.description,
+                "rationale": proposal.rationale,
+                "files": proposal.files_to_modify.join(", "),
+                "lines": proposal.estimated_lines_changed,
+                "benefits": proposal.expected_benefits.join(", "),
+                "risks": proposal.potential_risks.join(", "),
+            }),
         )
     }
 }
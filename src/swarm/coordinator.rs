@@ -10,17 +10,31 @@ use log::{debug, error, info, warn};
 use std::path::{Path, PathBuf};
 use std::sync::Arc;
 use tokio::sync::Mutex;
+use tokio_util::sync::CancellationToken;
 
+use crate::code_generation::code_query::CodeQueryTool;
+use crate::code_generation::crate_docs::{
+    CrateInfoTool, CrateSearchTool, CratesClient, DocsRsTool,
+};
 use crate::code_generation::llm::{LlmFactory, LlmProvider};
 use crate::code_generation::llm_tool::{
-    BashTool, CompilationFeedbackTool, EditTool, FindTestsTool, GitCommandTool, GitHistoryTool,
-    GrepTool, ReadTool, TestRunnerTool, TodoWriteTool, ToolRegistry, WebFetchTool, WebSearchTool,
-    WriteTool,
+    ApplyPatchTool, BashTool, CompilationFeedbackTool, DeleteTool, EditTool, FindTestsTool,
+    GitBisectTool, GitCommandTool, GitHistoryTool, GrepTool, LlmTool, LsTool, MoveTool, ReadTool,
+    TestRunnerTool, ToolRegistry, WebSearchTool, WriteTool,
 };
-use crate::core::config::{Config, LlmConfig, LlmLoggingConfig, ModelConfig, PhaseConfig};
-use crate::providers::ResponseFormat;
+use crate::code_generation::lsp::LspSession;
+use crate::code_generation::memory::{RecallTool, RememberTool};
+use crate::code_generation::semantic_index::{self, SemanticSearchTool};
+use crate::code_generation::todos::{TodoReadTool, TodoWriteTool};
+use crate::code_generation::web_fetch::WebFetchTool;
+use crate::core::config::{Config, ModelConfig, NoTestsPolicy, PhaseConfig, WebFetchConfig};
+use crate::core::error::BorgError;
+use crate::core::events::{self, RunEvent};
+use crate::core::status::StatusReporter;
+use crate::providers::{metadata, ResponseFormat};
 use crate::testing::test_runner::TestRunner;
 use crate::version_control::git::GitManager;
+use crate::version_control::rollback::{MergeLedger, MergeRecord};
 
 use super::agent::Proposal;
 use super::constitution::Constitution;
@@ -55,6 +69,9 @@ pub struct SwarmCoordinator {
     approval_threshold: f64,
     git_manager: Arc<Mutex<dyn GitManager>>,
     test_runner: Arc<dyn TestRunner>,
+    status: Option<Arc<StatusReporter>>,
+    cancel: CancellationToken,
+    merges: Option<Arc<MergeLedger>>,
 }
 
 impl SwarmCoordinator {
@@ -75,6 +92,12 @@ impl SwarmCoordinator {
         );
         info!("TDD models: {:?}", config.phases.tdd.models);
 
+        let status = config
+            .agent
+            .status_file
+            .as_ref()
+            .map(|path| Arc::new(StatusReporter::new(path)));
+
         Ok(Self {
             telos,
             constitution,
@@ -82,42 +105,30 @@ impl SwarmCoordinator {
             approval_threshold: 0.5,
             git_manager,
             test_runner,
+            status,
+            cancel: CancellationToken::new(),
+            merges: None,
         })
     }
 
-    /// Create an LLM provider for a specific model config
-    fn create_llm_for_model(
-        model_config: &ModelConfig,
-        log_dir: &str,
-    ) -> Result<Box<dyn LlmProvider>> {
-        // Convert ModelConfig to LlmConfig format for LlmFactory
-        let llm_config = LlmConfig {
-            provider: model_config.provider.clone(),
-            api_key: model_config.api_key.clone().unwrap_or_default(),
-            model: model_config.model.clone(),
-            max_tokens: model_config.max_tokens,
-            temperature: model_config.temperature,
-            api_base: model_config.api_base.clone(),
-            headers: None,
-            enable_streaming: None,
-            enable_thinking: model_config.enable_thinking,
-            reasoning_effort: model_config.reasoning_effort.clone(),
-            reasoning_budget_tokens: model_config.reasoning_budget_tokens,
-            first_token_timeout_ms: None,
-            stall_timeout_ms: None,
-        };
+    /// Stop the current cycle, dropping in-flight LLM requests, when `token`
+    /// is cancelled
+    pub fn with_cancellation(mut self, token: CancellationToken) -> Self {
+        self.cancel = token;
+        self
+    }
 
-        let llm_logging = LlmLoggingConfig {
-            enabled: true,
-            log_dir: log_dir.to_string(),
-            console_logging: false,
-            include_full_prompts: true,
-            include_full_responses: true,
-            max_log_size_mb: 100,
-            log_files_to_keep: 10,
-        };
+    /// Record every merge of a proposal branch in `ledger`, so it can be
+    /// rolled back
+    pub fn with_merge_ledger(mut self, ledger: Arc<MergeLedger>) -> Self {
+        self.merges = Some(ledger);
+        self
+    }
 
-        LlmFactory::create(llm_config, llm_logging)
+    /// Create an LLM provider for a specific model config, with its
+    /// fallbacks and race partner
+    fn create_llm_for_model(&self, model_config: &ModelConfig) -> Result<Box<dyn LlmProvider>> {
+        LlmFactory::create_for_config(&self.config, model_config)
     }
 
     /// Create a ToolRegistry filtered by the phase's allowed tools
@@ -125,6 +136,8 @@ impl SwarmCoordinator {
         phase: &PhaseConfig,
         workspace: &Path,
         git_manager: Arc<Mutex<dyn GitManager>>,
+        no_tests_policy: NoTestsPolicy,
+        web_fetch: &WebFetchConfig,
     ) -> ToolRegistry {
         let mut registry = ToolRegistry::new();
         let allowed_tools: std::collections::HashSet<&str> =
@@ -141,6 +154,29 @@ impl SwarmCoordinator {
         if allowed_tools.contains("Glob") || allowed_tools.contains("explore_dir") {
             registry.register(BashTool::new(workspace.to_path_buf()));
         }
+        if allowed_tools.contains("LS") {
+            registry.register(LsTool::new(workspace.to_path_buf()));
+        }
+        let lsp = Arc::new(LspSession::new(workspace.to_path_buf()));
+        let (find_definition, find_references, document_symbols) = lsp.tools();
+        if allowed_tools.contains("FindDefinition") {
+            registry.register(find_definition);
+        }
+        if allowed_tools.contains("FindReferences") {
+            registry.register(find_references);
+        }
+        if allowed_tools.contains("DocumentSymbols") {
+            registry.register(document_symbols);
+        }
+        if allowed_tools.contains("CodeQuery") {
+            registry.register(CodeQueryTool::new(workspace.to_path_buf()));
+        }
+        if allowed_tools.contains("SemanticSearch") {
+            match semantic_index::global() {
+                Some(index) => registry.register(SemanticSearchTool::new(index)),
+                None => warn!("SemanticSearch needs index.embedding_model; leaving it out"),
+            }
+        }
         if allowed_tools.contains("find_tests") {
             registry.register(FindTestsTool::new(workspace.to_path_buf()));
         }
@@ -154,13 +190,25 @@ impl SwarmCoordinator {
             registry.register(CompilationFeedbackTool::new(workspace.to_path_buf()));
         }
         if allowed_tools.contains("run_tests") {
-            registry.register(TestRunnerTool::new(workspace.to_path_buf()));
+            registry.register(
+                TestRunnerTool::new(workspace.to_path_buf()).with_no_tests_policy(no_tests_policy),
+            );
         }
         if allowed_tools.contains("WebSearch") || allowed_tools.contains("web_search") {
             registry.register(WebSearchTool::new());
         }
         if allowed_tools.contains("WebFetch") {
-            registry.register(WebFetchTool::new());
+            registry.register(WebFetchTool::new(web_fetch.clone()));
+        }
+        let crates = CratesClient::new();
+        if allowed_tools.contains("CrateSearch") {
+            registry.register(CrateSearchTool::new(crates.clone()));
+        }
+        if allowed_tools.contains("CrateInfo") {
+            registry.register(CrateInfoTool::new(crates.clone()));
+        }
+        if allowed_tools.contains("DocsRs") {
+            registry.register(DocsRsTool::new(crates));
         }
 
         // Write tools (supporting new names: Write, Edit, Bash and old names)
@@ -170,14 +218,47 @@ impl SwarmCoordinator {
         if allowed_tools.contains("Edit") || allowed_tools.contains("modify_file") {
             registry.register(EditTool::new(workspace.to_path_buf()));
         }
+        if allowed_tools.contains("ApplyPatch") {
+            registry.register(ApplyPatchTool::new(workspace.to_path_buf()));
+        }
+        if allowed_tools.contains("Move") {
+            registry.register(MoveTool::new(workspace.to_path_buf()));
+        }
+        if allowed_tools.contains("Delete") {
+            registry.register(DeleteTool::new(workspace.to_path_buf()));
+        }
         if allowed_tools.contains("Bash") || allowed_tools.contains("git_command") {
             registry.register(GitCommandTool::new(workspace.to_path_buf()));
         }
+        if allowed_tools.contains("GitBisect") {
+            registry.register(GitBisectTool::new(workspace.to_path_buf()));
+        }
 
-        // TodoWrite tool (coordinator-level tracking)
+        // Todo tools (per-goal tracking, kept across attempts)
         if allowed_tools.contains("TodoWrite") {
             registry.register(TodoWriteTool::new());
         }
+        if allowed_tools.contains("TodoRead") {
+            registry.register(TodoReadTool::new());
+        }
+
+        // Memory tools (lessons about files and modules, kept across goals)
+        if allowed_tools.contains("Remember") {
+            registry.register(RememberTool::new());
+        }
+        if allowed_tools.contains("Recall") {
+            registry.register(RecallTool::new());
+        }
+
+        // MCP tools, allowed per server (`mcp__<server>`) or one by one
+        for tool in crate::mcp::global_tools() {
+            let server = tool.name().rsplit_once("__").map(|(server, _)| server);
+            if allowed_tools.contains(tool.name())
+                || server.is_some_and(|s| allowed_tools.contains(s))
+            {
+                registry.register(tool);
+            }
+        }
 
         // Note: Task tool is NOT added to the registry - it's coordinator-level only
         // to prevent recursion
@@ -189,13 +270,46 @@ impl SwarmCoordinator {
         registry
     }
 
+    /// Phases of a swarm cycle, as reported in the status file
+    const PHASES: [&'static str; 3] = ["Research", "Deliberation", "Execution"];
+
+    /// Report the start of a cycle phase
+    fn report_phase(&self, index: usize) {
+        if let Some(status) = &self.status {
+            status.start_step(index, Self::PHASES[index]);
+        }
+    }
+
+    /// Report the outcome of a cycle
+    fn report_outcome(&self, result: &SwarmCycleResult) {
+        if let Some(status) = &self.status {
+            status.finish_goal(match result {
+                SwarmCycleResult::Success { .. } => "succeeded",
+                SwarmCycleResult::NoConsensus { .. } => "no consensus",
+                SwarmCycleResult::ExecutionFailed { .. } => "failed",
+                SwarmCycleResult::NoImprovementsFound => "no improvements found",
+            });
+        }
+    }
+
     /// Run a single swarm cycle
     pub async fn run_cycle(&self, codebase_context: &str) -> Result<SwarmCycleResult> {
+        if let Some(status) = &self.status {
+            status.start_goal("swarm-cycle", "Swarm improvement cycle", Self::PHASES.len());
+        }
+        let result = self.run_cycle_phases(codebase_context).await?;
+        self.report_outcome(&result);
+        Ok(result)
+    }
+
+    /// Run the research, deliberation and execution phases of a cycle
+    async fn run_cycle_phases(&self, codebase_context: &str) -> Result<SwarmCycleResult> {
         info!("Starting swarm cycle");
         info!("Telos: {}", self.telos.purpose);
 
         // Phase 1: Research - run prompt on all research models
         info!("Phase 1: Research");
+        self.report_phase(0);
         let proposals = self.research_phase(codebase_context).await?;
 
         if proposals.is_empty() {
@@ -207,6 +321,7 @@ impl SwarmCoordinator {
 
         // Phase 2: Deliberation - score proposals using multiple models
         info!("Phase 2: Deliberation");
+        self.report_phase(1);
         let consensus = self.deliberation_phase(proposals.clone()).await?;
 
         let approved_proposal = match consensus {
@@ -242,9 +357,22 @@ impl SwarmCoordinator {
 
         // Phase 3: Execution - TDD loop
         info!("Phase 3: Execution");
-        let execution_result = self
-            .execution_phase(&approved_proposal, codebase_context)
-            .await;
+        events::emit(RunEvent::GoalSelected {
+            goal_id: approved_proposal.id.clone(),
+            title: approved_proposal.title.clone(),
+        });
+        if let Some(status) = &self.status {
+            status.update(|s| {
+                s.goal_id = Some(approved_proposal.id.clone());
+                s.goal_title = Some(approved_proposal.title.clone());
+            });
+        }
+        self.report_phase(2);
+        let execution_result = metadata::scope(
+            metadata::attribution(Some(&approved_proposal.id), "execution"),
+            self.execution_phase(&approved_proposal, codebase_context),
+        )
+        .await;
 
         match execution_result {
             Ok((changes_applied, tests_passed)) => {
@@ -281,8 +409,13 @@ impl SwarmCoordinator {
             debug!("Available tools: {:?}", phase.tools);
             // Create tool registry for this phase
             let workspace = PathBuf::from(&self.config.agent.working_dir);
-            let _tool_registry =
-                Self::create_tool_registry(phase, &workspace, self.git_manager.clone());
+            let _tool_registry = Self::create_tool_registry(
+                phase,
+                &workspace,
+                self.git_manager.clone(),
+                self.config.testing.no_tests,
+                &self.config.web_fetch,
+            );
             // TODO: Wire tool_registry into the LLM conversation loop
             // This requires multi-turn conversation support with tool calls
         }
@@ -297,19 +430,16 @@ impl SwarmCoordinator {
         let mut futures = Vec::new();
         for model_name in &phase.models {
             if let Some(model_config) = self.config.get_model(model_name) {
-                let model_config = model_config.clone();
-                let log_dir = self.config.logging.llm_log_dir.clone();
+                let llm = self.create_llm_for_model(model_config);
                 let prompt = prompt.clone();
                 let constitution = self.constitution.clone();
                 let model_name = model_name.clone();
 
                 futures.push(async move {
-                    Self::run_research_on_model(
-                        &model_name,
-                        &model_config,
-                        &log_dir,
-                        &prompt,
-                        &constitution,
+                    let agent = format!("research:{}", model_name);
+                    metadata::scope(
+                        metadata::attribution(None, &agent),
+                        Self::run_research_on_model(&model_name, llm?, &prompt, &constitution),
                     )
                     .await
                 });
@@ -340,13 +470,10 @@ impl SwarmCoordinator {
     /// Run research on a single model
     async fn run_research_on_model(
         model_name: &str,
-        model_config: &ModelConfig,
-        log_dir: &str,
+        llm: Box<dyn LlmProvider>,
         prompt: &str,
         constitution: &Constitution,
     ) -> Result<Proposal> {
-        let llm = Self::create_llm_for_model(model_config, log_dir)?;
-
         // Use JSON mode to ensure valid JSON responses
         let response = llm
             .generate_with_format(
@@ -500,14 +627,18 @@ impl SwarmCoordinator {
         let mut futures = Vec::new();
         for model_name in &self.config.phases.deliberation.models {
             if let Some(model_config) = self.config.get_model(model_name) {
-                let model_config = model_config.clone();
-                let log_dir = self.config.logging.llm_log_dir.clone();
+                let llm = self.create_llm_for_model(model_config);
                 let prompt = prompt.clone();
                 let model_name = model_name.clone();
+                let proposal_id = proposal.id.clone();
 
                 futures.push(async move {
-                    Self::run_deliberation_on_model(&model_name, &model_config, &log_dir, &prompt)
-                        .await
+                    let agent = format!("deliberation:{}", model_name);
+                    metadata::scope(
+                        metadata::attribution(Some(&proposal_id), &agent),
+                        Self::run_deliberation_on_model(&model_name, llm?, &prompt),
+                    )
+                    .await
                 });
             }
         }
@@ -534,12 +665,9 @@ impl SwarmCoordinator {
     /// Run deliberation on a single model
     async fn run_deliberation_on_model(
         _model_name: &str,
-        model_config: &ModelConfig,
-        log_dir: &str,
+        llm: Box<dyn LlmProvider>,
         prompt: &str,
     ) -> Result<f64> {
-        let llm = Self::create_llm_for_model(model_config, log_dir)?;
-
         let response = llm
             .generate_with_format(
                 prompt,
@@ -597,8 +725,47 @@ impl SwarmCoordinator {
 
         // Run tests to verify current state
         let test_result = self.test_runner.run_tests(&branch_name, None).await?;
+        if !test_result.success {
+            return Ok((false, false));
+        }
 
-        Ok((false, test_result.success))
+        let merged = self.merge_proposal(proposal).await?;
+        Ok((merged.is_some(), true))
+    }
+
+    /// Merge the branch of `proposal` into the mainline and record the
+    /// merge under the proposal id; returns the record, or `None` when the
+    /// branch has nothing to merge
+    pub async fn merge_proposal(&self, proposal: &Proposal) -> Result<Option<MergeRecord>> {
+        let branch = format!("swarm/{}", proposal.id);
+        let git = self.git_manager.lock().await;
+        let mainline = git.mainline_branch().await?;
+        if git.commit_summaries(&mainline, &branch).await?.is_empty() {
+            info!("Branch {} has no commits to merge", branch);
+            return Ok(None);
+        }
+
+        let message = format!(
+            "Merge branch '{}' into {}\n\n{}",
+            branch, mainline, proposal.title
+        );
+        let Some(merge_commit) = git
+            .merge_into(&branch, &mainline, &message, &self.config.git.checkout)
+            .await?
+        else {
+            return Ok(None);
+        };
+        events::emit(RunEvent::Merged {
+            branch: branch.clone(),
+        });
+
+        let record = MergeRecord::new(&proposal.id, &branch, &mainline, &merge_commit);
+        if let Some(ledger) = &self.merges {
+            if let Err(e) = ledger.save(&record).await {
+                warn!("Failed to record the merge of {}: {:#}", proposal.id, e);
+            }
+        }
+        Ok(Some(record))
     }
 
     /// Run the continuous improvement loop
@@ -613,7 +780,17 @@ impl SwarmCoordinator {
         for cycle in 0..max_cycles {
             info!("=== Swarm Cycle {} ===", cycle + 1);
 
-            let result = self.run_cycle(codebase_context).await?;
+            let result = tokio::select! {
+                biased;
+                _ = self.cancel.cancelled() => {
+                    warn!("Swarm cycle {} cancelled", cycle + 1);
+                    return Err(anyhow::anyhow!(BorgError::Cancelled(format!(
+                        "swarm cycle {} was cancelled",
+                        cycle + 1
+                    ))));
+                }
+                result = self.run_cycle(codebase_context) => result?,
+            };
 
             let should_continue = matches!(result, SwarmCycleResult::Success { .. });
 
@@ -39,23 +39,13 @@ impl Default for EudaimonicTelos {
 impl EudaimonicTelos {
     /// Generate a prompt that guides the swarm toward flourishing-aligned improvements
     pub fn generate_research_prompt(&self, codebase_context: &str) -> String {
-        format!(
-            r#"You are part of an autonomous swarm with an intrinsic purpose: {}
-
-Your task is to identify improvements to this codebase that would best serve human flourishing.
-
-Consider these dimensions of flourishing:
-- Character & Virtue: Does this promote ethical behavior and integrity?
-- Relationships: Does this facilitate genuine human connection?
-- Health: Does this support human well-being?
-- Meaning: Does this help humans understand their purpose?
-- Happiness: Does this contribute to long-term life satisfaction?
-
-Codebase context:
-{}
-
-Propose an improvement that genuinely advances human flourishing, not just superficial metrics."#,
-            self.purpose, codebase_context
+        crate::code_generation::prompt::render(
+            "research",
+            None,
+            &serde_json::json!({
+                "purpose": self.purpose,
+                "codebase_context": codebase_context,
+            }),
         )
     }
 }
@@ -0,0 +1,199 @@
+//! Checking acceptance criteria one by one.
+//!
+//! A passing test run says little about whether each criterion of a
+//! specification holds: the test meant to verify one may never have been
+//! written, or may have been filtered out. Each criterion that names a test
+//! is checked against the test output, and each that names a command is
+//! checked by running it.
+
+use log::{info, warn};
+use regex::Regex;
+use std::fmt;
+use std::path::Path;
+use std::time::Duration;
+use tokio::process::Command;
+use tokio::time::timeout;
+
+use crate::code_generation::spec_generator::AcceptanceCriterion;
+
+/// Longest a criterion's command may run
+const COMMAND_TIMEOUT: Duration = Duration::from_secs(300);
+
+/// How a criterion fared
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CriterionStatus {
+    /// Every check passed
+    Passed,
+    /// A check failed, for the given reason
+    Failed(String),
+    /// The criterion has no checks of its own
+    Unchecked,
+}
+
+/// A criterion and how it fared
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CriterionOutcome {
+    /// The criterion's description
+    pub criterion: String,
+
+    /// How it fared
+    pub status: CriterionStatus,
+}
+
+impl CriterionOutcome {
+    /// Whether the criterion is not known to be met
+    pub fn failed(&self) -> bool {
+        matches!(self.status, CriterionStatus::Failed(_))
+    }
+}
+
+impl fmt::Display for CriterionOutcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.status {
+            CriterionStatus::Passed => write!(f, "PASS {}", self.criterion),
+            CriterionStatus::Failed(reason) => write!(f, "FAIL {}: {}", self.criterion, reason),
+            CriterionStatus::Unchecked => write!(f, "UNCHECKED {}", self.criterion),
+        }
+    }
+}
+
+/// Whether the test `name` passed according to cargo's `test_output`, or
+/// `None` when it did not run; `name` matches the test's full path or its
+/// last segments
+pub fn test_outcome(test_output: &str, name: &str) -> Option<bool> {
+    let ansi = Regex::new(r"\x1b\[[0-9;]*m").unwrap();
+    let line_re = Regex::new(r"^test (\S+)(?: - should panic)? \.\.\. (\w+)").unwrap();
+    let name = name.trim();
+    let mut outcome = None;
+    for line in test_output.lines() {
+        let line = ansi.replace_all(line, "");
+        let Some(cap) = line_re.captures(line.trim()) else {
+            continue;
+        };
+        let path = &cap[1];
+        if path != name && !path.ends_with(&format!("::{}", name)) {
+            continue;
+        }
+        match &cap[2] {
+            "ok" => outcome = Some(outcome.unwrap_or(true)),
+            "FAILED" => outcome = Some(false),
+            // Ignored tests did not run
+            _ => {}
+        }
+    }
+    outcome
+}
+
+/// Check every criterion against `test_output`, running the commands at
+/// `workspace`
+pub async fn check_criteria(
+    criteria: &[AcceptanceCriterion],
+    test_output: &str,
+    workspace: &Path,
+) -> Vec<CriterionOutcome> {
+    let mut outcomes = Vec::new();
+    for criterion in criteria {
+        let status = check_criterion(criterion, test_output, workspace).await;
+        outcomes.push(CriterionOutcome {
+            criterion: criterion.description.clone(),
+            status,
+        });
+    }
+    outcomes
+}
+
+async fn check_criterion(
+    criterion: &AcceptanceCriterion,
+    test_output: &str,
+    workspace: &Path,
+) -> CriterionStatus {
+    if !criterion.is_checkable() {
+        return CriterionStatus::Unchecked;
+    }
+    if let Some(name) = &criterion.test {
+        match test_outcome(test_output, name) {
+            Some(true) => {}
+            Some(false) => return CriterionStatus::Failed(format!("test {} failed", name)),
+            None => return CriterionStatus::Failed(format!("test {} did not run", name)),
+        }
+    }
+    if let Some(command) = &criterion.command {
+        info!("Checking '{}' with `{}`", criterion.description, command);
+        let run = Command::new("sh")
+            .arg("-c")
+            .arg(command)
+            .current_dir(workspace)
+            .kill_on_drop(true)
+            .output();
+        match timeout(COMMAND_TIMEOUT, run).await {
+            Ok(Ok(output)) if output.status.success() => {}
+            Ok(Ok(output)) => {
+                let stderr = String::from_utf8_lossy(&output.stderr);
+                let detail = stderr.lines().rev().find(|l| !l.trim().is_empty());
+                return CriterionStatus::Failed(match detail {
+                    Some(line) => format!("`{}` failed: {}", command, line.trim()),
+                    None => format!("`{}` failed", command),
+                });
+            }
+            Ok(Err(e)) => {
+                warn!("Failed to run `{}`: {}", command, e);
+                return CriterionStatus::Failed(format!("`{}` could not run: {}", command, e));
+            }
+            Err(_) => {
+                return CriterionStatus::Failed(format!(
+                    "`{}` timed out after {:?}",
+                    command, COMMAND_TIMEOUT
+                ))
+            }
+        }
+    }
+    CriterionStatus::Passed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const OUTPUT: &str = "running 3 tests\n\
+                          test parser::tests::test_parses_empty ... \x1b[32mok\x1b[0m\n\
+                          test parser::tests::test_rejects_junk ... FAILED\n\
+                          test parser::tests::test_slow ... ignored\n";
+
+    #[test]
+    fn test_outcomes_of_named_tests() {
+        assert_eq!(test_outcome(OUTPUT, "test_parses_empty"), Some(true));
+        assert_eq!(
+            test_outcome(OUTPUT, "parser::tests::test_rejects_junk"),
+            Some(false)
+        );
+        assert_eq!(test_outcome(OUTPUT, "test_slow"), None);
+        assert_eq!(test_outcome(OUTPUT, "empty"), None);
+    }
+
+    #[tokio::test]
+    async fn test_each_criterion_is_checked() {
+        let dir = tempfile::tempdir().unwrap();
+        let criteria = vec![
+            AcceptanceCriterion::new("Empty input parses").with_test("test_parses_empty"),
+            AcceptanceCriterion::new("Junk is rejected").with_test("test_rejects_junk"),
+            AcceptanceCriterion::new("Output exists")
+                .with_test("test_parses_empty")
+                .with_command("echo missing >&2; test -f out.txt"),
+            AcceptanceCriterion::new("Runs fast").with_command("true"),
+            AcceptanceCriterion::new("Reads well"),
+        ];
+        let outcomes = check_criteria(&criteria, OUTPUT, dir.path()).await;
+        let lines: Vec<String> = outcomes.iter().map(|o| o.to_string()).collect();
+        assert_eq!(
+            lines,
+            vec![
+                "PASS Empty input parses",
+                "FAIL Junk is rejected: test test_rejects_junk failed",
+                "FAIL Output exists: `echo missing >&2; test -f out.txt` failed: missing",
+                "PASS Runs fast",
+                "UNCHECKED Reads well",
+            ]
+        );
+        assert_eq!(outcomes.iter().filter(|o| o.failed()).count(), 2);
+    }
+}
@@ -0,0 +1,523 @@
+//! Multi-sample benchmark execution with statistical comparison.
+//!
+//! A single benchmark run is noisy, so before/after comparisons collect
+//! several samples per branch and only flag a regression when Welch's
+//! t-test says the slowdown is significant *and* it exceeds the configured
+//! threshold.
+
+use anyhow::{anyhow, Result};
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+use crate::core::config::BenchmarkConfig;
+use crate::testing::test_runner::TestRunner;
+use crate::testing::worktree::Worktree;
+
+/// Summary statistics for a set of benchmark samples
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SampleStats {
+    /// Number of samples
+    pub count: usize,
+
+    /// Arithmetic mean
+    pub mean: f64,
+
+    /// Sample standard deviation (n - 1 denominator)
+    pub std_dev: f64,
+}
+
+impl SampleStats {
+    /// Compute statistics for the given samples
+    pub fn from_samples(samples: &[f64]) -> Self {
+        let count = samples.len();
+        if count == 0 {
+            return Self {
+                count,
+                mean: 0.0,
+                std_dev: 0.0,
+            };
+        }
+
+        let mean = samples.iter().sum::<f64>() / count as f64;
+        let variance = if count > 1 {
+            samples.iter().map(|x| (x - mean).powi(2)).sum::<f64>() / (count - 1) as f64
+        } else {
+            0.0
+        };
+
+        Self {
+            count,
+            mean,
+            std_dev: variance.sqrt(),
+        }
+    }
+
+    fn variance(&self) -> f64 {
+        self.std_dev * self.std_dev
+    }
+}
+
+/// Outcome of Welch's unequal-variance t-test
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WelchTTest {
+    /// The t statistic (positive when the second sample has the larger mean)
+    pub t_statistic: f64,
+
+    /// Welch–Satterthwaite degrees of freedom
+    pub degrees_of_freedom: f64,
+
+    /// Two-sided p-value
+    pub p_value: f64,
+}
+
+/// Run Welch's t-test comparing `baseline` against `candidate`
+pub fn welch_t_test(baseline: &SampleStats, candidate: &SampleStats) -> WelchTTest {
+    if baseline.count < 2 || candidate.count < 2 {
+        return WelchTTest {
+            t_statistic: 0.0,
+            degrees_of_freedom: 0.0,
+            p_value: 1.0,
+        };
+    }
+
+    let se_a = baseline.variance() / baseline.count as f64;
+    let se_b = candidate.variance() / candidate.count as f64;
+    let se_sum = se_a + se_b;
+    let diff = candidate.mean - baseline.mean;
+
+    if se_sum == 0.0 {
+        // Zero variance in both groups: any difference is exact
+        let p_value = if diff == 0.0 { 1.0 } else { 0.0 };
+        return WelchTTest {
+            t_statistic: if diff == 0.0 {
+                0.0
+            } else {
+                diff.signum() * f64::INFINITY
+            },
+            degrees_of_freedom: (baseline.count + candidate.count - 2) as f64,
+            p_value,
+        };
+    }
+
+    let t = diff / se_sum.sqrt();
+    let df = se_sum.powi(2)
+        / (se_a.powi(2) / (baseline.count - 1) as f64
+            + se_b.powi(2) / (candidate.count - 1) as f64);
+
+    // Two-sided p-value from the Student t distribution
+    let x = df / (df + t * t);
+    let p_value = regularized_incomplete_beta(x, df / 2.0, 0.5).clamp(0.0, 1.0);
+
+    WelchTTest {
+        t_statistic: t,
+        degrees_of_freedom: df,
+        p_value,
+    }
+}
+
+/// Result of comparing candidate benchmark samples against a baseline
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BenchmarkComparison {
+    /// Baseline (before) statistics
+    pub baseline: SampleStats,
+
+    /// Candidate (after) statistics
+    pub candidate: SampleStats,
+
+    /// Change of the candidate mean relative to the baseline, in percent
+    pub percent_change: f64,
+
+    /// Cohen's d effect size (positive means the candidate is slower)
+    pub effect_size: f64,
+
+    /// Welch's t-test result
+    pub t_test: WelchTTest,
+
+    /// Whether the difference is statistically significant
+    pub significant: bool,
+
+    /// Whether the candidate is a significant regression beyond the threshold
+    pub regression: bool,
+
+    /// Whether the candidate is a significant improvement
+    pub improvement: bool,
+}
+
+impl BenchmarkComparison {
+    /// Compare two sets of samples (lower is better, e.g. seconds)
+    pub fn from_samples(baseline: &[f64], candidate: &[f64], config: &BenchmarkConfig) -> Self {
+        let baseline = SampleStats::from_samples(baseline);
+        let candidate = SampleStats::from_samples(candidate);
+        let t_test = welch_t_test(&baseline, &candidate);
+
+        let percent_change = if baseline.mean != 0.0 {
+            (candidate.mean - baseline.mean) / baseline.mean * 100.0
+        } else {
+            0.0
+        };
+
+        let pooled_sd = ((baseline.variance() + candidate.variance()) / 2.0).sqrt();
+        let effect_size = if pooled_sd > 0.0 {
+            (candidate.mean - baseline.mean) / pooled_sd
+        } else {
+            0.0
+        };
+
+        let significant = t_test.p_value < config.significance_level;
+        let regression = significant && percent_change > config.regression_threshold_percent;
+        let improvement = significant && percent_change < 0.0;
+
+        Self {
+            baseline,
+            candidate,
+            percent_change,
+            effect_size,
+            t_test,
+            significant,
+            regression,
+            improvement,
+        }
+    }
+
+    /// One-line human readable summary
+    pub fn summary(&self) -> String {
+        format!(
+            "baseline {:.4}s ± {:.4}, candidate {:.4}s ± {:.4} ({:+.2}%, d = {:.2}, p = {:.4}){}",
+            self.baseline.mean,
+            self.baseline.std_dev,
+            self.candidate.mean,
+            self.candidate.std_dev,
+            self.percent_change,
+            self.effect_size,
+            self.t_test.p_value,
+            if self.regression {
+                " REGRESSION"
+            } else if self.improvement {
+                " improvement"
+            } else {
+                ""
+            }
+        )
+    }
+}
+
+/// Collect benchmark samples for baseline and candidate in turn.
+///
+/// Each branch is checked out in a worktree of its own so the two builds
+/// never share a tree, and each round runs the baseline and then the
+/// candidate, one after the other, so neither competes with the other for
+/// the machine; the sample value is the wall-clock duration in seconds.
+/// A warm-up round runs first and is discarded, so the cold build of each
+/// fresh checkout is not counted as a sample.
+pub async fn compare_benchmarks(
+    runner: &dyn TestRunner,
+    repo: &Path,
+    baseline_branch: &str,
+    candidate_branch: &str,
+    config: &BenchmarkConfig,
+) -> Result<BenchmarkComparison> {
+    let baseline_tree = Worktree::add(repo, baseline_branch).await?;
+    let candidate_tree = Worktree::add(repo, candidate_branch).await?;
+
+    let samples = config.samples.max(2);
+    let mut baseline = Vec::with_capacity(samples);
+    let mut candidate = Vec::with_capacity(samples);
+
+    info!(
+        "Benchmark warm-up: {} vs {}",
+        baseline_branch, candidate_branch
+    );
+    for (branch, tree) in [
+        (baseline_branch, &baseline_tree),
+        (candidate_branch, &candidate_tree),
+    ] {
+        let warm_up = runner.run_benchmark(branch, Some(tree.path())).await?;
+        if !warm_up.success {
+            return Err(anyhow!("Benchmark warm-up failed on branch {}", branch));
+        }
+    }
+
+    for round in 1..=samples {
+        info!(
+            "Benchmark round {}/{}: {} vs {}",
+            round, samples, baseline_branch, candidate_branch
+        );
+
+        let before = runner
+            .run_benchmark(baseline_branch, Some(baseline_tree.path()))
+            .await?;
+        let after = runner
+            .run_benchmark(candidate_branch, Some(candidate_tree.path()))
+            .await?;
+
+        if !before.success || !after.success {
+            warn!("Benchmark run failed in round {}", round);
+            return Err(anyhow!(
+                "Benchmark run failed (baseline success: {}, candidate success: {})",
+                before.success,
+                after.success
+            ));
+        }
+
+        baseline.push(before.duration.as_secs_f64());
+        candidate.push(after.duration.as_secs_f64());
+    }
+
+    let comparison = BenchmarkComparison::from_samples(&baseline, &candidate, config);
+    info!("Benchmark comparison: {}", comparison.summary());
+    Ok(comparison)
+}
+
+/// Natural log of the gamma function (Lanczos approximation)
+fn ln_gamma(x: f64) -> f64 {
+    const COEFFS: [f64; 6] = [
+        76.180_091_729_471_46,
+        -86.505_320_329_416_77,
+        24.014_098_240_830_91,
+        -1.231_739_572_450_155,
+        0.120_865_097_386_617_9e-2,
+        -0.539_523_938_495_3e-5,
+    ];
+
+    let mut y = x;
+    let tmp = x + 5.5;
+    let tmp = tmp - (x + 0.5) * tmp.ln();
+    let mut series = 1.000_000_000_190_015;
+    for c in COEFFS {
+        y += 1.0;
+        series += c / y;
+    }
+    -tmp + (2.506_628_274_631_000_5 * series / x).ln()
+}
+
+/// Regularized incomplete beta function I_x(a, b)
+fn regularized_incomplete_beta(x: f64, a: f64, b: f64) -> f64 {
+    if x <= 0.0 {
+        return 0.0;
+    }
+    if x >= 1.0 {
+        return 1.0;
+    }
+
+    let ln_front = ln_gamma(a + b) - ln_gamma(a) - ln_gamma(b) + a * x.ln() + b * (1.0 - x).ln();
+    let front = ln_front.exp();
+
+    // Use the symmetry relation for faster convergence of the continued fraction
+    if x < (a + 1.0) / (a + b + 2.0) {
+        front * beta_continued_fraction(x, a, b) / a
+    } else {
+        1.0 - front * beta_continued_fraction(1.0 - x, b, a) / b
+    }
+}
+
+/// Continued fraction for the incomplete beta function (modified Lentz)
+fn beta_continued_fraction(x: f64, a: f64, b: f64) -> f64 {
+    const MAX_ITERATIONS: usize = 200;
+    const EPSILON: f64 = 3e-14;
+    const TINY: f64 = 1e-300;
+
+    let qab = a + b;
+    let qap = a + 1.0;
+    let qam = a - 1.0;
+    let mut c = 1.0;
+    let mut d = 1.0 - qab * x / qap;
+    if d.abs() < TINY {
+        d = TINY;
+    }
+    d = 1.0 / d;
+    let mut h = d;
+
+    for m in 1..=MAX_ITERATIONS {
+        let m = m as f64;
+        let m2 = 2.0 * m;
+
+        let aa = m * (b - m) * x / ((qam + m2) * (a + m2));
+        d = 1.0 + aa * d;
+        if d.abs() < TINY {
+            d = TINY;
+        }
+        c = 1.0 + aa / c;
+        if c.abs() < TINY {
+            c = TINY;
+        }
+        d = 1.0 / d;
+        h *= d * c;
+
+        let aa = -(a + m) * (qab + m) * x / ((a + m2) * (qap + m2));
+        d = 1.0 + aa * d;
+        if d.abs() < TINY {
+            d = TINY;
+        }
+        c = 1.0 + aa / c;
+        if c.abs() < TINY {
+            c = TINY;
+        }
+        d = 1.0 / d;
+        let delta = d * c;
+        h *= delta;
+
+        if (delta - 1.0).abs() < EPSILON {
+            break;
+        }
+    }
+
+    h
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::test_runner::TestResult;
+    use async_trait::async_trait;
+    use std::collections::HashSet;
+    use std::path::PathBuf;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Mutex;
+    use std::time::Duration;
+
+    /// Runner whose benchmark takes as many milliseconds as the
+    /// `duration.txt` of the checkout it is given, plus a second for the
+    /// build on the first run in a checkout, and which fails if two
+    /// benchmarks overlap
+    #[derive(Default)]
+    struct CheckoutBenchRunner {
+        running: AtomicBool,
+        built: Mutex<HashSet<PathBuf>>,
+    }
+
+    #[async_trait]
+    impl TestRunner for CheckoutBenchRunner {
+        async fn run_tests(&self, _branch: &str, _target: Option<&Path>) -> Result<TestResult> {
+            Err(anyhow!("not used"))
+        }
+
+        async fn run_benchmark(&self, branch: &str, target: Option<&Path>) -> Result<TestResult> {
+            let target = target.ok_or_else(|| anyhow!("no checkout given"))?;
+            let mut millis: u64 = std::fs::read_to_string(target.join("duration.txt"))?
+                .trim()
+                .parse()?;
+            if self.built.lock().unwrap().insert(target.to_path_buf()) {
+                millis += 1000;
+            }
+            if self.running.swap(true, Ordering::SeqCst) {
+                return Err(anyhow!("benchmarks overlapped"));
+            }
+            tokio::time::sleep(Duration::from_millis(5)).await;
+            self.running.store(false, Ordering::SeqCst);
+            Ok(TestResult {
+                success: true,
+                output: String::new(),
+                duration: Duration::from_millis(millis),
+                metrics: None,
+                report: None,
+                failures: None,
+                compilation_errors: None,
+                exit_code: Some(0),
+                branch: Some(branch.to_string()),
+                test_stage: Some("benchmark".to_string()),
+                tests: None,
+            })
+        }
+    }
+
+    fn git(dir: &Path, args: &[&str]) {
+        let output = std::process::Command::new("git")
+            .current_dir(dir)
+            .args(args)
+            .output()
+            .unwrap();
+        assert!(output.status.success(), "git {:?} failed", args);
+    }
+
+    #[tokio::test]
+    async fn test_each_branch_is_benchmarked_in_turn_in_its_own_checkout() {
+        let dir = tempfile::tempdir().unwrap();
+        let repo = dir.path();
+        git(repo, &["init", "-q", "-b", "master"]);
+        git(repo, &["config", "user.name", "Test"]);
+        git(repo, &["config", "user.email", "test@example.com"]);
+        std::fs::write(repo.join("duration.txt"), "100").unwrap();
+        git(repo, &["add", "."]);
+        git(repo, &["commit", "-q", "-m", "Initial commit"]);
+        git(repo, &["checkout", "-q", "-b", "improvement/goal-1"]);
+        std::fs::write(repo.join("duration.txt"), "200").unwrap();
+        git(repo, &["commit", "-q", "-am", "Slow down"]);
+        git(repo, &["checkout", "-q", "master"]);
+
+        let config = BenchmarkConfig {
+            samples: 3,
+            ..BenchmarkConfig::default()
+        };
+        let comparison = compare_benchmarks(
+            &CheckoutBenchRunner::default(),
+            repo,
+            "master",
+            "improvement/goal-1",
+            &config,
+        )
+        .await
+        .unwrap();
+        assert!((comparison.baseline.mean - 0.1).abs() < 1e-9);
+        assert!((comparison.candidate.mean - 0.2).abs() < 1e-9);
+        assert!((comparison.percent_change - 100.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_sample_stats() {
+        let stats = SampleStats::from_samples(&[2.0, 4.0, 4.0, 4.0, 5.0, 5.0, 7.0, 9.0]);
+        assert_eq!(stats.count, 8);
+        assert!((stats.mean - 5.0).abs() < 1e-9);
+        assert!((stats.std_dev - 2.138_089_935).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_welch_p_value_matches_reference() {
+        // Reference values: t = 2.1071, df = 11.09, two-sided p = 0.0587
+        let a = SampleStats::from_samples(&[27.5, 21.0, 19.0, 23.6, 17.0, 17.9, 16.9, 20.1]);
+        let b = SampleStats::from_samples(&[27.1, 22.0, 20.8, 23.4, 23.4, 23.5, 25.8, 22.0]);
+        let result = welch_t_test(&a, &b);
+        assert!((result.t_statistic - 2.1071).abs() < 1e-3);
+        assert!((result.degrees_of_freedom - 11.093).abs() < 1e-2);
+        assert!((result.p_value - 0.0587).abs() < 1e-3);
+    }
+
+    #[test]
+    fn test_significant_regression_is_flagged() {
+        let config = BenchmarkConfig::default();
+        let baseline = [1.00, 1.02, 0.98, 1.01, 0.99, 1.00];
+        let candidate = [1.20, 1.22, 1.18, 1.21, 1.19, 1.20];
+
+        let comparison = BenchmarkComparison::from_samples(&baseline, &candidate, &config);
+        assert!(comparison.significant);
+        assert!(comparison.regression);
+        assert!(comparison.effect_size > 1.0);
+        assert!((comparison.percent_change - 20.0).abs() < 0.5);
+    }
+
+    #[test]
+    fn test_noisy_difference_is_not_flagged() {
+        let config = BenchmarkConfig::default();
+        let baseline = [1.0, 1.4, 0.7, 1.2, 0.8];
+        let candidate = [1.1, 1.5, 0.8, 1.3, 0.9];
+
+        let comparison = BenchmarkComparison::from_samples(&baseline, &candidate, &config);
+        assert!(comparison.percent_change > config.regression_threshold_percent);
+        assert!(!comparison.significant);
+        assert!(!comparison.regression);
+    }
+
+    #[test]
+    fn test_significant_change_below_threshold_is_not_regression() {
+        let config = BenchmarkConfig {
+            regression_threshold_percent: 10.0,
+            ..BenchmarkConfig::default()
+        };
+        let baseline = [1.000, 1.001, 0.999, 1.000, 1.000];
+        let candidate = [1.030, 1.031, 1.029, 1.030, 1.030];
+
+        let comparison = BenchmarkComparison::from_samples(&baseline, &candidate, &config);
+        assert!(comparison.significant);
+        assert!(!comparison.regression);
+    }
+}
@@ -8,7 +8,9 @@ use std::time::{Duration, Instant};
 
 use crate::core::error::BorgError;
 use crate::testing::result_analyzer::{TestAnalysis, TestError, TestResultAnalyzer};
-use crate::testing::test_runner::{TestMetrics, TestResult, TestRunner};
+use crate::testing::test_runner::{
+    compile_check_result, TestMetrics, TestResult, TestRunner, CARGO_CHECK_ARGS,
+};
 
 /// The stage of testing being performed
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
@@ -237,6 +239,7 @@ impl ComprehensiveTestRunner {
             exit_code: Some(output.status.code().unwrap_or(0)),
             branch: Some(branch.to_string()),
             test_stage: Some(stage.to_string()),
+            tests: None,
         })
     }
 
@@ -659,6 +662,7 @@ impl TestRunner for ComprehensiveTestRunner {
             exit_code: None,
             branch: Some(branch.to_string()),
             test_stage: Some("comprehensive".to_string()),
+            tests: None,
         })
     }
 
@@ -670,4 +674,21 @@ impl TestRunner for ComprehensiveTestRunner {
 
         Ok(result)
     }
+
+    async fn run_compile_check(
+        &self,
+        branch: &str,
+        target_path: Option<&Path>,
+    ) -> Result<TestResult> {
+        Self::check_command("cargo")?;
+
+        let mut cmd = Command::new("cargo");
+        cmd.current_dir(target_path.unwrap_or(&self.workspace))
+            .args(CARGO_CHECK_ARGS);
+
+        let result = self
+            .run_command(&mut cmd, TestStage::Compilation, branch)
+            .await?;
+        Ok(compile_check_result(result))
+    }
 }
@@ -198,15 +198,22 @@ impl CoverageReporter {
         Ok(report)
     }
 
+    /// Whether grcov, which reports are made with, is installed
+    pub fn is_available() -> bool {
+        Command::new("grcov")
+            .arg("--version")
+            .output()
+            .is_ok_and(|output| output.status.success())
+    }
+
     /// Check if grcov is installed
     fn check_grcov(&self) -> Result<()> {
-        let status = Command::new("grcov").arg("--version").status();
-
-        match status {
-            Ok(status) if status.success() => Ok(()),
-            _ => Err(anyhow::anyhow!(BorgError::TestingError(
+        if Self::is_available() {
+            Ok(())
+        } else {
+            Err(anyhow::anyhow!(BorgError::TestingError(
                 "grcov is not installed. Install it with: cargo install grcov".to_string()
-            ))),
+            )))
         }
     }
 
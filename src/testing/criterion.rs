@@ -0,0 +1,375 @@
+//! Criterion benchmarks compared between two branches.
+//!
+//! Timing `cargo bench` as a whole mostly measures compilation. Criterion
+//! already samples every benchmark many times, so each branch's benches are
+//! run once with `--save-baseline` and the per-iteration times it records
+//! are compared benchmark by benchmark. The baseline branch is benchmarked
+//! in a temporary worktree, leaving the workspace on the candidate branch;
+//! both share a target directory so dependencies are only built once.
+
+use anyhow::{anyhow, Context, Result};
+use glob::glob;
+use log::{debug, info};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tokio::process::Command;
+use tokio::time::timeout;
+
+use crate::core::config::BenchmarkConfig;
+use crate::testing::benchmark::BenchmarkComparison;
+use crate::testing::worktree::Worktree;
+
+/// Criterion baseline the baseline branch's results are saved as
+pub const BASELINE_LABEL: &str = "borg-baseline";
+
+/// Criterion baseline the candidate branch's results are saved as
+pub const CANDIDATE_LABEL: &str = "borg-candidate";
+
+/// Per-iteration times in nanoseconds, keyed by benchmark id
+pub type Samples = BTreeMap<String, Vec<f64>>;
+
+/// The comparison of one benchmark between the two branches
+#[derive(Debug, Clone)]
+pub struct BenchmarkRow {
+    /// Criterion id of the benchmark, e.g. `parse/large`
+    pub benchmark: String,
+
+    /// How the candidate compares to the baseline
+    pub comparison: BenchmarkComparison,
+}
+
+/// Names of the criterion bench targets declared in a package manifest:
+/// `[[bench]]` entries with `harness = false` in a package depending on
+/// criterion
+pub fn discover_benches(manifest: &str) -> Vec<String> {
+    let Ok(table) = toml::from_str::<toml::Table>(manifest) else {
+        return Vec::new();
+    };
+    let uses_criterion = ["dev-dependencies", "dependencies"].iter().any(|section| {
+        table
+            .get(*section)
+            .and_then(|deps| deps.as_table())
+            .is_some_and(|deps| deps.contains_key("criterion"))
+    });
+    if !uses_criterion {
+        return Vec::new();
+    }
+    let mut benches: Vec<String> = table
+        .get("bench")
+        .and_then(|benches| benches.as_array())
+        .into_iter()
+        .flatten()
+        .filter(|bench| bench.get("harness").and_then(|h| h.as_bool()) == Some(false))
+        .filter_map(|bench| bench.get("name")?.as_str().map(str::to_string))
+        .collect();
+    benches.sort();
+    benches
+}
+
+#[derive(Deserialize)]
+struct SampleFile {
+    iters: Vec<f64>,
+    times: Vec<f64>,
+}
+
+#[derive(Deserialize)]
+struct BenchmarkFile {
+    full_id: String,
+}
+
+/// The samples criterion saved under `label` in `target_dir`
+pub fn read_samples(target_dir: &Path, label: &str) -> Result<Samples> {
+    let criterion_dir = target_dir.join("criterion");
+    let pattern = criterion_dir.join("**").join(label).join("sample.json");
+    let mut samples = Samples::new();
+    for path in glob(&pattern.to_string_lossy())?.flatten() {
+        let Some(dir) = path.parent() else {
+            continue;
+        };
+        let sample: SampleFile = serde_json::from_str(
+            &std::fs::read_to_string(&path)
+                .with_context(|| format!("Failed to read {}", path.display()))?,
+        )
+        .with_context(|| format!("Failed to parse {}", path.display()))?;
+        let id = std::fs::read_to_string(dir.join("benchmark.json"))
+            .ok()
+            .and_then(|content| serde_json::from_str::<BenchmarkFile>(&content).ok())
+            .map(|benchmark| benchmark.full_id)
+            .or_else(|| {
+                let relative = dir.parent()?.strip_prefix(&criterion_dir).ok()?;
+                Some(relative.to_string_lossy().to_string())
+            })
+            .unwrap_or_else(|| dir.to_string_lossy().to_string());
+        let per_iteration = sample
+            .times
+            .iter()
+            .zip(&sample.iters)
+            .filter(|(_, iters)| **iters > 0.0)
+            .map(|(time, iters)| time / iters)
+            .collect();
+        samples.insert(id, per_iteration);
+    }
+    Ok(samples)
+}
+
+/// Compare every benchmark measured on both branches
+pub fn compare_samples(
+    baseline: &Samples,
+    candidate: &Samples,
+    config: &BenchmarkConfig,
+) -> Vec<BenchmarkRow> {
+    baseline
+        .iter()
+        .filter_map(|(benchmark, before)| {
+            let after = candidate.get(benchmark)?;
+            Some(BenchmarkRow {
+                benchmark: benchmark.clone(),
+                comparison: BenchmarkComparison::from_samples(before, after, config),
+            })
+        })
+        .collect()
+}
+
+/// Markdown table of the comparisons, one row per benchmark
+pub fn comparison_table(rows: &[BenchmarkRow]) -> String {
+    let mut table = String::from(
+        "| Benchmark | Baseline | Candidate | Change | p-value | Verdict |\n\
+         |---|---|---|---|---|---|\n",
+    );
+    for row in rows {
+        let comparison = &row.comparison;
+        let verdict = if comparison.regression {
+            "regression"
+        } else if comparison.improvement {
+            "improvement"
+        } else if comparison.significant {
+            "within threshold"
+        } else {
+            "no change"
+        };
+        table.push_str(&format!(
+            "| {} | {} | {} | {:+.2}% | {:.4} | {} |\n",
+            row.benchmark,
+            format_nanos(comparison.baseline.mean),
+            format_nanos(comparison.candidate.mean),
+            comparison.percent_change,
+            comparison.t_test.p_value,
+            verdict
+        ));
+    }
+    table
+}
+
+/// A duration in nanoseconds in the largest fitting unit
+fn format_nanos(nanos: f64) -> String {
+    if nanos >= 1e9 {
+        format!("{:.3} s", nanos / 1e9)
+    } else if nanos >= 1e6 {
+        format!("{:.3} ms", nanos / 1e6)
+    } else if nanos >= 1e3 {
+        format!("{:.3} µs", nanos / 1e3)
+    } else {
+        format!("{:.1} ns", nanos)
+    }
+}
+
+/// Runs a workspace's criterion benches and compares branches
+pub struct CriterionHarness {
+    workspace: PathBuf,
+    benches: Vec<String>,
+    time_limit: Duration,
+}
+
+impl CriterionHarness {
+    /// Harness for the criterion benches of `workspace`, `None` when it has
+    /// none
+    pub fn discover(workspace: &Path, time_limit: Duration) -> Option<Self> {
+        let manifest = std::fs::read_to_string(workspace.join("Cargo.toml")).ok()?;
+        let benches = discover_benches(&manifest);
+        (!benches.is_empty()).then(|| Self {
+            workspace: workspace.to_path_buf(),
+            benches,
+            time_limit,
+        })
+    }
+
+    /// Names of the bench targets that are run
+    pub fn benches(&self) -> &[String] {
+        &self.benches
+    }
+
+    /// Target directory shared by every checkout
+    fn target_dir(&self) -> PathBuf {
+        match std::env::var_os("CARGO_TARGET_DIR") {
+            Some(dir) => self.workspace.join(dir),
+            None => self.workspace.join("target"),
+        }
+    }
+
+    /// Run every bench in `checkout`, saving the results as `label`, and
+    /// return their samples
+    pub async fn run(&self, checkout: &Path, label: &str) -> Result<Samples> {
+        let target_dir = self.target_dir();
+        for bench in &self.benches {
+            info!("Running criterion bench '{}' as '{}'", bench, label);
+            let run = Command::new("cargo")
+                .current_dir(checkout)
+                .env("CARGO_TARGET_DIR", &target_dir)
+                .args([
+                    "bench",
+                    "--bench",
+                    bench,
+                    "--",
+                    "--noplot",
+                    "--save-baseline",
+                ])
+                .arg(label)
+                .kill_on_drop(true)
+                .output();
+            let output = match timeout(self.time_limit, run).await {
+                Ok(output) => output.context("Failed to run cargo bench")?,
+                Err(_) => {
+                    return Err(anyhow!(
+                        "Bench '{}' timed out after {}s",
+                        bench,
+                        self.time_limit.as_secs()
+                    ))
+                }
+            };
+            if !output.status.success() {
+                let stderr = String::from_utf8_lossy(&output.stderr);
+                debug!("cargo bench output: {}", stderr);
+                let detail = stderr
+                    .lines()
+                    .rev()
+                    .find(|l| !l.trim().is_empty())
+                    .unwrap_or("no output");
+                return Err(anyhow!("Bench '{}' failed: {}", bench, detail.trim()));
+            }
+        }
+        read_samples(&target_dir, label)
+    }
+
+    /// Benchmark `baseline_branch` in a temporary worktree and the
+    /// workspace's checkout, and compare the two
+    pub async fn compare(
+        &self,
+        baseline_branch: &str,
+        config: &BenchmarkConfig,
+    ) -> Result<Vec<BenchmarkRow>> {
+        let worktree = Worktree::add(&self.workspace, baseline_branch)
+            .await
+            .context("Failed to check out the baseline for benchmarking")?;
+        let baseline = self.run(worktree.path(), BASELINE_LABEL).await;
+        drop(worktree);
+
+        let baseline = baseline?;
+        let candidate = self.run(&self.workspace, CANDIDATE_LABEL).await?;
+        let rows = compare_samples(&baseline, &candidate, config);
+        if rows.is_empty() {
+            return Err(anyhow!(
+                "No criterion benchmark was measured on both branches"
+            ));
+        }
+        Ok(rows)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    fn write_sample(target: &Path, id: &str, label: &str, iters: &[f64], times: &[f64]) {
+        let dir = target.join("criterion").join(id).join(label);
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(
+            dir.join("sample.json"),
+            serde_json::json!({"sampling_mode": "Linear", "iters": iters, "times": times})
+                .to_string(),
+        )
+        .unwrap();
+        fs::write(
+            dir.join("benchmark.json"),
+            serde_json::json!({"group_id": id, "full_id": id.replace('_', " ")}).to_string(),
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn test_benches_need_criterion_and_no_harness() {
+        let manifest = r#"
+[package]
+name = "demo"
+
+[dev-dependencies]
+criterion = "0.5"
+
+[[bench]]
+name = "parsing"
+harness = false
+
+[[bench]]
+name = "libtest_bench"
+
+[[bench]]
+name = "encoding"
+harness = false
+"#;
+        assert_eq!(discover_benches(manifest), ["encoding", "parsing"]);
+        let without_criterion = manifest.replace("criterion = \"0.5\"", "");
+        assert!(discover_benches(&without_criterion).is_empty());
+        assert!(discover_benches("not toml [").is_empty());
+    }
+
+    #[test]
+    fn test_samples_are_read_per_iteration() {
+        let dir = tempfile::tempdir().unwrap();
+        write_sample(
+            dir.path(),
+            "parse_small",
+            BASELINE_LABEL,
+            &[10.0, 20.0],
+            &[1000.0, 2200.0],
+        );
+        write_sample(dir.path(), "parse_small", CANDIDATE_LABEL, &[1.0], &[5.0]);
+
+        let samples = read_samples(dir.path(), BASELINE_LABEL).unwrap();
+        assert_eq!(samples.len(), 1);
+        assert_eq!(samples["parse small"], [100.0, 110.0]);
+        assert!(read_samples(dir.path(), "other").unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_only_benchmarks_on_both_branches_are_compared() {
+        let config = BenchmarkConfig::default();
+        let baseline = Samples::from([
+            ("fast".to_string(), vec![100.0, 101.0, 99.0, 100.5, 99.5]),
+            ("removed".to_string(), vec![1.0, 1.0]),
+        ]);
+        let candidate = Samples::from([
+            ("fast".to_string(), vec![150.0, 151.0, 149.0, 150.5, 149.5]),
+            ("added".to_string(), vec![1.0, 1.0]),
+        ]);
+        let rows = compare_samples(&baseline, &candidate, &config);
+        assert_eq!(rows.len(), 1);
+        assert_eq!(rows[0].benchmark, "fast");
+        assert!(rows[0].comparison.regression);
+
+        let table = comparison_table(&rows);
+        let lines: Vec<&str> = table.lines().collect();
+        assert_eq!(lines.len(), 3);
+        assert!(lines[2].starts_with("| fast | 100.0 ns | 150.0 ns | +50.00% |"));
+        assert!(lines[2].ends_with("| regression |"));
+    }
+
+    #[test]
+    fn test_durations_use_the_largest_unit() {
+        assert_eq!(format_nanos(512.0), "512.0 ns");
+        assert_eq!(format_nanos(1_500.0), "1.500 µs");
+        assert_eq!(format_nanos(2_000_000.0), "2.000 ms");
+        assert_eq!(format_nanos(3e9), "3.000 s");
+    }
+}
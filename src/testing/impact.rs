@@ -0,0 +1,315 @@
+//! Test impact analysis: the tests that exercise a set of changed files.
+//!
+//! Running the whole suite after every attempt dominates iteration time on
+//! large crates. A change to `src/parser/lexer.rs` can only break the unit
+//! tests of `parser::lexer`, of the modules using it, and the integration
+//! tests importing any of them, so only those are run while iterating. The
+//! module graph is read from `crate::` paths in the sources, which misses
+//! re-exports; the merge gate still runs its full set.
+
+use glob::glob;
+use regex::Regex;
+use std::collections::BTreeSet;
+use std::path::Path;
+
+use crate::code_generation::test_generator::package_name;
+use crate::core::config::{TestFilter, TestTarget};
+
+/// Test filters selecting the tests affected by `changed_files`, paths
+/// relative to `workspace`; `None` when the change can affect any test (a
+/// manifest, build script or crate root changed, or a file the analysis
+/// does not understand) or affects none
+pub fn affected_filters(workspace: &Path, changed_files: &[String]) -> Option<Vec<TestFilter>> {
+    let mut modules = BTreeSet::new();
+    let mut test_targets = BTreeSet::new();
+    let mut all_test_targets = false;
+
+    for path in changed_files {
+        let path = path.trim().trim_start_matches("./");
+        if path.ends_with(".md") || path.starts_with("docs/") {
+            continue;
+        }
+        if let Some(rest) = path.strip_prefix("tests/") {
+            match rest.split_once('/') {
+                None => test_targets.insert(rest.strip_suffix(".rs")?.to_string()),
+                Some((dir, _)) if workspace.join("tests").join(dir).join("main.rs").is_file() => {
+                    test_targets.insert(dir.to_string())
+                }
+                // A helper module shared by the integration tests
+                Some(_) => {
+                    all_test_targets = true;
+                    continue;
+                }
+            };
+            continue;
+        }
+        modules.insert(module_path(path)?);
+    }
+
+    let sources = crate_sources(workspace);
+    let modules = with_dependents(modules, &sources);
+    if !all_test_targets {
+        let crate_name = std::fs::read_to_string(workspace.join("Cargo.toml"))
+            .ok()
+            .and_then(|manifest| package_name(&manifest))
+            .map(|name| name.replace('-', "_"));
+        let patterns: Vec<Regex> = match &crate_name {
+            Some(crate_name) => modules
+                .iter()
+                .map(|module| reference_pattern(crate_name, module))
+                .collect(),
+            None => Vec::new(),
+        };
+        for (name, content) in integration_tests(workspace) {
+            let imports = patterns.iter().any(|pattern| pattern.is_match(&content));
+            // `tests/parser_tests.rs` for `src/parser.rs`
+            let named_after = modules
+                .iter()
+                .filter_map(|module| module.rsplit("::").next())
+                .any(|segment| name.contains(segment));
+            if imports || named_after {
+                test_targets.insert(name);
+            }
+        }
+    }
+
+    let mut filters = Vec::new();
+    if !modules.is_empty() {
+        let target = if workspace.join("src/lib.rs").is_file() {
+            TestTarget::Lib
+        } else {
+            TestTarget::Bins
+        };
+        filters.push(TestFilter {
+            targets: vec![target],
+            include: modules
+                .iter()
+                .map(|module| format!("{}::", module))
+                .collect(),
+            ..TestFilter::default()
+        });
+    }
+    if all_test_targets {
+        filters.push(TestFilter {
+            targets: vec![TestTarget::Tests],
+            ..TestFilter::default()
+        });
+    } else if !test_targets.is_empty() {
+        filters.push(TestFilter {
+            test_targets: test_targets.into_iter().collect(),
+            ..TestFilter::default()
+        });
+    }
+    (!filters.is_empty()).then_some(filters)
+}
+
+/// The module path of a source file below `src/`, e.g. `parser::lexer` for
+/// `src/parser/lexer.rs` or `src/parser/lexer/mod.rs`; `None` for crate
+/// roots, binaries and anything that is not a module
+pub fn module_path(path: &str) -> Option<String> {
+    let rest = path.strip_prefix("src/")?.strip_suffix(".rs")?;
+    if rest == "lib" || rest == "main" || rest.starts_with("bin/") {
+        return None;
+    }
+    let rest = rest.strip_suffix("/mod").unwrap_or(rest);
+    Some(rest.replace('/', "::"))
+}
+
+/// Module path and content of every module of the crate
+fn crate_sources(workspace: &Path) -> Vec<(String, String)> {
+    let pattern = workspace.join("src/**/*.rs");
+    let Ok(paths) = glob(&pattern.to_string_lossy()) else {
+        return Vec::new();
+    };
+    paths
+        .flatten()
+        .filter_map(|path| {
+            let relative = path
+                .strip_prefix(workspace)
+                .ok()?
+                .to_string_lossy()
+                .to_string();
+            let module = module_path(&relative)?;
+            Some((module, std::fs::read_to_string(&path).ok()?))
+        })
+        .collect()
+}
+
+/// Name and content of every integration test target
+fn integration_tests(workspace: &Path) -> Vec<(String, String)> {
+    ["tests/*.rs", "tests/*/main.rs"]
+        .iter()
+        .filter_map(|pattern| glob(&workspace.join(pattern).to_string_lossy()).ok())
+        .flat_map(|paths| paths.flatten())
+        .filter_map(|path| {
+            let name = if path.file_name()? == "main.rs" {
+                path.parent()?.file_name()?
+            } else {
+                path.file_stem()?
+            };
+            Some((
+                name.to_string_lossy().to_string(),
+                std::fs::read_to_string(&path).ok()?,
+            ))
+        })
+        .collect()
+}
+
+/// `modules` with every module using one of them, directly or not
+fn with_dependents(
+    mut modules: BTreeSet<String>,
+    sources: &[(String, String)],
+) -> BTreeSet<String> {
+    let mut patterns: Vec<Regex> = modules
+        .iter()
+        .map(|module| reference_pattern("crate", module))
+        .collect();
+    loop {
+        let dependents: Vec<String> = sources
+            .iter()
+            .filter(|(module, _)| !modules.contains(module))
+            .filter(|(_, content)| patterns.iter().any(|pattern| pattern.is_match(content)))
+            .map(|(module, _)| module.clone())
+            .collect();
+        if dependents.is_empty() {
+            return modules;
+        }
+        patterns.extend(
+            dependents
+                .iter()
+                .map(|module| reference_pattern("crate", module)),
+        );
+        modules.extend(dependents);
+    }
+}
+
+/// Pattern matching a reference to `module` through `root` (`crate` or the
+/// crate's name), as `root::a::b` or in a group such as `root::a::{b, c}`
+fn reference_pattern(root: &str, module: &str) -> Regex {
+    let (parent, last) = match module.rsplit_once("::") {
+        Some((parent, last)) => (format!("{}::{}", root, parent), last),
+        None => (root.to_string(), module),
+    };
+    Regex::new(&format!(
+        r"\b{}::(?:{}\b|\{{[^}}]*\b{}\b)",
+        regex::escape(&parent),
+        regex::escape(last),
+        regex::escape(last)
+    ))
+    .unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    /// A crate with `parser::lexer`, used by `parser` and `report`, the
+    /// unrelated `net`, and integration tests
+    fn workspace() -> tempfile::TempDir {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        fs::create_dir_all(root.join("src/parser")).unwrap();
+        fs::create_dir_all(root.join("tests/common")).unwrap();
+        fs::create_dir_all(root.join("tests/cli")).unwrap();
+        fs::write(root.join("Cargo.toml"), "[package]\nname = \"demo-app\"\n").unwrap();
+        fs::write(
+            root.join("src/lib.rs"),
+            "pub mod net;\npub mod parser;\npub mod report;\n",
+        )
+        .unwrap();
+        fs::write(root.join("src/parser/lexer.rs"), "pub fn lex() {}\n").unwrap();
+        fs::write(
+            root.join("src/parser/mod.rs"),
+            "pub mod lexer;\nuse crate::parser::lexer::lex;\n",
+        )
+        .unwrap();
+        fs::write(
+            root.join("src/report.rs"),
+            "use crate::{net, parser};\npub fn run() { parser::parse() }\n",
+        )
+        .unwrap();
+        fs::write(root.join("src/net.rs"), "pub fn get() {}\n").unwrap();
+        fs::write(
+            root.join("tests/parsing.rs"),
+            "use demo_app::parser::lexer;\n",
+        )
+        .unwrap();
+        fs::write(root.join("tests/network.rs"), "use demo_app::net::get;\n").unwrap();
+        fs::write(root.join("tests/reporting.rs"), "use demo_app::report;\n").unwrap();
+        fs::write(root.join("tests/common/mod.rs"), "pub fn setup() {}\n").unwrap();
+        fs::write(root.join("tests/cli/main.rs"), "fn main() {}\n").unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_module_paths_of_source_files() {
+        assert_eq!(
+            module_path("src/parser/lexer.rs").as_deref(),
+            Some("parser::lexer")
+        );
+        assert_eq!(module_path("src/parser/mod.rs").as_deref(), Some("parser"));
+        assert_eq!(module_path("src/lib.rs"), None);
+        assert_eq!(module_path("src/bin/tool.rs"), None);
+        assert_eq!(module_path("build.rs"), None);
+    }
+
+    #[test]
+    fn test_changed_module_selects_its_dependents() {
+        let dir = workspace();
+        let filters = affected_filters(dir.path(), &["src/parser/lexer.rs".to_string()]).unwrap();
+        assert_eq!(
+            filters,
+            [
+                TestFilter {
+                    targets: vec![TestTarget::Lib],
+                    include: vec![
+                        "parser::".to_string(),
+                        "parser::lexer::".to_string(),
+                        "report::".to_string()
+                    ],
+                    ..TestFilter::default()
+                },
+                TestFilter {
+                    test_targets: vec!["parsing".to_string(), "reporting".to_string()],
+                    ..TestFilter::default()
+                }
+            ]
+        );
+
+        let filters = affected_filters(dir.path(), &["src/net.rs".to_string()]).unwrap();
+        assert_eq!(filters[0].include, ["net::", "report::"]);
+        assert_eq!(filters[1].test_targets, ["network", "reporting"]);
+    }
+
+    #[test]
+    fn test_changed_tests_select_their_targets() {
+        let dir = workspace();
+        let filters = affected_filters(
+            dir.path(),
+            &[
+                "tests/parsing.rs".to_string(),
+                "tests/cli/main.rs".to_string(),
+            ],
+        )
+        .unwrap();
+        assert_eq!(filters.len(), 1);
+        assert_eq!(filters[0].test_targets, ["cli", "parsing"]);
+
+        let filters = affected_filters(dir.path(), &["tests/common/mod.rs".to_string()]).unwrap();
+        assert_eq!(filters[0].targets, [TestTarget::Tests]);
+    }
+
+    #[test]
+    fn test_crate_wide_changes_select_everything() {
+        let dir = workspace();
+        for changed in ["Cargo.toml", "src/lib.rs", "build.rs", "src/data.json"] {
+            assert!(
+                affected_filters(dir.path(), &[changed.to_string()]).is_none(),
+                "{}",
+                changed
+            );
+        }
+        assert!(affected_filters(dir.path(), &["README.md".to_string()]).is_none());
+    }
+}
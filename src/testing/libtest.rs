@@ -0,0 +1,371 @@
+//! Structured results of `cargo test`.
+//!
+//! Tests are run with `--message-format=json`, so cargo reports compiler
+//! diagnostics as JSON, and with libtest's `--format json`, so every test
+//! binary reports one event per test with its name, status, duration and
+//! captured output. libtest only takes that format with unstable options,
+//! which a stable toolchain allows when `RUSTC_BOOTSTRAP` is set.
+//!
+//! [`parse`] collects the events into a [`TestRun`], which renders back
+//! into the usual human-readable output for the logs and for prompts.
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::time::Duration;
+
+use crate::testing::test_runner::{parse_compiler_errors, CompilationError, TestFailure};
+
+/// Arguments passed to cargo for JSON diagnostics
+pub const CARGO_JSON_ARGS: &[&str] = &["--message-format=json"];
+
+/// Arguments passed to each test binary for JSON test events
+pub const LIBTEST_JSON_ARGS: &[&str] = &[
+    "-Z",
+    "unstable-options",
+    "--format",
+    "json",
+    "--report-time",
+];
+
+/// Environment variable allowing unstable libtest options on stable
+pub const UNSTABLE_OPTIONS_ENV: (&str, &str) = ("RUSTC_BOOTSTRAP", "1");
+
+/// How a test ended
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TestStatus {
+    Passed,
+    Failed,
+    Ignored,
+}
+
+/// One test of a run
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TestCase {
+    /// Full name, e.g. `parser::tests::test_parses_empty`
+    pub name: String,
+
+    /// How it ended
+    pub status: TestStatus,
+
+    /// How long it ran, when reported
+    #[serde(default)]
+    pub duration: Option<Duration>,
+
+    /// Its captured output, reported for failed tests
+    #[serde(default)]
+    pub stdout: Option<String>,
+}
+
+/// The tests of one test binary
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Suite {
+    /// The tests in the order they finished
+    pub tests: Vec<TestCase>,
+
+    /// Tests left out by the name filters
+    pub filtered_out: usize,
+
+    /// How long the binary ran, once it finished
+    pub duration: Option<Duration>,
+
+    /// Whether the binary finished; a crashed binary never reports its end
+    pub finished: bool,
+}
+
+impl Suite {
+    fn count(&self, status: TestStatus) -> usize {
+        self.tests.iter().filter(|t| t.status == status).count()
+    }
+}
+
+/// The outcome of a `cargo test` run
+#[derive(Debug, Clone, Default)]
+pub struct TestRun {
+    /// One suite per test binary, in the order they ran
+    pub suites: Vec<Suite>,
+
+    /// Errors that kept the tests from building
+    pub compilation_errors: Vec<CompilationError>,
+
+    /// Output lines that were not JSON events
+    pub other_lines: Vec<String>,
+}
+
+/// The run described by the JSON events in `output`, or `None` when it
+/// has none (the run predates JSON output or the toolchain refused it)
+pub fn parse(output: &str) -> Option<TestRun> {
+    let mut run = TestRun::default();
+    let mut saw_event = false;
+    for line in output.lines() {
+        let event = match serde_json::from_str::<Value>(line.trim()) {
+            Ok(event) if event.is_object() => event,
+            _ => {
+                if !line.trim().is_empty() {
+                    run.other_lines.push(line.to_string());
+                }
+                continue;
+            }
+        };
+        // Cargo's own messages are handled as a batch below
+        if event.get("reason").is_some() {
+            saw_event = true;
+            continue;
+        }
+        match (event["type"].as_str(), event["event"].as_str()) {
+            (Some("suite"), Some("started")) => {
+                saw_event = true;
+                run.suites.push(Suite::default());
+            }
+            (Some("suite"), Some(_)) => {
+                saw_event = true;
+                let suite = current_suite(&mut run);
+                suite.finished = true;
+                suite.filtered_out = event["filtered_out"].as_u64().unwrap_or(0) as usize;
+                suite.duration = seconds(&event["exec_time"]);
+            }
+            (Some("test"), Some(status)) => {
+                saw_event = true;
+                let status = match status {
+                    "ok" => TestStatus::Passed,
+                    "failed" => TestStatus::Failed,
+                    "ignored" => TestStatus::Ignored,
+                    // "started", and "timeout" warnings of slow tests
+                    _ => continue,
+                };
+                let Some(name) = event["name"].as_str() else {
+                    continue;
+                };
+                current_suite(&mut run).tests.push(TestCase {
+                    name: name.to_string(),
+                    status,
+                    duration: seconds(&event["exec_time"]),
+                    stdout: event["stdout"].as_str().map(str::to_string),
+                });
+            }
+            // Benchmarks and anything newer
+            _ => {}
+        }
+    }
+    if !saw_event {
+        return None;
+    }
+    run.compilation_errors = parse_compiler_errors(output);
+    Some(run)
+}
+
+fn current_suite(run: &mut TestRun) -> &mut Suite {
+    if run.suites.is_empty() {
+        run.suites.push(Suite::default());
+    }
+    run.suites.last_mut().unwrap()
+}
+
+fn seconds(value: &Value) -> Option<Duration> {
+    value
+        .as_f64()
+        .filter(|s| s.is_finite() && *s >= 0.0)
+        .map(Duration::from_secs_f64)
+}
+
+impl TestRun {
+    /// Every test of the run
+    pub fn tests(&self) -> impl Iterator<Item = &TestCase> {
+        self.suites.iter().flat_map(|suite| &suite.tests)
+    }
+
+    /// Tests with `status`
+    pub fn count(&self, status: TestStatus) -> usize {
+        self.tests().filter(|test| test.status == status).count()
+    }
+
+    /// The failed tests, located at the place they panicked
+    pub fn failures(&self) -> Vec<TestFailure> {
+        self.tests()
+            .filter(|test| test.status == TestStatus::Failed)
+            .map(|test| {
+                let stdout = test.stdout.clone().unwrap_or_default();
+                let panic = parse_panic(&stdout);
+                TestFailure {
+                    test_name: test.name.clone(),
+                    expected: panic.right,
+                    actual: panic.left,
+                    file: panic.file,
+                    line: panic.line,
+                    output: stdout,
+                    context: None,
+                }
+            })
+            .collect()
+    }
+
+    /// The run as `cargo test` prints it without JSON: the compiler errors,
+    /// then a block per suite with a `test result:` summary
+    pub fn render(&self) -> String {
+        let mut out: Vec<String> = self
+            .compilation_errors
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+        out.extend(self.other_lines.iter().cloned());
+        for suite in &self.suites {
+            out.push(String::new());
+            out.push(format!("running {} tests", suite.tests.len()));
+            for test in &suite.tests {
+                let status = match test.status {
+                    TestStatus::Passed => "ok",
+                    TestStatus::Failed => "FAILED",
+                    TestStatus::Ignored => "ignored",
+                };
+                out.push(format!("test {} ... {}", test.name, status));
+            }
+            let failed: Vec<&TestCase> = suite
+                .tests
+                .iter()
+                .filter(|test| test.status == TestStatus::Failed)
+                .collect();
+            if !failed.is_empty() {
+                out.push(String::new());
+                out.push("failures:".to_string());
+                for test in &failed {
+                    out.push(String::new());
+                    out.push(format!("---- {} stdout ----", test.name));
+                    out.push(
+                        test.stdout
+                            .as_deref()
+                            .unwrap_or_default()
+                            .trim_end()
+                            .to_string(),
+                    );
+                }
+                out.push(String::new());
+                out.push("failures:".to_string());
+                out.extend(failed.iter().map(|test| format!("    {}", test.name)));
+            }
+            if suite.finished {
+                out.push(String::new());
+                out.push(format!(
+                    "test result: {}. {} passed; {} failed; {} ignored; 0 measured; {} filtered out; finished in {:.2}s",
+                    if failed.is_empty() { "ok" } else { "FAILED" },
+                    suite.count(TestStatus::Passed),
+                    failed.len(),
+                    suite.count(TestStatus::Ignored),
+                    suite.filtered_out,
+                    suite.duration.unwrap_or_default().as_secs_f64()
+                ));
+            }
+        }
+        let mut rendered = out.join("\n");
+        rendered.push('\n');
+        rendered
+    }
+}
+
+/// Where and how a test panicked
+#[derive(Debug, Default, PartialEq)]
+struct Panic {
+    file: Option<String>,
+    line: Option<usize>,
+    left: Option<String>,
+    right: Option<String>,
+}
+
+/// The location and compared values of the panic in a test's output
+fn parse_panic(stdout: &str) -> Panic {
+    let mut panic = Panic::default();
+    for line in stdout.lines() {
+        let trimmed = line.trim();
+        if let Some(rest) = trimmed
+            .strip_prefix("thread '")
+            .and_then(|rest| rest.split_once("panicked at ").map(|(_, at)| at))
+        {
+            // `src/lib.rs:10:9:` (or `'msg', src/lib.rs:10:9` before 1.73)
+            let location = rest
+                .rsplit(", ")
+                .next()
+                .unwrap_or(rest)
+                .trim_end_matches(':');
+            let mut parts = location.rsplitn(3, ':');
+            let (_column, line, file) = (parts.next(), parts.next(), parts.next());
+            if let (Some(file), Some(line)) = (file, line.and_then(|l| l.parse().ok())) {
+                panic.file = Some(file.to_string());
+                panic.line = Some(line);
+            }
+        } else if let Some(value) = trimmed.strip_prefix("left:") {
+            panic.left = Some(compared_value(value));
+        } else if let Some(value) = trimmed.strip_prefix("right:") {
+            panic.right = Some(compared_value(value));
+        }
+    }
+    panic
+}
+
+/// A `left:`/`right:` value, without the quoting of older toolchains
+fn compared_value(value: &str) -> String {
+    let value = value.trim().trim_end_matches("',").trim_end_matches(',');
+    value.trim_matches('`').to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const OUTPUT: &str = concat!(
+        r#"{"reason":"compiler-artifact","target":{"name":"demo"}}"#,
+        "\n",
+        r#"{ "type": "suite", "event": "started", "test_count": 3 }"#,
+        "\n",
+        r#"{ "type": "test", "event": "started", "name": "tests::test_add" }"#,
+        "\n",
+        r#"{ "type": "test", "name": "tests::test_add", "event": "ok", "exec_time": 0.002 }"#,
+        "\n",
+        r#"{ "type": "test", "name": "tests::test_sub", "event": "failed", "exec_time": 0.01, "stdout": "\nthread 'tests::test_sub' (41) panicked at src/lib.rs:10:9:\nassertion `left == right` failed\n  left: 5\n right: 3\n" }"#,
+        "\n",
+        r#"{ "type": "test", "name": "tests::test_slow", "event": "ignored" }"#,
+        "\n",
+        r#"{ "type": "suite", "event": "failed", "passed": 1, "failed": 1, "ignored": 1, "measured": 0, "filtered_out": 2, "exec_time": 0.5 }"#,
+        "\n",
+        r#"{ "type": "suite", "event": "started", "test_count": 0 }"#,
+        "\n",
+        r#"{ "type": "suite", "event": "ok", "passed": 0, "failed": 0, "ignored": 0, "measured": 0, "filtered_out": 0, "exec_time": 0.0 }"#,
+        "\n",
+    );
+
+    #[test]
+    fn test_events_become_typed_results() {
+        let run = parse(OUTPUT).unwrap();
+        assert_eq!(run.suites.len(), 2);
+        assert_eq!(run.count(TestStatus::Passed), 1);
+        let add = run.tests().next().unwrap();
+        assert_eq!(add.duration, Some(Duration::from_millis(2)));
+        assert!(add.stdout.is_none());
+
+        let failures = run.failures();
+        assert_eq!(failures.len(), 1);
+        assert_eq!(failures[0].test_name, "tests::test_sub");
+        assert_eq!(failures[0].file.as_deref(), Some("src/lib.rs"));
+        assert_eq!(failures[0].line, Some(10));
+        assert_eq!(failures[0].actual.as_deref(), Some("5"));
+        assert_eq!(failures[0].expected.as_deref(), Some("3"));
+
+        assert!(parse("running 1 test\ntest a ... ok\n").is_none());
+    }
+
+    #[test]
+    fn test_runs_render_like_cargo_test() {
+        let rendered = parse(OUTPUT).unwrap().render();
+        assert!(rendered.contains(
+            "running 3 tests\ntest tests::test_add ... ok\ntest tests::test_sub ... FAILED\ntest tests::test_slow ... ignored\n"
+        ));
+        assert!(rendered.contains(
+            "---- tests::test_sub stdout ----\n\nthread 'tests::test_sub' (41) panicked"
+        ));
+        assert!(rendered.contains(
+            "test result: FAILED. 1 passed; 1 failed; 1 ignored; 0 measured; 2 filtered out; finished in 0.50s"
+        ));
+        assert_eq!(
+            crate::testing::test_runner::count_executed_tests(&rendered),
+            Some(2)
+        );
+    }
+}
@@ -0,0 +1,495 @@
+//! Per-category success metrics for optimization goals.
+//!
+//! Passing tests only show that a change did not break anything. Whether it
+//! achieved what the goal asked for depends on the goal's category: a
+//! performance goal must not slow the benchmarks down, a readability goal
+//! must not add lint findings, and so on. Each category maps to a
+//! `MetricEvaluator` that compares the candidate branch against the mainline.
+//! Each side is measured in a checkout of its own, one after the other.
+
+use anyhow::Result;
+use async_trait::async_trait;
+use log::warn;
+use regex::Regex;
+use std::collections::{BTreeSet, HashMap};
+use std::path::Path;
+use std::sync::{Arc, OnceLock};
+use std::time::Duration;
+
+use crate::core::config::BenchmarkConfig;
+use crate::core::optimization::{OptimizationCategory, OptimizationGoal};
+use crate::testing::benchmark::compare_benchmarks;
+use crate::testing::criterion::{comparison_table, CriterionHarness};
+use crate::testing::test_runner::{TestResult, TestRunner};
+use crate::testing::worktree::Worktree;
+
+/// Everything an evaluator needs to judge a change
+pub struct MetricContext<'a> {
+    /// The goal being evaluated
+    pub goal: &'a OptimizationGoal,
+
+    /// Runner used to collect measurements
+    pub runner: &'a dyn TestRunner,
+
+    /// Branch the change is compared against
+    pub baseline_branch: &'a str,
+
+    /// Branch carrying the change
+    pub candidate_branch: &'a str,
+
+    /// Checkout of the candidate branch
+    pub workspace: &'a Path,
+
+    /// Benchmark sampling and significance settings
+    pub benchmark_config: &'a BenchmarkConfig,
+}
+
+/// Verdict of a metric evaluation
+#[derive(Debug, Clone, PartialEq)]
+pub struct MetricOutcome {
+    /// Name of the metric that was evaluated
+    pub metric: String,
+
+    /// Whether the change meets the metric
+    pub passed: bool,
+
+    /// Human-readable summary of the measurement
+    pub summary: String,
+
+    /// Longer report for the execution log, such as a comparison table
+    pub details: Option<String>,
+}
+
+/// Judges whether a change meets the metric relevant to its goal
+#[async_trait]
+pub trait MetricEvaluator: Send + Sync {
+    /// Short name of the metric, used in logs
+    fn name(&self) -> &str;
+
+    /// Measure the baseline and candidate and return a verdict
+    async fn evaluate(&self, ctx: &MetricContext<'_>) -> Result<MetricOutcome>;
+}
+
+/// A measurement taken with the test runner
+#[derive(Clone, Copy)]
+enum Measurement {
+    Lint,
+    Coverage,
+    Audit,
+}
+
+impl Measurement {
+    /// Take the measurement on a fresh checkout of `branch`
+    async fn take(self, ctx: &MetricContext<'_>, branch: &str) -> Result<TestResult> {
+        let worktree = Worktree::add(ctx.workspace, branch).await?;
+        let checkout = Some(worktree.path());
+        match self {
+            Measurement::Lint => ctx.runner.run_linting(branch, checkout).await,
+            Measurement::Coverage => ctx.runner.run_coverage_analysis(branch, checkout).await,
+            Measurement::Audit => ctx.runner.run_security_audit(branch, checkout).await,
+        }
+    }
+
+    /// The measurement of the baseline and then of the candidate
+    async fn compare(self, ctx: &MetricContext<'_>) -> Result<(TestResult, TestResult)> {
+        let baseline = self.take(ctx, ctx.baseline_branch).await?;
+        let candidate = self.take(ctx, ctx.candidate_branch).await?;
+        Ok((baseline, candidate))
+    }
+}
+
+/// Performance: no statistically significant benchmark regression, judged
+/// per criterion benchmark when the crate has any and on the runner's
+/// benchmark timings otherwise
+pub struct BenchmarkMetric;
+
+#[async_trait]
+impl MetricEvaluator for BenchmarkMetric {
+    fn name(&self) -> &str {
+        "benchmarks"
+    }
+
+    async fn evaluate(&self, ctx: &MetricContext<'_>) -> Result<MetricOutcome> {
+        let time_limit = Duration::from_secs(ctx.benchmark_config.timeout_seconds);
+        if let Some(harness) = CriterionHarness::discover(ctx.workspace, time_limit) {
+            let rows = harness
+                .compare(ctx.baseline_branch, ctx.benchmark_config)
+                .await?;
+            let regressed: Vec<&str> = rows
+                .iter()
+                .filter(|row| row.comparison.regression)
+                .map(|row| row.benchmark.as_str())
+                .collect();
+            let summary = if regressed.is_empty() {
+                format!("no regression in {} criterion benchmarks", rows.len())
+            } else {
+                format!(
+                    "{} of {} criterion benchmarks regressed: {}",
+                    regressed.len(),
+                    rows.len(),
+                    regressed.join(", ")
+                )
+            };
+            return Ok(MetricOutcome {
+                metric: self.name().to_string(),
+                passed: regressed.is_empty(),
+                summary,
+                details: Some(comparison_table(&rows)),
+            });
+        }
+
+        let comparison = compare_benchmarks(
+            ctx.runner,
+            ctx.workspace,
+            ctx.baseline_branch,
+            ctx.candidate_branch,
+            ctx.benchmark_config,
+        )
+        .await?;
+
+        Ok(MetricOutcome {
+            metric: self.name().to_string(),
+            passed: !comparison.regression,
+            summary: comparison.summary(),
+            details: None,
+        })
+    }
+}
+
+/// Readability and complexity: the change must not add lint findings
+pub struct LintMetric;
+
+#[async_trait]
+impl MetricEvaluator for LintMetric {
+    fn name(&self) -> &str {
+        "lint findings"
+    }
+
+    async fn evaluate(&self, ctx: &MetricContext<'_>) -> Result<MetricOutcome> {
+        let (baseline, candidate) = Measurement::Lint.compare(ctx).await?;
+
+        let before = count_lint_findings(&baseline.output);
+        let after = count_lint_findings(&candidate.output);
+
+        Ok(MetricOutcome {
+            metric: self.name().to_string(),
+            passed: after <= before && (candidate.success || !baseline.success),
+            summary: format!("{} -> {} findings", before, after),
+            details: None,
+        })
+    }
+}
+
+/// Test coverage: line coverage must increase
+pub struct CoverageMetric;
+
+#[async_trait]
+impl MetricEvaluator for CoverageMetric {
+    fn name(&self) -> &str {
+        "coverage"
+    }
+
+    async fn evaluate(&self, ctx: &MetricContext<'_>) -> Result<MetricOutcome> {
+        let (baseline, candidate) = Measurement::Coverage.compare(ctx).await?;
+
+        match (
+            parse_coverage_percent(&baseline.output),
+            parse_coverage_percent(&candidate.output),
+        ) {
+            (Some(before), Some(after)) => Ok(MetricOutcome {
+                metric: self.name().to_string(),
+                passed: after > before,
+                summary: format!("{:.2}% -> {:.2}% ({:+.2})", before, after, after - before),
+                details: None,
+            }),
+            _ => {
+                // Without a coverage tool the metric cannot be measured
+                warn!("Coverage not reported; falling back to test results");
+                Ok(MetricOutcome {
+                    metric: self.name().to_string(),
+                    passed: candidate.success,
+                    summary: "coverage unavailable".to_string(),
+                    details: None,
+                })
+            }
+        }
+    }
+}
+
+/// Security: the change must not introduce new advisories
+pub struct AuditMetric;
+
+#[async_trait]
+impl MetricEvaluator for AuditMetric {
+    fn name(&self) -> &str {
+        "security advisories"
+    }
+
+    async fn evaluate(&self, ctx: &MetricContext<'_>) -> Result<MetricOutcome> {
+        let (baseline, candidate) = Measurement::Audit.compare(ctx).await?;
+
+        let before = audit_advisories(&baseline.output);
+        let after = audit_advisories(&candidate.output);
+        let introduced: Vec<_> = after.difference(&before).cloned().collect();
+
+        let summary = if introduced.is_empty() {
+            format!("{} -> {} advisories", before.len(), after.len())
+        } else {
+            format!(
+                "{} -> {} advisories (new: {})",
+                before.len(),
+                after.len(),
+                introduced.join(", ")
+            )
+        };
+
+        Ok(MetricOutcome {
+            metric: self.name().to_string(),
+            passed: introduced.is_empty(),
+            summary,
+            details: None,
+        })
+    }
+}
+
+/// Maps goal categories to the evaluator that judges them
+#[derive(Clone)]
+pub struct MetricRegistry {
+    evaluators: HashMap<OptimizationCategory, Arc<dyn MetricEvaluator>>,
+}
+
+impl MetricRegistry {
+    /// Registry with no evaluators; every goal is judged by tests alone
+    pub fn empty() -> Self {
+        Self {
+            evaluators: HashMap::new(),
+        }
+    }
+
+    /// Use `evaluator` for goals of `category`, replacing any previous one
+    pub fn register(
+        &mut self,
+        category: OptimizationCategory,
+        evaluator: Arc<dyn MetricEvaluator>,
+    ) {
+        self.evaluators.insert(category, evaluator);
+    }
+
+    /// Evaluator responsible for `category`, if any
+    pub fn evaluator_for(
+        &self,
+        category: &OptimizationCategory,
+    ) -> Option<Arc<dyn MetricEvaluator>> {
+        self.evaluators.get(category).cloned()
+    }
+}
+
+impl Default for MetricRegistry {
+    fn default() -> Self {
+        let mut registry = Self::empty();
+        let lint: Arc<dyn MetricEvaluator> = Arc::new(LintMetric);
+        registry.register(OptimizationCategory::Performance, Arc::new(BenchmarkMetric));
+        registry.register(OptimizationCategory::Readability, lint.clone());
+        registry.register(OptimizationCategory::Complexity, lint);
+        registry.register(OptimizationCategory::TestCoverage, Arc::new(CoverageMetric));
+        registry.register(OptimizationCategory::Security, Arc::new(AuditMetric));
+        registry
+    }
+}
+
+/// Number of warnings and errors in compiler or clippy output
+///
+/// Handles both the default and `--message-format=short` layouts and ignores
+/// summary lines such as "generated 3 warnings".
+pub fn count_lint_findings(output: &str) -> usize {
+    static FINDING: OnceLock<Regex> = OnceLock::new();
+    let finding = FINDING.get_or_init(|| {
+        Regex::new(r"^(?:\S+:\d+:\d+: )?(?:warning|error)(?:\[[^\]]+\])?: ").unwrap()
+    });
+
+    output
+        .lines()
+        .map(str::trim)
+        .filter(|line| finding.is_match(line))
+        .filter(|line| {
+            !(line.contains(" generated ")
+                || line.contains("aborting due to")
+                || line.contains("could not compile")
+                || line.contains("build failed"))
+        })
+        .count()
+}
+
+/// Overall line coverage percentage from a coverage tool's output
+///
+/// Takes the last percentage on a line mentioning coverage, which matches
+/// the summary line printed by tarpaulin and llvm-cov.
+pub fn parse_coverage_percent(output: &str) -> Option<f64> {
+    static PERCENT: OnceLock<Regex> = OnceLock::new();
+    let percent = PERCENT.get_or_init(|| Regex::new(r"(\d+(?:\.\d+)?)\s*%").unwrap());
+
+    output
+        .lines()
+        .filter(|line| line.to_lowercase().contains("coverage"))
+        .filter_map(|line| percent.captures_iter(line).last())
+        .filter_map(|caps| caps[1].parse::<f64>().ok())
+        .next_back()
+}
+
+/// Distinct RustSec advisory IDs reported by `cargo audit`
+pub fn audit_advisories(output: &str) -> BTreeSet<String> {
+    static ADVISORY: OnceLock<Regex> = OnceLock::new();
+    let advisory = ADVISORY.get_or_init(|| Regex::new(r"RUSTSEC-\d{4}-\d{4}").unwrap());
+
+    advisory
+        .find_iter(output)
+        .map(|m| m.as_str().to_string())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::anyhow;
+
+    /// Runner whose lint output is the `lint.txt` of the checkout it is
+    /// given
+    struct CheckoutLintRunner;
+
+    #[async_trait]
+    impl TestRunner for CheckoutLintRunner {
+        async fn run_tests(&self, _branch: &str, _target: Option<&Path>) -> Result<TestResult> {
+            Err(anyhow!("not used"))
+        }
+
+        async fn run_benchmark(&self, _branch: &str, _target: Option<&Path>) -> Result<TestResult> {
+            Err(anyhow!("not used"))
+        }
+
+        async fn run_linting(&self, branch: &str, target: Option<&Path>) -> Result<TestResult> {
+            let target = target.ok_or_else(|| anyhow!("no checkout given"))?;
+            Ok(TestResult {
+                success: true,
+                output: std::fs::read_to_string(target.join("lint.txt"))?,
+                duration: Duration::ZERO,
+                metrics: None,
+                report: None,
+                failures: None,
+                compilation_errors: None,
+                exit_code: Some(0),
+                branch: Some(branch.to_string()),
+                test_stage: Some("linting".to_string()),
+                tests: None,
+            })
+        }
+    }
+
+    fn git(dir: &Path, args: &[&str]) {
+        let output = std::process::Command::new("git")
+            .current_dir(dir)
+            .args(args)
+            .output()
+            .unwrap();
+        assert!(output.status.success(), "git {:?} failed", args);
+    }
+
+    #[tokio::test]
+    async fn test_lint_metric_measures_each_branch_in_its_own_checkout() {
+        let dir = tempfile::tempdir().unwrap();
+        let repo = dir.path();
+        git(repo, &["init", "-q", "-b", "master"]);
+        git(repo, &["config", "user.name", "Test"]);
+        git(repo, &["config", "user.email", "test@example.com"]);
+        std::fs::write(repo.join("lint.txt"), "src/lib.rs:1:1: warning: unused\n").unwrap();
+        git(repo, &["add", "."]);
+        git(repo, &["commit", "-q", "-m", "Initial commit"]);
+        git(repo, &["checkout", "-q", "-b", "improvement/goal-1"]);
+        std::fs::write(
+            repo.join("lint.txt"),
+            "src/lib.rs:1:1: warning: unused\nsrc/lib.rs:2:1: warning: unused\n",
+        )
+        .unwrap();
+        git(repo, &["commit", "-q", "-am", "Add a finding"]);
+        // The workspace has neither branch's findings
+        git(repo, &["checkout", "-q", "--detach", "master"]);
+        std::fs::remove_file(repo.join("lint.txt")).unwrap();
+
+        let goal = OptimizationGoal::new("goal-1", "Title", "Description");
+        let ctx = MetricContext {
+            goal: &goal,
+            runner: &CheckoutLintRunner,
+            baseline_branch: "master",
+            candidate_branch: "improvement/goal-1",
+            workspace: repo,
+            benchmark_config: &BenchmarkConfig::default(),
+        };
+        let outcome = LintMetric.evaluate(&ctx).await.unwrap();
+        assert_eq!(outcome.summary, "1 -> 2 findings");
+        assert!(!outcome.passed);
+
+        let worktrees = std::process::Command::new("git")
+            .current_dir(repo)
+            .args(["worktree", "list"])
+            .output()
+            .unwrap();
+        assert_eq!(
+            String::from_utf8_lossy(&worktrees.stdout).lines().count(),
+            1
+        );
+    }
+
+    #[test]
+    fn test_count_lint_findings_ignores_summaries() {
+        let output = "\
+warning: unused variable: `x`
+  --> src/lib.rs:3:9
+src/main.rs:10:5: warning: this `if` has identical blocks
+error[E0425]: cannot find value `y` in this scope
+warning: `demo` (lib) generated 2 warnings
+error: could not compile `demo` due to 1 previous error
+";
+        assert_eq!(count_lint_findings(output), 3);
+        assert_eq!(count_lint_findings("    Finished dev profile"), 0);
+    }
+
+    #[test]
+    fn test_parse_coverage_percent() {
+        let tarpaulin = "|| src/lib.rs: 8/10\n85.23% coverage, 100/120 lines covered";
+        assert_eq!(parse_coverage_percent(tarpaulin), Some(85.23));
+        assert_eq!(
+            parse_coverage_percent("Coverage analysis not implemented"),
+            None
+        );
+    }
+
+    #[test]
+    fn test_audit_advisories_are_deduplicated() {
+        let output = "ID: RUSTSEC-2021-0139\nID: RUSTSEC-2023-0071\nsee RUSTSEC-2021-0139";
+        let ids = audit_advisories(output);
+        assert_eq!(ids.len(), 2);
+        assert!(ids.contains("RUSTSEC-2023-0071"));
+    }
+
+    #[test]
+    fn test_default_registry_covers_measurable_categories() {
+        let registry = MetricRegistry::default();
+        let name = |c| registry.evaluator_for(&c).map(|e| e.name().to_string());
+        assert_eq!(
+            name(OptimizationCategory::Performance).as_deref(),
+            Some("benchmarks")
+        );
+        assert_eq!(
+            name(OptimizationCategory::Readability).as_deref(),
+            Some("lint findings")
+        );
+        assert_eq!(
+            name(OptimizationCategory::TestCoverage).as_deref(),
+            Some("coverage")
+        );
+        assert_eq!(
+            name(OptimizationCategory::Security).as_deref(),
+            Some("security advisories")
+        );
+        assert!(name(OptimizationCategory::General).is_none());
+    }
+}
@@ -1,6 +1,15 @@
+pub mod acceptance;
+pub mod benchmark;
 pub mod comprehensive;
 pub mod coverage;
+pub mod criterion;
 pub mod factory;
+pub mod impact;
+pub mod libtest;
+pub mod metrics;
+pub mod mutation;
+pub mod polyglot;
 pub mod result_analyzer;
 pub mod simple;
 pub mod test_runner;
+pub mod worktree;
@@ -0,0 +1,300 @@
+//! Mutation testing with cargo-mutants.
+//!
+//! Passing tests only show that a change does what its tests check. Mutating
+//! the changed code (replacing a function body with a default value,
+//! flipping an operator) and running the tests again shows how much they
+//! check: a mutant the tests still pass on "survives", and points at
+//! behaviour nothing verifies. The surviving mutants of a change are shown
+//! to the reviewer so it can judge whether the generated tests mean
+//! anything.
+
+use anyhow::{anyhow, Context, Result};
+use async_trait::async_trait;
+use log::{debug, info};
+use regex::Regex;
+use std::fmt;
+use std::path::Path;
+use std::time::Duration;
+use tokio::process::Command;
+use tokio::time::timeout;
+
+/// A mutation of the code
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Mutant {
+    /// Workspace-relative path of the mutated file
+    pub file: String,
+
+    /// Line of the mutation, when reported
+    pub line: Option<usize>,
+
+    /// What was changed, e.g. `replace add -> u32 with 0`
+    pub description: String,
+}
+
+impl fmt::Display for Mutant {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.line {
+            Some(line) => write!(f, "{}:{}: {}", self.file, line, self.description),
+            None => write!(f, "{}: {}", self.file, self.description),
+        }
+    }
+}
+
+/// How the mutants of a run fared against the tests
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MutationReport {
+    /// Mutants the tests failed on
+    pub caught: usize,
+
+    /// Mutants the tests still passed on
+    pub missed: Vec<Mutant>,
+
+    /// Mutants the tests did not finish on, usually infinite loops
+    pub timeouts: Vec<Mutant>,
+
+    /// Mutants that did not build, and so say nothing about the tests
+    pub unviable: usize,
+}
+
+impl MutationReport {
+    /// Mutants the tests let through: the missed ones and, since a hang is
+    /// not a clear failure, the timed out ones
+    pub fn surviving(&self) -> impl Iterator<Item = &Mutant> {
+        self.missed.iter().chain(&self.timeouts)
+    }
+
+    /// Fraction of the viable mutants the tests caught, `None` without any
+    pub fn score(&self) -> Option<f64> {
+        let viable = self.caught + self.missed.len() + self.timeouts.len();
+        (viable > 0).then(|| self.caught as f64 / viable as f64)
+    }
+
+    /// The report of a cargo-mutants run from its `mutants.out` directory
+    pub fn from_output_dir(dir: &Path) -> Result<Self> {
+        Ok(Self {
+            caught: read_mutants(&dir.join("caught.txt"))?.len(),
+            missed: read_mutants(&dir.join("missed.txt"))?,
+            timeouts: read_mutants(&dir.join("timeout.txt"))?,
+            unviable: read_mutants(&dir.join("unviable.txt"))?.len(),
+        })
+    }
+}
+
+impl fmt::Display for MutationReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} caught, {} missed, {} timed out, {} unviable",
+            self.caught,
+            self.missed.len(),
+            self.timeouts.len(),
+            self.unviable
+        )
+    }
+}
+
+/// The mutants listed in one of cargo-mutants' outcome files, one per line
+/// as `file:line:column: description`; a missing file lists none
+fn read_mutants(path: &Path) -> Result<Vec<Mutant>> {
+    let content = match std::fs::read_to_string(path) {
+        Ok(content) => content,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e).with_context(|| format!("Failed to read {}", path.display())),
+    };
+    Ok(content.lines().filter_map(parse_mutant).collect())
+}
+
+/// A mutant from its line in an outcome file
+pub fn parse_mutant(line: &str) -> Option<Mutant> {
+    let line = line.trim();
+    if line.is_empty() {
+        return None;
+    }
+    let located = Regex::new(r"^(.+?):(\d+)(?::\d+)?: (.+)$").unwrap();
+    Some(match located.captures(line) {
+        Some(cap) => Mutant {
+            file: cap[1].to_string(),
+            line: cap[2].parse().ok(),
+            description: cap[3].to_string(),
+        },
+        None => {
+            let (file, description) = line.split_once(": ")?;
+            Mutant {
+                file: file.to_string(),
+                line: None,
+                description: description.to_string(),
+            }
+        }
+    })
+}
+
+/// Runs mutation tests
+#[async_trait]
+pub trait MutationRunner: Send + Sync {
+    /// Mutate `files` of `workspace` and run its tests against each mutant
+    async fn run(
+        &self,
+        workspace: &Path,
+        files: &[String],
+        time_limit: Duration,
+    ) -> Result<MutationReport>;
+}
+
+/// Mutation testing with `cargo mutants`, which mutates a copy of the
+/// workspace so the branch itself is never touched
+#[derive(Debug, Clone, Default)]
+pub struct CargoMutants;
+
+impl CargoMutants {
+    /// Create a runner using the installed cargo-mutants
+    pub fn new() -> Self {
+        Self
+    }
+
+    /// Arguments of a run over `files` writing its results under `output`
+    fn args(files: &[String], output: &Path) -> Vec<String> {
+        let mut args = vec![
+            "mutants".to_string(),
+            "--no-shuffle".to_string(),
+            "--output".to_string(),
+            output.to_string_lossy().to_string(),
+        ];
+        for file in files {
+            args.push("--file".to_string());
+            args.push(file.clone());
+        }
+        args
+    }
+}
+
+#[async_trait]
+impl MutationRunner for CargoMutants {
+    async fn run(
+        &self,
+        workspace: &Path,
+        files: &[String],
+        time_limit: Duration,
+    ) -> Result<MutationReport> {
+        if files.is_empty() {
+            return Ok(MutationReport::default());
+        }
+        let output_dir =
+            std::env::temp_dir().join(format!("borg-mutants-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&output_dir)
+            .with_context(|| format!("Failed to create {}", output_dir.display()))?;
+
+        info!("Running cargo mutants on {}", files.join(", "));
+        let run = Command::new("cargo")
+            .current_dir(workspace)
+            .args(Self::args(files, &output_dir))
+            .kill_on_drop(true)
+            .output();
+        let result = match timeout(time_limit, run).await {
+            Ok(Ok(output)) => match output.status.code() {
+                // Everything caught, some mutants missed, some timed out
+                Some(0) | Some(2) | Some(3) => {
+                    MutationReport::from_output_dir(&output_dir.join("mutants.out"))
+                }
+                code => {
+                    let stderr = String::from_utf8_lossy(&output.stderr);
+                    debug!("cargo mutants output: {}", stderr);
+                    let detail = stderr
+                        .lines()
+                        .rev()
+                        .find(|l| !l.trim().is_empty())
+                        .unwrap_or("no output");
+                    Err(anyhow!(
+                        "cargo mutants failed (exit code {:?}): {}",
+                        code,
+                        detail.trim()
+                    ))
+                }
+            },
+            Ok(Err(e)) => Err(anyhow!("Failed to run cargo mutants: {}", e)),
+            Err(_) => Err(anyhow!(
+                "cargo mutants timed out after {}s",
+                time_limit.as_secs()
+            )),
+        };
+        let _ = std::fs::remove_dir_all(&output_dir);
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_outcome_lines_become_mutants() {
+        assert_eq!(
+            parse_mutant("src/lib.rs:12:5: replace add -> u32 with 0"),
+            Some(Mutant {
+                file: "src/lib.rs".to_string(),
+                line: Some(12),
+                description: "replace add -> u32 with 0".to_string(),
+            })
+        );
+        // Before cargo-mutants reported columns
+        let mutant = parse_mutant("src/lib.rs:3: replace < with <= in check").unwrap();
+        assert_eq!(mutant.line, Some(3));
+        assert_eq!(
+            mutant.to_string(),
+            "src/lib.rs:3: replace < with <= in check"
+        );
+        assert!(parse_mutant("  ").is_none());
+    }
+
+    #[test]
+    fn test_report_reads_the_output_directory() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(
+            dir.path().join("caught.txt"),
+            "src/lib.rs:1:38: replace add -> u32 with 1\nsrc/lib.rs:1:40: replace + with -\n",
+        )
+        .unwrap();
+        std::fs::write(
+            dir.path().join("missed.txt"),
+            "src/lib.rs:1:38: replace add -> u32 with 0\n",
+        )
+        .unwrap();
+        std::fs::write(
+            dir.path().join("unviable.txt"),
+            "src/lib.rs:5:1: replace name -> String with Default::default()\n",
+        )
+        .unwrap();
+
+        let report = MutationReport::from_output_dir(dir.path()).unwrap();
+        assert_eq!(report.caught, 2);
+        assert_eq!(report.unviable, 1);
+        assert!(report.timeouts.is_empty());
+        let surviving: Vec<String> = report.surviving().map(ToString::to_string).collect();
+        assert_eq!(surviving, ["src/lib.rs:1: replace add -> u32 with 0"]);
+        assert_eq!(report.score(), Some(2.0 / 3.0));
+        assert_eq!(
+            report.to_string(),
+            "2 caught, 1 missed, 0 timed out, 1 unviable"
+        );
+    }
+
+    #[test]
+    fn test_arguments_name_each_file() {
+        let args = CargoMutants::args(
+            &["src/a.rs".to_string(), "src/b.rs".to_string()],
+            Path::new("/tmp/out"),
+        );
+        assert_eq!(
+            args,
+            [
+                "mutants",
+                "--no-shuffle",
+                "--output",
+                "/tmp/out",
+                "--file",
+                "src/a.rs",
+                "--file",
+                "src/b.rs"
+            ]
+        );
+    }
+}
@@ -0,0 +1,374 @@
+//! Test runner for workspaces in languages other than (or besides) Rust.
+//!
+//! Every language of the workspace is checked and tested with its shell
+//! commands, in turn; the run passes when all of them do. Rust without
+//! configured commands goes through the cargo runner instead, keeping its
+//! test filters and JSON diagnostics, which also serves the cargo-only
+//! stages (benchmarks, linting, coverage, audit).
+
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use log::{error, info};
+use regex::Regex;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::process::Command;
+
+use crate::code_generation::language::{workspace_languages, Language};
+use crate::core::config::LanguageCommands;
+use crate::testing::test_runner::{CompilationError, TestResult, TestRunner};
+
+/// How a language is checked and tested
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Toolchain {
+    /// Through the cargo runner
+    Cargo,
+    /// With shell commands
+    Commands(LanguageCommands),
+}
+
+/// Checks and tests each language of a workspace with its own commands
+pub struct PolyglotTestRunner {
+    workspace: PathBuf,
+    languages: Vec<(Language, Toolchain)>,
+    cargo: Arc<dyn TestRunner>,
+}
+
+impl PolyglotTestRunner {
+    /// Runner for the languages detected in `workspace`, with their default
+    /// commands; `cargo` runs Rust
+    pub fn new<P: AsRef<Path>>(workspace: P, cargo: Arc<dyn TestRunner>) -> Self {
+        let workspace = workspace.as_ref().to_path_buf();
+        let languages = workspace_languages(&workspace)
+            .into_iter()
+            .map(|language| {
+                let toolchain = match language {
+                    Language::Rust => Toolchain::Cargo,
+                    other => Toolchain::Commands(other.default_commands()),
+                };
+                (language, toolchain)
+            })
+            .collect();
+        Self {
+            workspace,
+            languages,
+            cargo,
+        }
+    }
+
+    /// Use the configured commands of each language, adding the languages
+    /// that were not detected
+    pub fn with_commands(mut self, commands: &HashMap<Language, LanguageCommands>) -> Self {
+        for language in Language::ALL {
+            let Some(configured) = commands.get(&language) else {
+                continue;
+            };
+            let toolchain = Toolchain::Commands(configured.clone());
+            match self.languages.iter_mut().find(|(l, _)| *l == language) {
+                Some((_, existing)) => *existing = toolchain,
+                None => self.languages.push((language, toolchain)),
+            }
+        }
+        self
+    }
+
+    /// The languages checked and tested
+    pub fn languages(&self) -> Vec<Language> {
+        self.languages
+            .iter()
+            .map(|(language, _)| *language)
+            .collect()
+    }
+
+    /// Whether this runner only ever runs cargo, so the cargo runner can be
+    /// used directly
+    pub fn is_cargo_only(&self) -> bool {
+        self.languages
+            .iter()
+            .all(|(_, toolchain)| *toolchain == Toolchain::Cargo)
+    }
+
+    /// Run `command` through the shell at the workspace root
+    async fn run_command(&self, branch: &str, command: &str, stage: &str) -> Result<TestResult> {
+        info!("Running {} `{}` on branch {}", stage, command, branch);
+        let start = Instant::now();
+        let output = Command::new("sh")
+            .arg("-c")
+            .arg(command)
+            .current_dir(&self.workspace)
+            .output()
+            .await
+            .with_context(|| format!("Failed to run `{}`", command))?;
+
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        let combined = format!("{}\n{}", stdout, stderr);
+        let errors = parse_located_errors(&combined);
+        Ok(TestResult {
+            success: output.status.success(),
+            output: combined,
+            duration: start.elapsed(),
+            metrics: None,
+            report: None,
+            failures: None,
+            compilation_errors: (!errors.is_empty()).then_some(errors),
+            exit_code: output.status.code(),
+            branch: Some(branch.to_string()),
+            test_stage: Some(stage.to_string()),
+            tests: None,
+        })
+    }
+
+    /// Run one stage for every language and combine the results
+    async fn run_stage(&self, branch: &str, stage: Stage) -> Result<TestResult> {
+        let mut results: Vec<(Language, TestResult)> = Vec::new();
+        for (language, toolchain) in &self.languages {
+            let result = match toolchain {
+                Toolchain::Cargo => match stage {
+                    Stage::Check => self.cargo.run_compile_check(branch, None).await?,
+                    Stage::Test => self.cargo.run_tests(branch, None).await?,
+                    Stage::MergeGate => self.cargo.run_merge_gate_tests(branch, None).await?,
+                },
+                Toolchain::Commands(commands) => {
+                    let command = match stage {
+                        Stage::Check => &commands.check,
+                        Stage::Test | Stage::MergeGate => &commands.test,
+                    };
+                    match command {
+                        Some(command) => self.run_command(branch, command, stage.name()).await?,
+                        None => continue,
+                    }
+                }
+            };
+            if !result.success {
+                error!("{} {} failed on branch {}", language, stage.name(), branch);
+            }
+            results.push((*language, result));
+        }
+        Ok(combine(branch, stage, results))
+    }
+}
+
+/// The stages run per language
+#[derive(Debug, Clone, Copy)]
+enum Stage {
+    Check,
+    Test,
+    MergeGate,
+}
+
+impl Stage {
+    fn name(self) -> &'static str {
+        match self {
+            Stage::Check => "compile_check",
+            Stage::Test => "tests",
+            Stage::MergeGate => "merge_gate",
+        }
+    }
+}
+
+/// One result for the results of every language
+fn combine(branch: &str, stage: Stage, results: Vec<(Language, TestResult)>) -> TestResult {
+    // A single language keeps its output as is, so cargo's test summary
+    // lines stay where `count_executed_tests` looks for them
+    let output = match results.as_slice() {
+        [(_, result)] => result.output.clone(),
+        _ => results
+            .iter()
+            .map(|(language, result)| format!("## {}\n{}", language, result.output.trim()))
+            .collect::<Vec<_>>()
+            .join("\n\n"),
+    };
+    let errors: Vec<CompilationError> = results
+        .iter()
+        .flat_map(|(_, result)| result.compilation_errors.clone().unwrap_or_default())
+        .collect();
+    let failures: Vec<_> = results
+        .iter()
+        .flat_map(|(_, result)| result.failures.clone().unwrap_or_default())
+        .collect();
+
+    TestResult {
+        success: results.iter().all(|(_, result)| result.success),
+        output,
+        duration: results
+            .iter()
+            .map(|(_, result)| result.duration)
+            .sum::<Duration>(),
+        metrics: results
+            .iter()
+            .find_map(|(_, result)| result.metrics.clone()),
+        report: None,
+        failures: (!failures.is_empty()).then_some(failures),
+        compilation_errors: (!errors.is_empty()).then_some(errors),
+        exit_code: results
+            .iter()
+            .map(|(_, result)| result.exit_code)
+            .find(|code| *code != Some(0))
+            .unwrap_or(Some(0)),
+        branch: Some(branch.to_string()),
+        test_stage: Some(stage.name().to_string()),
+        tests: None,
+    }
+}
+
+/// Errors reported as `path:line:col: message` (Go, most linters) or
+/// `path(line,col): message` (tsc) in the output of a build
+pub fn parse_located_errors(output: &str) -> Vec<CompilationError> {
+    let re = Regex::new(
+        r"^\s*(?:\./)?([\w./-]+\.(\w+))(?::(\d+)(?::(\d+))?|\((\d+),(\d+)\)):\s*(?:error:?\s*)?(.+)$",
+    )
+    .unwrap();
+    let code_re = Regex::new(r"^(?:error\s+)?([A-Z]+\d+):\s*(.+)$").unwrap();
+    output
+        .lines()
+        .filter_map(|line| re.captures(line))
+        .filter(|cap| Language::from_extension(&cap[2]).is_some())
+        .map(|cap| {
+            let number = |i: usize| cap.get(i).and_then(|m| m.as_str().parse().ok());
+            let message = cap[7].trim();
+            let (error_code, message) = match code_re.captures(message) {
+                Some(code) => (Some(code[1].to_string()), code[2].to_string()),
+                None => (None, message.to_string()),
+            };
+            CompilationError {
+                message,
+                file: Some(cap[1].to_string()),
+                line: number(3).or(number(5)),
+                column: number(4).or(number(6)),
+                code_snippet: None,
+                error_code,
+            }
+        })
+        .collect()
+}
+
+#[async_trait]
+impl TestRunner for PolyglotTestRunner {
+    async fn run_tests(&self, branch: &str, _target_path: Option<&Path>) -> Result<TestResult> {
+        self.run_stage(branch, Stage::Test).await
+    }
+
+    async fn run_merge_gate_tests(
+        &self,
+        branch: &str,
+        _target_path: Option<&Path>,
+    ) -> Result<TestResult> {
+        self.run_stage(branch, Stage::MergeGate).await
+    }
+
+    async fn run_compile_check(
+        &self,
+        branch: &str,
+        _target_path: Option<&Path>,
+    ) -> Result<TestResult> {
+        self.run_stage(branch, Stage::Check).await
+    }
+
+    async fn run_benchmark(&self, branch: &str, target_path: Option<&Path>) -> Result<TestResult> {
+        self.cargo.run_benchmark(branch, target_path).await
+    }
+
+    async fn run_benchmarks(&self, branch: &str) -> Result<TestResult> {
+        self.cargo.run_benchmarks(branch).await
+    }
+
+    async fn run_linting(&self, branch: &str, target_path: Option<&Path>) -> Result<TestResult> {
+        self.cargo.run_linting(branch, target_path).await
+    }
+
+    async fn run_coverage_analysis(
+        &self,
+        branch: &str,
+        target_path: Option<&Path>,
+    ) -> Result<TestResult> {
+        self.cargo.run_coverage_analysis(branch, target_path).await
+    }
+
+    async fn run_security_audit(
+        &self,
+        branch: &str,
+        target_path: Option<&Path>,
+    ) -> Result<TestResult> {
+        self.cargo.run_security_audit(branch, target_path).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Cargo runner that must not be used
+    struct NoCargo;
+
+    #[async_trait]
+    impl TestRunner for NoCargo {
+        async fn run_tests(&self, _branch: &str, _target: Option<&Path>) -> Result<TestResult> {
+            panic!("cargo is not part of this workspace")
+        }
+
+        async fn run_benchmark(&self, _branch: &str, _target: Option<&Path>) -> Result<TestResult> {
+            panic!("cargo is not part of this workspace")
+        }
+    }
+
+    fn commands(check: Option<&str>, test: &str) -> LanguageCommands {
+        LanguageCommands {
+            check: check.map(str::to_string),
+            test: Some(test.to_string()),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_each_language_runs_its_commands() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("go.mod"), "module x\n").unwrap();
+        std::fs::write(dir.path().join("pyproject.toml"), "").unwrap();
+        let runner =
+            PolyglotTestRunner::new(dir.path(), Arc::new(NoCargo)).with_commands(&HashMap::from([
+                (Language::Python, commands(Some("true"), "echo '3 passed'")),
+                (
+                    Language::Go,
+                    commands(
+                        Some("echo './main.go:5:2: undefined: x' >&2; exit 2"),
+                        "echo ok",
+                    ),
+                ),
+            ]));
+        assert_eq!(runner.languages(), vec![Language::Python, Language::Go]);
+        assert!(!runner.is_cargo_only());
+
+        let tests = runner.run_tests("main", None).await.unwrap();
+        assert!(tests.success);
+        assert!(tests.output.starts_with("## Python\n3 passed"));
+        assert!(tests.output.contains("## Go\nok"));
+
+        let check = runner.run_compile_check("main", None).await.unwrap();
+        assert!(!check.success);
+        assert_eq!(check.exit_code, Some(2));
+        let errors = check.compilation_errors.unwrap();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].to_string(), "main.go:5:2: error: undefined: x");
+    }
+
+    #[test]
+    fn test_located_errors_of_go_and_tsc() {
+        let output = "# example\n\
+                      ./cmd/main.go:12:9: undefined: helper\n\
+                      src/app.ts(3,7): error TS2322: Type 'string' is not assignable to type 'number'.\n\
+                      Found 1 error in src/app.ts:3\n\
+                      notes.txt:1:1: not source\n";
+        let errors = parse_located_errors(output);
+        assert_eq!(errors.len(), 2);
+        assert_eq!(errors[0].file.as_deref(), Some("cmd/main.go"));
+        assert_eq!((errors[0].line, errors[0].column), (Some(12), Some(9)));
+        assert_eq!(errors[1].error_code.as_deref(), Some("TS2322"));
+        assert_eq!((errors[1].line, errors[1].column), (Some(3), Some(7)));
+        assert_eq!(
+            errors[1].message,
+            "Type 'string' is not assignable to type 'number'."
+        );
+    }
+}
@@ -5,8 +5,13 @@ use std::path::{Path, PathBuf};
 use std::process::Command;
 use std::time::Instant;
 
+use crate::core::config::{TestFilter, TestFiltersConfig};
 use crate::core::error::BorgError;
-use crate::testing::test_runner::{TestMetrics, TestResult, TestRunner};
+use crate::testing::libtest;
+use crate::testing::test_runner::{
+    affected_test_filters, cargo_test_args, cargo_test_result, combine_results,
+    compile_check_result, TestMetrics, TestResult, TestRunner, CARGO_CHECK_ARGS,
+};
 
 /// A simple test runner for Rust code
 pub struct SimpleTestRunner {
@@ -16,6 +21,9 @@ pub struct SimpleTestRunner {
     /// Timeout for tests in seconds
     #[allow(dead_code)]
     timeout_seconds: u64,
+
+    /// Test selection for the iteration and merge gates
+    filters: TestFiltersConfig,
 }
 
 impl SimpleTestRunner {
@@ -24,9 +32,28 @@ impl SimpleTestRunner {
         Ok(Self {
             workspace: workspace.as_ref().to_path_buf(),
             timeout_seconds: 120, // Default timeout of 2 minutes
+            filters: TestFiltersConfig::default(),
         })
     }
 
+    /// Use the given test filters for the iteration and merge gates
+    pub fn with_filters(mut self, filters: TestFiltersConfig) -> Self {
+        self.filters = filters;
+        self
+    }
+
+    /// Build the `cargo test` command for a filter
+    fn build_test_command(&self, target_dir: &Path, filter: &TestFilter) -> Command {
+        let mut cmd = Command::new("cargo");
+        cmd.current_dir(target_dir)
+            .args(cargo_test_args(filter))
+            .env(
+                libtest::UNSTABLE_OPTIONS_ENV.0,
+                libtest::UNSTABLE_OPTIONS_ENV.1,
+            );
+        cmd
+    }
+
     /// Parse test output to extract metrics
     fn parse_test_output(&self, output: &str) -> Option<TestMetrics> {
         let mut tests_run = 0;
@@ -76,12 +103,19 @@ impl SimpleTestRunner {
             None
         }
     }
-}
 
-#[async_trait]
-impl TestRunner for SimpleTestRunner {
-    async fn run_tests(&self, branch: &str, target_path: Option<&Path>) -> Result<TestResult> {
-        info!("Running tests on branch {} with SimpleTestRunner", branch);
+    /// Run `cargo test` restricted by the given filter
+    fn run_filtered_tests(
+        &self,
+        branch: &str,
+        target_path: Option<&Path>,
+        filter: &TestFilter,
+        stage: &str,
+    ) -> Result<TestResult> {
+        info!(
+            "Running {} tests on branch {} with SimpleTestRunner",
+            stage, branch
+        );
 
         let start_time = Instant::now();
 
@@ -92,10 +126,7 @@ impl TestRunner for SimpleTestRunner {
         };
 
         // Build the command
-        let mut cmd = Command::new("cargo");
-        cmd.current_dir(&target_dir)
-            .arg("test")
-            .arg("--color=always");
+        let mut cmd = self.build_test_command(&target_dir, filter);
 
         // Run the command
         let output = match cmd.output() {
@@ -118,11 +149,25 @@ impl TestRunner for SimpleTestRunner {
         // Determine if tests passed based on exit status
         let success = output.status.success();
 
-        // Parse metrics from output
-        let metrics = self.parse_test_output(&combined_output);
+        let mut result = cargo_test_result(TestResult {
+            success,
+            output: combined_output,
+            duration,
+            metrics: None,
+            report: None,
+            failures: None,
+            compilation_errors: None,
+            exit_code: Some(output.status.code().unwrap_or(-1)),
+            branch: Some(branch.to_string()),
+            test_stage: Some(stage.to_string()),
+            tests: None,
+        });
+        if result.metrics.is_none() {
+            result.metrics = self.parse_test_output(&result.output);
+        }
 
         // Log test summary
-        if let Some(metrics) = &metrics {
+        if let Some(metrics) = &result.metrics {
             info!(
                 "Test results: {} passed, {} failed, {} total",
                 metrics.tests_passed, metrics.tests_failed, metrics.tests_run
@@ -131,18 +176,48 @@ impl TestRunner for SimpleTestRunner {
             warn!("Could not parse test metrics from output");
         }
 
-        Ok(TestResult {
-            success,
-            output: combined_output,
-            duration,
-            metrics,
-            report: None,
-            failures: None,
-            compilation_errors: None,
-            exit_code: Some(output.status.code().unwrap_or(-1)),
-            branch: Some(branch.to_string()),
-            test_stage: Some("unit".to_string()),
-        })
+        Ok(result)
+    }
+}
+
+#[async_trait]
+impl TestRunner for SimpleTestRunner {
+    async fn run_tests(&self, branch: &str, target_path: Option<&Path>) -> Result<TestResult> {
+        self.run_filtered_tests(branch, target_path, &self.filters.iteration, "unit")
+    }
+
+    async fn run_merge_gate_tests(
+        &self,
+        branch: &str,
+        target_path: Option<&Path>,
+    ) -> Result<TestResult> {
+        self.run_filtered_tests(branch, target_path, &self.filters.merge, "merge")
+    }
+
+    async fn run_affected_tests(
+        &self,
+        branch: &str,
+        target_path: Option<&Path>,
+        changed_files: &[String],
+    ) -> Result<TestResult> {
+        let target_dir = target_path.unwrap_or(&self.workspace);
+        let filters = match self.filters.affected {
+            true => affected_test_filters(target_dir, changed_files, &self.filters.iteration),
+            false => None,
+        };
+        let Some(filters) = filters else {
+            return self.run_tests(branch, target_path).await;
+        };
+        let results = filters
+            .iter()
+            .map(|filter| self.run_filtered_tests(branch, target_path, filter, "affected"))
+            .collect::<Result<Vec<_>>>()?;
+        let result = combine_results(results).context("No affected tests to run")?;
+        // Changed code no test exercises is left to the iteration set
+        if result.is_empty_pass() {
+            return self.run_tests(branch, target_path).await;
+        }
+        Ok(result)
     }
 
     async fn run_benchmark(&self, branch: &str, target_path: Option<&Path>) -> Result<TestResult> {
@@ -181,6 +256,105 @@ impl TestRunner for SimpleTestRunner {
             exit_code: Some(exit_code),
             branch: Some(branch.to_string()),
             test_stage: Some("benchmark".to_string()),
+            tests: None,
         })
     }
+
+    async fn run_compile_check(
+        &self,
+        branch: &str,
+        target_path: Option<&Path>,
+    ) -> Result<TestResult> {
+        info!(
+            "Running compile check on branch {} with SimpleTestRunner",
+            branch
+        );
+
+        let start_time = Instant::now();
+        let output = Command::new("cargo")
+            .current_dir(target_path.unwrap_or(&self.workspace))
+            .args(CARGO_CHECK_ARGS)
+            .output()
+            .context("Failed to run cargo check")?;
+
+        let stdout = String::from_utf8_lossy(&output.stdout).to_string();
+        let stderr = String::from_utf8_lossy(&output.stderr).to_string();
+
+        Ok(compile_check_result(TestResult {
+            success: output.status.success(),
+            output: format!("{}\n{}", stdout, stderr),
+            duration: start_time.elapsed(),
+            metrics: None,
+            report: None,
+            failures: None,
+            compilation_errors: None,
+            exit_code: Some(output.status.code().unwrap_or(-1)),
+            branch: Some(branch.to_string()),
+            test_stage: None,
+            tests: None,
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::config::TestTarget;
+
+    #[test]
+    fn test_command_includes_configured_filters() {
+        let filters = TestFiltersConfig {
+            iteration: TestFilter {
+                targets: vec![TestTarget::Lib],
+                test_targets: vec![],
+                include: vec!["core::".to_string()],
+                exclude: vec!["slow_".to_string(), "integration".to_string()],
+            },
+            merge: TestFilter::default(),
+            affected: false,
+        };
+        let runner = SimpleTestRunner::new("/tmp").unwrap().with_filters(filters);
+
+        let cmd = runner.build_test_command(Path::new("/tmp"), &runner.filters.iteration);
+        let args: Vec<_> = cmd.get_args().map(|a| a.to_str().unwrap()).collect();
+        assert_eq!(
+            args,
+            [
+                "test",
+                "--message-format=json",
+                "--lib",
+                "--",
+                "-Z",
+                "unstable-options",
+                "--format",
+                "json",
+                "--report-time",
+                "core::",
+                "--skip",
+                "slow_",
+                "--skip",
+                "integration"
+            ]
+        );
+
+        // The merge gate runs the full suite
+        let cmd = runner.build_test_command(Path::new("/tmp"), &runner.filters.merge);
+        let args: Vec<_> = cmd.get_args().map(|a| a.to_str().unwrap()).collect();
+        assert_eq!(
+            args,
+            [
+                "test",
+                "--message-format=json",
+                "--",
+                "-Z",
+                "unstable-options",
+                "--format",
+                "json",
+                "--report-time"
+            ]
+        );
+        assert!(cmd
+            .get_envs()
+            .any(|(k, v)| k == "RUSTC_BOOTSTRAP" && v.is_some()));
+    }
 }
@@ -0,0 +1,61 @@
+//! Temporary checkouts for measuring other revisions.
+//!
+//! Measurements that compare two revisions run each in a detached
+//! `git worktree` of its own, so neither disturbs the workspace or races
+//! the other on `target/`. The worktree is removed when dropped.
+
+use anyhow::{anyhow, Context, Result};
+use log::warn;
+use std::path::{Path, PathBuf};
+use tokio::process::Command;
+
+/// A detached checkout of one revision, removed on drop
+pub struct Worktree {
+    repo: PathBuf,
+    path: PathBuf,
+}
+
+impl Worktree {
+    /// Check out `revision` of the repository at `repo` in a new
+    /// temporary worktree
+    pub async fn add(repo: &Path, revision: &str) -> Result<Self> {
+        let path = std::env::temp_dir().join(format!("borg-worktree-{}", uuid::Uuid::new_v4()));
+        let added = Command::new("git")
+            .current_dir(repo)
+            .args(["worktree", "add", "--detach"])
+            .arg(&path)
+            .arg(revision)
+            .output()
+            .await
+            .context("Failed to run git worktree")?;
+        if !added.status.success() {
+            return Err(anyhow!(
+                "Failed to check out '{}': {}",
+                revision,
+                String::from_utf8_lossy(&added.stderr).trim()
+            ));
+        }
+        Ok(Self {
+            repo: repo.to_path_buf(),
+            path,
+        })
+    }
+
+    /// Root of the checkout
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for Worktree {
+    fn drop(&mut self) {
+        let removed = std::process::Command::new("git")
+            .current_dir(&self.repo)
+            .args(["worktree", "remove", "--force"])
+            .arg(&self.path)
+            .output();
+        if !matches!(removed, Ok(ref output) if output.status.success()) {
+            warn!("Failed to remove worktree {:?}", self.path);
+        }
+    }
+}
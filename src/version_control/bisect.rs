@@ -0,0 +1,382 @@
+//! Hunting regressions with `git bisect`.
+//!
+//! Given a commit where a check command passes and one where it fails, the
+//! commits in between are bisected in a detached worktree, so the
+//! workspace and its checked-out branch are left alone. Each step builds
+//! and runs the check; exit code 125 skips a commit that cannot be tested,
+//! as with `git bisect run`. The commit found can seed a goal to fix it.
+
+use anyhow::{anyhow, Context, Result};
+use log::{info, warn};
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tokio::process::Command;
+use tokio::time::timeout;
+
+use crate::core::optimization::{OptimizationGoal, PriorityLevel};
+
+/// Exit code of a check that cannot test a commit
+const SKIP_EXIT_CODE: i32 = 125;
+
+/// Output kept of the check at the first bad commit, from its end
+const MAX_OUTPUT_CHARS: usize = 4000;
+
+/// What the check said about a commit
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StepOutcome {
+    /// The check passed
+    Good,
+
+    /// The check failed
+    Bad,
+
+    /// The commit could not be tested
+    Skip,
+}
+
+impl StepOutcome {
+    fn as_str(self) -> &'static str {
+        match self {
+            StepOutcome::Good => "good",
+            StepOutcome::Bad => "bad",
+            StepOutcome::Skip => "skip",
+        }
+    }
+}
+
+/// One tested commit
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BisectStep {
+    /// The commit
+    pub commit: String,
+
+    /// What the check said about it
+    pub outcome: StepOutcome,
+}
+
+/// The commit that introduced a regression
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BisectReport {
+    /// The check that was run
+    pub check: String,
+
+    /// The first commit the check fails at
+    pub culprit: String,
+
+    /// The culprit's subject line
+    pub summary: String,
+
+    /// Files the culprit changed
+    pub files: Vec<String>,
+
+    /// What the check printed at the culprit, cut to its last lines when long
+    pub output: String,
+
+    /// The commits tested, in order
+    pub steps: Vec<BisectStep>,
+}
+
+impl fmt::Display for BisectReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "First bad commit: {} {}", self.culprit, self.summary)?;
+        writeln!(f, "Check: {}", self.check)?;
+        if !self.files.is_empty() {
+            writeln!(f, "Changed files: {}", self.files.join(", "))?;
+        }
+        writeln!(f, "Steps:")?;
+        for step in &self.steps {
+            writeln!(f, "  {} {}", short(&step.commit), step.outcome.as_str())?;
+        }
+        if !self.output.is_empty() {
+            writeln!(
+                f,
+                "Check output at {}:\n{}",
+                short(&self.culprit),
+                self.output
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Bisects a repository with a check command
+#[derive(Debug, Clone)]
+pub struct Bisector {
+    repo: PathBuf,
+    check: String,
+    timeout: Duration,
+}
+
+impl Bisector {
+    /// Bisect the repository at `repo` by running `check` through `sh -c`;
+    /// each run may take up to ten minutes
+    pub fn new(repo: &Path, check: &str) -> Self {
+        Self {
+            repo: repo.to_path_buf(),
+            check: check.to_string(),
+            timeout: Duration::from_secs(600),
+        }
+    }
+
+    /// Give each run of the check at most `timeout`; a run that takes
+    /// longer counts as failing
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Find the first commit between `good`, where the check passes, and
+    /// `bad`, where it fails
+    pub async fn run(&self, good: &str, bad: &str) -> Result<BisectReport> {
+        let good = self.rev_parse(good).await?;
+        let bad = self.rev_parse(bad).await?;
+        let worktree = std::env::temp_dir().join(format!("borg-bisect-{}", uuid::Uuid::new_v4()));
+        git(
+            &self.repo,
+            &["worktree", "add", "--detach", &path_arg(&worktree), &bad],
+        )
+        .await
+        .context("Failed to create the bisect worktree")?;
+
+        let result = self.bisect_in(&worktree, &good, &bad).await;
+
+        // Bisect refs belong to the worktree and go with it
+        if let Err(e) = git(
+            &self.repo,
+            &["worktree", "remove", "--force", &path_arg(&worktree)],
+        )
+        .await
+        {
+            warn!("Failed to remove the bisect worktree {:?}: {}", worktree, e);
+        }
+        result
+    }
+
+    async fn bisect_in(&self, worktree: &Path, good: &str, bad: &str) -> Result<BisectReport> {
+        if self.check_at(worktree, bad).await?.0 != StepOutcome::Bad {
+            return Err(anyhow!(
+                "The check passes at {}; nothing to bisect",
+                short(bad)
+            ));
+        }
+        if self.check_at(worktree, good).await?.0 != StepOutcome::Good {
+            return Err(anyhow!(
+                "The check does not pass at {}; pick an earlier good commit",
+                short(good)
+            ));
+        }
+
+        info!(
+            "Bisecting {}..{} with `{}`",
+            short(good),
+            short(bad),
+            self.check
+        );
+        let mut reply = git(worktree, &["bisect", "start", bad, good]).await?;
+        let mut steps = Vec::new();
+        let mut last_bad: Option<(String, String)> = None;
+        loop {
+            if let Some(culprit) = first_bad_commit(&reply) {
+                let summary = git(worktree, &["log", "-1", "--format=%s", &culprit]).await?;
+                let files = git(worktree, &["show", "--name-only", "--format=", &culprit]).await?;
+                let output = match last_bad {
+                    Some((commit, output)) if commit == culprit => output,
+                    _ => self.check_at(worktree, &culprit).await?.1,
+                };
+                info!("Bisect found {}: {}", short(&culprit), summary.trim());
+                return Ok(BisectReport {
+                    check: self.check.clone(),
+                    culprit,
+                    summary: summary.trim().to_string(),
+                    files: files
+                        .lines()
+                        .filter(|line| !line.is_empty())
+                        .map(str::to_string)
+                        .collect(),
+                    output,
+                    steps,
+                });
+            }
+            if reply.contains("only 'skip'ped commits left") {
+                return Err(anyhow!(
+                    "Bisect could not narrow down the first bad commit:\n{}",
+                    reply.trim()
+                ));
+            }
+
+            let commit = git(worktree, &["rev-parse", "HEAD"]).await?;
+            let commit = commit.trim().to_string();
+            let (outcome, output) = self.run_check(worktree).await?;
+            info!("Bisect step {}: {}", short(&commit), outcome.as_str());
+            if outcome == StepOutcome::Bad {
+                last_bad = Some((commit.clone(), output));
+            }
+            reply = git(worktree, &["bisect", outcome.as_str()]).await?;
+            steps.push(BisectStep { commit, outcome });
+        }
+    }
+
+    /// Check out `commit` in `worktree` and run the check there
+    async fn check_at(&self, worktree: &Path, commit: &str) -> Result<(StepOutcome, String)> {
+        git(worktree, &["checkout", "--detach", "--quiet", commit]).await?;
+        self.run_check(worktree).await
+    }
+
+    async fn run_check(&self, worktree: &Path) -> Result<(StepOutcome, String)> {
+        let mut command = Command::new("sh");
+        command
+            .arg("-c")
+            .arg(&self.check)
+            .current_dir(worktree)
+            // Steps share one build directory instead of starting cold
+            .env("CARGO_TARGET_DIR", self.repo.join("target").join("bisect"))
+            .kill_on_drop(true);
+        let output = match timeout(self.timeout, command.output()).await {
+            Ok(output) => output.with_context(|| format!("Failed to run `{}`", self.check))?,
+            Err(_) => {
+                return Ok((
+                    StepOutcome::Bad,
+                    format!("timed out after {} seconds", self.timeout.as_secs()),
+                ))
+            }
+        };
+        let outcome = match output.status.code() {
+            Some(0) => StepOutcome::Good,
+            Some(SKIP_EXIT_CODE) => StepOutcome::Skip,
+            _ => StepOutcome::Bad,
+        };
+        let printed = format!(
+            "{}{}",
+            String::from_utf8_lossy(&output.stdout),
+            String::from_utf8_lossy(&output.stderr)
+        );
+        Ok((outcome, tail(printed.trim(), MAX_OUTPUT_CHARS)))
+    }
+
+    async fn rev_parse(&self, revision: &str) -> Result<String> {
+        let commit = git(
+            &self.repo,
+            &["rev-parse", "--verify", &format!("{}^{{commit}}", revision)],
+        )
+        .await
+        .with_context(|| format!("Unknown revision: {}", revision))?;
+        Ok(commit.trim().to_string())
+    }
+}
+
+/// A goal to fix the regression `report` found
+pub fn fix_goal(report: &BisectReport) -> OptimizationGoal {
+    let commit = short(&report.culprit);
+    let mut goal = OptimizationGoal::new(
+        &format!("fix-regression-{}", commit),
+        &format!("Fix regression from {}: {}", commit, report.summary),
+        &format!(
+            "`{}` started failing at commit {} ({}). Make it pass again without \
+             undoing what that commit was for.\n\nFailure:\n{}",
+            report.check, report.culprit, report.summary, report.output
+        ),
+    );
+    goal.priority = PriorityLevel::High.into();
+    goal.tags = vec!["regression".to_string()];
+    goal.test_results = Some(report.output.clone());
+    goal.implementation_notes = Some(format!(
+        "Found by bisect; {} changed {}",
+        commit,
+        report.files.join(", ")
+    ));
+    goal
+}
+
+/// The commit `git bisect` reports as the first bad one, if it is done
+fn first_bad_commit(reply: &str) -> Option<String> {
+    reply.lines().find_map(|line| {
+        line.strip_suffix(" is the first bad commit")
+            .map(|commit| commit.trim().to_string())
+    })
+}
+
+async fn git(dir: &Path, args: &[&str]) -> Result<String> {
+    let output = Command::new("git")
+        .args(args)
+        .current_dir(dir)
+        .output()
+        .await
+        .with_context(|| format!("Failed to run git {}", args.join(" ")))?;
+    if !output.status.success() {
+        return Err(anyhow!(
+            "git {} failed: {}",
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).to_string())
+}
+
+fn path_arg(path: &Path) -> String {
+    path.to_string_lossy().to_string()
+}
+
+fn short(commit: &str) -> &str {
+    &commit[..commit.len().min(8)]
+}
+
+/// The last `max` characters of `text`
+fn tail(text: &str, max: usize) -> String {
+    let count = text.chars().count();
+    if count <= max {
+        return text.to_string();
+    }
+    text.chars().skip(count - max).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    fn run_git(dir: &Path, args: &[&str]) -> String {
+        let output = std::process::Command::new("git")
+            .args(args)
+            .current_dir(dir)
+            .output()
+            .unwrap();
+        assert!(output.status.success(), "git {:?} failed", args);
+        String::from_utf8_lossy(&output.stdout).trim().to_string()
+    }
+
+    #[tokio::test]
+    async fn test_bisect_finds_the_commit_that_breaks_the_check() {
+        let repo = tempfile::tempdir().unwrap();
+        let path = repo.path();
+        run_git(path, &["init", "-b", "master"]);
+        run_git(path, &["config", "user.name", "Test"]);
+        run_git(path, &["config", "user.email", "test@example.com"]);
+        let mut commits = Vec::new();
+        for i in 0..8 {
+            let value = if i < 5 { "ok" } else { "broken" };
+            fs::write(path.join("state"), value).unwrap();
+            fs::write(path.join(format!("file{}", i)), "x").unwrap();
+            run_git(path, &["add", "."]);
+            run_git(path, &["commit", "-m", &format!("Change {}", i)]);
+            commits.push(run_git(path, &["rev-parse", "HEAD"]));
+        }
+
+        let bisector = Bisector::new(path, "grep -qx ok state");
+        let report = bisector.run(&commits[0], "master").await.unwrap();
+
+        assert_eq!(report.culprit, commits[5]);
+        assert_eq!(report.summary, "Change 5");
+        assert!(report.files.contains(&"state".to_string()));
+        assert!(!report.steps.is_empty());
+        assert_eq!(run_git(path, &["worktree", "list"]).lines().count(), 1);
+
+        let goal = fix_goal(&report);
+        assert_eq!(goal.id, format!("fix-regression-{}", &commits[5][..8]));
+        assert!(goal.description.contains("grep -qx ok state"));
+        assert!(goal.implementation_notes.unwrap().contains("state"));
+
+        let err = bisector.run(&commits[0], &commits[3]).await.unwrap_err();
+        assert!(err.to_string().contains("nothing to bisect"));
+    }
+}
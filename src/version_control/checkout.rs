@@ -0,0 +1,170 @@
+//! Configurable working-tree checkout.
+//!
+//! git2's default `checkout_tree` options fail with a cryptic conflict error
+//! (or silently skip files) when untracked files would be overwritten. This
+//! helper applies the configured safe/force behavior explicitly and reports
+//! which paths blocked the checkout.
+
+use anyhow::Result;
+use git2::build::CheckoutBuilder;
+use git2::{CheckoutNotificationType, Object, Repository};
+use std::cell::RefCell;
+
+use crate::core::config::{CheckoutConfig, CheckoutMode, ConflictStyle};
+use crate::core::error::BorgError;
+
+/// Check out `target` into the working tree using the configured options
+pub fn checkout_tree(repo: &Repository, target: &Object, config: &CheckoutConfig) -> Result<()> {
+    let conflicts: RefCell<Vec<String>> = RefCell::new(Vec::new());
+
+    let result = {
+        let mut builder = CheckoutBuilder::new();
+        match config.mode {
+            CheckoutMode::Safe => builder.safe(),
+            CheckoutMode::Force => builder.force(),
+        };
+        builder.remove_untracked(config.remove_untracked);
+        for path in &config.paths {
+            builder.path(path);
+        }
+        match config.conflict_style {
+            Some(ConflictStyle::Merge) => builder.conflict_style_merge(true),
+            Some(ConflictStyle::Diff3) => builder.conflict_style_diff3(true),
+            None => &mut builder,
+        };
+        builder.notify_on(CheckoutNotificationType::CONFLICT);
+        builder.notify(|_, path, _, _, _| {
+            if let Some(path) = path {
+                conflicts
+                    .borrow_mut()
+                    .push(path.to_string_lossy().to_string());
+            }
+            true
+        });
+
+        repo.checkout_tree(target, Some(&mut builder))
+    };
+
+    match result {
+        Ok(()) => Ok(()),
+        Err(e) => {
+            let conflicts = conflicts.into_inner();
+            if conflicts.is_empty() {
+                Err(anyhow::anyhow!(BorgError::GitError(format!(
+                    "Checkout failed: {}",
+                    e.message()
+                ))))
+            } else {
+                Err(anyhow::anyhow!(BorgError::GitError(format!(
+                    "Checkout blocked by {} working-tree file(s) that would be overwritten: {}. \
+                     Commit or remove them, or set git.checkout.mode to \"force\"",
+                    conflicts.len(),
+                    conflicts.join(", ")
+                ))))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use git2::Signature;
+    use std::fs;
+    use std::path::Path;
+    use tempfile::TempDir;
+
+    fn commit_file(repo: &Repository, name: &str, content: &str, message: &str) {
+        let root = repo.workdir().unwrap().to_path_buf();
+        fs::write(root.join(name), content).unwrap();
+        let mut index = repo.index().unwrap();
+        index.add_path(Path::new(name)).unwrap();
+        index.write().unwrap();
+        let tree = repo.find_tree(index.write_tree().unwrap()).unwrap();
+        let sig = Signature::now("Test", "test@example.com").unwrap();
+        let parents: Vec<git2::Commit> = repo
+            .head()
+            .ok()
+            .and_then(|h| h.peel_to_commit().ok())
+            .into_iter()
+            .collect();
+        let parent_refs: Vec<&git2::Commit> = parents.iter().collect();
+        repo.commit(Some("HEAD"), &sig, &sig, message, &tree, &parent_refs)
+            .unwrap();
+    }
+
+    /// Repo on the mainline with an untracked `feature.txt` that conflicts
+    /// with the committed file on the `feature` branch
+    fn repo_with_untracked_conflict() -> (TempDir, Repository, String) {
+        let dir = TempDir::new().unwrap();
+        let repo = Repository::init(dir.path()).unwrap();
+        commit_file(&repo, "README.md", "readme\n", "Initial commit");
+        let mainline = repo.head().unwrap().name().unwrap().to_string();
+
+        {
+            let head = repo.head().unwrap().peel_to_commit().unwrap();
+            repo.branch("feature", &head, false).unwrap();
+        }
+        repo.set_head("refs/heads/feature").unwrap();
+        commit_file(&repo, "feature.txt", "committed\n", "Add feature");
+
+        {
+            let main_obj = repo.revparse_single(&mainline).unwrap();
+            repo.checkout_tree(&main_obj, Some(CheckoutBuilder::new().force()))
+                .unwrap();
+        }
+        repo.set_head(&mainline).unwrap();
+
+        fs::write(dir.path().join("feature.txt"), "untracked\n").unwrap();
+        (dir, repo, mainline)
+    }
+
+    #[test]
+    fn test_safe_checkout_reports_untracked_conflict() {
+        let (dir, repo, _) = repo_with_untracked_conflict();
+        let target = repo.revparse_single("refs/heads/feature").unwrap();
+
+        let err = checkout_tree(&repo, &target, &CheckoutConfig::default()).unwrap_err();
+        let message = err.to_string();
+        assert!(message.contains("Checkout blocked"), "{}", message);
+        assert!(message.contains("feature.txt"), "{}", message);
+
+        // The untracked file is left untouched
+        let content = fs::read_to_string(dir.path().join("feature.txt")).unwrap();
+        assert_eq!(content, "untracked\n");
+    }
+
+    #[test]
+    fn test_force_checkout_overwrites_untracked_file() {
+        let (dir, repo, _) = repo_with_untracked_conflict();
+        let target = repo.revparse_single("refs/heads/feature").unwrap();
+        let config = CheckoutConfig {
+            mode: CheckoutMode::Force,
+            remove_untracked: false,
+            ..CheckoutConfig::default()
+        };
+
+        checkout_tree(&repo, &target, &config).unwrap();
+
+        let content = fs::read_to_string(dir.path().join("feature.txt")).unwrap();
+        assert_eq!(content, "committed\n");
+    }
+
+    #[test]
+    fn test_remove_untracked_cleans_working_tree() {
+        let (dir, repo, mainline) = repo_with_untracked_conflict();
+        fs::remove_file(dir.path().join("feature.txt")).unwrap();
+        fs::write(dir.path().join("scratch.txt"), "junk\n").unwrap();
+        let target = repo.revparse_single(&mainline).unwrap();
+        let config = CheckoutConfig {
+            mode: CheckoutMode::Safe,
+            remove_untracked: true,
+            ..CheckoutConfig::default()
+        };
+
+        checkout_tree(&repo, &target, &config).unwrap();
+
+        assert!(!dir.path().join("scratch.txt").exists());
+        assert!(dir.path().join("README.md").exists());
+    }
+}
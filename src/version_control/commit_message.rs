@@ -0,0 +1,315 @@
+//! Commit message policy.
+//!
+//! Models decorate commit messages with quotes, fences and labels, run long
+//! subjects and forget conventions. Every generated message goes through
+//! [`enforce`], which cleans it up, rewrites the subject to the configured
+//! style and length and adds the configured trailers.
+
+use regex::Regex;
+use std::path::Path;
+
+use crate::code_generation::context_builder::is_test_file;
+use crate::core::config::{CommitMessageConfig, CommitStyle, CommitTrailer};
+use crate::core::optimization::OptimizationCategory;
+use crate::version_control::trailers::append_trailers;
+
+/// Types a Conventional Commits subject may start with
+const CONVENTIONAL_TYPES: &[&str] = &[
+    "feat", "fix", "perf", "refactor", "test", "docs", "style", "build", "ci", "chore", "revert",
+];
+
+/// What is known about the change being committed
+#[derive(Debug, Clone)]
+pub struct CommitDetails {
+    /// The goal the change works on
+    pub goal_id: String,
+
+    /// Subject used when the model returns nothing usable
+    pub goal_title: String,
+
+    /// Category of the goal, used to infer the commit type
+    pub category: OptimizationCategory,
+
+    /// Number of the attempt that produced the change, when known
+    pub attempt: Option<u32>,
+
+    /// Paths of the changed files
+    pub files: Vec<String>,
+}
+
+/// `message` corrected to follow `config`
+pub fn enforce(message: &str, config: &CommitMessageConfig, details: &CommitDetails) -> String {
+    let message = clean(message);
+    let (subject, body) = match message.split_once('\n') {
+        Some((subject, body)) => (subject.trim().to_string(), body.trim().to_string()),
+        None => (message.trim().to_string(), String::new()),
+    };
+    let subject = match subject.is_empty() {
+        true => details.goal_title.trim().to_string(),
+        false => subject,
+    };
+    let subject = match config.style {
+        CommitStyle::Free => subject,
+        CommitStyle::Conventional => conventional_subject(&subject, details),
+    };
+
+    // A subject that had to be shortened is kept whole in the body
+    let short = shorten(&subject, config.max_subject_length);
+    let body = match (short != subject, body.is_empty()) {
+        (true, true) => subject.clone(),
+        (true, false) => format!("{}\n\n{}", subject, body),
+        (false, _) => body,
+    };
+    let message = match body.is_empty() {
+        true => format!("{}\n", short),
+        false => format!("{}\n\n{}\n", short, body),
+    };
+
+    let trailers: Vec<String> = config
+        .trailers
+        .iter()
+        .filter_map(|trailer| match trailer {
+            CommitTrailer::GoalId => Some(format!("Goal-Id: {}", details.goal_id)),
+            CommitTrailer::Attempt => details
+                .attempt
+                .map(|attempt| format!("Borg-Attempt: {}", attempt)),
+        })
+        .collect();
+    append_trailers(&message, &trailers)
+}
+
+/// `message` without the fences, quotes, labels and markdown models wrap
+/// around it, with a blank line after the subject
+fn clean(message: &str) -> String {
+    let fence_re = Regex::new(r"```[\w-]*[ \t]*\r?\n([\s\S]*?)```").unwrap();
+    let label_re =
+        Regex::new(r"(?i)^\s*(?:#+\s*)?(?:\*\*)?(?:commit message|subject)(?:\*\*)?:\s*").unwrap();
+    // Only the fenced block counts when the model talks around it
+    let message = match fence_re.captures(message) {
+        Some(cap) => cap.get(1).map_or("", |m| m.as_str()),
+        None => message,
+    };
+    let mut lines: Vec<&str> = message
+        .trim()
+        .lines()
+        .filter(|line| !line.trim_start().starts_with("```"))
+        .map(str::trim_end)
+        .skip_while(|line| line.trim().is_empty())
+        .collect();
+    while lines.last().is_some_and(|line| line.trim().is_empty()) {
+        lines.pop();
+    }
+    let Some(first) = lines.first() else {
+        return String::new();
+    };
+
+    let subject = label_re.replace(first, "");
+    let subject = subject
+        .trim_start_matches('#')
+        .trim()
+        .trim_matches(|c| c == '"' || c == '\'' || c == '`')
+        .trim()
+        .to_string();
+    let body = lines[1..].join("\n");
+    match body.trim().is_empty() {
+        true => subject,
+        false => format!("{}\n\n{}", subject, body.trim_start_matches('\n')),
+    }
+}
+
+/// `subject` as `type(scope): description`, keeping a type the model chose
+fn conventional_subject(subject: &str, details: &CommitDetails) -> String {
+    let re = Regex::new(r"^(\w+)(\([^)]*\))?(!)?:\s*(.+)$").unwrap();
+    if let Some(cap) = re.captures(subject) {
+        let kind = cap[1].to_lowercase();
+        if CONVENTIONAL_TYPES.contains(&kind.as_str()) {
+            return format!(
+                "{}{}{}: {}",
+                kind,
+                cap.get(2).map_or("", |m| m.as_str()),
+                cap.get(3).map_or("", |m| m.as_str()),
+                cap[4].trim_end_matches('.')
+            );
+        }
+    }
+
+    let description = subject.trim_end_matches('.');
+    // "Add cache" becomes "add cache", but "HTTP client" keeps its case
+    let mut chars = description.chars();
+    let description = match (chars.next(), chars.next()) {
+        (Some(first), Some(second)) if first.is_uppercase() && second.is_lowercase() => first
+            .to_lowercase()
+            .chain(description.chars().skip(1))
+            .collect(),
+        _ => description.to_string(),
+    };
+    match infer_scope(&details.files) {
+        Some(scope) => format!(
+            "{}({}): {}",
+            infer_type(subject, details),
+            scope,
+            description
+        ),
+        None => format!("{}: {}", infer_type(subject, details), description),
+    }
+}
+
+/// The Conventional Commits type of a change, from the files it touches,
+/// the verb its subject starts with and the category of its goal
+pub fn infer_type(subject: &str, details: &CommitDetails) -> &'static str {
+    let files = &details.files;
+    if !files.is_empty() && files.iter().all(|file| is_test_file(file)) {
+        return "test";
+    }
+    let is_doc = |file: &String| file.ends_with(".md") || file.starts_with("docs/");
+    if !files.is_empty() && files.iter().all(is_doc) {
+        return "docs";
+    }
+
+    let verb = subject
+        .split_whitespace()
+        .next()
+        .unwrap_or_default()
+        .to_lowercase();
+    match verb.as_str() {
+        "fix" | "fixes" | "fixed" | "correct" | "handle" | "prevent" => return "fix",
+        "add" | "adds" | "implement" | "support" | "introduce" | "allow" => return "feat",
+        "refactor" | "simplify" | "extract" | "rename" | "move" | "split" => return "refactor",
+        "speed" | "optimize" | "optimise" | "cache" => return "perf",
+        "test" | "cover" => return "test",
+        "document" | "doc" | "docs" => return "docs",
+        _ => {}
+    }
+
+    match details.category {
+        OptimizationCategory::Performance => "perf",
+        OptimizationCategory::Readability | OptimizationCategory::Complexity => "refactor",
+        OptimizationCategory::TestCoverage => "test",
+        OptimizationCategory::Security | OptimizationCategory::ErrorHandling => "fix",
+        _ => "chore",
+    }
+}
+
+/// The module every changed file belongs to: the directory under `src/`
+/// (or the file stem of a file directly in it), else the top directory
+pub fn infer_scope(files: &[String]) -> Option<String> {
+    let scope_of = |file: &String| -> Option<String> {
+        let components: Vec<String> = Path::new(file)
+            .components()
+            .map(|c| c.as_os_str().to_string_lossy().to_string())
+            .collect();
+        match components.as_slice() {
+            [src, file] if src == "src" => Path::new(file)
+                .file_stem()
+                .map(|stem| stem.to_string_lossy().to_string()),
+            [src, dir, ..] if src == "src" => Some(dir.clone()),
+            [dir, _, ..] => Some(dir.clone()),
+            _ => None,
+        }
+    };
+    let mut scopes = files.iter().map(scope_of);
+    let first = scopes.next()??;
+    scopes
+        .all(|scope| scope.as_deref() == Some(first.as_str()))
+        .then_some(first.to_lowercase())
+}
+
+/// `subject` cut at the last word that fits in `max` characters
+fn shorten(subject: &str, max: usize) -> String {
+    if max == 0 || subject.chars().count() <= max {
+        return subject.to_string();
+    }
+    let cut: String = subject.chars().take(max).collect();
+    let next_is_space = subject.chars().nth(max).is_some_and(char::is_whitespace);
+    let cut = match (next_is_space, cut.rfind(char::is_whitespace)) {
+        (true, _) => cut.as_str(),
+        (false, Some(space)) if space > 0 => &cut[..space],
+        (false, _) => cut.as_str(),
+    };
+    cut.trim_end_matches(|c: char| c.is_whitespace() || ",;:-".contains(c))
+        .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn details(files: &[&str]) -> CommitDetails {
+        CommitDetails {
+            goal_id: "goal-7".to_string(),
+            goal_title: "Speed up parsing".to_string(),
+            category: OptimizationCategory::General,
+            attempt: Some(2),
+            files: files.iter().map(|f| f.to_string()).collect(),
+        }
+    }
+
+    fn conventional() -> CommitMessageConfig {
+        CommitMessageConfig {
+            style: CommitStyle::Conventional,
+            max_subject_length: 50,
+            trailers: vec![CommitTrailer::GoalId, CommitTrailer::Attempt],
+        }
+    }
+
+    #[test]
+    fn test_messages_are_cleaned_and_trailers_added() {
+        let message = "Here is the message:\n```\nCommit message: \"Fix overflow in tokenizer.\"\n\
+                       The length was added unchecked.\n```\n";
+        let enforced = enforce(
+            message,
+            &conventional(),
+            &details(&["src/parser/tokenizer.rs", "src/parser/mod.rs"]),
+        );
+        assert_eq!(
+            enforced,
+            "fix(parser): fix overflow in tokenizer\n\n\
+             The length was added unchecked.\n\n\
+             Goal-Id: goal-7\nBorg-Attempt: 2\n"
+        );
+    }
+
+    #[test]
+    fn test_conventional_subjects_keep_the_models_type() {
+        let config = conventional();
+        let enforced = enforce("Perf(lexer)!: Avoid copies", &config, &details(&["a.rs"]));
+        assert!(enforced.starts_with("perf(lexer)!: Avoid copies\n\n"));
+
+        let enforced = enforce("HTTP client retries", &config, &details(&["src/main.rs"]));
+        assert!(enforced.starts_with("chore(main): HTTP client retries\n"));
+
+        let enforced = enforce(
+            "Cover the edge cases",
+            &config,
+            &details(&["tests/parse.rs"]),
+        );
+        assert!(enforced.starts_with("test(tests): cover the edge cases\n"));
+    }
+
+    #[test]
+    fn test_long_subjects_are_cut_at_a_word_and_kept_in_the_body() {
+        let config = CommitMessageConfig {
+            trailers: Vec::new(),
+            ..conventional()
+        };
+        let enforced = enforce(
+            "Add a configurable retry policy to every outgoing provider request",
+            &config,
+            &details(&["src/providers/retry.rs", "src/core/config.rs"]),
+        );
+        assert_eq!(
+            enforced,
+            "feat: add a configurable retry policy to every\n\n\
+             feat: add a configurable retry policy to every outgoing provider request\n"
+        );
+
+        // Free style leaves the subject alone, and an empty answer falls
+        // back to the goal's title
+        let free = CommitMessageConfig::default();
+        assert_eq!(enforce("Tidy up\n", &free, &details(&[])), "Tidy up\n");
+        assert_eq!(
+            enforce("```\n```", &free, &details(&[])),
+            "Speed up parsing\n"
+        );
+    }
+}
@@ -0,0 +1,133 @@
+//! `git diff --stat`-style summaries computed with libgit2.
+
+use anyhow::{Context, Result};
+use git2::Repository;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// Files changed / insertions / deletions for a set of changes
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DiffStat {
+    /// Number of files changed
+    pub files_changed: usize,
+
+    /// Number of inserted lines
+    pub insertions: usize,
+
+    /// Number of deleted lines
+    pub deletions: usize,
+}
+
+impl DiffStat {
+    /// Compute the stat of the staged index against the HEAD tree
+    pub fn staged(repo: &Repository) -> Result<Self> {
+        let head_tree = match repo.head() {
+            Ok(head) => Some(head.peel_to_tree().context("Failed to peel HEAD to tree")?),
+            // Unborn branch: everything in the index is new
+            Err(_) => None,
+        };
+        let index = repo.index().context("Failed to get repository index")?;
+
+        let diff = repo
+            .diff_tree_to_index(head_tree.as_ref(), Some(&index), None)
+            .context("Failed to diff HEAD against index")?;
+        let stats = diff.stats().context("Failed to compute diff stats")?;
+
+        Ok(Self {
+            files_changed: stats.files_changed(),
+            insertions: stats.insertions(),
+            deletions: stats.deletions(),
+        })
+    }
+}
+
+impl fmt::Display for DiffStat {
+    /// Formats like the summary line of `git diff --stat`
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let plural =
+            |n: usize, one: &'static str, many: &'static str| if n == 1 { one } else { many };
+
+        write!(
+            f,
+            "{} {} changed",
+            self.files_changed,
+            plural(self.files_changed, "file", "files")
+        )?;
+        if self.insertions > 0 || self.deletions == 0 {
+            write!(
+                f,
+                ", {} {}(+)",
+                self.insertions,
+                plural(self.insertions, "insertion", "insertions")
+            )?;
+        }
+        if self.deletions > 0 || self.insertions == 0 {
+            write!(
+                f,
+                ", {} {}(-)",
+                self.deletions,
+                plural(self.deletions, "deletion", "deletions")
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use git2::Signature;
+    use std::fs;
+    use std::path::Path;
+    use tempfile::TempDir;
+
+    fn stage(repo: &Repository, name: &str) {
+        let mut index = repo.index().unwrap();
+        index.add_path(Path::new(name)).unwrap();
+        index.write().unwrap();
+    }
+
+    #[test]
+    fn test_staged_stat_matches_known_change() {
+        let dir = TempDir::new().unwrap();
+        let repo = Repository::init(dir.path()).unwrap();
+
+        fs::write(dir.path().join("lib.rs"), "one\ntwo\nthree\n").unwrap();
+        stage(&repo, "lib.rs");
+        let mut index = repo.index().unwrap();
+        let tree = repo.find_tree(index.write_tree().unwrap()).unwrap();
+        let sig = Signature::now("Test", "test@example.com").unwrap();
+        repo.commit(Some("HEAD"), &sig, &sig, "Initial", &tree, &[])
+            .unwrap();
+
+        // Replace one line and append two; add a new two-line file
+        fs::write(dir.path().join("lib.rs"), "one\nTWO\nthree\nfour\nfive\n").unwrap();
+        fs::write(dir.path().join("new.rs"), "a\nb\n").unwrap();
+        stage(&repo, "lib.rs");
+        stage(&repo, "new.rs");
+
+        let stat = DiffStat::staged(&repo).unwrap();
+        assert_eq!(
+            stat,
+            DiffStat {
+                files_changed: 2,
+                insertions: 5,
+                deletions: 1,
+            }
+        );
+        assert_eq!(
+            stat.to_string(),
+            "2 files changed, 5 insertions(+), 1 deletion(-)"
+        );
+    }
+
+    #[test]
+    fn test_display_omits_zero_side() {
+        let stat = DiffStat {
+            files_changed: 1,
+            insertions: 1,
+            deletions: 0,
+        };
+        assert_eq!(stat.to_string(), "1 file changed, 1 insertion(+)");
+    }
+}
@@ -4,7 +4,11 @@ use git2::{BranchType, ObjectType, Repository, Signature};
 use log::{debug, info};
 use std::path::{Path, PathBuf};
 
+use crate::core::config::CheckoutConfig;
 use crate::core::error::BorgError;
+use crate::version_control::diff_stat::DiffStat;
+use crate::version_control::repository::{self, BranchSync};
+use crate::version_control::signing::{commit_on_head, CommitSigner};
 
 /// Git manager trait for version control operations
 #[async_trait]
@@ -51,6 +55,90 @@ pub trait GitManager: Send + Sync {
 
     /// List all active worktrees (returns paths to worktree directories)
     async fn list_worktrees(&self) -> Result<Vec<PathBuf>>;
+
+    /// Name of the mainline branch: `master` if present, else `main`
+    async fn mainline_branch(&self) -> Result<String>;
+
+    /// Check out a branch, creating it from HEAD first when it does not
+    /// exist, with `checkout` deciding what happens to conflicting files;
+    /// returns whether the branch was created
+    async fn switch_branch(&self, branch_name: &str, checkout: &CheckoutConfig) -> Result<bool>;
+
+    /// Read a file as of a revision; `None` when it is not part of it
+    async fn file_at_revision(&self, revision: &str, file_path: &str) -> Result<Option<String>>;
+
+    /// Size of the staged change
+    async fn staged_diff_stat(&self) -> Result<DiffStat>;
+
+    /// Files the working tree changes relative to a revision, untracked
+    /// files included
+    async fn changed_files(&self, base: &str) -> Result<Vec<String>>;
+
+    /// Subject lines of the commits on `to_branch` that are not on `from_branch`
+    async fn commit_summaries(&self, from_branch: &str, to_branch: &str) -> Result<Vec<String>>;
+
+    /// Check out `target` and merge a branch into it with a merge commit
+    /// carrying `message`; returns the merge commit, `None` when the branch
+    /// was already merged
+    async fn merge_into(
+        &self,
+        branch_name: &str,
+        target: &str,
+        message: &str,
+        checkout: &CheckoutConfig,
+    ) -> Result<Option<String>>;
+
+    /// Commit the revert of a commit (against its first parent for merges)
+    /// on the current branch; returns the revert commit
+    async fn revert_commit(&self, commit: &str, message: &str) -> Result<String>;
+
+    /// Create a branch at `start_point`, a branch or commit, without
+    /// checking it out
+    async fn create_branch_from(&self, branch_name: &str, start_point: &str) -> Result<()>;
+
+    /// Whether the tip of a branch is part of `target`
+    async fn is_merged(&self, branch_name: &str, target: &str) -> Result<bool>;
+
+    /// Replay the commits of a branch that are not on `upstream` onto `onto`
+    /// and move the branch there, checking it out again when it is checked
+    /// out; returns the new tip
+    async fn restack_branch(
+        &self,
+        branch_name: &str,
+        upstream: &str,
+        onto: &str,
+        checkout: &CheckoutConfig,
+    ) -> Result<String>;
+
+    /// Fetch the branches of a remote, authenticating with `token` when given
+    async fn fetch(&self, remote: &str, token: Option<&str>) -> Result<()>;
+
+    /// Fast-forward a branch to its counterpart on `remote` when it is
+    /// behind and has nothing of its own
+    async fn fast_forward(
+        &self,
+        branch_name: &str,
+        remote: &str,
+        checkout: &CheckoutConfig,
+    ) -> Result<BranchSync>;
+
+    /// URL of a remote
+    async fn remote_url(&self, remote: &str) -> Result<String>;
+
+    /// Push a branch to a remote, authenticating with `token` when given;
+    /// unless `force` is set, the remote must not have commits the branch
+    /// lacks
+    async fn push_branch(
+        &self,
+        remote: &str,
+        branch_name: &str,
+        force: bool,
+        token: Option<&str>,
+    ) -> Result<()>;
+
+    /// A manager of the checkout at `path`, the repository itself or one of
+    /// its worktrees
+    fn checkout_at(&self, path: &Path) -> Box<dyn GitManager>;
 }
 
 /// Git manager implementation using libgit2
@@ -63,6 +151,9 @@ pub struct LibGitManager {
 
     /// Author email for commits
     author_email: String,
+
+    /// Signs the commits, when signing is configured
+    signer: Option<CommitSigner>,
 }
 
 impl LibGitManager {
@@ -72,9 +163,16 @@ impl LibGitManager {
             repo_path: repo_path.as_ref().to_path_buf(),
             author_name: author_name.to_string(),
             author_email: author_email.to_string(),
+            signer: None,
         }
     }
 
+    /// Sign the commits with `signer`
+    pub fn with_signer(mut self, signer: Option<CommitSigner>) -> Self {
+        self.signer = signer;
+        self
+    }
+
     /// Open the repository
     fn open_repo(&self) -> Result<Repository> {
         let repo = Repository::open(&self.repo_path)
@@ -202,16 +300,15 @@ impl GitManager for LibGitManager {
 
         let parent_commits_refs: Vec<&git2::Commit> = parent_commits.iter().collect();
 
-        let commit_oid = repo
-            .commit(
-                Some("HEAD"),
-                &sig,
-                &sig,
-                message,
-                &tree,
-                &parent_commits_refs,
-            )
-            .context("Failed to create commit")?;
+        let commit_oid = commit_on_head(
+            &repo,
+            &sig,
+            message,
+            &tree,
+            &parent_commits_refs,
+            self.signer.as_ref(),
+        )
+        .context("Failed to create commit")?;
 
         info!("Created commit: {}", commit_oid);
         Ok(commit_oid.to_string())
@@ -309,13 +406,13 @@ impl GitManager for LibGitManager {
             let head_commit = repo.head()?.peel_to_commit()?;
             let branch_commit = repo.find_commit(branch_ref.target().unwrap())?;
 
-            repo.commit(
-                Some("HEAD"),
-                &sig,
+            commit_on_head(
+                &repo,
                 &sig,
                 &format!("Merge branch '{}'", branch_name),
                 &tree,
                 &[&head_commit, &branch_commit],
+                self.signer.as_ref(),
             )?;
 
             // Clean up merge state
@@ -463,6 +560,22 @@ impl GitManager for LibGitManager {
             ))));
         }
 
+        // Worktrees are named after their directory, which is how
+        // remove_worktree finds them again
+        let name = path.file_name().and_then(|n| n.to_str()).ok_or_else(|| {
+            anyhow::anyhow!(BorgError::GitError(format!(
+                "Invalid worktree path: {:?}",
+                path
+            )))
+        })?;
+
+        // A worktree whose directory was deleted is still registered
+        if let Ok(stale) = repo.find_worktree(name) {
+            stale
+                .prune(Some(git2::WorktreePruneOptions::new().valid(true)))
+                .with_context(|| format!("Failed to prune stale worktree: {}", name))?;
+        }
+
         // Check if the branch exists
         let branch_exists = repo.find_branch(branch, BranchType::Local).is_ok();
 
@@ -472,7 +585,7 @@ impl GitManager for LibGitManager {
 
             // Use git2's worktree API to add a new worktree
             repo.worktree(
-                branch,
+                name,
                 path,
                 Some(
                     git2::WorktreeAddOptions::new()
@@ -508,7 +621,7 @@ impl GitManager for LibGitManager {
             let branch_ref = new_branch.get();
 
             repo.worktree(
-                branch,
+                name,
                 path,
                 Some(git2::WorktreeAddOptions::new().reference(Some(branch_ref))),
             )
@@ -596,4 +709,118 @@ impl GitManager for LibGitManager {
 
         Ok(worktree_paths)
     }
+
+    async fn mainline_branch(&self) -> Result<String> {
+        Ok(repository::mainline_branch(&self.open_repo()?))
+    }
+
+    async fn switch_branch(&self, branch_name: &str, checkout: &CheckoutConfig) -> Result<bool> {
+        repository::switch_branch(&self.open_repo()?, branch_name, checkout)
+    }
+
+    async fn file_at_revision(&self, revision: &str, file_path: &str) -> Result<Option<String>> {
+        repository::file_at_revision(&self.open_repo()?, revision, file_path)
+    }
+
+    async fn staged_diff_stat(&self) -> Result<DiffStat> {
+        DiffStat::staged(&self.open_repo()?)
+    }
+
+    async fn changed_files(&self, base: &str) -> Result<Vec<String>> {
+        repository::changed_files(&self.open_repo()?, base)
+    }
+
+    async fn commit_summaries(&self, from_branch: &str, to_branch: &str) -> Result<Vec<String>> {
+        repository::commit_summaries(&self.open_repo()?, from_branch, to_branch)
+    }
+
+    async fn merge_into(
+        &self,
+        branch_name: &str,
+        target: &str,
+        message: &str,
+        checkout: &CheckoutConfig,
+    ) -> Result<Option<String>> {
+        let signature = self.create_signature()?;
+        repository::merge_into(
+            &self.open_repo()?,
+            branch_name,
+            target,
+            message,
+            &signature,
+            self.signer.as_ref(),
+            checkout,
+        )
+    }
+
+    async fn revert_commit(&self, commit: &str, message: &str) -> Result<String> {
+        let signature = self.create_signature()?;
+        repository::revert_commit(
+            &self.open_repo()?,
+            commit,
+            message,
+            &signature,
+            self.signer.as_ref(),
+        )
+    }
+
+    async fn create_branch_from(&self, branch_name: &str, start_point: &str) -> Result<()> {
+        repository::create_branch_from(&self.open_repo()?, branch_name, start_point)
+    }
+
+    async fn is_merged(&self, branch_name: &str, target: &str) -> Result<bool> {
+        repository::is_merged(&self.open_repo()?, branch_name, target)
+    }
+
+    async fn restack_branch(
+        &self,
+        branch_name: &str,
+        upstream: &str,
+        onto: &str,
+        checkout: &CheckoutConfig,
+    ) -> Result<String> {
+        let signature = self.create_signature()?;
+        repository::restack_branch(
+            &self.open_repo()?,
+            branch_name,
+            upstream,
+            onto,
+            &signature,
+            self.signer.as_ref(),
+            checkout,
+        )
+    }
+
+    async fn fetch(&self, remote: &str, token: Option<&str>) -> Result<()> {
+        repository::fetch(&self.open_repo()?, remote, token)
+    }
+
+    async fn fast_forward(
+        &self,
+        branch_name: &str,
+        remote: &str,
+        checkout: &CheckoutConfig,
+    ) -> Result<BranchSync> {
+        repository::fast_forward(&self.open_repo()?, branch_name, remote, checkout)
+    }
+
+    async fn remote_url(&self, remote: &str) -> Result<String> {
+        repository::remote_url(&self.open_repo()?, remote)
+    }
+
+    async fn push_branch(
+        &self,
+        remote: &str,
+        branch_name: &str,
+        force: bool,
+        token: Option<&str>,
+    ) -> Result<()> {
+        repository::push_branch(&self.open_repo()?, remote, branch_name, force, token)
+    }
+
+    fn checkout_at(&self, path: &Path) -> Box<dyn GitManager> {
+        Box::new(
+            Self::new(path, &self.author_name, &self.author_email).with_signer(self.signer.clone()),
+        )
+    }
 }
@@ -5,8 +5,13 @@ use log::{error, info};
 use std::fs;
 use std::path::{Path, PathBuf};
 
+use crate::core::config::{CheckoutConfig, CommitIdentity, GitConfig};
 use crate::core::error::BorgError;
+use crate::version_control::checkout;
+use crate::version_control::diff_stat::DiffStat;
 use crate::version_control::git::GitManager;
+use crate::version_control::repository::{self, BranchSync};
+use crate::version_control::signing::{commit_on_head, CommitSigner};
 
 /// Git implementation using libgit2
 pub struct GitImplementation {
@@ -18,18 +23,55 @@ pub struct GitImplementation {
 
     /// Author email for commits
     author_email: String,
+
+    /// Signs the commits, when signing is configured
+    signer: Option<CommitSigner>,
+
+    /// Working-tree handling when checking out branches
+    checkout: CheckoutConfig,
 }
 
 impl GitImplementation {
     /// Create a new Git implementation
     pub fn new<P: AsRef<Path>>(repo_path: P) -> Result<Self> {
+        let author = CommitIdentity::default();
         Ok(Self {
             repo_path: repo_path.as_ref().to_path_buf(),
-            author_name: "Borg Agent".to_string(),
-            author_email: "borg@example.com".to_string(),
+            author_name: author.name,
+            author_email: author.email,
+            signer: None,
+            checkout: CheckoutConfig::default(),
         })
     }
 
+    /// Git implementation committing as the configured author, signing
+    /// commits when signing is configured
+    pub fn from_config<P: AsRef<Path>>(repo_path: P, git: &GitConfig) -> Result<Self> {
+        Ok(Self::new(repo_path)?
+            .with_author(&git.author.name, &git.author.email)
+            .with_signer(CommitSigner::from_config(&git.signing)?)
+            .with_checkout(git.checkout.clone()))
+    }
+
+    /// Commit as `name <email>`
+    pub fn with_author(mut self, name: &str, email: &str) -> Self {
+        self.author_name = name.to_string();
+        self.author_email = email.to_string();
+        self
+    }
+
+    /// Sign the commits with `signer`
+    pub fn with_signer(mut self, signer: Option<CommitSigner>) -> Self {
+        self.signer = signer;
+        self
+    }
+
+    /// Check out branches as `checkout` asks
+    pub fn with_checkout(mut self, checkout: CheckoutConfig) -> Self {
+        self.checkout = checkout;
+        self
+    }
+
     /// Open the repository
     fn open_repo(&self) -> Result<Repository> {
         let repo = Repository::open(&self.repo_path)
@@ -95,7 +137,7 @@ impl GitManager for GitImplementation {
 
         // Checkout branch
         let obj = branch_ref.peel(ObjectType::Any)?;
-        repo.checkout_tree(&obj, None)
+        checkout::checkout_tree(&repo, &obj, &self.checkout)
             .with_context(|| format!("Failed to checkout tree for branch: {}", branch_name))?;
 
         // Set HEAD to branch
@@ -182,13 +224,13 @@ impl GitManager for GitImplementation {
         };
 
         // Create commit
-        let commit_id = repo.commit(
-            Some("HEAD"),
-            &signature,
+        let commit_id = commit_on_head(
+            &repo,
             &signature,
             message,
             &tree,
             &parents,
+            self.signer.as_ref(),
         )?;
 
         info!("Created commit: {}", commit_id);
@@ -322,13 +364,13 @@ impl GitManager for GitImplementation {
                 let parent_commits = [&head_commit, &branch_commit];
 
                 // Create the merge commit
-                repo.commit(
-                    Some("HEAD"),
-                    &signature,
+                commit_on_head(
+                    &repo,
                     &signature,
                     &format!("Merge branch '{}'", branch_name),
                     &tree,
                     &parent_commits,
+                    self.signer.as_ref(),
                 )?;
 
                 // Clean up the merge state
@@ -403,6 +445,10 @@ impl GitManager for GitImplementation {
         // Convert diff to string
         let mut diff_text = String::new();
         diff.print(git2::DiffFormat::Patch, |_delta, _hunk, line| {
+            // Content lines carry their +/-/space marker in origin(), not content()
+            if matches!(line.origin(), '+' | '-' | ' ') {
+                diff_text.push(line.origin());
+            }
             if let Ok(text) = std::str::from_utf8(line.content()) {
                 diff_text.push_str(text);
             }
@@ -439,6 +485,22 @@ impl GitManager for GitImplementation {
             ))));
         }
 
+        // Worktrees are named after their directory, which is how
+        // remove_worktree finds them again
+        let name = path.file_name().and_then(|n| n.to_str()).ok_or_else(|| {
+            anyhow!(BorgError::GitError(format!(
+                "Invalid worktree path: {:?}",
+                path
+            )))
+        })?;
+
+        // A worktree whose directory was deleted is still registered
+        if let Ok(stale) = repo.find_worktree(name) {
+            stale
+                .prune(Some(git2::WorktreePruneOptions::new().valid(true)))
+                .with_context(|| format!("Failed to prune stale worktree: {}", name))?;
+        }
+
         // Check if the branch exists
         let branch_exists = repo.find_branch(branch, BranchType::Local).is_ok();
 
@@ -448,7 +510,7 @@ impl GitManager for GitImplementation {
 
             // Use git2's worktree API to add a new worktree
             repo.worktree(
-                branch,
+                name,
                 path,
                 Some(
                     git2::WorktreeAddOptions::new()
@@ -484,7 +546,7 @@ impl GitManager for GitImplementation {
             let branch_ref = new_branch.get();
 
             repo.worktree(
-                branch,
+                name,
                 path,
                 Some(git2::WorktreeAddOptions::new().reference(Some(branch_ref))),
             )
@@ -572,4 +634,176 @@ impl GitManager for GitImplementation {
 
         Ok(worktree_paths)
     }
+
+    async fn mainline_branch(&self) -> Result<String> {
+        Ok(repository::mainline_branch(&self.open_repo()?))
+    }
+
+    async fn switch_branch(&self, branch_name: &str, checkout: &CheckoutConfig) -> Result<bool> {
+        repository::switch_branch(&self.open_repo()?, branch_name, checkout)
+    }
+
+    async fn file_at_revision(&self, revision: &str, file_path: &str) -> Result<Option<String>> {
+        repository::file_at_revision(&self.open_repo()?, revision, file_path)
+    }
+
+    async fn staged_diff_stat(&self) -> Result<DiffStat> {
+        DiffStat::staged(&self.open_repo()?)
+    }
+
+    async fn changed_files(&self, base: &str) -> Result<Vec<String>> {
+        repository::changed_files(&self.open_repo()?, base)
+    }
+
+    async fn commit_summaries(&self, from_branch: &str, to_branch: &str) -> Result<Vec<String>> {
+        repository::commit_summaries(&self.open_repo()?, from_branch, to_branch)
+    }
+
+    async fn merge_into(
+        &self,
+        branch_name: &str,
+        target: &str,
+        message: &str,
+        checkout: &CheckoutConfig,
+    ) -> Result<Option<String>> {
+        let signature = self.create_signature()?;
+        repository::merge_into(
+            &self.open_repo()?,
+            branch_name,
+            target,
+            message,
+            &signature,
+            self.signer.as_ref(),
+            checkout,
+        )
+    }
+
+    async fn revert_commit(&self, commit: &str, message: &str) -> Result<String> {
+        let signature = self.create_signature()?;
+        repository::revert_commit(
+            &self.open_repo()?,
+            commit,
+            message,
+            &signature,
+            self.signer.as_ref(),
+        )
+    }
+
+    async fn create_branch_from(&self, branch_name: &str, start_point: &str) -> Result<()> {
+        repository::create_branch_from(&self.open_repo()?, branch_name, start_point)
+    }
+
+    async fn is_merged(&self, branch_name: &str, target: &str) -> Result<bool> {
+        repository::is_merged(&self.open_repo()?, branch_name, target)
+    }
+
+    async fn restack_branch(
+        &self,
+        branch_name: &str,
+        upstream: &str,
+        onto: &str,
+        checkout: &CheckoutConfig,
+    ) -> Result<String> {
+        let signature = self.create_signature()?;
+        repository::restack_branch(
+            &self.open_repo()?,
+            branch_name,
+            upstream,
+            onto,
+            &signature,
+            self.signer.as_ref(),
+            checkout,
+        )
+    }
+
+    async fn fetch(&self, remote: &str, token: Option<&str>) -> Result<()> {
+        repository::fetch(&self.open_repo()?, remote, token)
+    }
+
+    async fn fast_forward(
+        &self,
+        branch_name: &str,
+        remote: &str,
+        checkout: &CheckoutConfig,
+    ) -> Result<BranchSync> {
+        repository::fast_forward(&self.open_repo()?, branch_name, remote, checkout)
+    }
+
+    async fn remote_url(&self, remote: &str) -> Result<String> {
+        repository::remote_url(&self.open_repo()?, remote)
+    }
+
+    async fn push_branch(
+        &self,
+        remote: &str,
+        branch_name: &str,
+        force: bool,
+        token: Option<&str>,
+    ) -> Result<()> {
+        repository::push_branch(&self.open_repo()?, remote, branch_name, force, token)
+    }
+
+    fn checkout_at(&self, path: &Path) -> Box<dyn GitManager> {
+        Box::new(Self {
+            repo_path: path.to_path_buf(),
+            author_name: self.author_name.clone(),
+            author_email: self.author_email.clone(),
+            signer: self.signer.clone(),
+            checkout: self.checkout.clone(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run_git(dir: &Path, args: &[&str]) {
+        let output = std::process::Command::new("git")
+            .args(args)
+            .current_dir(dir)
+            .output()
+            .unwrap();
+        assert!(output.status.success(), "git {:?} failed", args);
+    }
+
+    #[tokio::test]
+    async fn test_safe_checkout_keeps_dirty_files() {
+        let repo = tempfile::tempdir().unwrap();
+        let path = repo.path();
+        run_git(path, &["init", "-q", "-b", "master"]);
+        run_git(path, &["config", "user.name", "Test"]);
+        run_git(path, &["config", "user.email", "test@example.com"]);
+        fs::write(path.join("notes.txt"), "notes\n").unwrap();
+        fs::write(path.join("lib.rs"), "fn a() {}\n").unwrap();
+        run_git(path, &["add", "."]);
+        run_git(path, &["commit", "-q", "-m", "Initial commit"]);
+        run_git(path, &["checkout", "-q", "-b", "feature"]);
+        fs::write(path.join("lib.rs"), "fn a() {}\nfn b() {}\n").unwrap();
+        run_git(path, &["commit", "-q", "-am", "Add b"]);
+        run_git(path, &["checkout", "-q", "master"]);
+        fs::write(path.join("notes.txt"), "edited notes\n").unwrap();
+
+        let git = GitImplementation::new(path).unwrap();
+        git.checkout_branch("feature").await.unwrap();
+        assert_eq!(git.get_current_branch().await.unwrap(), "feature");
+        assert_eq!(
+            fs::read_to_string(path.join("lib.rs")).unwrap(),
+            "fn a() {}\nfn b() {}\n"
+        );
+        assert_eq!(
+            fs::read_to_string(path.join("notes.txt")).unwrap(),
+            "edited notes\n"
+        );
+
+        // A dirty file the checkout would overwrite blocks it instead
+        fs::write(path.join("lib.rs"), "fn dirty() {}\n").unwrap();
+        let err = git.checkout_branch("master").await.unwrap_err();
+        assert!(format!("{:#}", err).contains("lib.rs"), "{:#}", err);
+        assert_eq!(git.get_current_branch().await.unwrap(), "feature");
+        assert_eq!(
+            fs::read_to_string(path.join("lib.rs")).unwrap(),
+            "fn dirty() {}\n"
+        );
+    }
 }
@@ -0,0 +1,227 @@
+//! Pre-commit hooks for the commits the agent makes.
+//!
+//! Either the commands under `git.pre_commit.commands` run, or, when none
+//! are configured, the repository's own `pre-commit` hook from
+//! `core.hooksPath` or the hooks directory. A failing hook stops the commit
+//! and its output goes back to the model for the next attempt.
+
+use anyhow::{Context, Result};
+use git2::Repository;
+use log::info;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tokio::process::Command;
+use tokio::time::timeout;
+
+use crate::core::config::PreCommitConfig;
+
+/// Output kept of a failing hook, from its end
+const MAX_OUTPUT_CHARS: usize = 4000;
+
+/// A hook that failed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HookFailure {
+    /// The command or hook file that failed
+    pub hook: String,
+
+    /// What it printed, cut to its last lines when long
+    pub output: String,
+}
+
+impl fmt::Display for HookFailure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} failed: {}", self.hook, self.output)
+    }
+}
+
+/// The hooks to run before a commit
+#[derive(Debug, Clone)]
+pub struct PreCommitHooks {
+    commands: Vec<String>,
+    timeout: Duration,
+}
+
+impl PreCommitHooks {
+    /// Hooks for `config`; `None` when they are off
+    pub fn from_config(config: &PreCommitConfig) -> Option<Self> {
+        config.enabled.then(|| Self {
+            commands: config.commands.clone(),
+            timeout: Duration::from_secs(config.timeout_seconds),
+        })
+    }
+
+    /// Run the hooks in the checkout at `workspace`; returns the ones that
+    /// failed, none when the commit may go ahead
+    pub async fn run(&self, workspace: &Path) -> Result<Vec<HookFailure>> {
+        let mut failures = Vec::new();
+        if self.commands.is_empty() {
+            if let Some(hook) = repository_hook(workspace)? {
+                let mut command = Command::new(&hook);
+                let name = hook.display().to_string();
+                failures.extend(self.run_one(&name, &mut command, workspace).await?);
+            }
+            return Ok(failures);
+        }
+
+        for hook in &self.commands {
+            let mut command = Command::new("sh");
+            command.arg("-c").arg(hook);
+            failures.extend(self.run_one(hook, &mut command, workspace).await?);
+        }
+        Ok(failures)
+    }
+
+    async fn run_one(
+        &self,
+        name: &str,
+        command: &mut Command,
+        workspace: &Path,
+    ) -> Result<Option<HookFailure>> {
+        info!("Running pre-commit hook {}", name);
+        let output = command.current_dir(workspace).kill_on_drop(true).output();
+        let output = match timeout(self.timeout, output).await {
+            Ok(output) => output.with_context(|| format!("Failed to run hook {}", name))?,
+            Err(_) => {
+                return Ok(Some(HookFailure {
+                    hook: name.to_string(),
+                    output: format!("timed out after {} seconds", self.timeout.as_secs()),
+                }))
+            }
+        };
+        if output.status.success() {
+            return Ok(None);
+        }
+
+        let printed = format!(
+            "{}{}",
+            String::from_utf8_lossy(&output.stdout),
+            String::from_utf8_lossy(&output.stderr)
+        );
+        Ok(Some(HookFailure {
+            hook: name.to_string(),
+            output: tail(printed.trim(), MAX_OUTPUT_CHARS),
+        }))
+    }
+}
+
+/// The executable `pre-commit` hook of the repository checked out at
+/// `workspace`, if it has one
+fn repository_hook(workspace: &Path) -> Result<Option<PathBuf>> {
+    let repo = Repository::open(workspace)
+        .with_context(|| format!("Failed to open repository at {:?}", workspace))?;
+    let hooks_dir = match repo
+        .config()
+        .and_then(|config| config.get_path("core.hooksPath"))
+    {
+        Ok(path) if path.is_relative() => workspace.join(path),
+        Ok(path) => path,
+        // Worktrees share the hooks of the main repository
+        Err(_) => repo.commondir().join("hooks"),
+    };
+    let hook = hooks_dir.join("pre-commit");
+    Ok(is_executable(&hook).then_some(hook))
+}
+
+#[cfg(unix)]
+fn is_executable(path: &Path) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    std::fs::metadata(path)
+        .is_ok_and(|meta| meta.is_file() && meta.permissions().mode() & 0o111 != 0)
+}
+
+#[cfg(not(unix))]
+fn is_executable(path: &Path) -> bool {
+    path.is_file()
+}
+
+/// The last `max` characters of `text`
+fn tail(text: &str, max: usize) -> String {
+    let count = text.chars().count();
+    if count <= max {
+        return text.to_string();
+    }
+    let rest: String = text.chars().skip(count - max).collect();
+    format!("...{}", rest)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    fn run_git(dir: &Path, args: &[&str]) {
+        let output = std::process::Command::new("git")
+            .args(args)
+            .current_dir(dir)
+            .output()
+            .unwrap();
+        assert!(output.status.success(), "git {:?} failed", args);
+    }
+
+    fn hooks(commands: &[&str]) -> PreCommitHooks {
+        PreCommitHooks::from_config(&PreCommitConfig {
+            enabled: true,
+            commands: commands.iter().map(|c| c.to_string()).collect(),
+            timeout_seconds: 60,
+        })
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_configured_commands_report_each_failure() {
+        let dir = tempfile::tempdir().unwrap();
+        let failures = hooks(&[
+            "true",
+            "echo 'lib.rs is not formatted' >&2; exit 1",
+            "exit 2",
+        ])
+        .run(dir.path())
+        .await
+        .unwrap();
+
+        assert_eq!(
+            failures,
+            [
+                HookFailure {
+                    hook: "echo 'lib.rs is not formatted' >&2; exit 1".to_string(),
+                    output: "lib.rs is not formatted".to_string(),
+                },
+                HookFailure {
+                    hook: "exit 2".to_string(),
+                    output: String::new(),
+                },
+            ]
+        );
+        assert!(PreCommitHooks::from_config(&PreCommitConfig::default()).is_none());
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_the_repository_hook_runs_without_configured_commands() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path();
+        run_git(path, &["init", "-b", "master"]);
+        let hooks = hooks(&[]);
+        assert!(hooks.run(path).await.unwrap().is_empty());
+
+        let hook = path.join(".git/hooks/pre-commit");
+        fs::write(&hook, "#!/bin/sh\necho 'clippy: needless return'\nexit 1\n").unwrap();
+        // Not executable, so git would not run it either
+        assert!(hooks.run(path).await.unwrap().is_empty());
+
+        run_git(path, &["config", "core.hooksPath", "ci/hooks"]);
+        fs::create_dir_all(path.join("ci/hooks")).unwrap();
+        fs::rename(&hook, path.join("ci/hooks/pre-commit")).unwrap();
+        fs::set_permissions(
+            path.join("ci/hooks/pre-commit"),
+            std::os::unix::fs::PermissionsExt::from_mode(0o755),
+        )
+        .unwrap();
+
+        let failures = hooks.run(path).await.unwrap();
+        assert_eq!(failures.len(), 1);
+        assert!(failures[0].hook.ends_with("ci/hooks/pre-commit"));
+        assert_eq!(failures[0].output, "clippy: needless return");
+    }
+}
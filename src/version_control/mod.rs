@@ -1,2 +1,12 @@
+pub mod bisect;
+pub mod checkout;
+pub mod commit_message;
+pub mod diff_stat;
 pub mod git;
 pub mod git_implementation;
+pub mod github;
+pub mod hooks;
+pub mod repository;
+pub mod rollback;
+pub mod signing;
+pub mod trailers;
@@ -0,0 +1,873 @@
+//! Branch workflow operations shared by the `GitManager` implementations.
+//!
+//! Both managers wrap libgit2; the operations a strategy needs beyond the
+//! basic ones (switching to a possibly new branch, previewing files at a
+//! revision, merging with a given message) are written once here against a
+//! `Repository`.
+
+use anyhow::{anyhow, Context, Result};
+use git2::{
+    BranchType, Cred, FetchOptions, MergeOptions, PushOptions, RemoteCallbacks, Repository,
+    RevertOptions, Signature,
+};
+use log::info;
+use std::collections::BTreeSet;
+use std::path::Path;
+
+use crate::core::config::CheckoutConfig;
+use crate::version_control::checkout::checkout_tree;
+use crate::version_control::signing::{commit_on_head, create_commit, CommitSigner};
+
+/// The mainline branch: `master` if present, else `main`
+pub fn mainline_branch(repo: &Repository) -> String {
+    if repo.find_branch("master", BranchType::Local).is_ok() {
+        "master".to_string()
+    } else {
+        "main".to_string()
+    }
+}
+
+/// Check out `branch`, creating it from HEAD first when it does not exist;
+/// returns whether it was created
+pub fn switch_branch(repo: &Repository, branch: &str, checkout: &CheckoutConfig) -> Result<bool> {
+    let created = repo.find_branch(branch, BranchType::Local).is_err();
+    if created {
+        info!("Creating branch {}", branch);
+        let head_commit = repo
+            .head()
+            .context("Failed to get HEAD reference")?
+            .peel_to_commit()
+            .context("Failed to peel HEAD to commit")?;
+        repo.branch(branch, &head_commit, false)
+            .context(format!("Failed to create branch '{}'", branch))?;
+    }
+
+    let branch_ref = format!("refs/heads/{}", branch);
+    let obj = repo
+        .revparse_single(&branch_ref)
+        .context(format!("Failed to find branch '{}'", branch))?;
+    checkout_tree(repo, &obj, checkout)
+        .context(format!("Failed to checkout branch '{}'", branch))?;
+    repo.set_head(&branch_ref)
+        .context(format!("Failed to set HEAD to branch '{}'", branch))?;
+
+    info!("Checked out branch {}", branch);
+    Ok(created)
+}
+
+/// Create `branch` at `start_point`, a branch or commit, without checking
+/// it out
+pub fn create_branch_from(repo: &Repository, branch: &str, start_point: &str) -> Result<()> {
+    let commit = repo
+        .revparse_single(start_point)
+        .and_then(|object| object.peel_to_commit())
+        .context(format!("Failed to find '{}'", start_point))?;
+    repo.branch(branch, &commit, false)
+        .context(format!("Failed to create branch '{}'", branch))?;
+    info!("Created branch {} from {}", branch, start_point);
+    Ok(())
+}
+
+/// Whether the tip of `branch` is part of `target`
+pub fn is_merged(repo: &Repository, branch: &str, target: &str) -> Result<bool> {
+    let tip = |name: &str| {
+        repo.revparse_single(&format!("refs/heads/{}", name))
+            .and_then(|object| object.peel_to_commit())
+            .map(|commit| commit.id())
+            .context(format!("Failed to find branch '{}'", name))
+    };
+    let (branch_tip, target_tip) = (tip(branch)?, tip(target)?);
+    Ok(branch_tip == target_tip
+        || repo
+            .graph_descendant_of(target_tip, branch_tip)
+            .context("Failed to compare branches")?)
+}
+
+/// Content of `file_path` at `revision`, `None` when the file is not part
+/// of it
+pub fn file_at_revision(
+    repo: &Repository,
+    revision: &str,
+    file_path: &str,
+) -> Result<Option<String>> {
+    let tree = repo
+        .revparse_single(revision)
+        .and_then(|object| object.peel_to_tree())
+        .context(format!("Failed to find the tree of {}", revision))?;
+    let Ok(entry) = tree.get_path(Path::new(file_path)) else {
+        return Ok(None);
+    };
+    let blob = entry
+        .to_object(repo)
+        .and_then(|object| object.peel_to_blob())
+        .context(format!("Failed to read {}", file_path))?;
+    Ok(Some(String::from_utf8_lossy(blob.content()).into_owned()))
+}
+
+/// Paths of the files the working tree changes relative to `base`, new
+/// files included
+pub fn changed_files(repo: &Repository, base: &str) -> Result<Vec<String>> {
+    let base_tree = repo
+        .revparse_single(base)
+        .and_then(|object| object.peel_to_tree())
+        .context(format!("Failed to find the tree of {}", base))?;
+    let mut options = git2::DiffOptions::new();
+    options.include_untracked(true).recurse_untracked_dirs(true);
+    let diff = repo
+        .diff_tree_to_workdir_with_index(Some(&base_tree), Some(&mut options))
+        .context("Failed to diff the working tree")?;
+    Ok(diff
+        .deltas()
+        .flat_map(|delta| [delta.old_file().path(), delta.new_file().path()])
+        .flatten()
+        .map(|path| path.to_string_lossy().to_string())
+        .collect::<BTreeSet<_>>()
+        .into_iter()
+        .collect())
+}
+
+/// Subject lines of the commits on `to` that are not on `from`, newest first
+pub fn commit_summaries(repo: &Repository, from: &str, to: &str) -> Result<Vec<String>> {
+    let to_commit = repo
+        .revparse_single(&format!("refs/heads/{}", to))
+        .and_then(|object| object.peel_to_commit())
+        .context(format!("Failed to find branch '{}'", to))?;
+    let from_commit = repo
+        .revparse_single(&format!("refs/heads/{}", from))
+        .and_then(|object| object.peel_to_commit())
+        .context(format!("Failed to find branch '{}'", from))?;
+
+    let mut revwalk = repo.revwalk().context("Failed to create revwalk")?;
+    revwalk
+        .push(to_commit.id())
+        .context("Failed to push branch commit to revwalk")?;
+    revwalk
+        .hide(from_commit.id())
+        .context("Failed to hide base commit in revwalk")?;
+
+    Ok(revwalk
+        .flatten()
+        .filter_map(|oid| repo.find_commit(oid).ok())
+        .map(|commit| {
+            let message = commit.message().unwrap_or("No message");
+            message.lines().next().unwrap_or("No message").to_string()
+        })
+        .collect())
+}
+
+/// Check out `target` and merge `branch` into it with a merge commit
+/// carrying `message`, even when a fast-forward is possible; conflicts fail
+/// the merge. Returns the merge commit, `None` when `branch` was already
+/// merged
+pub fn merge_into(
+    repo: &Repository,
+    branch: &str,
+    target: &str,
+    message: &str,
+    signature: &Signature,
+    signer: Option<&CommitSigner>,
+    checkout: &CheckoutConfig,
+) -> Result<Option<String>> {
+    let target_ref = format!("refs/heads/{}", target);
+    let obj = repo
+        .revparse_single(&target_ref)
+        .context(format!("Failed to find {} branch", target))?;
+    checkout_tree(repo, &obj, checkout).context(format!("Failed to checkout {} branch", target))?;
+    repo.set_head(&target_ref)
+        .context(format!("Failed to set HEAD to {} branch", target))?;
+    info!("Checked out {} branch", target);
+
+    let branch_ref = repo
+        .find_reference(&format!("refs/heads/{}", branch))
+        .context(format!("Failed to find branch reference '{}'", branch))?;
+    let annotated_commit = repo
+        .reference_to_annotated_commit(&branch_ref)
+        .context("Failed to convert reference to annotated commit")?;
+    let (merge_analysis, _) = repo
+        .merge_analysis(&[&annotated_commit])
+        .context("Failed to analyze merge")?;
+
+    if merge_analysis.is_up_to_date() {
+        info!("Branch {} is already merged into {}", branch, target);
+        return Ok(None);
+    }
+    if merge_analysis.is_fast_forward() {
+        info!("Fast-forward merge possible, but performing normal merge instead");
+    }
+
+    let mut merge_opts = MergeOptions::new();
+    merge_opts.fail_on_conflict(false);
+    repo.merge(&[&annotated_commit], Some(&mut merge_opts), None)
+        .context("Failed to merge branches")?;
+
+    let statuses = repo
+        .statuses(None)
+        .context("Failed to get repository status")?;
+    let mut has_conflicts = false;
+    for entry in statuses.iter() {
+        if entry.status().is_conflicted() {
+            has_conflicts = true;
+            info!("Conflict in file: {:?}", entry.path());
+        }
+    }
+    if has_conflicts {
+        return Err(anyhow!(
+            "Merge conflicts detected. Manual resolution required."
+        ));
+    }
+
+    let mut index = repo.index().context("Failed to get repository index")?;
+    let tree_id = index.write_tree().context("Failed to write tree")?;
+    let tree = repo.find_tree(tree_id).context("Failed to find tree")?;
+    let head_commit = repo
+        .head()
+        .context("Failed to get HEAD")?
+        .peel_to_commit()
+        .context("Failed to peel HEAD to commit")?;
+    let branch_commit = branch_ref
+        .peel_to_commit()
+        .context("Failed to peel branch reference to commit")?;
+    let merge_commit = commit_on_head(
+        repo,
+        signature,
+        message,
+        &tree,
+        &[&head_commit, &branch_commit],
+        signer,
+    )
+    .context("Failed to create merge commit")?;
+    repo.cleanup_state()
+        .context("Failed to cleanup merge state")?;
+
+    info!("Successfully merged branch {} into {}", branch, target);
+    Ok(Some(merge_commit.to_string()))
+}
+
+/// Commit the revert of `commit` on HEAD, against its first parent when it
+/// is a merge; conflicts abort the revert. Returns the revert commit
+pub fn revert_commit(
+    repo: &Repository,
+    commit: &str,
+    message: &str,
+    signature: &Signature,
+    signer: Option<&CommitSigner>,
+) -> Result<String> {
+    let reverted = repo
+        .revparse_single(commit)
+        .and_then(|object| object.peel_to_commit())
+        .context(format!("Failed to find commit {}", commit))?;
+    let mut options = RevertOptions::new();
+    if reverted.parent_count() > 1 {
+        options.mainline(1);
+    }
+    repo.revert(&reverted, Some(&mut options))
+        .context(format!("Failed to revert commit {}", commit))?;
+
+    let mut index = repo.index().context("Failed to get repository index")?;
+    if index.has_conflicts() {
+        // Put back only the files the revert touched
+        let touched: Vec<String> = repo
+            .statuses(None)
+            .context("Failed to get repository status")?
+            .iter()
+            .filter(|entry| {
+                entry.status().is_conflicted()
+                    || entry.status().intersects(
+                        git2::Status::INDEX_NEW
+                            | git2::Status::INDEX_MODIFIED
+                            | git2::Status::INDEX_DELETED,
+                    )
+            })
+            .filter_map(|entry| entry.path().map(str::to_string))
+            .collect();
+        let head = repo
+            .head()
+            .and_then(|head| head.peel(git2::ObjectType::Commit))
+            .context("Failed to get HEAD")?;
+        repo.reset_default(Some(&head), touched.iter())
+            .context("Failed to unstage the conflicting revert")?;
+        let mut restore = git2::build::CheckoutBuilder::new();
+        restore.force().remove_untracked(true);
+        for path in &touched {
+            restore.path(path);
+        }
+        repo.checkout_head(Some(&mut restore))
+            .context("Failed to undo the conflicting revert")?;
+        repo.cleanup_state()
+            .context("Failed to cleanup revert state")?;
+        return Err(anyhow!(
+            "Reverting {} conflicts with later changes. Manual resolution required.",
+            commit
+        ));
+    }
+
+    let tree_id = index.write_tree().context("Failed to write tree")?;
+    let tree = repo.find_tree(tree_id).context("Failed to find tree")?;
+    let head_commit = repo
+        .head()
+        .context("Failed to get HEAD")?
+        .peel_to_commit()
+        .context("Failed to peel HEAD to commit")?;
+    let revert_commit = commit_on_head(repo, signature, message, &tree, &[&head_commit], signer)
+        .context("Failed to create revert commit")?;
+    repo.cleanup_state()
+        .context("Failed to cleanup revert state")?;
+
+    info!("Reverted {} in {}", commit, revert_commit);
+    Ok(revert_commit.to_string())
+}
+
+/// Replay the commits of `branch` that are not on `upstream` onto `onto`
+/// and move the branch to the result, keeping their authors; a checked out
+/// branch has the result checked out. Conflicts leave the branch untouched.
+/// Returns the new tip
+pub fn restack_branch(
+    repo: &Repository,
+    branch: &str,
+    upstream: &str,
+    onto: &str,
+    committer: &Signature,
+    signer: Option<&CommitSigner>,
+    checkout: &CheckoutConfig,
+) -> Result<String> {
+    let branch_ref = format!("refs/heads/{}", branch);
+    let find = |revision: &str| {
+        repo.revparse_single(revision)
+            .and_then(|object| object.peel_to_commit())
+            .context(format!("Failed to find '{}'", revision))
+    };
+    let branch_tip = find(&branch_ref)?;
+    let upstream = find(upstream)?;
+    let mut tip = find(onto)?;
+
+    let mut walk = repo.revwalk().context("Failed to walk history")?;
+    walk.set_sorting(git2::Sort::TOPOLOGICAL | git2::Sort::REVERSE)
+        .context("Failed to walk history")?;
+    walk.push(branch_tip.id())
+        .context("Failed to walk history")?;
+    walk.hide(upstream.id()).context("Failed to walk history")?;
+
+    let mut replayed = 0;
+    for oid in walk {
+        let commit = repo
+            .find_commit(oid.context("Failed to walk history")?)
+            .context("Failed to find commit")?;
+        if commit.parent_count() > 1 {
+            return Err(anyhow!(
+                "Branch '{}' contains merge commit {}; it cannot be restacked",
+                branch,
+                commit.id()
+            ));
+        }
+        let mut index = repo
+            .cherrypick_commit(&commit, &tip, 0, None)
+            .context(format!("Failed to replay commit {}", commit.id()))?;
+        if index.has_conflicts() {
+            return Err(anyhow!(
+                "Replaying commit {} of branch '{}' onto {} conflicts. Manual resolution required.",
+                commit.id(),
+                branch,
+                onto
+            ));
+        }
+        let tree_id = index.write_tree_to(repo).context("Failed to write tree")?;
+        let tree = repo.find_tree(tree_id).context("Failed to find tree")?;
+        let message = commit.message().unwrap_or_default();
+        let oid = create_commit(
+            repo,
+            &commit.author(),
+            committer,
+            message,
+            &tree,
+            &[&tip],
+            signer,
+        )
+        .context(format!("Failed to replay commit {}", commit.id()))?;
+        tip = repo.find_commit(oid).context("Failed to find commit")?;
+        replayed += 1;
+    }
+
+    move_branch(repo, branch, tip.as_object(), checkout)?;
+    info!(
+        "Restacked {} commits of branch {} onto {}",
+        replayed, branch, onto
+    );
+    Ok(tip.id().to_string())
+}
+
+/// How a local branch stands against its remote-tracking branch
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BranchSync {
+    /// Both point at the same commit
+    UpToDate,
+    /// The local branch was behind and has been moved forward
+    FastForwarded,
+    /// The local branch has commits the remote does not, or the remote has
+    /// no such branch
+    Ahead,
+    /// Both have commits the other does not; the local branch is left be
+    Diverged,
+}
+
+/// Remote callbacks authenticating with `token` when given, else with the
+/// SSH agent or the configured credential helper
+fn authenticated_callbacks<'a>(
+    repo: &Repository,
+    token: Option<&'a str>,
+) -> Result<RemoteCallbacks<'a>> {
+    let config = repo.config().context("Failed to read git config")?;
+    let mut callbacks = RemoteCallbacks::new();
+    callbacks.credentials(move |url, username, allowed| {
+        if let Some(token) = token {
+            if allowed.is_user_pass_plaintext() {
+                return Cred::userpass_plaintext("x-access-token", token);
+            }
+        }
+        if allowed.is_ssh_key() {
+            return Cred::ssh_key_from_agent(username.unwrap_or("git"));
+        }
+        Cred::credential_helper(&config, url, username)
+    });
+    Ok(callbacks)
+}
+
+/// Fetch the branches of a remote into its remote-tracking branches,
+/// authenticating with `token` when given
+pub fn fetch(repo: &Repository, remote: &str, token: Option<&str>) -> Result<()> {
+    let mut remote_handle = repo
+        .find_remote(remote)
+        .context(format!("Failed to find remote '{}'", remote))?;
+    let mut options = FetchOptions::new();
+    options.remote_callbacks(authenticated_callbacks(repo, token)?);
+    let refspec = format!("+refs/heads/*:refs/remotes/{}/*", remote);
+    remote_handle
+        .fetch(&[refspec.as_str()], Some(&mut options), None)
+        .context(format!("Failed to fetch {}", remote))?;
+    info!("Fetched {}", remote);
+    Ok(())
+}
+
+/// Bring `branch` up to date with the remote-tracking branch of `remote`
+/// when it is behind and has nothing of its own, checking the new commit
+/// out when the branch is checked out
+pub fn fast_forward(
+    repo: &Repository,
+    branch: &str,
+    remote: &str,
+    checkout: &CheckoutConfig,
+) -> Result<BranchSync> {
+    let branch_ref = format!("refs/heads/{}", branch);
+    let local = repo
+        .revparse_single(&branch_ref)
+        .and_then(|object| object.peel_to_commit())
+        .context(format!("Failed to find branch '{}'", branch))?;
+    let Ok(upstream) = repo
+        .revparse_single(&format!("refs/remotes/{}/{}", remote, branch))
+        .and_then(|object| object.peel_to_commit())
+    else {
+        return Ok(BranchSync::Ahead);
+    };
+
+    let (ahead, behind) = repo
+        .graph_ahead_behind(local.id(), upstream.id())
+        .context(format!("Failed to compare {} with {}", branch, remote))?;
+    let sync = match (ahead, behind) {
+        (0, 0) => BranchSync::UpToDate,
+        (_, 0) => BranchSync::Ahead,
+        (0, _) => {
+            move_branch(repo, branch, upstream.as_object(), checkout)?;
+            info!("Fast-forwarded {} to {}/{}", branch, remote, branch);
+            BranchSync::FastForwarded
+        }
+        _ => BranchSync::Diverged,
+    };
+    Ok(sync)
+}
+
+/// Point `branch` at `target`, checking `target` out first when the branch
+/// is checked out so the working tree follows
+fn move_branch(
+    repo: &Repository,
+    branch: &str,
+    target: &git2::Object,
+    checkout: &CheckoutConfig,
+) -> Result<()> {
+    let branch_ref = format!("refs/heads/{}", branch);
+    let checked_out = repo
+        .head()
+        .ok()
+        .and_then(|head| head.name().map(str::to_string))
+        == Some(branch_ref.clone());
+    if checked_out {
+        checkout_tree(repo, target, checkout)
+            .context(format!("Failed to check out the new tip of '{}'", branch))?;
+    }
+    repo.reference(&branch_ref, target.id(), true, "borg: move branch")
+        .context(format!("Failed to move branch '{}'", branch))?;
+    Ok(())
+}
+
+/// URL of a remote
+pub fn remote_url(repo: &Repository, remote: &str) -> Result<String> {
+    let remote = repo
+        .find_remote(remote)
+        .context(format!("Failed to find remote '{}'", remote))?;
+    remote
+        .url()
+        .map(str::to_string)
+        .ok_or_else(|| anyhow!("Remote URL is not valid UTF-8"))
+}
+
+/// Push `branch` to `remote`, replacing what the remote has for it when
+/// `force` is set; `token` authenticates HTTPS remotes, otherwise the git
+/// credential helper and SSH agent are used
+pub fn push_branch(
+    repo: &Repository,
+    remote: &str,
+    branch: &str,
+    force: bool,
+    token: Option<&str>,
+) -> Result<()> {
+    let mut remote_handle = repo
+        .find_remote(remote)
+        .context(format!("Failed to find remote '{}'", remote))?;
+
+    let mut rejection = None;
+    let mut callbacks = authenticated_callbacks(repo, token)?;
+    callbacks.push_update_reference(|reference, status| {
+        if let Some(status) = status {
+            rejection = Some(format!("{}: {}", reference, status));
+        }
+        Ok(())
+    });
+
+    let mut options = PushOptions::new();
+    options.remote_callbacks(callbacks);
+    let refspec = format!(
+        "{}refs/heads/{1}:refs/heads/{1}",
+        if force { "+" } else { "" },
+        branch
+    );
+    remote_handle
+        .push(&[refspec.as_str()], Some(&mut options))
+        .context(format!("Failed to push branch '{}' to {}", branch, remote))?;
+    drop(options);
+
+    if let Some(rejection) = rejection {
+        return Err(anyhow!("{} rejected the push of {}", remote, rejection));
+    }
+    info!("Pushed branch {} to {}", branch, remote);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::TempDir;
+
+    fn run_git(dir: &Path, args: &[&str]) {
+        let status = std::process::Command::new("git")
+            .args(args)
+            .current_dir(dir)
+            .output()
+            .unwrap();
+        assert!(status.status.success(), "git {:?} failed", args);
+    }
+
+    /// master with `lib.rs`, and `feature` adding `fn b` in a second commit
+    fn repo_with_feature_branch() -> (TempDir, Repository) {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path();
+        run_git(path, &["init", "-b", "master"]);
+        run_git(path, &["config", "user.name", "Test"]);
+        run_git(path, &["config", "user.email", "test@example.com"]);
+        fs::write(path.join("lib.rs"), "fn a() {}\n").unwrap();
+        run_git(path, &["add", "."]);
+        run_git(path, &["commit", "-m", "Initial commit"]);
+        run_git(path, &["checkout", "-b", "feature"]);
+        fs::write(path.join("lib.rs"), "fn a() {}\nfn b() {}\n").unwrap();
+        run_git(path, &["commit", "-am", "Add b\n\nBody"]);
+        run_git(path, &["checkout", "master"]);
+        let repo = Repository::open(path).unwrap();
+        (dir, repo)
+    }
+
+    #[test]
+    fn test_switching_creates_missing_branches() {
+        let (dir, repo) = repo_with_feature_branch();
+        let checkout = CheckoutConfig::default();
+
+        assert!(!switch_branch(&repo, "feature", &checkout).unwrap());
+        assert_eq!(repo.head().unwrap().shorthand(), Some("feature"));
+        assert_eq!(
+            fs::read_to_string(dir.path().join("lib.rs")).unwrap(),
+            "fn a() {}\nfn b() {}\n"
+        );
+
+        assert!(switch_branch(&repo, "fresh", &checkout).unwrap());
+        assert_eq!(repo.head().unwrap().shorthand(), Some("fresh"));
+        assert_eq!(mainline_branch(&repo), "master");
+    }
+
+    #[test]
+    fn test_files_and_changes_are_read_against_a_revision() {
+        let (dir, repo) = repo_with_feature_branch();
+        assert_eq!(
+            file_at_revision(&repo, "feature", "lib.rs")
+                .unwrap()
+                .as_deref(),
+            Some("fn a() {}\nfn b() {}\n")
+        );
+        assert_eq!(file_at_revision(&repo, "HEAD", "missing.rs").unwrap(), None);
+
+        assert!(changed_files(&repo, "master").unwrap().is_empty());
+        fs::write(dir.path().join("new.rs"), "fn c() {}\n").unwrap();
+        assert_eq!(
+            changed_files(&repo, "feature").unwrap(),
+            ["lib.rs", "new.rs"]
+        );
+        assert_eq!(
+            commit_summaries(&repo, "master", "feature").unwrap(),
+            ["Add b"]
+        );
+    }
+
+    #[test]
+    fn test_merges_always_create_a_merge_commit() {
+        let (_dir, repo) = repo_with_feature_branch();
+        let signature = Signature::now("Borg Agent", "borg@example.com").unwrap();
+
+        let merge_commit = merge_into(
+            &repo,
+            "feature",
+            "master",
+            "Merge feature",
+            &signature,
+            None,
+            &CheckoutConfig::default(),
+        )
+        .unwrap();
+
+        let head = repo.head().unwrap().peel_to_commit().unwrap();
+        assert_eq!(merge_commit, Some(head.id().to_string()));
+        assert_eq!(head.message(), Some("Merge feature"));
+        assert_eq!(head.parent_count(), 2);
+        // Merging again has nothing to do
+        let merge_commit = merge_into(
+            &repo,
+            "feature",
+            "master",
+            "Merge again",
+            &signature,
+            None,
+            &CheckoutConfig::default(),
+        )
+        .unwrap();
+        assert_eq!(merge_commit, None);
+        assert_eq!(
+            repo.head().unwrap().peel_to_commit().unwrap().id(),
+            head.id()
+        );
+    }
+
+    #[test]
+    fn test_stacked_branches_are_restacked_once_their_base_merges() {
+        let (dir, repo) = repo_with_feature_branch();
+        let path = dir.path();
+        create_branch_from(&repo, "stacked", "feature").unwrap();
+        run_git(path, &["checkout", "stacked"]);
+        fs::write(path.join("c.rs"), "fn c() {}\n").unwrap();
+        run_git(path, &["add", "."]);
+        run_git(
+            path,
+            &["commit", "--author", "Dev <dev@example.com>", "-m", "Add c"],
+        );
+        run_git(path, &["checkout", "master"]);
+        assert!(!is_merged(&repo, "feature", "master").unwrap());
+        assert!(is_merged(&repo, "feature", "stacked").unwrap());
+
+        let signature = Signature::now("Borg Agent", "borg@example.com").unwrap();
+        merge_into(
+            &repo,
+            "feature",
+            "master",
+            "Merge feature",
+            &signature,
+            None,
+            &CheckoutConfig::default(),
+        )
+        .unwrap();
+        assert!(is_merged(&repo, "feature", "master").unwrap());
+
+        let checkout = CheckoutConfig::default();
+        let tip = restack_branch(
+            &repo, "stacked", "feature", "master", &signature, None, &checkout,
+        )
+        .unwrap();
+        let restacked = repo
+            .find_commit(git2::Oid::from_str(&tip).unwrap())
+            .unwrap();
+        let master = repo.head().unwrap().peel_to_commit().unwrap();
+        assert_eq!(restacked.parent_id(0).unwrap(), master.id());
+        assert_eq!(restacked.author().email(), Some("dev@example.com"));
+        assert_eq!(
+            commit_summaries(&repo, "master", "stacked").unwrap(),
+            ["Add c"]
+        );
+        assert_eq!(
+            file_at_revision(&repo, "stacked", "lib.rs")
+                .unwrap()
+                .as_deref(),
+            Some("fn a() {}\nfn b() {}\n")
+        );
+
+        // A checked out branch has its new tip checked out
+        run_git(path, &["commit", "--allow-empty", "-m", "Empty"]);
+        run_git(path, &["checkout", "stacked"]);
+        restack_branch(
+            &repo, "stacked", "master", "master", &signature, None, &checkout,
+        )
+        .unwrap();
+        assert!(is_merged(&repo, "master", "stacked").unwrap());
+        assert_eq!(
+            repo.head().unwrap().peel_to_commit().unwrap().summary(),
+            Some("Add c")
+        );
+        assert!(path.join("c.rs").exists());
+    }
+
+    #[test]
+    fn test_merges_are_reverted_against_the_mainline() {
+        let (dir, repo) = repo_with_feature_branch();
+        let signature = Signature::now("Borg Agent", "borg@example.com").unwrap();
+        let merge_commit = merge_into(
+            &repo,
+            "feature",
+            "master",
+            "Merge feature",
+            &signature,
+            None,
+            &CheckoutConfig::default(),
+        )
+        .unwrap()
+        .unwrap();
+
+        let revert =
+            revert_commit(&repo, &merge_commit, "Revert feature", &signature, None).unwrap();
+
+        let head = repo.head().unwrap().peel_to_commit().unwrap();
+        assert_eq!(head.id().to_string(), revert);
+        assert_eq!(head.parent_id(0).unwrap().to_string(), merge_commit);
+        assert_eq!(
+            fs::read_to_string(dir.path().join("lib.rs")).unwrap(),
+            "fn a() {}\n"
+        );
+        assert_eq!(repo.state(), git2::RepositoryState::Clean);
+    }
+
+    #[test]
+    fn test_conflicting_reverts_leave_the_tree_untouched() {
+        let (dir, repo) = repo_with_feature_branch();
+        let signature = Signature::now("Borg Agent", "borg@example.com").unwrap();
+        let merge_commit = merge_into(
+            &repo,
+            "feature",
+            "master",
+            "Merge feature",
+            &signature,
+            None,
+            &CheckoutConfig::default(),
+        )
+        .unwrap()
+        .unwrap();
+        fs::write(dir.path().join("lib.rs"), "fn a() {}\nfn b() { 1 }\n").unwrap();
+        run_git(dir.path(), &["commit", "-am", "Change b"]);
+        let repo = Repository::open(dir.path()).unwrap();
+
+        let error =
+            revert_commit(&repo, &merge_commit, "Revert feature", &signature, None).unwrap_err();
+
+        assert!(error.to_string().contains("conflicts"), "{:#}", error);
+        assert_eq!(
+            fs::read_to_string(dir.path().join("lib.rs")).unwrap(),
+            "fn a() {}\nfn b() { 1 }\n"
+        );
+        assert_eq!(repo.state(), git2::RepositoryState::Clean);
+        assert!(repo.statuses(None).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_branches_are_pushed_to_the_remote() {
+        let (dir, repo) = repo_with_feature_branch();
+        let remote_dir = tempfile::tempdir().unwrap();
+        run_git(remote_dir.path(), &["init", "--bare", "-b", "master"]);
+        let remote_path = remote_dir.path().to_str().unwrap();
+        run_git(dir.path(), &["remote", "add", "origin", remote_path]);
+
+        assert_eq!(remote_url(&repo, "origin").unwrap(), remote_path);
+        push_branch(&repo, "origin", "feature", true, None).unwrap();
+
+        let remote = Repository::open_bare(remote_dir.path()).unwrap();
+        let pushed = remote
+            .find_branch("feature", BranchType::Local)
+            .unwrap()
+            .get()
+            .peel_to_commit()
+            .unwrap();
+        assert_eq!(pushed.summary(), Some("Add b"));
+        assert!(push_branch(&repo, "upstream", "feature", true, None).is_err());
+    }
+
+    #[test]
+    fn test_fetched_branches_are_fast_forwarded_unless_they_diverged() {
+        let (dir, repo) = repo_with_feature_branch();
+        let remote_dir = tempfile::tempdir().unwrap();
+        run_git(remote_dir.path(), &["init", "--bare", "-b", "master"]);
+        let remote_path = remote_dir.path().to_str().unwrap();
+        run_git(dir.path(), &["remote", "add", "origin", remote_path]);
+        push_branch(&repo, "origin", "master", false, None).unwrap();
+        let checkout = CheckoutConfig::default();
+
+        fetch(&repo, "origin", None).unwrap();
+        assert_eq!(
+            fast_forward(&repo, "master", "origin", &checkout).unwrap(),
+            BranchSync::UpToDate
+        );
+        assert_eq!(
+            fast_forward(&repo, "feature", "origin", &checkout).unwrap(),
+            BranchSync::Ahead
+        );
+
+        // Someone else pushes to the remote
+        let other = tempfile::tempdir().unwrap();
+        run_git(other.path(), &["clone", remote_path, "."]);
+        run_git(other.path(), &["config", "user.name", "Other"]);
+        run_git(other.path(), &["config", "user.email", "other@example.com"]);
+        fs::write(other.path().join("d.rs"), "fn d() {}\n").unwrap();
+        run_git(other.path(), &["add", "."]);
+        run_git(other.path(), &["commit", "-m", "Add d"]);
+        run_git(other.path(), &["push", "origin", "master"]);
+
+        fetch(&repo, "origin", None).unwrap();
+        assert_eq!(
+            fast_forward(&repo, "master", "origin", &checkout).unwrap(),
+            BranchSync::FastForwarded
+        );
+        // master is checked out, so its working tree follows
+        assert!(dir.path().join("d.rs").exists());
+
+        // A local commit on top of another remote one diverges
+        run_git(other.path(), &["commit", "--allow-empty", "-m", "Remote"]);
+        run_git(other.path(), &["push", "origin", "master"]);
+        run_git(dir.path(), &["commit", "--allow-empty", "-m", "Local"]);
+        fetch(&repo, "origin", None).unwrap();
+        let local = repo.head().unwrap().target();
+        assert_eq!(
+            fast_forward(&repo, "master", "origin", &checkout).unwrap(),
+            BranchSync::Diverged
+        );
+        assert_eq!(repo.head().unwrap().target(), local);
+        assert!(push_branch(&repo, "origin", "master", false, None).is_err());
+    }
+}
@@ -0,0 +1,305 @@
+//! Rolling back merged improvements.
+//!
+//! Every local merge of an improvement branch is recorded per goal in the
+//! `merges` collection. When validation after the merge finds a regression,
+//! or a human runs `borg rollback <goal-id>`, the merge commit is reverted
+//! on the mainline and the goal is reopened with the reason, so the next
+//! attempt knows what went wrong.
+
+use anyhow::{anyhow, Context, Result};
+use chrono::{DateTime, Utc};
+use log::info;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+use crate::core::optimization::{GoalStatus, OptimizationGoal};
+use crate::database::{DatabaseError, DatabaseInterface, DatabaseManager};
+use crate::version_control::git::GitManager;
+
+/// The merge that landed a goal
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MergeRecord {
+    /// The goal id; a goal's latest merge replaces earlier ones
+    pub id: String,
+
+    /// The merged branch
+    pub branch: String,
+
+    /// The branch it was merged into
+    pub target: String,
+
+    /// The merge commit
+    pub merge_commit: String,
+
+    /// When it was merged
+    pub merged_at: DateTime<Utc>,
+
+    /// The revert of the merge, once rolled back
+    #[serde(default)]
+    pub reverted: Option<Revert>,
+}
+
+impl MergeRecord {
+    /// Merge of `goal_id` from `branch` into `target` by `merge_commit`,
+    /// made now
+    pub fn new(goal_id: &str, branch: &str, target: &str, merge_commit: &str) -> Self {
+        Self {
+            id: goal_id.to_string(),
+            branch: branch.to_string(),
+            target: target.to_string(),
+            merge_commit: merge_commit.to_string(),
+            merged_at: Utc::now(),
+            reverted: None,
+        }
+    }
+}
+
+/// A revert of a recorded merge
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Revert {
+    /// The revert commit
+    pub commit: String,
+
+    /// Why the merge was rolled back
+    pub reason: String,
+
+    /// When it was rolled back
+    pub reverted_at: DateTime<Utc>,
+}
+
+/// Persists the merge of each goal
+pub struct MergeLedger {
+    store: Arc<dyn DatabaseInterface<MergeRecord>>,
+}
+
+impl MergeLedger {
+    /// Merges kept in `store`
+    pub fn new(store: Arc<dyn DatabaseInterface<MergeRecord>>) -> Self {
+        Self { store }
+    }
+
+    /// Save a merge, replacing the one recorded for its goal
+    pub async fn save(&self, record: &MergeRecord) -> Result<()> {
+        let saved = match self.get(&record.id).await? {
+            Some(_) => self.store.update(record.clone(), None).await,
+            None => self.store.insert(record.clone()).await,
+        };
+        saved.context("Failed to save merge record")?;
+        Ok(())
+    }
+
+    /// The recorded merge of a goal
+    pub async fn get(&self, goal_id: &str) -> Result<Option<MergeRecord>> {
+        match self.store.get(&goal_id.to_string()).await {
+            Ok(record) => Ok(Some(record.entity)),
+            Err(DatabaseError::NotFound(_)) => Ok(None),
+            Err(e) => Err(e).context("Failed to load merge record"),
+        }
+    }
+
+    /// Revert the recorded merge of `goal_id` on the current branch of
+    /// `git`, which must be the branch it was merged into
+    pub async fn roll_back(
+        &self,
+        git: &dyn GitManager,
+        goal_id: &str,
+        reason: &str,
+    ) -> Result<MergeRecord> {
+        let mut record = self
+            .get(goal_id)
+            .await?
+            .ok_or_else(|| anyhow!("No merge recorded for goal {}", goal_id))?;
+        revert_merge(git, &mut record, reason).await?;
+        self.save(&record).await?;
+        Ok(record)
+    }
+}
+
+/// Revert a merge on the current branch of `git`, which must be the branch
+/// it was merged into, and record the revert in `record`
+pub async fn revert_merge(
+    git: &dyn GitManager,
+    record: &mut MergeRecord,
+    reason: &str,
+) -> Result<()> {
+    if let Some(revert) = &record.reverted {
+        return Err(anyhow!(
+            "The merge of goal {} was already reverted in {}",
+            record.id,
+            revert.commit
+        ));
+    }
+    let current = git.get_current_branch().await?;
+    if current != record.target {
+        return Err(anyhow!(
+            "Goal {} was merged into {}, but {} is checked out",
+            record.id,
+            record.target,
+            current
+        ));
+    }
+
+    let message = format!(
+        "Revert merge of {} for goal {}\n\n{}\n\nThis reverts commit {}.",
+        record.branch, record.id, reason, record.merge_commit
+    );
+    let commit = git.revert_commit(&record.merge_commit, &message).await?;
+    info!(
+        "Rolled back goal {}: reverted {} in {}",
+        record.id, record.merge_commit, commit
+    );
+
+    record.reverted = Some(Revert {
+        commit,
+        reason: reason.to_string(),
+        reverted_at: Utc::now(),
+    });
+    Ok(())
+}
+
+/// Reopen a goal whose merge was rolled back, keeping why in its test
+/// results and implementation notes for the next attempt
+pub fn reopen_goal(goal: &mut OptimizationGoal, record: &MergeRecord) {
+    let Some(revert) = &record.reverted else {
+        return;
+    };
+    goal.update_status(GoalStatus::NotStarted);
+    goal.test_results = Some(revert.reason.clone());
+    let note = format!(
+        "Merge {} of branch {} was reverted in {}: {}",
+        record.merge_commit, record.branch, revert.commit, revert.reason
+    );
+    goal.implementation_notes = Some(match goal.implementation_notes.take() {
+        Some(notes) if !notes.is_empty() => format!("{}\n{}", notes, note),
+        _ => note,
+    });
+}
+
+/// Revert the recorded merge of `goal_id` on the current branch of `git`
+/// and reopen the goal if it is stored, recording both together; returns
+/// the updated record and whether a goal was reopened
+pub async fn roll_back_goal(
+    database: &DatabaseManager,
+    git: &dyn GitManager,
+    goal_id: &str,
+    reason: &str,
+) -> Result<(MergeRecord, bool)> {
+    let merges = database.merges();
+    let mut record = MergeLedger::new(merges.clone())
+        .get(goal_id)
+        .await?
+        .ok_or_else(|| anyhow!("No merge recorded for goal {}", goal_id))?;
+    revert_merge(git, &mut record, reason).await?;
+
+    // The revert and the reopened goal are recorded together
+    let mut txn = database.begin();
+    txn.update(merges.as_ref(), record.clone(), None)?;
+    let goals = database.goals();
+    let stored = goals.get(&goal_id.to_string()).await.ok();
+    if let Some(stored) = &stored {
+        let mut goal = stored.entity.clone();
+        reopen_goal(&mut goal, &record);
+        txn.update(goals.as_ref(), goal, Some(stored.version))?;
+    }
+    txn.commit()
+        .await
+        .context("Failed to record the rollback")?;
+    Ok((record, stored.is_some()))
+}
+
+/// The merge ledger of the current services, if there is one
+pub fn global() -> Option<Arc<MergeLedger>> {
+    crate::core::services::Services::current().merges.clone()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::config::CheckoutConfig;
+    use crate::database::FileDb;
+    use crate::version_control::git_implementation::GitImplementation;
+    use std::fs;
+    use std::path::Path;
+
+    fn run_git(dir: &Path, args: &[&str]) {
+        let output = std::process::Command::new("git")
+            .args(args)
+            .current_dir(dir)
+            .output()
+            .unwrap();
+        assert!(output.status.success(), "git {:?} failed", args);
+    }
+
+    #[tokio::test]
+    async fn test_recorded_merges_are_rolled_back_once() {
+        let repo = tempfile::tempdir().unwrap();
+        let path = repo.path();
+        run_git(path, &["init", "-b", "master"]);
+        run_git(path, &["config", "user.name", "Test"]);
+        run_git(path, &["config", "user.email", "test@example.com"]);
+        fs::write(path.join("lib.rs"), "fn a() {}\n").unwrap();
+        run_git(path, &["add", "."]);
+        run_git(path, &["commit", "-m", "Initial commit"]);
+        run_git(path, &["checkout", "-b", "improvement/goal-1"]);
+        fs::write(path.join("lib.rs"), "fn a() {}\nfn b() {}\n").unwrap();
+        run_git(path, &["commit", "-am", "Add b"]);
+
+        let git = GitImplementation::new(path).unwrap();
+        let merge_commit = git
+            .merge_into(
+                "improvement/goal-1",
+                "master",
+                "Merge b",
+                &CheckoutConfig::default(),
+            )
+            .await
+            .unwrap()
+            .unwrap();
+
+        let data = tempfile::tempdir().unwrap();
+        let ledger = MergeLedger::new(Arc::new(
+            FileDb::<MergeRecord>::new(data.path(), "merges")
+                .await
+                .unwrap(),
+        ));
+        ledger
+            .save(&MergeRecord::new(
+                "goal-1",
+                "improvement/goal-1",
+                "master",
+                &merge_commit,
+            ))
+            .await
+            .unwrap();
+
+        let record = ledger
+            .roll_back(&git, "goal-1", "tests on master failed")
+            .await
+            .unwrap();
+
+        let revert = record.reverted.clone().unwrap();
+        assert_eq!(revert.reason, "tests on master failed");
+        assert_eq!(
+            fs::read_to_string(path.join("lib.rs")).unwrap(),
+            "fn a() {}\n"
+        );
+        assert_eq!(ledger.get("goal-1").await.unwrap(), Some(record.clone()));
+        assert!(ledger
+            .roll_back(&git, "goal-1", "again")
+            .await
+            .unwrap_err()
+            .to_string()
+            .contains("already reverted"));
+        assert!(ledger.roll_back(&git, "goal-2", "none").await.is_err());
+
+        let mut goal = OptimizationGoal::new("goal-1", "Add b", "Add function b");
+        goal.update_status(GoalStatus::Completed);
+        reopen_goal(&mut goal, &record);
+        assert_eq!(goal.status, GoalStatus::NotStarted);
+        assert_eq!(goal.test_results.as_deref(), Some("tests on master failed"));
+        assert!(goal
+            .implementation_notes
+            .unwrap()
+            .contains(&format!("was reverted in {}", revert.commit)));
+    }
+}
@@ -0,0 +1,242 @@
+//! Signing the commits the agent creates.
+//!
+//! Signatures are made the way git makes them: the commit is serialized,
+//! signed by `gpg` or `ssh-keygen -Y sign`, and stored in the `gpgsig`
+//! header, so `git verify-commit` and forge signed-commit policies accept
+//! them.
+
+use anyhow::{anyhow, Context, Result};
+use git2::{Commit, Oid, Repository, Signature, Tree};
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+use crate::core::config::{SigningConfig, SigningFormat};
+
+/// Signs serialized commits with an external program
+#[derive(Debug, Clone)]
+pub struct CommitSigner {
+    format: SigningFormat,
+    key: Option<String>,
+    program: String,
+}
+
+impl CommitSigner {
+    /// Signer for `config`; `None` when signing is off
+    pub fn from_config(config: &SigningConfig) -> Result<Option<Self>> {
+        let program = match config.format {
+            SigningFormat::None => return Ok(None),
+            SigningFormat::Gpg => "gpg",
+            SigningFormat::Ssh => {
+                if config.key.is_none() {
+                    return Err(anyhow!(
+                        "SSH commit signing needs git.signing.key, the path of the private key"
+                    ));
+                }
+                "ssh-keygen"
+            }
+        };
+        Ok(Some(Self {
+            format: config.format,
+            key: config.key.clone(),
+            program: config
+                .program
+                .clone()
+                .unwrap_or_else(|| program.to_string()),
+        }))
+    }
+
+    /// Armored signature of `payload`
+    pub fn sign(&self, payload: &str) -> Result<String> {
+        let mut command = Command::new(&self.program);
+        match (self.format, &self.key) {
+            (SigningFormat::Ssh, Some(key)) => {
+                command.args(["-Y", "sign", "-n", "git", "-f", key]);
+            }
+            (SigningFormat::Gpg, Some(key)) => {
+                command.args(["--status-fd=2", "-bsau", key]);
+            }
+            (SigningFormat::Gpg, None) => {
+                command.args(["--status-fd=2", "-bsa"]);
+            }
+            _ => return Err(anyhow!("Commit signing is not configured")),
+        }
+
+        let mut child = command
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .with_context(|| format!("Failed to run {}", self.program))?;
+        child
+            .stdin
+            .take()
+            .ok_or_else(|| anyhow!("Failed to open stdin of {}", self.program))?
+            .write_all(payload.as_bytes())
+            .with_context(|| format!("Failed to pass the commit to {}", self.program))?;
+        let output = child
+            .wait_with_output()
+            .with_context(|| format!("Failed to run {}", self.program))?;
+
+        let signature = String::from_utf8_lossy(&output.stdout).to_string();
+        if !output.status.success() || signature.trim().is_empty() {
+            return Err(anyhow!(
+                "{} failed to sign the commit: {}",
+                self.program,
+                String::from_utf8_lossy(&output.stderr).trim()
+            ));
+        }
+        Ok(signature)
+    }
+}
+
+/// Create a commit, signed by `signer` when given, without moving any
+/// reference to it
+pub fn create_commit(
+    repo: &Repository,
+    author: &Signature,
+    committer: &Signature,
+    message: &str,
+    tree: &Tree,
+    parents: &[&Commit],
+    signer: Option<&CommitSigner>,
+) -> Result<Oid> {
+    let Some(signer) = signer else {
+        return repo
+            .commit(None, author, committer, message, tree, parents)
+            .context("Failed to create commit");
+    };
+
+    let buffer = repo
+        .commit_create_buffer(author, committer, message, tree, parents)
+        .context("Failed to serialize commit")?;
+    let payload = buffer
+        .as_str()
+        .ok_or_else(|| anyhow!("Commit is not valid UTF-8"))?;
+    let commit_signature = signer.sign(payload)?;
+    repo.commit_signed(payload, &commit_signature, None)
+        .context("Failed to create signed commit")
+}
+
+/// Create a commit on HEAD, signed by `signer` when given, and move HEAD
+/// (or the branch it points at) to it
+pub fn commit_on_head(
+    repo: &Repository,
+    signature: &Signature,
+    message: &str,
+    tree: &Tree,
+    parents: &[&Commit],
+    signer: Option<&CommitSigner>,
+) -> Result<Oid> {
+    if signer.is_none() {
+        return repo
+            .commit(Some("HEAD"), signature, signature, message, tree, parents)
+            .context("Failed to create commit");
+    }
+    let oid = create_commit(repo, signature, signature, message, tree, parents, signer)?;
+
+    let reflog = format!("commit (signed): {}", message.lines().next().unwrap_or(""));
+    let head = repo.find_reference("HEAD").context("Failed to find HEAD")?;
+    match head.symbolic_target() {
+        Some(branch) => {
+            repo.reference(branch, oid, true, &reflog)
+                .with_context(|| format!("Failed to move {} to the new commit", branch))?;
+        }
+        None => repo
+            .set_head_detached(oid)
+            .context("Failed to move HEAD to the new commit")?,
+    }
+    Ok(oid)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+
+    fn run(dir: &Path, program: &str, args: &[&str]) {
+        let output = Command::new(program)
+            .args(args)
+            .current_dir(dir)
+            .output()
+            .unwrap();
+        assert!(output.status.success(), "{} {:?} failed", program, args);
+    }
+
+    #[test]
+    fn test_commits_are_signed_with_ssh_keys() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path();
+        run(path, "git", &["init", "-b", "master"]);
+        run(
+            path,
+            "ssh-keygen",
+            &[
+                "-q",
+                "-t",
+                "ed25519",
+                "-N",
+                "",
+                "-C",
+                "borg",
+                "-f",
+                "signing_key",
+            ],
+        );
+        let key = path.join("signing_key").to_string_lossy().to_string();
+        let signer = CommitSigner::from_config(&SigningConfig {
+            format: SigningFormat::Ssh,
+            key: Some(key),
+            program: None,
+        })
+        .unwrap()
+        .unwrap();
+
+        let repo = Repository::open(path).unwrap();
+        let tree = repo
+            .find_tree(repo.index().unwrap().write_tree().unwrap())
+            .unwrap();
+        let signature = Signature::now("Borg Agent", "borg@example.com").unwrap();
+        let oid = commit_on_head(&repo, &signature, "Signed", &tree, &[], Some(&signer)).unwrap();
+
+        assert_eq!(repo.head().unwrap().target(), Some(oid));
+        assert_eq!(repo.head().unwrap().shorthand(), Some("master"));
+        let (commit_signature, _) = repo.extract_signature(&oid, None).unwrap();
+        assert!(commit_signature
+            .as_str()
+            .unwrap()
+            .starts_with("-----BEGIN SSH SIGNATURE-----"));
+
+        // git itself accepts the signature
+        let public_key = std::fs::read_to_string(path.join("signing_key.pub")).unwrap();
+        std::fs::write(
+            path.join("allowed_signers"),
+            format!("borg@example.com {}", public_key),
+        )
+        .unwrap();
+        run(
+            path,
+            "git",
+            &[
+                "-c",
+                "gpg.format=ssh",
+                "-c",
+                "gpg.ssh.allowedSignersFile=allowed_signers",
+                "verify-commit",
+                "HEAD",
+            ],
+        );
+    }
+
+    #[test]
+    fn test_signing_is_off_by_default_and_ssh_needs_a_key() {
+        assert!(CommitSigner::from_config(&SigningConfig::default())
+            .unwrap()
+            .is_none());
+        assert!(CommitSigner::from_config(&SigningConfig {
+            format: SigningFormat::Ssh,
+            key: None,
+            program: None,
+        })
+        .is_err());
+    }
+}
@@ -0,0 +1,124 @@
+//! Commit message trailers, such as the `Co-authored-by:` trailers
+//! crediting the models behind a change.
+
+use regex::Regex;
+
+/// Email domain used for model co-author identities
+const MODEL_EMAIL_DOMAIN: &str = "models.borg.invalid";
+
+/// Build the `Co-authored-by:` trailer for a model
+pub fn co_authored_by_trailer(model: &str) -> String {
+    let local_part: String = model
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '.' || c == '-' || c == '_' {
+                c.to_ascii_lowercase()
+            } else {
+                '-'
+            }
+        })
+        .collect();
+    format!(
+        "Co-authored-by: {} <{}@{}>",
+        model, local_part, MODEL_EMAIL_DOMAIN
+    )
+}
+
+/// Append one trailer per distinct model to a commit message
+///
+/// Trailers are only added when more than one model participated; a change
+/// produced by a single model needs no extra credit. Models keep their first
+/// occurrence order and trailers already present in the message are not
+/// repeated.
+pub fn append_co_authored_by(message: &str, models: &[String]) -> String {
+    let mut distinct: Vec<&str> = Vec::new();
+    for model in models {
+        if !model.is_empty() && !distinct.contains(&model.as_str()) {
+            distinct.push(model);
+        }
+    }
+    if distinct.len() < 2 {
+        return message.to_string();
+    }
+
+    let trailers: Vec<String> = distinct.into_iter().map(co_authored_by_trailer).collect();
+    append_trailers(message, &trailers)
+}
+
+/// Whether every line of `paragraph` is a `Key: value` trailer
+fn is_trailer_block(paragraph: &str) -> bool {
+    let re = Regex::new(r"^[A-Za-z][\w-]*: \S").unwrap();
+    !paragraph.trim().is_empty() && paragraph.trim().lines().all(|line| re.is_match(line))
+}
+
+/// Append `trailers` that are not already in `message`
+///
+/// They join the message's closing trailer block when it has one, so git
+/// still reads every trailer, and start a new paragraph otherwise.
+pub fn append_trailers(message: &str, trailers: &[String]) -> String {
+    let trailers: Vec<&str> = trailers
+        .iter()
+        .map(String::as_str)
+        .filter(|trailer| !message.contains(trailer))
+        .collect();
+    if trailers.is_empty() {
+        return message.to_string();
+    }
+
+    let message = message.trim_end();
+    let last_paragraph = message.rsplit("\n\n").next().unwrap_or_default();
+    let has_body = message.contains("\n\n");
+    if has_body && is_trailer_block(last_paragraph) {
+        format!("{}\n{}\n", message, trailers.join("\n"))
+    } else {
+        format!("{}\n\n{}\n", message, trailers.join("\n"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_trailers_added_for_multiple_models() {
+        let models = vec![
+            "anthropic/claude-sonnet-4".to_string(),
+            "gpt-4o".to_string(),
+            "gpt-4o".to_string(),
+        ];
+
+        let message = append_co_authored_by("Add caching layer\n", &models);
+
+        assert_eq!(
+            message,
+            "Add caching layer\n\n\
+             Co-authored-by: anthropic/claude-sonnet-4 <anthropic-claude-sonnet-4@models.borg.invalid>\n\
+             Co-authored-by: gpt-4o <gpt-4o@models.borg.invalid>\n"
+        );
+        assert_eq!(append_co_authored_by(&message, &models), message);
+    }
+
+    #[test]
+    fn test_trailers_join_the_closing_trailer_block() {
+        let trailers = vec!["Goal-Id: goal-1".to_string()];
+        assert_eq!(
+            append_trailers("Fix bug\n\nBorg-Attempt: 2\n", &trailers),
+            "Fix bug\n\nBorg-Attempt: 2\nGoal-Id: goal-1\n"
+        );
+        assert_eq!(
+            append_trailers("Fix bug\n\nThe cache was stale.", &trailers),
+            "Fix bug\n\nThe cache was stale.\n\nGoal-Id: goal-1\n"
+        );
+        // A subject alone is never a trailer block
+        assert_eq!(
+            append_trailers("Docs: fix typo", &trailers),
+            "Docs: fix typo\n\nGoal-Id: goal-1\n"
+        );
+    }
+
+    #[test]
+    fn test_single_model_adds_no_trailers() {
+        let models = vec!["gpt-4o".to_string()];
+        assert_eq!(append_co_authored_by("Fix bug", &models), "Fix bug");
+    }
+}
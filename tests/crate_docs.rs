@@ -0,0 +1,168 @@
+// File: tests/crate_docs.rs
+use borg::code_generation::crate_docs::{
+    page_candidates, rustdoc_text, CrateInfoTool, CrateSearchTool, CratesClient, DocsRsTool,
+};
+use borg::code_generation::llm_tool::LlmTool;
+use httpmock::prelude::*;
+use serde_json::json;
+
+fn client(server: &MockServer) -> CratesClient {
+    CratesClient::new().with_base_urls(server.url("/api/v1"), server.base_url())
+}
+
+#[tokio::test]
+async fn crate_search_lists_matches() {
+    let server = MockServer::start_async().await;
+    let m = server
+        .mock_async(|when, then| {
+            when.method(GET)
+                .path("/api/v1/crates")
+                .query_param("q", "http client")
+                .query_param("per_page", "2");
+            then.status(200).json_body(json!({
+                "crates": [
+                    {"name": "reqwest", "max_stable_version": "0.12.9", "downloads": 250000000,
+                     "description": "higher level\n  HTTP client library"},
+                    {"name": "ureq", "max_stable_version": "2.10.1", "downloads": 60000000}
+                ],
+                "meta": {"total": 2}
+            }));
+        })
+        .await;
+
+    let tool = CrateSearchTool::new(client(&server));
+    let out = tool
+        .execute_positional(&["http client", "2"])
+        .await
+        .unwrap();
+    m.assert_async().await;
+    assert_eq!(
+        out,
+        "Crates matching 'http client':\n\
+         1. reqwest 0.12.9 (250000000 downloads)\n   higher level HTTP client library\n\
+         2. ureq 2.10.1 (60000000 downloads)\n"
+    );
+}
+
+#[tokio::test]
+async fn crate_info_reports_versions_and_features() {
+    let server = MockServer::start_async().await;
+    server
+        .mock_async(|when, then| {
+            when.method(GET).path("/api/v1/crates/tokio");
+            then.status(200).json_body(json!({
+                "crate": {
+                    "name": "tokio", "max_stable_version": "1.41.0",
+                    "description": "An event-driven, non-blocking I/O platform",
+                    "repository": "https://github.com/tokio-rs/tokio"
+                },
+                "versions": [
+                    {"num": "1.42.0-rc.1", "yanked": false},
+                    {"num": "1.41.0", "yanked": false, "rust_version": "1.70",
+                     "features": {"full": ["fs", "rt"], "fs": [], "rt": []}},
+                    {"num": "1.40.1", "yanked": true},
+                    {"num": "1.40.0", "yanked": false}
+                ]
+            }));
+        })
+        .await;
+    // Versions listed without features are looked up individually
+    server
+        .mock_async(|when, then| {
+            when.method(GET).path("/api/v1/crates/tokio/1.40.0");
+            then.status(200)
+                .json_body(json!({"version": {"num": "1.40.0", "features": {}}}));
+        })
+        .await;
+
+    let tool = CrateInfoTool::new(client(&server));
+    let latest = tool.execute_positional(&["tokio"]).await.unwrap();
+    assert_eq!(
+        latest,
+        "tokio 1.41.0 (latest stable)\n\
+         Description: An event-driven, non-blocking I/O platform\n\
+         Repository: https://github.com/tokio-rs/tokio\n\
+         Minimum Rust version: 1.70\n\
+         Recent versions: 1.42.0-rc.1, 1.41.0, 1.40.0\n\
+         Features:\n  fs = []\n  full = [fs, rt]\n  rt = []\n"
+    );
+
+    let older = tool.execute_positional(&["tokio", "1.40.0"]).await.unwrap();
+    assert!(older.starts_with("tokio 1.40.0\n"));
+    assert!(older.ends_with("Features: none\n"));
+
+    let missing = tool.execute_positional(&["tokio", "9.9.9"]).await;
+    assert!(missing
+        .unwrap_err()
+        .to_string()
+        .contains("Crate 'tokio' has no version 9.9.9"));
+}
+
+#[tokio::test]
+async fn docs_rs_resolves_item_kind_and_cleans_page() {
+    let server = MockServer::start_async().await;
+    let page = server
+        .mock_async(|when, then| {
+            when.method(GET)
+                .path("/tokio/latest/tokio/sync/struct.Mutex.html");
+            then.status(200).body(
+                "<html><head><script>var x = 1;</script></head><body>\
+                 <nav class=\"sidebar\">Crate items</nav>\
+                 <main><section id=\"main-content\" class=\"content\">\
+                 <h1>Struct <span>tokio</span>::<wbr>sync::<wbr>Mutex<a class=\"src\" href=\"#\">Source</a></h1>\
+                 <pre class=\"rust item-decl\"><code>pub struct Mutex&lt;T: ?Sized&gt; { /* private fields */ }</code></pre>\
+                 <div class=\"docblock\"><p>An asynchronous <code>Mutex</code>-like type.</p>\
+                 <ul><li>fair</li><li>cancel safe</li></ul></div>\
+                 </section></main><footer>docs.rs</footer></body></html>",
+            );
+        })
+        .await;
+    let missing = server
+        .mock_async(|when, then| {
+            when.method(GET).path_contains("/tokio/latest/tokio/sync/");
+            then.status(404);
+        })
+        .await;
+
+    let tool = DocsRsTool::new(client(&server));
+    let out = tool
+        .execute_positional(&["tokio", "tokio::sync::Mutex"])
+        .await
+        .unwrap();
+    page.assert_async().await;
+    assert_eq!(missing.hits_async().await, 0);
+    assert_eq!(
+        out,
+        format!(
+            "{}/tokio/latest/tokio/sync/struct.Mutex.html\n\n\
+             Struct tokio::sync::Mutex\n\
+             pub struct Mutex<T: ?Sized> {{ /* private fields */ }}\n\
+             An asynchronous Mutex-like type.\n\
+             - fair\n\
+             - cancel safe",
+            server.base_url()
+        )
+    );
+
+    let err = tool
+        .execute_positional(&["tokio", "sync::Nope"])
+        .await
+        .unwrap_err();
+    assert!(err.to_string().contains("No docs.rs page for 'sync::Nope'"));
+}
+
+#[test]
+fn page_candidates_follow_item_case() {
+    assert_eq!(page_candidates("serde-json", ""), vec!["index.html"]);
+    let types = page_candidates("serde-json", "serde_json::value::Value");
+    assert_eq!(types[0], "value/struct.Value.html");
+    assert_eq!(types.last().unwrap(), "value/Value/index.html");
+    let lowercase = page_candidates("tokio", "crate::spawn");
+    assert_eq!(lowercase[0], "spawn/index.html");
+    assert!(lowercase.contains(&"fn.spawn.html".to_string()));
+}
+
+#[test]
+fn rustdoc_text_without_main_content_uses_whole_page() {
+    assert_eq!(rustdoc_text("<p>a &amp; b</p>\n\n\n<p>c</p>"), "a & b\n\nc");
+}
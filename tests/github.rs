@@ -0,0 +1,226 @@
+use borg::core::config::GitHubConfig;
+use borg::version_control::github::{
+    CiStatus, GitHubClient, MergeReadiness, PullRequest, PullRequestDraft,
+};
+use httpmock::prelude::*;
+use serde_json::json;
+
+fn client(server: &MockServer) -> GitHubClient {
+    GitHubClient::new("rhernaus/borg", Some("secret".to_string())).with_base_url(server.base_url())
+}
+
+fn draft() -> PullRequestDraft {
+    PullRequestDraft {
+        title: "Speed up parsing".to_string(),
+        body: "## Goal\n\nFaster".to_string(),
+        head: "borg/improvement/goal-1".to_string(),
+        base: "main".to_string(),
+        labels: vec!["performance".to_string()],
+    }
+}
+
+fn pull_request() -> PullRequest {
+    PullRequest {
+        number: 7,
+        html_url: "https://github.com/rhernaus/borg/pull/7".to_string(),
+        head_sha: "abc123".to_string(),
+    }
+}
+
+fn config(require_ci: bool, require_approval: bool) -> GitHubConfig {
+    GitHubConfig {
+        require_ci,
+        require_approval,
+        poll_interval_seconds: 0,
+        timeout_seconds: 0,
+        ..GitHubConfig::default()
+    }
+}
+
+#[tokio::test]
+async fn pull_requests_are_opened_and_labelled() {
+    let server = MockServer::start_async().await;
+    let create = server
+        .mock_async(|when, then| {
+            when.method(POST)
+                .path("/repos/rhernaus/borg/pulls")
+                .header("authorization", "Bearer secret")
+                .json_body(json!({
+                    "title": "Speed up parsing",
+                    "body": "## Goal\n\nFaster",
+                    "head": "borg/improvement/goal-1",
+                    "base": "main",
+                }));
+            then.status(201).json_body(json!({
+                "number": 7,
+                "html_url": "https://github.com/rhernaus/borg/pull/7",
+                "head": {"sha": "abc123"}
+            }));
+        })
+        .await;
+    let label = server
+        .mock_async(|when, then| {
+            when.method(POST)
+                .path("/repos/rhernaus/borg/issues/7/labels")
+                .json_body(json!({"labels": ["performance"]}));
+            then.status(200).json_body(json!([{"name": "performance"}]));
+        })
+        .await;
+
+    let pr = client(&server).open_pull_request(&draft()).await.unwrap();
+    create.assert_async().await;
+    label.assert_async().await;
+    assert_eq!(pr.number, 7);
+    assert_eq!(pr.head_sha, "abc123");
+}
+
+#[tokio::test]
+async fn an_open_pull_request_for_the_branch_is_reused() {
+    let server = MockServer::start_async().await;
+    server
+        .mock_async(|when, then| {
+            when.method(POST).path("/repos/rhernaus/borg/pulls");
+            then.status(422)
+                .json_body(json!({"message": "Validation Failed"}));
+        })
+        .await;
+    server
+        .mock_async(|when, then| {
+            when.method(GET)
+                .path("/repos/rhernaus/borg/pulls")
+                .query_param("state", "open")
+                .query_param("head", "rhernaus:borg/improvement/goal-1");
+            then.status(200).json_body(json!([{
+                "number": 5,
+                "html_url": "https://github.com/rhernaus/borg/pull/5",
+                "head": {"sha": "def456"}
+            }]));
+        })
+        .await;
+    server
+        .mock_async(|when, then| {
+            when.method(POST)
+                .path("/repos/rhernaus/borg/issues/5/labels");
+            then.status(200).json_body(json!([]));
+        })
+        .await;
+
+    let pr = client(&server).open_pull_request(&draft()).await.unwrap();
+    assert_eq!(pr.number, 5);
+    assert_eq!(pr.head_sha, "def456");
+}
+
+#[tokio::test]
+async fn ci_status_combines_statuses_and_check_runs() {
+    let server = MockServer::start_async().await;
+    server
+        .mock_async(|when, then| {
+            when.method(GET)
+                .path("/repos/rhernaus/borg/commits/abc123/status");
+            then.status(200)
+                .json_body(json!({"state": "success", "total_count": 1}));
+        })
+        .await;
+    let checks = server
+        .mock_async(|when, then| {
+            when.method(GET)
+                .path("/repos/rhernaus/borg/commits/abc123/check-runs");
+            then.status(200).json_body(json!({"check_runs": [
+                {"status": "completed", "conclusion": "success"},
+                {"status": "in_progress", "conclusion": null}
+            ]}));
+        })
+        .await;
+    let github = client(&server);
+    assert_eq!(github.ci_status("abc123").await.unwrap(), CiStatus::Pending);
+
+    checks.delete_async().await;
+    server
+        .mock_async(|when, then| {
+            when.method(GET)
+                .path("/repos/rhernaus/borg/commits/abc123/check-runs");
+            then.status(200).json_body(json!({"check_runs": [
+                {"status": "completed", "conclusion": "failure"}
+            ]}));
+        })
+        .await;
+    assert_eq!(github.ci_status("abc123").await.unwrap(), CiStatus::Failure);
+}
+
+#[tokio::test]
+async fn readiness_waits_for_checks_and_approval() {
+    let server = MockServer::start_async().await;
+    server
+        .mock_async(|when, then| {
+            when.method(GET)
+                .path("/repos/rhernaus/borg/commits/abc123/status");
+            then.status(200)
+                .json_body(json!({"state": "pending", "total_count": 0}));
+        })
+        .await;
+    server
+        .mock_async(|when, then| {
+            when.method(GET)
+                .path("/repos/rhernaus/borg/commits/abc123/check-runs");
+            then.status(200).json_body(json!({"check_runs": [
+                {"status": "completed", "conclusion": "success"}
+            ]}));
+        })
+        .await;
+    server
+        .mock_async(|when, then| {
+            when.method(GET)
+                .path("/repos/rhernaus/borg/pulls/7/reviews");
+            then.status(200).json_body(json!([
+                {"user": {"login": "alice"}, "state": "CHANGES_REQUESTED"},
+                {"user": {"login": "bob"}, "state": "APPROVED"},
+                {"user": {"login": "alice"}, "state": "COMMENTED"}
+            ]));
+        })
+        .await;
+    let github = client(&server);
+
+    assert_eq!(
+        github
+            .wait_until_ready(&pull_request(), &config(true, false))
+            .await
+            .unwrap(),
+        MergeReadiness::Ready
+    );
+    assert_eq!(
+        github
+            .wait_until_ready(&pull_request(), &config(true, true))
+            .await
+            .unwrap(),
+        MergeReadiness::TimedOut("timed out waiting for an approving review".to_string())
+    );
+}
+
+#[tokio::test]
+async fn pull_requests_are_merged_with_a_merge_commit() {
+    let server = MockServer::start_async().await;
+    let merge = server
+        .mock_async(|when, then| {
+            when.method(PUT)
+                .path("/repos/rhernaus/borg/pulls/7/merge")
+                .json_body(json!({
+                    "commit_title": "Speed up parsing (#7)",
+                    "merge_method": "merge",
+                }));
+            then.status(200).json_body(json!({"merged": true}));
+        })
+        .await;
+    server
+        .mock_async(|when, then| {
+            when.method(PUT).path("/repos/rhernaus/borg/pulls/8/merge");
+            then.status(405)
+                .json_body(json!({"message": "Pull Request is not mergeable"}));
+        })
+        .await;
+
+    let github = client(&server);
+    github.merge(7, "Speed up parsing (#7)").await.unwrap();
+    merge.assert_async().await;
+    let error = github.merge(8, "Other").await.unwrap_err();
+    assert!(format!("{:#}", error).contains("HTTP 405: Pull Request is not mergeable"));
+}
@@ -30,6 +30,14 @@ fn make_openrouter_config() -> LlmConfig {
         reasoning_budget_tokens: None,
         first_token_timeout_ms: None,
         stall_timeout_ms: None,
+        empty_response_retries: None,
+        stream_resume_retries: None,
+        deployment: None,
+        api_version: None,
+        retry: None,
+        rate_limit: None,
+        cache: None,
+        network: None,
     }
 }
 
@@ -49,6 +57,14 @@ fn make_unpinned_default_config() -> LlmConfig {
         reasoning_budget_tokens: None,
         first_token_timeout_ms: None,
         stall_timeout_ms: None,
+        empty_response_retries: None,
+        stream_resume_retries: None,
+        deployment: None,
+        api_version: None,
+        retry: None,
+        rate_limit: None,
+        cache: None,
+        network: None,
     }
 }
 
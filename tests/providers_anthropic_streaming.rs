@@ -46,6 +46,7 @@ fn make_req_with_tools() -> GenerateRequest {
         response_format: None,
         max_output_tokens: Some(123),
         metadata: None,
+        logprobs: None,
     }
 }
 
@@ -64,6 +65,14 @@ fn make_cfg(base: &str) -> borg::core::config::LlmConfig {
         reasoning_budget_tokens: None,
         first_token_timeout_ms: Some(5_000),
         stall_timeout_ms: Some(3_000),
+        empty_response_retries: None,
+        stream_resume_retries: None,
+        deployment: None,
+        api_version: None,
+        retry: None,
+        rate_limit: None,
+        cache: None,
+        network: None,
     }
 }
 
@@ -183,3 +192,347 @@ data: [DONE]
     assert!(saw_finished, "should emit Finished");
     assert_eq!(res.text, "Hello world");
 }
+
+#[tokio::test]
+async fn test_anthropic_streaming_tool_input_json_deltas() {
+    let server = MockServer::start();
+
+    let sse = r#"event: message_start
+data: {"type":"message_start","message":{"id":"msg_9"}}
+
+event: content_block_start
+data: {"type":"content_block_start","index":0,"content_block":{"type":"text","text":""}}
+
+event: content_block_delta
+data: {"type":"content_block_delta","index":0,"delta":{"type":"text_delta","text":"Searching"}}
+
+event: content_block_stop
+data: {"type":"content_block_stop","index":0}
+
+event: content_block_start
+data: {"type":"content_block_start","index":1,"content_block":{"type":"tool_use","id":"toolu_1","name":"search","input":{}}}
+
+event: content_block_delta
+data: {"type":"content_block_delta","index":1,"delta":{"type":"input_json_delta","partial_json":"{\"q\": \"bor"}}
+
+event: content_block_delta
+data: {"type":"content_block_delta","index":1,"delta":{"type":"input_json_delta","partial_json":"g\"}"}}
+
+event: content_block_stop
+data: {"type":"content_block_stop","index":1}
+
+event: message_stop
+data: {"type":"message_stop"}
+
+"#;
+
+    server.mock(|when, then| {
+        when.method(POST).path("/messages");
+        then.status(200)
+            .header("content-type", "text/event-stream")
+            .body(sse);
+    });
+
+    let cfg = make_cfg(&server.base_url());
+    let provider =
+        borg::providers::anthropic::AnthropicProvider::from_config(&cfg).expect("provider");
+
+    let mut fragments = Vec::new();
+    let mut calls = Vec::new();
+    let mut on_event = |ev: StreamEvent| match ev {
+        StreamEvent::ToolDelta(d) => fragments.push(d),
+        StreamEvent::ToolCall(tc) => calls.push(tc),
+        _ => {}
+    };
+    let res = provider
+        .generate_streaming(make_req_with_tools(), &mut on_event)
+        .await
+        .expect("stream ok");
+
+    assert_eq!(fragments.len(), 2);
+    assert_eq!(
+        calls.len(),
+        1,
+        "tool call emitted once, when its block closes"
+    );
+    assert_eq!(calls[0].id.as_deref(), Some("toolu_1"));
+    assert_eq!(calls[0].arguments_json, json!({"q": "borg"}));
+    assert_eq!(res.text, "Searching");
+    assert_eq!(res.tool_calls.len(), 1);
+}
+
+#[tokio::test]
+async fn test_anthropic_inline_image_uses_base64_source() {
+    let server = MockServer::start();
+    let m = server.mock(|when, then| {
+        when.method(POST)
+            .path("/messages")
+            .body_contains("\"type\":\"base64\"")
+            .body_contains("\"media_type\":\"image/png\"")
+            .body_contains("\"data\":\"iVBORw==\"");
+        then.status(200)
+            .header("content-type", "application/json")
+            .body(r#"{"content":[{"type":"text","text":"a diagram"}]}"#);
+    });
+
+    let mut req = make_req_with_tools();
+    req.messages[0].content[1] = ContentPart::image_base64("image/png", &[0x89, b'P', b'N', b'G']);
+
+    let provider =
+        borg::providers::anthropic::AnthropicProvider::from_config(&make_cfg(&server.base_url()))
+            .expect("provider");
+    let res = provider.generate(req).await.expect("generate ok");
+
+    m.assert();
+    assert_eq!(res.text, "a diagram");
+}
+
+#[tokio::test]
+async fn test_anthropic_json_schema_forces_tool_and_returns_its_input() {
+    let server = MockServer::start();
+    let m = server.mock(|when, then| {
+        when.method(POST)
+            .path("/messages")
+            .body_contains("\"name\":\"verdict\"")
+            .body_contains("\"tool_choice\":{\"name\":\"verdict\",\"type\":\"tool\"}");
+        then.status(200)
+            .header("content-type", "application/json")
+            .body(
+                r#"{"content":[{"type":"tool_use","id":"t1","name":"verdict","input":{"ok":true}}]}"#,
+            );
+    });
+
+    let mut req = make_req_with_tools();
+    req.tools = None;
+    req.tool_choice = None;
+    req.response_format = Some(borg::providers::ResponseFormat::json_schema(
+        "verdict".to_string(),
+        json!({"type": "object", "properties": {"ok": {"type": "boolean"}}}),
+    ));
+
+    let provider =
+        borg::providers::anthropic::AnthropicProvider::from_config(&make_cfg(&server.base_url()))
+            .expect("provider");
+    let res = provider.generate(req).await.expect("generate ok");
+
+    m.assert();
+    assert_eq!(res.text, r#"{"ok":true}"#);
+    assert!(res.tool_calls.is_empty());
+}
+
+#[tokio::test]
+async fn test_anthropic_health_reports_rejected_key() {
+    let server = MockServer::start();
+    server.mock(|when, then| {
+        when.method(GET)
+            .path("/models")
+            .header("x-api-key", "test-anthropic");
+        then.status(401)
+            .header("content-type", "application/json")
+            .body(r#"{"type":"error","error":{"type":"authentication_error"}}"#);
+    });
+
+    let provider =
+        borg::providers::anthropic::AnthropicProvider::from_config(&make_cfg(&server.base_url()))
+            .expect("provider");
+    match borg::providers::health::check_provider(&provider, "claude-3-7-sonnet").await {
+        borg::providers::health::CheckStatus::Failed(reason) => {
+            assert!(reason.contains("Authentication"), "{}", reason)
+        }
+        other => panic!("expected failure, got {:?}", other),
+    }
+}
+
+#[tokio::test]
+async fn test_anthropic_thinking_is_captured_as_reasoning() {
+    let server = MockServer::start();
+    let sse = "\
+event: content_block_start
+data: {\"type\":\"content_block_start\",\"index\":0,\"content_block\":{\"type\":\"thinking\",\"thinking\":\"\"}}
+
+event: content_block_delta
+data: {\"type\":\"content_block_delta\",\"index\":0,\"delta\":{\"type\":\"thinking_delta\",\"thinking\":\"Check the \"}}
+
+event: content_block_delta
+data: {\"type\":\"content_block_delta\",\"index\":0,\"delta\":{\"type\":\"thinking_delta\",\"thinking\":\"borrow.\"}}
+
+event: content_block_delta
+data: {\"type\":\"content_block_delta\",\"index\":1,\"delta\":{\"type\":\"text_delta\",\"text\":\"Done\"}}
+
+event: message_stop
+data: {\"type\":\"message_stop\"}
+";
+    server.mock(|when, then| {
+        when.method(POST)
+            .path("/messages")
+            .body_contains("\"stream\":true");
+        then.status(200)
+            .header("content-type", "text/event-stream")
+            .body(sse);
+    });
+    server.mock(|when, then| {
+        when.method(POST).path("/messages");
+        then.status(200)
+            .header("content-type", "application/json")
+            .json_body(json!({
+                "content": [
+                    {"type": "thinking", "thinking": "Weigh options.", "signature": "sig"},
+                    {"type": "text", "text": "Answer"}
+                ]
+            }));
+    });
+
+    let provider =
+        borg::providers::anthropic::AnthropicProvider::from_config(&make_cfg(&server.base_url()))
+            .expect("provider");
+
+    let mut req = make_req_with_tools();
+    req.tools = None;
+    req.tool_choice = None;
+    let res = provider.generate(req.clone()).await.expect("generate ok");
+    assert_eq!(res.text, "Answer");
+    assert_eq!(res.reasoning.as_deref(), Some("Weigh options."));
+
+    let mut thoughts = String::new();
+    let mut on_event = |ev: StreamEvent| {
+        if let StreamEvent::ReasoningDelta(d) = ev {
+            thoughts.push_str(&d);
+        }
+    };
+    let res = provider
+        .generate_streaming(req, &mut on_event)
+        .await
+        .expect("stream ok");
+    assert_eq!(thoughts, "Check the borrow.");
+    assert_eq!(res.text, "Done");
+    assert_eq!(res.reasoning.as_deref(), Some("Check the borrow."));
+}
+
+#[tokio::test]
+async fn test_anthropic_cache_breakpoints_and_cache_usage() {
+    let server = MockServer::start();
+    let sse = "\
+event: message_start
+data: {\"type\":\"message_start\",\"message\":{\"id\":\"msg_c\",\"usage\":{\"input_tokens\":12,\"cache_read_input_tokens\":900,\"cache_creation_input_tokens\":0,\"output_tokens\":1}}}
+
+event: content_block_delta
+data: {\"type\":\"content_block_delta\",\"index\":0,\"delta\":{\"type\":\"text_delta\",\"text\":\"ok\"}}
+
+event: message_delta
+data: {\"type\":\"message_delta\",\"usage\":{\"output_tokens\":4}}
+
+event: message_stop
+data: {\"type\":\"message_stop\"}
+";
+    let cached_block =
+        r#"{"cache_control":{"type":"ephemeral"},"text":"repository context","type":"text"}"#;
+    let stream_mock = server.mock(|when, then| {
+        when.method(POST)
+            .path("/messages")
+            .body_contains("\"stream\":true")
+            .body_contains(cached_block)
+            .body_contains(r#"{"text":"question","type":"text"}"#);
+        then.status(200)
+            .header("content-type", "text/event-stream")
+            .body(sse);
+    });
+    server.mock(|when, then| {
+        when.method(POST)
+            .path("/messages")
+            .body_contains(cached_block);
+        then.status(200)
+            .header("content-type", "application/json")
+            .json_body(json!({
+                "content": [{"type": "text", "text": "Answer"}],
+                "usage": {
+                    "input_tokens": 10,
+                    "output_tokens": 3,
+                    "cache_read_input_tokens": 0,
+                    "cache_creation_input_tokens": 800
+                }
+            }));
+    });
+
+    let provider =
+        borg::providers::anthropic::AnthropicProvider::from_config(&make_cfg(&server.base_url()))
+            .expect("provider");
+
+    let mut req = make_req_with_tools();
+    req.tools = None;
+    req.tool_choice = None;
+    req.messages = vec![Message {
+        role: Role::User,
+        content: vec![
+            ContentPart::Text {
+                text: "repository context".to_string(),
+            },
+            ContentPart::CacheBreakpoint,
+            ContentPart::Text {
+                text: "question".to_string(),
+            },
+        ],
+    }];
+
+    let res = provider.generate(req.clone()).await.expect("generate ok");
+    let usage = res.usage.expect("usage");
+    assert_eq!(usage.cache_write_tokens, Some(800));
+    assert_eq!(usage.cache_read_tokens, Some(0));
+
+    let mut streamed = Vec::new();
+    let mut on_event = |ev: StreamEvent| {
+        if let StreamEvent::Usage(u) = ev {
+            streamed.push(u);
+        }
+    };
+    let res = provider
+        .generate_streaming(req, &mut on_event)
+        .await
+        .expect("stream ok");
+    stream_mock.assert();
+    let usage = res.usage.expect("streaming usage");
+    assert_eq!(usage.prompt_tokens, Some(12));
+    assert_eq!(usage.completion_tokens, Some(4));
+    assert_eq!(usage.total_tokens, Some(16));
+    assert_eq!(usage.cache_read_tokens, Some(900));
+    assert_eq!(streamed.len(), 1);
+    assert_eq!(streamed[0].cache_read_tokens, Some(900));
+}
+
+#[tokio::test]
+async fn test_anthropic_tool_results_sent_as_user_blocks() {
+    let server = MockServer::start();
+    let m = server.mock(|when, then| {
+        when.method(POST)
+            .path("/messages")
+            .body_contains(r#"{"id":"toolu_1","input":{"path":"a.rs"},"name":"Read","type":"tool_use"}"#)
+            .body_contains(r#"{"content":[{"content":"missing","is_error":true,"tool_use_id":"toolu_1","type":"tool_result"}],"role":"user"}"#);
+        then.status(200)
+            .header("content-type", "application/json")
+            .body(r#"{ "content": [ { "type": "text", "text": "OK" } ] }"#);
+    });
+
+    let provider =
+        borg::providers::anthropic::AnthropicProvider::from_config(&make_cfg(&server.base_url()))
+            .expect("provider");
+    let mut req = make_req_with_tools();
+    req.messages.push(Message {
+        role: Role::Assistant,
+        content: vec![ContentPart::ToolCall {
+            id: "toolu_1".to_string(),
+            name: "Read".to_string(),
+            arguments: json!({"path": "a.rs"}),
+        }],
+    });
+    req.messages.push(Message {
+        role: Role::Tool,
+        content: vec![ContentPart::ToolResult {
+            call_id: "toolu_1".to_string(),
+            content: "missing".to_string(),
+            is_error: true,
+        }],
+    });
+
+    let res = provider.generate(req).await.expect("generate ok");
+    m.assert();
+    assert_eq!(res.text, "OK");
+}
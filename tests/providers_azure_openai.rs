@@ -0,0 +1,161 @@
+// File: tests/providers_azure_openai.rs
+use borg::code_generation::llm::LlmFactory;
+use borg::core::config::{LlmConfig, LlmLoggingConfig};
+use borg::providers::azure_openai::AzureOpenAiProvider;
+use borg::providers::{ContentPart, GenerateRequest, Message, Provider, Role, StreamEvent};
+use httpmock::prelude::*;
+
+fn make_cfg(base: &str) -> LlmConfig {
+    LlmConfig {
+        provider: "azure_openai".to_string(),
+        api_key: "test-azure".to_string(),
+        model: "gpt-4o".to_string(),
+        max_tokens: 1024,
+        temperature: 0.0,
+        api_base: Some(base.to_string()),
+        headers: None,
+        enable_streaming: Some(true),
+        enable_thinking: None,
+        reasoning_effort: None,
+        reasoning_budget_tokens: None,
+        first_token_timeout_ms: Some(5_000),
+        stall_timeout_ms: Some(3_000),
+        empty_response_retries: None,
+        stream_resume_retries: None,
+        deployment: Some("prod-gpt4o".to_string()),
+        api_version: Some("2024-06-01".to_string()),
+        retry: None,
+        rate_limit: None,
+        cache: None,
+        network: None,
+    }
+}
+
+fn make_req() -> GenerateRequest {
+    GenerateRequest {
+        system: Some("sys".to_string()),
+        messages: vec![Message {
+            role: Role::User,
+            content: vec![ContentPart::Text {
+                text: "hello".to_string(),
+            }],
+        }],
+        tools: None,
+        tool_choice: None,
+        temperature: Some(0.2),
+        top_p: None,
+        stop: None,
+        seed: None,
+        logit_bias: None,
+        response_format: None,
+        max_output_tokens: Some(77),
+        metadata: None,
+        logprobs: None,
+    }
+}
+
+#[tokio::test]
+async fn test_azure_routes_to_deployment_with_api_key_header() {
+    let server = MockServer::start();
+    let m = server.mock(|when, then| {
+        when.method(POST)
+            .path("/openai/deployments/prod-gpt4o/chat/completions")
+            .query_param("api-version", "2024-06-01")
+            .header("api-key", "test-azure")
+            .body_contains("\"max_tokens\":77")
+            .body_contains("\"role\":\"system\"");
+        then.status(200)
+            .header("content-type", "application/json")
+            .body(
+                r#"{"choices":[{"message":{"role":"assistant","content":"Hi from Azure"}}],
+                    "usage":{"prompt_tokens":9,"completion_tokens":4}}"#,
+            );
+    });
+
+    let provider =
+        AzureOpenAiProvider::from_config(&make_cfg(&server.base_url())).expect("provider");
+    let res = provider.generate(make_req()).await.expect("generate ok");
+
+    m.assert();
+    assert_eq!(res.text, "Hi from Azure");
+    assert_eq!(res.usage.and_then(|u| u.total_tokens), Some(13));
+}
+
+#[tokio::test]
+async fn test_azure_streaming_via_factory() {
+    let server = MockServer::start();
+    let sse = "\
+data: {\"choices\":[],\"prompt_filter_results\":[]}
+
+data: {\"choices\":[{\"delta\":{\"content\":\"Hello \"}}]}
+
+data: {\"choices\":[{\"delta\":{\"content\":\"Azure\"},\"finish_reason\":\"stop\"}]}
+
+data: [DONE]
+
+";
+    let m = server.mock(|when, then| {
+        when.method(POST)
+            .path("/openai/deployments/prod-gpt4o/chat/completions")
+            .query_param("api-version", "2024-06-01")
+            .header("api-key", "test-azure")
+            .header("accept", "text/event-stream")
+            .body_contains("\"stream\":true");
+        then.status(200)
+            .header("content-type", "text/event-stream")
+            .body(sse);
+    });
+
+    let provider =
+        AzureOpenAiProvider::from_config(&make_cfg(&server.base_url())).expect("provider");
+    let mut saw_finished = false;
+    let mut on_event = |ev: StreamEvent| {
+        if matches!(ev, StreamEvent::Finished) {
+            saw_finished = true;
+        }
+    };
+    let res = provider
+        .generate_streaming(make_req(), &mut on_event)
+        .await
+        .expect("stream ok");
+    assert_eq!(res.text, "Hello Azure");
+    assert!(saw_finished);
+
+    let llm = LlmFactory::create(
+        make_cfg(&server.base_url()),
+        LlmLoggingConfig {
+            enabled: false,
+            ..Default::default()
+        },
+    )
+    .expect("llm");
+    let text = llm
+        .generate_streaming("hello", Some(16), None, false)
+        .await
+        .expect("factory stream ok");
+    assert_eq!(text, "Hello Azure");
+    assert_eq!(m.hits(), 2);
+}
+
+#[tokio::test]
+async fn test_azure_deployment_and_version_default() {
+    let server = MockServer::start();
+    let m = server.mock(|when, then| {
+        when.method(POST)
+            .path("/openai/deployments/gpt-4o/chat/completions")
+            .query_param(
+                "api-version",
+                borg::providers::azure_openai::DEFAULT_AZURE_API_VERSION,
+            );
+        then.status(200)
+            .header("content-type", "application/json")
+            .body(r#"{"choices":[{"message":{"content":"ok"}}]}"#);
+    });
+
+    let mut cfg = make_cfg(&server.base_url());
+    cfg.deployment = None;
+    cfg.api_version = None;
+    let provider = AzureOpenAiProvider::from_config(&cfg).expect("provider");
+    assert_eq!(provider.generate(make_req()).await.expect("ok").text, "ok");
+    m.assert();
+}
@@ -0,0 +1,121 @@
+// File: tests/providers_deepseek.rs
+use borg::core::config::LlmConfig;
+use borg::providers::deepseek::DeepSeekProvider;
+use borg::providers::{ContentPart, GenerateRequest, Message, Provider, Role, StreamEvent};
+use httpmock::prelude::*;
+
+fn make_cfg(base: &str) -> LlmConfig {
+    LlmConfig {
+        provider: "deepseek".to_string(),
+        api_key: "test-deepseek".to_string(),
+        model: "deepseek-reasoner".to_string(),
+        max_tokens: 1024,
+        temperature: 0.0,
+        api_base: Some(base.to_string()),
+        headers: None,
+        enable_streaming: Some(true),
+        enable_thinking: None,
+        reasoning_effort: None,
+        reasoning_budget_tokens: None,
+        first_token_timeout_ms: Some(5_000),
+        stall_timeout_ms: Some(3_000),
+        empty_response_retries: None,
+        stream_resume_retries: None,
+        deployment: None,
+        api_version: None,
+        retry: None,
+        rate_limit: None,
+        cache: None,
+        network: None,
+    }
+}
+
+fn make_req() -> GenerateRequest {
+    GenerateRequest {
+        system: None,
+        messages: vec![Message {
+            role: Role::User,
+            content: vec![ContentPart::Text {
+                text: "fix it".to_string(),
+            }],
+        }],
+        tools: None,
+        tool_choice: None,
+        temperature: None,
+        top_p: None,
+        stop: None,
+        seed: None,
+        logit_bias: None,
+        response_format: None,
+        max_output_tokens: Some(64),
+        metadata: None,
+        logprobs: None,
+    }
+}
+
+#[tokio::test]
+async fn test_deepseek_reasoning_content_kept_out_of_text() {
+    let server = MockServer::start();
+    let m = server.mock(|when, then| {
+        when.method(POST)
+            .path("/chat/completions")
+            .header("authorization", "Bearer test-deepseek")
+            .body_contains("\"max_tokens\":64");
+        then.status(200)
+            .header("content-type", "application/json")
+            .body(
+                r#"{"choices":[{"message":{"content":"```diff\n+fixed\n```",
+                    "reasoning_content":"The bug is an off-by-one."}}]}"#,
+            );
+    });
+
+    let provider = DeepSeekProvider::from_config(&make_cfg(&server.base_url())).unwrap();
+    let res = provider.generate(make_req()).await.expect("generate ok");
+
+    m.assert();
+    assert_eq!(res.text, "```diff\n+fixed\n```");
+    assert_eq!(res.reasoning.as_deref(), Some("The bug is an off-by-one."));
+}
+
+#[tokio::test]
+async fn test_deepseek_streaming_separates_reasoning_and_think_tags() {
+    let server = MockServer::start();
+    let sse = "\
+data: {\"choices\":[{\"delta\":{\"reasoning_content\":\"Native \"}}]}
+
+data: {\"choices\":[{\"delta\":{\"content\":\"<thi\"}}]}
+
+data: {\"choices\":[{\"delta\":{\"content\":\"nk>inline</think>\\n\\nans\"}}]}
+
+data: {\"choices\":[{\"delta\":{\"content\":\"wer\"},\"finish_reason\":\"stop\"}]}
+
+data: [DONE]
+
+";
+    server.mock(|when, then| {
+        when.method(POST)
+            .path("/chat/completions")
+            .body_contains("\"stream\":true");
+        then.status(200)
+            .header("content-type", "text/event-stream")
+            .body(sse);
+    });
+
+    let provider = DeepSeekProvider::from_config(&make_cfg(&server.base_url())).unwrap();
+    let mut text = String::new();
+    let mut thinking = String::new();
+    let mut on_event = |ev: StreamEvent| match ev {
+        StreamEvent::TextDelta(d) => text.push_str(&d),
+        StreamEvent::ReasoningDelta(d) => thinking.push_str(&d),
+        _ => {}
+    };
+    let res = provider
+        .generate_streaming(make_req(), &mut on_event)
+        .await
+        .expect("stream ok");
+
+    assert_eq!(text, "\n\nanswer");
+    assert_eq!(thinking, "Native inline");
+    assert_eq!(res.text, "answer");
+    assert_eq!(res.reasoning.as_deref(), Some("Native \ninline"));
+}
@@ -0,0 +1,154 @@
+// File: tests/providers_empty_response.rs
+use borg::code_generation::llm::LlmFactory;
+use borg::core::config::{LlmConfig, LlmLoggingConfig};
+use borg::core::error::{BorgError, ProviderError};
+use borg::providers::{ContentPart, GenerateRequest, Message, Provider, Role};
+use httpmock::prelude::*;
+
+fn make_cfg(provider: &str, model: &str, base: &str) -> LlmConfig {
+    LlmConfig {
+        provider: provider.to_string(),
+        api_key: "test-key".to_string(),
+        model: model.to_string(),
+        max_tokens: 1024,
+        temperature: 0.0,
+        api_base: Some(base.to_string()),
+        headers: None,
+        enable_streaming: Some(false),
+        enable_thinking: None,
+        reasoning_effort: None,
+        reasoning_budget_tokens: None,
+        first_token_timeout_ms: Some(5_000),
+        stall_timeout_ms: Some(3_000),
+        empty_response_retries: None,
+        stream_resume_retries: None,
+        deployment: None,
+        api_version: None,
+        retry: None,
+        rate_limit: None,
+        cache: None,
+        network: None,
+    }
+}
+
+fn disabled_logger() -> LlmLoggingConfig {
+    LlmLoggingConfig {
+        enabled: false,
+        ..Default::default()
+    }
+}
+
+fn make_req() -> GenerateRequest {
+    GenerateRequest {
+        system: None,
+        messages: vec![Message {
+            role: Role::User,
+            content: vec![ContentPart::Text {
+                text: "hello".to_string(),
+            }],
+        }],
+        tools: None,
+        tool_choice: None,
+        temperature: None,
+        top_p: None,
+        stop: None,
+        seed: None,
+        logit_bias: None,
+        response_format: None,
+        max_output_tokens: Some(32),
+        metadata: None,
+        logprobs: None,
+    }
+}
+
+fn assert_empty_response(err: anyhow::Error) {
+    assert!(
+        matches!(
+            err.downcast_ref::<BorgError>(),
+            Some(BorgError::EmptyResponse(_))
+        ),
+        "expected BorgError::EmptyResponse, got {:?}",
+        err
+    );
+}
+
+#[tokio::test]
+async fn test_openrouter_no_choices_is_empty_response() {
+    let server = MockServer::start();
+    let mock = server.mock(|when, then| {
+        when.method(POST).path("/chat/completions");
+        then.status(200)
+            .header("content-type", "application/json")
+            .body(r#"{ "choices": [] }"#);
+    });
+
+    let cfg = make_cfg("openrouter", "openrouter/auto", &server.base_url());
+    let provider =
+        borg::providers::openrouter::OpenRouterProvider::from_config(&cfg).expect("provider");
+    let err = provider.generate(make_req()).await.unwrap_err();
+    assert!(matches!(err, ProviderError::EmptyResponse { .. }));
+
+    // Through the factory: same error type after one retry
+    let llm = LlmFactory::create(cfg, disabled_logger()).expect("llm");
+    assert_empty_response(llm.generate("hello", None, None).await.unwrap_err());
+    assert_eq!(mock.hits(), 3);
+}
+
+#[tokio::test]
+async fn test_anthropic_empty_content_is_empty_response() {
+    let server = MockServer::start();
+    let mock = server.mock(|when, then| {
+        when.method(POST).path("/messages");
+        then.status(200)
+            .header("content-type", "application/json")
+            .body(r#"{ "content": [], "usage": { "input_tokens": 3, "output_tokens": 0 } }"#);
+    });
+
+    let cfg = make_cfg("anthropic", "claude-3-7-sonnet", &server.base_url());
+    let provider =
+        borg::providers::anthropic::AnthropicProvider::from_config(&cfg).expect("provider");
+    let err = provider.generate(make_req()).await.unwrap_err();
+    assert!(matches!(err, ProviderError::EmptyResponse { .. }));
+
+    let llm = LlmFactory::create(cfg, disabled_logger()).expect("llm");
+    assert_empty_response(llm.generate("hello", None, None).await.unwrap_err());
+    assert_eq!(mock.hits(), 3);
+}
+
+#[tokio::test]
+async fn test_ollama_blank_message_is_empty_response() {
+    let server = MockServer::start();
+    server.mock(|when, then| {
+        when.method(POST).path("/api/chat");
+        then.status(200)
+            .header("content-type", "application/json")
+            .body(r#"{ "message": { "role": "assistant", "content": "  " }, "done": true }"#);
+    });
+
+    let cfg = make_cfg("ollama", "llama3", &server.base_url());
+    let provider = borg::providers::ollama::OllamaProvider::from_config(&cfg).expect("provider");
+    let err = provider.generate(make_req()).await.unwrap_err();
+    assert!(matches!(err, ProviderError::EmptyResponse { .. }));
+}
+
+#[tokio::test]
+async fn test_openai_no_choices_is_empty_response() {
+    let server = MockServer::start();
+    let chat = server.mock(|when, then| {
+        when.method(POST).path("/chat/completions");
+        then.status(200)
+            .header("content-type", "application/json")
+            .body(r#"{ "choices": [] }"#);
+    });
+
+    let mut cfg = make_cfg("openai", "test-model-empty-response", &server.base_url());
+    cfg.empty_response_retries = Some(2);
+    let llm = LlmFactory::create(cfg, disabled_logger()).expect("llm");
+
+    assert_empty_response(llm.generate("hello", None, None).await.unwrap_err());
+    assert_eq!(
+        chat.hits(),
+        3,
+        "initial attempt plus two configured retries"
+    );
+}
@@ -0,0 +1,51 @@
+// File: tests/providers_fallback.rs
+use borg::code_generation::llm::LlmFactory;
+use borg::core::config::ModelConfig;
+use httpmock::prelude::*;
+
+fn model(yaml: &str) -> ModelConfig {
+    serde_yaml::from_str(yaml).expect("model config")
+}
+
+#[tokio::test]
+async fn test_factory_fails_over_to_next_model() {
+    let primary = MockServer::start();
+    let local = MockServer::start();
+
+    let down = primary.mock(|when, then| {
+        when.method(POST).path("/messages");
+        then.status(503)
+            .header("content-type", "application/json")
+            .body(r#"{"error":{"type":"overloaded_error","message":"overloaded"}}"#);
+    });
+    let answered = local.mock(|when, then| {
+        when.method(POST).path("/api/chat");
+        then.status(200)
+            .header("content-type", "application/json")
+            .body(r#"{"message":{"role":"assistant","content":"from local"},"done":true}"#);
+    });
+
+    let claude = model(&format!(
+        "name: claude\nprovider: anthropic\napi_key: test\nmodel: claude-3-7-sonnet\n\
+         api_base: {}\nfallbacks: [local]\nretry: {{ max_attempts: 2, initial_backoff_ms: 1 }}\n",
+        primary.base_url()
+    ));
+    let llama = model(&format!(
+        "name: local\nprovider: ollama\nmodel: llama3:8b\napi_base: {}\n",
+        local.base_url()
+    ));
+
+    let log_dir = tempfile::tempdir().expect("tempdir");
+    let llm =
+        LlmFactory::create_with_fallbacks(&claude, &[llama], log_dir.path().to_str().unwrap())
+            .expect("llm");
+    let text = llm
+        .generate("hi", Some(16), None)
+        .await
+        .expect("fallback answers");
+
+    assert_eq!(text, "from local");
+    // The primary exhausts its own retries before the chain moves on
+    assert_eq!(down.hits(), 2);
+    answered.assert();
+}
@@ -0,0 +1,307 @@
+// File: tests/providers_groq_mistral.rs
+use borg::code_generation::llm::LlmFactory;
+use borg::core::config::{LlmConfig, LlmLoggingConfig};
+use borg::core::error::ProviderError;
+use borg::providers::groq::GroqProvider;
+use borg::providers::mistral::MistralProvider;
+use borg::providers::{
+    ContentPart, GenerateRequest, Message, Provider, Role, StreamEvent, ToolChoice, ToolSpec,
+};
+use httpmock::prelude::*;
+use serde_json::json;
+
+fn make_cfg(provider: &str, base: &str) -> LlmConfig {
+    LlmConfig {
+        provider: provider.to_string(),
+        api_key: format!("test-{}", provider),
+        model: "test-model".to_string(),
+        max_tokens: 1024,
+        temperature: 0.0,
+        api_base: Some(base.to_string()),
+        headers: None,
+        enable_streaming: Some(true),
+        enable_thinking: None,
+        reasoning_effort: None,
+        reasoning_budget_tokens: None,
+        first_token_timeout_ms: Some(5_000),
+        stall_timeout_ms: Some(3_000),
+        empty_response_retries: None,
+        stream_resume_retries: None,
+        deployment: None,
+        api_version: None,
+        retry: None,
+        rate_limit: None,
+        cache: None,
+        network: None,
+    }
+}
+
+fn make_req() -> GenerateRequest {
+    GenerateRequest {
+        system: Some("sys".to_string()),
+        messages: vec![Message {
+            role: Role::User,
+            content: vec![ContentPart::Text {
+                text: "hello".to_string(),
+            }],
+        }],
+        tools: None,
+        tool_choice: None,
+        temperature: Some(0.2),
+        top_p: None,
+        stop: Some((1..=5).map(|i| format!("STOP{}", i)).collect()),
+        seed: Some(7),
+        logit_bias: None,
+        response_format: None,
+        max_output_tokens: Some(77),
+        metadata: None,
+        logprobs: None,
+    }
+}
+
+#[tokio::test]
+async fn test_groq_sends_max_completion_tokens_and_caps_stops() {
+    let server = MockServer::start();
+    let m = server.mock(|when, then| {
+        when.method(POST)
+            .path("/chat/completions")
+            .header("authorization", "Bearer test-groq")
+            .body_contains("\"max_completion_tokens\":77")
+            .body_contains("\"stop\":[\"STOP1\",\"STOP2\",\"STOP3\",\"STOP4\"]")
+            .body_contains("\"seed\":7");
+        then.status(200)
+            .header("content-type", "application/json")
+            .body(
+                r#"{"choices":[{"message":{"content":"fast"}}],
+                    "usage":{"prompt_tokens":5,"completion_tokens":1,"total_tokens":6}}"#,
+            );
+    });
+
+    let provider = GroqProvider::from_config(&make_cfg("groq", &server.base_url())).unwrap();
+    let res = provider.generate(make_req()).await.expect("generate ok");
+
+    m.assert();
+    assert_eq!(res.text, "fast");
+    assert_eq!(res.usage.and_then(|u| u.total_tokens), Some(6));
+}
+
+#[tokio::test]
+async fn test_groq_streaming_reports_x_groq_usage() {
+    let server = MockServer::start();
+    let sse = "\
+data: {\"choices\":[{\"delta\":{\"content\":\"Hel\"}}]}
+
+data: {\"choices\":[{\"delta\":{\"content\":\"lo\"},\"finish_reason\":\"stop\"}],\"x_groq\":{\"usage\":{\"prompt_tokens\":3,\"completion_tokens\":2}}}
+
+data: [DONE]
+
+";
+    server.mock(|when, then| {
+        when.method(POST)
+            .path("/chat/completions")
+            .body_contains("\"stream\":true");
+        then.status(200)
+            .header("content-type", "text/event-stream")
+            .body(sse);
+    });
+
+    let provider = GroqProvider::from_config(&make_cfg("groq", &server.base_url())).unwrap();
+    let mut text = String::new();
+    let mut usage_events = 0;
+    let mut on_event = |ev: StreamEvent| match ev {
+        StreamEvent::TextDelta(d) => text.push_str(&d),
+        StreamEvent::Usage(_) => usage_events += 1,
+        _ => {}
+    };
+    let res = provider
+        .generate_streaming(make_req(), &mut on_event)
+        .await
+        .expect("stream ok");
+
+    assert_eq!(text, "Hello");
+    assert_eq!(res.text, "Hello");
+    assert_eq!(usage_events, 1);
+    assert_eq!(res.usage.and_then(|u| u.completion_tokens), Some(2));
+}
+
+#[tokio::test]
+async fn test_mistral_uses_random_seed_and_any_tool_choice() {
+    let server = MockServer::start();
+    let m = server.mock(|when, then| {
+        when.method(POST)
+            .path("/chat/completions")
+            .header("authorization", "Bearer test-mistral")
+            .body_contains("\"max_tokens\":77")
+            .body_contains("\"random_seed\":7")
+            .body_contains("\"tool_choice\":\"any\"")
+            .body_contains("STOP5");
+        then.status(200)
+            .header("content-type", "application/json")
+            .body(
+                r#"{"choices":[{"message":{"content":"","tool_calls":[
+                    {"id":"c1","function":{"name":"search","arguments":"{\"q\":\"rust\"}"}}]}}]}"#,
+            );
+    });
+
+    let mut req = make_req();
+    req.tools = Some(vec![ToolSpec {
+        name: "search".to_string(),
+        description: None,
+        json_schema: Some(json!({"type": "object"})),
+    }]);
+    req.tool_choice = Some(ToolChoice::Required);
+
+    let provider = MistralProvider::from_config(&make_cfg("mistral", &server.base_url())).unwrap();
+    let res = provider.generate(req).await.expect("generate ok");
+
+    m.assert();
+    assert_eq!(res.tool_calls.len(), 1);
+    assert_eq!(res.tool_calls[0].arguments_json, json!({"q": "rust"}));
+}
+
+#[tokio::test]
+async fn test_errors_are_normalized() {
+    let server = MockServer::start();
+    server.mock(|when, then| {
+        when.method(POST)
+            .path("/chat/completions")
+            .body_contains("too-big");
+        then.status(413).body("Request Entity Too Large");
+    });
+    server.mock(|when, then| {
+        when.method(POST).path("/chat/completions");
+        then.status(429)
+            .header("retry-after", "2")
+            .body(r#"{"message":"Requests rate limit exceeded"}"#);
+    });
+
+    let groq = GroqProvider::from_config(&make_cfg("groq", &server.base_url())).unwrap();
+    match groq.generate(make_req()).await {
+        Err(ProviderError::RateLimited { retry_after_ms, .. }) => {
+            assert_eq!(retry_after_ms, Some(2000))
+        }
+        other => panic!("expected rate limit, got {:?}", other.map(|r| r.text)),
+    }
+
+    let mut big = make_req();
+    big.messages[0].content = vec![ContentPart::Text {
+        text: "too-big".to_string(),
+    }];
+    assert!(matches!(
+        groq.generate(big).await,
+        Err(ProviderError::ContextLengthExceeded { .. })
+    ));
+}
+
+#[tokio::test]
+async fn test_factory_selects_mistral() {
+    let server = MockServer::start();
+    let m = server.mock(|when, then| {
+        when.method(POST).path("/chat/completions");
+        then.status(200)
+            .header("content-type", "application/json")
+            .body(r#"{"choices":[{"message":{"content":"bonjour"}}]}"#);
+    });
+
+    let mut cfg = make_cfg("mistral", &server.base_url());
+    cfg.enable_streaming = Some(false);
+    let llm = LlmFactory::create(
+        cfg,
+        LlmLoggingConfig {
+            enabled: false,
+            ..Default::default()
+        },
+    )
+    .expect("factory");
+    let text = llm.generate("hi", Some(10), None).await.expect("generate");
+
+    m.assert();
+    assert_eq!(text, "bonjour");
+}
+
+#[tokio::test]
+async fn test_reasoning_fields_are_captured() {
+    let server = MockServer::start();
+    let sse = "\
+data: {\"choices\":[{\"delta\":{\"content\":\"\",\"reasoning\":\"Think \"}}]}
+
+data: {\"choices\":[{\"delta\":{\"content\":\"\",\"reasoning\":\"hard.\"}}]}
+
+data: {\"choices\":[{\"delta\":{\"content\":\"42\"},\"finish_reason\":\"stop\"}]}
+
+data: [DONE]
+
+";
+    server.mock(|when, then| {
+        when.method(POST)
+            .path("/chat/completions")
+            .body_contains("\"stream\":true");
+        then.status(200)
+            .header("content-type", "text/event-stream")
+            .body(sse);
+    });
+    server.mock(|when, then| {
+        when.method(POST).path("/chat/completions");
+        then.status(200)
+            .header("content-type", "application/json")
+            .body(
+                r#"{"choices":[{"message":{"content":"42","reasoning_content":"Six times seven."}}]}"#,
+            );
+    });
+
+    let provider = GroqProvider::from_config(&make_cfg("groq", &server.base_url())).unwrap();
+    let res = provider.generate(make_req()).await.expect("generate ok");
+    assert_eq!(res.reasoning.as_deref(), Some("Six times seven."));
+
+    let mut thoughts = String::new();
+    let mut on_event = |ev: StreamEvent| {
+        if let StreamEvent::ReasoningDelta(d) = ev {
+            thoughts.push_str(&d);
+        }
+    };
+    let res = provider
+        .generate_streaming(make_req(), &mut on_event)
+        .await
+        .expect("stream ok");
+    assert_eq!(thoughts, "Think hard.");
+    assert_eq!(res.text, "42");
+    assert_eq!(res.reasoning.as_deref(), Some("Think hard."));
+}
+
+#[tokio::test]
+async fn test_scoped_metadata_is_forwarded_as_headers() {
+    use borg::providers::metadata;
+
+    let server = MockServer::start();
+    let m = server.mock(|when, then| {
+        when.method(POST)
+            .path("/chat/completions")
+            .header("authorization", "Bearer test-mistral")
+            .header(metadata::GOAL_ID_HEADER, "goal-42")
+            .header(metadata::AGENT_HEADER, "research:mistral")
+            .header_exists(metadata::REQUEST_ID_HEADER);
+        then.status(200)
+            .header("content-type", "application/json")
+            .body(r#"{"choices":[{"message":{"content":"tagged"}}]}"#);
+    });
+
+    let mut cfg = make_cfg("mistral", &server.base_url());
+    cfg.enable_streaming = Some(false);
+    let llm = LlmFactory::create(
+        cfg,
+        LlmLoggingConfig {
+            enabled: false,
+            ..Default::default()
+        },
+    )
+    .expect("factory");
+    let text = metadata::scope(
+        metadata::attribution(Some("goal-42"), "research:mistral"),
+        llm.generate("hi", Some(10), None),
+    )
+    .await
+    .expect("generate");
+
+    m.assert();
+    assert_eq!(text, "tagged");
+}
@@ -0,0 +1,303 @@
+// File: tests/providers_ollama.rs
+use borg::code_generation::llm::LlmFactory;
+use borg::core::config::{LlmConfig, LlmLoggingConfig};
+use borg::providers::ollama::OllamaProvider;
+use borg::providers::{
+    ContentPart, GenerateRequest, Message, NdjsonDecoder, Provider, Role, StreamEvent,
+};
+use httpmock::prelude::*;
+
+fn make_cfg(base: &str) -> LlmConfig {
+    LlmConfig {
+        provider: "ollama".to_string(),
+        api_key: String::new(),
+        model: "llama3:8b".to_string(),
+        max_tokens: 2048,
+        temperature: 0.7,
+        api_base: Some(base.to_string()),
+        headers: None,
+        enable_streaming: Some(true),
+        enable_thinking: None,
+        reasoning_effort: None,
+        reasoning_budget_tokens: None,
+        first_token_timeout_ms: Some(5_000),
+        stall_timeout_ms: Some(3_000),
+        empty_response_retries: None,
+        stream_resume_retries: None,
+        deployment: None,
+        api_version: None,
+        retry: None,
+        rate_limit: None,
+        cache: None,
+        network: None,
+    }
+}
+
+fn make_req() -> GenerateRequest {
+    GenerateRequest {
+        system: Some("sys".to_string()),
+        messages: vec![Message {
+            role: Role::User,
+            content: vec![ContentPart::Text {
+                text: "hello".to_string(),
+            }],
+        }],
+        tools: None,
+        tool_choice: None,
+        temperature: Some(0.1),
+        top_p: None,
+        stop: None,
+        seed: None,
+        logit_bias: None,
+        response_format: None,
+        max_output_tokens: Some(55),
+        metadata: None,
+        logprobs: None,
+    }
+}
+
+#[tokio::test]
+async fn test_ollama_chat_maps_sampling_options() {
+    let server = MockServer::start();
+    let m = server.mock(|when, then| {
+        when.method(POST)
+            .path("/api/chat")
+            .body_contains("\"model\":\"llama3:8b\"")
+            .body_contains("\"stream\":false")
+            .body_contains("\"num_predict\":55")
+            .body_contains("\"temperature\":0.1")
+            .body_contains("\"role\":\"system\"");
+        then.status(200)
+            .header("content-type", "application/json")
+            .body(
+                r#"{"message":{"role":"assistant","content":"Hi there"},"done":true,
+                    "prompt_eval_count":12,"eval_count":3}"#,
+            );
+    });
+
+    let provider = OllamaProvider::from_config(&make_cfg(&server.base_url())).expect("provider");
+    let res = provider.generate(make_req()).await.expect("generate ok");
+
+    m.assert();
+    assert_eq!(res.text, "Hi there");
+    let usage = res.usage.expect("usage");
+    assert_eq!(usage.prompt_tokens, Some(12));
+    assert_eq!(usage.total_tokens, Some(15));
+}
+
+#[tokio::test]
+async fn test_ollama_streaming_ndjson() {
+    let server = MockServer::start();
+    let ndjson = "\
+{\"message\":{\"role\":\"assistant\",\"content\":\"Hello \"},\"done\":false}
+{\"message\":{\"role\":\"assistant\",\"content\":\"world\"},\"done\":false}
+{\"message\":{\"role\":\"assistant\",\"content\":\"\"},\"done\":true,\"prompt_eval_count\":4,\"eval_count\":2}";
+
+    let m = server.mock(|when, then| {
+        when.method(POST)
+            .path("/api/chat")
+            .body_contains("\"stream\":true");
+        then.status(200)
+            .header("content-type", "application/x-ndjson")
+            .body(ndjson);
+    });
+
+    let provider = OllamaProvider::from_config(&make_cfg(&server.base_url())).expect("provider");
+
+    let mut deltas = Vec::new();
+    let mut saw_finished = false;
+    let mut on_event = |ev: StreamEvent| match ev {
+        StreamEvent::TextDelta(t) => deltas.push(t),
+        StreamEvent::Finished => saw_finished = true,
+        _ => {}
+    };
+    let res = provider
+        .generate_streaming(make_req(), &mut on_event)
+        .await
+        .expect("stream ok");
+
+    m.assert();
+    assert_eq!(deltas.join(""), "Hello world");
+    assert!(
+        saw_finished,
+        "final unterminated line should still be handled"
+    );
+    assert_eq!(res.text, "Hello world");
+    assert_eq!(res.usage.and_then(|u| u.completion_tokens), Some(2));
+}
+
+#[tokio::test]
+async fn test_ollama_via_factory_uses_config_defaults() {
+    let server = MockServer::start();
+    let m = server.mock(|when, then| {
+        when.method(POST)
+            .path("/api/chat")
+            .body_contains("\"temperature\":0.7");
+        then.status(200)
+            .header("content-type", "application/json")
+            .body(r#"{"message":{"role":"assistant","content":"local"},"done":true}"#);
+    });
+
+    let llm = LlmFactory::create(
+        make_cfg(&server.base_url()),
+        LlmLoggingConfig {
+            enabled: false,
+            ..Default::default()
+        },
+    )
+    .expect("ollama needs no API key");
+    let text = llm.generate("hi", Some(10), None).await.expect("generate");
+
+    m.assert();
+    assert_eq!(text, "local");
+}
+
+#[test]
+fn test_ndjson_decoder_joins_split_lines() {
+    let mut decoder = NdjsonDecoder::new();
+    assert!(decoder.push_chunk("{\"a\":").is_empty());
+    assert_eq!(decoder.push_chunk("1}\n\n{\"b\""), vec!["{\"a\":1}"]);
+    assert_eq!(decoder.push_chunk(":2}\n"), vec!["{\"b\":2}"]);
+    assert_eq!(decoder.finish(), None);
+}
+
+#[tokio::test]
+async fn test_ollama_sends_inline_images() {
+    let server = MockServer::start();
+    let m = server.mock(|when, then| {
+        when.method(POST)
+            .path("/api/chat")
+            .body_contains("\"images\":[\"iVBORw==\"]");
+        then.status(200)
+            .header("content-type", "application/json")
+            .body(r#"{"message":{"role":"assistant","content":"a screenshot"},"done":true}"#);
+    });
+
+    let mut req = make_req();
+    req.messages[0].content.push(ContentPart::image_base64(
+        "image/png",
+        &[0x89, b'P', b'N', b'G'],
+    ));
+
+    let provider = OllamaProvider::from_config(&make_cfg(&server.base_url())).expect("provider");
+    let res = provider.generate(req).await.expect("generate ok");
+
+    m.assert();
+    assert_eq!(res.text, "a screenshot");
+}
+
+#[tokio::test]
+async fn test_structured_output_is_reprompted_until_it_matches_the_schema() {
+    let server = MockServer::start();
+    let fixed = server.mock(|when, then| {
+        when.method(POST)
+            .path("/api/chat")
+            .body_contains("\"format\":{")
+            .body_contains("missing required property");
+        then.status(200)
+            .header("content-type", "application/json")
+            .body(r#"{"message":{"role":"assistant","content":"{\"name\":\"ok\"}"},"done":true}"#);
+    });
+    let first = server.mock(|when, then| {
+        when.method(POST).path("/api/chat").matches(|req| {
+            let body = String::from_utf8_lossy(req.body.as_deref().unwrap_or_default());
+            !body.contains("missing required property")
+        });
+        then.status(200)
+            .header("content-type", "application/json")
+            .body(r#"{"message":{"role":"assistant","content":"{}"},"done":true}"#);
+    });
+
+    let llm = LlmFactory::create(
+        make_cfg(&server.base_url()),
+        LlmLoggingConfig {
+            enabled: false,
+            ..Default::default()
+        },
+    )
+    .expect("factory");
+    let format = borg::providers::ResponseFormat::json_schema(
+        "named".to_string(),
+        serde_json::json!({
+            "type": "object",
+            "properties": {"name": {"type": "string"}},
+            "required": ["name"]
+        }),
+    );
+    let text = llm
+        .generate_with_format("name it", Some(10), None, Some(format))
+        .await
+        .expect("second attempt conforms");
+
+    first.assert_hits(1);
+    fixed.assert_hits(1);
+    assert_eq!(text, r#"{"name":"ok"}"#);
+}
+
+#[tokio::test]
+async fn test_cancelling_a_stream_returns_promptly() {
+    let server = MockServer::start();
+    server.mock(|when, then| {
+        when.method(POST).path("/api/chat");
+        then.status(200)
+            .delay(std::time::Duration::from_secs(10))
+            .header("content-type", "application/x-ndjson")
+            .body("{\"message\":{\"content\":\"late\"},\"done\":true}\n");
+    });
+
+    let provider = OllamaProvider::from_config(&make_cfg(&server.base_url())).expect("provider");
+    let cancel = tokio_util::sync::CancellationToken::new();
+    let trigger = cancel.clone();
+    tokio::spawn(async move {
+        tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+        trigger.cancel();
+    });
+
+    let started = std::time::Instant::now();
+    let mut on_event = |_ev: StreamEvent| {};
+    let res = borg::providers::generate_streaming_cancellable(
+        &provider,
+        make_req(),
+        &mut on_event,
+        &cancel,
+    )
+    .await;
+
+    assert!(matches!(
+        res,
+        Err(borg::core::error::ProviderError::Cancelled { .. })
+    ));
+    assert!(started.elapsed() < std::time::Duration::from_secs(5));
+}
+
+#[tokio::test]
+async fn test_ollama_lists_models_for_provider_check() {
+    use borg::providers::health::{check_provider, CheckStatus};
+
+    let server = MockServer::start();
+    let m = server.mock(|when, then| {
+        when.method(GET).path("/api/tags");
+        then.status(200)
+            .header("content-type", "application/json")
+            .body(r#"{"models":[{"name":"llama3:8b"},{"name":"qwen2.5-coder:latest"}]}"#);
+    });
+
+    let provider = OllamaProvider::from_config(&make_cfg(&server.base_url())).expect("provider");
+    let models = provider.list_models().await.expect("models");
+    assert_eq!(models.len(), 2);
+    assert_eq!(models[0].id, "llama3:8b");
+
+    assert_eq!(
+        check_provider(&provider, "llama3:8b").await,
+        CheckStatus::Ok
+    );
+    assert_eq!(
+        check_provider(&provider, "qwen2.5-coder").await,
+        CheckStatus::Ok
+    );
+    assert_eq!(
+        check_provider(&provider, "mistral").await,
+        CheckStatus::ModelNotFound
+    );
+    assert!(m.hits() >= 3);
+}
@@ -1,6 +1,7 @@
 // File: tests/providers_openai_mapping.rs
 use borg::code_generation::llm::LlmFactory;
-use borg::core::config::{LlmConfig, LlmLoggingConfig};
+use borg::core::config::{LlmConfig, LlmLoggingConfig, ReasoningEffort};
+use borg::providers::{ContentPart, GenerateRequest, Message, Provider, Role};
 use httpmock::prelude::*;
 
 fn disabled_logger() -> LlmLoggingConfig {
@@ -25,25 +26,29 @@ fn make_openai_config(model: &str, api_base: &str) -> LlmConfig {
         reasoning_budget_tokens: None,
         first_token_timeout_ms: Some(5000),
         stall_timeout_ms: Some(3000),
+        empty_response_retries: None,
+        stream_resume_retries: None,
+        deployment: None,
+        api_version: None,
+        retry: None,
+        rate_limit: None,
+        cache: None,
+        network: None,
     }
 }
 
-fn clear_openai_cache_file() {
-    let _ = std::fs::create_dir_all("./logs/llm");
-    let _ = std::fs::remove_file("./logs/llm/openai_endpoint_cache.json");
-}
-
 #[tokio::test]
-async fn test_openai_chat_mapping_sends_max_tokens() {
-    clear_openai_cache_file();
+async fn test_openai_chat_mapping_sends_max_completion_tokens() {
     let server = MockServer::start();
 
-    // Expect chat/completions with a body containing "max_tokens" and "messages" (ignore exact value)
+    // Reasoning models reject `max_tokens`, so the limit always goes out as
+    // `max_completion_tokens`
     let chat_mock = server.mock(|when, then| {
         when.method(POST)
             .path("/chat/completions")
             .header("content-type", "application/json")
-            .body_contains("\"max_tokens\"")
+            .header("authorization", "Bearer test-key")
+            .body_contains("\"max_completion_tokens\":123")
             .body_contains("\"messages\"");
         then.status(200)
             .header("content-type", "application/json")
@@ -60,32 +65,28 @@ async fn test_openai_chat_mapping_sends_max_tokens() {
         .expect("chat generate");
 
     assert_eq!(res, "OK-CHAT");
-    assert!(
-        chat_mock.hits() >= 1,
-        "chat endpoint should be hit at least once; hits={}",
-        chat_mock.hits()
-    );
+    chat_mock.assert();
 }
 
 #[tokio::test]
 async fn test_openai_invalid_param_triggers_responses_fallback_and_caches() {
-    clear_openai_cache_file();
     let server = MockServer::start();
 
-    // 1) Chat endpoint returns the invalid-parameter error about 'max_tokens' and suggests 'max_completion_tokens'
+    // 1) Chat rejects the request with an unsupported-parameter error
     let chat_err = r#"{ "error": { "message": "Unsupported parameter: 'max_tokens'. Use 'max_completion_tokens' instead." } }"#;
     let chat_mock = server.mock(|when, then| {
         when.method(POST)
             .path("/chat/completions")
             .header("content-type", "application/json")
             .body_contains("\"messages\"")
-            .body_contains("fallback please"); // first call prompt
+            .body_contains("fallback please");
         then.status(400)
             .header("content-type", "application/json")
             .body(chat_err);
     });
 
-    // 2) First attempt to /responses uses max_output_tokens (no cache yet) and gets a 400 that mentions 'max_completion_tokens'
+    // 2) The first /responses attempt uses max_output_tokens and is told to
+    // use max_completion_tokens instead
     let responses_err = r#"{ "error": { "message": "Unsupported parameter. Use 'max_completion_tokens' instead." } }"#;
     let responses_first = server.mock(|when, then| {
         when.method(POST)
@@ -99,7 +100,7 @@ async fn test_openai_invalid_param_triggers_responses_fallback_and_caches() {
             .body(responses_err);
     });
 
-    // 3) Retry with max_completion_tokens succeeds
+    // 3) The retry with max_completion_tokens succeeds
     let responses_retry = server.mock(|when, then| {
         when.method(POST)
             .path("/responses")
@@ -112,70 +113,173 @@ async fn test_openai_invalid_param_triggers_responses_fallback_and_caches() {
             .body(r#"{ "output_text": "OK-RESP-RETRY" }"#);
     });
 
-    // 4) Second call should hit /responses directly (cache used) and succeed again for a different prompt
+    // 4) The second call goes straight to /responses with the cached field
     let responses_cached = server.mock(|when, then| {
         when.method(POST)
             .path("/responses")
             .header("content-type", "application/json")
             .body_contains("\"input\"")
-            .body_contains("again"); // second call prompt
+            .body_contains("again")
+            .body_contains("\"max_completion_tokens\"");
         then.status(200)
             .header("content-type", "application/json")
-            .body(r#"{ "output_text": "OK-RESP-CACHED" }"#);
+            .body(r#"{ "output": [ { "content": [ { "type": "output_text", "text": "OK-RESP-CACHED" } ] } ] }"#);
     });
 
     let cfg = make_openai_config("test-model-fallback-1", &server.base_url());
     let logging = disabled_logger();
     let provider = LlmFactory::create(cfg, logging).expect("provider creation");
 
-    // First call: chat -> 400 invalid-parameter -> responses (max_output_tokens) -> 400 -> responses (max_completion_tokens) -> 200
     let out1 = provider
         .generate("fallback please", Some(77), Some(0.1))
         .await
         .expect("fallback generate");
+    assert_eq!(out1, "OK-RESP-RETRY");
+    chat_mock.assert();
+    responses_first.assert();
+    responses_retry.assert();
 
-    assert!(
-        out1 == "OK-RESP-RETRY",
-        "expected retry success payload, got: {}",
-        out1
-    );
-    assert!(
-        chat_mock.hits() >= 1,
-        "chat endpoint must be hit; hits={}",
-        chat_mock.hits()
-    );
-    assert!(
-        responses_first.hits() >= 1,
-        "first responses attempt should be hit; hits={}",
-        responses_first.hits()
-    );
-    assert!(
-        responses_retry.hits() >= 1,
-        "retry with completion tokens should be hit; hits={}",
-        responses_retry.hits()
-    );
-
-    // Second call should use cached preference (Responses) directly and not go through Chat
     let out2 = provider
         .generate("again", Some(42), Some(0.0))
         .await
         .expect("cached generate");
-
-    // The exact content may vary if cache already exists from previous runs.
-    // Assert we did NOT hit chat again, and that we hit some /responses mock.
-    assert!(
-        chat_mock.hits() == 1,
-        "chat should have been used exactly once; hits={}",
-        chat_mock.hits()
-    );
-    let responses_total = responses_first.hits() + responses_retry.hits() + responses_cached.hits();
-    assert!(
-        responses_total >= 2,
-        "expected at least two /responses calls across both requests; total={}",
-        responses_total
+    assert_eq!(out2, "OK-RESP-CACHED");
+    assert_eq!(
+        chat_mock.hits(),
+        1,
+        "chat should not be retried once cached"
     );
+    responses_cached.assert();
+}
+
+#[tokio::test]
+async fn test_openai_reasoning_effort_and_logprobs_forwarded() {
+    let server = MockServer::start();
+    let chat_mock = server.mock(|when, then| {
+        when.method(POST)
+            .path("/chat/completions")
+            .body_contains("\"reasoning_effort\":\"high\"")
+            .body_contains("\"logprobs\":true");
+        then.status(200)
+            .header("content-type", "application/json")
+            .body(
+                r#"{ "choices": [ { "message": { "content": "OK" },
+                     "logprobs": { "content": [ { "token": "OK", "logprob": -0.5 } ] } } ],
+                     "usage": { "prompt_tokens": 9, "completion_tokens": 1, "total_tokens": 10 } }"#,
+            );
+    });
+
+    let mut cfg = make_openai_config("o3-mini", &server.base_url());
+    cfg.reasoning_effort = Some(ReasoningEffort::High);
+    let provider =
+        borg::providers::openai::OpenAiProvider::from_config(&cfg).expect("provider creation");
     assert!(
-        !out2.is_empty(),
-        "second call should succeed and return non-empty content"
+        !provider.supports_native_batch(),
+        "custom base has no Batch API"
     );
+
+    let res = provider
+        .generate(GenerateRequest {
+            system: None,
+            messages: vec![Message {
+                role: Role::User,
+                content: vec![ContentPart::Text {
+                    text: "hello".to_string(),
+                }],
+            }],
+            tools: None,
+            tool_choice: None,
+            temperature: None,
+            top_p: None,
+            stop: None,
+            seed: None,
+            logit_bias: None,
+            response_format: None,
+            max_output_tokens: None,
+            metadata: None,
+            logprobs: Some(0),
+        })
+        .await
+        .expect("generate");
+
+    chat_mock.assert();
+    assert_eq!(res.text, "OK");
+    assert_eq!(res.usage.and_then(|u| u.total_tokens), Some(10));
+    assert_eq!(res.logprobs.map(|l| l.len()), Some(1));
+}
+
+#[tokio::test]
+async fn test_openai_conversation_sends_tool_calls_and_results() {
+    use borg::providers::conversation::Conversation;
+    use borg::providers::ToolCallNormalized;
+
+    let server = MockServer::start();
+    let chat_mock = server.mock(|when, then| {
+        when.method(POST)
+            .path("/chat/completions")
+            .body_contains(r#""tool_calls":[{"function":{"arguments":"{\"path\":\"a.rs\"}","name":"Read"},"id":"call_7","type":"function"}]"#)
+            .body_contains(r#"{"content":"fn main() {}","role":"tool","tool_call_id":"call_7"}"#);
+        then.status(200)
+            .header("content-type", "application/json")
+            .body(r#"{ "choices": [ { "message": { "content": "read it" } } ] }"#);
+    });
+
+    let cfg = make_openai_config("gpt-4o", &server.base_url());
+    let provider =
+        borg::providers::openai::OpenAiProvider::from_config(&cfg).expect("provider creation");
+
+    let mut conversation = Conversation::new("gpt-4o");
+    conversation.push_user("read a.rs");
+    conversation.push_response(&borg::providers::GenerateResponse {
+        text: String::new(),
+        tool_calls: vec![ToolCallNormalized {
+            id: Some("call_7".to_string()),
+            name: "Read".to_string(),
+            arguments_json: serde_json::json!({"path": "a.rs"}),
+        }],
+        usage: None,
+        raw: None,
+        provider: None,
+        reasoning: None,
+        logprobs: None,
+    });
+    conversation.push_tool_result("call_7", "fn main() {}", false);
+
+    let res = conversation.send(&provider).await.expect("generate");
+    chat_mock.assert();
+    assert_eq!(res.text, "read it");
+    assert!(conversation.pending_tool_calls().is_empty());
+}
+
+#[tokio::test]
+async fn test_openai_embeddings_come_back_in_input_order() {
+    let server = MockServer::start();
+    let embed_mock = server.mock(|when, then| {
+        when.method(POST)
+            .path("/embeddings")
+            .header("authorization", "Bearer test-key")
+            .json_body(serde_json::json!({
+                "model": "text-embedding-3-small",
+                "input": ["fn parse()", "struct Config"]
+            }));
+        then.status(200)
+            .header("content-type", "application/json")
+            .body(
+                r#"{ "data": [
+                    { "index": 1, "embedding": [0.0, 1.0] },
+                    { "index": 0, "embedding": [1.0, 0.5] }
+                ] }"#,
+            );
+    });
+
+    let cfg = make_openai_config("text-embedding-3-small", &server.base_url());
+    let provider =
+        borg::providers::openai::OpenAiProvider::from_config(&cfg).expect("provider creation");
+
+    let vectors = provider
+        .embed(&["fn parse()".to_string(), "struct Config".to_string()])
+        .await
+        .expect("embed");
+    embed_mock.assert();
+    assert_eq!(vectors, vec![vec![1.0, 0.5], vec![0.0, 1.0]]);
 }
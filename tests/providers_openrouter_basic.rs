@@ -1,6 +1,9 @@
 // File: tests/providers_openrouter_basic.rs
-use borg::providers::{ContentPart, GenerateRequest, Message, Provider, Role, StreamEvent};
+use borg::providers::{
+    ContentPart, GenerateRequest, Message, Provider, Role, StreamEvent, ToolChoice, ToolSpec,
+};
 use httpmock::prelude::*;
+use serde_json::json;
 
 fn make_cfg_with_headers(base: &str) -> borg::core::config::LlmConfig {
     let mut headers = std::collections::HashMap::new();
@@ -21,6 +24,14 @@ fn make_cfg_with_headers(base: &str) -> borg::core::config::LlmConfig {
         reasoning_budget_tokens: None,
         first_token_timeout_ms: Some(5_000),
         stall_timeout_ms: Some(3_000),
+        empty_response_retries: None,
+        stream_resume_retries: None,
+        deployment: None,
+        api_version: None,
+        retry: None,
+        rate_limit: None,
+        cache: None,
+        network: None,
     }
 }
 
@@ -43,6 +54,7 @@ fn make_req_basic() -> GenerateRequest {
         response_format: None,
         max_output_tokens: Some(55),
         metadata: None,
+        logprobs: None,
     }
 }
 
@@ -53,8 +65,11 @@ async fn test_openrouter_streaming_headers_forwarded() {
     // SSE compatible with OpenAI-chat delta format
     let sse = "\
 data: {\"choices\":[{\"delta\":{\"content\":\"Hi \"}}]}
+
 data: {\"choices\":[{\"delta\":{\"content\":\"there\"}}]}
+
 data: [DONE]
+
 ";
 
     // Expect headers and stream=true
@@ -143,3 +158,219 @@ async fn test_openrouter_retry_to_responses_style_tokens() {
     assert!(first.hits() >= 1, "first attempt must be hit");
     assert!(retry.hits() >= 1, "retry should be hit");
 }
+
+fn make_req_with_tool() -> GenerateRequest {
+    let mut req = make_req_basic();
+    req.tools = Some(vec![ToolSpec {
+        name: "read_file".to_string(),
+        description: Some("Read a file".to_string()),
+        json_schema: Some(json!({
+            "type": "object",
+            "properties": { "path": { "type": "string" } },
+            "required": ["path"]
+        })),
+    }]);
+    req.tool_choice = Some(ToolChoice::Auto);
+    req
+}
+
+#[tokio::test]
+async fn test_openrouter_tool_calls_mapped_and_parsed() {
+    let server = MockServer::start();
+    let m = server.mock(|when, then| {
+        when.method(POST)
+            .path("/chat/completions")
+            .body_contains("\"tools\":[{\"function\"")
+            .body_contains("\"name\":\"read_file\"")
+            .body_contains("\"tool_choice\":\"auto\"");
+        then.status(200)
+            .header("content-type", "application/json")
+            .body(
+                r#"{"choices":[{"message":{"content":null,"tool_calls":[
+                    {"id":"call_1","type":"function",
+                     "function":{"name":"read_file","arguments":"{\"path\":\"src/lib.rs\"}"}}
+                ]},"finish_reason":"tool_calls"}]}"#,
+            );
+    });
+
+    let cfg = make_cfg_with_headers(&server.base_url());
+    let provider =
+        borg::providers::openrouter::OpenRouterProvider::from_config(&cfg).expect("provider");
+    let res = provider
+        .generate(make_req_with_tool())
+        .await
+        .expect("tool-only response is not empty");
+
+    m.assert();
+    assert_eq!(res.tool_calls.len(), 1);
+    assert_eq!(res.tool_calls[0].id.as_deref(), Some("call_1"));
+    assert_eq!(res.tool_calls[0].arguments_json["path"], "src/lib.rs");
+}
+
+#[tokio::test]
+async fn test_openrouter_logprobs_requested_and_parsed() {
+    let server = MockServer::start();
+    let m = server.mock(|when, then| {
+        when.method(POST)
+            .path("/chat/completions")
+            .body_contains("\"logprobs\":true")
+            .body_contains("\"top_logprobs\":2");
+        then.status(200)
+            .header("content-type", "application/json")
+            .body(
+                r#"{"choices":[{"message":{"content":"Yes"},"finish_reason":"stop",
+                    "logprobs":{"content":[
+                        {"token":"Yes","logprob":-0.1,"top_logprobs":[
+                            {"token":"Yes","logprob":-0.1},{"token":"No","logprob":-2.4}
+                        ]}
+                    ]}}]}"#,
+            );
+    });
+
+    let cfg = make_cfg_with_headers(&server.base_url());
+    let provider =
+        borg::providers::openrouter::OpenRouterProvider::from_config(&cfg).expect("provider");
+    let mut req = make_req_basic();
+    req.logprobs = Some(2);
+    let res = provider.generate(req).await.expect("response");
+
+    m.assert();
+    let tokens = res.logprobs.as_ref().expect("logprobs");
+    assert_eq!(tokens.len(), 1);
+    assert_eq!(tokens[0].token, "Yes");
+    assert_eq!(tokens[0].top.len(), 2);
+    assert_eq!(tokens[0].top[1].token, "No");
+    let confidence = res.confidence().expect("confidence");
+    assert!((confidence - (-0.1f64).exp()).abs() < 1e-9);
+}
+
+#[tokio::test]
+async fn test_openrouter_streaming_tool_call_fragments() {
+    let server = MockServer::start();
+    let sse = r#"data: {"choices":[{"delta":{"tool_calls":[{"index":0,"id":"call_9","type":"function","function":{"name":"read_file","arguments":""}}]}}]}
+
+data: {"choices":[{"delta":{"tool_calls":[{"index":0,"function":{"arguments":"{\"pa"}}]}}]}
+
+data: {"choices":[{"delta":{"tool_calls":[{"index":0,"function":{"arguments":"th\":\"a.rs\"}"}}]}}]}
+
+data: {"choices":[{"delta":{},"finish_reason":"tool_calls"}]}
+
+data: [DONE]
+
+"#;
+    server.mock(|when, then| {
+        when.method(POST)
+            .path("/chat/completions")
+            .body_contains("\"stream\":true");
+        then.status(200)
+            .header("content-type", "text/event-stream")
+            .body(sse);
+    });
+
+    let cfg = make_cfg_with_headers(&server.base_url());
+    let provider =
+        borg::providers::openrouter::OpenRouterProvider::from_config(&cfg).expect("provider");
+
+    let mut fragments = String::new();
+    let mut calls = Vec::new();
+    let mut on_event = |ev: StreamEvent| match ev {
+        StreamEvent::ToolDelta(d) => fragments.push_str(&d),
+        StreamEvent::ToolCall(tc) => calls.push(tc),
+        _ => {}
+    };
+    let res = provider
+        .generate_streaming(make_req_with_tool(), &mut on_event)
+        .await
+        .expect("stream ok");
+
+    assert_eq!(fragments, r#"{"path":"a.rs"}"#);
+    assert_eq!(calls.len(), 1);
+    assert_eq!(calls[0].name, "read_file");
+    assert_eq!(calls[0].id.as_deref(), Some("call_9"));
+    assert_eq!(calls[0].arguments_json["path"], "a.rs");
+    assert_eq!(res.tool_calls.len(), 1);
+}
+
+#[tokio::test]
+async fn test_openai_native_batch_round_trip() {
+    let server = MockServer::start();
+
+    let upload = server.mock(|when, then| {
+        when.method(POST)
+            .path("/files")
+            .header("authorization", "Bearer test-openrouter")
+            .body_contains("name=\"purpose\"")
+            .body_contains("\"custom_id\":\"request-1\"")
+            .body_contains("\"url\":\"/v1/chat/completions\"");
+        then.status(200).json_body(json!({"id": "file-in"}));
+    });
+    let create = server.mock(|when, then| {
+        when.method(POST).path("/batches").json_body(json!({
+            "input_file_id": "file-in",
+            "endpoint": "/v1/chat/completions",
+            "completion_window": "24h"
+        }));
+        then.status(200)
+            .json_body(json!({"id": "batch_1", "status": "validating"}));
+    });
+    server.mock(|when, then| {
+        when.method(GET).path("/batches/batch_1");
+        then.status(200).json_body(json!({
+            "id": "batch_1",
+            "status": "completed",
+            "output_file_id": "file-out",
+            "error_file_id": "file-err"
+        }));
+    });
+    // Results arrive out of order and are matched back by custom_id
+    let output = [
+        json!({"custom_id": "request-1", "response": {"status_code": 200, "body": {
+            "choices": [{"message": {"content": "second"}}],
+            "usage": {"prompt_tokens": 3, "completion_tokens": 1}
+        }}}),
+        json!({"custom_id": "request-0", "response": {"status_code": 200, "body": {
+            "choices": [{"message": {"content": "first"}}]
+        }}}),
+    ]
+    .iter()
+    .map(|l| l.to_string())
+    .collect::<Vec<_>>()
+    .join("\n");
+    server.mock(|when, then| {
+        when.method(GET).path("/files/file-out/content");
+        then.status(200).body(output);
+    });
+    server.mock(|when, then| {
+        when.method(GET).path("/files/file-err/content");
+        then.status(200).body(
+            json!({"custom_id": "request-2", "response": {"status_code": 429, "body": {
+                "error": {"message": "rate limited"}
+            }}})
+            .to_string(),
+        );
+    });
+
+    let provider = borg::providers::openrouter::OpenRouterProvider::from_config(
+        &make_cfg_with_headers(&server.base_url()),
+    )
+    .expect("provider")
+    .with_native_batch(true)
+    .with_batch_poll_interval(std::time::Duration::from_millis(10));
+    assert!(provider.supports_native_batch());
+
+    let results = provider
+        .generate_batch(vec![make_req_basic(), make_req_basic(), make_req_basic()])
+        .await;
+
+    upload.assert();
+    create.assert();
+    assert_eq!(results.len(), 3);
+    assert_eq!(results[0].as_ref().expect("first").text, "first");
+    let second = results[1].as_ref().expect("second");
+    assert_eq!(second.text, "second");
+    assert_eq!(second.usage.as_ref().and_then(|u| u.prompt_tokens), Some(3));
+    assert!(matches!(
+        results[2],
+        Err(borg::core::error::ProviderError::RateLimited { .. })
+    ));
+}
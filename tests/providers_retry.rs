@@ -0,0 +1,193 @@
+// File: tests/providers_retry.rs
+use async_trait::async_trait;
+use borg::core::config::{LlmConfig, RetryConfig};
+use borg::core::error::ProviderError;
+use borg::providers::retry::RetryingProvider;
+use borg::providers::{
+    ContentPart, GenerateRequest, GenerateResponse, Message, Provider, Role, StreamEvent,
+};
+use httpmock::prelude::*;
+use std::sync::atomic::{AtomicU32, Ordering};
+
+fn fast_retry(max_attempts: u32) -> RetryConfig {
+    RetryConfig {
+        max_attempts,
+        initial_backoff_ms: 1,
+        max_backoff_ms: 5,
+        multiplier: 2.0,
+        jitter: 0.0,
+    }
+}
+
+fn make_cfg(base: &str) -> LlmConfig {
+    LlmConfig {
+        provider: "anthropic".to_string(),
+        api_key: "test-anthropic".to_string(),
+        model: "claude-3-7-sonnet".to_string(),
+        max_tokens: 1024,
+        temperature: 0.0,
+        api_base: Some(base.to_string()),
+        headers: None,
+        enable_streaming: Some(false),
+        enable_thinking: None,
+        reasoning_effort: None,
+        reasoning_budget_tokens: None,
+        first_token_timeout_ms: Some(5_000),
+        stall_timeout_ms: Some(3_000),
+        empty_response_retries: None,
+        stream_resume_retries: None,
+        deployment: None,
+        api_version: None,
+        retry: None,
+        rate_limit: None,
+        cache: None,
+        network: None,
+    }
+}
+
+fn make_req() -> GenerateRequest {
+    GenerateRequest {
+        system: None,
+        messages: vec![Message {
+            role: Role::User,
+            content: vec![ContentPart::Text {
+                text: "hello".to_string(),
+            }],
+        }],
+        tools: None,
+        tool_choice: None,
+        temperature: None,
+        top_p: None,
+        stop: None,
+        seed: None,
+        logit_bias: None,
+        response_format: None,
+        max_output_tokens: Some(16),
+        metadata: None,
+        logprobs: None,
+    }
+}
+
+/// Fails with a 503 the first `failures` times, then succeeds
+struct FlakyProvider {
+    failures: u32,
+    calls: AtomicU32,
+}
+
+#[async_trait]
+impl Provider for FlakyProvider {
+    async fn generate(&self, _req: GenerateRequest) -> Result<GenerateResponse, ProviderError> {
+        let call = self.calls.fetch_add(1, Ordering::SeqCst);
+        if call < self.failures {
+            return Err(ProviderError::ServerError {
+                details: None,
+                code: None,
+                message: "overloaded".to_string(),
+                status: Some(503),
+            });
+        }
+        Ok(GenerateResponse {
+            text: "ok".to_string(),
+            tool_calls: Vec::new(),
+            usage: None,
+            raw: None,
+            provider: None,
+            reasoning: None,
+            logprobs: None,
+        })
+    }
+
+    async fn generate_streaming(
+        &self,
+        req: GenerateRequest,
+        on_event: &mut (dyn FnMut(StreamEvent) + Send),
+    ) -> Result<GenerateResponse, ProviderError> {
+        let res = self.generate(req).await?;
+        on_event(StreamEvent::TextDelta(res.text.clone()));
+        Ok(res)
+    }
+}
+
+#[tokio::test]
+async fn test_transient_errors_are_retried_and_counted() {
+    let inner = FlakyProvider {
+        failures: 2,
+        calls: AtomicU32::new(0),
+    };
+    let provider = RetryingProvider::new(Box::new(inner), fast_retry(3));
+
+    let res = provider
+        .generate(make_req())
+        .await
+        .expect("third attempt ok");
+    assert_eq!(res.text, "ok");
+    assert_eq!(res.usage.and_then(|u| u.retries), Some(2));
+}
+
+#[tokio::test]
+async fn test_gives_up_after_max_attempts() {
+    let inner = FlakyProvider {
+        failures: 5,
+        calls: AtomicU32::new(0),
+    };
+    let provider = RetryingProvider::new(Box::new(inner), fast_retry(2));
+
+    let mut on_event = |_: StreamEvent| {};
+    let err = provider
+        .generate_streaming(make_req(), &mut on_event)
+        .await
+        .expect_err("retries exhausted");
+    assert!(matches!(err, ProviderError::ServerError { .. }));
+}
+
+#[tokio::test]
+async fn test_rate_limit_retry_after_is_honoured() {
+    let server = MockServer::start();
+    let m = server.mock(|when, then| {
+        when.method(POST).path("/messages");
+        then.status(429)
+            .header("content-type", "application/json")
+            .header("retry-after-ms", "20")
+            .body(r#"{"error":{"type":"rate_limit_error","message":"slow down"}}"#);
+    });
+
+    let inner =
+        borg::providers::anthropic::AnthropicProvider::from_config(&make_cfg(&server.base_url()))
+            .expect("provider");
+    let provider = RetryingProvider::new(Box::new(inner), fast_retry(3));
+
+    let started = std::time::Instant::now();
+    let err = provider
+        .generate(make_req())
+        .await
+        .expect_err("still limited");
+
+    assert_eq!(m.hits(), 3);
+    assert!(started.elapsed() >= std::time::Duration::from_millis(40));
+    match err {
+        ProviderError::RateLimited { retry_after_ms, .. } => assert_eq!(retry_after_ms, Some(20)),
+        other => panic!("unexpected error: {:?}", other),
+    }
+}
+
+#[tokio::test]
+async fn test_client_errors_are_not_retried() {
+    let server = MockServer::start();
+    let m = server.mock(|when, then| {
+        when.method(POST).path("/messages");
+        then.status(400)
+            .header("content-type", "application/json")
+            .body(r#"{"error":{"type":"invalid_request_error","message":"bad"}}"#);
+    });
+
+    let inner =
+        borg::providers::anthropic::AnthropicProvider::from_config(&make_cfg(&server.base_url()))
+            .expect("provider");
+    let provider = RetryingProvider::new(Box::new(inner), fast_retry(3));
+
+    provider
+        .generate(make_req())
+        .await
+        .expect_err("bad request");
+    assert_eq!(m.hits(), 1);
+}
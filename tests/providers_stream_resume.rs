@@ -0,0 +1,168 @@
+// File: tests/providers_stream_resume.rs
+use borg::code_generation::llm::LlmFactory;
+use borg::core::config::{LlmConfig, LlmLoggingConfig};
+use borg::providers::resume::generate_streaming_with_resume;
+use borg::providers::{ContentPart, GenerateRequest, Message, Role, StreamEvent};
+use httpmock::prelude::*;
+
+const PARTIAL: &str = "The quick brown fox";
+
+fn make_cfg(base: &str) -> LlmConfig {
+    LlmConfig {
+        provider: "anthropic".to_string(),
+        api_key: "test-anthropic".to_string(),
+        model: "claude-3-7-sonnet".to_string(),
+        max_tokens: 1024,
+        temperature: 0.0,
+        api_base: Some(base.to_string()),
+        headers: None,
+        enable_streaming: Some(true),
+        enable_thinking: None,
+        reasoning_effort: None,
+        reasoning_budget_tokens: None,
+        first_token_timeout_ms: Some(5_000),
+        stall_timeout_ms: Some(3_000),
+        empty_response_retries: None,
+        stream_resume_retries: None,
+        deployment: None,
+        api_version: None,
+        retry: None,
+        rate_limit: None,
+        cache: None,
+        network: None,
+    }
+}
+
+fn make_req() -> GenerateRequest {
+    GenerateRequest {
+        system: None,
+        messages: vec![Message {
+            role: Role::User,
+            content: vec![ContentPart::Text {
+                text: "Finish the pangram".to_string(),
+            }],
+        }],
+        tools: None,
+        tool_choice: None,
+        temperature: None,
+        top_p: None,
+        stop: None,
+        seed: None,
+        logit_bias: None,
+        response_format: None,
+        max_output_tokens: Some(64),
+        metadata: None,
+        logprobs: None,
+    }
+}
+
+fn is_continuation(req: &HttpMockRequest) -> bool {
+    let body = String::from_utf8_lossy(req.body.as_deref().unwrap_or_default()).to_string();
+    body.contains(r#""role":"assistant""#) && body.contains(PARTIAL)
+}
+
+fn is_initial(req: &HttpMockRequest) -> bool {
+    !is_continuation(req)
+}
+
+/// Stream that is cut off after the first delta (no `message_stop`)
+const TRUNCATED_SSE: &str = "\
+event: message_start
+data: {\"type\":\"message_start\",\"message\":{\"id\":\"msg_1\"}}
+
+event: content_block_delta
+data: {\"type\":\"content_block_delta\",\"delta\":{\"type\":\"text_delta\",\"text\":\"The quick brown fox \"}}
+
+";
+
+/// Continuation that completes normally
+const CONTINUED_SSE: &str = "\
+event: content_block_delta
+data: {\"type\":\"content_block_delta\",\"delta\":{\"type\":\"text_delta\",\"text\":\" jumps over the lazy dog.\"}}
+
+event: message_stop
+data: {\"type\":\"message_stop\"}
+
+";
+
+fn mock_disconnecting_server(server: &MockServer) -> (httpmock::Mock<'_>, httpmock::Mock<'_>) {
+    let initial = server.mock(|when, then| {
+        when.method(POST).path("/messages").matches(is_initial);
+        then.status(200)
+            .header("content-type", "text/event-stream")
+            .body(TRUNCATED_SSE);
+    });
+    let continuation = server.mock(|when, then| {
+        when.method(POST).path("/messages").matches(is_continuation);
+        then.status(200)
+            .header("content-type", "text/event-stream")
+            .body(CONTINUED_SSE);
+    });
+    (initial, continuation)
+}
+
+#[tokio::test]
+async fn test_interrupted_stream_is_continued_to_full_text() {
+    let server = MockServer::start();
+    let (initial, continuation) = mock_disconnecting_server(&server);
+
+    let provider =
+        borg::providers::anthropic::AnthropicProvider::from_config(&make_cfg(&server.base_url()))
+            .expect("provider");
+
+    let mut deltas = String::new();
+    let mut on_event = |ev: StreamEvent| {
+        if let StreamEvent::TextDelta(d) = ev {
+            deltas.push_str(&d);
+        }
+    };
+    let res = generate_streaming_with_resume(&provider, make_req(), &mut on_event, 2)
+        .await
+        .expect("stream resumed");
+
+    assert_eq!(res.text, "The quick brown fox jumps over the lazy dog.");
+    assert_eq!(initial.hits(), 1);
+    assert_eq!(continuation.hits(), 1);
+    assert!(deltas.ends_with("lazy dog."));
+}
+
+#[tokio::test]
+async fn test_factory_streaming_resumes_after_disconnect() {
+    let server = MockServer::start();
+    let (_, continuation) = mock_disconnecting_server(&server);
+
+    let llm = LlmFactory::create(
+        make_cfg(&server.base_url()),
+        LlmLoggingConfig {
+            enabled: false,
+            ..Default::default()
+        },
+    )
+    .expect("llm");
+
+    let text = llm
+        .generate_streaming("Finish the pangram", Some(64), None, false)
+        .await
+        .expect("stream resumed");
+
+    assert_eq!(text, "The quick brown fox jumps over the lazy dog.");
+    assert_eq!(continuation.hits(), 1);
+}
+
+#[tokio::test]
+async fn test_resume_disabled_returns_partial_text() {
+    let server = MockServer::start();
+    let (_, continuation) = mock_disconnecting_server(&server);
+
+    let provider =
+        borg::providers::anthropic::AnthropicProvider::from_config(&make_cfg(&server.base_url()))
+            .expect("provider");
+
+    let mut on_event = |_: StreamEvent| {};
+    let res = generate_streaming_with_resume(&provider, make_req(), &mut on_event, 0)
+        .await
+        .expect("stream ok");
+
+    assert_eq!(res.text, "The quick brown fox ");
+    assert_eq!(continuation.hits(), 0);
+}
@@ -0,0 +1,156 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use std::fs;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Mutex;
+
+use borg::core::config::Config;
+use borg::core::optimization::{GoalStatus, OptimizationGoal};
+use borg::database::DatabaseManager;
+use borg::swarm::{Proposal, SwarmCoordinator};
+use borg::testing::test_runner::{TestResult, TestRunner};
+use borg::version_control::git_implementation::GitImplementation;
+use borg::version_control::rollback::{self, MergeLedger};
+
+/// Runner whose tests always pass
+struct PassingRunner;
+
+#[async_trait]
+impl TestRunner for PassingRunner {
+    async fn run_tests(&self, branch: &str, _target: Option<&Path>) -> Result<TestResult> {
+        Ok(TestResult {
+            success: true,
+            output: String::new(),
+            duration: Duration::ZERO,
+            metrics: None,
+            report: None,
+            failures: None,
+            compilation_errors: None,
+            exit_code: Some(0),
+            branch: Some(branch.to_string()),
+            test_stage: Some("tests".to_string()),
+            tests: None,
+        })
+    }
+
+    async fn run_benchmark(&self, branch: &str, target: Option<&Path>) -> Result<TestResult> {
+        self.run_tests(branch, target).await
+    }
+}
+
+fn git(dir: &Path, args: &[&str]) {
+    let output = std::process::Command::new("git")
+        .current_dir(dir)
+        .args(args)
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "git {:?} failed", args);
+}
+
+#[tokio::test]
+async fn test_swarm_merges_are_recorded_and_rolled_back() {
+    let repo = tempfile::tempdir().unwrap();
+    let path = repo.path();
+    git(path, &["init", "-q", "-b", "master"]);
+    git(path, &["config", "user.name", "Test"]);
+    git(path, &["config", "user.email", "test@example.com"]);
+    fs::write(path.join("lib.rs"), "fn a() {}\n").unwrap();
+    git(path, &["add", "."]);
+    git(path, &["commit", "-q", "-m", "Initial commit"]);
+    git(path, &["checkout", "-q", "-b", "swarm/proposal-1"]);
+    fs::write(path.join("lib.rs"), "fn a() {}\nfn b() {}\n").unwrap();
+    git(path, &["commit", "-q", "-am", "Add b"]);
+    git(path, &["checkout", "-q", "master"]);
+
+    let data = tempfile::tempdir().unwrap();
+    let config: Config = serde_yaml::from_str(&format!(
+        r#"
+models: []
+phases:
+  research: {{ models: [], tools: [], prompt: "" }}
+  deliberation: {{ models: [], tools: [], prompt: "" }}
+  tdd: {{ models: [], tools: [], prompt: "" }}
+agent: {{ working_dir: {}, timeout_seconds: 60, max_memory_usage_mb: 1024, max_cpu_usage_percent: 80 }}
+database: {{ path: ./data/borg.db }}
+git: {{ branch_prefix: borg/ }}
+logging: {{ enabled: false, llm_log_dir: {} }}
+"#,
+        path.display(),
+        data.path().join("logs").display()
+    ))
+    .unwrap();
+    let database = DatabaseManager::new(data.path().join("data"), &config)
+        .await
+        .unwrap();
+    database
+        .goals()
+        .insert({
+            let mut goal = OptimizationGoal::new("proposal-1", "Add b", "Add function b");
+            goal.update_status(GoalStatus::Completed);
+            goal
+        })
+        .await
+        .unwrap();
+
+    let git_manager = Arc::new(Mutex::new(GitImplementation::new(path).unwrap()));
+    let coordinator = SwarmCoordinator::new(config, git_manager.clone(), Arc::new(PassingRunner))
+        .await
+        .unwrap()
+        .with_merge_ledger(Arc::new(MergeLedger::new(database.merges())));
+    let proposal = Proposal {
+        id: "proposal-1".to_string(),
+        agent_id: "researcher".to_string(),
+        title: "Add b".to_string(),
+        description: "Add function b".to_string(),
+        rationale: String::new(),
+        files_to_modify: vec!["lib.rs".to_string()],
+        files_to_create: Vec::new(),
+        files_to_delete: Vec::new(),
+        estimated_lines_changed: 1,
+        expected_benefits: Vec::new(),
+        potential_risks: Vec::new(),
+    };
+
+    let merged = coordinator
+        .merge_proposal(&proposal)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(merged.branch, "swarm/proposal-1");
+    assert_eq!(merged.target, "master");
+    assert_eq!(
+        fs::read_to_string(path.join("lib.rs")).unwrap(),
+        "fn a() {}\nfn b() {}\n"
+    );
+    // Merging again finds nothing new
+    assert!(coordinator
+        .merge_proposal(&proposal)
+        .await
+        .unwrap()
+        .is_none());
+
+    let git_manager = git_manager.lock().await;
+    let (record, reopened) = rollback::roll_back_goal(
+        &database,
+        &*git_manager,
+        "proposal-1",
+        "tests on master failed",
+    )
+    .await
+    .unwrap();
+    assert!(reopened);
+    assert_eq!(record.merge_commit, merged.merge_commit);
+    assert_eq!(record.reverted.unwrap().reason, "tests on master failed");
+    assert_eq!(
+        fs::read_to_string(path.join("lib.rs")).unwrap(),
+        "fn a() {}\n"
+    );
+    let goal = database
+        .goals()
+        .get(&"proposal-1".to_string())
+        .await
+        .unwrap();
+    assert_eq!(goal.entity.status, GoalStatus::NotStarted);
+}
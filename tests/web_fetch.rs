@@ -0,0 +1,161 @@
+// File: tests/web_fetch.rs
+use borg::code_generation::llm_tool::LlmTool;
+use borg::code_generation::web_fetch::WebFetchTool;
+use borg::core::config::WebFetchConfig;
+use httpmock::prelude::*;
+
+fn config(cache_dir: Option<&std::path::Path>) -> WebFetchConfig {
+    WebFetchConfig {
+        cache_dir: cache_dir.map(|d| d.to_string_lossy().to_string()),
+        fresh_secs: 0,
+        ..WebFetchConfig::default()
+    }
+}
+
+#[tokio::test]
+async fn web_fetch_revalidates_cached_pages_by_etag() {
+    let server = MockServer::start_async().await;
+    server
+        .mock_async(|when, then| {
+            when.method(GET).path("/robots.txt");
+            then.status(404);
+        })
+        .await;
+    let unchanged = server
+        .mock_async(|when, then| {
+            when.method(GET)
+                .path("/guide")
+                .header("if-none-match", "\"v1\"");
+            then.status(304);
+        })
+        .await;
+    let first = server
+        .mock_async(|when, then| {
+            when.method(GET).path("/guide");
+            then.status(200)
+                .header("content-type", "text/html; charset=utf-8")
+                .header("etag", "\"v1\"")
+                .body("<h2>Setup</h2><pre><code class=\"language-sh\">cargo add borg</code></pre>");
+        })
+        .await;
+
+    let dir = tempfile::tempdir().unwrap();
+    let tool = WebFetchTool::new(config(Some(dir.path())));
+    let url = server.url("/guide");
+    let fetched = tool.execute_positional(&[&url]).await.unwrap();
+    assert_eq!(fetched, "## Setup\n\n```sh\ncargo add borg\n```");
+
+    // A new tool with the same cache directory revalidates instead of
+    // downloading the page again
+    let tool = WebFetchTool::new(config(Some(dir.path())));
+    let cached = tool.execute_positional(&[&url]).await.unwrap();
+    assert_eq!(cached, fetched);
+    assert_eq!(first.hits_async().await, 1);
+    assert_eq!(unchanged.hits_async().await, 1);
+
+    // Within `fresh_secs` the server is not asked at all
+    let tool = WebFetchTool::new(WebFetchConfig {
+        fresh_secs: 3600,
+        ..config(Some(dir.path()))
+    });
+    tool.execute_positional(&[&url]).await.unwrap();
+    assert_eq!(unchanged.hits_async().await, 1);
+}
+
+#[tokio::test]
+async fn web_fetch_obeys_robots_txt() {
+    let server = MockServer::start_async().await;
+    let robots = server
+        .mock_async(|when, then| {
+            when.method(GET).path("/robots.txt");
+            then.status(200).body("User-agent: *\nDisallow: /private\n");
+        })
+        .await;
+    let private = server
+        .mock_async(|when, then| {
+            when.method(GET).path("/private/notes");
+            then.status(200).body("secret");
+        })
+        .await;
+    server
+        .mock_async(|when, then| {
+            when.method(GET).path("/public");
+            then.status(200)
+                .header("content-type", "text/plain")
+                .body("hello");
+        })
+        .await;
+
+    let tool = WebFetchTool::new(config(None));
+    let err = tool
+        .execute_positional(&[&server.url("/private/notes")])
+        .await
+        .unwrap_err();
+    assert!(err.to_string().contains("robots.txt"), "{}", err);
+    assert_eq!(private.hits_async().await, 0);
+
+    let out = tool
+        .execute_positional(&[&server.url("/public")])
+        .await
+        .unwrap();
+    assert_eq!(out, "hello");
+    // robots.txt is read once per site
+    assert_eq!(robots.hits_async().await, 1);
+
+    let ignoring = WebFetchTool::new(WebFetchConfig {
+        respect_robots_txt: false,
+        ..config(None)
+    });
+    let out = ignoring
+        .execute_positional(&[&server.url("/private/notes")])
+        .await
+        .unwrap();
+    assert_eq!(out, "secret");
+}
+
+#[tokio::test]
+async fn web_fetch_applies_domain_lists_to_redirects() {
+    let server = MockServer::start_async().await;
+    server
+        .mock_async(|when, then| {
+            when.method(GET).path("/robots.txt");
+            then.status(404);
+        })
+        .await;
+    let redirected = format!("http://localhost:{}/landing", server.port());
+    server
+        .mock_async(|when, then| {
+            when.method(GET).path("/moved");
+            then.status(302).header("location", redirected.as_str());
+        })
+        .await;
+    let landing = server
+        .mock_async(|when, then| {
+            when.method(GET).path("/landing");
+            then.status(200).body("landed");
+        })
+        .await;
+
+    let tool = WebFetchTool::new(WebFetchConfig {
+        blocked_domains: vec!["localhost".to_string()],
+        ..config(None)
+    });
+    let err = tool.execute_positional(&[&redirected]).await.unwrap_err();
+    assert!(err.to_string().contains("blocked_domains"), "{}", err);
+    let err = tool
+        .execute_positional(&[&server.url("/moved")])
+        .await
+        .unwrap_err();
+    assert!(err.to_string().contains("Failed to fetch URL"), "{}", err);
+    assert_eq!(landing.hits_async().await, 0);
+
+    let allowed_only = WebFetchTool::new(WebFetchConfig {
+        allowed_domains: vec!["docs.rs".to_string()],
+        ..config(None)
+    });
+    let err = allowed_only
+        .execute_positional(&[&server.url("/landing")])
+        .await
+        .unwrap_err();
+    assert!(err.to_string().contains("allowed_domains"), "{}", err);
+}